@@ -515,7 +515,10 @@ pub struct AdminUserContextWithClock<'info> {
 pub struct SwapInfo(stable_swap_client::state::SwapInfo);
 
 impl SwapInfo {
-    /// The length, in bytes, of the packed representation
+    /// The length, in bytes, of the packed representation of the swaps of the current
+    /// program. Pools deployed earlier may be stored in
+    /// [SWAP_INFO_LEGACY_LEN](stable_swap_client::state::SWAP_INFO_LEGACY_LEN) bytes,
+    /// which deserialize as well.
     pub const LEN: usize = stable_swap_client::state::SwapInfo::LEN;
 
     /// Computes the minimum rent exempt balance of a [SwapInfo].
@@ -0,0 +1,445 @@
+//! Instruction builders which resolve the accounts of a swap from its [SwapInfo].
+//!
+//! The builders derive the swap authority, resolve the associated token accounts
+//! of the user and pick the admin fee account matching the direction of a trade,
+//! so that callers only need to provide the user and the amounts. Swaps and deposits
+//! pass the global state of the program, so that they fail while every pool is paused.
+
+use crate::{
+    error::SwapError,
+    instruction,
+    state::{SwapInfo, SwapTokenInfo, GLOBAL_STATE_SEED, SHARED_AUTHORITY_SEED},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+
+/// The SPL Associated Token Account program.
+pub mod associated_token {
+    solana_program::declare_id!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+}
+
+/// Derives the associated token account of a wallet for a mint.
+pub fn get_associated_token_address(wallet: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), spl_token::id().as_ref(), mint.as_ref()],
+        &associated_token::id(),
+    )
+    .0
+}
+
+/// Derives the authority of a swap from its nonce.
+pub fn get_swap_authority(swap_pubkey: &Pubkey, nonce: u8) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(&[swap_pubkey.as_ref(), &[nonce]], &crate::ID)
+        .map_err(|_| SwapError::InvalidProgramAddress.into())
+}
+
+/// Derives the authority shared by the pools that opted into it from its nonce.
+pub fn get_shared_authority(nonce: u8) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(&[SHARED_AUTHORITY_SEED, &[nonce]], &crate::ID)
+        .map_err(|_| SwapError::InvalidProgramAddress.into())
+}
+
+/// Derives the global state of the program.
+pub fn get_global_state_address() -> Pubkey {
+    Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID).0
+}
+
+/// Builds the instructions of a swap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwapInstructionBuilder {
+    /// Public key of the swap account
+    pub swap_pubkey: Pubkey,
+    /// Authority of the swap, derived from its nonce
+    pub swap_authority: Pubkey,
+    /// Global state of the program
+    pub global_state: Pubkey,
+    /// State of the swap
+    pub swap_info: SwapInfo,
+}
+
+impl SwapInstructionBuilder {
+    /// Creates a builder from the state of a swap.
+    pub fn new(swap_pubkey: &Pubkey, swap_info: SwapInfo) -> Result<Self, ProgramError> {
+        let swap_authority = if swap_info.shared_authority {
+            get_shared_authority(swap_info.nonce)?
+        } else {
+            get_swap_authority(swap_pubkey, swap_info.nonce)?
+        };
+        Ok(Self {
+            swap_pubkey: *swap_pubkey,
+            swap_authority,
+            global_state: get_global_state_address(),
+            swap_info,
+        })
+    }
+
+    /// Creates a builder from the raw data of a swap account, in the current layout
+    /// of the program, of any version, or the legacy layout.
+    pub fn from_account_data(swap_pubkey: &Pubkey, data: &[u8]) -> Result<Self, ProgramError> {
+        Self::new(swap_pubkey, SwapInfo::unpack(data)?)
+    }
+
+    /// Fetches a swap account and creates a builder from it.
+    #[cfg(feature = "rpc")]
+    pub fn load(
+        client: &solana_client::rpc_client::RpcClient,
+        swap_pubkey: &Pubkey,
+    ) -> Result<Self, LoadError> {
+        let data = client.get_account_data(swap_pubkey)?;
+        Ok(Self::from_account_data(swap_pubkey, &data)?)
+    }
+
+    /// Returns the (source, destination) tokens of a trade of `source_mint`.
+    fn trade_tokens(
+        &self,
+        source_mint: &Pubkey,
+    ) -> Result<(&SwapTokenInfo, &SwapTokenInfo), ProgramError> {
+        let SwapInfo {
+            token_a, token_b, ..
+        } = &self.swap_info;
+        if *source_mint == token_a.mint {
+            Ok((token_a, token_b))
+        } else if *source_mint == token_b.mint {
+            Ok((token_b, token_a))
+        } else {
+            Err(SwapError::IncorrectMint.into())
+        }
+    }
+
+    /// Adds the global state to a swap or deposit instruction, which the program
+    /// requires after the clock sysvar.
+    fn with_global_state(&self, mut instruction: Instruction) -> Instruction {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(self.global_state, false));
+        instruction
+    }
+
+    /// Creates a 'swap' instruction trading `amount_in` of `source_mint`
+    /// between the associated token accounts of the user.
+    pub fn swap(
+        &self,
+        user_authority: &Pubkey,
+        source_mint: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<Instruction, ProgramError> {
+        let (source, destination) = self.trade_tokens(source_mint)?;
+        instruction::swap(
+            &spl_token::id(),
+            &self.swap_pubkey,
+            &self.swap_authority,
+            user_authority,
+            &get_associated_token_address(user_authority, &source.mint),
+            &source.reserves,
+            &destination.reserves,
+            &get_associated_token_address(user_authority, &destination.mint),
+            // Trade fees are taken from the output token
+            &destination.admin_fees,
+            amount_in,
+            minimum_amount_out,
+        )
+        .map(|instruction| self.with_global_state(instruction))
+    }
+
+    /// Creates a 'deposit' instruction from the associated token accounts of the user.
+    pub fn deposit(
+        &self,
+        user_authority: &Pubkey,
+        token_a_amount: u64,
+        token_b_amount: u64,
+        min_mint_amount: u64,
+    ) -> Result<Instruction, ProgramError> {
+        let SwapInfo {
+            token_a,
+            token_b,
+            pool_mint,
+            ..
+        } = &self.swap_info;
+        instruction::deposit(
+            &spl_token::id(),
+            &self.swap_pubkey,
+            &self.swap_authority,
+            user_authority,
+            &get_associated_token_address(user_authority, &token_a.mint),
+            &get_associated_token_address(user_authority, &token_b.mint),
+            &token_a.reserves,
+            &token_b.reserves,
+            pool_mint,
+            &get_associated_token_address(user_authority, pool_mint),
+            token_a_amount,
+            token_b_amount,
+            min_mint_amount,
+        )
+        .map(|instruction| self.with_global_state(instruction))
+    }
+
+    /// Creates a 'withdraw' instruction into the associated token accounts of the user.
+    pub fn withdraw(
+        &self,
+        user_authority: &Pubkey,
+        pool_token_amount: u64,
+        minimum_token_a_amount: u64,
+        minimum_token_b_amount: u64,
+    ) -> Result<Instruction, ProgramError> {
+        let SwapInfo {
+            token_a,
+            token_b,
+            pool_mint,
+            ..
+        } = &self.swap_info;
+        instruction::withdraw(
+            &spl_token::id(),
+            &self.swap_pubkey,
+            &self.swap_authority,
+            user_authority,
+            pool_mint,
+            &get_associated_token_address(user_authority, pool_mint),
+            &token_a.reserves,
+            &token_b.reserves,
+            &get_associated_token_address(user_authority, &token_a.mint),
+            &get_associated_token_address(user_authority, &token_b.mint),
+            &token_a.admin_fees,
+            &token_b.admin_fees,
+            pool_token_amount,
+            minimum_token_a_amount,
+            minimum_token_b_amount,
+        )
+    }
+
+    /// Creates a 'withdraw_one' instruction withdrawing `base_mint`
+    /// into the associated token account of the user.
+    pub fn withdraw_one(
+        &self,
+        user_authority: &Pubkey,
+        base_mint: &Pubkey,
+        pool_token_amount: u64,
+        minimum_token_amount: u64,
+    ) -> Result<Instruction, ProgramError> {
+        let (base, quote) = self.trade_tokens(base_mint)?;
+        let pool_mint = &self.swap_info.pool_mint;
+        instruction::withdraw_one(
+            &spl_token::id(),
+            &self.swap_pubkey,
+            &self.swap_authority,
+            user_authority,
+            pool_mint,
+            &get_associated_token_address(user_authority, pool_mint),
+            &base.reserves,
+            &quote.reserves,
+            &get_associated_token_address(user_authority, &base.mint),
+            &base.admin_fees,
+            pool_token_amount,
+            minimum_token_amount,
+        )
+    }
+}
+
+/// Errors that may be returned when loading a swap over RPC.
+#[cfg(feature = "rpc")]
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    /// The RPC request failed.
+    #[error(transparent)]
+    Client(#[from] solana_client::client_error::ClientError),
+    /// The swap account could not be decoded.
+    #[error(transparent)]
+    Program(#[from] ProgramError),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        fees::Fees,
+        state::{AMP_PRECISION, SWAP_INFO_LEGACY_LEN, SWAP_INFO_VERSION},
+    };
+    use solana_program::instruction::AccountMeta;
+
+    fn swap_token_info(index: u8) -> SwapTokenInfo {
+        SwapTokenInfo {
+            reserves: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            admin_fees: Pubkey::new_unique(),
+            index,
+        }
+    }
+
+    fn builder() -> SwapInstructionBuilder {
+        let swap_pubkey = Pubkey::new_unique();
+        let (_, nonce) = Pubkey::find_program_address(&[swap_pubkey.as_ref()], &crate::ID);
+        let swap_info = SwapInfo {
+            is_initialized: true,
+            is_paused: false,
+            nonce,
+            shared_authority: false,
+            initial_amp_factor: 100,
+            target_amp_factor: 100,
+            start_ramp_ts: 0,
+            stop_ramp_ts: 0,
+            future_admin_deadline: 0,
+            future_admin_key: Pubkey::default(),
+            admin_key: Pubkey::new_unique(),
+            token_a: swap_token_info(0),
+            token_b: swap_token_info(1),
+            pool_mint: Pubkey::new_unique(),
+            fees: Fees::default(),
+        };
+        SwapInstructionBuilder::new(&swap_pubkey, swap_info).unwrap()
+    }
+
+    #[test]
+    fn test_swap_authority() {
+        let builder = builder();
+        let (expected, _) =
+            Pubkey::find_program_address(&[builder.swap_pubkey.as_ref()], &crate::ID);
+        assert_eq!(builder.swap_authority, expected);
+
+        // swap accounts in either layout
+        for len in [SwapInfo::LEN, SWAP_INFO_LEGACY_LEN] {
+            let mut data = vec![0u8; len];
+            SwapInfo::pack(builder.swap_info, &mut data).unwrap();
+            assert_eq!(
+                SwapInstructionBuilder::from_account_data(&builder.swap_pubkey, &data).unwrap(),
+                builder
+            );
+            assert_eq!(
+                SwapInstructionBuilder::from_account_data(&builder.swap_pubkey, &data[..len - 1]),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
+        assert_eq!(
+            SwapInstructionBuilder::from_account_data(&builder.swap_pubkey, &[0u8; SwapInfo::LEN]),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_from_program_account() {
+        let builder = builder();
+        let (shared_authority, nonce) =
+            Pubkey::find_program_address(&[SHARED_AUTHORITY_SEED], &crate::ID);
+        let swap_info = SwapInfo {
+            nonce,
+            shared_authority: true,
+            ..builder.swap_info
+        };
+        // a swap as the program packs it: initialized, with a shared authority and
+        // rejecting zero amounts, coefficients in hundredths, then the fields and the
+        // checksum of the program
+        let mut data = vec![0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut data).unwrap();
+        data[0] = 0b1100_0001;
+        data[1] = SWAP_INFO_VERSION;
+        data[3..11].copy_from_slice(&(100 * AMP_PRECISION).to_le_bytes());
+        data[11..19].copy_from_slice(&(100 * AMP_PRECISION).to_le_bytes());
+        data[SWAP_INFO_LEGACY_LEN..].fill(0xab);
+
+        let loaded =
+            SwapInstructionBuilder::from_account_data(&builder.swap_pubkey, &data).unwrap();
+        assert_eq!(
+            loaded,
+            SwapInstructionBuilder {
+                swap_authority: shared_authority,
+                swap_info,
+                ..builder
+            }
+        );
+        let ix = loaded
+            .swap(&Pubkey::new_unique(), &swap_info.token_a.mint, 100, 90)
+            .unwrap();
+        assert_eq!(
+            ix.accounts[1],
+            AccountMeta::new_readonly(shared_authority, false)
+        );
+        assert_eq!(
+            ix.accounts.last(),
+            Some(&AccountMeta::new_readonly(
+                Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID).0,
+                false
+            ))
+        );
+    }
+
+    #[test]
+    fn test_swap_direction() {
+        let builder = builder();
+        let user = Pubkey::new_unique();
+        let SwapInfo {
+            token_a, token_b, ..
+        } = builder.swap_info;
+
+        let ix = builder.swap(&user, &token_a.mint, 100, 90).unwrap();
+        let mut expected = instruction::swap(
+            &spl_token::id(),
+            &builder.swap_pubkey,
+            &builder.swap_authority,
+            &user,
+            &get_associated_token_address(&user, &token_a.mint),
+            &token_a.reserves,
+            &token_b.reserves,
+            &get_associated_token_address(&user, &token_b.mint),
+            &token_b.admin_fees,
+            100,
+            90,
+        )
+        .unwrap();
+        expected
+            .accounts
+            .push(AccountMeta::new_readonly(get_global_state_address(), false));
+        assert_eq!(ix, expected);
+
+        let ix = builder.swap(&user, &token_b.mint, 100, 90).unwrap();
+        assert_eq!(ix.accounts[4], AccountMeta::new(token_b.reserves, false));
+        assert_eq!(ix.accounts[5], AccountMeta::new(token_a.reserves, false));
+        assert_eq!(ix.accounts[7], AccountMeta::new(token_a.admin_fees, false));
+
+        assert_eq!(
+            builder.swap(&user, &builder.swap_info.pool_mint, 100, 90),
+            Err(SwapError::IncorrectMint.into())
+        );
+    }
+
+    #[test]
+    fn test_deposit_and_withdraw() {
+        let builder = builder();
+        let user = Pubkey::new_unique();
+        let SwapInfo {
+            token_a,
+            token_b,
+            pool_mint,
+            ..
+        } = builder.swap_info;
+        let user_pool = get_associated_token_address(&user, &pool_mint);
+
+        let ix = builder.deposit(&user, 10, 20, 5).unwrap();
+        assert_eq!(
+            ix.accounts.last(),
+            Some(&AccountMeta::new_readonly(builder.global_state, false))
+        );
+        assert_eq!(
+            ix.accounts[3],
+            AccountMeta::new(get_associated_token_address(&user, &token_a.mint), false)
+        );
+        assert_eq!(ix.accounts[8], AccountMeta::new(user_pool, false));
+
+        let ix = builder.withdraw(&user, 10, 1, 2).unwrap();
+        assert_eq!(ix.accounts[4], AccountMeta::new(user_pool, false));
+        assert_eq!(ix.accounts[9], AccountMeta::new(token_a.admin_fees, false));
+        assert_eq!(ix.accounts[10], AccountMeta::new(token_b.admin_fees, false));
+
+        let ix = builder.withdraw_one(&user, &token_b.mint, 10, 1).unwrap();
+        assert_eq!(ix.accounts[5], AccountMeta::new(token_b.reserves, false));
+        assert_eq!(ix.accounts[6], AccountMeta::new(token_a.reserves, false));
+        assert_eq!(
+            ix.accounts[7],
+            AccountMeta::new(get_associated_token_address(&user, &token_b.mint), false)
+        );
+        assert_eq!(ix.accounts[8], AccountMeta::new(token_b.admin_fees, false));
+    }
+}
@@ -92,7 +92,7 @@ pub struct RampAData {
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum AdminInstruction {
-    /// Starts a ramp of A to the next value.
+    /// Schedules a ramp of A to the next value, starting 1 day later.
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
@@ -140,7 +140,7 @@ pub enum AdminInstruction {
     /// 3. `[]` Clock sysvar
     CommitNewAdmin,
 
-    /// Updates the swap fees.
+    /// Commits new swap fees, which can be applied after 1 day.
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
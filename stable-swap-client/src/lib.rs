@@ -4,6 +4,7 @@
 #![allow(rustdoc::missing_doc_code_examples)]
 #![deny(missing_docs)]
 
+pub mod builder;
 pub mod error;
 pub mod fees;
 pub mod instruction;
@@ -25,9 +25,16 @@ pub struct SwapInfo {
     /// token mint.
     pub nonce: u8,
 
-    /// Initial amplification coefficient (A)
+    /// Whether the token accounts of the pool are owned by the program-wide authority
+    /// derived from [SHARED_AUTHORITY_SEED] instead of one derived from the swap.
+    /// Not stored in legacy accounts.
+    pub shared_authority: bool,
+
+    /// Initial amplification coefficient (A), whole. Coefficients stored in hundredths
+    /// are rounded down.
     pub initial_amp_factor: u64,
-    /// Target amplification coefficient (A)
+    /// Target amplification coefficient (A), whole. Coefficients stored in hundredths
+    /// are rounded down.
     pub target_amp_factor: u64,
     /// Ramp A start timestamp
     pub start_ramp_ts: i64,
@@ -67,6 +74,34 @@ pub struct SwapTokenInfo {
     pub index: u8,
 }
 
+/// Layout version of swap accounts of [SwapInfo::LEN] bytes, stored in their second
+/// byte, where the legacy layout stored `is_paused`.
+pub const SWAP_INFO_VERSION: u8 = 4;
+/// Layout version of swap accounts storing whole amplification coefficients, with
+/// their magic bytes and checksum.
+pub const SWAP_INFO_VERSION_WHOLE_AMP: u8 = 3;
+/// Layout version of swap accounts storing whole amplification coefficients, without
+/// magic bytes nor checksum. Accounts packed by this client are of this version.
+pub const SWAP_INFO_VERSION_WITHOUT_CHECKSUM: u8 = 2;
+/// Length of swap accounts in the legacy layout of the first deployed pools, which
+/// stores each flag in its own byte and nothing after the fees.
+pub const SWAP_INFO_LEGACY_LEN: usize = 395;
+/// Amplification coefficients of the current layout version are stored in hundredths.
+pub const AMP_PRECISION: u64 = 100;
+
+/// Seed of the authority shared by the pools that opted into it, see
+/// [SwapInfo::shared_authority]
+pub const SHARED_AUTHORITY_SEED: &[u8] = b"shared_authority";
+/// Seed of the global state of the program, checked by swaps and deposits for a
+/// pause of every pool
+pub const GLOBAL_STATE_SEED: &[u8] = b"global_state";
+
+/// Flags of the current layout, packed in its first byte. Flags of the program unknown
+/// to this client are ignored.
+const FLAG_INITIALIZED: u8 = 1;
+const FLAG_PAUSED: u8 = 1 << 1;
+const FLAG_SHARED_AUTHORITY: u8 = 1 << 6;
+
 impl Sealed for SwapInfo {}
 impl IsInitialized for SwapInfo {
     fn is_initialized(&self) -> bool {
@@ -74,16 +109,28 @@ impl IsInitialized for SwapInfo {
     }
 }
 
-impl Pack for SwapInfo {
-    const LEN: usize = 395;
+impl SwapInfo {
+    /// First seed of the swap authority: the swap key, or [SHARED_AUTHORITY_SEED]
+    /// for pools with a shared authority
+    pub fn authority_seed<'a>(&self, swap_key: &'a Pubkey) -> &'a [u8] {
+        if self.shared_authority {
+            SHARED_AUTHORITY_SEED
+        } else {
+            swap_key.as_ref()
+        }
+    }
 
-    /// Unpacks a byte buffer into a [SwapInfo](struct.SwapInfo.html).
-    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
-        let input = array_ref![input, 0, 395];
+    /// Unpacks the fields shared by both layouts, the flags set aside
+    fn unpack_fields(
+        input: &[u8; SWAP_INFO_LEGACY_LEN],
+        is_initialized: bool,
+        is_paused: bool,
+        shared_authority: bool,
+        amp_precision: u64,
+    ) -> Result<Self, ProgramError> {
         #[allow(clippy::ptr_offset_with_cast)]
         let (
-            is_initialized,
-            is_paused,
+            _flags,
             nonce,
             initial_amp_factor,
             target_amp_factor,
@@ -100,21 +147,14 @@ impl Pack for SwapInfo {
             admin_fee_key_a,
             admin_fee_key_b,
             fees,
-        ) = array_refs![input, 1, 1, 1, 8, 8, 8, 8, 8, 32, 32, 32, 32, 32, 32, 32, 32, 32, 64];
+        ) = array_refs![input, 2, 1, 8, 8, 8, 8, 8, 32, 32, 32, 32, 32, 32, 32, 32, 32, 64];
         Ok(Self {
-            is_initialized: match is_initialized {
-                [0] => false,
-                [1] => true,
-                _ => return Err(ProgramError::InvalidAccountData),
-            },
-            is_paused: match is_paused {
-                [0] => false,
-                [1] => true,
-                _ => return Err(ProgramError::InvalidAccountData),
-            },
+            is_initialized,
+            is_paused,
             nonce: nonce[0],
-            initial_amp_factor: u64::from_le_bytes(*initial_amp_factor),
-            target_amp_factor: u64::from_le_bytes(*target_amp_factor),
+            shared_authority,
+            initial_amp_factor: u64::from_le_bytes(*initial_amp_factor) / amp_precision,
+            target_amp_factor: u64::from_le_bytes(*target_amp_factor) / amp_precision,
             start_ramp_ts: i64::from_le_bytes(*start_ramp_ts),
             stop_ramp_ts: i64::from_le_bytes(*stop_ramp_ts),
             future_admin_deadline: i64::from_le_bytes(*future_admin_deadline),
@@ -137,11 +177,10 @@ impl Pack for SwapInfo {
         })
     }
 
-    fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, 395];
+    /// Packs the fields shared by both layouts, the flags set aside
+    fn pack_fields(&self, output: &mut [u8; SWAP_INFO_LEGACY_LEN]) {
         let (
-            is_initialized,
-            is_paused,
+            _flags,
             nonce,
             initial_amp_factor,
             target_amp_factor,
@@ -158,9 +197,7 @@ impl Pack for SwapInfo {
             admin_fee_key_a,
             admin_fee_key_b,
             fees,
-        ) = mut_array_refs![output, 1, 1, 1, 8, 8, 8, 8, 8, 32, 32, 32, 32, 32, 32, 32, 32, 32, 64];
-        is_initialized[0] = self.is_initialized as u8;
-        is_paused[0] = self.is_paused as u8;
+        ) = mut_array_refs![output, 2, 1, 8, 8, 8, 8, 8, 32, 32, 32, 32, 32, 32, 32, 32, 32, 64];
         nonce[0] = self.nonce;
         *initial_amp_factor = self.initial_amp_factor.to_le_bytes();
         *target_amp_factor = self.target_amp_factor.to_le_bytes();
@@ -178,6 +215,101 @@ impl Pack for SwapInfo {
         admin_fee_key_b.copy_from_slice(self.token_b.admin_fees.as_ref());
         self.fees.pack_into_slice(&mut fees[..]);
     }
+
+    /// Unpacks an account in the legacy layout
+    fn unpack_legacy(input: &[u8; SWAP_INFO_LEGACY_LEN]) -> Result<Self, ProgramError> {
+        let (is_initialized, is_paused) = match (input[0], input[1]) {
+            (is_initialized @ (0 | 1), is_paused @ (0 | 1)) => {
+                (is_initialized == 1, is_paused == 1)
+            }
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Self::unpack_fields(input, is_initialized, is_paused, false, 1)
+    }
+
+    /// Packs into an account in the legacy layout
+    fn pack_into_legacy_slice(&self, output: &mut [u8; SWAP_INFO_LEGACY_LEN]) {
+        output[0] = self.is_initialized as u8;
+        output[1] = self.is_paused as u8;
+        self.pack_fields(output);
+    }
+}
+
+impl Pack for SwapInfo {
+    /// Length of the swap accounts of the current program. Pools deployed earlier may
+    /// be stored in [SWAP_INFO_LEGACY_LEN] bytes.
+    const LEN: usize = 829;
+
+    /// Unpacks an account in the current or the legacy layout.
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.len() {
+            SWAP_INFO_LEGACY_LEN => Self::unpack_legacy(array_ref![input, 0, SWAP_INFO_LEGACY_LEN]),
+            Self::LEN => Self::unpack_from_slice(input),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Packs into an account in the current or the legacy layout.
+    fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        match dst.len() {
+            SWAP_INFO_LEGACY_LEN => {
+                // Legacy accounts have no room for the shared authority flag
+                if src.shared_authority {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                src.pack_into_legacy_slice(array_mut_ref![dst, 0, SWAP_INFO_LEGACY_LEN]);
+                Ok(())
+            }
+            Self::LEN => {
+                src.pack_into_slice(dst);
+                Ok(())
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Unpacks an account in the current layout, of any version. The fields of the
+    /// program unknown to this client and the checksum are not read.
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let flags = input[0];
+        let amp_precision = match input[1] {
+            SWAP_INFO_VERSION => AMP_PRECISION,
+            SWAP_INFO_VERSION_WHOLE_AMP | SWAP_INFO_VERSION_WITHOUT_CHECKSUM => 1,
+            // Never packed
+            0 if flags == 0 => 1,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Self::unpack_fields(
+            array_ref![input, 0, SWAP_INFO_LEGACY_LEN],
+            flags & FLAG_INITIALIZED != 0,
+            flags & FLAG_PAUSED != 0,
+            flags & FLAG_SHARED_AUTHORITY != 0,
+            amp_precision,
+        )
+    }
+
+    /// Packs into an account in the current layout, as [SWAP_INFO_VERSION_WITHOUT_CHECKSUM]
+    /// with the fields of the program unknown to this client unset.
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 829];
+        output.fill(0);
+        let mut flags = 0;
+        if self.is_initialized {
+            flags |= FLAG_INITIALIZED;
+        }
+        if self.is_paused {
+            flags |= FLAG_PAUSED;
+        }
+        if self.shared_authority {
+            flags |= FLAG_SHARED_AUTHORITY;
+        }
+        output[0] = flags;
+        output[1] = SWAP_INFO_VERSION_WITHOUT_CHECKSUM;
+        self.pack_fields(array_mut_ref![output, 0, SWAP_INFO_LEGACY_LEN]);
+    }
 }
 
 #[cfg(test)]
@@ -236,6 +368,7 @@ mod tests {
             is_initialized,
             is_paused,
             nonce,
+            shared_authority: false,
             initial_amp_factor,
             target_amp_factor,
             start_ramp_ts,
@@ -264,34 +397,83 @@ mod tests {
         let unpacked = SwapInfo::unpack(&packed).unwrap();
         assert_eq!(swap_info, unpacked);
 
-        let mut packed = vec![
+        // legacy layout, one byte per flag
+        let mut legacy = vec![
             1_u8, // is_initialized
             0_u8, // is_paused
             nonce,
         ];
-        packed.extend_from_slice(&initial_amp_factor.to_le_bytes());
-        packed.extend_from_slice(&target_amp_factor.to_le_bytes());
-        packed.extend_from_slice(&start_ramp_ts.to_le_bytes());
-        packed.extend_from_slice(&stop_ramp_ts.to_le_bytes());
-        packed.extend_from_slice(&future_admin_deadline.to_le_bytes());
-        packed.extend_from_slice(&future_admin_key_raw);
-        packed.extend_from_slice(&admin_key_raw);
-        packed.extend_from_slice(&token_a_raw);
-        packed.extend_from_slice(&token_b_raw);
-        packed.extend_from_slice(&pool_mint_raw);
-        packed.extend_from_slice(&token_a_mint_raw);
-        packed.extend_from_slice(&token_b_mint_raw);
-        packed.extend_from_slice(&admin_fee_key_a_raw);
-        packed.extend_from_slice(&admin_fee_key_b_raw);
-        packed.extend_from_slice(&admin_trade_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&admin_trade_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&admin_withdraw_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&admin_withdraw_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&trade_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&trade_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&withdraw_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&withdraw_fee_denominator.to_le_bytes());
-        let unpacked = SwapInfo::unpack(&packed).unwrap();
+        legacy.extend_from_slice(&initial_amp_factor.to_le_bytes());
+        legacy.extend_from_slice(&target_amp_factor.to_le_bytes());
+        legacy.extend_from_slice(&start_ramp_ts.to_le_bytes());
+        legacy.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+        legacy.extend_from_slice(&future_admin_deadline.to_le_bytes());
+        legacy.extend_from_slice(&future_admin_key_raw);
+        legacy.extend_from_slice(&admin_key_raw);
+        legacy.extend_from_slice(&token_a_raw);
+        legacy.extend_from_slice(&token_b_raw);
+        legacy.extend_from_slice(&pool_mint_raw);
+        legacy.extend_from_slice(&token_a_mint_raw);
+        legacy.extend_from_slice(&token_b_mint_raw);
+        legacy.extend_from_slice(&admin_fee_key_a_raw);
+        legacy.extend_from_slice(&admin_fee_key_b_raw);
+        legacy.extend_from_slice(&admin_trade_fee_numerator.to_le_bytes());
+        legacy.extend_from_slice(&admin_trade_fee_denominator.to_le_bytes());
+        legacy.extend_from_slice(&admin_withdraw_fee_numerator.to_le_bytes());
+        legacy.extend_from_slice(&admin_withdraw_fee_denominator.to_le_bytes());
+        legacy.extend_from_slice(&trade_fee_numerator.to_le_bytes());
+        legacy.extend_from_slice(&trade_fee_denominator.to_le_bytes());
+        legacy.extend_from_slice(&withdraw_fee_numerator.to_le_bytes());
+        legacy.extend_from_slice(&withdraw_fee_denominator.to_le_bytes());
+        assert_eq!(legacy.len(), SWAP_INFO_LEGACY_LEN);
+        let unpacked = SwapInfo::unpack(&legacy).unwrap();
         assert_eq!(swap_info, unpacked);
+        let mut repacked = vec![0u8; SWAP_INFO_LEGACY_LEN];
+        SwapInfo::pack(swap_info, &mut repacked).unwrap();
+        assert_eq!(repacked, legacy);
+        legacy[1] = 2;
+        assert_eq!(
+            SwapInfo::unpack(&legacy),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // current layout: flags byte, layout version, the same fields, then fields of
+        // the program unknown to this client
+        let mut current = vec![0u8; SwapInfo::LEN];
+        current[..SWAP_INFO_LEGACY_LEN].copy_from_slice(&repacked);
+        current[0] = 0b1;
+        current[1] = SWAP_INFO_VERSION_WITHOUT_CHECKSUM;
+        assert_eq!(packed.to_vec(), current);
+        current[0] = 0b10_0011; // initialized, paused and deprecated
+        current[SWAP_INFO_LEGACY_LEN] = 0xff;
+        let unpacked = SwapInfo::unpack(&current).unwrap();
+        assert!(unpacked.is_initialized && unpacked.is_paused);
+        assert_eq!(unpacked.fees, fees);
+        // a shared authority, which legacy accounts have no room for
+        current[0] = 0b100_0001;
+        let shared = SwapInfo::unpack(&current).unwrap();
+        assert!(shared.shared_authority);
+        assert_eq!(
+            SwapInfo::pack(shared, &mut repacked),
+            Err(ProgramError::InvalidAccountData)
+        );
+        current[0] = 0b1;
+        current[1] = SWAP_INFO_VERSION_WHOLE_AMP;
+        assert_eq!(SwapInfo::unpack(&current).unwrap(), swap_info);
+
+        // the current version stores hundredths of coefficients
+        current[1] = SWAP_INFO_VERSION;
+        current[3..11].copy_from_slice(&(AMP_PRECISION * 3 / 2).to_le_bytes());
+        current[11..19].copy_from_slice(&AMP_PRECISION.to_le_bytes());
+        assert_eq!(SwapInfo::unpack(&current).unwrap(), swap_info);
+        current[1] = SWAP_INFO_VERSION + 1;
+        assert_eq!(
+            SwapInfo::unpack(&current),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapInfo::unpack(&current[..SwapInfo::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
     }
 }
@@ -17,6 +17,8 @@ pub const MIN_AMP: u64 = 1;
 pub const MAX_AMP: u64 = 1_000_000;
 /// Max number of tokens to swap at once.
 pub const MAX_TOKENS_IN: u64 = u64::MAX >> 4;
+/// Max number of steps of `dx` when rounding [StableSwap::get_dx]
+const MAX_DX_ROUNDING_STEPS: u64 = 8;
 
 /// Encodes all results of swapping from a source token to a destination token
 pub struct SwapResult {
@@ -87,7 +89,10 @@ impl StableSwap {
 
     /// Compute the amplification coefficient (A)
     pub fn compute_amp_factor(&self) -> Option<u64> {
-        if self.current_ts < self.stop_ramp_ts {
+        if self.current_ts < self.start_ramp_ts {
+            // scheduled ramp not started yet
+            Some(self.initial_amp_factor)
+        } else if self.current_ts < self.stop_ramp_ts {
             let time_range = self.stop_ramp_ts.checked_sub(self.start_ramp_ts)?;
             let time_delta = self.current_ts.checked_sub(self.start_ramp_ts)?;
 
@@ -295,23 +300,95 @@ impl StableSwap {
         Some((dy, dy_0 - dy))
     }
 
-    /// Compute SwapResult after an exchange
-    pub fn swap_to(
+    /// Compute the amount of destination token received for `amount_in` of source token.
+    /// Returns the amount received and the trade fee, both in destination token.
+    pub fn get_dy(
         &self,
-        source_amount: u64,
+        amount_in: u64,
         swap_source_amount: u64,
         swap_destination_amount: u64,
         fees: &Fees,
-    ) -> Option<SwapResult> {
+    ) -> Option<(u64, u64)> {
         let y = self.compute_y(
-            swap_source_amount.checked_add(source_amount)?,
+            swap_source_amount.checked_add(amount_in)?,
             self.compute_d(swap_source_amount, swap_destination_amount)?,
         )?;
         let dy = swap_destination_amount.checked_sub(y)?;
         let dy_fee = fees.trade_fee(dy)?;
+        Some((dy.checked_sub(dy_fee)?, dy_fee))
+    }
+
+    /// Compute the amount of source token needed to receive at least `amount_out` of
+    /// destination token. Returns the amount needed and the trade fee, in destination token.
+    pub fn get_dx(
+        &self,
+        amount_out: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+    ) -> Option<(u64, u64)> {
+        // dy = amount_out * fee_denominator / (fee_denominator - fee_numerator), rounded up
+        let fee_denominator = u128::from(fees.trade_fee_denominator);
+        let net_denominator = fee_denominator.checked_sub(fees.trade_fee_numerator.into())?;
+        let mut dy = u128::from(amount_out)
+            .checked_mul(fee_denominator)?
+            .checked_add(net_denominator.checked_sub(1)?)?
+            .checked_div(net_denominator)?
+            .to_u64()?;
+        while dy.checked_sub(fees.trade_fee(dy)?)? < amount_out {
+            dy = dy.checked_add(1)?;
+        }
+
+        let y = swap_destination_amount.checked_sub(dy)?;
+        let x = self.compute_y(
+            y,
+            self.compute_d(swap_source_amount, swap_destination_amount)?,
+        )?;
+        let mut dx = x.checked_sub(swap_source_amount)?;
+        // Newton's method converges to within 1, step to the smallest dx reaching the output
+        let reaches_output = |dx: u64| -> Option<(bool, u64)> {
+            let (dy, dy_fee) =
+                self.get_dy(dx, swap_source_amount, swap_destination_amount, fees)?;
+            Some((dy >= amount_out, dy_fee))
+        };
+        let (mut reached, mut dy_fee) = reaches_output(dx)?;
+        for _ in 0..MAX_DX_ROUNDING_STEPS {
+            if reached {
+                if dx == 0 {
+                    return Some((dx, dy_fee));
+                }
+                let (reached_below, dy_fee_below) = reaches_output(dx - 1)?;
+                if !reached_below {
+                    return Some((dx, dy_fee));
+                }
+                dx -= 1;
+                dy_fee = dy_fee_below;
+            } else {
+                dx = dx.checked_add(1)?;
+                let (reached_above, dy_fee_above) = reaches_output(dx)?;
+                reached = reached_above;
+                dy_fee = dy_fee_above;
+            }
+        }
+        None
+    }
+
+    /// Compute SwapResult after an exchange
+    pub fn swap_to(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        let (amount_swapped, dy_fee) = self.get_dy(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            fees,
+        )?;
         let admin_fee = fees.admin_trade_fee(dy_fee)?;
 
-        let amount_swapped = dy.checked_sub(dy_fee)?;
         let new_destination_amount = swap_destination_amount
             .checked_sub(amount_swapped)?
             .checked_sub(admin_fee)?;
@@ -326,3 +403,60 @@ impl StableSwap {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEES: Fees = Fees {
+        admin_trade_fee_numerator: 1,
+        admin_trade_fee_denominator: 2,
+        admin_withdraw_fee_numerator: 0,
+        admin_withdraw_fee_denominator: 1,
+        trade_fee_numerator: 4,
+        trade_fee_denominator: 10_000,
+        withdraw_fee_numerator: 0,
+        withdraw_fee_denominator: 1,
+    };
+
+    #[test]
+    fn test_get_dy() {
+        let invariant = StableSwap::new(100, 100, ZERO_TS, ZERO_TS, ZERO_TS);
+        let (dy, dy_fee) = invariant
+            .get_dy(1_000_000, 50_000_000, 70_000_000, &FEES)
+            .unwrap();
+        let result = invariant
+            .swap_to(1_000_000, 50_000_000, 70_000_000, &FEES)
+            .unwrap();
+        assert_eq!(dy, result.amount_swapped);
+        assert_eq!(dy_fee, result.fee);
+        assert_eq!(dy_fee, FEES.trade_fee(dy + dy_fee).unwrap());
+    }
+
+    #[test]
+    fn test_get_dx() {
+        let invariant = StableSwap::new(100, 100, ZERO_TS, ZERO_TS, ZERO_TS);
+        for &(amount_out, source, destination) in [
+            (1, 1_000, 1_000),
+            (1_000_000, 50_000_000, 70_000_000),
+            (9_999_999, 70_000_000, 20_000_000),
+            (123_456_789_000, 1_000_000_000_000, 900_000_000_000),
+        ]
+        .iter()
+        {
+            let (dx, dy_fee) = invariant
+                .get_dx(amount_out, source, destination, &FEES)
+                .unwrap();
+            let (dy, fee) = invariant.get_dy(dx, source, destination, &FEES).unwrap();
+            assert!(dy >= amount_out);
+            assert_eq!(dy_fee, fee);
+            let (dy, _) = invariant
+                .get_dy(dx - 1, source, destination, &FEES)
+                .unwrap();
+            assert!(dy < amount_out);
+        }
+
+        // more than the pool holds
+        assert_eq!(invariant.get_dx(1_001, 1_000, 1_000, &FEES), None);
+    }
+}
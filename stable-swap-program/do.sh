@@ -10,6 +10,7 @@ usage() {
     cat <<EOF
 Usage: do.sh <action> <action specific arguments>
 Supported actions:
+    bench
     build
     clean
     e2e-test
@@ -20,6 +21,12 @@ EOF
 
 perform_action() {
     case "$1" in
+    bench)
+        (
+            ./do.sh build
+            BPF_OUT_DIR="$PWD"/target/deploy cargo bench --manifest-path program/Cargo.toml --bench compute_units ${@:2}
+        )
+        ;;
     build)
         (
             cargo build-bpf --manifest-path program/Cargo.toml
@@ -0,0 +1,84 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use solana_program::{
+    instruction::CompiledInstruction, program_error::ProgramError, pubkey::Pubkey,
+};
+use stable_swap::{
+    error::SwapError,
+    instruction::{decode, AdminInstruction, SwapInstruction},
+};
+
+/// Instruction data and the accounts of a compiled instruction carrying it
+#[derive(Debug, Arbitrary)]
+struct Input {
+    data: Vec<u8>,
+    accounts: Vec<u8>,
+    num_account_keys: u8,
+}
+
+fuzz_target!(|input: Input| run_input(input));
+
+/// Decodes the data as every decoder of the program does, none of which may panic,
+/// and checks that they agree.
+fn run_input(input: Input) {
+    let data = &input.data;
+    let swap = SwapInstruction::unpack(data);
+    let admin = AdminInstruction::unpack(data);
+
+    match &swap {
+        Ok(instruction) => {
+            let packed = instruction.pack();
+            assert_eq!(SwapInstruction::unpack(&packed).as_ref(), Ok(instruction));
+            check_unpack_checked(
+                SwapInstruction::unpack_checked(data).map(|_| ()),
+                data,
+                &packed,
+            );
+        }
+        Err(err) => assert_eq!(SwapInstruction::unpack_checked(data).as_ref(), Err(err)),
+    }
+    match &admin {
+        Ok(Some(instruction)) => {
+            let packed = instruction.pack();
+            assert_eq!(
+                AdminInstruction::unpack(&packed).unwrap().as_ref(),
+                Some(instruction)
+            );
+            check_unpack_checked(
+                AdminInstruction::unpack_checked(data).map(|_| ()),
+                data,
+                &packed,
+            );
+        }
+        result => assert_eq!(&AdminInstruction::unpack_checked(data), result),
+    }
+    // tags are either admin or swap tags
+    if let Ok(Some(_)) = admin {
+        assert!(swap.is_err());
+    }
+
+    let account_keys: Vec<Pubkey> = (0..input.num_account_keys)
+        .map(|_| Pubkey::new_unique())
+        .collect();
+    let compiled = CompiledInstruction {
+        program_id_index: 0,
+        accounts: input.accounts.clone(),
+        data: input.data.clone(),
+    };
+    if let Ok(parsed) = decode(&compiled, &account_keys) {
+        assert!(matches!(admin, Ok(Some(_))) || swap.is_ok());
+        assert_eq!(parsed.accounts.len(), input.accounts.len());
+    }
+}
+
+/// Checks that unpack_checked rejects the data unpack accepts only when it is longer
+/// than the instruction packs to.
+fn check_unpack_checked(result: Result<(), ProgramError>, data: &[u8], packed: &[u8]) {
+    if data.len() > packed.len() {
+        assert_eq!(result, Err(SwapError::TrailingInstructionData.into()));
+    } else {
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -15,7 +15,7 @@ use spl_token::error::TokenError;
 use stable_swap::{
     curve::{StableSwap, MAX_AMP, MIN_AMP},
     error::SwapError,
-    fees::Fees,
+    fees::{Fees, NO_DYNAMIC_FEE, NO_FEE_TIERS},
     instruction::*,
 };
 use std::collections::{HashMap, HashSet};
@@ -107,6 +107,10 @@ fn run_actions(actions: Vec<Action>) {
         trade_fee_denominator,
         withdraw_fee_numerator,
         withdraw_fee_denominator,
+        trade_fee_tiers: NO_FEE_TIERS,
+        dynamic_fee: NO_DYNAMIC_FEE,
+        rebalancing_rebate_bps: 0,
+        host_fee_bps: 0,
     };
 
     let mut stable_swap = NativeStableSwap::new(
@@ -8,7 +8,11 @@ use arbitrary::Arbitrary;
 use solana_program::{
     bpf_loader, entrypoint::ProgramResult, program_pack::Pack, pubkey::Pubkey, system_program,
 };
-use stable_swap::{fees::Fees, instruction::*, state::SwapInfo};
+use stable_swap::{
+    fees::Fees,
+    instruction::*,
+    state::{find_global_state_address, GlobalState, SwapInfo},
+};
 /// Helper enum to tell which token for WithdrawOne.
 #[derive(Arbitrary, Clone, Debug, PartialEq)]
 pub enum TokenType {
@@ -37,6 +41,7 @@ pub struct NativeStableSwap {
     pub admin_fee_a_account: NativeAccountData,
     pub admin_fee_b_account: NativeAccountData,
     pub token_program_account: NativeAccountData,
+    pub global_state_account: NativeAccountData,
 }
 
 impl NativeStableSwap {
@@ -54,6 +59,13 @@ impl NativeStableSwap {
 
         let mut authority_account = create_program_account(authority_key);
         let mut token_program_account = create_program_account(spl_token::id());
+        let mut global_state_account = NativeAccountData::new(GlobalState::LEN, stable_swap::id());
+        global_state_account.key = find_global_state_address(&stable_swap::id()).0;
+        let global_state = GlobalState {
+            is_initialized: true,
+            ..GlobalState::default()
+        };
+        GlobalState::pack(global_state, &mut global_state_account.data).unwrap();
 
         let mut pool_mint_account = native_token::create_mint(&authority_account.key);
         let mut pool_token_account =
@@ -134,6 +146,7 @@ impl NativeStableSwap {
             admin_fee_a_account,
             admin_fee_b_account,
             token_program_account,
+            global_state_account,
         }
     }
 
@@ -202,6 +215,7 @@ impl NativeStableSwap {
                 self.admin_fee_b_account.as_account_info(),
                 self.token_program_account.as_account_info(),
                 NativeAccountData::new_clock(current_ts).as_account_info(),
+                self.global_state_account.as_account_info(),
             ],
         )
     }
@@ -243,6 +257,7 @@ impl NativeStableSwap {
                 self.admin_fee_a_account.as_account_info(),
                 self.token_program_account.as_account_info(),
                 NativeAccountData::new_clock(current_ts).as_account_info(),
+                self.global_state_account.as_account_info(),
             ],
         )
     }
@@ -289,6 +304,7 @@ impl NativeStableSwap {
                 pool_token_account.as_account_info(),
                 self.token_program_account.as_account_info(),
                 NativeAccountData::new_clock(current_ts).as_account_info(),
+                self.global_state_account.as_account_info(),
             ],
         )
     }
@@ -0,0 +1,138 @@
+//! Compute unit benchmarks of the instructions going through the curve math.
+//!
+//! Run with `./do.sh bench`, which builds and loads the BPF program. Natively the
+//! program's own instructions are not metered, so a native `cargo bench` only prints
+//! what it measured. Against BPF, a case fails when it uses more than
+//! [`MAX_REGRESSION_PERCENT`] above its entry in `benches/compute_units.txt`;
+//! `UPDATE_CU_BASELINE=1 ./do.sh bench` records the current numbers instead.
+
+#![allow(clippy::unwrap_used)]
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::TestPool;
+use solana_program_test::tokio;
+use solana_sdk::instruction::Instruction;
+use stable_swap::curve::{MAX_AMP, MIN_AMP};
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::PathBuf, process};
+
+/// Allowed growth over the recorded baseline before a case fails
+const MAX_REGRESSION_PERCENT: u64 = 10;
+
+const AMP_FACTORS: [u64; 4] = [MIN_AMP, 100, 10_000, MAX_AMP];
+const RESERVES: [u64; 3] = [1_000_000, 1_000_000_000_000, 1_000_000_000_000_000_000];
+
+/// Instruction name, amplification factor and reserves of each token
+type Case = (&'static str, u64, u64);
+
+fn baseline_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/compute_units.txt")
+}
+
+fn is_bpf() -> bool {
+    env::var_os("BPF_OUT_DIR").is_some() || env::var_os("SBF_OUT_DIR").is_some()
+}
+
+fn read_baseline() -> BTreeMap<(String, u64, u64), u64> {
+    let contents = fs::read_to_string(baseline_path()).unwrap_or_default();
+    contents
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields.len(), 4, "malformed baseline line: {}", line);
+            (
+                (
+                    fields[0].to_string(),
+                    fields[1].parse().unwrap(),
+                    fields[2].parse().unwrap(),
+                ),
+                fields[3].parse().unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn write_baseline(results: &[(Case, u64)]) {
+    let mut contents = String::from("# instruction amp_factor reserves compute_units\n");
+    for ((name, amp_factor, reserves), units) in results {
+        writeln!(contents, "{} {} {} {}", name, amp_factor, reserves, units).unwrap();
+    }
+    fs::write(baseline_path(), contents).unwrap();
+}
+
+/// Measures every benchmarked instruction against a fresh balanced pool
+async fn measure(amp_factor: u64, reserves: u64) -> Vec<(Case, u64)> {
+    let mut pool = TestPool::with_config(amp_factor, reserves, reserves, reserves / 10).await;
+    pool.initialize().await;
+    let pool_tokens = pool.token_balance(&pool.user_pool_token.clone()).await;
+
+    let instructions: [(&'static str, Instruction); 4] = [
+        ("swap", pool.swap_instruction(true, reserves / 1_000, 0)),
+        (
+            "deposit",
+            pool.deposit_instruction(reserves / 100, reserves / 100),
+        ),
+        ("withdraw", pool.withdraw_instruction(pool_tokens / 4)),
+        (
+            "withdraw_one",
+            pool.withdraw_one_instruction(pool_tokens / 4),
+        ),
+    ];
+    let mut results = vec![];
+    for (name, instruction) in instructions {
+        let units = pool.compute_units(instruction, &[]).await;
+        results.push(((name, amp_factor, reserves), units));
+    }
+    results
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut results = vec![];
+    for amp_factor in AMP_FACTORS {
+        for reserves in RESERVES {
+            results.extend(measure(amp_factor, reserves).await);
+        }
+    }
+
+    if env::var_os("UPDATE_CU_BASELINE").is_some() {
+        assert!(
+            is_bpf(),
+            "the baseline must be recorded against the BPF program"
+        );
+        write_baseline(&results);
+        println!("recorded {} cases to {:?}", results.len(), baseline_path());
+        return;
+    }
+
+    let baseline = read_baseline();
+    let mut failures = 0;
+    for ((name, amp_factor, reserves), units) in &results {
+        let recorded = baseline.get(&(name.to_string(), *amp_factor, *reserves));
+        let verdict = match recorded {
+            _ if !is_bpf() => "native, not compared".to_string(),
+            None => {
+                failures += 1;
+                "no baseline".to_string()
+            }
+            Some(&recorded) if units * 100 > recorded * (100 + MAX_REGRESSION_PERCENT) => {
+                failures += 1;
+                format!("REGRESSED from {}", recorded)
+            }
+            Some(recorded) => format!("baseline {}", recorded),
+        };
+        println!(
+            "{:<12} amp={:<7} reserves={:<19} {:>7} CUs  {}",
+            name, amp_factor, reserves, units, verdict
+        );
+    }
+    if failures > 0 {
+        eprintln!(
+            "{} case(s) regressed by more than {}% or have no baseline",
+            failures, MAX_REGRESSION_PERCENT
+        );
+        process::exit(1);
+    }
+}
@@ -0,0 +1,64 @@
+//! Minimal interface to the SPL Associated Token Account program, used to create
+//! the token accounts of users that do not have one yet.
+
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+
+solana_program::declare_id!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Finds the associated token account address of a wallet for a mint
+pub fn find_associated_token_address(wallet: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), spl_token::id().as_ref(), mint.as_ref()],
+        &id(),
+    )
+}
+
+/// Creates a `Create` instruction. The rent sysvar is not passed, which
+/// requires version 1.0.3 or later of the associated token account program.
+pub fn create_associated_token_account(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    let (associated_token_address, _bump_seed) = find_associated_token_address(wallet, mint);
+    Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(associated_token_address, false),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_associated_token_account() {
+        let payer = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let ix = create_associated_token_account(&payer, &wallet, &mint);
+        assert_eq!(ix.program_id, id());
+        assert!(ix.data.is_empty());
+        assert!(ix.accounts[0].is_signer);
+        assert_eq!(
+            ix.accounts[1].pubkey,
+            find_associated_token_address(&wallet, &mint).0
+        );
+        assert_ne!(
+            find_associated_token_address(&wallet, &mint),
+            find_associated_token_address(&mint, &wallet)
+        );
+    }
+}
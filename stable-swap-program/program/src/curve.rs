@@ -1,8 +1,12 @@
 //! Swap calculations and curve invariant implementation
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use num_traits::ToPrimitive;
 
-use crate::{bn::U192, fees::Fees};
+use crate::{
+    bn::{U192, U256},
+    fees::{Fees, BPS_DENOMINATOR},
+};
 
 /// Number of coins
 const N_COINS: u8 = 2;
@@ -10,12 +14,69 @@ const N_COINS: u8 = 2;
 pub const ZERO_TS: i64 = 0;
 /// Minimum ramp duration
 pub const MIN_RAMP_DURATION: i64 = 86400;
+/// Delay between scheduling a ramp of A or of the fees and its start
+pub const RAMP_DELAY: i64 = 86400;
 /// Min amplification coefficient
 pub const MIN_AMP: u64 = 1;
 /// Max amplification coefficient
 pub const MAX_AMP: u64 = 1_000_000;
+/// Amplification coefficients are stored and ramped in hundredths of A, so that ramps
+/// move the price in small steps. [MIN_AMP] and [MAX_AMP] are in whole units.
+pub const AMP_PRECISION: u64 = 100;
 /// Max number of tokens to swap at once.
 pub const MAX_TOKENS_IN: u64 = u64::MAX >> 4;
+/// Divisor of the reserves the initial pool token supply is computed on, for pools
+/// initialized with a reserve above [MAX_TOKENS_IN], whose invariant may not fit the
+/// math. The curve of such a pool runs on its reserves divided by INITIAL_SUPPLY_SCALE
+/// too, so that its D stays in proportion to its pool token supply.
+pub const INITIAL_SUPPLY_SCALE: u64 = 1 << 4;
+/// Peg rates are the value of a token in the other token of the pool, in billionths
+pub const PEG_RATE_PRECISION: u64 = 1_000_000_000;
+/// Units taken off the amount out of [StableSwap::quick_swap_to], covering the
+/// rounding of D and y by the full solve
+pub const QUICK_SWAP_ROUNDING_MARGIN: u64 = 1;
+
+/// Value of `amount` of a token of the given peg rate, rounded down.
+/// The curve runs on these virtual amounts, so that a pegged pool is balanced
+/// when its reserves are worth the same rather than when they are equal.
+fn to_virtual_amount(amount: u64, rate: u64) -> Option<u64> {
+    if rate == PEG_RATE_PRECISION {
+        return Some(amount);
+    }
+    u128::from(amount)
+        .checked_mul(rate.into())?
+        .checked_div(PEG_RATE_PRECISION.into())?
+        .to_u64()
+}
+
+/// Amount of a token of the given peg rate a virtual amount is worth, rounded down
+fn from_virtual_amount(amount: u64, rate: u64) -> Option<u64> {
+    if rate == PEG_RATE_PRECISION {
+        return Some(amount);
+    }
+    u128::from(amount)
+        .checked_mul(PEG_RATE_PRECISION.into())?
+        .checked_div(rate.into())?
+        .to_u64()
+}
+
+/// Whether a deposit of `deposit_amount_a` and `deposit_amount_b` into reserves of
+/// `swap_amount_a` and `swap_amount_b` is proportional to them up to rounding, i.e.
+/// one of the deposit amounts is within one token of the amount proportional to the
+/// other
+pub fn is_proportional_deposit(
+    deposit_amount_a: u64,
+    deposit_amount_b: u64,
+    swap_amount_a: u64,
+    swap_amount_b: u64,
+) -> bool {
+    if deposit_amount_a == 0 || deposit_amount_b == 0 || swap_amount_a == 0 || swap_amount_b == 0 {
+        return false;
+    }
+    let a_side = u128::from(deposit_amount_a) * u128::from(swap_amount_b);
+    let b_side = u128::from(deposit_amount_b) * u128::from(swap_amount_a);
+    a_side.abs_diff(b_side) < u128::from(swap_amount_a.max(swap_amount_b))
+}
 
 /// Encodes all results of swapping from a source token to a destination token
 pub struct SwapResult {
@@ -29,13 +90,212 @@ pub struct SwapResult {
     pub admin_fee: u64,
     /// Fee for the swap
     pub fee: u64,
+    /// Amount of destination token the source amount is worth at the marginal
+    /// price of the pool before the swap, before fees. Zero if it could not be computed.
+    pub spot_amount_swapped: u64,
+}
+
+impl SwapResult {
+    /// Price impact of the swap in basis points: how much less than
+    /// [spot_amount_swapped](#structfield.spot_amount_swapped) the swap paid out
+    /// before fees. Rounds up, so that a swap never shows less impact than it had.
+    pub fn price_impact_bps(&self) -> Option<u64> {
+        if self.spot_amount_swapped == 0 {
+            return Some(0);
+        }
+        let amount_before_fees = self.amount_swapped.checked_add(self.fee)?;
+        let shortfall = self.spot_amount_swapped.saturating_sub(amount_before_fees);
+        crate::math::mul_div_ceil(shortfall, BPS_DENOMINATOR, self.spot_amount_swapped)
+    }
+}
+
+/// Invariant a pool trades on, chosen at initialization. Another curve is a new variant
+/// with its own [CurveCalculator], existing pools keep theirs.
+#[repr(u8)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CurveType {
+    /// The StableSwap invariant, see [StableSwap]
+    StableSwap = 0,
+}
+
+impl CurveType {
+    /// Converts a packed curve type
+    pub fn from_u8(curve_type: u8) -> Option<Self> {
+        match curve_type {
+            0 => Some(CurveType::StableSwap),
+            _ => None,
+        }
+    }
+
+    /// Calculator of the curve at `current_ts`, given the amplification coefficients
+    /// and ramp stored in the pool
+    pub fn calculator(
+        self,
+        initial_amp_factor: u64,
+        target_amp_factor: u64,
+        current_ts: i64,
+        start_ramp_ts: i64,
+        stop_ramp_ts: i64,
+    ) -> Box<dyn CurveCalculator> {
+        match self {
+            CurveType::StableSwap => Box::new(StableSwap::new(
+                initial_amp_factor,
+                target_amp_factor,
+                current_ts,
+                start_ramp_ts,
+                stop_ramp_ts,
+            )),
+        }
+    }
+}
+
+/// Calculations of a two token invariant, in the amounts of the pool
+pub trait CurveCalculator {
+    /// Invariant of the reserves
+    fn compute_d(&self, amount_a: u64, amount_b: u64) -> Option<U192>;
+
+    /// Result of swapping `source_amount` into the pool
+    fn swap_to(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult>;
+
+    /// Base tokens withdrawn for `pool_token_amount` and the fee they were charged
+    fn withdraw_one(
+        &self,
+        pool_token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        fees: &Fees,
+    ) -> Option<(u64, u64)>;
+
+    /// Pool tokens minted for a deposit
+    fn mint_for_deposit(
+        &self,
+        deposit_amount_a: u64,
+        deposit_amount_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        fees: &Fees,
+    ) -> Option<u64>;
+
+    /// Invariant of the reserves of tokens worth `rate_a` and `rate_b`, in
+    /// [PEG_RATE_PRECISION] units
+    fn compute_d_with_rates(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        rate_a: u64,
+        rate_b: u64,
+    ) -> Option<U192>;
+
+    /// Pool tokens minted for the first deposit, and whether the deposit was scaled
+    /// down to fit the calculations
+    fn initial_mint_amount(&self, amount_a: u64, amount_b: u64) -> Option<(u64, bool)>;
+
+    /// [mint_for_deposit](#tymethod.mint_for_deposit) with rates
+    #[allow(clippy::too_many_arguments)]
+    fn mint_for_deposit_with_rates(
+        &self,
+        deposit_amount_a: u64,
+        deposit_amount_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        rate_a: u64,
+        rate_b: u64,
+        fees: &Fees,
+    ) -> Option<u64>;
+
+    /// Pool tokens minted for admin fees left in the reserves, with rates
+    #[allow(clippy::too_many_arguments)]
+    fn admin_fee_mint_amount_with_rates(
+        &self,
+        admin_fee_a: u64,
+        admin_fee_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        rate_a: u64,
+        rate_b: u64,
+    ) -> Option<u64>;
+
+    /// [swap_to](#tymethod.swap_to) with rates
+    fn swap_to_with_rates(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult>;
+
+    /// [swap_to_with_rates](#tymethod.swap_to_with_rates) paying out a cheaper estimate
+    /// for swaps up to `quick_swap_max_bps` of the source reserves
+    #[allow(clippy::too_many_arguments)]
+    fn swap_to_with_rates_quick(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        quick_swap_max_bps: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult>;
+
+    /// [withdraw_one](#tymethod.withdraw_one) with rates
+    #[allow(clippy::too_many_arguments)]
+    fn withdraw_one_with_rates(
+        &self,
+        pool_token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        base_rate: u64,
+        quote_rate: u64,
+        fees: &Fees,
+    ) -> Option<(u64, u64)>;
+
+    /// Pool tokens burnt to withdraw exactly `token_amount` base tokens, with rates
+    #[allow(clippy::too_many_arguments)]
+    fn withdraw_one_exact_out_with_rates(
+        &self,
+        token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        base_rate: u64,
+        quote_rate: u64,
+        fees: &Fees,
+    ) -> Option<u64>;
+
+    /// Part of a single token deposit to swap first and the result of the swap, with
+    /// rates
+    fn zap_in_swap_with_rates(
+        &self,
+        amount_in: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        fees: &Fees,
+    ) -> Option<(u64, SwapResult)>;
 }
 
 /// The StableSwap invariant calculator.
 pub struct StableSwap {
-    /// Initial amplification coefficient (A)
+    /// Initial amplification coefficient (A), in [AMP_PRECISION] units
     initial_amp_factor: u64,
-    /// Target amplification coefficient (A)
+    /// Target amplification coefficient (A), in [AMP_PRECISION] units
     target_amp_factor: u64,
     /// Current unix timestamp
     current_ts: i64,
@@ -46,7 +306,7 @@ pub struct StableSwap {
 }
 
 impl StableSwap {
-    /// New StableSwap calculator
+    /// New StableSwap calculator, with amplification coefficients in [AMP_PRECISION] units
     pub fn new(
         initial_amp_factor: u64,
         target_amp_factor: u64,
@@ -72,21 +332,45 @@ impl StableSwap {
     ) -> Option<U192> {
         let ann = amp_factor.checked_mul(N_COINS.into())?;
         let leverage = (sum_x as u128).checked_mul(ann.into())?;
-        // d = (ann * sum_x + d_prod * n_coins) * d / ((ann - 1) * d + (n_coins + 1) * d_prod)
+        // d = (ann * sum_x + d_prod * n_coins) * d / ((ann - 1) * d + (n_coins + 1) * d_prod),
+        // with ann and both sides scaled by AMP_PRECISION
         let numerator = d_init.checked_mul(
             d_prod
-                .checked_mul(N_COINS.into())?
+                .checked_mul((u64::from(N_COINS) * AMP_PRECISION).into())?
                 .checked_add(leverage.into())?,
         )?;
         let denominator = d_init
-            .checked_mul(ann.checked_sub(1)?.into())?
-            .checked_add(d_prod.checked_mul((N_COINS + 1).into())?)?;
+            .checked_mul(ann.checked_sub(AMP_PRECISION)?.into())?
+            .checked_add(d_prod.checked_mul((u64::from(N_COINS + 1) * AMP_PRECISION).into())?)?;
+        numerator.checked_div(denominator)
+    }
+
+    /// [compute_next_d](#method.compute_next_d) in u128
+    fn compute_next_d_u128(
+        amp_factor: u64,
+        d_init: u128,
+        d_prod: u128,
+        sum_x: u64,
+    ) -> Option<u128> {
+        let ann = amp_factor.checked_mul(N_COINS.into())?;
+        let leverage = (sum_x as u128).checked_mul(ann.into())?;
+        let numerator = d_init.checked_mul(
+            d_prod
+                .checked_mul((u64::from(N_COINS) * AMP_PRECISION).into())?
+                .checked_add(leverage)?,
+        )?;
+        let denominator = d_init
+            .checked_mul(ann.checked_sub(AMP_PRECISION)?.into())?
+            .checked_add(d_prod.checked_mul((u64::from(N_COINS + 1) * AMP_PRECISION).into())?)?;
         numerator.checked_div(denominator)
     }
 
-    /// Compute the amplification coefficient (A)
+    /// Compute the amplification coefficient (A), in [AMP_PRECISION] units
     pub fn compute_amp_factor(&self) -> Option<u64> {
-        if self.current_ts < self.stop_ramp_ts {
+        if self.current_ts < self.start_ramp_ts {
+            // scheduled ramp not started yet
+            Some(self.initial_amp_factor)
+        } else if self.current_ts < self.stop_ramp_ts {
             let time_range = self.stop_ramp_ts.checked_sub(self.start_ramp_ts)?;
             let time_delta = self.current_ts.checked_sub(self.start_ramp_ts)?;
 
@@ -130,6 +414,16 @@ impl StableSwap {
             let amount_a_times_coins = amount_a.checked_mul(N_COINS.into())?;
             let amount_b_times_coins = amount_b.checked_mul(N_COINS.into())?;
 
+            // Most pools never leave u128, which costs far fewer compute units
+            if let Some(d) = Self::compute_d_u128(
+                amp_factor,
+                sum_x,
+                amount_a_times_coins,
+                amount_b_times_coins,
+            ) {
+                return Some(d.into());
+            }
+
             // Newton's method to approximate D
             let mut d_prev: U192;
             let mut d: U192 = sum_x.into();
@@ -157,7 +451,57 @@ impl StableSwap {
         }
     }
 
-    /// Compute the amount of pool tokens to mint after a deposit
+    /// [compute_d](#method.compute_d) in u128, `None` if an intermediate value overflows
+    fn compute_d_u128(
+        amp_factor: u64,
+        sum_x: u64,
+        amount_a_times_coins: u64,
+        amount_b_times_coins: u64,
+    ) -> Option<u128> {
+        let mut d_prev: u128;
+        let mut d: u128 = sum_x.into();
+        for _ in 0..256 {
+            let mut d_prod = d;
+            d_prod = d_prod
+                .checked_mul(d)?
+                .checked_div(amount_a_times_coins.into())?;
+            d_prod = d_prod
+                .checked_mul(d)?
+                .checked_div(amount_b_times_coins.into())?;
+            d_prev = d;
+            d = Self::compute_next_d_u128(amp_factor, d, d_prod, sum_x)?;
+            // Equality with the precision of 1
+            if d > d_prev {
+                if d - d_prev <= 1 {
+                    break;
+                }
+            } else if d_prev - d <= 1 {
+                break;
+            }
+        }
+        Some(d)
+    }
+
+    /// Compute the amount of pool tokens to mint on initialization, the invariant of the
+    /// reserves, and whether it was computed on reserves divided by
+    /// [INITIAL_SUPPLY_SCALE] because one of them is above [MAX_TOKENS_IN]
+    pub fn compute_initial_mint_amount(&self, amount_a: u64, amount_b: u64) -> Option<(u64, bool)> {
+        let is_scaled = amount_a > MAX_TOKENS_IN || amount_b > MAX_TOKENS_IN;
+        let (amount_a, amount_b) = if is_scaled {
+            (
+                amount_a / INITIAL_SUPPLY_SCALE,
+                amount_b / INITIAL_SUPPLY_SCALE,
+            )
+        } else {
+            (amount_a, amount_b)
+        };
+        let mint_amount = self.compute_d(amount_a, amount_b)?.to_u64()?;
+        Some((mint_amount, is_scaled))
+    }
+
+    /// Compute the amount of pool tokens to mint after a deposit. Fees are charged on
+    /// the imbalance of the deposit only, so deposits proportional to the reserves, see
+    /// [is_proportional_deposit], are minted their share of the supply without fees.
     pub fn compute_mint_amount_for_deposit(
         &self,
         deposit_amount_a: u64,
@@ -167,6 +511,23 @@ impl StableSwap {
         pool_token_supply: u64,
         fees: &Fees,
     ) -> Option<u64> {
+        if pool_token_supply > 0
+            && is_proportional_deposit(
+                deposit_amount_a,
+                deposit_amount_b,
+                swap_amount_a,
+                swap_amount_b,
+            )
+        {
+            // No imbalance to charge a fee on, and the invariant grows in proportion.
+            // Mints for the smaller of the two shares, rounded down, the rest of the
+            // other token is rounding dust left to the pool.
+            let mint_amount_a =
+                crate::math::mul_div_floor(pool_token_supply, deposit_amount_a, swap_amount_a)?;
+            let mint_amount_b =
+                crate::math::mul_div_floor(pool_token_supply, deposit_amount_b, swap_amount_b)?;
+            return Some(mint_amount_a.min(mint_amount_b));
+        }
         // Initial invariant
         let d_0 = self.compute_d(swap_amount_a, swap_amount_b)?;
         let old_balances = [swap_amount_a, swap_amount_b];
@@ -195,6 +556,7 @@ impl StableSwap {
             }
 
             let d_2 = self.compute_d(new_balances[0], new_balances[1])?;
+            // Rounds down, the minted pool tokens are a claim on the pool
             U192::from(pool_token_supply)
                 .checked_mul(d_2.checked_sub(d_0)?)?
                 .checked_div(d_0)?
@@ -202,6 +564,99 @@ impl StableSwap {
         }
     }
 
+    /// [compute_mint_amount_for_deposit](#method.compute_mint_amount_for_deposit) in a pool
+    /// whose tokens are worth `rate_a` and `rate_b`, in [PEG_RATE_PRECISION] units
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_mint_amount_for_deposit_with_rates(
+        &self,
+        deposit_amount_a: u64,
+        deposit_amount_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        rate_a: u64,
+        rate_b: u64,
+        fees: &Fees,
+    ) -> Option<u64> {
+        self.compute_mint_amount_for_deposit(
+            to_virtual_amount(deposit_amount_a, rate_a)?,
+            to_virtual_amount(deposit_amount_b, rate_b)?,
+            to_virtual_amount(swap_amount_a, rate_a)?,
+            to_virtual_amount(swap_amount_b, rate_b)?,
+            pool_token_supply,
+            fees,
+        )
+    }
+
+    /// [compute_d](#method.compute_d) of a pool whose tokens are worth `rate_a` and
+    /// `rate_b`, in [PEG_RATE_PRECISION] units
+    pub fn compute_d_with_rates(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        rate_a: u64,
+        rate_b: u64,
+    ) -> Option<U192> {
+        self.compute_d(
+            to_virtual_amount(amount_a, rate_a)?,
+            to_virtual_amount(amount_b, rate_b)?,
+        )
+    }
+
+    /// Compute the amount of pool tokens worth the admin fees left in the reserves,
+    /// `swap_amount_a` and `swap_amount_b` including them. Minting
+    /// `supply * (D_1 - D_0) / D_0`, with `D_0` the invariant without the admin fees and
+    /// `D_1` the invariant with them, gives the admin the share `(D_1 - D_0) / D_1` of
+    /// the pool.
+    pub fn compute_admin_fee_mint_amount(
+        &self,
+        admin_fee_a: u64,
+        admin_fee_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+    ) -> Option<u64> {
+        let d_0 = self.compute_d(
+            swap_amount_a.checked_sub(admin_fee_a)?,
+            swap_amount_b.checked_sub(admin_fee_b)?,
+        )?;
+        let d_1 = self.compute_d(swap_amount_a, swap_amount_b)?;
+        // Rounds down, the minted pool tokens are a claim on the pool
+        U192::from(pool_token_supply)
+            .checked_mul(d_1.checked_sub(d_0)?)?
+            .checked_div(d_0)?
+            .to_u64()
+    }
+
+    /// [compute_admin_fee_mint_amount](#method.compute_admin_fee_mint_amount) in a pool
+    /// whose tokens are worth `rate_a` and `rate_b`, in [PEG_RATE_PRECISION] units
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_admin_fee_mint_amount_with_rates(
+        &self,
+        admin_fee_a: u64,
+        admin_fee_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        rate_a: u64,
+        rate_b: u64,
+    ) -> Option<u64> {
+        // The reserves without the fees are rounded the same way as with them
+        let swap_amount_a_without_fee =
+            to_virtual_amount(swap_amount_a.checked_sub(admin_fee_a)?, rate_a)?;
+        let swap_amount_b_without_fee =
+            to_virtual_amount(swap_amount_b.checked_sub(admin_fee_b)?, rate_b)?;
+        let swap_amount_a = to_virtual_amount(swap_amount_a, rate_a)?;
+        let swap_amount_b = to_virtual_amount(swap_amount_b, rate_b)?;
+        self.compute_admin_fee_mint_amount(
+            swap_amount_a.checked_sub(swap_amount_a_without_fee)?,
+            swap_amount_b.checked_sub(swap_amount_b_without_fee)?,
+            swap_amount_a,
+            swap_amount_b,
+            pool_token_supply,
+        )
+    }
+
     /// Compute swap amount `y` in proportion to `x`
     /// Solve for y:
     /// y**2 + y * (sum' - (A*n**n - 1) * D / (A * n**n)) = D ** (n + 1) / (n ** (2 * n) * prod' * A)
@@ -209,7 +664,11 @@ impl StableSwap {
     #[allow(clippy::many_single_char_names)]
     pub fn compute_y_raw(&self, x: u64, d: U192) -> Option<U192> {
         let amp_factor = self.compute_amp_factor()?;
-        let ann = amp_factor.checked_mul(N_COINS.into())?; // A * n ** n
+        let ann = amp_factor.checked_mul(N_COINS.into())?; // A * n ** n * AMP_PRECISION
+
+        if let Some(y) = d.to_u128().and_then(|d| Self::compute_y_u128(ann, x, d)) {
+            return Some(y.into());
+        }
 
         // sum' = prod' = x
         // c =  D ** (n + 1) / (n ** (2 * n) * prod' * A)
@@ -218,9 +677,13 @@ impl StableSwap {
             .checked_div(x.checked_mul(N_COINS.into())?.into())?;
         c = c
             .checked_mul(d)?
+            .checked_mul(AMP_PRECISION.into())?
             .checked_div(ann.checked_mul(N_COINS.into())?.into())?;
         // b = sum' - (A*n**n - 1) * D / (A * n**n)
-        let b = d.checked_div(ann.into())?.checked_add(x.into())?; // d is subtracted on line 147
+        let b = d
+            .checked_mul(AMP_PRECISION.into())?
+            .checked_div(ann.into())?
+            .checked_add(x.into())?; // d is subtracted in the loop
 
         // Solve for y by approximating: y**2 + b*y = c
         let mut y_prev: U192;
@@ -242,11 +705,122 @@ impl StableSwap {
         Some(y)
     }
 
-    /// Compute swap amount `y` in proportion to `x`
+    /// [compute_y_raw](#method.compute_y_raw) in u128, `None` if an intermediate value overflows
+    fn compute_y_u128(ann: u64, x: u64, d: u128) -> Option<u128> {
+        let (b, c) = Self::compute_y_coefficients_u128(ann, x, d)?;
+
+        let mut y_prev: u128;
+        let mut y = d;
+        for _ in 0..256 {
+            y_prev = y;
+            let y_numerator = y.checked_mul(y)?.checked_add(c)?;
+            let y_denominator = y.checked_mul(2)?.checked_add(b)?.checked_sub(d)?;
+            y = y_numerator.checked_div(y_denominator)?;
+            if y > y_prev {
+                if y - y_prev <= 1 {
+                    break;
+                }
+            } else if y_prev - y <= 1 {
+                break;
+            }
+        }
+        Some(y)
+    }
+
+    /// `b` and `c` of `y**2 + (b - D) * y = c`, see [compute_y_raw](#method.compute_y_raw)
+    fn compute_y_coefficients_u128(ann: u64, x: u64, d: u128) -> Option<(u128, u128)> {
+        let mut c = d
+            .checked_mul(d)?
+            .checked_div(x.checked_mul(N_COINS.into())?.into())?;
+        c = c
+            .checked_mul(d)?
+            .checked_mul(AMP_PRECISION.into())?
+            .checked_div(ann.checked_mul(N_COINS.into())?.into())?;
+        let b = d
+            .checked_mul(AMP_PRECISION.into())?
+            .checked_div(ann.into())?
+            .checked_add(x.into())?;
+        Some((b, c))
+    }
+
+    /// Solves `y**2 + (b - D) * y = c` of [compute_y_raw](#method.compute_y_raw) with the
+    /// quadratic formula instead of Newton's method: `y = (D - b + sqrt((b - D)**2 + 4c)) / 2`.
+    /// The real root lies in `[n / 2, (n + 1) / 2)`, `n` being the numerator with the
+    /// square root rounded down, so the root rounded up is `n / 2`, or `n / 2 + 1` when
+    /// `n / 2` falls short of it: the smallest `y` with `y**2 + (b - D) * y >= c`.
+    /// Rounding y up leaves more in the pool. `None` if an intermediate value overflows.
+    fn compute_y_closed_form_u128(ann: u64, x: u64, d: u128) -> Option<u128> {
+        let (b, c) = Self::compute_y_coefficients_u128(ann, x, d)?;
+        let discriminant = b
+            .max(d)
+            .checked_sub(b.min(d))?
+            .checked_pow(2)?
+            .checked_add(c.checked_mul(4)?)?;
+        let root = crate::math::sqrt_floor(discriminant);
+        let numerator = if b < d {
+            (d - b).checked_add(root)?
+        } else {
+            root.checked_sub(b - d)?
+        };
+        let y = numerator / 2;
+        // y**2 + (b - D) * y < c
+        if y.checked_add(b)?.checked_mul(y)? < y.checked_mul(d)?.checked_add(c)? {
+            y.checked_add(1)
+        } else {
+            Some(y)
+        }
+    }
+
+    /// Compute swap amount `y` in proportion to `x`. Solves the quadratic of
+    /// [compute_y_raw](#method.compute_y_raw) in closed form, which saves the Newton
+    /// iterations, falling back to them once the discriminant leaves u128. Both stop
+    /// within 1 of the root, the closed form always rounding up.
     pub fn compute_y(&self, x: u64, d: U192) -> Option<u64> {
+        let ann = self.compute_amp_factor()?.checked_mul(N_COINS.into())?; // A * n ** n * AMP_PRECISION
+        if let Some(y) = d
+            .to_u128()
+            .and_then(|d| Self::compute_y_closed_form_u128(ann, x, d))
+        {
+            return y.to_u64();
+        }
         self.compute_y_raw(x, d)?.to_u64()
     }
 
+    /// Compute the amount of destination token `amount_in` is worth at the marginal price
+    /// of the curve at the given reserves and invariant, before fees.
+    /// Differentiating the invariant gives the price
+    /// dy/dx = (4 * A * n**n * x * y + D**3 / x) / (4 * A * n**n * x * y + D**3 / y)
+    pub fn compute_spot_amount(
+        &self,
+        amount_in: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        d: U192,
+    ) -> Option<u64> {
+        if swap_source_amount == 0 || swap_destination_amount == 0 {
+            return Some(0);
+        }
+        let ann = self.compute_amp_factor()?.checked_mul(N_COINS.into())?; // A * n ** n * AMP_PRECISION
+        let d = U256::from(d.to_u128()?);
+        let d_squared = d.checked_mul(d)?.checked_mul(AMP_PRECISION.into())?;
+        let leverage = U256::from(ann)
+            .checked_mul(4.into())?
+            .checked_mul(swap_source_amount.into())?
+            .checked_mul(swap_destination_amount.into())?;
+        let numerator = d_squared
+            .checked_div(swap_source_amount.into())?
+            .checked_mul(d)?
+            .checked_add(leverage)?;
+        let denominator = d_squared
+            .checked_div(swap_destination_amount.into())?
+            .checked_mul(d)?
+            .checked_add(leverage)?;
+        U256::from(amount_in)
+            .checked_mul(numerator)?
+            .checked_div(denominator)?
+            .to_u64()
+    }
+
     /// Calculate withdrawal amount when withdrawing only one type of token
     /// Calculation:
     /// 1. Get current D
@@ -258,14 +832,35 @@ impl StableSwap {
         swap_base_amount: u64,  // Same denomination of token to be withdrawn
         swap_quote_amount: u64, // Counter denomination of token to be withdrawn
         fees: &Fees,
+    ) -> Option<(u64, u64)> {
+        self.compute_withdraw_one_solving_y(
+            pool_token_amount,
+            pool_token_supply,
+            swap_base_amount,
+            swap_quote_amount,
+            fees,
+            Self::compute_y,
+        )
+    }
+
+    /// [compute_withdraw_one](#method.compute_withdraw_one) solving for y with `compute_y`
+    fn compute_withdraw_one_solving_y(
+        &self,
+        pool_token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        fees: &Fees,
+        compute_y: fn(&Self, u64, U192) -> Option<u64>,
     ) -> Option<(u64, u64)> {
         let d_0 = self.compute_d(swap_base_amount, swap_quote_amount)?;
+        // The burnt share of D rounds down, which leaves more in the pool
         let d_1 = d_0.checked_sub(
             U192::from(pool_token_amount)
                 .checked_mul(d_0)?
                 .checked_div(pool_token_supply.into())?,
         )?;
-        let new_y = self.compute_y(swap_quote_amount, d_1)?;
+        let new_y = compute_y(self, swap_quote_amount, d_1)?;
 
         // expected_base_amount = swap_base_amount * d_1 / d_0 - new_y;
         let expected_base_amount = U192::from(swap_base_amount)
@@ -287,49 +882,684 @@ impl StableSwap {
         let new_quote_amount = swap_quote_amount
             .checked_sub(fees.normalized_trade_fee(N_COINS, expected_quote_amount)?)?;
         let dy = new_base_amount
-            .checked_sub(self.compute_y(new_quote_amount, d_1)?)?
+            .checked_sub(compute_y(self, new_quote_amount, d_1)?)?
             .checked_sub(1)?; // Withdraw less to account for rounding errors
         let dy_0 = swap_base_amount.checked_sub(new_y)?;
 
         Some((dy, dy_0 - dy))
     }
 
-    /// Compute SwapResult after an exchange
-    pub fn swap_to(
+    /// [compute_withdraw_one](#method.compute_withdraw_one) in a pool whose base and quote
+    /// tokens are worth `base_rate` and `quote_rate`, in [PEG_RATE_PRECISION] units
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_withdraw_one_with_rates(
         &self,
-        source_amount: u64,
-        swap_source_amount: u64,
-        swap_destination_amount: u64,
+        pool_token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        base_rate: u64,
+        quote_rate: u64,
         fees: &Fees,
-    ) -> Option<SwapResult> {
-        let y = self.compute_y(
-            swap_source_amount.checked_add(source_amount)?,
-            self.compute_d(swap_source_amount, swap_destination_amount)?,
+    ) -> Option<(u64, u64)> {
+        let (dy, dy_fee) = self.compute_withdraw_one(
+            pool_token_amount,
+            pool_token_supply,
+            to_virtual_amount(swap_base_amount, base_rate)?,
+            to_virtual_amount(swap_quote_amount, quote_rate)?,
+            fees,
         )?;
-        let dy = swap_destination_amount.checked_sub(y)?;
-        let dy_fee = fees.trade_fee(dy)?;
-        let admin_fee = fees.admin_trade_fee(dy_fee)?;
-
-        let amount_swapped = dy.checked_sub(dy_fee)?;
-        let new_destination_amount = swap_destination_amount
-            .checked_sub(amount_swapped)?
-            .checked_sub(admin_fee)?;
-        let new_source_amount = swap_source_amount.checked_add(source_amount)?;
-
-        Some(SwapResult {
-            new_source_amount,
-            new_destination_amount,
-            amount_swapped,
-            admin_fee,
-            fee: dy_fee,
-        })
+        Some((
+            from_virtual_amount(dy, base_rate)?,
+            from_virtual_amount(dy_fee, base_rate)?,
+        ))
     }
-}
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use super::*;
+    /// Compute the amount of pool tokens to burn to withdraw at least `token_amount`
+    /// of the base token, after the withdraw fee, by inverting
+    /// [compute_withdraw_one](#method.compute_withdraw_one) with a binary search.
+    pub fn compute_withdraw_one_exact_out(
+        &self,
+        token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        fees: &Fees,
+    ) -> Option<u64> {
+        self.compute_withdraw_one_exact_out_with_rates(
+            token_amount,
+            pool_token_supply,
+            swap_base_amount,
+            swap_quote_amount,
+            PEG_RATE_PRECISION,
+            PEG_RATE_PRECISION,
+            fees,
+        )
+    }
+
+    /// [compute_withdraw_one_exact_out](#method.compute_withdraw_one_exact_out) in a pool
+    /// whose base and quote tokens are worth `base_rate` and `quote_rate`,
+    /// in [PEG_RATE_PRECISION] units
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_withdraw_one_exact_out_with_rates(
+        &self,
+        token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        base_rate: u64,
+        quote_rate: u64,
+        fees: &Fees,
+    ) -> Option<u64> {
+        let withdrawn_amount = |pool_token_amount: u64| -> Option<u64> {
+            let (dy, _dy_fee) = self.compute_withdraw_one_with_rates(
+                pool_token_amount,
+                pool_token_supply,
+                swap_base_amount,
+                swap_quote_amount,
+                base_rate,
+                quote_rate,
+                fees,
+            )?;
+            dy.checked_sub(fees.withdraw_fee(dy)?)
+        };
+
+        let mut low: u64 = 1;
+        let mut high = pool_token_supply;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match withdrawn_amount(mid) {
+                Some(amount) if amount >= token_amount => high = mid,
+                _ => low = mid.checked_add(1)?,
+            }
+        }
+        if withdrawn_amount(low)? < token_amount {
+            return None;
+        }
+        Some(low)
+    }
+
+    /// Compute SwapResult after an exchange
+    pub fn swap_to(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        self.swap_to_solving_y(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            fees,
+            Self::compute_y,
+        )
+    }
+
+    /// [swap_to](#method.swap_to) solving for y with `compute_y`
+    fn swap_to_solving_y(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+        compute_y: fn(&Self, u64, U192) -> Option<u64>,
+    ) -> Option<SwapResult> {
+        let new_source_amount = swap_source_amount.checked_add(source_amount)?;
+        let d = self.compute_d(swap_source_amount, swap_destination_amount)?;
+        let y = compute_y(self, new_source_amount, d)?;
+        let dy = swap_destination_amount.checked_sub(y)?;
+        // The trade fee rounds up and the admin fee and rebate round down,
+        // so that rounding never takes from the pool
+        let dy_fee = fees.dynamic_trade_fee(dy, new_source_amount, y)?;
+        let admin_fee = fees.admin_trade_fee(dy_fee)?;
+
+        // Trades that leave the pool more balanced get part of the admin fee back
+        let imbalance_before = swap_source_amount.max(swap_destination_amount)
+            - swap_source_amount.min(swap_destination_amount);
+        let imbalance_after = new_source_amount.max(y) - new_source_amount.min(y);
+        let rebate = if imbalance_after < imbalance_before {
+            fees.rebalancing_rebate(dy_fee, admin_fee)?
+        } else {
+            0
+        };
+        let dy_fee = dy_fee.checked_sub(rebate)?;
+        let admin_fee = admin_fee.checked_sub(rebate)?;
+
+        let amount_swapped = dy.checked_sub(dy_fee)?;
+        let new_destination_amount = swap_destination_amount
+            .checked_sub(amount_swapped)?
+            .checked_sub(admin_fee)?;
+
+        Some(SwapResult {
+            new_source_amount,
+            new_destination_amount,
+            amount_swapped,
+            admin_fee,
+            fee: dy_fee,
+            // Only reported, so it never fails the swap
+            spot_amount_swapped: self
+                .compute_spot_amount(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    d,
+                )
+                .unwrap_or(0),
+        })
+    }
+
+    /// Conservative estimate of [swap_to](#method.swap_to) for trades of at most
+    /// `max_bps` of the source reserves, which skips solving for D and y. `None` if the
+    /// trade is larger, if the dynamic fee is enabled or if a value overflows.
+    ///
+    /// The marginal price of [compute_spot_amount](#method.compute_spot_amount) is a
+    /// weighted mean of 1 and y / x, with a weight of D**3 / (D**3 + k * x * y**2) on
+    /// y / x where k = 4 * A * n**n. D is between 2 * sqrt(x * y) and x + y, so the price
+    /// is at least 1 - (x + y)**3 * (x - y) / (x * ((x + y)**3 + k * x * y**2)) when y < x,
+    /// and at least 1 + 8 * (y - x) / (k * y + 8 * x) otherwise. The bound grows with y
+    /// and the curve is convex, so the bound at the new source amount and the lowest
+    /// destination amount the trade can leave, paying at most dx * max(1, y / x), bounds
+    /// the amount out from below. The trade fee uses the tier of that largest amount,
+    /// and no rebalancing rebate is paid.
+    pub fn quick_swap_to(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        max_bps: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        if fees.dynamic_fee.is_enabled() || swap_source_amount == 0 {
+            return None;
+        }
+        let max_source_amount =
+            crate::math::mul_div_floor(swap_source_amount, max_bps, BPS_DENOMINATOR)?;
+        if source_amount > max_source_amount {
+            return None;
+        }
+
+        let ann = self.compute_amp_factor()?.checked_mul(N_COINS.into())?; // A * n ** n * AMP_PRECISION
+        let new_source_amount = swap_source_amount.checked_add(source_amount)?;
+        let max_dy = crate::math::mul_div_ceil(
+            source_amount,
+            swap_source_amount.max(swap_destination_amount),
+            swap_source_amount,
+        )?
+        .min(swap_destination_amount);
+        let min_destination_amount = swap_destination_amount.checked_sub(max_dy)?;
+        let x = U256::from(new_source_amount);
+        let y = U256::from(min_destination_amount);
+        let dy = if min_destination_amount >= new_source_amount {
+            // dx * 8 * (y - x) / (k * y + 8 * x), rounded down
+            let numerator = U256::from(source_amount)
+                .checked_mul(y.checked_sub(x)?)?
+                .checked_mul((8 * AMP_PRECISION).into())?;
+            let denominator = U256::from(ann)
+                .checked_mul(4.into())?
+                .checked_mul(y)?
+                .checked_add(x.checked_mul((8 * AMP_PRECISION).into())?)?;
+            source_amount.checked_add(numerator.checked_div(denominator)?.to_u64()?)?
+        } else {
+            // dx * (x - y) / x, then times (x + y)**3 / ((x + y)**3 + k * x * y**2),
+            // each rounded up
+            let imbalance_shortfall = crate::math::mul_div_ceil(
+                source_amount,
+                new_source_amount - min_destination_amount,
+                new_source_amount,
+            )?;
+            let sum = x.checked_add(y)?;
+            let sum_cubed = sum.checked_mul(sum)?.checked_mul(sum)?;
+            let leverage = U256::from(ann)
+                .checked_mul(4.into())?
+                .checked_mul(x)?
+                .checked_mul(y)?
+                .checked_mul(y)?
+                .checked_div(AMP_PRECISION.into())?;
+            let numerator = U256::from(imbalance_shortfall).checked_mul(sum_cubed)?;
+            let denominator = sum_cubed.checked_add(leverage)?;
+            let mut shortfall = numerator.checked_div(denominator)?;
+            if !numerator.checked_rem(denominator)?.is_zero() {
+                shortfall = shortfall.checked_add(1.into())?;
+            }
+            source_amount.checked_sub(shortfall.to_u64()?)?
+        }
+        .saturating_sub(QUICK_SWAP_ROUNDING_MARGIN);
+
+        let dy_fee = crate::math::mul_div_ceil(
+            dy,
+            fees.tiered_trade_fee_numerator(max_dy),
+            fees.trade_fee_denominator,
+        )?;
+        let admin_fee = fees.admin_trade_fee(dy_fee)?;
+        let amount_swapped = dy.checked_sub(dy_fee)?;
+        let new_destination_amount = swap_destination_amount
+            .checked_sub(amount_swapped)?
+            .checked_sub(admin_fee)?;
+
+        Some(SwapResult {
+            new_source_amount,
+            new_destination_amount,
+            amount_swapped,
+            admin_fee,
+            fee: dy_fee,
+            // The marginal price needs D
+            spot_amount_swapped: 0,
+        })
+    }
+
+    /// [swap_to](#method.swap_to) in a pool whose source and destination tokens are worth
+    /// `source_rate` and `destination_rate`, in [PEG_RATE_PRECISION] units. The amounts
+    /// paid out round down.
+    pub fn swap_to_with_rates(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        Self::with_rates(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            source_rate,
+            destination_rate,
+            |source_amount, swap_source_amount, swap_destination_amount| {
+                self.swap_to(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    fees,
+                )
+            },
+        )
+    }
+
+    /// [swap_to_with_rates](#method.swap_to_with_rates), paying out the
+    /// [quick_swap_to](#method.quick_swap_to) estimate instead for trades of at most
+    /// `quick_swap_max_bps` of the source reserves. 0 always solves in full.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_to_with_rates_quick(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        quick_swap_max_bps: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        Self::with_rates(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            source_rate,
+            destination_rate,
+            |source_amount, swap_source_amount, swap_destination_amount| {
+                if quick_swap_max_bps != 0 {
+                    if let Some(result) = self.quick_swap_to(
+                        source_amount,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        quick_swap_max_bps,
+                        fees,
+                    ) {
+                        return Some(result);
+                    }
+                }
+                self.swap_to(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    fees,
+                )
+            },
+        )
+    }
+
+    /// Runs `swap` on the virtual amounts of a pool whose source and destination tokens
+    /// are worth `source_rate` and `destination_rate`, and converts its result back
+    fn with_rates<F>(
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        swap: F,
+    ) -> Option<SwapResult>
+    where
+        F: Fn(u64, u64, u64) -> Option<SwapResult>,
+    {
+        if source_rate == PEG_RATE_PRECISION && destination_rate == PEG_RATE_PRECISION {
+            return swap(source_amount, swap_source_amount, swap_destination_amount);
+        }
+        let result = swap(
+            to_virtual_amount(source_amount, source_rate)?,
+            to_virtual_amount(swap_source_amount, source_rate)?,
+            to_virtual_amount(swap_destination_amount, destination_rate)?,
+        )?;
+        let amount_swapped = from_virtual_amount(result.amount_swapped, destination_rate)?;
+        let admin_fee = from_virtual_amount(result.admin_fee, destination_rate)?;
+        Some(SwapResult {
+            new_source_amount: swap_source_amount.checked_add(source_amount)?,
+            new_destination_amount: swap_destination_amount
+                .checked_sub(amount_swapped)?
+                .checked_sub(admin_fee)?,
+            amount_swapped,
+            admin_fee,
+            fee: from_virtual_amount(result.fee, destination_rate)?,
+            spot_amount_swapped: from_virtual_amount(result.spot_amount_swapped, destination_rate)?,
+        })
+    }
+
+    /// Compute the part of a single token deposit of `amount_in` to swap first, so that
+    /// the rest and the swapped tokens are deposited in proportion to the reserves after
+    /// the swap. Returns the amount to swap and the result of the swap.
+    pub fn compute_zap_in_swap(
+        &self,
+        amount_in: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+    ) -> Option<(u64, SwapResult)> {
+        self.compute_zap_in_swap_with_rates(
+            amount_in,
+            swap_source_amount,
+            swap_destination_amount,
+            PEG_RATE_PRECISION,
+            PEG_RATE_PRECISION,
+            fees,
+        )
+    }
+
+    /// [compute_zap_in_swap](#method.compute_zap_in_swap) in a pool whose source and
+    /// destination tokens are worth `source_rate` and `destination_rate`,
+    /// in [PEG_RATE_PRECISION] units
+    pub fn compute_zap_in_swap_with_rates(
+        &self,
+        amount_in: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        fees: &Fees,
+    ) -> Option<(u64, SwapResult)> {
+        // Whether the source side of the deposit is still at least proportional
+        let is_balanced_or_source_heavy = |swap_amount: u64| -> Option<SwapResult> {
+            let result = self.swap_to_with_rates(
+                swap_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                source_rate,
+                destination_rate,
+                fees,
+            )?;
+            let source_side = u128::from(amount_in.checked_sub(swap_amount)?)
+                .checked_mul(result.new_destination_amount.into())?;
+            let destination_side =
+                u128::from(result.amount_swapped).checked_mul(result.new_source_amount.into())?;
+            if source_side >= destination_side {
+                Some(result)
+            } else {
+                None
+            }
+        };
+
+        let mut low: u64 = 0;
+        let mut high = amount_in;
+        while low < high {
+            let mid = high - (high - low) / 2;
+            match is_balanced_or_source_heavy(mid) {
+                Some(_) => low = mid,
+                None => high = mid - 1,
+            }
+        }
+        if low == 0 {
+            // Too small to swap anything
+            return Some((
+                0,
+                SwapResult {
+                    new_source_amount: swap_source_amount,
+                    new_destination_amount: swap_destination_amount,
+                    amount_swapped: 0,
+                    admin_fee: 0,
+                    fee: 0,
+                    spot_amount_swapped: 0,
+                },
+            ));
+        }
+        Some((low, is_balanced_or_source_heavy(low)?))
+    }
+}
+
+impl CurveCalculator for StableSwap {
+    fn compute_d(&self, amount_a: u64, amount_b: u64) -> Option<U192> {
+        StableSwap::compute_d(self, amount_a, amount_b)
+    }
+
+    fn swap_to(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        StableSwap::swap_to(
+            self,
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            fees,
+        )
+    }
+
+    fn withdraw_one(
+        &self,
+        pool_token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        fees: &Fees,
+    ) -> Option<(u64, u64)> {
+        self.compute_withdraw_one(
+            pool_token_amount,
+            pool_token_supply,
+            swap_base_amount,
+            swap_quote_amount,
+            fees,
+        )
+    }
+
+    fn mint_for_deposit(
+        &self,
+        deposit_amount_a: u64,
+        deposit_amount_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        fees: &Fees,
+    ) -> Option<u64> {
+        self.compute_mint_amount_for_deposit(
+            deposit_amount_a,
+            deposit_amount_b,
+            swap_amount_a,
+            swap_amount_b,
+            pool_token_supply,
+            fees,
+        )
+    }
+
+    fn compute_d_with_rates(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        rate_a: u64,
+        rate_b: u64,
+    ) -> Option<U192> {
+        StableSwap::compute_d_with_rates(self, amount_a, amount_b, rate_a, rate_b)
+    }
+
+    fn initial_mint_amount(&self, amount_a: u64, amount_b: u64) -> Option<(u64, bool)> {
+        self.compute_initial_mint_amount(amount_a, amount_b)
+    }
+
+    fn mint_for_deposit_with_rates(
+        &self,
+        deposit_amount_a: u64,
+        deposit_amount_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        rate_a: u64,
+        rate_b: u64,
+        fees: &Fees,
+    ) -> Option<u64> {
+        self.compute_mint_amount_for_deposit_with_rates(
+            deposit_amount_a,
+            deposit_amount_b,
+            swap_amount_a,
+            swap_amount_b,
+            pool_token_supply,
+            rate_a,
+            rate_b,
+            fees,
+        )
+    }
+
+    fn admin_fee_mint_amount_with_rates(
+        &self,
+        admin_fee_a: u64,
+        admin_fee_b: u64,
+        swap_amount_a: u64,
+        swap_amount_b: u64,
+        pool_token_supply: u64,
+        rate_a: u64,
+        rate_b: u64,
+    ) -> Option<u64> {
+        self.compute_admin_fee_mint_amount_with_rates(
+            admin_fee_a,
+            admin_fee_b,
+            swap_amount_a,
+            swap_amount_b,
+            pool_token_supply,
+            rate_a,
+            rate_b,
+        )
+    }
+
+    fn swap_to_with_rates(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        StableSwap::swap_to_with_rates(
+            self,
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            source_rate,
+            destination_rate,
+            fees,
+        )
+    }
+
+    fn swap_to_with_rates_quick(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        quick_swap_max_bps: u64,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        StableSwap::swap_to_with_rates_quick(
+            self,
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            source_rate,
+            destination_rate,
+            quick_swap_max_bps,
+            fees,
+        )
+    }
+
+    fn withdraw_one_with_rates(
+        &self,
+        pool_token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        base_rate: u64,
+        quote_rate: u64,
+        fees: &Fees,
+    ) -> Option<(u64, u64)> {
+        self.compute_withdraw_one_with_rates(
+            pool_token_amount,
+            pool_token_supply,
+            swap_base_amount,
+            swap_quote_amount,
+            base_rate,
+            quote_rate,
+            fees,
+        )
+    }
+
+    fn withdraw_one_exact_out_with_rates(
+        &self,
+        token_amount: u64,
+        pool_token_supply: u64,
+        swap_base_amount: u64,
+        swap_quote_amount: u64,
+        base_rate: u64,
+        quote_rate: u64,
+        fees: &Fees,
+    ) -> Option<u64> {
+        self.compute_withdraw_one_exact_out_with_rates(
+            token_amount,
+            pool_token_supply,
+            swap_base_amount,
+            swap_quote_amount,
+            base_rate,
+            quote_rate,
+            fees,
+        )
+    }
+
+    fn zap_in_swap_with_rates(
+        &self,
+        amount_in: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        source_rate: u64,
+        destination_rate: u64,
+        fees: &Fees,
+    ) -> Option<(u64, SwapResult)> {
+        self.compute_zap_in_swap_with_rates(
+            amount_in,
+            swap_source_amount,
+            swap_destination_amount,
+            source_rate,
+            destination_rate,
+            fees,
+        )
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::fees::{
+        DynamicFee, FeeMode, BPS_DENOMINATOR, FEE_GAMMA_PRECISION, NO_DYNAMIC_FEE, NO_FEE_TIERS,
+    };
     use crate::pool_converter::PoolTokenConverter;
     use proptest::prelude::*;
     use rand::Rng;
@@ -345,6 +1575,10 @@ mod tests {
         trade_fee_denominator: MODEL_FEE_DENOMINATOR,
         withdraw_fee_numerator: 0,
         withdraw_fee_denominator: 1,
+        trade_fee_tiers: NO_FEE_TIERS,
+        dynamic_fee: NO_DYNAMIC_FEE,
+        rebalancing_rebate_bps: 0,
+        host_fee_bps: 0,
     };
 
     const RAMP_TICKS: i64 = 100000;
@@ -352,7 +1586,7 @@ mod tests {
     #[test]
     fn test_ramp_amp_up() {
         let mut rng = rand::thread_rng();
-        let initial_amp_factor = 100;
+        let initial_amp_factor = 100 * AMP_PRECISION;
         let target_amp_factor = initial_amp_factor * 2;
         let start_ramp_ts = rng.gen_range(ZERO_TS..=i64::MAX - RAMP_TICKS);
         let stop_ramp_ts = start_ramp_ts + MIN_RAMP_DURATION;
@@ -382,7 +1616,7 @@ mod tests {
     #[test]
     fn test_ramp_amp_down() {
         let mut rng = rand::thread_rng();
-        let initial_amp_factor = 100;
+        let initial_amp_factor = 100 * AMP_PRECISION;
         let target_amp_factor = initial_amp_factor / 10;
         let amp_range = initial_amp_factor - target_amp_factor;
         let start_ramp_ts = rng.gen_range(ZERO_TS..=i64::MAX - RAMP_TICKS);
@@ -410,6 +1644,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ramp_amp_in_hundredths() {
+        // a ramp by a single unit of A moves the price in a hundred steps
+        let initial_amp_factor = 100 * AMP_PRECISION;
+        let target_amp_factor = 101 * AMP_PRECISION;
+        let (amount_a, amount_b) = (1_000_000_000, 3_000_000_000);
+        let mut prev_amp_factor = initial_amp_factor;
+        let mut prev_d = StableSwap::new(
+            initial_amp_factor,
+            initial_amp_factor,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        )
+        .compute_d(amount_a, amount_b)
+        .unwrap();
+        let mut steps = 0;
+        for current_ts in (0..=MIN_RAMP_DURATION).step_by(60) {
+            let invariant = StableSwap::new(
+                initial_amp_factor,
+                target_amp_factor,
+                current_ts,
+                ZERO_TS,
+                MIN_RAMP_DURATION,
+            );
+            let amp_factor = invariant.compute_amp_factor().unwrap();
+            assert!(amp_factor - prev_amp_factor <= 1);
+            let d = invariant.compute_d(amount_a, amount_b).unwrap();
+            assert!(d >= prev_d);
+            if amp_factor > prev_amp_factor {
+                steps += 1;
+            }
+            prev_amp_factor = amp_factor;
+            prev_d = d;
+        }
+        assert_eq!(prev_amp_factor, target_amp_factor);
+        assert_eq!(steps, AMP_PRECISION);
+
+        // a coefficient between two whole ones gives an invariant between theirs
+        let invariant = StableSwap::new(
+            initial_amp_factor + AMP_PRECISION / 2,
+            initial_amp_factor + AMP_PRECISION / 2,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let d = invariant.compute_d(amount_a, amount_b).unwrap();
+        let whole_d = |amp_factor: u64| {
+            StableSwap::new(amp_factor, amp_factor, ZERO_TS, ZERO_TS, ZERO_TS)
+                .compute_d(amount_a, amount_b)
+                .unwrap()
+        };
+        assert!(whole_d(initial_amp_factor) < d && d < whole_d(target_amp_factor));
+    }
+
     fn check_d(
         model: &Model,
         amount_a: u64,
@@ -419,8 +1708,8 @@ mod tests {
         stop_ramp_ts: i64,
     ) -> U192 {
         let swap = StableSwap {
-            initial_amp_factor: model.amp_factor,
-            target_amp_factor: model.amp_factor,
+            initial_amp_factor: model.amp_factor * AMP_PRECISION,
+            target_amp_factor: model.amp_factor * AMP_PRECISION,
             current_ts,
             start_ramp_ts,
             stop_ramp_ts,
@@ -439,8 +1728,8 @@ mod tests {
         stop_ramp_ts: i64,
     ) {
         let swap = StableSwap {
-            initial_amp_factor: model.amp_factor,
-            target_amp_factor: model.amp_factor,
+            initial_amp_factor: model.amp_factor * AMP_PRECISION,
+            target_amp_factor: model.amp_factor * AMP_PRECISION,
             current_ts,
             start_ramp_ts,
             stop_ramp_ts,
@@ -514,8 +1803,8 @@ mod tests {
 
     #[test]
     fn test_compute_mint_amount_for_deposit() {
-        let initial_amp_factor = MIN_AMP;
-        let target_amp_factor = MAX_AMP;
+        let initial_amp_factor = MIN_AMP * AMP_PRECISION;
+        let target_amp_factor = MAX_AMP * AMP_PRECISION;
         let current_ts = MIN_RAMP_DURATION / 2;
         let start_ramp_ts = ZERO_TS;
         let stop_ramp_ts = MIN_RAMP_DURATION;
@@ -539,11 +1828,430 @@ mod tests {
                 swap_amount_a,
                 swap_amount_b,
                 pool_token_supply,
-                &MODEL_FEES,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        let expected_mint_amount = MAX_TOKENS_IN;
+        assert_eq!(actual_mint_amount, expected_mint_amount);
+    }
+
+    #[test]
+    fn test_compute_initial_mint_amount() {
+        let amp_factor = 100 * AMP_PRECISION;
+        let invariant = StableSwap::new(amp_factor, amp_factor, ZERO_TS, ZERO_TS, ZERO_TS);
+        assert_eq!(
+            invariant.compute_initial_mint_amount(1_000_000, 2_000_000),
+            Some((
+                invariant
+                    .compute_d(1_000_000, 2_000_000)
+                    .unwrap()
+                    .to_u64()
+                    .unwrap(),
+                false
+            ))
+        );
+        assert_eq!(
+            invariant.compute_initial_mint_amount(MAX_TOKENS_IN, MAX_TOKENS_IN),
+            Some((2 * MAX_TOKENS_IN, false))
+        );
+        // reserves whose invariant overflows the math
+        assert_eq!(invariant.compute_d(u64::MAX, u64::MAX), None);
+        for (amount_a, amount_b) in [
+            (u64::MAX, u64::MAX),
+            (MAX_TOKENS_IN + 1, MAX_TOKENS_IN),
+            (u64::MAX, 1_000_000_000),
+            (1_000_000_000, u64::MAX / 2),
+        ] {
+            let (mint_amount, is_scaled) = invariant
+                .compute_initial_mint_amount(amount_a, amount_b)
+                .unwrap();
+            assert!(is_scaled);
+            let scaled_d = invariant
+                .compute_d(
+                    amount_a / INITIAL_SUPPLY_SCALE,
+                    amount_b / INITIAL_SUPPLY_SCALE,
+                )
+                .unwrap();
+            assert_eq!(U192::from(mint_amount), scaled_d);
+            // never more than the reserves are worth
+            assert!(
+                u128::from(mint_amount) * u128::from(INITIAL_SUPPLY_SCALE)
+                    <= u128::from(amount_a) + u128::from(amount_b)
+            );
+        }
+        assert_eq!(
+            invariant.compute_initial_mint_amount(u64::MAX, u64::MAX),
+            Some((2 * (u64::MAX / INITIAL_SUPPLY_SCALE), true))
+        );
+        // too imbalanced to price once scaled
+        assert_eq!(
+            invariant.compute_initial_mint_amount(u64::MAX, INITIAL_SUPPLY_SCALE - 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_curve_calculator() {
+        let amp_factor = 100 * AMP_PRECISION;
+        let invariant = StableSwap::new(amp_factor, amp_factor, ZERO_TS, ZERO_TS, ZERO_TS);
+        let curve: &dyn CurveCalculator = &invariant;
+        let fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        assert_eq!(
+            curve.compute_d(1_000_000, 2_000_000),
+            invariant.compute_d(1_000_000, 2_000_000)
+        );
+        let result = curve.swap_to(1_000, 1_000_000, 2_000_000, &fees).unwrap();
+        let expected = invariant
+            .swap_to(1_000, 1_000_000, 2_000_000, &fees)
+            .unwrap();
+        assert_eq!(
+            (result.amount_swapped, result.fee, result.admin_fee),
+            (expected.amount_swapped, expected.fee, expected.admin_fee)
+        );
+        assert_eq!(
+            curve.withdraw_one(1_000, 3_000_000, 1_000_000, 2_000_000, &fees),
+            invariant.compute_withdraw_one(1_000, 3_000_000, 1_000_000, 2_000_000, &fees)
+        );
+        assert_eq!(
+            curve.mint_for_deposit(1_000, 0, 1_000_000, 2_000_000, 3_000_000, &fees),
+            invariant
+                .compute_mint_amount_for_deposit(1_000, 0, 1_000_000, 2_000_000, 3_000_000, &fees)
+        );
+
+        // the rates of the pool pass through
+        let rate = 2 * PEG_RATE_PRECISION;
+        let curve =
+            CurveType::StableSwap.calculator(amp_factor, amp_factor, ZERO_TS, ZERO_TS, ZERO_TS);
+        assert_eq!(
+            curve.compute_d_with_rates(1_000_000, 2_000_000, rate, PEG_RATE_PRECISION),
+            invariant.compute_d_with_rates(1_000_000, 2_000_000, rate, PEG_RATE_PRECISION)
+        );
+        assert_eq!(
+            curve.initial_mint_amount(1_000_000, 2_000_000),
+            invariant.compute_initial_mint_amount(1_000_000, 2_000_000)
+        );
+        assert_eq!(
+            curve.mint_for_deposit_with_rates(
+                1_000,
+                0,
+                1_000_000,
+                2_000_000,
+                3_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees
+            ),
+            invariant.compute_mint_amount_for_deposit_with_rates(
+                1_000,
+                0,
+                1_000_000,
+                2_000_000,
+                3_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees
+            )
+        );
+        assert_eq!(
+            curve.admin_fee_mint_amount_with_rates(
+                100,
+                200,
+                1_000_000,
+                2_000_000,
+                3_000_000,
+                rate,
+                PEG_RATE_PRECISION
+            ),
+            invariant.compute_admin_fee_mint_amount_with_rates(
+                100,
+                200,
+                1_000_000,
+                2_000_000,
+                3_000_000,
+                rate,
+                PEG_RATE_PRECISION
+            )
+        );
+        let result = curve
+            .swap_to_with_rates(1_000, 1_000_000, 2_000_000, rate, PEG_RATE_PRECISION, &fees)
+            .unwrap();
+        let expected = invariant
+            .swap_to_with_rates(1_000, 1_000_000, 2_000_000, rate, PEG_RATE_PRECISION, &fees)
+            .unwrap();
+        assert_eq!(
+            (result.amount_swapped, result.fee, result.admin_fee),
+            (expected.amount_swapped, expected.fee, expected.admin_fee)
+        );
+        let result = curve
+            .swap_to_with_rates_quick(
+                1_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                100,
+                &fees,
+            )
+            .unwrap();
+        let expected = invariant
+            .swap_to_with_rates_quick(
+                1_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                100,
+                &fees,
+            )
+            .unwrap();
+        assert_eq!(
+            (result.amount_swapped, result.fee, result.admin_fee),
+            (expected.amount_swapped, expected.fee, expected.admin_fee)
+        );
+        assert_eq!(
+            curve.withdraw_one_with_rates(
+                1_000,
+                3_000_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees
+            ),
+            invariant.compute_withdraw_one_with_rates(
+                1_000,
+                3_000_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees
+            )
+        );
+        assert_eq!(
+            curve.withdraw_one_exact_out_with_rates(
+                1_000,
+                3_000_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees
+            ),
+            invariant.compute_withdraw_one_exact_out_with_rates(
+                1_000,
+                3_000_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees
+            )
+        );
+        let (swap_amount, result) = curve
+            .zap_in_swap_with_rates(
+                10_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees,
+            )
+            .unwrap();
+        let (expected_swap_amount, expected) = invariant
+            .compute_zap_in_swap_with_rates(
+                10_000,
+                1_000_000,
+                2_000_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &fees,
+            )
+            .unwrap();
+        assert_eq!(
+            (swap_amount, result.amount_swapped),
+            (expected_swap_amount, expected.amount_swapped)
+        );
+
+        assert_eq!(CurveType::from_u8(0), Some(CurveType::StableSwap));
+        assert_eq!(CurveType::from_u8(1), None);
+    }
+
+    #[test]
+    fn test_is_proportional_deposit() {
+        assert!(is_proportional_deposit(1_000, 3_000, 1_000_000, 3_000_000));
+        // within a token
+        assert!(is_proportional_deposit(1_000, 3_001, 1_000_000, 3_000_000));
+        assert!(is_proportional_deposit(1, 2, 1_000_000, 3_000_000));
+        assert!(!is_proportional_deposit(1_000, 3_003, 1_000_000, 3_000_000));
+        assert!(!is_proportional_deposit(1_001, 3_000, 1_000_000, 3_000_000));
+        // one-sided deposits and empty pools
+        assert!(!is_proportional_deposit(1_000, 0, 1_000_000, 3_000_000));
+        assert!(!is_proportional_deposit(1_000, 3_000, 0, 3_000_000));
+    }
+
+    #[test]
+    fn test_proportional_deposit_is_fee_free() {
+        let invariant = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            ..MODEL_FEES
+        };
+        let swap_amount_a = 1_000_000_000;
+        let swap_amount_b = 3_000_000_000;
+        let pool_token_supply = 3_900_000_000;
+
+        // a thousandth of the pool mints a thousandth of the supply
+        let mint_amount = invariant
+            .compute_mint_amount_for_deposit(
+                1_000_000,
+                3_000_000,
+                swap_amount_a,
+                swap_amount_b,
+                pool_token_supply,
+                &fees,
+            )
+            .unwrap();
+        assert_eq!(mint_amount, pool_token_supply / 1_000);
+        // rounded down to the smaller share
+        let mint_amount = invariant
+            .compute_mint_amount_for_deposit(
+                1_000_000,
+                3_000_001,
+                swap_amount_a,
+                swap_amount_b,
+                pool_token_supply,
+                &fees,
+            )
+            .unwrap();
+        assert_eq!(mint_amount, pool_token_supply / 1_000);
+        // imbalanced deposits pay the trade fee on the imbalance
+        let mint_amount = invariant
+            .compute_mint_amount_for_deposit(
+                2_000_000,
+                3_000_000,
+                swap_amount_a,
+                swap_amount_b,
+                pool_token_supply,
+                &fees,
+            )
+            .unwrap();
+        let fee_free_mint_amount = invariant
+            .compute_mint_amount_for_deposit(
+                2_000_000,
+                3_000_000,
+                swap_amount_a,
+                swap_amount_b,
+                pool_token_supply,
+                &Fees {
+                    trade_fee_numerator: 0,
+                    ..fees
+                },
             )
             .unwrap();
-        let expected_mint_amount = MAX_TOKENS_IN;
-        assert_eq!(actual_mint_amount, expected_mint_amount);
+        assert!(mint_amount < fee_free_mint_amount);
+    }
+
+    proptest! {
+        #[test]
+        fn test_proportional_deposit_round_trip(
+            swap_amount_a in 1_000_000..MAX_TOKENS_IN >> 24,
+            swap_amount_b in 1_000_000..MAX_TOKENS_IN >> 24,
+            deposit_share_bps in 1..BPS_DENOMINATOR,
+        ) {
+            let invariant = StableSwap::new(
+                100 * AMP_PRECISION,
+                100 * AMP_PRECISION,
+                ZERO_TS,
+                ZERO_TS,
+                ZERO_TS,
+            );
+            let fees = Fees {
+                trade_fee_numerator: 1,
+                trade_fee_denominator: 100,
+                ..MODEL_FEES
+            };
+            let pool_token_supply = swap_amount_a + swap_amount_b;
+            let deposit_amount_a =
+                crate::math::mul_div_floor(swap_amount_a, deposit_share_bps, BPS_DENOMINATOR)
+                    .unwrap();
+            let deposit_amount_b =
+                crate::math::mul_div_floor(deposit_amount_a, swap_amount_b, swap_amount_a)
+                    .unwrap();
+            prop_assume!(deposit_amount_a > 0 && deposit_amount_b > 0);
+
+            let mint_amount = invariant
+                .compute_mint_amount_for_deposit(
+                    deposit_amount_a,
+                    deposit_amount_b,
+                    swap_amount_a,
+                    swap_amount_b,
+                    pool_token_supply,
+                    &fees,
+                )
+                .unwrap();
+            let converter = PoolTokenConverter {
+                supply: pool_token_supply + mint_amount,
+                token_a: swap_amount_a + deposit_amount_a,
+                token_b: swap_amount_b + deposit_amount_b,
+                fees: &fees,
+            };
+            let withdrawn = converter.withdraw_preview(mint_amount).unwrap();
+            // Only rounding dust is lost: up to two tokens, and less than a token of B
+            // worth of token A from rounding the deposit of token B
+            let lost_a = deposit_amount_a - withdrawn.a;
+            let lost_b = deposit_amount_b - withdrawn.b;
+            prop_assert!(lost_b <= 2);
+            prop_assert!(
+                u128::from(lost_a) * u128::from(swap_amount_b)
+                    <= 2 * u128::from(swap_amount_b) + u128::from(swap_amount_a)
+            );
+        }
+    }
+
+    #[test]
+    fn test_compute_admin_fee_mint_amount() {
+        let invariant = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        // A balanced pool values both tokens alike
+        assert_eq!(
+            invariant.compute_admin_fee_mint_amount(1_000, 0, 1_001_000, 1_000_000, 2_000_000),
+            Some(1_000)
+        );
+        assert_eq!(
+            invariant.compute_admin_fee_mint_amount(0, 0, 1_000_000, 1_000_000, 2_000_000),
+            Some(0)
+        );
+        // The fees are part of the reserves
+        assert_eq!(
+            invariant.compute_admin_fee_mint_amount(1_000, 0, 999, 1_000_000, 2_000_000),
+            None
+        );
+        // Worth twice as much in a pool whose token A is worth twice token B
+        assert_eq!(
+            invariant.compute_admin_fee_mint_amount_with_rates(
+                1_000,
+                0,
+                1_001_000,
+                2_000_000,
+                4_000_000,
+                2 * PEG_RATE_PRECISION,
+                PEG_RATE_PRECISION,
+            ),
+            Some(2_000)
+        );
     }
 
     #[test]
@@ -597,9 +2305,21 @@ mod tests {
         swap_source_amount: u64,
         swap_destination_amount: u64,
     ) {
+        // The model only takes whole amplification coefficients
+        let amp_factor = StableSwap::new(
+            initial_amp_factor * AMP_PRECISION,
+            target_amp_factor * AMP_PRECISION,
+            current_ts,
+            start_ramp_ts,
+            stop_ramp_ts,
+        )
+        .compute_amp_factor()
+        .unwrap()
+            / AMP_PRECISION
+            * AMP_PRECISION;
         let swap = StableSwap::new(
-            initial_amp_factor,
-            target_amp_factor,
+            amp_factor,
+            amp_factor,
             current_ts,
             start_ramp_ts,
             stop_ramp_ts,
@@ -613,7 +2333,7 @@ mod tests {
             )
             .unwrap();
         let model = Model::new(
-            swap.compute_amp_factor().unwrap(),
+            amp_factor / AMP_PRECISION,
             vec![swap_source_amount.into(), swap_destination_amount.into()],
             N_COINS.into(),
         );
@@ -700,9 +2420,21 @@ mod tests {
         swap_base_amount: u64,
         swap_quote_amount: u64,
     ) {
+        // The model only takes whole amplification coefficients
+        let amp_factor = StableSwap::new(
+            initial_amp_factor * AMP_PRECISION,
+            target_amp_factor * AMP_PRECISION,
+            current_ts,
+            start_ramp_ts,
+            stop_ramp_ts,
+        )
+        .compute_amp_factor()
+        .unwrap()
+            / AMP_PRECISION
+            * AMP_PRECISION;
         let swap = StableSwap::new(
-            initial_amp_factor,
-            target_amp_factor,
+            amp_factor,
+            amp_factor,
             current_ts,
             start_ramp_ts,
             stop_ramp_ts,
@@ -717,7 +2449,7 @@ mod tests {
             )
             .unwrap();
         let model = Model::new_with_pool_tokens(
-            swap.compute_amp_factor().unwrap(),
+            amp_factor / AMP_PRECISION,
             vec![swap_base_amount.into(), swap_quote_amount.into()],
             N_COINS.into(),
             pool_token_supply.into(),
@@ -819,7 +2551,7 @@ mod tests {
 
             let start_ramp_ts = cmp::max(0, current_ts - MIN_RAMP_DURATION);
             let stop_ramp_ts = cmp::min(i64::MAX, current_ts + MIN_RAMP_DURATION);
-            let invariant = StableSwap::new(amp_factor, amp_factor, current_ts, start_ramp_ts, stop_ramp_ts);
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, current_ts, start_ramp_ts, stop_ramp_ts);
             let d0 = invariant.compute_d(swap_token_a_amount, swap_token_b_amount).unwrap();
 
             let mint_amount = invariant.compute_mint_amount_for_deposit(
@@ -842,6 +2574,37 @@ mod tests {
         }
     }
 
+    proptest! {
+        #[test]
+        fn test_virtual_price_does_not_decrease_from_admin_fee_mint(
+            amp_factor in MIN_AMP..MAX_AMP,
+            admin_fee_a in 0..MAX_TOKENS_IN,
+            admin_fee_b in 0..MAX_TOKENS_IN,
+            swap_token_a_amount in 1..MAX_TOKENS_IN,
+            swap_token_b_amount in 1..MAX_TOKENS_IN,
+            pool_token_supply in 1..MAX_TOKENS_IN,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let admin_fee_a = admin_fee_a % swap_token_a_amount;
+            let admin_fee_b = admin_fee_b % swap_token_b_amount;
+            let d0 = invariant.compute_d(swap_token_a_amount - admin_fee_a, swap_token_b_amount - admin_fee_b).unwrap();
+            let d1 = invariant.compute_d(swap_token_a_amount, swap_token_b_amount).unwrap();
+
+            let mint_amount = invariant.compute_admin_fee_mint_amount(
+                admin_fee_a,
+                admin_fee_b,
+                swap_token_a_amount,
+                swap_token_b_amount,
+                pool_token_supply,
+            );
+            prop_assume!(mint_amount.is_some());
+
+            // The pool tokens held before are worth at least what they were
+            let new_pool_token_supply = U192::from(pool_token_supply) + U192::from(mint_amount.unwrap());
+            assert!(d0 * new_pool_token_supply <= d1 * U192::from(pool_token_supply));
+        }
+    }
+
     proptest! {
         #[test]
         fn test_virtual_price_does_not_decrease_from_swap(
@@ -857,7 +2620,7 @@ mod tests {
 
             let start_ramp_ts = cmp::max(0, current_ts - MIN_RAMP_DURATION);
             let stop_ramp_ts = cmp::min(i64::MAX, current_ts + MIN_RAMP_DURATION);
-            let invariant = StableSwap::new(amp_factor, amp_factor, current_ts, start_ramp_ts, stop_ramp_ts);
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, current_ts, start_ramp_ts, stop_ramp_ts);
             let d0 = invariant.compute_d(swap_source_amount, swap_destination_amount).unwrap();
 
             let swap_result = invariant.swap_to(source_token_amount, swap_source_amount, swap_destination_amount, &MODEL_FEES);
@@ -886,7 +2649,7 @@ mod tests {
 
             let start_ramp_ts = cmp::max(0, current_ts - MIN_RAMP_DURATION);
             let stop_ramp_ts = cmp::min(i64::MAX, current_ts + MIN_RAMP_DURATION);
-            let invariant = StableSwap::new(amp_factor, amp_factor, current_ts, start_ramp_ts, stop_ramp_ts);
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, current_ts, start_ramp_ts, stop_ramp_ts);
             let d0 = invariant.compute_d(swap_token_a_amount, swap_token_b_amount).unwrap();
 
             let converter = PoolTokenConverter {
@@ -929,7 +2692,7 @@ mod tests {
 
             let start_ramp_ts = cmp::max(0, current_ts - MIN_RAMP_DURATION);
             let stop_ramp_ts = cmp::min(i64::MAX, current_ts + MIN_RAMP_DURATION);
-            let invariant = StableSwap::new(amp_factor, amp_factor, current_ts, start_ramp_ts, stop_ramp_ts);
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, current_ts, start_ramp_ts, stop_ramp_ts);
             let d0 = invariant.compute_d(base_token_amount, quote_token_amount).unwrap();
 
             prop_assume!(U192::from(pool_token_amount) * U192::from(base_token_amount) / U192::from(pool_token_supply) >= U192::from(1));
@@ -950,4 +2713,688 @@ mod tests {
            (total, intermediate)
        }
     }
+
+    #[test]
+    fn test_u128_fast_path() {
+        let invariant = StableSwap::new(
+            MAX_AMP * AMP_PRECISION,
+            MAX_AMP * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+
+        // typical reserves stay in u128
+        let (amount_a, amount_b) = (1_000_000_000_000, 1_200_000_000_000);
+        let d = StableSwap::compute_d_u128(
+            MAX_AMP * AMP_PRECISION,
+            amount_a + amount_b,
+            amount_a * 2,
+            amount_b * 2,
+        )
+        .unwrap();
+        assert_eq!(invariant.compute_d(amount_a, amount_b).unwrap(), d.into());
+        let y = StableSwap::compute_y_u128(MAX_AMP * AMP_PRECISION * 2, amount_a, d).unwrap();
+        assert_eq!(
+            invariant.compute_y_raw(amount_a, d.into()).unwrap(),
+            y.into()
+        );
+
+        // huge reserves fall back to U192
+        let (amount_a, amount_b) = (MAX_TOKENS_IN, MAX_TOKENS_IN / 3);
+        assert_eq!(
+            StableSwap::compute_d_u128(
+                MAX_AMP * AMP_PRECISION,
+                amount_a + amount_b,
+                amount_a * 2,
+                amount_b * 2
+            ),
+            None
+        );
+        let d = invariant.compute_d(amount_a, amount_b).unwrap();
+        assert!(d > U192::from(u64::MAX / 16));
+        let y = invariant.compute_y(amount_a, d).unwrap();
+        assert!(y.saturating_sub(amount_b) <= 1 && amount_b.saturating_sub(y) <= 1);
+    }
+
+    #[test]
+    fn test_compute_y_closed_form() {
+        let ann = MIN_AMP * AMP_PRECISION * 2;
+        let (amount_a, amount_b) = (1_000_000, 3_000_000);
+        let d =
+            StableSwap::compute_d_u128(ann / 2, amount_a + amount_b, amount_a * 2, amount_b * 2)
+                .unwrap();
+        let y = StableSwap::compute_y_closed_form_u128(ann, amount_a, d).unwrap();
+        assert!(y.abs_diff(amount_b.into()) <= 1);
+        // the smallest y solving y**2 + (b - D) * y >= c
+        let (b, c) = StableSwap::compute_y_coefficients_u128(ann, amount_a, d).unwrap();
+        assert!(y * (y + b) >= y * d + c);
+        assert!((y - 1) * (y - 1 + b) < (y - 1) * d + c);
+
+        // discriminants past u128 fall back to Newton's method
+        let invariant = StableSwap::new(
+            MIN_AMP * AMP_PRECISION,
+            MIN_AMP * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let (amount_a, amount_b) = (MAX_TOKENS_IN / 2, MAX_TOKENS_IN);
+        let d = invariant.compute_d(amount_a, amount_b).unwrap();
+        assert_eq!(
+            StableSwap::compute_y_closed_form_u128(ann, amount_a, d.to_u128().unwrap()),
+            None
+        );
+        assert_eq!(
+            invariant.compute_y(amount_a, d),
+            invariant.compute_y_raw(amount_a, d).unwrap().to_u64()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_compute_y_closed_form_matches_newton(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in 1..MAX_TOKENS_IN,
+            amount_b in 1..MAX_TOKENS_IN,
+            amount_in in 0..MAX_TOKENS_IN,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let d = invariant.compute_d(amount_a, amount_b).unwrap();
+            let x = amount_a.saturating_add(amount_in);
+            let y = invariant.compute_y(x, d).unwrap();
+            let newton_y = invariant.compute_y_raw(x, d).unwrap().to_u64().unwrap();
+            // rounds up where the iteration may stop one below
+            prop_assert!(y >= newton_y && y - newton_y <= 1);
+        }
+    }
+
+    /// y with Newton's method only, see [StableSwap::compute_y]
+    fn compute_y_newton(invariant: &StableSwap, x: u64, d: U192) -> Option<u64> {
+        invariant.compute_y_raw(x, d)?.to_u64()
+    }
+
+    proptest! {
+        #[test]
+        fn test_closed_form_never_pays_more_than_newton(
+            amp_factor in MIN_AMP..MAX_AMP,
+            swap_base_amount in 1..MAX_TOKENS_IN,
+            swap_quote_amount in 1..MAX_TOKENS_IN,
+            amount_in in 0..MAX_TOKENS_IN,
+            pool_token_bps in 1..=BPS_DENOMINATOR,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+
+            let swap = invariant.swap_to(amount_in, swap_quote_amount, swap_base_amount, &MODEL_FEES);
+            let newton_swap = invariant.swap_to_solving_y(
+                amount_in,
+                swap_quote_amount,
+                swap_base_amount,
+                &MODEL_FEES,
+                compute_y_newton,
+            );
+            if let (Some(swap), Some(newton_swap)) = (swap, newton_swap) {
+                prop_assert!(swap.amount_swapped <= newton_swap.amount_swapped);
+                prop_assert!(swap.new_destination_amount >= newton_swap.new_destination_amount);
+            }
+
+            let pool_token_supply = swap_base_amount + swap_quote_amount;
+            let pool_token_amount = (pool_token_supply / BPS_DENOMINATOR * pool_token_bps).max(1);
+            let withdraw = invariant.compute_withdraw_one(
+                pool_token_amount,
+                pool_token_supply,
+                swap_base_amount,
+                swap_quote_amount,
+                &MODEL_FEES,
+            );
+            let newton_withdraw = invariant.compute_withdraw_one_solving_y(
+                pool_token_amount,
+                pool_token_supply,
+                swap_base_amount,
+                swap_quote_amount,
+                &MODEL_FEES,
+                compute_y_newton,
+            );
+            if let (Some((dy, _)), Some((newton_dy, _))) = (withdraw, newton_withdraw) {
+                prop_assert!(dy <= newton_dy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compute_withdraw_one_exact_out() {
+        let fees = Fees {
+            admin_trade_fee_numerator: 0,
+            admin_trade_fee_denominator: 1,
+            admin_withdraw_fee_numerator: 0,
+            admin_withdraw_fee_denominator: 1,
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            withdraw_fee_numerator: 5,
+            withdraw_fee_denominator: 1_000,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
+        };
+        let invariant = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let withdrawn_amount = |pool_token_amount: u64, base: u64, quote: u64, supply: u64| {
+            let (dy, _dy_fee) = invariant
+                .compute_withdraw_one(pool_token_amount, supply, base, quote, &fees)
+                .unwrap();
+            dy - fees.withdraw_fee(dy).unwrap()
+        };
+
+        for &(token_amount, base, quote, supply) in [
+            (1_000, 1_000_000, 1_000_000, 2_000_000),
+            (123_456, 1_000_000, 3_000_000, 4_000_000),
+            (500_000, 5_000_000, 1_000_000, 6_000_000),
+            (
+                999_999_999,
+                100_000_000_000,
+                50_000_000_000,
+                150_000_000_000,
+            ),
+        ]
+        .iter()
+        {
+            let burn = invariant
+                .compute_withdraw_one_exact_out(token_amount, supply, base, quote, &fees)
+                .unwrap();
+            assert!(withdrawn_amount(burn, base, quote, supply) >= token_amount);
+            assert!(withdrawn_amount(burn - 1, base, quote, supply) < token_amount);
+        }
+
+        // more than the pool holds
+        assert_eq!(
+            invariant
+                .compute_withdraw_one_exact_out(1_000_001, 2_000_000, 1_000_000, 1_000_000, &fees),
+            None
+        );
+    }
+
+    #[test]
+    fn test_compute_zap_in_swap() {
+        let invariant = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        for &(amount_in, source, destination) in [
+            (1_000, 1_000_000, 1_000_000),
+            (500_000, 1_000_000, 1_000_000),
+            (123_456, 1_000_000, 3_000_000),
+            (1_000_000, 3_000_000, 2_000_000),
+        ]
+        .iter()
+        {
+            let (swap_amount, result) = invariant
+                .compute_zap_in_swap(amount_in, source, destination, &MODEL_FEES)
+                .unwrap();
+            assert!(swap_amount > 0 && swap_amount < amount_in);
+            let swapped = invariant
+                .swap_to(swap_amount, source, destination, &MODEL_FEES)
+                .unwrap();
+            assert_eq!(result.amount_swapped, swapped.amount_swapped);
+            assert_eq!(result.new_source_amount, swapped.new_source_amount);
+
+            // the deposit is proportional to the new reserves, up to one token swapped
+            let rest = u128::from(amount_in - swap_amount);
+            assert!(
+                rest * u128::from(result.new_destination_amount)
+                    >= u128::from(result.amount_swapped) * u128::from(result.new_source_amount)
+            );
+            let next = invariant
+                .swap_to(swap_amount + 1, source, destination, &MODEL_FEES)
+                .unwrap();
+            assert!(
+                (rest - 1) * u128::from(next.new_destination_amount)
+                    < u128::from(next.amount_swapped) * u128::from(next.new_source_amount)
+            );
+        }
+
+        // most of the input is swapped into a pool short of the source token
+        let (swap_amount, _result) = invariant
+            .compute_zap_in_swap(1_000, 100_000, 10_000_000, &MODEL_FEES)
+            .unwrap();
+        assert!(swap_amount > 900);
+    }
+
+    #[test]
+    fn test_swap_with_rates() {
+        let invariant = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let rate = 1_050_000_000;
+
+        // unpegged pools swap as before
+        let result = invariant
+            .swap_to_with_rates(
+                1_000,
+                1_000_000,
+                1_000_000,
+                PEG_RATE_PRECISION,
+                PEG_RATE_PRECISION,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        let expected = invariant
+            .swap_to(1_000, 1_000_000, 1_000_000, &MODEL_FEES)
+            .unwrap();
+        assert_eq!(result.amount_swapped, expected.amount_swapped);
+        assert_eq!(
+            result.new_destination_amount,
+            expected.new_destination_amount
+        );
+
+        // a pegged pool whose reserves are worth the same swaps at the peg rate
+        let result = invariant
+            .swap_to_with_rates(
+                1_000,
+                1_000_000,
+                1_050_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        let expected = invariant
+            .swap_to(1_050, 1_050_000, 1_050_000, &MODEL_FEES)
+            .unwrap();
+        assert_eq!(result.amount_swapped, expected.amount_swapped);
+        assert_eq!(result.new_source_amount, 1_001_000);
+        assert_eq!(
+            result.new_destination_amount,
+            1_050_000 - result.amount_swapped - result.admin_fee
+        );
+        let back = invariant
+            .swap_to_with_rates(
+                1_050,
+                1_050_000,
+                1_000_000,
+                PEG_RATE_PRECISION,
+                rate,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        assert!(back.amount_swapped < 1_000 && back.amount_swapped > 990);
+
+        // deposits are valued at the peg rate
+        let balanced = invariant
+            .compute_mint_amount_for_deposit_with_rates(
+                1_050,
+                1_000,
+                1_050_000,
+                1_000_000,
+                2_050_000,
+                PEG_RATE_PRECISION,
+                rate,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        let unpegged = invariant
+            .compute_mint_amount_for_deposit(
+                1_050,
+                1_050,
+                1_050_000,
+                1_050_000,
+                2_050_000,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        assert_eq!(balanced, unpegged);
+
+        // single-token withdrawals pay out the value of the pool tokens at the peg rate
+        let (dy, _dy_fee) = invariant
+            .compute_withdraw_one_with_rates(
+                1_000,
+                2_100_000,
+                1_000_000,
+                1_050_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        let (virtual_dy, _dy_fee) = invariant
+            .compute_withdraw_one(1_000, 2_100_000, 1_050_000, 1_050_000, &MODEL_FEES)
+            .unwrap();
+        assert_eq!(dy, virtual_dy * 20 / 21);
+        let burn = invariant
+            .compute_withdraw_one_exact_out_with_rates(
+                dy,
+                2_100_000,
+                1_000_000,
+                1_050_000,
+                rate,
+                PEG_RATE_PRECISION,
+                &MODEL_FEES,
+            )
+            .unwrap();
+        assert!(burn <= 1_000);
+    }
+
+    #[test]
+    fn test_price_impact_bps() {
+        let swap = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let impact = |amount_in: u64, swap_source_amount: u64, swap_destination_amount: u64| {
+            let result = swap
+                .swap_to(
+                    amount_in,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    &MODEL_FEES,
+                )
+                .unwrap();
+            (
+                result.spot_amount_swapped,
+                result.price_impact_bps().unwrap(),
+            )
+        };
+
+        // Small swaps trade at the marginal price, which is 1:1 in a balanced pool,
+        // less the unit y rounds up by
+        assert_eq!(
+            impact(1_000_000, 1_000_000_000_000, 1_000_000_000_000),
+            (1_000_000, 1)
+        );
+
+        // In an imbalanced pool the marginal price matches what a small swap pays out
+        let (swap_source_amount, swap_destination_amount) = (3_000_000_000_000, 1_000_000_000_000);
+        let d = swap
+            .compute_d(swap_source_amount, swap_destination_amount)
+            .unwrap();
+        let (spot_amount, impact_bps) =
+            impact(1_000_000, swap_source_amount, swap_destination_amount);
+        let y = swap.compute_y(swap_source_amount + 1_000_000, d).unwrap();
+        assert!(spot_amount < 1_000_000);
+        // up to the precision of compute_y
+        assert!((swap_destination_amount - y).abs_diff(spot_amount) <= 2);
+        assert!(impact_bps <= 1);
+
+        // The impact grows with the size of the swap
+        let mut previous_impact_bps = 0;
+        for amount_in in [
+            10_000_000_000,
+            100_000_000_000,
+            1_000_000_000_000,
+            10_000_000_000_000,
+        ] {
+            let (_, impact_bps) = impact(amount_in, 1_000_000_000_000, 1_000_000_000_000);
+            assert!(impact_bps > previous_impact_bps);
+            previous_impact_bps = impact_bps;
+        }
+        assert!(previous_impact_bps < BPS_DENOMINATOR);
+
+        // A swap worth nothing at the marginal price has no impact
+        let result = SwapResult {
+            new_source_amount: 1,
+            new_destination_amount: 1,
+            amount_swapped: 0,
+            admin_fee: 0,
+            fee: 0,
+            spot_amount_swapped: 0,
+        };
+        assert_eq!(result.price_impact_bps(), Some(0));
+    }
+
+    #[test]
+    fn test_swap_dynamic_fee() {
+        let swap = StableSwap::new(
+            MIN_AMP * 100 * AMP_PRECISION,
+            MIN_AMP * 100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let flat_fees = Fees {
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            ..MODEL_FEES
+        };
+        let dynamic_fees = Fees {
+            dynamic_fee: DynamicFee {
+                mode: FeeMode::Dynamic,
+                fee_gamma: FEE_GAMMA_PRECISION / 100,
+                out_fee_numerator: 40,
+            },
+            ..flat_fees
+        };
+        let reserves = 1_000_000_000;
+
+        // small trades on a balanced pool pay the flat fee
+        let flat = swap
+            .swap_to(1_000_000, reserves, reserves, &flat_fees)
+            .unwrap();
+        let dynamic = swap
+            .swap_to(1_000_000, reserves, reserves, &dynamic_fees)
+            .unwrap();
+        assert_eq!(flat.fee, dynamic.fee);
+        assert_eq!(flat.amount_swapped, dynamic.amount_swapped);
+
+        // trades that imbalance the pool pay more
+        let flat = swap
+            .swap_to(900_000_000, reserves, reserves, &flat_fees)
+            .unwrap();
+        let dynamic = swap
+            .swap_to(900_000_000, reserves, reserves, &dynamic_fees)
+            .unwrap();
+        assert!(dynamic.fee > flat.fee);
+        assert!(dynamic.amount_swapped < flat.amount_swapped);
+        let dy = flat.amount_swapped + flat.fee;
+        assert_eq!(dy, dynamic.amount_swapped + dynamic.fee);
+        assert!(dynamic.fee <= dy * 40 / 10_000);
+    }
+
+    #[test]
+    fn test_swap_rebalancing_rebate() {
+        let swap = StableSwap::new(
+            MIN_AMP * 100 * AMP_PRECISION,
+            MIN_AMP * 100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let fees = Fees {
+            admin_trade_fee_numerator: 1,
+            admin_trade_fee_denominator: 2,
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            ..MODEL_FEES
+        };
+        let rebate_fees = Fees {
+            rebalancing_rebate_bps: 2_500,
+            host_fee_bps: 0,
+            ..fees
+        };
+        let amount_in = 100_000_000;
+
+        // trades that imbalance the pool pay the full fee
+        let full = swap
+            .swap_to(amount_in, 1_000_000_000, 1_000_000_000, &fees)
+            .unwrap();
+        let rebated = swap
+            .swap_to(amount_in, 1_000_000_000, 1_000_000_000, &rebate_fees)
+            .unwrap();
+        assert_eq!(full.fee, rebated.fee);
+        assert_eq!(full.amount_swapped, rebated.amount_swapped);
+
+        // trades that rebalance the pool get a quarter of the fee back out of the admin fee
+        let full = swap
+            .swap_to(amount_in, 1_000_000_000, 1_500_000_000, &fees)
+            .unwrap();
+        let rebated = swap
+            .swap_to(amount_in, 1_000_000_000, 1_500_000_000, &rebate_fees)
+            .unwrap();
+        let rebate = full.fee / 4;
+        assert!(rebate > 0);
+        assert_eq!(rebated.fee, full.fee - rebate);
+        assert_eq!(rebated.admin_fee, full.admin_fee - rebate);
+        assert_eq!(rebated.amount_swapped, full.amount_swapped + rebate);
+        // liquidity providers keep their share of the fee
+        assert_eq!(rebated.new_destination_amount, full.new_destination_amount);
+    }
+
+    proptest! {
+        #[test]
+        fn test_swap_rounds_in_favor_of_the_pool(
+            amp_factor in MIN_AMP..MAX_AMP,
+            source_amount in 1..MAX_TOKENS_IN,
+            swap_source_amount in 1..MAX_TOKENS_IN,
+            swap_destination_amount in 1..MAX_TOKENS_IN,
+            trade_fee_bps in 0..=100u64,
+        ) {
+            let fees = Fees::from_bps(trade_fee_bps, 0, 5_000).unwrap();
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let result = invariant.swap_to(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &fees,
+            );
+            prop_assume!(result.is_some());
+            let result = result.unwrap();
+            let dy = u128::from(result.amount_swapped + result.fee);
+
+            // the fee is never less than the exact trade fee
+            prop_assert!(
+                u128::from(result.fee) * u128::from(BPS_DENOMINATOR)
+                    >= dy * u128::from(trade_fee_bps)
+            );
+            // the admin never takes more than its exact share of the fee
+            prop_assert!(u128::from(result.admin_fee) * 2 <= u128::from(result.fee));
+            prop_assert_eq!(
+                result.new_destination_amount,
+                swap_destination_amount - result.amount_swapped - result.admin_fee
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_quick_swap_never_pays_more(
+            amp_factor in MIN_AMP..MAX_AMP,
+            swap_source_amount in 1..MAX_TOKENS_IN,
+            swap_destination_amount in 1..MAX_TOKENS_IN,
+            source_bps in 0..=100u64,
+            trade_fee_bps in 0..=100u64,
+        ) {
+            let fees = Fees::from_bps(trade_fee_bps, 0, 5_000).unwrap();
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let source_amount = swap_source_amount / BPS_DENOMINATOR * source_bps;
+            let quick = invariant.quick_swap_to(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                100,
+                &fees,
+            );
+            let full = invariant.swap_to(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &fees,
+            );
+            prop_assume!(quick.is_some() && full.is_some());
+            let (quick, full) = (quick.unwrap(), full.unwrap());
+
+            prop_assert!(quick.amount_swapped <= full.amount_swapped);
+            prop_assert!(quick.new_destination_amount >= full.new_destination_amount);
+            prop_assert_eq!(
+                quick.new_destination_amount,
+                swap_destination_amount - quick.amount_swapped - quick.admin_fee
+            );
+        }
+    }
+
+    #[test]
+    fn test_quick_swap_to() {
+        let invariant = StableSwap::new(
+            MIN_AMP * 100 * AMP_PRECISION,
+            MIN_AMP * 100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let fees = Fees::from_bps(4, 0, 5_000).unwrap();
+        let reserves = 1_000_000_000_000;
+
+        // within a hair of the full solve on balanced and imbalanced pools
+        for (swap_source_amount, swap_destination_amount) in [
+            (reserves, reserves),
+            (reserves, reserves * 3 / 2),
+            (reserves * 3 / 2, reserves),
+        ] {
+            let quick = invariant
+                .quick_swap_to(
+                    1_000_000,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    1,
+                    &fees,
+                )
+                .unwrap();
+            let full = invariant
+                .swap_to(
+                    1_000_000,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    &fees,
+                )
+                .unwrap();
+            assert!(quick.amount_swapped <= full.amount_swapped);
+            assert!(quick.amount_swapped * 1_001 >= full.amount_swapped * 1_000);
+        }
+
+        // larger trades are solved in full
+        assert!(invariant
+            .quick_swap_to(100_000_001, reserves, reserves, 1, &fees)
+            .is_none());
+        let quick = invariant
+            .swap_to_with_rates_quick(
+                100_000_001,
+                reserves,
+                reserves,
+                PEG_RATE_PRECISION,
+                PEG_RATE_PRECISION,
+                1,
+                &fees,
+            )
+            .unwrap();
+        let full = invariant
+            .swap_to(100_000_001, reserves, reserves, &fees)
+            .unwrap();
+        assert_eq!(quick.amount_swapped, full.amount_swapped);
+
+        // as are trades paying the dynamic fee
+        let dynamic_fees = Fees {
+            dynamic_fee: DynamicFee {
+                mode: FeeMode::Dynamic,
+                fee_gamma: FEE_GAMMA_PRECISION / 100,
+                out_fee_numerator: 40,
+            },
+            ..fees
+        };
+        assert!(invariant
+            .quick_swap_to(1_000_000, reserves, reserves, 1, &dynamic_fees)
+            .is_none());
+    }
 }
@@ -1,117 +1,402 @@
 //! Error types
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use num_derive::FromPrimitive;
+use num_traits::FromPrimitive as _;
+#[cfg(not(target_arch = "wasm32"))]
 use solana_program::{
     decode_error::DecodeError,
+    instruction::Instruction,
     msg,
     program_error::{PrintProgramError, ProgramError},
+    pubkey::Pubkey,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use std::convert::TryFrom;
 use thiserror::Error;
 
 /// Errors that may be returned by the StableSwap program.
+///
+/// Each variant is returned as `ProgramError::Custom(code)`. Codes are stable:
+/// new variants are appended with the next code, and a code is never reused.
+/// Checks may log what they compared first, see [SwapError::with_context].
 #[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
 pub enum SwapError {
     /// The account cannot be initialized because it is already being used.
     #[error("Swap account already in use")]
-    AlreadyInUse,
+    AlreadyInUse = 0,
     /// The address of the admin fee account is incorrect.
     #[error("Address of the admin fee account is incorrect")]
-    InvalidAdmin,
+    InvalidAdmin = 1,
     /// The owner of the input isn't set to the program address generated by the program.
     #[error("Input account owner is not the program address")]
-    InvalidOwner,
+    InvalidOwner = 2,
     /// The owner of the pool token output is set to the program address generated by the program.
     #[error("Output pool account owner cannot be the program address")]
-    InvalidOutputOwner,
+    InvalidOutputOwner = 3,
     /// The program address provided doesn't match the value generated by the program.
     #[error("Invalid program address generated from nonce and key")]
-    InvalidProgramAddress,
+    InvalidProgramAddress = 4,
     /// The deserialization of the account returned something besides State::Mint.
     #[error("Deserialized account is not an SPL Token mint")]
-    ExpectedMint,
+    ExpectedMint = 5,
     /// The deserialization of the account returned something besides State::Account.
     #[error("Deserialized account is not an SPL Token account")]
-    ExpectedAccount,
+    ExpectedAccount = 6,
     /// The pool supply is empty.
     #[error("Pool token supply is 0")]
-    EmptyPool,
+    EmptyPool = 7,
     /// The input token account is empty.
     #[error("Input token account empty")]
-    EmptySupply,
+    EmptySupply = 8,
     /// The pool token mint has a non-zero supply.
     #[error("Pool token mint has a non-zero supply")]
-    InvalidSupply,
+    InvalidSupply = 9,
     /// The provided token account has a delegate.
     #[error("Token account has a delegate")]
-    InvalidDelegate,
+    InvalidDelegate = 10,
     /// The input token is invalid for swap.
     #[error("InvalidInput")]
-    InvalidInput,
+    InvalidInput = 11,
     /// Address of the provided swap token account is incorrect.
     #[error("Address of the provided swap token account is incorrect")]
-    IncorrectSwapAccount,
+    IncorrectSwapAccount = 12,
     /// Address of the provided token mint is incorrect
     #[error("Address of the provided token mint is incorrect")]
-    IncorrectMint,
+    IncorrectMint = 13,
     /// The calculation failed.
     #[error("CalculationFailure")]
-    CalculationFailure,
+    CalculationFailure = 14,
     /// Invalid instruction number passed in.
     #[error("Invalid instruction")]
-    InvalidInstruction,
+    InvalidInstruction = 15,
     /// Swap input token accounts have the same mint
     #[error("Swap input token accounts have the same mint")]
-    RepeatedMint,
+    RepeatedMint = 16,
     /// Swap instruction exceeds desired slippage limit
     #[error("Swap instruction exceeds desired slippage limit")]
-    ExceededSlippage,
+    ExceededSlippage = 17,
     /// The provided token account has a close authority.
     #[error("Token account has a close authority")]
-    InvalidCloseAuthority,
+    InvalidCloseAuthority = 18,
     /// The pool token mint has a freeze authority.
     #[error("Pool token mint has a freeze authority")]
-    InvalidFreezeAuthority,
+    InvalidFreezeAuthority = 19,
     /// ConversionFailure
     #[error("Conversion to u64 failed with an overflow or underflow")]
-    ConversionFailure,
+    ConversionFailure = 20,
     /// Unauthorized
     #[error("Account is not authorized to execute this instruction")]
-    Unauthorized,
+    Unauthorized = 21,
     /// Swap pool is paused
     #[error("Swap pool is paused")]
-    IsPaused,
+    IsPaused = 22,
     /// Amp. coefficient change is within min ramp duration
     #[error("Ramp is locked in this time period")]
-    RampLocked,
+    RampLocked = 23,
     /// Insufficient ramp time for the ramp operation
     #[error("Insufficient ramp time")]
-    InsufficientRampTime,
+    InsufficientRampTime = 24,
     /// Active admin transfer in progress
     #[error("Active admin transfer in progress")]
-    ActiveTransfer,
+    ActiveTransfer = 25,
     /// No active admin transfer in progress
     #[error("No active admin transfer in progress")]
-    NoActiveTransfer,
+    NoActiveTransfer = 26,
     /// Admin transfer deadline exceeded
     #[error("Admin transfer deadline exceeded")]
-    AdminDeadlineExceeded,
+    AdminDeadlineExceeded = 27,
     /// Token mint decimals must be the same.
     #[error("Token mints must have same decimals")]
-    MismatchedDecimals,
+    MismatchedDecimals = 28,
+    /// Active fee change in progress
+    #[error("Active fee change in progress")]
+    ActiveFeeChange = 29,
+    /// No active fee change in progress
+    #[error("No active fee change in progress")]
+    NoActiveFeeChange = 30,
+    /// Fee change cannot be applied before its activation time
+    #[error("Fee change is not active yet")]
+    FeeChangeLocked = 31,
+    /// Nothing to cancel
+    #[error("No pending fee change or admin transfer")]
+    NoPendingChange = 32,
+    /// All pools of the program are paused
+    #[error("All pools are paused by the emergency authority")]
+    GloballyPaused = 33,
+    /// The token account to create is not the associated token account of the user
+    #[error("Token account is not the associated token account of the user")]
+    InvalidAssociatedTokenAccount = 34,
+    /// The pool price deviates too much from the oracle price
+    #[error("Pool price deviates too much from the oracle price")]
+    PriceDeviation = 35,
+    /// The price account is not a trading Pyth price account, or its price is stale
+    #[error("Invalid or stale oracle price account")]
+    InvalidOracle = 36,
+    /// The user authority of a deposit is not on the deposit allowlist of the swap
+    #[error("Depositor is not on the deposit allowlist")]
+    NotAllowlisted = 37,
+    /// The swap takes more than the max share of the output reserve
+    #[error("Swap exceeds the max share of the reserves")]
+    ExceededMaxSwapSize = 38,
+    /// A fee has a zero denominator or exceeds 100%
+    #[error("Invalid fees")]
+    InvalidFees = 39,
+    /// The instruction is only available while the swap is paused
+    #[error("Swap pool is not paused")]
+    NotPaused = 40,
+    /// The checksum of the swap account does not match its data
+    #[error("Swap account checksum mismatch")]
+    InvalidChecksum = 41,
+    /// The swap is deprecated, only withdrawals are allowed
+    #[error("Swap pool is deprecated")]
+    PoolDeprecated = 42,
+    /// The instruction is not available to pools whose authority is shared
+    #[error("Not available with a shared authority")]
+    SharedAuthority = 43,
+    /// The instruction moves no tokens, and the swap rejects those
+    #[error("Zero amount")]
+    ZeroAmount = 44,
+    /// The swap does not allow the calling program to invoke it
+    #[error("Cross-program invocation not allowed")]
+    CpiNotAllowed = 45,
+    /// The user authority swapped the other way in the same slot, see the same-slot guard
+    #[error("Swap reverses a swap of the same slot")]
+    SameSlotReversal = 46,
+    /// The instruction left the pool breaking one of its invariants, only checked by
+    /// builds with the `paranoid` feature
+    #[error("Pool invariant violated")]
+    InvariantViolation = 47,
+    /// A delegate of the pool tokens withdrew to token accounts not owned by their owner
+    #[error("Withdraw destination not owned by the owner of the pool tokens")]
+    InvalidWithdrawDestination = 48,
+    /// A token transfer moved another amount than the one it was issued for, e.g. a
+    /// transfer of a mint charging fees
+    #[error("Token transfer moved an unexpected amount")]
+    UnexpectedBalanceChange = 49,
+    /// The deposit would take the pool over its deposit caps
+    #[error("Deposit cap exceeded")]
+    DepositCapExceeded = 50,
+    /// The instruction data ends before the last field of the instruction
+    #[error("Instruction data too short")]
+    InstructionTooShort = 51,
+    /// The instruction data goes on after the last field of the instruction
+    #[error("Trailing instruction data")]
+    TrailingInstructionData = 52,
+    /// A SimulateSwap ran, its result is in the return data and the logs
+    #[error("Simulation complete")]
+    SimulationComplete = 53,
+    /// The last trade of the same-slot guard already holds the most user authorities
+    /// it records per slot
+    #[error("Too many traders in the slot")]
+    SlotTradersFull = 54,
 }
 
+impl SwapError {
+    /// The code returned in `ProgramError::Custom`.
+    pub fn code(&self) -> u32 {
+        self.clone() as u32
+    }
+
+    /// Looks up the error returned as `ProgramError::Custom(code)`.
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::from_u32(code)
+    }
+
+    /// What the caller can do about the error, if anything beyond retrying.
+    pub fn hint(&self) -> Option<&'static str> {
+        let hint = match self {
+            SwapError::InvalidAdmin => "Pass the admin fee accounts stored in the swap",
+            SwapError::InvalidOwner => {
+                "Pass token accounts owned by the swap authority, see the logged keys"
+            }
+            SwapError::InvalidProgramAddress => {
+                "Derive the swap authority from the swap key and its nonce"
+            }
+            SwapError::IncorrectSwapAccount => {
+                "Pass the reserves stored in the swap, see the logged keys"
+            }
+            SwapError::IncorrectMint => "Pass the mints stored in the swap, see the logged keys",
+            SwapError::InvalidInstruction => {
+                "Check the instruction data against the program version"
+            }
+            SwapError::ExceededSlippage => "Lower the minimum amount out or refresh the quote",
+            SwapError::Unauthorized => "Sign with the admin of the swap",
+            SwapError::IsPaused | SwapError::GloballyPaused => {
+                "Wait for the admin to unpause the pool"
+            }
+            SwapError::RampLocked | SwapError::InsufficientRampTime => {
+                "Respect the minimum ramp duration"
+            }
+            SwapError::FeeChangeLocked => "Apply the new fees once their activation time passed",
+            SwapError::InvalidOracle => "Pass a fresh oracle price account",
+            SwapError::NotAllowlisted => "Ask the admin to allowlist the depositor",
+            SwapError::ExceededMaxSwapSize => "Split the swap into smaller ones",
+            SwapError::InvalidChecksum => "The swap account is corrupt, contact the admin",
+            SwapError::PoolDeprecated => "Withdraw the liquidity and move to the new pool",
+            SwapError::ZeroAmount => "Pass a non-zero amount",
+            SwapError::CpiNotAllowed => {
+                "Call the swap directly, or from a program the admin allowed"
+            }
+            SwapError::SameSlotReversal => "Swap back in a later slot",
+            SwapError::SlotTradersFull => "Swap in a later slot",
+            SwapError::InvariantViolation => {
+                "The pool is in an unexpected state, contact the admin"
+            }
+            SwapError::UnexpectedBalanceChange => {
+                "Use tokens whose transfers move the exact amount, without transfer fees"
+            }
+            SwapError::DepositCapExceeded => "Deposit less, up to the cap of the pool",
+            SwapError::SimulationComplete => {
+                "Expected of a SimulateSwap, read its result from the return data"
+            }
+            SwapError::InstructionTooShort | SwapError::TrailingInstructionData => {
+                "Build the instruction with the instruction builders of the program version"
+            }
+            _ => return None,
+        };
+        Some(hint)
+    }
+}
+
+/// Tag of the `sol_log_data` logs of error contexts, telling them apart from events
+pub const ERROR_CONTEXT_DATA_TAG: [u8; 8] = *b"swapctx\0";
+
+/// What a failed check compared, logged borsh encoded with `sol_log_data` after
+/// [ERROR_CONTEXT_DATA_TAG] right before the program returns the error, so that
+/// integrators see more than a bare error code. See
+/// [parse_error_context](../events/fn.parse_error_context.html).
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Eq, PartialEq)]
+pub enum ErrorContext {
+    /// Two keys that did not match, logged in the order the check compared them. A
+    /// missing key is logged as the default key.
+    Keys {
+        /// First key of the check
+        left: [u8; 32],
+        /// Second key of the check
+        right: [u8; 32],
+    },
+    /// An amount out of bounds
+    Amounts {
+        /// Amount the check allowed at most or at least, e.g. the minimum amount out
+        expected: u64,
+        /// Amount computed by the program
+        actual: u64,
+    },
+    /// A token program instruction invoked by the swap failed
+    TokenProgram {
+        /// Tag of the token instruction, e.g. 3 for a `Transfer`
+        instruction: u8,
+        /// First account of the instruction: the source of a transfer, the mint of a
+        /// `MintTo`, the account of the others
+        account: [u8; 32],
+        /// The error it returned, as encoded by `u64::from(ProgramError)`
+        error: u64,
+    },
+}
+
+impl ErrorContext {
+    /// Decodes the data of a `Program data: ` log, if it holds an error context
+    pub fn from_log_data(data: &[&[u8]]) -> Option<Self> {
+        match data {
+            [tag, context] if *tag == ERROR_CONTEXT_DATA_TAG => Self::try_from_slice(context).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ErrorContext {
+    /// Keys compared by a failed check, in the order they were compared
+    pub fn keys(left: &Pubkey, right: &Pubkey) -> Self {
+        Self::Keys {
+            left: left.to_bytes(),
+            right: right.to_bytes(),
+        }
+    }
+
+    /// A failed token program instruction and the error it returned
+    pub fn token_program(instruction: &Instruction, error: &ProgramError) -> Self {
+        Self::TokenProgram {
+            instruction: instruction.data.first().copied().unwrap_or_default(),
+            account: instruction
+                .accounts
+                .first()
+                .map(|meta| meta.pubkey.to_bytes())
+                .unwrap_or_default(),
+            error: u64::from(error.clone()),
+        }
+    }
+
+    /// Logs the context. Amounts are also logged with `msg!` in the format of the
+    /// slippage errors of earlier program versions.
+    pub fn log(&self) {
+        if let ErrorContext::Amounts { expected, actual } = self {
+            solana_program::log::sol_log_64(0, 0, 0, *expected, *actual);
+        }
+        if let Ok(data) = self.try_to_vec() {
+            solana_program::log::sol_log_data(&[&ERROR_CONTEXT_DATA_TAG, &data]);
+        }
+    }
+}
+
+/// A [SwapError] and the context of the check that failed with it. Converting it into
+/// a `ProgramError` logs the context, see [ErrorContext::log].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapErrorWithContext {
+    /// Error returned
+    pub error: SwapError,
+    /// What the failed check compared
+    pub context: ErrorContext,
+}
+
+impl SwapError {
+    /// The error with the context of the check that failed with it
+    pub fn with_context(self, context: ErrorContext) -> SwapErrorWithContext {
+        SwapErrorWithContext {
+            error: self,
+            context,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<SwapErrorWithContext> for ProgramError {
+    fn from(e: SwapErrorWithContext) -> Self {
+        e.context.log();
+        e.error.into()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl From<SwapError> for ProgramError {
     fn from(e: SwapError) -> Self {
-        ProgramError::Custom(e as u32)
+        ProgramError::Custom(e.code())
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl TryFrom<ProgramError> for SwapError {
+    type Error = ProgramError;
+
+    /// Recovers the swap error from a program error, or hands back the
+    /// program error if it is not one.
+    fn try_from(error: ProgramError) -> Result<Self, Self::Error> {
+        match error {
+            ProgramError::Custom(code) => Self::from_code(code).ok_or(error),
+            _ => Err(error),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl<T> DecodeError<T> for SwapError {
     fn type_of() -> &'static str {
         "Swap Error"
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl PrintProgramError for SwapError {
     fn print<E>(&self)
     where
@@ -121,58 +406,90 @@ impl PrintProgramError for SwapError {
             + PrintProgramError
             + num_traits::FromPrimitive,
     {
-        match self {
-            SwapError::AlreadyInUse => msg!("Error: Swap account already in use"),
-            SwapError::InvalidAdmin => {
-                msg!("Error: Address of the admin fee account is incorrect")
-            }
-            SwapError::InvalidOwner => {
-                msg!("Error: The input account owner is not the program address")
-            }
-            SwapError::InvalidOutputOwner => {
-                msg!("Error: Output pool account owner cannot be the program address")
-            }
-            SwapError::InvalidProgramAddress => {
-                msg!("Error: Invalid program address generated from nonce and key")
-            }
-            SwapError::ExpectedMint => {
-                msg!("Error: Deserialized account is not an SPL Token mint")
-            }
-            SwapError::ExpectedAccount => {
-                msg!("Error: Deserialized account is not an SPL Token account")
-            }
-            SwapError::EmptySupply => msg!("Error: Input token account empty"),
-            SwapError::EmptyPool => msg!("Error: Pool token supply is 0"),
-            SwapError::InvalidSupply => msg!("Error: Pool token mint has a non-zero supply"),
-            SwapError::RepeatedMint => msg!("Error: Swap input token accounts have the same mint"),
-            SwapError::InvalidDelegate => msg!("Error: Token account has a delegate"),
-            SwapError::InvalidInput => msg!("Error: InvalidInput"),
-            SwapError::IncorrectSwapAccount => {
-                msg!("Error: Address of the provided swap token account is incorrect")
-            }
-            SwapError::IncorrectMint => {
-                msg!("Error: Address of the provided token mint is incorrect")
-            }
-            SwapError::CalculationFailure => msg!("Error: CalculationFailure"),
-            SwapError::InvalidInstruction => msg!("Error: InvalidInstruction"),
-            SwapError::ExceededSlippage => {
-                msg!("Error: Swap instruction exceeds desired slippage limit")
-            }
-            SwapError::InvalidCloseAuthority => msg!("Error: Token account has a close authority"),
-            SwapError::InvalidFreezeAuthority => {
-                msg!("Error: Pool token mint has a freeze authority")
+        msg!("Error {}: {}", self.code(), self);
+        if let Some(hint) = self.hint() {
+            msg!("Hint: {}", hint);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        // codes integrators already match on
+        assert_eq!(SwapError::AlreadyInUse.code(), 0);
+        assert_eq!(SwapError::ExceededSlippage.code(), 17);
+        assert_eq!(SwapError::Unauthorized.code(), 21);
+        assert_eq!(SwapError::IsPaused.code(), 22);
+        assert_eq!(SwapError::ZeroAmount.code(), 44);
+
+        let mut code = 0;
+        while let Some(error) = SwapError::from_code(code) {
+            assert_eq!(error.code(), code);
+            assert_eq!(
+                SwapError::try_from(ProgramError::from(error.clone())),
+                Ok(error)
+            );
+            code += 1;
+        }
+        assert_eq!(code, SwapError::SlotTradersFull.code() + 1);
+
+        assert_eq!(
+            SwapError::try_from(ProgramError::InvalidArgument),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            SwapError::try_from(ProgramError::Custom(code)),
+            Err(ProgramError::Custom(code))
+        );
+    }
+
+    #[test]
+    fn test_error_with_context() {
+        let context = ErrorContext::Amounts {
+            expected: 100,
+            actual: 99,
+        };
+        let error = SwapError::ExceededSlippage.with_context(context.clone());
+        assert_eq!(
+            ProgramError::from(error),
+            SwapError::ExceededSlippage.into()
+        );
+        let data = context.try_to_vec().unwrap();
+        assert_eq!(
+            ErrorContext::from_log_data(&[&ERROR_CONTEXT_DATA_TAG, &data]),
+            Some(context)
+        );
+        assert_eq!(ErrorContext::from_log_data(&[b"swapevt\0", &data]), None);
+
+        let (left, right) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(
+            ErrorContext::keys(&left, &right),
+            ErrorContext::Keys {
+                left: left.to_bytes(),
+                right: right.to_bytes()
             }
-            SwapError::ConversionFailure => msg!("Error: Conversion to or from u64 failed"),
-            SwapError::Unauthorized => {
-                msg!("Error: Account is not authorized to execute this instruction")
+        );
+        let transfer = spl_token::instruction::transfer(
+            &spl_token::id(),
+            &left,
+            &right,
+            &Pubkey::new_unique(),
+            &[],
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            ErrorContext::token_program(&transfer, &ProgramError::Custom(1)),
+            ErrorContext::TokenProgram {
+                instruction: 3,
+                account: left.to_bytes(),
+                error: 1
             }
-            SwapError::IsPaused => msg!("Error: Swap pool is paused"),
-            SwapError::RampLocked => msg!("Error: Ramp is locked in this time period"),
-            SwapError::InsufficientRampTime => msg!("Error: Insufficient ramp time"),
-            SwapError::ActiveTransfer => msg!("Error: Active admin transfer in progress"),
-            SwapError::NoActiveTransfer => msg!("Error: No active admin transfer in progress"),
-            SwapError::AdminDeadlineExceeded => msg!("Error: Admin transfer deadline exceeded"),
-            SwapError::MismatchedDecimals => msg!("Error: Token mints must have same decimals"),
-        }
+        );
     }
 }
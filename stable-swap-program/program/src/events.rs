@@ -0,0 +1,457 @@
+//! Events logged by the program, and a parser turning the logs of a transaction
+//! back into them.
+//!
+//! Every event is logged in the original `msg!` format,
+//!
+//! ```text
+//! Program log: Event: SwapAToB
+//! Program log: 0x2, 0x2710, 0x2704, 0x1, 0x4
+//! Program log: Timestamp: 1650000000
+//! ```
+//!
+//! followed by a borsh encoded [LoggedEvent] logged with `sol_log_data`, which the
+//! runtime shows base64 encoded after [EVENT_DATA_TAG]:
+//!
+//! ```text
+//! Program data: c3dhcGV2dAA= AhAnAAAAAAAABCcAAAAAAAABAAAAAAAAAAQAAAAAAAAAAYAAWWIAAAAA
+//! ```
+//!
+//! [parse_logs] reads both, so that transactions of program versions logging only
+//! the first format are parsed too.
+//!
+//! Pools with [quiet_logs](../state/struct.SwapInfo.html#structfield.quiet_logs) set,
+//! and every pool of a build with the `quiet-logs` feature, log only the second.
+//!
+//! Failed checks log what they compared before returning their error, see
+//! [ErrorContext], which [parse_error_context] reads back.
+
+use crate::error::ErrorContext;
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::convert::TryFrom;
+
+/// Tag of the `sol_log_data` logs of events, telling them apart from data logged by
+/// other instructions of the program
+pub const EVENT_DATA_TAG: [u8; 8] = *b"swapevt\0";
+
+/// Event kind, logged as the first value of the event. The order of the variants is
+/// their code, new variants are appended.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// Burn event
+    Burn,
+    /// Deposit event
+    Deposit,
+    /// Swap event A -> B, which logs the price impact in basis points in
+    /// place of a pool token amount
+    SwapAToB,
+    /// Swap event B -> A, which logs the price impact in basis points in
+    /// place of a pool token amount
+    SwapBToA,
+    /// Withdraw event (A)
+    WithdrawA,
+    /// Withdraw event (B)
+    WithdrawB,
+    /// Admin fees collected
+    CollectAdminFees,
+    /// Tokens donated to the reserves
+    DonateToReserves,
+    /// Reserves migrated to a new swap
+    MigratePool,
+    /// Admin fees harvested into the rewards distributor
+    HarvestFees,
+    /// Instruction whose amounts are all zero, which moved no tokens
+    Noop,
+    /// Reserves synced, logging the tokens transferred to them directly
+    ReservesSynced,
+    /// Amplification coefficient cut by the admin in an emergency, logging it before
+    /// and after, in hundredths, in place of the token amounts
+    EmergencyAmpDown,
+    /// Admin fees taken from a swap, or from the swap of a withdrawal in one token
+    TradeAdminFees,
+    /// Admin fees taken from a withdrawal
+    WithdrawAdminFees,
+}
+
+impl Event {
+    /// Every event, in the order of their codes
+    pub const ALL: [Event; 15] = [
+        Event::Burn,
+        Event::Deposit,
+        Event::SwapAToB,
+        Event::SwapBToA,
+        Event::WithdrawA,
+        Event::WithdrawB,
+        Event::CollectAdminFees,
+        Event::DonateToReserves,
+        Event::MigratePool,
+        Event::HarvestFees,
+        Event::Noop,
+        Event::ReservesSynced,
+        Event::EmergencyAmpDown,
+        Event::TradeAdminFees,
+        Event::WithdrawAdminFees,
+    ];
+
+    /// The name logged after `Event: `
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Burn => "Burn",
+            Event::Deposit => "Deposit",
+            Event::SwapAToB => "SwapAToB",
+            Event::SwapBToA => "SwapBToA",
+            Event::WithdrawA => "WithdrawA",
+            Event::WithdrawB => "WithdrawB",
+            Event::CollectAdminFees => "CollectAdminFees",
+            Event::DonateToReserves => "DonateToReserves",
+            Event::MigratePool => "MigratePool",
+            Event::HarvestFees => "HarvestFees",
+            Event::Noop => "Noop",
+            Event::ReservesSynced => "ReservesSynced",
+            Event::EmergencyAmpDown => "EmergencyAmpDown",
+            Event::TradeAdminFees => "TradeAdminFees",
+            Event::WithdrawAdminFees => "WithdrawAdminFees",
+        }
+    }
+
+    /// Looks up an event by its logged name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|event| event.name() == name)
+    }
+
+    /// Looks up an event by its code
+    pub fn from_code(code: u64) -> Option<Self> {
+        Self::ALL.get(usize::try_from(code).ok()?).copied()
+    }
+}
+
+/// An event and the amounts logged with it
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Eq, PartialEq)]
+pub struct LoggedEvent {
+    /// Event kind
+    pub event: Event,
+    /// Amount of token A
+    pub token_a_amount: u64,
+    /// Amount of token B
+    pub token_b_amount: u64,
+    /// Amount of pool tokens, or the price impact in bps of swaps
+    pub pool_token_amount: u64,
+    /// Fee
+    pub fee: u64,
+    /// Unix timestamp of the event, missing from the logs of old program versions
+    pub timestamp: Option<i64>,
+}
+
+impl LoggedEvent {
+    /// Decodes the data of a `Program data: ` log, if it holds an event
+    pub fn from_log_data(data: &[&[u8]]) -> Option<Self> {
+        match data {
+            [tag, event] if *tag == EVENT_DATA_TAG => Self::try_from_slice(event).ok(),
+            _ => None,
+        }
+    }
+
+    fn from_msg_values(event: Event, values: &str) -> Option<Self> {
+        let mut values = values.split(", ").map(|value| {
+            value
+                .strip_prefix("0x")
+                .and_then(|value| u64::from_str_radix(value, 16).ok())
+        });
+        let mut next = || values.next().flatten();
+        // The first value is the event code
+        if next()? != event as u64 {
+            return None;
+        }
+        Some(Self {
+            event,
+            token_a_amount: next()?,
+            token_b_amount: next()?,
+            pool_token_amount: next()?,
+            fee: next()?,
+            timestamp: None,
+        })
+    }
+}
+
+/// Parses the events logged by the program `program_id`, given base58 encoded, from
+/// the log messages of a transaction. Logs of other programs are skipped, including
+/// those of programs the swap invokes and programs that invoke the swap.
+pub fn parse_logs<S: AsRef<str>>(program_id: &str, logs: &[S]) -> Vec<LoggedEvent> {
+    let mut events: Vec<LoggedEvent> = vec![];
+    // Programs of the invocations the logs are in, innermost last
+    let mut invocations: Vec<&str> = vec![];
+    // Event named by the previous `Event: ` log, logging its values next
+    let mut pending_event = None;
+    // Whether the last event was parsed from `msg!` logs, which the same event logged
+    // with `sol_log_data` replaces
+    let mut last_from_msg = false;
+    for log in logs.iter().map(AsRef::as_ref) {
+        match invocation_log(log) {
+            Some((program, "invoke")) => {
+                invocations.push(program);
+                pending_event = None;
+                last_from_msg = false;
+                continue;
+            }
+            Some((program, _)) if invocations.last() == Some(&program) => {
+                invocations.pop();
+                pending_event = None;
+                last_from_msg = false;
+                continue;
+            }
+            _ => {}
+        }
+        if invocations.last() != Some(&program_id) {
+            continue;
+        }
+        if let Some(data) = log.strip_prefix("Program data: ") {
+            let event = decode_log_data(data, LoggedEvent::from_log_data);
+            if let Some(event) = event {
+                if last_from_msg && events.last().map(|last| last.event) == Some(event.event) {
+                    events.pop();
+                }
+                events.push(event);
+                last_from_msg = false;
+            }
+            pending_event = None;
+        } else if let Some(message) = log.strip_prefix("Program log: ") {
+            if let Some(name) = message.strip_prefix("Event: ") {
+                pending_event = Event::from_name(name);
+            } else if let Some(event) = pending_event.take() {
+                if let Some(event) = LoggedEvent::from_msg_values(event, message) {
+                    events.push(event);
+                    last_from_msg = true;
+                }
+            } else if let Some(timestamp) = message.strip_prefix("Timestamp: ") {
+                if let Some(last) = events.last_mut().filter(|_| last_from_msg) {
+                    last.timestamp = timestamp.parse().ok();
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Parses the context of the error the program `program_id`, given base58 encoded,
+/// failed with from the log messages of a failed transaction, see [ErrorContext].
+/// `None` if the program logged none, e.g. program versions before it was logged.
+pub fn parse_error_context<S: AsRef<str>>(program_id: &str, logs: &[S]) -> Option<ErrorContext> {
+    let mut invocations: Vec<&str> = vec![];
+    let mut context = None;
+    for log in logs.iter().map(AsRef::as_ref) {
+        match invocation_log(log) {
+            Some((program, "invoke")) => {
+                invocations.push(program);
+                continue;
+            }
+            Some((program, outcome)) if invocations.last() == Some(&program) => {
+                invocations.pop();
+                // contexts of checks whose error the program recovered from
+                if program == program_id && outcome == "success" {
+                    context = None;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if invocations.last() != Some(&program_id) {
+            continue;
+        }
+        if let Some(data) = log.strip_prefix("Program data: ") {
+            if let Some(logged) = decode_log_data(data, ErrorContext::from_log_data) {
+                context = Some(logged);
+            }
+        }
+    }
+    context
+}
+
+/// The program and the outcome, `invoke`, `success` or `failed:`, of a log of the
+/// runtime around an invocation
+fn invocation_log(log: &str) -> Option<(&str, &str)> {
+    let mut words = log.strip_prefix("Program ")?.split(' ');
+    match (words.next(), words.next()) {
+        (Some(program), Some(outcome)) if ["invoke", "success", "failed:"].contains(&outcome) => {
+            Some((program, outcome))
+        }
+        _ => None,
+    }
+}
+
+/// Decodes the base64 encoded slices of a `Program data: ` log with `decode`
+fn decode_log_data<T>(data: &str, decode: fn(&[&[u8]]) -> Option<T>) -> Option<T> {
+    let decoded = data
+        .split(' ')
+        .map(base64::decode)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    let slices: Vec<&[u8]> = decoded.iter().map(Vec::as_slice).collect();
+    decode(&slices)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::error::ERROR_CONTEXT_DATA_TAG;
+
+    const PROGRAM_ID: &str = "SSwpkEEcbUqx4vtoEByFjSkhKdCT862DNVb52nZg1UZ";
+    const OTHER_PROGRAM_ID: &str = "JUP2jxvXaqu7NQY1GmNF4m1vodw12LVXYxbFL2uJvfo";
+    const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+    fn swap_event() -> LoggedEvent {
+        LoggedEvent {
+            event: Event::SwapAToB,
+            token_a_amount: 10_000,
+            token_b_amount: 9_988,
+            pool_token_amount: 1,
+            fee: 4,
+            timestamp: Some(1_650_000_000),
+        }
+    }
+
+    fn msg_logs(event: &LoggedEvent) -> Vec<String> {
+        let mut logs = vec![
+            format!("Program log: Event: {}", event.event.name()),
+            format!(
+                "Program log: {:#x}, {:#x}, {:#x}, {:#x}, {:#x}",
+                event.event as u64,
+                event.token_a_amount,
+                event.token_b_amount,
+                event.pool_token_amount,
+                event.fee
+            ),
+        ];
+        if let Some(timestamp) = event.timestamp {
+            logs.push(format!("Program log: Timestamp: {}", timestamp));
+        }
+        logs
+    }
+
+    fn data_log(event: &LoggedEvent) -> String {
+        format!(
+            "Program data: {} {}",
+            base64::encode(EVENT_DATA_TAG),
+            base64::encode(event.try_to_vec().unwrap())
+        )
+    }
+
+    fn invocation(program_id: &str, logs: Vec<String>) -> Vec<String> {
+        let mut invocation = vec![format!("Program {} invoke [1]", program_id)];
+        invocation.extend(logs);
+        invocation.push(format!("Program {} success", program_id));
+        invocation
+    }
+
+    #[test]
+    fn test_event_codes() {
+        for (code, event) in Event::ALL.iter().enumerate() {
+            assert_eq!(*event as usize, code);
+            assert_eq!(Event::from_code(code as u64), Some(*event));
+            assert_eq!(Event::from_name(event.name()), Some(*event));
+            assert_eq!(event.try_to_vec().unwrap(), vec![code as u8]);
+        }
+        assert_eq!(Event::from_code(Event::ALL.len() as u64), None);
+        assert_eq!(Event::from_name("Swap"), None);
+    }
+
+    #[test]
+    fn test_parse_msg_logs() {
+        let event = swap_event();
+        let logs = invocation(PROGRAM_ID, msg_logs(&event));
+        assert_eq!(parse_logs(PROGRAM_ID, &logs), vec![event.clone()]);
+
+        // logs of program versions without the timestamp
+        let event = LoggedEvent {
+            timestamp: None,
+            ..event
+        };
+        let logs = invocation(PROGRAM_ID, msg_logs(&event));
+        assert_eq!(parse_logs(PROGRAM_ID, &logs), vec![event]);
+    }
+
+    #[test]
+    fn test_parse_data_logs() {
+        let event = swap_event();
+        let mut logs = msg_logs(&event);
+        logs.push(data_log(&event));
+        let logs = invocation(PROGRAM_ID, logs);
+        assert_eq!(parse_logs(PROGRAM_ID, &logs), vec![event.clone()]);
+
+        let logs = invocation(PROGRAM_ID, vec![data_log(&event)]);
+        assert_eq!(parse_logs(PROGRAM_ID, &logs), vec![event.clone()]);
+
+        // data of other instructions
+        let logs = invocation(
+            PROGRAM_ID,
+            vec![format!("Program data: {}", base64::encode([1, 2, 3]))],
+        );
+        assert_eq!(parse_logs(PROGRAM_ID, &logs), vec![]);
+    }
+
+    #[test]
+    fn test_parse_nested_logs() {
+        let withdraw_a = LoggedEvent {
+            event: Event::WithdrawA,
+            token_a_amount: 500,
+            token_b_amount: 0,
+            pool_token_amount: 0,
+            fee: 2,
+            timestamp: Some(1_650_000_000),
+        };
+        let withdraw_b = LoggedEvent {
+            event: Event::WithdrawB,
+            token_a_amount: 0,
+            token_b_amount: 400,
+            pool_token_amount: 0,
+            fee: 1,
+            timestamp: Some(1_650_000_000),
+        };
+        let mut swap_logs = vec![format!("Program {} invoke [2]", TOKEN_PROGRAM_ID)];
+        // a program the swap invokes logging alike
+        swap_logs.extend(msg_logs(&withdraw_b));
+        swap_logs.push(format!("Program {} success", TOKEN_PROGRAM_ID));
+        swap_logs.extend(msg_logs(&withdraw_a));
+        swap_logs.push(data_log(&withdraw_a));
+        swap_logs.extend(msg_logs(&withdraw_b));
+        swap_logs.push(data_log(&withdraw_b));
+
+        // invoked by an aggregator
+        let mut logs = vec![format!("Program {} invoke [1]", OTHER_PROGRAM_ID)];
+        logs.extend(msg_logs(&swap_event()));
+        logs.push(format!("Program {} invoke [2]", PROGRAM_ID));
+        logs.extend(swap_logs);
+        logs.push(format!("Program {} success", PROGRAM_ID));
+        logs.push(format!("Program {} success", OTHER_PROGRAM_ID));
+        assert_eq!(
+            parse_logs(PROGRAM_ID, &logs),
+            vec![withdraw_a.clone(), withdraw_b.clone()]
+        );
+        assert_eq!(parse_logs(OTHER_PROGRAM_ID, &logs), vec![swap_event()]);
+    }
+
+    #[test]
+    fn test_parse_error_context() {
+        let context = ErrorContext::Amounts {
+            expected: 10_000,
+            actual: 9_988,
+        };
+        let context_log = format!(
+            "Program data: {} {}",
+            base64::encode(ERROR_CONTEXT_DATA_TAG),
+            base64::encode(context.try_to_vec().unwrap())
+        );
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            "Program log: 0x0, 0x0, 0x0, 0x2710, 0x2704".to_string(),
+            context_log.clone(),
+            format!("Program {} failed: custom program error: 0x11", PROGRAM_ID),
+        ];
+        assert_eq!(parse_error_context(PROGRAM_ID, &logs), Some(context));
+        assert_eq!(parse_error_context(OTHER_PROGRAM_ID, &logs), None);
+        assert_eq!(parse_logs(PROGRAM_ID, &logs), vec![]);
+
+        // the program succeeded after all
+        let logs = invocation(PROGRAM_ID, vec![context_log]);
+        assert_eq!(parse_error_context(PROGRAM_ID, &logs), None);
+    }
+}
@@ -1,17 +1,193 @@
 //! Program fees
 
-use crate::math;
-use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use crate::{bn::U192, error::SwapError, math};
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::fmt;
 
+#[cfg(not(target_arch = "wasm32"))]
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+#[cfg(not(target_arch = "wasm32"))]
 use solana_program::{
     program_error::ProgramError,
     program_pack::{Pack, Sealed},
 };
 
+/// Maximum number of trade fee tiers
+pub const MAX_FEE_TIERS: usize = 3;
+
+/// Packed length of the original fee layout, which has no trade fee tiers.
+/// Instructions carrying fees accept this layout, [FEES_V2_LEN], [FEES_V3_LEN],
+/// [FEES_V4_LEN], or the full [Fees::LEN] layout.
+pub const FEES_V1_LEN: usize = 64;
+
+/// Packed length of the fee layout with trade fee tiers but no dynamic fee.
+pub const FEES_V2_LEN: usize = 112;
+
+/// Packed length of the fee layout with a dynamic fee but no rebalancing rebate.
+pub const FEES_V3_LEN: usize = 129;
+
+/// Packed length of the fee layout with a rebalancing rebate but no host fee.
+pub const FEES_V4_LEN: usize = 137;
+
+/// Denominator of fees expressed in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Largest share of the trade and withdraw fees the admin may take, in basis points, so
+/// that a compromised admin key cannot take the whole fee. Builds with the
+/// `uncapped-admin-fee` feature allow up to 100%.
+#[cfg(not(feature = "uncapped-admin-fee"))]
+pub const MAX_ADMIN_FEE_SHARE_BPS: u64 = 5_000;
+
+/// Largest share of the trade and withdraw fees the admin may take, in basis points.
+/// Builds without the `uncapped-admin-fee` feature allow up to 50%.
+#[cfg(feature = "uncapped-admin-fee")]
+pub const MAX_ADMIN_FEE_SHARE_BPS: u64 = BPS_DENOMINATOR;
+
+/// Dynamic fee parameters for the flat fee mode
+pub const NO_DYNAMIC_FEE: DynamicFee = DynamicFee {
+    mode: FeeMode::Flat,
+    fee_gamma: 0,
+    out_fee_numerator: 0,
+};
+
+/// Precision of [DynamicFee::fee_gamma] and of the pool balance ratio used by the dynamic fee.
+pub const FEE_GAMMA_PRECISION: u64 = 1_000_000_000_000_000_000;
+
+/// Trade fee tier schedule with every tier disabled
+pub const NO_FEE_TIERS: [FeeTier; MAX_FEE_TIERS] = [FeeTier {
+    threshold: 0,
+    trade_fee_numerator: 0,
+}; MAX_FEE_TIERS];
+
+/// A trade fee tier. Swaps whose output amount (before fees) is at least
+/// `threshold` pay `trade_fee_numerator / trade_fee_denominator` instead of
+/// the base trade fee. A tier with a zero threshold is disabled.
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeTier {
+    /// Minimum trade size the tier applies to
+    pub threshold: u64,
+    /// Trade fee numerator for the tier
+    pub trade_fee_numerator: u64,
+}
+
+impl FeeTier {
+    /// Whether the tier is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.threshold != 0
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Sealed for FeeTier {}
+#[cfg(not(target_arch = "wasm32"))]
+impl Pack for FeeTier {
+    const LEN: usize = 16;
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 16];
+        let (threshold, trade_fee_numerator) = array_refs![input, 8, 8];
+        Ok(Self {
+            threshold: u64::from_le_bytes(*threshold),
+            trade_fee_numerator: u64::from_le_bytes(*trade_fee_numerator),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 16];
+        let (threshold, trade_fee_numerator) = mut_array_refs![output, 8, 8];
+        *threshold = self.threshold.to_le_bytes();
+        *trade_fee_numerator = self.trade_fee_numerator.to_le_bytes();
+    }
+}
+
+/// How the trade fee is computed
+#[repr(u8)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FeeMode {
+    /// The trade fee only depends on the trade size
+    Flat = 0,
+    /// The trade fee rises as the trade leaves the pool more imbalanced
+    Dynamic = 1,
+}
+
+impl FeeMode {
+    /// Converts a packed fee mode
+    pub fn from_u8(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(FeeMode::Flat),
+            1 => Some(FeeMode::Dynamic),
+            _ => None,
+        }
+    }
+}
+
+/// Dynamic trade fee parameters. In [FeeMode::Dynamic] the trade fee
+/// numerator moves from the flat (tiered) numerator when the pool is balanced
+/// towards `out_fee_numerator` as the pool becomes imbalanced after the trade:
+///
+/// `f = fee_gamma / (fee_gamma + 1 - K)`, where `K = 4xy / (x + y)^2`
+///
+/// `fee = flat_fee * f + out_fee * (1 - f)`
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynamicFee {
+    /// Fee mode
+    pub mode: FeeMode,
+    /// How fast the fee rises with imbalance, scaled by [FEE_GAMMA_PRECISION].
+    /// Smaller values make the fee rise sooner.
+    pub fee_gamma: u64,
+    /// Trade fee numerator charged on a fully imbalanced pool
+    pub out_fee_numerator: u64,
+}
+
+impl Default for DynamicFee {
+    fn default() -> Self {
+        NO_DYNAMIC_FEE
+    }
+}
+
+impl DynamicFee {
+    /// Whether the dynamic fee is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.mode == FeeMode::Dynamic
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Sealed for DynamicFee {}
+#[cfg(not(target_arch = "wasm32"))]
+impl Pack for DynamicFee {
+    const LEN: usize = 17;
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 17];
+        let (mode, fee_gamma, out_fee_numerator) = array_refs![input, 1, 8, 8];
+        Ok(Self {
+            mode: FeeMode::from_u8(mode[0]).ok_or(ProgramError::InvalidAccountData)?,
+            fee_gamma: u64::from_le_bytes(*fee_gamma),
+            out_fee_numerator: u64::from_le_bytes(*out_fee_numerator),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 17];
+        let (mode, fee_gamma, out_fee_numerator) = mut_array_refs![output, 1, 8, 8];
+        mode[0] = self.mode as u8;
+        *fee_gamma = self.fee_gamma.to_le_bytes();
+        *out_fee_numerator = self.out_fee_numerator.to_le_bytes();
+    }
+}
+
 /// Fees struct
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fees {
     /// Admin trade fee numerator
     pub admin_trade_fee_numerator: u64,
@@ -29,69 +205,369 @@ pub struct Fees {
     pub withdraw_fee_numerator: u64,
     /// Withdraw fee denominator
     pub withdraw_fee_denominator: u64,
+    /// Trade fee tiers, sorted by ascending threshold. Disabled tiers come last.
+    pub trade_fee_tiers: [FeeTier; MAX_FEE_TIERS],
+    /// Dynamic trade fee parameters
+    pub dynamic_fee: DynamicFee,
+    /// Share of the trade fee, in basis points, rebated to trades that leave
+    /// the pool more balanced. The rebate is paid out of the admin trade fee.
+    pub rebalancing_rebate_bps: u64,
+    /// Share of the admin trade fee, in basis points, paid to the host fee account
+    /// passed to a swap, e.g. the aggregator that routed it
+    pub host_fee_bps: u64,
 }
 
 impl Fees {
-    /// Apply admin trade fee
+    /// Creates flat fees from basis points: the trade and withdraw fees, and the
+    /// share of both fees going to the admin. Trade fee tiers, the dynamic fee,
+    /// the rebalancing rebate and the host fee are disabled.
+    pub fn from_bps(
+        trade_fee_bps: u64,
+        withdraw_fee_bps: u64,
+        admin_share_bps: u64,
+    ) -> Result<Self, SwapError> {
+        if trade_fee_bps > BPS_DENOMINATOR
+            || withdraw_fee_bps > BPS_DENOMINATOR
+            || admin_share_bps > BPS_DENOMINATOR
+        {
+            return Err(SwapError::InvalidInput);
+        }
+        Ok(Self {
+            admin_trade_fee_numerator: admin_share_bps,
+            admin_trade_fee_denominator: BPS_DENOMINATOR,
+            admin_withdraw_fee_numerator: admin_share_bps,
+            admin_withdraw_fee_denominator: BPS_DENOMINATOR,
+            trade_fee_numerator: trade_fee_bps,
+            trade_fee_denominator: BPS_DENOMINATOR,
+            withdraw_fee_numerator: withdraw_fee_bps,
+            withdraw_fee_denominator: BPS_DENOMINATOR,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
+        })
+    }
+
+    /// Checks that every fee has a non-zero denominator and is at most 100%,
+    /// including the host share of the admin trade fee, and that the admin shares
+    /// of the trade and withdraw fees are at most [MAX_ADMIN_FEE_SHARE_BPS]
+    pub fn validate(&self) -> Result<(), SwapError> {
+        for (numerator, denominator) in [
+            (self.trade_fee_numerator, self.trade_fee_denominator),
+            (self.withdraw_fee_numerator, self.withdraw_fee_denominator),
+            (
+                self.admin_trade_fee_numerator,
+                self.admin_trade_fee_denominator,
+            ),
+            (
+                self.admin_withdraw_fee_numerator,
+                self.admin_withdraw_fee_denominator,
+            ),
+        ] {
+            if denominator == 0 || numerator > denominator {
+                return Err(SwapError::InvalidFees);
+            }
+        }
+        for (numerator, denominator) in [
+            (
+                self.admin_trade_fee_numerator,
+                self.admin_trade_fee_denominator,
+            ),
+            (
+                self.admin_withdraw_fee_numerator,
+                self.admin_withdraw_fee_denominator,
+            ),
+        ] {
+            if u128::from(numerator) * u128::from(BPS_DENOMINATOR)
+                > u128::from(denominator) * u128::from(MAX_ADMIN_FEE_SHARE_BPS)
+            {
+                return Err(SwapError::InvalidFees);
+            }
+        }
+        if self.host_fee_bps > BPS_DENOMINATOR {
+            return Err(SwapError::InvalidFees);
+        }
+        Ok(())
+    }
+
+    /// Apply admin trade fee. Rounds down, the admin fee leaves the pool.
     pub fn admin_trade_fee(&self, fee_amount: u64) -> Option<u64> {
-        math::mul_div_imbalanced(
+        math::mul_div_floor(
             fee_amount,
             self.admin_trade_fee_numerator,
             self.admin_trade_fee_denominator,
         )
     }
 
-    /// Apply admin withdraw fee
+    /// Apply admin withdraw fee. Rounds down, the admin fee leaves the pool.
     pub fn admin_withdraw_fee(&self, fee_amount: u64) -> Option<u64> {
-        math::mul_div_imbalanced(
+        math::mul_div_floor(
             fee_amount,
             self.admin_withdraw_fee_numerator,
             self.admin_withdraw_fee_denominator,
         )
     }
 
-    /// Compute trade fee from amount
+    /// Compute trade fee from amount, applying the highest tier the amount reaches.
+    /// Rounds up, the fee stays in the pool.
     pub fn trade_fee(&self, trade_amount: u64) -> Option<u64> {
-        math::mul_div_imbalanced(
+        math::mul_div_ceil(
             trade_amount,
-            self.trade_fee_numerator,
+            self.tiered_trade_fee_numerator(trade_amount),
             self.trade_fee_denominator,
         )
     }
 
-    /// Compute withdraw fee from amount
+    /// Compute trade fee from amount, given the reserves the trade leaves in the pool.
+    /// Same as [Fees::trade_fee] unless the dynamic fee is enabled. Rounds up.
+    pub fn dynamic_trade_fee(
+        &self,
+        trade_amount: u64,
+        new_reserve_x: u64,
+        new_reserve_y: u64,
+    ) -> Option<u64> {
+        math::mul_div_ceil(
+            trade_amount,
+            self.dynamic_trade_fee_numerator(trade_amount, new_reserve_x, new_reserve_y)?,
+            self.trade_fee_denominator,
+        )
+    }
+
+    /// Trade fee numerator applicable to a trade of the given size that
+    /// leaves the given reserves in the pool
+    pub fn dynamic_trade_fee_numerator(
+        &self,
+        trade_amount: u64,
+        new_reserve_x: u64,
+        new_reserve_y: u64,
+    ) -> Option<u64> {
+        let flat_fee_numerator = self.tiered_trade_fee_numerator(trade_amount);
+        if !self.dynamic_fee.is_enabled() {
+            return Some(flat_fee_numerator);
+        }
+        let out_fee_numerator = self.dynamic_fee.out_fee_numerator.max(flat_fee_numerator);
+
+        let precision = U192::from(FEE_GAMMA_PRECISION);
+        let sum = U192::from(new_reserve_x).checked_add(new_reserve_y.into())?;
+        // Balance ratio K, 1 when the pool is balanced and 0 when it is fully imbalanced
+        let k = if sum.is_zero() {
+            precision
+        } else {
+            U192::from(new_reserve_x)
+                .checked_mul(new_reserve_y.into())?
+                .checked_mul(4.into())?
+                .checked_mul(precision)?
+                .checked_div(sum.checked_mul(sum)?)?
+        };
+        let fee_gamma = U192::from(self.dynamic_fee.fee_gamma);
+        let f = fee_gamma
+            .checked_mul(precision)?
+            .checked_div(fee_gamma.checked_add(precision)?.checked_sub(k)?)?;
+        U192::from(flat_fee_numerator)
+            .checked_mul(f)?
+            .checked_add(U192::from(out_fee_numerator).checked_mul(precision.checked_sub(f)?)?)?
+            .checked_div(precision)?
+            .to_u64()
+    }
+
+    /// Checks that the dynamic fee never charges less than the flat fee nor more than 100%
+    pub fn validate_dynamic_fee(&self) -> bool {
+        !self.dynamic_fee.is_enabled()
+            || (self.dynamic_fee.fee_gamma != 0
+                && self.dynamic_fee.out_fee_numerator >= self.trade_fee_numerator
+                && self.dynamic_fee.out_fee_numerator <= self.trade_fee_denominator)
+    }
+
+    /// Compute the rebate of a rebalancing trade, given its trade fee and
+    /// admin trade fee. The rebate never exceeds the admin trade fee. Rounds down,
+    /// the rebate is paid to the trader.
+    pub fn rebalancing_rebate(&self, fee_amount: u64, admin_fee_amount: u64) -> Option<u64> {
+        let rebate = math::mul_div_floor(fee_amount, self.rebalancing_rebate_bps, BPS_DENOMINATOR)?;
+        Some(rebate.min(admin_fee_amount))
+    }
+
+    /// Compute the share of an admin trade fee paid to the host fee account.
+    /// Rounds to the nearest unit, the split does not change what the pool keeps.
+    pub fn host_fee(&self, admin_fee_amount: u64) -> Option<u64> {
+        math::mul_div_round(admin_fee_amount, self.host_fee_bps, BPS_DENOMINATOR)
+    }
+
+    /// Checks that the rebalancing rebate is at most 100% of the trade fee
+    pub fn validate_rebalancing_rebate(&self) -> bool {
+        self.rebalancing_rebate_bps <= BPS_DENOMINATOR
+    }
+
+    /// Trade fee numerator applicable to a trade of the given size
+    pub fn tiered_trade_fee_numerator(&self, trade_amount: u64) -> u64 {
+        self.trade_fee_tiers
+            .iter()
+            .take_while(|tier| tier.is_enabled())
+            .filter(|tier| trade_amount >= tier.threshold)
+            .last()
+            .map_or(self.trade_fee_numerator, |tier| tier.trade_fee_numerator)
+    }
+
+    /// Checks that the tiers are enabled in ascending order of threshold, that
+    /// larger trades never pay less than smaller ones, and that no tier charges
+    /// more than 100%.
+    pub fn validate_trade_fee_tiers(&self) -> bool {
+        let mut prev_threshold = 0;
+        let mut prev_numerator = self.trade_fee_numerator;
+        let mut disabled_seen = false;
+        for tier in self.trade_fee_tiers.iter() {
+            if !tier.is_enabled() {
+                disabled_seen = true;
+                continue;
+            }
+            if disabled_seen
+                || tier.threshold <= prev_threshold
+                || tier.trade_fee_numerator < prev_numerator
+                || tier.trade_fee_numerator > self.trade_fee_denominator
+            {
+                return false;
+            }
+            prev_threshold = tier.threshold;
+            prev_numerator = tier.trade_fee_numerator;
+        }
+        true
+    }
+
+    /// Unpacks fees from instruction data in either a legacy layout
+    /// ([FEES_V1_LEN], [FEES_V2_LEN], [FEES_V3_LEN] or [FEES_V4_LEN]) or the full
+    /// [Fees::LEN] layout. Fields missing from legacy layouts are left disabled.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn unpack_versioned(input: &[u8]) -> Result<Self, ProgramError> {
+        if [FEES_V1_LEN, FEES_V2_LEN, FEES_V3_LEN, FEES_V4_LEN].contains(&input.len()) {
+            let mut buf = [0u8; Fees::LEN];
+            buf[..input.len()].copy_from_slice(input);
+            Self::unpack_from_slice(&buf)
+        } else {
+            Self::unpack_unchecked(input)
+        }
+    }
+
+    /// Compute withdraw fee from amount. Rounds up, the fee stays in the pool.
     pub fn withdraw_fee(&self, withdraw_amount: u64) -> Option<u64> {
-        math::mul_div_imbalanced(
+        math::mul_div_ceil(
             withdraw_amount,
             self.withdraw_fee_numerator,
             self.withdraw_fee_denominator,
         )
     }
 
-    /// Compute normalized fee for symmetric/asymmetric deposits/withdraws
+    /// Compute normalized fee for symmetric/asymmetric deposits/withdraws.
+    /// Rounds up, the fee stays in the pool.
     pub fn normalized_trade_fee(&self, n_coins: u8, amount: u64) -> Option<u64> {
         // adjusted_fee_numerator: uint256 = self.fee * N_COINS / (4 * (N_COINS - 1))
         // The number 4 comes from Curve, originating from some sort of calculus
         // https://github.com/curvefi/curve-contract/blob/e5fb8c0e0bcd2fe2e03634135806c0f36b245511/tests/simulation.py#L124
-        let adjusted_trade_fee_numerator = math::mul_div(
+        let adjusted_trade_fee_numerator = math::mul_div_ceil(
             self.trade_fee_numerator,
             n_coins.into(),
             (n_coins.checked_sub(1)?).checked_mul(4)?.into(),
         )?;
 
-        math::mul_div(
+        math::mul_div_ceil(
             amount,
             adjusted_trade_fee_numerator,
             self.trade_fee_denominator,
         )
     }
+
+    /// Doubles the trade fee, its tiers included, and the withdraw fee, each capped at
+    /// 100%. The admin shares are left as they are.
+    pub fn doubled(&self) -> Self {
+        let double =
+            |numerator: u64, denominator: u64| numerator.saturating_mul(2).min(denominator);
+        let mut trade_fee_tiers = self.trade_fee_tiers;
+        for tier in trade_fee_tiers.iter_mut() {
+            tier.trade_fee_numerator = double(tier.trade_fee_numerator, self.trade_fee_denominator);
+        }
+        Self {
+            trade_fee_numerator: double(self.trade_fee_numerator, self.trade_fee_denominator),
+            withdraw_fee_numerator: double(
+                self.withdraw_fee_numerator,
+                self.withdraw_fee_denominator,
+            ),
+            trade_fee_tiers,
+            ..*self
+        }
+    }
+}
+
+/// Writes a fee ratio in basis points, with two decimals
+fn fmt_bps(f: &mut fmt::Formatter, numerator: u64, denominator: u64) -> fmt::Result {
+    if denominator == 0 {
+        return write!(f, "{}/0", numerator);
+    }
+    let hundredths_of_bps =
+        u128::from(numerator) * u128::from(BPS_DENOMINATOR) * 100 / u128::from(denominator);
+    write!(
+        f,
+        "{}.{:02} bps",
+        hundredths_of_bps / 100,
+        hundredths_of_bps % 100
+    )
+}
+
+impl fmt::Display for Fees {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "trade fee ")?;
+        fmt_bps(f, self.trade_fee_numerator, self.trade_fee_denominator)?;
+        for tier in self
+            .trade_fee_tiers
+            .iter()
+            .take_while(|tier| tier.is_enabled())
+        {
+            write!(f, ", ")?;
+            fmt_bps(f, tier.trade_fee_numerator, self.trade_fee_denominator)?;
+            write!(f, " from {}", tier.threshold)?;
+        }
+        write!(f, ", withdraw fee ")?;
+        fmt_bps(
+            f,
+            self.withdraw_fee_numerator,
+            self.withdraw_fee_denominator,
+        )?;
+        write!(f, ", admin share of trade fees ")?;
+        fmt_bps(
+            f,
+            self.admin_trade_fee_numerator,
+            self.admin_trade_fee_denominator,
+        )?;
+        write!(f, ", admin share of withdraw fees ")?;
+        fmt_bps(
+            f,
+            self.admin_withdraw_fee_numerator,
+            self.admin_withdraw_fee_denominator,
+        )?;
+        if self.dynamic_fee.is_enabled() {
+            write!(f, ", dynamic trade fee up to ")?;
+            fmt_bps(
+                f,
+                self.dynamic_fee.out_fee_numerator,
+                self.trade_fee_denominator,
+            )?;
+        }
+        if self.rebalancing_rebate_bps != 0 {
+            write!(f, ", rebalancing rebate ")?;
+            fmt_bps(f, self.rebalancing_rebate_bps, BPS_DENOMINATOR)?;
+        }
+        if self.host_fee_bps != 0 {
+            write!(f, ", host share of admin trade fees ")?;
+            fmt_bps(f, self.host_fee_bps, BPS_DENOMINATOR)?;
+        }
+        Ok(())
+    }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Sealed for Fees {}
+#[cfg(not(target_arch = "wasm32"))]
 impl Pack for Fees {
-    const LEN: usize = 64;
+    const LEN: usize = 145;
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
-        let input = array_ref![input, 0, 64];
+        let input = array_ref![input, 0, 145];
         #[allow(clippy::ptr_offset_with_cast)]
         let (
             admin_trade_fee_numerator,
@@ -102,7 +578,15 @@ impl Pack for Fees {
             trade_fee_denominator,
             withdraw_fee_numerator,
             withdraw_fee_denominator,
-        ) = array_refs![input, 8, 8, 8, 8, 8, 8, 8, 8];
+            trade_fee_tiers,
+            dynamic_fee,
+            rebalancing_rebate_bps,
+            host_fee_bps,
+        ) = array_refs![input, 8, 8, 8, 8, 8, 8, 8, 8, 48, 17, 8, 8];
+        let mut tiers = NO_FEE_TIERS;
+        for (tier, packed) in tiers.iter_mut().zip(trade_fee_tiers.chunks(FeeTier::LEN)) {
+            *tier = FeeTier::unpack_from_slice(packed)?;
+        }
         Ok(Self {
             admin_trade_fee_numerator: u64::from_le_bytes(*admin_trade_fee_numerator),
             admin_trade_fee_denominator: u64::from_le_bytes(*admin_trade_fee_denominator),
@@ -112,11 +596,15 @@ impl Pack for Fees {
             trade_fee_denominator: u64::from_le_bytes(*trade_fee_denominator),
             withdraw_fee_numerator: u64::from_le_bytes(*withdraw_fee_numerator),
             withdraw_fee_denominator: u64::from_le_bytes(*withdraw_fee_denominator),
+            trade_fee_tiers: tiers,
+            dynamic_fee: DynamicFee::unpack_from_slice(dynamic_fee)?,
+            rebalancing_rebate_bps: u64::from_le_bytes(*rebalancing_rebate_bps),
+            host_fee_bps: u64::from_le_bytes(*host_fee_bps),
         })
     }
 
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, 64];
+        let output = array_mut_ref![output, 0, 145];
         let (
             admin_trade_fee_numerator,
             admin_trade_fee_denominator,
@@ -126,7 +614,11 @@ impl Pack for Fees {
             trade_fee_denominator,
             withdraw_fee_numerator,
             withdraw_fee_denominator,
-        ) = mut_array_refs![output, 8, 8, 8, 8, 8, 8, 8, 8];
+            trade_fee_tiers,
+            dynamic_fee,
+            rebalancing_rebate_bps,
+            host_fee_bps,
+        ) = mut_array_refs![output, 8, 8, 8, 8, 8, 8, 8, 8, 48, 17, 8, 8];
         *admin_trade_fee_numerator = self.admin_trade_fee_numerator.to_le_bytes();
         *admin_trade_fee_denominator = self.admin_trade_fee_denominator.to_le_bytes();
         *admin_withdraw_fee_numerator = self.admin_withdraw_fee_numerator.to_le_bytes();
@@ -135,6 +627,16 @@ impl Pack for Fees {
         *trade_fee_denominator = self.trade_fee_denominator.to_le_bytes();
         *withdraw_fee_numerator = self.withdraw_fee_numerator.to_le_bytes();
         *withdraw_fee_denominator = self.withdraw_fee_denominator.to_le_bytes();
+        for (tier, packed) in self
+            .trade_fee_tiers
+            .iter()
+            .zip(trade_fee_tiers.chunks_mut(FeeTier::LEN))
+        {
+            tier.pack_into_slice(packed);
+        }
+        self.dynamic_fee.pack_into_slice(dynamic_fee);
+        *rebalancing_rebate_bps = self.rebalancing_rebate_bps.to_le_bytes();
+        *host_fee_bps = self.host_fee_bps.to_le_bytes();
     }
 }
 
@@ -153,6 +655,24 @@ mod tests {
         let trade_fee_denominator = 6;
         let withdraw_fee_numerator = 7;
         let withdraw_fee_denominator = 8;
+        let trade_fee_tiers = [
+            FeeTier {
+                threshold: 9,
+                trade_fee_numerator: 10,
+            },
+            FeeTier {
+                threshold: 11,
+                trade_fee_numerator: 12,
+            },
+            FeeTier::default(),
+        ];
+        let dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: 13,
+            out_fee_numerator: 14,
+        };
+        let rebalancing_rebate_bps = 15;
+        let host_fee_bps = 16;
         let fees = Fees {
             admin_trade_fee_numerator,
             admin_trade_fee_denominator,
@@ -162,12 +682,19 @@ mod tests {
             trade_fee_denominator,
             withdraw_fee_numerator,
             withdraw_fee_denominator,
+            trade_fee_tiers,
+            dynamic_fee,
+            rebalancing_rebate_bps,
+            host_fee_bps,
         };
 
         let mut packed = [0u8; Fees::LEN];
         Pack::pack_into_slice(&fees, &mut packed[..]);
         let unpacked = Fees::unpack_from_slice(&packed).unwrap();
         assert_eq!(fees, unpacked);
+        // the borsh encoding is the packed layout
+        assert_eq!(fees.try_to_vec().unwrap(), packed);
+        assert_eq!(Fees::try_from_slice(&packed).unwrap(), fees);
 
         let mut packed = vec![];
         packed.extend_from_slice(&admin_trade_fee_numerator.to_le_bytes());
@@ -178,8 +705,57 @@ mod tests {
         packed.extend_from_slice(&trade_fee_denominator.to_le_bytes());
         packed.extend_from_slice(&withdraw_fee_numerator.to_le_bytes());
         packed.extend_from_slice(&withdraw_fee_denominator.to_le_bytes());
+        let legacy = Fees::unpack_versioned(&packed).unwrap();
+        assert_eq!(
+            legacy,
+            Fees {
+                trade_fee_tiers: NO_FEE_TIERS,
+                dynamic_fee: NO_DYNAMIC_FEE,
+                rebalancing_rebate_bps: 0,
+                host_fee_bps: 0,
+                ..fees
+            }
+        );
+        for tier in trade_fee_tiers.iter() {
+            packed.extend_from_slice(&tier.threshold.to_le_bytes());
+            packed.extend_from_slice(&tier.trade_fee_numerator.to_le_bytes());
+        }
+        let legacy = Fees::unpack_versioned(&packed).unwrap();
+        assert_eq!(
+            legacy,
+            Fees {
+                dynamic_fee: NO_DYNAMIC_FEE,
+                rebalancing_rebate_bps: 0,
+                host_fee_bps: 0,
+                ..fees
+            }
+        );
+        packed.push(FeeMode::Dynamic as u8);
+        packed.extend_from_slice(&dynamic_fee.fee_gamma.to_le_bytes());
+        packed.extend_from_slice(&dynamic_fee.out_fee_numerator.to_le_bytes());
+        let legacy = Fees::unpack_versioned(&packed).unwrap();
+        assert_eq!(
+            legacy,
+            Fees {
+                rebalancing_rebate_bps: 0,
+                host_fee_bps: 0,
+                ..fees
+            }
+        );
+        packed.extend_from_slice(&rebalancing_rebate_bps.to_le_bytes());
+        let legacy = Fees::unpack_versioned(&packed).unwrap();
+        assert_eq!(
+            legacy,
+            Fees {
+                host_fee_bps: 0,
+                ..fees
+            }
+        );
+        packed.extend_from_slice(&host_fee_bps.to_le_bytes());
         let unpacked = Fees::unpack_from_slice(&packed).unwrap();
         assert_eq!(fees, unpacked);
+        let unpacked = Fees::unpack_versioned(&packed).unwrap();
+        assert_eq!(fees, unpacked);
     }
 
     #[test]
@@ -201,10 +777,16 @@ mod tests {
             trade_fee_denominator,
             withdraw_fee_numerator,
             withdraw_fee_denominator,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
         };
 
         let trade_amount = 1_000_000_000;
-        let expected_trade_fee = trade_amount * trade_fee_numerator / trade_fee_denominator;
+        // fees round up
+        let expected_trade_fee =
+            (trade_amount * trade_fee_numerator).div_ceil(trade_fee_denominator);
         let trade_fee = fees.trade_fee(trade_amount.into()).unwrap();
         assert_eq!(trade_fee, expected_trade_fee);
         let expected_admin_trade_fee =
@@ -229,13 +811,382 @@ mod tests {
 
         let n_coins: u8 = 2;
         let adjusted_trade_fee_numerator: u64 =
-            trade_fee_numerator * (n_coins as u64) / (4 * ((n_coins as u64) - 1));
+            (trade_fee_numerator * (n_coins as u64)).div_ceil(4 * ((n_coins as u64) - 1));
         let expected_normalized_fee =
-            trade_amount * adjusted_trade_fee_numerator / trade_fee_denominator;
+            (trade_amount * adjusted_trade_fee_numerator).div_ceil(trade_fee_denominator);
         assert_eq!(
             fees.normalized_trade_fee(n_coins, trade_amount.into())
                 .unwrap(),
             expected_normalized_fee
         );
     }
+    #[test]
+    fn trade_fee_tiers() {
+        let mut fees = Fees {
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            trade_fee_tiers: [
+                FeeTier {
+                    threshold: 1_000,
+                    trade_fee_numerator: 10,
+                },
+                FeeTier {
+                    threshold: 100_000,
+                    trade_fee_numerator: 30,
+                },
+                FeeTier::default(),
+            ],
+            ..Fees::default()
+        };
+        assert!(fees.validate_trade_fee_tiers());
+
+        assert_eq!(fees.tiered_trade_fee_numerator(999), 4);
+        assert_eq!(fees.tiered_trade_fee_numerator(1_000), 10);
+        assert_eq!(fees.tiered_trade_fee_numerator(99_999), 10);
+        assert_eq!(fees.tiered_trade_fee_numerator(100_000), 30);
+        assert_eq!(fees.tiered_trade_fee_numerator(u64::MAX), 30);
+        assert_eq!(fees.trade_fee(500_000).unwrap(), 1_500);
+
+        // tiers after a disabled tier are ignored and rejected
+        fees.trade_fee_tiers[1] = FeeTier::default();
+        fees.trade_fee_tiers[2] = FeeTier {
+            threshold: 100_000,
+            trade_fee_numerator: 30,
+        };
+        assert_eq!(fees.tiered_trade_fee_numerator(500_000), 10);
+        assert!(!fees.validate_trade_fee_tiers());
+
+        // thresholds must increase
+        fees.trade_fee_tiers[1] = FeeTier {
+            threshold: 1_000,
+            trade_fee_numerator: 30,
+        };
+        fees.trade_fee_tiers[2] = FeeTier::default();
+        assert!(!fees.validate_trade_fee_tiers());
+
+        // larger trades cannot pay a lower fee
+        fees.trade_fee_tiers[1] = FeeTier {
+            threshold: 100_000,
+            trade_fee_numerator: 5,
+        };
+        assert!(!fees.validate_trade_fee_tiers());
+        fees.trade_fee_tiers[0].trade_fee_numerator = 3;
+        fees.trade_fee_tiers[1] = FeeTier::default();
+        assert!(!fees.validate_trade_fee_tiers());
+
+        // tier fee cannot exceed 100%
+        fees.trade_fee_tiers[0].trade_fee_numerator = 10_001;
+        assert!(!fees.validate_trade_fee_tiers());
+    }
+
+    #[test]
+    fn dynamic_trade_fee() {
+        let mut fees = Fees {
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            ..Fees::default()
+        };
+        assert!(fees.validate_dynamic_fee());
+
+        // flat mode ignores the reserves
+        assert_eq!(
+            fees.dynamic_trade_fee_numerator(1_000, 1, 1_000_000),
+            Some(4)
+        );
+        assert_eq!(fees.dynamic_trade_fee(1_000_000, 1, 1_000_000), Some(400));
+
+        fees.dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: FEE_GAMMA_PRECISION / 100,
+            out_fee_numerator: 40,
+        };
+        assert!(fees.validate_dynamic_fee());
+
+        // balanced pool pays the flat fee
+        assert_eq!(
+            fees.dynamic_trade_fee_numerator(1_000, 1_000_000, 1_000_000),
+            Some(4)
+        );
+        assert_eq!(fees.dynamic_trade_fee_numerator(1_000, 0, 0), Some(4));
+        // fully imbalanced pool pays close to the out fee
+        assert_eq!(
+            fees.dynamic_trade_fee_numerator(1_000, 1_000_000, 0),
+            Some(39)
+        );
+        // fee rises with imbalance
+        let mut prev_numerator = 4;
+        for reserve_y in [900_000, 500_000, 100_000, 10_000].iter() {
+            let numerator = fees
+                .dynamic_trade_fee_numerator(1_000, 1_000_000, *reserve_y)
+                .unwrap();
+            assert!(numerator >= prev_numerator);
+            assert!(numerator <= 40);
+            prev_numerator = numerator;
+        }
+        assert!(prev_numerator > 4);
+        assert_eq!(
+            fees.dynamic_trade_fee(1_000_000, u64::MAX, u64::MAX),
+            Some(400)
+        );
+
+        // out fee cannot be lower than the flat fee or exceed 100%
+        fees.dynamic_fee.out_fee_numerator = 3;
+        assert!(!fees.validate_dynamic_fee());
+        fees.dynamic_fee.out_fee_numerator = 10_001;
+        assert!(!fees.validate_dynamic_fee());
+        fees.dynamic_fee.out_fee_numerator = 40;
+        fees.dynamic_fee.fee_gamma = 0;
+        assert!(!fees.validate_dynamic_fee());
+    }
+
+    #[test]
+    fn rebalancing_rebate() {
+        let mut fees = Fees {
+            admin_trade_fee_numerator: 1,
+            admin_trade_fee_denominator: 2,
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            ..Fees::default()
+        };
+        assert!(fees.validate_rebalancing_rebate());
+        assert_eq!(fees.rebalancing_rebate(1_000, 500), Some(0));
+
+        fees.rebalancing_rebate_bps = 2_500;
+        assert_eq!(fees.rebalancing_rebate(1_000, 500), Some(250));
+        // the rebate is capped by the admin fee
+        fees.rebalancing_rebate_bps = BPS_DENOMINATOR;
+        assert!(fees.validate_rebalancing_rebate());
+        assert_eq!(fees.rebalancing_rebate(1_000, 500), Some(500));
+
+        fees.rebalancing_rebate_bps = BPS_DENOMINATOR + 1;
+        assert!(!fees.validate_rebalancing_rebate());
+    }
+
+    #[test]
+    fn fees_round_in_favor_of_the_pool() {
+        let fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        // fees kept by the pool round up
+        assert_eq!(fees.trade_fee(1), Some(1));
+        assert_eq!(fees.trade_fee(2_501), Some(2));
+        assert_eq!(fees.dynamic_trade_fee(1, 1, 1), Some(1));
+        assert_eq!(fees.withdraw_fee(1), Some(1));
+        assert_eq!(fees.normalized_trade_fee(2, 1), Some(1));
+        // shares leaving the pool round down
+        assert_eq!(fees.admin_trade_fee(1), Some(0));
+        assert_eq!(fees.admin_withdraw_fee(3), Some(1));
+        let fees = Fees {
+            rebalancing_rebate_bps: 2_500,
+            ..fees
+        };
+        assert_eq!(fees.rebalancing_rebate(3, 3), Some(0));
+    }
+
+    #[test]
+    fn host_fee() {
+        let mut fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        assert_eq!(fees.host_fee(1_000), Some(0));
+
+        fees.host_fee_bps = 2_000;
+        assert_eq!(fees.validate(), Ok(()));
+        assert_eq!(fees.host_fee(1_000), Some(200));
+        // the split rounds to the nearest unit
+        assert_eq!(fees.host_fee(2), Some(0));
+        assert_eq!(fees.host_fee(3), Some(1));
+        fees.host_fee_bps = BPS_DENOMINATOR;
+        assert_eq!(fees.validate(), Ok(()));
+        assert_eq!(fees.host_fee(1_000), Some(1_000));
+
+        fees.host_fee_bps = BPS_DENOMINATOR + 1;
+        assert_eq!(fees.validate(), Err(SwapError::InvalidFees));
+    }
+
+    #[test]
+    fn fees_from_bps() {
+        let fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        assert_eq!(
+            fees,
+            Fees {
+                admin_trade_fee_numerator: 5_000,
+                admin_trade_fee_denominator: BPS_DENOMINATOR,
+                admin_withdraw_fee_numerator: 5_000,
+                admin_withdraw_fee_denominator: BPS_DENOMINATOR,
+                trade_fee_numerator: 4,
+                trade_fee_denominator: BPS_DENOMINATOR,
+                withdraw_fee_numerator: 10,
+                withdraw_fee_denominator: BPS_DENOMINATOR,
+                ..Fees::default()
+            }
+        );
+        assert_eq!(fees.trade_fee(10_000), Some(4));
+        assert_eq!(fees.admin_trade_fee(4), Some(2));
+
+        assert!(Fees::from_bps(BPS_DENOMINATOR, BPS_DENOMINATOR, BPS_DENOMINATOR).is_ok());
+        assert_eq!(
+            Fees::from_bps(BPS_DENOMINATOR + 1, 0, 0),
+            Err(SwapError::InvalidInput)
+        );
+        assert_eq!(
+            Fees::from_bps(0, BPS_DENOMINATOR + 1, 0),
+            Err(SwapError::InvalidInput)
+        );
+        assert_eq!(
+            Fees::from_bps(0, 0, BPS_DENOMINATOR + 1),
+            Err(SwapError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn doubled_fees() {
+        let fees = Fees {
+            trade_fee_tiers: [
+                FeeTier {
+                    threshold: 1_000,
+                    trade_fee_numerator: 5,
+                },
+                NO_FEE_TIERS[0],
+                NO_FEE_TIERS[0],
+            ],
+            ..Fees::from_bps(10, 6_000, 5_000).unwrap()
+        };
+        let doubled = fees.doubled();
+        assert_eq!(doubled.trade_fee_numerator, 20);
+        assert_eq!(doubled.trade_fee_tiers[0].trade_fee_numerator, 10);
+        assert_eq!(doubled.trade_fee_tiers[1], NO_FEE_TIERS[0]);
+        // capped at 100%
+        assert_eq!(doubled.withdraw_fee_numerator, BPS_DENOMINATOR);
+        assert_eq!(doubled.admin_trade_fee_numerator, 5_000);
+        assert_eq!(doubled.admin_withdraw_fee_numerator, 5_000);
+        assert_eq!(doubled.validate(), Ok(()));
+        assert_eq!(
+            Fees::from_bps(0, 0, 0).unwrap().doubled(),
+            Fees::from_bps(0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn fees_display() {
+        let mut fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        assert_eq!(
+            fees.to_string(),
+            "trade fee 4.00 bps, withdraw fee 10.00 bps, admin share of trade fees 5000.00 bps, \
+             admin share of withdraw fees 5000.00 bps"
+        );
+
+        fees.trade_fee_numerator = 1;
+        fees.trade_fee_denominator = 300_000;
+        fees.trade_fee_tiers[0] = FeeTier {
+            threshold: 1_000,
+            trade_fee_numerator: 0,
+        };
+        fees.dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: 1,
+            out_fee_numerator: 300,
+        };
+        fees.rebalancing_rebate_bps = 2_500;
+        fees.host_fee_bps = 2_000;
+        fees.withdraw_fee_denominator = 0;
+        assert_eq!(
+            fees.to_string(),
+            "trade fee 0.03 bps, 0.00 bps from 1000, withdraw fee 10/0, \
+             admin share of trade fees 5000.00 bps, admin share of withdraw fees 5000.00 bps, \
+             dynamic trade fee up to 10.00 bps, rebalancing rebate 2500.00 bps, \
+             host share of admin trade fees 2000.00 bps"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fees_serde() {
+        let mut fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        fees.dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: 1,
+            out_fee_numerator: 300,
+        };
+        let json = serde_json::to_string(&fees).unwrap();
+        assert_eq!(serde_json::from_str::<Fees>(&json).unwrap(), fees);
+    }
+
+    #[test]
+    fn validate_fees() {
+        let fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        assert_eq!(fees.validate(), Ok(()));
+        assert_eq!(Fees::default().validate(), Err(SwapError::InvalidFees));
+        assert_eq!(
+            Fees {
+                withdraw_fee_denominator: 0,
+                withdraw_fee_numerator: 0,
+                ..fees
+            }
+            .validate(),
+            Err(SwapError::InvalidFees)
+        );
+        assert_eq!(
+            Fees {
+                trade_fee_numerator: BPS_DENOMINATOR + 1,
+                ..fees
+            }
+            .validate(),
+            Err(SwapError::InvalidFees)
+        );
+        assert_eq!(
+            Fees {
+                admin_withdraw_fee_numerator: BPS_DENOMINATOR + 1,
+                ..fees
+            }
+            .validate(),
+            Err(SwapError::InvalidFees)
+        );
+        // 100% fees are allowed, with up to the maximum admin share
+        assert_eq!(
+            Fees::from_bps(BPS_DENOMINATOR, BPS_DENOMINATOR, MAX_ADMIN_FEE_SHARE_BPS)
+                .unwrap()
+                .validate(),
+            Ok(())
+        );
+    }
+
+    #[cfg(not(feature = "uncapped-admin-fee"))]
+    #[test]
+    fn validate_admin_fee_share() {
+        let fees = Fees::from_bps(4, 10, MAX_ADMIN_FEE_SHARE_BPS).unwrap();
+        assert_eq!(fees.validate(), Ok(()));
+        assert_eq!(
+            Fees::from_bps(4, 10, MAX_ADMIN_FEE_SHARE_BPS + 1)
+                .unwrap()
+                .validate(),
+            Err(SwapError::InvalidFees)
+        );
+        // the cap applies to each share, whatever its denominator
+        assert_eq!(
+            Fees {
+                admin_trade_fee_numerator: 1,
+                admin_trade_fee_denominator: 2,
+                admin_withdraw_fee_numerator: 501,
+                admin_withdraw_fee_denominator: 1_000,
+                ..fees
+            }
+            .validate(),
+            Err(SwapError::InvalidFees)
+        );
+        assert_eq!(
+            Fees {
+                admin_trade_fee_numerator: u64::MAX / 2 + 1,
+                admin_trade_fee_denominator: u64::MAX,
+                ..fees
+            }
+            .validate(),
+            Err(SwapError::InvalidFees)
+        );
+        assert_eq!(
+            Fees {
+                admin_trade_fee_numerator: u64::MAX / 2,
+                admin_trade_fee_denominator: u64::MAX,
+                ..fees
+            }
+            .validate(),
+            Ok(())
+        );
+    }
 }
@@ -0,0 +1,547 @@
+//! Golden-file tests of the packed layouts integrators depend on.
+//!
+//! The packed bytes of [SwapInfo], [Fees] and every instruction are compared against
+//! `fixtures/packed_layouts.txt`. A layout change that is intended must update the
+//! fixtures, by running the tests with `UPDATE_GOLDEN=1`.
+
+use crate::curve::CurveType;
+use crate::fees::{DynamicFee, FeeMode, FeeTier, Fees, MAX_FEE_TIERS};
+use crate::instruction::*;
+use crate::state::{
+    CpiPolicy, PegRateSource, PoolCategory, SwapInfo, SwapTokenInfo, SWAP_INFO_LEGACY_LEN,
+};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+use std::{env, fs, path::PathBuf};
+
+const FIXTURES_PATH: &str = "fixtures/packed_layouts.txt";
+
+fn fixtures_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(FIXTURES_PATH)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Names every admin instruction, so that adding one fails to build until it has a fixture
+fn admin_instruction_name(instruction: &AdminInstruction) -> &'static str {
+    match instruction {
+        AdminInstruction::RampA(_) => "RampA",
+        AdminInstruction::StopRampA => "StopRampA",
+        AdminInstruction::Pause => "Pause",
+        AdminInstruction::Unpause => "Unpause",
+        AdminInstruction::SetFeeAccount => "SetFeeAccount",
+        AdminInstruction::ApplyNewAdmin => "ApplyNewAdmin",
+        AdminInstruction::CommitNewAdmin => "CommitNewAdmin",
+        AdminInstruction::SetNewFees(_) => "SetNewFees",
+        AdminInstruction::SetFeeTiers(_) => "SetFeeTiers",
+        AdminInstruction::SetFeeMode(_) => "SetFeeMode",
+        AdminInstruction::CommitNewFees(_) => "CommitNewFees",
+        AdminInstruction::ApplyNewFees => "ApplyNewFees",
+        AdminInstruction::CancelPending => "CancelPending",
+        AdminInstruction::SetAdminMultisig(_) => "SetAdminMultisig",
+        AdminInstruction::SetFeeExemptions(_) => "SetFeeExemptions",
+        AdminInstruction::SetPoolMetadata(_) => "SetPoolMetadata",
+        AdminInstruction::CollectAdminFees(_) => "CollectAdminFees",
+        AdminInstruction::InitPoolStats => "InitPoolStats",
+        AdminInstruction::SetDonationsAdminOnly(_) => "SetDonationsAdminOnly",
+        AdminInstruction::SetSplTokenSwapCompat(_) => "SetSplTokenSwapCompat",
+        AdminInstruction::SetPriceOracles(_) => "SetPriceOracles",
+        AdminInstruction::RampFees(_) => "RampFees",
+        AdminInstruction::SetDepositAllowlist(_) => "SetDepositAllowlist",
+        AdminInstruction::SetDepositAllowlistEnabled(_) => "SetDepositAllowlistEnabled",
+        AdminInstruction::MigratePool => "MigratePool",
+        AdminInstruction::SetMaxSwapBps(_) => "SetMaxSwapBps",
+        AdminInstruction::SetPoolInfo(_) => "SetPoolInfo",
+        AdminInstruction::SetRewardsDistributor => "SetRewardsDistributor",
+        AdminInstruction::FreezeLp => "FreezeLp",
+        AdminInstruction::ThawLp => "ThawLp",
+        AdminInstruction::DeprecatePool => "DeprecatePool",
+        AdminInstruction::MigrateToSharedAuthority => "MigrateToSharedAuthority",
+        AdminInstruction::SetRejectZeroAmounts(_) => "SetRejectZeroAmounts",
+        AdminInstruction::SetStakingConfig(_) => "SetStakingConfig",
+        AdminInstruction::RampAPrecise(_) => "RampAPrecise",
+        AdminInstruction::SetWithdrawFeeGracePeriod(_) => "SetWithdrawFeeGracePeriod",
+        AdminInstruction::SetCpiPolicy(_) => "SetCpiPolicy",
+        AdminInstruction::SetCpiAllowlist(_) => "SetCpiAllowlist",
+        AdminInstruction::SetPegRateSource(_) => "SetPegRateSource",
+        AdminInstruction::SetQuickSwapMaxBps(_) => "SetQuickSwapMaxBps",
+        AdminInstruction::SetSameSlotGuard(_) => "SetSameSlotGuard",
+        AdminInstruction::SetAdminFeesAsLp(_) => "SetAdminFeesAsLp",
+        AdminInstruction::SetWithdrawOneWhilePaused(_) => "SetWithdrawOneWhilePaused",
+        AdminInstruction::SetQuietLogs(_) => "SetQuietLogs",
+        AdminInstruction::SetMaxLpSupply(_) => "SetMaxLpSupply",
+        AdminInstruction::SetMaxLpPerOwner(_) => "SetMaxLpPerOwner",
+        AdminInstruction::EmergencyAmpDown(_) => "EmergencyAmpDown",
+    }
+}
+
+/// Names every swap instruction, so that adding one fails to build until it has a fixture
+fn swap_instruction_name(instruction: &SwapInstruction) -> &'static str {
+    match instruction {
+        SwapInstruction::Initialize(_) => "Initialize",
+        SwapInstruction::Swap(_) => "Swap",
+        SwapInstruction::Deposit(_) => "Deposit",
+        SwapInstruction::Withdraw(_) => "Withdraw",
+        SwapInstruction::WithdrawOne(_) => "WithdrawOne",
+        SwapInstruction::SyncAmp => "SyncAmp",
+        SwapInstruction::SwapRoute(_) => "SwapRoute",
+        SwapInstruction::WithdrawOneExactOut(_) => "WithdrawOneExactOut",
+        SwapInstruction::SetEmergencyAuthority => "SetEmergencyAuthority",
+        SwapInstruction::GlobalPause => "GlobalPause",
+        SwapInstruction::GlobalUnpause => "GlobalUnpause",
+        SwapInstruction::DonateToReserves(_) => "DonateToReserves",
+        SwapInstruction::SyncLpPosition => "SyncLpPosition",
+        SwapInstruction::ZapIn(_) => "ZapIn",
+        SwapInstruction::ZapOut(_) => "ZapOut",
+        SwapInstruction::RedeemMigratedLp(_) => "RedeemMigratedLp",
+        SwapInstruction::RecoverStrandedTokens => "RecoverStrandedTokens",
+        SwapInstruction::SwapBatch(_) => "SwapBatch",
+        SwapInstruction::HarvestFees => "HarvestFees",
+        SwapInstruction::EmergencyWithdraw(_) => "EmergencyWithdraw",
+        SwapInstruction::SyncReserves => "SyncReserves",
+        SwapInstruction::RefreshPegRate => "RefreshPegRate",
+        SwapInstruction::SwapAllowPartial(_) => "SwapAllowPartial",
+        SwapInstruction::CreateOrder(_) => "CreateOrder",
+        SwapInstruction::FillOrder => "FillOrder",
+        SwapInstruction::CancelOrder => "CancelOrder",
+        SwapInstruction::GetAmpFactor => "GetAmpFactor",
+        SwapInstruction::InitializeWithRamp(_) => "InitializeWithRamp",
+        SwapInstruction::SwapSplit(_) => "SwapSplit",
+        SwapInstruction::SimulateSwap(_) => "SimulateSwap",
+    }
+}
+
+fn sample_fees() -> Fees {
+    let mut trade_fee_tiers = [FeeTier::default(); MAX_FEE_TIERS];
+    trade_fee_tiers[0] = FeeTier {
+        threshold: 1_000,
+        trade_fee_numerator: 2,
+    };
+    trade_fee_tiers[1] = FeeTier {
+        threshold: 1_000_000,
+        trade_fee_numerator: 1,
+    };
+    Fees {
+        admin_trade_fee_numerator: 1,
+        admin_trade_fee_denominator: 2,
+        admin_withdraw_fee_numerator: 3,
+        admin_withdraw_fee_denominator: 4,
+        trade_fee_numerator: 5,
+        trade_fee_denominator: 1_000,
+        withdraw_fee_numerator: 7,
+        withdraw_fee_denominator: 1_000,
+        trade_fee_tiers,
+        dynamic_fee: DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: 11,
+            out_fee_numerator: 12,
+        },
+        rebalancing_rebate_bps: 13,
+        host_fee_bps: 14,
+    }
+}
+
+fn sample_swap_info() -> SwapInfo {
+    SwapInfo {
+        is_initialized: true,
+        is_paused: true,
+        nonce: 254,
+        initial_amp_factor: 10_050,
+        target_amp_factor: 20_000,
+        start_ramp_ts: 1_600_000_000,
+        stop_ramp_ts: 1_600_086_400,
+        future_admin_deadline: 1_700_000_000,
+        future_admin_key: Pubkey::new_from_array([1u8; 32]),
+        admin_key: Pubkey::new_from_array([2u8; 32]),
+        token_a: SwapTokenInfo {
+            reserves: Pubkey::new_from_array([3u8; 32]),
+            mint: Pubkey::new_from_array([6u8; 32]),
+            admin_fees: Pubkey::new_from_array([8u8; 32]),
+            index: 0,
+        },
+        token_b: SwapTokenInfo {
+            reserves: Pubkey::new_from_array([4u8; 32]),
+            mint: Pubkey::new_from_array([7u8; 32]),
+            admin_fees: Pubkey::new_from_array([9u8; 32]),
+            index: 1,
+        },
+        pool_mint: Pubkey::new_from_array([5u8; 32]),
+        fees: sample_fees(),
+        future_fees_deadline: 1_700_086_400,
+        future_fees: Fees {
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 1_000,
+            ..Fees::default()
+        },
+        donations_admin_only: true,
+        spl_token_swap_compat: false,
+        fee_growth_a: 1 << 64,
+        fee_growth_b: 3,
+        pool_token_supply: 1_000_000,
+        price_oracle_a: Pubkey::new_from_array([10u8; 32]),
+        price_oracle_b: Pubkey::new_from_array([11u8; 32]),
+        max_price_deviation_bps: 50,
+        initial_trade_fee_numerator: 6,
+        initial_withdraw_fee_numerator: 8,
+        start_fee_ramp_ts: 1_600_000_000,
+        stop_fee_ramp_ts: 1_600_086_400,
+        deposit_allowlist_enabled: true,
+        max_swap_bps_of_reserves: 2_500,
+        is_deprecated: true,
+        shared_authority: true,
+        reject_zero_amounts: true,
+        reserve_amount_a: 2_000_000,
+        reserve_amount_b: 3_000_000,
+        withdraw_fee_grace_period: 2_592_000,
+        cpi_policy: CpiPolicy::AllowedCallers,
+        peg_rate: 1_050_000_000,
+        quick_swap_max_bps: 5,
+        same_slot_guard: true,
+        admin_fees_as_lp: true,
+        withdraw_one_while_paused: true,
+        quiet_logs: true,
+        deposit_cap_enabled: true,
+        curve_type: CurveType::StableSwap,
+        initial_supply_scaled: true,
+    }
+}
+
+fn sample_admin_instructions() -> Vec<AdminInstruction> {
+    vec![
+        AdminInstruction::RampA(RampAData {
+            target_amp: 200,
+            stop_ramp_ts: 1_600_086_400,
+        }),
+        AdminInstruction::StopRampA,
+        AdminInstruction::Pause,
+        AdminInstruction::Unpause,
+        AdminInstruction::SetFeeAccount,
+        AdminInstruction::ApplyNewAdmin,
+        AdminInstruction::CommitNewAdmin,
+        AdminInstruction::SetNewFees(sample_fees()),
+        AdminInstruction::SetFeeTiers(sample_fees().trade_fee_tiers),
+        AdminInstruction::SetFeeMode(sample_fees().dynamic_fee),
+        AdminInstruction::CommitNewFees(sample_fees()),
+        AdminInstruction::ApplyNewFees,
+        AdminInstruction::CancelPending,
+        AdminInstruction::SetAdminMultisig(SetAdminMultisigData {
+            threshold: 2,
+            signers: vec![
+                Pubkey::new_from_array([1u8; 32]),
+                Pubkey::new_from_array([2u8; 32]),
+                Pubkey::new_from_array([3u8; 32]),
+            ],
+        }),
+        AdminInstruction::SetFeeExemptions(vec![
+            Pubkey::new_from_array([4u8; 32]),
+            Pubkey::new_from_array([5u8; 32]),
+        ]),
+        AdminInstruction::SetPoolMetadata(SetPoolMetadataData {
+            name: "USDC-USDT LP".to_string(),
+            symbol: "USD".to_string(),
+            uri: "https://example.com/lp.json".to_string(),
+        }),
+        AdminInstruction::CollectAdminFees(CollectAdminFeesData {
+            token_a_amount: 1_000,
+            token_b_amount: 2_000,
+        }),
+        AdminInstruction::InitPoolStats,
+        AdminInstruction::SetDonationsAdminOnly(true),
+        AdminInstruction::SetSplTokenSwapCompat(true),
+        AdminInstruction::SetPriceOracles(50),
+        AdminInstruction::RampFees(RampFeesData {
+            target_fees: sample_fees(),
+            stop_ramp_ts: 1_600_086_400,
+        }),
+        AdminInstruction::SetDepositAllowlist(vec![Pubkey::new_from_array([6u8; 32])]),
+        AdminInstruction::SetDepositAllowlistEnabled(true),
+        AdminInstruction::MigratePool,
+        AdminInstruction::SetMaxSwapBps(2_500),
+        AdminInstruction::SetPoolInfo(SetPoolInfoData {
+            name: "USDC-USDT".to_string(),
+            category: PoolCategory::Stable,
+            is_deprecated: false,
+        }),
+        AdminInstruction::SetRewardsDistributor,
+        AdminInstruction::FreezeLp,
+        AdminInstruction::ThawLp,
+        AdminInstruction::DeprecatePool,
+        AdminInstruction::MigrateToSharedAuthority,
+        AdminInstruction::SetRejectZeroAmounts(true),
+        AdminInstruction::SetStakingConfig([1, 2, 3, 4, 5, 6, 7, 8]),
+        AdminInstruction::RampAPrecise(RampAData {
+            target_amp: 10_050,
+            stop_ramp_ts: 1_700_000_000,
+        }),
+        AdminInstruction::SetWithdrawFeeGracePeriod(2_592_000),
+        AdminInstruction::SetCpiPolicy(CpiPolicy::AllowedCallers),
+        AdminInstruction::SetCpiAllowlist(vec![Pubkey::new_from_array([7u8; 32])]),
+        AdminInstruction::SetPegRateSource(PegRateSource::StakePool),
+        AdminInstruction::SetQuickSwapMaxBps(5),
+        AdminInstruction::SetSameSlotGuard(true),
+        AdminInstruction::SetAdminFeesAsLp(true),
+        AdminInstruction::SetWithdrawOneWhilePaused(true),
+        AdminInstruction::SetQuietLogs(true),
+        AdminInstruction::SetMaxLpSupply(1_000_000),
+        AdminInstruction::SetMaxLpPerOwner(SetMaxLpPerOwnerData {
+            max_lp_per_owner: 1_000,
+            exempt_owners: vec![Pubkey::new_from_array([7u8; 32])],
+        }),
+        AdminInstruction::EmergencyAmpDown(10),
+    ]
+}
+
+fn sample_swap_instructions() -> Vec<SwapInstruction> {
+    vec![
+        SwapInstruction::Initialize(InitializeData {
+            nonce: 254,
+            amp_factor: 100,
+            fees: sample_fees(),
+            min_liquidity: 1_000,
+            curve_type: CurveType::StableSwap,
+        }),
+        SwapInstruction::Swap(SwapData {
+            amount_in: 1_000,
+            minimum_amount_out: 990,
+        }),
+        SwapInstruction::Deposit(DepositData {
+            token_a_amount: 1_000,
+            token_b_amount: 2_000,
+            min_mint_amount: 2_990,
+        }),
+        SwapInstruction::Withdraw(WithdrawData {
+            pool_token_amount: 3_000,
+            minimum_token_a_amount: 990,
+            minimum_token_b_amount: 1_990,
+        }),
+        SwapInstruction::WithdrawOne(WithdrawOneData {
+            pool_token_amount: 1_000,
+            minimum_token_amount: 990,
+        }),
+        SwapInstruction::SyncAmp,
+        SwapInstruction::SwapRoute(SwapData {
+            amount_in: 1_000,
+            minimum_amount_out: 980,
+        }),
+        SwapInstruction::WithdrawOneExactOut(WithdrawOneExactOutData {
+            token_amount: 1_000,
+            maximum_burn_amount: 1_010,
+        }),
+        SwapInstruction::SetEmergencyAuthority,
+        SwapInstruction::GlobalPause,
+        SwapInstruction::GlobalUnpause,
+        SwapInstruction::DonateToReserves(DonateToReservesData {
+            token_a_amount: 1_000,
+            token_b_amount: 2_000,
+        }),
+        SwapInstruction::SyncLpPosition,
+        SwapInstruction::ZapIn(ZapInData {
+            amount_in: 1_000,
+            input_is_a: true,
+            min_mint_amount: 990,
+        }),
+        SwapInstruction::ZapOut(ZapOutData {
+            pool_token_amount: 1_000,
+            want_token_a: false,
+            minimum_amount_out: 990,
+        }),
+        SwapInstruction::RedeemMigratedLp(RedeemMigratedLpData {
+            pool_token_amount: 1_000,
+        }),
+        SwapInstruction::RecoverStrandedTokens,
+        SwapInstruction::SwapBatch(vec![
+            SwapData {
+                amount_in: 1_000,
+                minimum_amount_out: 990,
+            },
+            SwapData {
+                amount_in: 2_000,
+                minimum_amount_out: 1_980,
+            },
+        ]),
+        SwapInstruction::HarvestFees,
+        SwapInstruction::EmergencyWithdraw(WithdrawData {
+            pool_token_amount: 1_000,
+            minimum_token_a_amount: 490,
+            minimum_token_b_amount: 490,
+        }),
+        SwapInstruction::SyncReserves,
+        SwapInstruction::RefreshPegRate,
+        SwapInstruction::SwapAllowPartial(SwapData {
+            amount_in: 1_000,
+            minimum_amount_out: 990,
+        }),
+        SwapInstruction::CreateOrder(CreateOrderData {
+            order_id: 1,
+            amount_in: 1_000,
+            minimum_amount_out: 1_001,
+        }),
+        SwapInstruction::FillOrder,
+        SwapInstruction::CancelOrder,
+        SwapInstruction::GetAmpFactor,
+        SwapInstruction::InitializeWithRamp(InitializeWithRampData {
+            nonce: 254,
+            initial_amp_factor: 1,
+            target_amp_factor: 100,
+            ramp_duration: 604_800,
+            fees: sample_fees(),
+            min_liquidity: 1_000,
+        }),
+        SwapInstruction::SwapSplit(SwapSplitData {
+            amount_in: 1_000,
+            minimum_amount_out: 980,
+            pool_count: 3,
+        }),
+        SwapInstruction::SimulateSwap(SwapData {
+            amount_in: 1_000,
+            minimum_amount_out: 980,
+        }),
+    ]
+}
+
+/// Packs every sample, one `<name> <hex>` line each
+fn packed_layouts() -> String {
+    let mut layouts = vec![];
+
+    let swap_info = sample_swap_info();
+    let mut packed = [0u8; SwapInfo::LEN];
+    SwapInfo::pack(swap_info, &mut packed).unwrap();
+    assert_eq!(SwapInfo::unpack(&packed).unwrap(), swap_info);
+    layouts.push(("state::SwapInfo".to_string(), packed.to_vec()));
+    // legacy accounts only have room for the fields up to the first layout of the fees,
+    // and store whole amplification coefficients
+    let fees = sample_fees();
+    let legacy_swap_info = SwapInfo {
+        initial_amp_factor: 10_000,
+        fees: Fees {
+            admin_trade_fee_numerator: fees.admin_trade_fee_numerator,
+            admin_trade_fee_denominator: fees.admin_trade_fee_denominator,
+            admin_withdraw_fee_numerator: fees.admin_withdraw_fee_numerator,
+            admin_withdraw_fee_denominator: fees.admin_withdraw_fee_denominator,
+            trade_fee_numerator: fees.trade_fee_numerator,
+            trade_fee_denominator: fees.trade_fee_denominator,
+            withdraw_fee_numerator: fees.withdraw_fee_numerator,
+            withdraw_fee_denominator: fees.withdraw_fee_denominator,
+            ..Fees::default()
+        },
+        future_fees_deadline: 0,
+        future_fees: Fees::default(),
+        donations_admin_only: false,
+        fee_growth_a: 0,
+        fee_growth_b: 0,
+        pool_token_supply: 0,
+        price_oracle_a: Pubkey::default(),
+        price_oracle_b: Pubkey::default(),
+        max_price_deviation_bps: 0,
+        initial_trade_fee_numerator: 0,
+        initial_withdraw_fee_numerator: 0,
+        start_fee_ramp_ts: 0,
+        stop_fee_ramp_ts: 0,
+        deposit_allowlist_enabled: false,
+        max_swap_bps_of_reserves: 0,
+        is_deprecated: false,
+        shared_authority: false,
+        reject_zero_amounts: false,
+        reserve_amount_a: 0,
+        reserve_amount_b: 0,
+        withdraw_fee_grace_period: 0,
+        cpi_policy: CpiPolicy::Any,
+        peg_rate: 0,
+        quick_swap_max_bps: 0,
+        same_slot_guard: false,
+        admin_fees_as_lp: false,
+        withdraw_one_while_paused: false,
+        quiet_logs: false,
+        deposit_cap_enabled: false,
+        initial_supply_scaled: false,
+        ..swap_info
+    };
+    let mut packed = [0u8; SWAP_INFO_LEGACY_LEN];
+    assert_eq!(
+        SwapInfo::pack(swap_info, &mut packed),
+        Err(ProgramError::AccountDataTooSmall)
+    );
+    SwapInfo::pack(legacy_swap_info, &mut packed).unwrap();
+    assert_eq!(SwapInfo::unpack(&packed).unwrap(), legacy_swap_info);
+    layouts.push(("state::SwapInfo::legacy".to_string(), packed.to_vec()));
+
+    let fees = sample_fees();
+    let mut packed = [0u8; Fees::LEN];
+    fees.pack_into_slice(&mut packed);
+    assert_eq!(Fees::unpack_from_slice(&packed).unwrap(), fees);
+    layouts.push(("fees::Fees".to_string(), packed.to_vec()));
+
+    for instruction in sample_admin_instructions() {
+        let packed = instruction.pack();
+        let name = format!("AdminInstruction::{}", admin_instruction_name(&instruction));
+        assert_eq!(
+            AdminInstruction::unpack(&packed).unwrap(),
+            Some(instruction),
+            "{}",
+            name
+        );
+        layouts.push((name, packed));
+    }
+    for instruction in sample_swap_instructions() {
+        let packed = instruction.pack();
+        let name = format!("SwapInstruction::{}", swap_instruction_name(&instruction));
+        assert_eq!(
+            SwapInstruction::unpack(&packed).unwrap(),
+            instruction,
+            "{}",
+            name
+        );
+        layouts.push((name, packed));
+    }
+
+    layouts
+        .iter()
+        .map(|(name, packed)| format!("{} {}\n", name, to_hex(packed)))
+        .collect()
+}
+
+#[test]
+fn test_packed_layouts() {
+    let layouts = packed_layouts();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(fixtures_path(), &layouts).unwrap();
+        return;
+    }
+    let fixtures = fs::read_to_string(fixtures_path()).unwrap();
+    for (expected, actual) in fixtures.lines().zip(layouts.lines()) {
+        let name = expected.split(' ').next().unwrap_or_default();
+        assert_eq!(
+            expected, actual,
+            "packed layout of {} drifted, run with UPDATE_GOLDEN=1 if intended",
+            name
+        );
+    }
+    assert_eq!(
+        fixtures, layouts,
+        "packed layouts were added or removed, run with UPDATE_GOLDEN=1 if intended"
+    );
+}
+
+#[test]
+fn test_every_instruction_has_a_sample() {
+    let admin_tags: Vec<u8> = sample_admin_instructions()
+        .iter()
+        .map(|instruction| instruction.pack()[0])
+        .collect();
+    assert_eq!(
+        admin_tags,
+        (100..100 + admin_tags.len() as u8).collect::<Vec<_>>()
+    );
+    let swap_tags: Vec<u8> = sample_swap_instructions()
+        .iter()
+        .map(|instruction| instruction.pack()[0])
+        .collect();
+    assert_eq!(swap_tags, (0..swap_tags.len() as u8).collect::<Vec<_>>());
+    // The tag after the last one is not an instruction
+    assert_eq!(
+        AdminInstruction::unpack(&[100 + admin_tags.len() as u8]).unwrap(),
+        None
+    );
+    assert!(SwapInstruction::unpack(&[swap_tags.len() as u8]).is_err());
+}
@@ -1,36 +1,91 @@
 //! Instruction types
+//!
+//! The fixed-size instruction data structs, e.g. [SwapData], also derive borsh, whose
+//! encoding is the data the instruction packs after its tag, so that off-chain tools
+//! build and decode it without packing bytes by hand. Data holding strings or lists
+//! of keys, e.g. [SetPoolMetadataData], is packed without borsh length prefixes.
 
 #![allow(clippy::too_many_arguments)]
 
+use crate::associated_token;
+use crate::curve::CurveType;
 use crate::error::SwapError;
-use crate::fees::Fees;
+use crate::fees::{DynamicFee, FeeTier, Fees, FEES_V1_LEN, MAX_FEE_TIERS};
+use crate::metadata;
+use crate::state::{
+    find_cpi_allowlist_address, find_deposit_allowlist_address, find_deposit_caps_address,
+    find_global_state_address, find_last_trade_address, find_lp_position_address,
+    find_order_address, find_pool_migration_address, find_pool_registry_entry_address,
+    find_rewards_distributor_address, find_shared_authority_address, find_staking_config_address,
+    CpiPolicy, PegRateSource, PoolCategory, MAX_ADMIN_SIGNERS, MAX_CPI_ALLOWLIST_PROGRAMS,
+    MAX_DEPOSIT_ALLOWLIST_ACCOUNTS, MAX_DEPOSIT_CAP_EXEMPT_OWNERS, MAX_FEE_EXEMPT_ACCOUNTS,
+    MAX_POOL_NAME_LEN,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    instruction::{AccountMeta, Instruction},
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, CompiledInstruction, Instruction},
     program_error::ProgramError,
     program_pack::Pack,
     pubkey::Pubkey,
-    sysvar::clock,
+    system_program,
+    sysvar::{self, clock, rent},
 };
 use std::convert::TryInto;
 use std::mem::size_of;
 
 /// Initialize instruction data
 #[repr(C)]
-#[derive(Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InitializeData {
     /// Nonce used to create valid program address
     pub nonce: u8,
-    /// Amplification coefficient (A)
+    /// Amplification coefficient (A), in whole units
     pub amp_factor: u64,
     /// Fees
     pub fees: Fees,
+    /// Pool tokens minted to an account no one controls instead of the bootstrapper,
+    /// see [with_locked_liquidity]. Zero for pools initialized without a lock.
+    pub min_liquidity: u64,
+    /// Invariant of the pool, see [with_curve_type]
+    pub curve_type: CurveType,
+}
+
+/// InitializeWithRamp instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InitializeWithRampData {
+    /// Nonce used to create valid program address
+    pub nonce: u8,
+    /// Amplification coefficient (A) the pool starts with, in whole units
+    pub initial_amp_factor: u64,
+    /// Amplification coefficient (A) the pool ramps to, in whole units
+    pub target_amp_factor: u64,
+    /// Duration of the ramp in seconds, at least
+    /// [MIN_RAMP_DURATION](../curve/constant.MIN_RAMP_DURATION.html)
+    pub ramp_duration: i64,
+    /// Fees
+    pub fees: Fees,
+    /// Pool tokens minted to an account no one controls instead of the bootstrapper,
+    /// see [with_locked_liquidity]. Zero for pools initialized without a lock.
+    pub min_liquidity: u64,
 }
 
+/// Pool tokens locked on initialization by [with_locked_liquidity] unless another
+/// amount is chosen, as Uniswap V2 does. Once the pool tokens can not all be
+/// withdrawn, the first depositor can not inflate the value of a pool token until
+/// later deposits round down to nothing.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
 /// Swap instruction data
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SwapData {
     /// SOURCE amount to transfer, output to DESTINATION is based on the exchange rate
     pub amount_in: u64,
@@ -38,10 +93,31 @@ pub struct SwapData {
     pub minimum_amount_out: u64,
 }
 
+/// Maximum number of fills of a SwapBatch
+pub const MAX_SWAP_BATCH_FILLS: usize = 16;
+
+/// Maximum number of pools a SwapSplit splits its amount across
+pub const MAX_SPLIT_POOLS: u8 = 3;
+
+/// SwapSplit instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapSplitData {
+    /// SOURCE amount to transfer, split across the pools
+    pub amount_in: u64,
+    /// Minimum amount of DESTINATION token the pools output together
+    pub minimum_amount_out: u64,
+    /// Number of pools passed, from 1 to [MAX_SPLIT_POOLS]
+    pub pool_count: u8,
+}
+
 /// Deposit instruction data
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DepositData {
     /// Token A amount to deposit
     pub token_a_amount: u64,
@@ -53,8 +129,9 @@ pub struct DepositData {
 
 /// Withdraw instruction data
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WithdrawData {
     /// Amount of pool tokens to burn. User receives an output of token a
     /// and b based on the percentage of the pool tokens that are returned.
@@ -67,8 +144,9 @@ pub struct WithdrawData {
 
 /// Withdraw instruction data
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WithdrawOneData {
     /// Amount of pool tokens to burn. User receives an output of token a
     /// or b based on the percentage of the pool tokens that are returned.
@@ -77,29 +155,185 @@ pub struct WithdrawOneData {
     pub minimum_token_amount: u64,
 }
 
+/// WithdrawOneExactOut instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WithdrawOneExactOutData {
+    /// Exact amount of token A or B to receive
+    pub token_amount: u64,
+    /// Maximum amount of pool tokens to burn, prevents excessive slippage
+    pub maximum_burn_amount: u64,
+}
+
+/// DonateToReserves instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DonateToReservesData {
+    /// Amount of token A to add to the reserves
+    pub token_a_amount: u64,
+    /// Amount of token B to add to the reserves
+    pub token_b_amount: u64,
+}
+
+/// ZapIn instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZapInData {
+    /// Amount of the input token to deposit
+    pub amount_in: u64,
+    /// Whether the input token is token A
+    pub input_is_a: bool,
+    /// Minimum amount of pool tokens to mint, prevents excessive slippage
+    pub min_mint_amount: u64,
+}
+
+/// ZapOut instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZapOutData {
+    /// Amount of pool tokens to burn
+    pub pool_token_amount: u64,
+    /// Whether the token to receive is token A
+    pub want_token_a: bool,
+    /// Minimum amount of the wanted token to receive, prevents excessive slippage
+    pub minimum_amount_out: u64,
+}
+
+/// RedeemMigratedLp instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedeemMigratedLpData {
+    /// Amount of pool tokens of the migrated swap to redeem
+    pub pool_token_amount: u64,
+}
+
+/// CreateOrder instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CreateOrderData {
+    /// Number of the order among the orders of the owner, seed of its address
+    pub order_id: u64,
+    /// Amount of tokens sold
+    pub amount_in: u64,
+    /// Minimum amount of tokens bought
+    pub minimum_amount_out: u64,
+}
+
 /// RampA instruction data
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RampAData {
-    /// Amp. Coefficient to ramp to
+    /// Amp. Coefficient to ramp to, in whole units for RampA and in
+    /// [AMP_PRECISION](crate::curve::AMP_PRECISION) units for RampAPrecise
     pub target_amp: u64,
     /// Unix timestamp to stop ramp
     pub stop_ramp_ts: i64,
 }
 
+/// SetAdminMultisig instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetAdminMultisigData {
+    /// Number of signers required to execute admin instructions
+    pub threshold: u8,
+    /// Signer public keys
+    pub signers: Vec<Pubkey>,
+}
+
+/// SetPoolMetadata instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetPoolMetadataData {
+    /// Name of the pool token
+    pub name: String,
+    /// Symbol of the pool token
+    pub symbol: String,
+    /// URI of the pool token metadata JSON
+    pub uri: String,
+}
+
+/// CollectAdminFees instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CollectAdminFeesData {
+    /// Amount of token A admin fees to collect
+    pub token_a_amount: u64,
+    /// Amount of token B admin fees to collect
+    pub token_b_amount: u64,
+}
+
+/// RampFees instruction data
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RampFeesData {
+    /// Fees to ramp to
+    pub target_fees: Fees,
+    /// Unix timestamp to stop ramp
+    pub stop_ramp_ts: i64,
+}
+
+/// SetMaxLpPerOwner instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetMaxLpPerOwnerData {
+    /// Max balance of the pool token account a deposit mints to, 0 to lift the cap
+    pub max_lp_per_owner: u64,
+    /// Owners the cap does not apply to, at most [MAX_DEPOSIT_CAP_EXEMPT_OWNERS]
+    pub exempt_owners: Vec<Pubkey>,
+}
+
+/// SetPoolInfo instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetPoolInfoData {
+    /// Short name of the pool, at most [MAX_POOL_NAME_LEN] bytes
+    pub name: String,
+    /// Category of the pool
+    pub category: PoolCategory,
+    /// Whether the pool is deprecated
+    pub is_deprecated: bool,
+}
+
 /// Admin only instructions.
+///
+/// When the admin key is an admin multisig, the admin account is the multisig
+/// (not a signer) and at least `threshold` of its signers must sign the instruction.
+/// Signers are passed as additional accounts after the accounts listed below.
+///
+/// The clock sysvar may be omitted from any instruction, see [omit_clock_sysvar].
+///
+/// Admin multisigs signing over hours sign them with a durable nonce, see
+/// [offline](../offline/index.html).
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum AdminInstruction {
-    /// Starts a ramp of A to the next value.
+    /// Schedules a ramp of A to the next value, in whole units, starting 1 day later.
+    /// Fails while paused.
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
     /// 2. `[]` Clock sysvar
     RampA(RampAData),
 
-    /// Cancels the pending ramp of A.
+    /// Cancels the pending or scheduled ramp of A.
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
@@ -107,6 +341,8 @@ pub enum AdminInstruction {
     StopRampA,
 
     /// Pauses swap, deposit, and withdraw_one.
+    /// The proportional withdraw stays open, and withdraw_one does too, at doubled
+    /// fees, if enabled by [AdminInstruction::SetWithdrawOneWhilePaused].
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
@@ -123,6 +359,8 @@ pub enum AdminInstruction {
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
     /// 2. `[]` Token account to receive fees. Must have mint of Token A or Token B.
+    ///    Its owner may be any account, e.g. a vault program address from
+    ///    [find_admin_fee_vault_address](../state/fn.find_admin_fee_vault_address.html).
     SetFeeAccount,
 
     /// Finalizes the admin transfer. This is run after CommitNewAdmin.
@@ -133,6 +371,7 @@ pub enum AdminInstruction {
     ApplyNewAdmin,
 
     /// Commits a new admin. The admin must accept ownership within 3 days.
+    /// The new admin cannot be the default pubkey.
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
@@ -140,17 +379,438 @@ pub enum AdminInstruction {
     /// 3. `[]` Clock sysvar
     CommitNewAdmin,
 
-    /// Updates the swap fees.
+    /// Commits new swap fees, like CommitNewFees. They cannot be applied while A or
+    /// the fees are ramping.
     ///
     /// 0. `[writable]` StableSwap
     /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
     SetNewFees(Fees),
+
+    /// Commits new trade fee tiers, keeping every other fee unchanged, like CommitNewFees.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    SetFeeTiers([FeeTier; MAX_FEE_TIERS]),
+
+    /// Commits a switch between the flat and dynamic trade fee, keeping every other
+    /// fee unchanged, like CommitNewFees.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    SetFeeMode(DynamicFee),
+
+    /// Commits new swap fees. The fees can be applied with ApplyNewFees after 1 day.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    CommitNewFees(Fees),
+
+    /// Applies the fees committed with CommitNewFees once they are active.
+    /// Fails while A or the fees are ramping.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    ApplyNewFees,
+
+    /// Cancels the pending fee change and admin transfer.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    CancelPending,
+
+    /// Creates or updates the admin multisig of the swap. The multisig only takes
+    /// control once the admin is transferred to it with CommitNewAdmin and ApplyNewAdmin.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Admin multisig, program address from [find_admin_multisig_address](../state/fn.find_admin_multisig_address.html)
+    /// 3. `[writable, signer]` Payer of the admin multisig account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetAdminMultisig(SetAdminMultisigData),
+
+    /// Creates or updates the list of user authorities exempt from the withdraw fee
+    /// and from the trade fee on imbalanced deposits.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Fee exemption list, program address from [find_fee_exemptions_address](../state/fn.find_fee_exemptions_address.html)
+    /// 3. `[writable, signer]` Payer of the fee exemption list account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetFeeExemptions(Vec<Pubkey>),
+
+    /// Creates or updates the Token Metadata of the pool mint, so that wallets
+    /// can display the name and symbol of the pool token.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` $authority, mint authority and metadata update authority
+    /// 3. `[]` Pool mint
+    /// 4. `[writable]` Pool mint metadata, from [find_metadata_address](../metadata/fn.find_metadata_address.html)
+    /// 5. `[writable, signer]` Payer of the metadata account rent
+    /// 6. `[]` Token Metadata program
+    /// 7. `[]` System program
+    /// 8. `[]` Rent sysvar
+    SetPoolMetadata(SetPoolMetadataData),
+
+    /// Transfers admin fees out of the admin fee accounts of the swap. The
+    /// admin fee accounts must be owned by the swap authority, or by an owner signing
+    /// the instruction, passed after the Clock sysvar. That owner may be the program
+    /// address of a vault program, see
+    /// [find_admin_fee_vault_address](../state/fn.find_admin_fee_vault_address.html),
+    /// signing when the vault program invokes the instruction. See
+    /// [with_admin_fee_owner](fn.with_admin_fee_owner.html).
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` $authority
+    /// 3. `[writable]` token_a admin fee account
+    /// 4. `[writable]` token_b admin fee account
+    /// 5. `[writable]` token_a destination account
+    /// 6. `[writable]` token_b destination account
+    /// 7. `[]` Token program id
+    /// 8. `[]` Clock sysvar
+    CollectAdminFees(CollectAdminFeesData),
+
+    /// Creates the statistics account of the swap, which is then updated by
+    /// every instruction it is passed to.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Pool statistics, program address from [find_pool_stats_address](../state/fn.find_pool_stats_address.html)
+    /// 3. `[writable, signer]` Payer of the pool statistics account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    InitPoolStats,
+
+    /// Restricts DonateToReserves to the admin, or opens it to anyone.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetDonationsAdminOnly(bool),
+
+    /// Enables or disables swaps passed with the accounts of an spl-token-swap `Swap`.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetSplTokenSwapCompat(bool),
+
+    /// Sets the Pyth price accounts of the tokens, and the max deviation in bps of the
+    /// swap price from the oracle price above which swaps are rejected. A max
+    /// deviation of 0 disables the check. Only Pyth price accounts are supported.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Pyth price account of token A
+    /// 3. `[]` Pyth price account of token B
+    SetPriceOracles(u64),
+
+    /// Schedules a ramp of the fees to the target fees, starting 1 day later. The
+    /// trade and withdraw fee numerators are interpolated linearly until `stop_ramp_ts`,
+    /// every other fee must be the current one and changes through CommitNewFees.
+    /// Fails while paused.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    RampFees(RampFeesData),
+
+    /// Creates or updates the list of user authorities allowed to deposit into the swap
+    /// once its deposit allowlist is enabled.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Deposit allowlist, program address from [find_deposit_allowlist_address](../state/fn.find_deposit_allowlist_address.html)
+    /// 3. `[writable, signer]` Payer of the deposit allowlist account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetDepositAllowlist(Vec<Pubkey>),
+
+    /// Restricts Deposit and ZapIn to the user authorities of the deposit allowlist,
+    /// or opens them to anyone.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetDepositAllowlistEnabled(bool),
+
+    /// Moves all the reserves of the swap into a new swap of the same mints and admin,
+    /// and pauses the swap. The pool tokens of the new swap minted for the reserves are
+    /// held in escrow, to be redeemed by pool token holders with RedeemMigratedLp.
+    /// Not available to swaps with a shared authority.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` $authority
+    /// 3. `[writable]` token_a reserves of the swap
+    /// 4. `[writable]` token_b reserves of the swap
+    /// 5. `[]` Pool mint of the swap
+    /// 6. `[writable]` New StableSwap
+    /// 7. `[]` $authority of the new swap
+    /// 8. `[writable]` token_a reserves of the new swap
+    /// 9. `[writable]` token_b reserves of the new swap
+    /// 10. `[writable]` Pool mint of the new swap
+    /// 11. `[writable]` Escrow account of the new pool tokens, $authority is the owner
+    /// 12. `[writable]` Pool migration, program address from [find_pool_migration_address](../state/fn.find_pool_migration_address.html)
+    /// 13. `[writable, signer]` Payer of the pool migration account rent
+    /// 14. `[]` System program
+    /// 15. `[]` Rent sysvar
+    /// 16. `[]` Token program id
+    /// 17. `[]` Clock sysvar
+    MigratePool,
+
+    /// Sets the max amount a swap can take out of the output reserve, in bps of
+    /// that reserve. 0 disables the check.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetMaxSwapBps(u64),
+
+    /// Sets the registry entry of the swap read by front-ends, creating it if needed.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Pool registry entry, program address from [find_pool_registry_entry_address](../state/fn.find_pool_registry_entry_address.html)
+    /// 3. `[writable, signer]` Payer of the pool registry entry rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetPoolInfo(SetPoolInfoData),
+
+    /// Sets the program the admin fees are harvested into with HarvestFees, and its
+    /// token accounts receiving them, creating the rewards distributor if needed.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Rewards distributor, program address from [find_rewards_distributor_address](../state/fn.find_rewards_distributor_address.html)
+    /// 3. `[writable, signer]` Payer of the rewards distributor rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    /// 6. `[]` Rewards distributor program
+    /// 7. `[]` token_a account receiving the admin fees
+    /// 8. `[]` token_b account receiving the admin fees
+    SetRewardsDistributor,
+
+    /// Freezes an LP token account, e.g. while an exploit is investigated. Only
+    /// pools initialized with the swap authority as the LP mint freeze authority.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` $authority
+    /// 3. `[]` Pool Token Mint
+    /// 4. `[writable]` LP token account to freeze
+    /// 5. `[]` Token program id
+    FreezeLp,
+
+    /// Thaws an LP token account frozen with FreezeLp.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` $authority
+    /// 3. `[]` Pool Token Mint
+    /// 4. `[writable]` LP token account to thaw
+    /// 5. `[]` Token program id
+    ThawLp,
+
+    /// Deprecates the pool to wind it down: swaps and deposits are rejected, and
+    /// withdrawals are free of withdraw fees. Deprecation cannot be undone, and
+    /// pools in the legacy layout must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    DeprecatePool,
+
+    /// Hands the token accounts and the pool mint of the swap over from its own
+    /// $authority to the program-wide authority of
+    /// [find_shared_authority_address](../state/fn.find_shared_authority_address.html).
+    /// Admin fee accounts are handed over if $authority owns them. Pools in the legacy
+    /// layout must be migrated first.
+    ///
+    /// Stranded tokens must be recovered and fees harvested beforehand: pools with a
+    /// shared authority cannot tell their own stray accounts from those of other pools.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` $authority
+    /// 3. `[]` Shared authority
+    /// 4. `[writable]` token_a reserves
+    /// 5. `[writable]` token_b reserves
+    /// 6. `[writable]` token_a admin fee account
+    /// 7. `[writable]` token_b admin fee account
+    /// 8. `[writable]` Pool Token Mint
+    /// 9. `[]` Token program id
+    MigrateToSharedAuthority,
+
+    /// Makes instructions whose amounts are all zero fail with ZeroAmount, or lets
+    /// them succeed as a no-op logging a Noop event, the default. Pools in the legacy
+    /// layout must be migrated before rejecting zero amounts.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetRejectZeroAmounts(bool),
+
+    /// Sets the staking program a Deposit can stake the minted pool tokens into and
+    /// the staking pool it stakes into, creating the staking config if needed. The
+    /// staking instruction data is the given prefix followed by the staked amount.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Staking config, program address from [find_staking_config_address](../state/fn.find_staking_config_address.html)
+    /// 3. `[writable, signer]` Payer of the staking config rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    /// 6. `[]` Staking program
+    /// 7. `[]` Staking pool, owned by the staking program
+    SetStakingConfig([u8; 8]),
+
+    /// Schedules a ramp of A to the next value, in [AMP_PRECISION](crate::curve::AMP_PRECISION)
+    /// units, starting 1 day later. Swaps in the legacy layout only store whole coefficients and can only
+    /// ramp to those.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    RampAPrecise(RampAData),
+
+    /// Sets the age, in seconds, after which the pool tokens of an LP position are
+    /// withdrawn without withdraw fees, 0 to disable the waiver. The age of a position
+    /// is averaged over the pool tokens deposited into it. Pools in the legacy layout
+    /// must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetWithdrawFeeGracePeriod(i64),
+
+    /// Sets which programs may invoke swaps, deposits, single-token withdrawals and zaps
+    /// of the swap: any program, none but the swap program itself, or the programs of the
+    /// CPI allowlist. Proportional withdrawals are never restricted. Pools in the legacy
+    /// layout must be migrated before restricting their callers.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetCpiPolicy(CpiPolicy),
+
+    /// Creates or updates the list of programs allowed to invoke the swap once its CPI
+    /// policy is AllowedCallers.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` CPI allowlist, program address from [find_cpi_allowlist_address](../state/fn.find_cpi_allowlist_address.html)
+    /// 3. `[writable, signer]` Payer of the CPI allowlist account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetCpiAllowlist(Vec<Pubkey>),
+
+    /// Sets the account the peg rate of the swap is refreshed from, creating the peg
+    /// config if needed, and reads the rate from it. The curve then values token B at
+    /// the peg rate in token A rather than 1:1. A source of None pegs the pool 1:1
+    /// again. Pools in the legacy layout must be migrated before pegging them.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Peg config, program address from [find_peg_config_address](../state/fn.find_peg_config_address.html)
+    /// 3. `[writable, signer]` Payer of the peg config rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    /// 6. `[]` Rate account: a Pyth price account or an SPL stake pool
+    /// 7. `[]` Clock sysvar
+    SetPegRateSource(PegRateSource),
+
+    /// Sets the largest swap, in bps of the source reserves, paid out a conservative
+    /// estimate of the curve instead of solving it, which costs far fewer compute units.
+    /// The estimate never pays more than the full solve. 0 disables the quick path.
+    /// Pools in the legacy layout must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetQuickSwapMaxBps(u64),
+
+    /// Enables or disables the same-slot guard, creating the last trade account if
+    /// needed. Once enabled, a user authority can not swap back in the slot of its own
+    /// swaps, even with swaps of others in between, and swaps need the account added by
+    /// [with_same_slot_guard]. Pools in the legacy layout must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Last trade, program address from [find_last_trade_address](../state/fn.find_last_trade_address.html)
+    /// 3. `[writable, signer]` Payer of the last trade account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetSameSlotGuard(bool),
+
+    /// Sets whether the admin fees of swaps and withdrawals stay in the reserves, the
+    /// admin receiving their value in pool tokens instead, see
+    /// [SwapInfo::admin_fees_as_lp](../state/struct.SwapInfo.html#structfield.admin_fees_as_lp).
+    /// Once enabled, swaps and withdrawals need the accounts added by
+    /// [with_admin_fees_as_lp]. Pools in the legacy layout must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetAdminFeesAsLp(bool),
+
+    /// Sets whether WithdrawOne stays open while the swap is paused, at twice the
+    /// fees, see
+    /// [SwapInfo::withdraw_one_while_paused](../state/struct.SwapInfo.html#structfield.withdraw_one_while_paused).
+    /// The proportional Withdraw stays open in either case. Pools in the legacy layout
+    /// must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetWithdrawOneWhilePaused(bool),
+
+    /// Sets whether the instructions of the swap leave out their detailed logs, see
+    /// [SwapInfo::quiet_logs](../state/struct.SwapInfo.html#structfield.quiet_logs).
+    /// Pools in the legacy layout must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    SetQuietLogs(bool),
+
+    /// Caps the supply of the pool mint after deposits and zap ins, creating the
+    /// deposit caps account if needed. 0 lifts the cap. While capped, deposits and zap
+    /// ins need the account added by [with_deposit_caps]. Pools in the legacy layout
+    /// must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Deposit caps, program address from [find_deposit_caps_address](../state/fn.find_deposit_caps_address.html)
+    /// 3. `[writable, signer]` Payer of the deposit caps account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetMaxLpSupply(u64),
+
+    /// Caps the balance of the pool token account deposits and zap ins mint to, after
+    /// them, except for the accounts of the exempt owners, replacing them. Creates the
+    /// deposit caps account if needed, as [SetMaxLpSupply](#variant.SetMaxLpSupply).
+    /// Pools in the legacy layout must be migrated first.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[writable]` Deposit caps, program address from [find_deposit_caps_address](../state/fn.find_deposit_caps_address.html)
+    /// 3. `[writable, signer]` Payer of the deposit caps account rent
+    /// 4. `[]` System program
+    /// 5. `[]` Rent sysvar
+    SetMaxLpPerOwner(SetMaxLpPerOwnerData),
+
+    /// Divides A by the divisor at once, down to [MIN_AMP](../curve/constant.MIN_AMP.html),
+    /// cancelling any ramp, for depegs during which ramping down A would lose the LPs
+    /// their funds. Unlike [RampA](#variant.RampA) it is neither ramp locked nor
+    /// paused. Pools in the legacy layout keep whole coefficients.
+    ///
+    /// 0. `[writable]` StableSwap
+    /// 1. `[signer]` Admin account
+    /// 2. `[]` Clock sysvar
+    EmergencyAmpDown(u64),
 }
 
 impl AdminInstruction {
     /// Unpacks a byte buffer into a [AdminInstruction](enum.AdminInstruction.html).
     pub fn unpack(input: &[u8]) -> Result<Option<Self>, ProgramError> {
-        let (&tag, rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
+        let (&tag, rest) = input.split_first().ok_or(SwapError::InstructionTooShort)?;
         Ok(match tag {
             100 => {
                 let (target_amp, rest) = unpack_u64(rest)?;
@@ -167,15 +827,274 @@ impl AdminInstruction {
             105 => Some(Self::ApplyNewAdmin),
             106 => Some(Self::CommitNewAdmin),
             107 => {
-                let fees = Fees::unpack_unchecked(rest)?;
+                let fees = unpack_fees(rest)?;
                 Some(Self::SetNewFees(fees))
             }
-            _ => None,
-        })
-    }
-
-    /// Packs a [AdminInstruction](enum.AdminInstruction.html) into a byte buffer.
-    pub fn pack(&self) -> Vec<u8> {
+            108 => {
+                check_data_len(rest, FeeTier::LEN * MAX_FEE_TIERS)?;
+                let mut tiers = [FeeTier::default(); MAX_FEE_TIERS];
+                for (tier, packed) in tiers.iter_mut().zip(rest.chunks(FeeTier::LEN)) {
+                    *tier = FeeTier::unpack_from_slice(packed)?;
+                }
+                Some(Self::SetFeeTiers(tiers))
+            }
+            109 => {
+                check_data_len(rest, DynamicFee::LEN)?;
+                let dynamic_fee = DynamicFee::unpack_from_slice(rest)
+                    .map_err(|_| SwapError::InvalidInstruction)?;
+                Some(Self::SetFeeMode(dynamic_fee))
+            }
+            110 => {
+                let fees = unpack_fees(rest)?;
+                Some(Self::CommitNewFees(fees))
+            }
+            111 => Some(Self::ApplyNewFees),
+            112 => Some(Self::CancelPending),
+            113 => {
+                let (&threshold, mut rest) =
+                    rest.split_first().ok_or(SwapError::InstructionTooShort)?;
+                let mut signers = vec![];
+                while !rest.is_empty() {
+                    let (signer, next) = unpack_pubkey(rest)?;
+                    signers.push(signer);
+                    rest = next;
+                }
+                if signers.len() > MAX_ADMIN_SIGNERS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Some(Self::SetAdminMultisig(SetAdminMultisigData {
+                    threshold,
+                    signers,
+                }))
+            }
+            114 => {
+                let mut rest = rest;
+                let mut exempt_accounts = vec![];
+                while !rest.is_empty() {
+                    let (account, next) = unpack_pubkey(rest)?;
+                    exempt_accounts.push(account);
+                    rest = next;
+                }
+                if exempt_accounts.len() > MAX_FEE_EXEMPT_ACCOUNTS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Some(Self::SetFeeExemptions(exempt_accounts))
+            }
+            115 => {
+                let (name, rest) = unpack_str(rest)?;
+                let (symbol, rest) = unpack_str(rest)?;
+                let (uri, _rest) = unpack_str(rest)?;
+                Some(Self::SetPoolMetadata(SetPoolMetadataData {
+                    name,
+                    symbol,
+                    uri,
+                }))
+            }
+            116 => {
+                let (token_a_amount, rest) = unpack_u64(rest)?;
+                let (token_b_amount, _rest) = unpack_u64(rest)?;
+                Some(Self::CollectAdminFees(CollectAdminFeesData {
+                    token_a_amount,
+                    token_b_amount,
+                }))
+            }
+            117 => Some(Self::InitPoolStats),
+            118 => match rest.first() {
+                Some(0) => Some(Self::SetDonationsAdminOnly(false)),
+                Some(1) => Some(Self::SetDonationsAdminOnly(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            119 => match rest.first() {
+                Some(0) => Some(Self::SetSplTokenSwapCompat(false)),
+                Some(1) => Some(Self::SetSplTokenSwapCompat(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            120 => {
+                let (max_deviation_bps, _rest) = unpack_u64(rest)?;
+                Some(Self::SetPriceOracles(max_deviation_bps))
+            }
+            121 => {
+                if rest.len() < Fees::LEN {
+                    return Err(SwapError::InstructionTooShort.into());
+                }
+                let (target_fees, rest) = rest.split_at(Fees::LEN);
+                let target_fees = Fees::unpack_unchecked(target_fees)?;
+                let (stop_ramp_ts, _rest) = unpack_i64(rest)?;
+                Some(Self::RampFees(RampFeesData {
+                    target_fees,
+                    stop_ramp_ts,
+                }))
+            }
+            122 => {
+                let mut rest = rest;
+                let mut allowed_accounts = vec![];
+                while !rest.is_empty() {
+                    let (account, next) = unpack_pubkey(rest)?;
+                    allowed_accounts.push(account);
+                    rest = next;
+                }
+                if allowed_accounts.len() > MAX_DEPOSIT_ALLOWLIST_ACCOUNTS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Some(Self::SetDepositAllowlist(allowed_accounts))
+            }
+            123 => match rest.first() {
+                Some(0) => Some(Self::SetDepositAllowlistEnabled(false)),
+                Some(1) => Some(Self::SetDepositAllowlistEnabled(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            124 => Some(Self::MigratePool),
+            125 => {
+                let (max_swap_bps, _rest) = unpack_u64(rest)?;
+                Some(Self::SetMaxSwapBps(max_swap_bps))
+            }
+            126 => {
+                let (name, rest) = unpack_str(rest)?;
+                if name.len() > MAX_POOL_NAME_LEN {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                let (&category, rest) = rest.split_first().ok_or(SwapError::InstructionTooShort)?;
+                let category =
+                    PoolCategory::from_u8(category).ok_or(SwapError::InvalidInstruction)?;
+                let is_deprecated = match rest.first() {
+                    Some(0) => false,
+                    Some(1) => true,
+                    None => return Err(SwapError::InstructionTooShort.into()),
+                    _ => return Err(SwapError::InvalidInstruction.into()),
+                };
+                Some(Self::SetPoolInfo(SetPoolInfoData {
+                    name,
+                    category,
+                    is_deprecated,
+                }))
+            }
+            127 => Some(Self::SetRewardsDistributor),
+            128 => Some(Self::FreezeLp),
+            129 => Some(Self::ThawLp),
+            130 => Some(Self::DeprecatePool),
+            131 => Some(Self::MigrateToSharedAuthority),
+            132 => match rest.first() {
+                Some(0) => Some(Self::SetRejectZeroAmounts(false)),
+                Some(1) => Some(Self::SetRejectZeroAmounts(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            133 => {
+                let stake_instruction_prefix = rest
+                    .get(..8)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(SwapError::InstructionTooShort)?;
+                Some(Self::SetStakingConfig(stake_instruction_prefix))
+            }
+            134 => {
+                let (target_amp, rest) = unpack_u64(rest)?;
+                let (stop_ramp_ts, _rest) = unpack_i64(rest)?;
+                Some(Self::RampAPrecise(RampAData {
+                    target_amp,
+                    stop_ramp_ts,
+                }))
+            }
+            135 => {
+                let (withdraw_fee_grace_period, _rest) = unpack_i64(rest)?;
+                Some(Self::SetWithdrawFeeGracePeriod(withdraw_fee_grace_period))
+            }
+            136 => {
+                let &cpi_policy = rest.first().ok_or(SwapError::InstructionTooShort)?;
+                let cpi_policy =
+                    CpiPolicy::from_u8(cpi_policy).ok_or(SwapError::InvalidInstruction)?;
+                Some(Self::SetCpiPolicy(cpi_policy))
+            }
+            137 => {
+                let mut rest = rest;
+                let mut allowed_programs = vec![];
+                while !rest.is_empty() {
+                    let (program, next) = unpack_pubkey(rest)?;
+                    allowed_programs.push(program);
+                    rest = next;
+                }
+                if allowed_programs.len() > MAX_CPI_ALLOWLIST_PROGRAMS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Some(Self::SetCpiAllowlist(allowed_programs))
+            }
+            138 => {
+                let &rate_source = rest.first().ok_or(SwapError::InstructionTooShort)?;
+                let rate_source =
+                    PegRateSource::from_u8(rate_source).ok_or(SwapError::InvalidInstruction)?;
+                Some(Self::SetPegRateSource(rate_source))
+            }
+            139 => {
+                let (quick_swap_max_bps, _rest) = unpack_u64(rest)?;
+                Some(Self::SetQuickSwapMaxBps(quick_swap_max_bps))
+            }
+            140 => match rest.first() {
+                Some(0) => Some(Self::SetSameSlotGuard(false)),
+                Some(1) => Some(Self::SetSameSlotGuard(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            141 => match rest.first() {
+                Some(0) => Some(Self::SetAdminFeesAsLp(false)),
+                Some(1) => Some(Self::SetAdminFeesAsLp(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            142 => match rest.first() {
+                Some(0) => Some(Self::SetWithdrawOneWhilePaused(false)),
+                Some(1) => Some(Self::SetWithdrawOneWhilePaused(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            143 => match rest.first() {
+                Some(0) => Some(Self::SetQuietLogs(false)),
+                Some(1) => Some(Self::SetQuietLogs(true)),
+                None => return Err(SwapError::InstructionTooShort.into()),
+                _ => return Err(SwapError::InvalidInstruction.into()),
+            },
+            144 => {
+                let (max_lp_supply, _rest) = unpack_u64(rest)?;
+                Some(Self::SetMaxLpSupply(max_lp_supply))
+            }
+            145 => {
+                let (max_lp_per_owner, mut rest) = unpack_u64(rest)?;
+                let mut exempt_owners = vec![];
+                while !rest.is_empty() {
+                    let (owner, next) = unpack_pubkey(rest)?;
+                    exempt_owners.push(owner);
+                    rest = next;
+                }
+                if exempt_owners.len() > MAX_DEPOSIT_CAP_EXEMPT_OWNERS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Some(Self::SetMaxLpPerOwner(SetMaxLpPerOwnerData {
+                    max_lp_per_owner,
+                    exempt_owners,
+                }))
+            }
+            146 => {
+                let (divisor, _rest) = unpack_u64(rest)?;
+                Some(Self::EmergencyAmpDown(divisor))
+            }
+            _ => None,
+        })
+    }
+
+    /// Unpacks a byte buffer into a [AdminInstruction](enum.AdminInstruction.html) as
+    /// [unpack](#method.unpack), failing with `TrailingInstructionData` on bytes after
+    /// the last field of the instruction instead of ignoring them.
+    pub fn unpack_checked(input: &[u8]) -> Result<Option<Self>, ProgramError> {
+        let instruction = Self::unpack(input)?;
+        if let Some(instruction) = &instruction {
+            check_no_trailing_data(input, &instruction.pack())?;
+        }
+        Ok(instruction)
+    }
+
+    /// Packs a [AdminInstruction](enum.AdminInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
         match *self {
             Self::RampA(RampAData {
@@ -198,6 +1117,189 @@ impl AdminInstruction {
                 Pack::pack_into_slice(&fees, &mut fees_slice[..]);
                 buf.extend_from_slice(&fees_slice);
             }
+            Self::SetFeeTiers(tiers) => {
+                buf.push(108);
+                for tier in tiers.iter() {
+                    let mut tier_slice = [0u8; FeeTier::LEN];
+                    Pack::pack_into_slice(tier, &mut tier_slice[..]);
+                    buf.extend_from_slice(&tier_slice);
+                }
+            }
+            Self::SetFeeMode(dynamic_fee) => {
+                buf.push(109);
+                let mut dynamic_fee_slice = [0u8; DynamicFee::LEN];
+                Pack::pack_into_slice(&dynamic_fee, &mut dynamic_fee_slice[..]);
+                buf.extend_from_slice(&dynamic_fee_slice);
+            }
+            Self::CommitNewFees(fees) => {
+                buf.push(110);
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(&fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+            }
+            Self::ApplyNewFees => buf.push(111),
+            Self::CancelPending => buf.push(112),
+            Self::SetAdminMultisig(SetAdminMultisigData {
+                threshold,
+                ref signers,
+            }) => {
+                buf.push(113);
+                buf.push(threshold);
+                for signer in signers.iter() {
+                    buf.extend_from_slice(signer.as_ref());
+                }
+            }
+            Self::SetFeeExemptions(ref exempt_accounts) => {
+                buf.push(114);
+                for account in exempt_accounts.iter() {
+                    buf.extend_from_slice(account.as_ref());
+                }
+            }
+            Self::SetPoolMetadata(SetPoolMetadataData {
+                ref name,
+                ref symbol,
+                ref uri,
+            }) => {
+                buf.push(115);
+                for value in [name, symbol, uri].iter() {
+                    buf.push(value.len() as u8);
+                    buf.extend_from_slice(value.as_bytes());
+                }
+            }
+            Self::CollectAdminFees(CollectAdminFeesData {
+                token_a_amount,
+                token_b_amount,
+            }) => {
+                buf.push(116);
+                buf.extend_from_slice(&token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&token_b_amount.to_le_bytes());
+            }
+            Self::InitPoolStats => buf.push(117),
+            Self::SetDonationsAdminOnly(admin_only) => {
+                buf.push(118);
+                buf.push(admin_only as u8);
+            }
+            Self::SetSplTokenSwapCompat(enabled) => {
+                buf.push(119);
+                buf.push(enabled as u8);
+            }
+            Self::SetPriceOracles(max_deviation_bps) => {
+                buf.push(120);
+                buf.extend_from_slice(&max_deviation_bps.to_le_bytes());
+            }
+            Self::RampFees(RampFeesData {
+                target_fees,
+                stop_ramp_ts,
+            }) => {
+                buf.push(121);
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(&target_fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+                buf.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+            }
+            Self::SetDepositAllowlist(ref allowed_accounts) => {
+                buf.push(122);
+                for account in allowed_accounts.iter() {
+                    buf.extend_from_slice(account.as_ref());
+                }
+            }
+            Self::SetDepositAllowlistEnabled(enabled) => {
+                buf.push(123);
+                buf.push(enabled as u8);
+            }
+            Self::MigratePool => buf.push(124),
+            Self::SetMaxSwapBps(max_swap_bps) => {
+                buf.push(125);
+                buf.extend_from_slice(&max_swap_bps.to_le_bytes());
+            }
+            Self::SetPoolInfo(SetPoolInfoData {
+                ref name,
+                category,
+                is_deprecated,
+            }) => {
+                buf.push(126);
+                buf.push(name.len() as u8);
+                buf.extend_from_slice(name.as_bytes());
+                buf.push(category as u8);
+                buf.push(is_deprecated as u8);
+            }
+            Self::SetRewardsDistributor => buf.push(127),
+            Self::FreezeLp => buf.push(128),
+            Self::ThawLp => buf.push(129),
+            Self::DeprecatePool => buf.push(130),
+            Self::MigrateToSharedAuthority => buf.push(131),
+            Self::SetRejectZeroAmounts(reject) => {
+                buf.push(132);
+                buf.push(reject as u8);
+            }
+            Self::SetStakingConfig(stake_instruction_prefix) => {
+                buf.push(133);
+                buf.extend_from_slice(&stake_instruction_prefix);
+            }
+            Self::RampAPrecise(RampAData {
+                target_amp,
+                stop_ramp_ts,
+            }) => {
+                buf.push(134);
+                buf.extend_from_slice(&target_amp.to_le_bytes());
+                buf.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+            }
+            Self::SetWithdrawFeeGracePeriod(withdraw_fee_grace_period) => {
+                buf.push(135);
+                buf.extend_from_slice(&withdraw_fee_grace_period.to_le_bytes());
+            }
+            Self::SetCpiPolicy(cpi_policy) => {
+                buf.push(136);
+                buf.push(cpi_policy as u8);
+            }
+            Self::SetCpiAllowlist(ref allowed_programs) => {
+                buf.push(137);
+                for program in allowed_programs.iter() {
+                    buf.extend_from_slice(program.as_ref());
+                }
+            }
+            Self::SetPegRateSource(rate_source) => {
+                buf.push(138);
+                buf.push(rate_source as u8);
+            }
+            Self::SetQuickSwapMaxBps(quick_swap_max_bps) => {
+                buf.push(139);
+                buf.extend_from_slice(&quick_swap_max_bps.to_le_bytes());
+            }
+            Self::SetSameSlotGuard(enabled) => {
+                buf.push(140);
+                buf.push(enabled as u8);
+            }
+            Self::SetAdminFeesAsLp(enabled) => {
+                buf.push(141);
+                buf.push(enabled as u8);
+            }
+            Self::SetWithdrawOneWhilePaused(enabled) => {
+                buf.push(142);
+                buf.push(enabled as u8);
+            }
+            Self::SetQuietLogs(enabled) => {
+                buf.push(143);
+                buf.push(enabled as u8);
+            }
+            Self::SetMaxLpSupply(max_lp_supply) => {
+                buf.push(144);
+                buf.extend_from_slice(&max_lp_supply.to_le_bytes());
+            }
+            Self::SetMaxLpPerOwner(SetMaxLpPerOwnerData {
+                max_lp_per_owner,
+                ref exempt_owners,
+            }) => {
+                buf.push(145);
+                buf.extend_from_slice(&max_lp_per_owner.to_le_bytes());
+                for owner in exempt_owners.iter() {
+                    buf.extend_from_slice(owner.as_ref());
+                }
+            }
+            Self::EmergencyAmpDown(divisor) => {
+                buf.push(146);
+                buf.extend_from_slice(&divisor.to_le_bytes());
+            }
         }
         buf
     }
@@ -218,7 +1320,7 @@ pub fn ramp_a(
     .pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
         AccountMeta::new(clock::id(), false),
     ];
@@ -230,6 +1332,81 @@ pub fn ramp_a(
     })
 }
 
+/// Creates a 'ramp_a_precise' instruction
+pub fn ramp_a_precise(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    target_amp: u64,
+    stop_ramp_ts: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::RampAPrecise(RampAData {
+        target_amp,
+        stop_ramp_ts,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_withdraw_fee_grace_period' instruction
+pub fn set_withdraw_fee_grace_period(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    withdraw_fee_grace_period: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetWithdrawFeeGracePeriod(withdraw_fee_grace_period).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'ramp_fees' instruction
+pub fn ramp_fees(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    target_fees: Fees,
+    stop_ramp_ts: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::RampFees(RampFeesData {
+        target_fees,
+        stop_ramp_ts,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
 /// Creates a 'stop_ramp_a' instruction
 pub fn stop_ramp_a(
     program_id: &Pubkey,
@@ -240,7 +1417,7 @@ pub fn stop_ramp_a(
     let data = AdminInstruction::StopRampA.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
         AccountMeta::new(clock::id(), false),
     ];
@@ -252,6 +1429,28 @@ pub fn stop_ramp_a(
     })
 }
 
+/// Creates an 'emergency_amp_down' instruction
+pub fn emergency_amp_down(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    divisor: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::EmergencyAmpDown(divisor).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
 /// Creates a 'pause' instruction
 pub fn pause(
     program_id: &Pubkey,
@@ -261,7 +1460,7 @@ pub fn pause(
     let data = AdminInstruction::Pause.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
     ];
 
@@ -281,7 +1480,7 @@ pub fn unpause(
     let data = AdminInstruction::Unpause.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
     ];
 
@@ -301,7 +1500,7 @@ pub fn apply_new_admin(
     let data = AdminInstruction::ApplyNewAdmin.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
         AccountMeta::new(clock::id(), false),
     ];
@@ -323,7 +1522,7 @@ pub fn commit_new_admin(
     let data = AdminInstruction::CommitNewAdmin.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
         AccountMeta::new(*new_admin_pubkey, false),
         AccountMeta::new(clock::id(), false),
@@ -346,7 +1545,7 @@ pub fn set_fee_account(
     let data = AdminInstruction::SetFeeAccount.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
         AccountMeta::new(*new_fee_account_pubkey, false),
     ];
@@ -368,8 +1567,9 @@ pub fn set_new_fees(
     let data = AdminInstruction::SetNewFees(new_fees).pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(clock::id(), false),
     ];
 
     Ok(Instruction {
@@ -379,57 +1579,1401 @@ pub fn set_new_fees(
     })
 }
 
-/// Instructions supported by the SwapInfo program.
-#[repr(C)]
-#[derive(Debug, PartialEq)]
-#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
-pub enum SwapInstruction {
-    ///   Initializes a new SwapInfo.
-    ///
-    ///   0. `[writable, signer]` New StableSwap to create.
-    ///   1. `[]` $authority derived from `create_program_address(&[StableSwap account])`
-    ///   2. `[]` admin Account.
-    ///   3. `[]` admin_fee_a admin fee Account for token_a.
-    ///   4. `[]` admin_fee_b admin fee Account for token_b.
-    ///   5. `[]` token_a Account. Must be non zero, owned by $authority.
-    ///   6. `[]` token_b Account. Must be non zero, owned by $authority.
-    ///   7. `[writable]` Pool Token Mint. Must be empty, owned by $authority.
-    ///   8. `[]` Clock sysvar
-    Initialize(InitializeData),
+/// Creates a 'set_fee_tiers' instruction
+pub fn set_fee_tiers(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    tiers: [FeeTier; MAX_FEE_TIERS],
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetFeeTiers(tiers).pack();
 
-    ///   Swap the tokens in the pool.
-    ///
-    ///   0. `[]`StableSwap
-    ///   1. `[]` $authority
-    ///   2. `[writable]` token_(A|B) SOURCE Account, amount is transferable by $authority,
-    ///   3. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
-    ///   4. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
-    ///   5. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
-    ///   6. `[writable]` token_(A|B) admin fee Account. Must have same mint as DESTINATION token.
-    ///   7. `[]` Token program id
-    ///   8. `[]` Clock sysvar
-    Swap(SwapData),
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(clock::id(), false),
+    ];
 
-    ///   Deposit some tokens into the pool.  The output is a "pool" token representing ownership
-    ///   into the pool. Inputs are converted to the current ratio.
-    ///
-    ///   0. `[]`StableSwap
-    ///   1. `[]` $authority
-    ///   2. `[writable]` token_a $authority can transfer amount,
-    ///   3. `[writable]` token_b $authority can transfer amount,
-    ///   4. `[writable]` token_a Base Account to deposit into.
-    ///   5. `[writable]` token_b Base Account to deposit into.
-    ///   6. `[writable]` Pool MINT account, $authority is the owner.
-    ///   7. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
-    ///   8. `[]` Token program id
-    ///   9. `[]` Clock sysvar
-    Deposit(DepositData),
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
 
-    ///   Withdraw tokens from the pool at the current ratio.
-    ///
-    ///   0. `[]`StableSwap
-    ///   1. `[]` $authority
-    ///   2. `[writable]` Pool mint account, $authority is the owner
+/// Creates a 'set_fee_mode' instruction
+pub fn set_fee_mode(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    dynamic_fee: DynamicFee,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetFeeMode(dynamic_fee).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'commit_new_fees' instruction
+pub fn commit_new_fees(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    new_fees: Fees,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::CommitNewFees(new_fees).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'apply_new_fees' instruction
+pub fn apply_new_fees(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::ApplyNewFees.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'cancel_pending' instruction
+pub fn cancel_pending(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::CancelPending.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_admin_multisig' instruction
+pub fn set_admin_multisig(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    admin_multisig_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    threshold: u8,
+    signer_pubkeys: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetAdminMultisig(SetAdminMultisigData {
+        threshold,
+        signers: signer_pubkeys.iter().map(|signer| **signer).collect(),
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*admin_multisig_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_fee_exemptions' instruction
+pub fn set_fee_exemptions(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    fee_exemptions_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    exempt_pubkeys: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetFeeExemptions(
+        exempt_pubkeys.iter().map(|account| **account).collect(),
+    )
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*fee_exemptions_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_pool_metadata' instruction
+pub fn set_pool_metadata(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    name: &str,
+    symbol: &str,
+    uri: &str,
+) -> Result<Instruction, ProgramError> {
+    if !metadata::validate(name, symbol, uri) {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let data = AdminInstruction::SetPoolMetadata(SetPoolMetadataData {
+        name: name.to_string(),
+        symbol: symbol.to_string(),
+        uri: uri.to_string(),
+    })
+    .pack();
+
+    let (metadata_pubkey, _bump_seed) = metadata::find_metadata_address(pool_mint_pubkey);
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*pool_mint_pubkey, false),
+        AccountMeta::new(metadata_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(metadata::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'collect_admin_fees' instruction
+pub fn collect_admin_fees(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    admin_fee_a_pubkey: &Pubkey,
+    admin_fee_b_pubkey: &Pubkey,
+    destination_a_pubkey: &Pubkey,
+    destination_b_pubkey: &Pubkey,
+    token_a_amount: u64,
+    token_b_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::CollectAdminFees(CollectAdminFeesData {
+        token_a_amount,
+        token_b_amount,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new(*admin_fee_a_pubkey, false),
+        AccountMeta::new(*admin_fee_b_pubkey, false),
+        AccountMeta::new(*destination_a_pubkey, false),
+        AccountMeta::new(*destination_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Adds the owner of the admin fee accounts to a collect admin fees instruction, for
+/// admin fee accounts the swap authority does not own. The owner signs, e.g. through
+/// the vault program owning
+/// [find_admin_fee_vault_address](../state/fn.find_admin_fee_vault_address.html).
+pub fn with_admin_fee_owner(mut instruction: Instruction, owner_pubkey: &Pubkey) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*owner_pubkey, true));
+    instruction
+}
+
+/// Creates an 'init_pool_stats' instruction
+pub fn init_pool_stats(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    pool_stats_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::InitPoolStats.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*pool_stats_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_donations_admin_only' instruction
+pub fn set_donations_admin_only(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    admin_only: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetDonationsAdminOnly(admin_only).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_spl_token_swap_compat' instruction
+pub fn set_spl_token_swap_compat(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    enabled: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetSplTokenSwapCompat(enabled).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_price_oracles' instruction
+pub fn set_price_oracles(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    price_oracle_a_pubkey: &Pubkey,
+    price_oracle_b_pubkey: &Pubkey,
+    max_deviation_bps: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetPriceOracles(max_deviation_bps).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*price_oracle_a_pubkey, false),
+        AccountMeta::new_readonly(*price_oracle_b_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_deposit_allowlist' instruction
+pub fn set_deposit_allowlist(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    deposit_allowlist_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    allowed_pubkeys: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetDepositAllowlist(
+        allowed_pubkeys.iter().map(|account| **account).collect(),
+    )
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*deposit_allowlist_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_cpi_policy' instruction
+pub fn set_cpi_policy(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    cpi_policy: CpiPolicy,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetCpiPolicy(cpi_policy).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_cpi_allowlist' instruction
+pub fn set_cpi_allowlist(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    cpi_allowlist_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    allowed_program_ids: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetCpiAllowlist(
+        allowed_program_ids
+            .iter()
+            .map(|program| **program)
+            .collect(),
+    )
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*cpi_allowlist_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_quick_swap_max_bps' instruction
+pub fn set_quick_swap_max_bps(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    quick_swap_max_bps: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetQuickSwapMaxBps(quick_swap_max_bps).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_same_slot_guard' instruction
+pub fn set_same_slot_guard(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    enabled: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetSameSlotGuard(enabled).pack();
+
+    let (last_trade_pubkey, _bump_seed) = find_last_trade_address(program_id, swap_pubkey);
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(last_trade_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_admin_fees_as_lp' instruction
+pub fn set_admin_fees_as_lp(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    enabled: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetAdminFeesAsLp(enabled).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_withdraw_one_while_paused' instruction
+pub fn set_withdraw_one_while_paused(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    enabled: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetWithdrawOneWhilePaused(enabled).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_quiet_logs' instruction
+pub fn set_quiet_logs(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    enabled: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetQuietLogs(enabled).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_max_lp_supply' instruction
+pub fn set_max_lp_supply(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    max_lp_supply: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetMaxLpSupply(max_lp_supply).pack();
+
+    let (deposit_caps_pubkey, _bump_seed) = find_deposit_caps_address(program_id, swap_pubkey);
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(deposit_caps_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_peg_rate_source' instruction
+pub fn set_peg_rate_source(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    peg_config_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    rate_source: PegRateSource,
+    rate_account_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetPegRateSource(rate_source).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*peg_config_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(*rate_account_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_deposit_allowlist_enabled' instruction
+pub fn set_deposit_allowlist_enabled(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    enabled: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetDepositAllowlistEnabled(enabled).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_max_swap_bps' instruction
+pub fn set_max_swap_bps(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    max_swap_bps: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetMaxSwapBps(max_swap_bps).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_pool_info' instruction
+pub fn set_pool_info(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    name: &str,
+    category: PoolCategory,
+    is_deprecated: bool,
+) -> Result<Instruction, ProgramError> {
+    if name.len() > MAX_POOL_NAME_LEN {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let data = AdminInstruction::SetPoolInfo(SetPoolInfoData {
+        name: name.to_string(),
+        category,
+        is_deprecated,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(
+            find_pool_registry_entry_address(program_id, swap_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_rewards_distributor' instruction
+pub fn set_rewards_distributor(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    rewards_program_id: &Pubkey,
+    rewards_a_pubkey: &Pubkey,
+    rewards_b_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetRewardsDistributor.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(
+            find_rewards_distributor_address(program_id, swap_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(*rewards_program_id, false),
+        AccountMeta::new_readonly(*rewards_a_pubkey, false),
+        AccountMeta::new_readonly(*rewards_b_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'freeze_lp' instruction
+pub fn freeze_lp(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    admin_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    lp_account_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::FreezeLp.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*pool_mint_pubkey, false),
+        AccountMeta::new(*lp_account_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'thaw_lp' instruction
+pub fn thaw_lp(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    admin_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    lp_account_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::ThawLp.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*pool_mint_pubkey, false),
+        AccountMeta::new(*lp_account_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'deprecate_pool' instruction
+pub fn deprecate_pool(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::DeprecatePool.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'migrate_to_shared_authority' instruction
+pub fn migrate_to_shared_authority(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    admin_fee_a_pubkey: &Pubkey,
+    admin_fee_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::MigrateToSharedAuthority.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(find_shared_authority_address(program_id).0, false),
+        AccountMeta::new(*token_a_pubkey, false),
+        AccountMeta::new(*token_b_pubkey, false),
+        AccountMeta::new(*admin_fee_a_pubkey, false),
+        AccountMeta::new(*admin_fee_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_reject_zero_amounts' instruction
+pub fn set_reject_zero_amounts(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    reject: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetRejectZeroAmounts(reject).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_staking_config' instruction
+pub fn set_staking_config(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    staking_program_id: &Pubkey,
+    staking_pool_pubkey: &Pubkey,
+    stake_instruction_prefix: [u8; 8],
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::SetStakingConfig(stake_instruction_prefix).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(
+            find_staking_config_address(program_id, swap_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(*staking_program_id, false),
+        AccountMeta::new_readonly(*staking_pool_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'migrate_pool' instruction
+pub fn migrate_pool(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    new_swap_pubkey: &Pubkey,
+    new_swap_authority_key: &Pubkey,
+    new_swap_token_a_pubkey: &Pubkey,
+    new_swap_token_b_pubkey: &Pubkey,
+    new_pool_mint_pubkey: &Pubkey,
+    lp_escrow_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AdminInstruction::MigratePool.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new_readonly(*pool_mint_pubkey, false),
+        AccountMeta::new(*new_swap_pubkey, false),
+        AccountMeta::new_readonly(*new_swap_authority_key, false),
+        AccountMeta::new(*new_swap_token_a_pubkey, false),
+        AccountMeta::new(*new_swap_token_b_pubkey, false),
+        AccountMeta::new(*new_pool_mint_pubkey, false),
+        AccountMeta::new(*lp_escrow_pubkey, false),
+        AccountMeta::new(
+            find_pool_migration_address(program_id, swap_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Locks `min_liquidity` of the pool tokens minted by an initialize instruction in
+/// `locked_lp_pubkey`, a pool token account owned by
+/// [find_locked_liquidity_address](../state/fn.find_locked_liquidity_address.html).
+/// The bootstrapper receives the remaining pool tokens.
+pub fn with_locked_liquidity(
+    mut instruction: Instruction,
+    locked_lp_pubkey: &Pubkey,
+    min_liquidity: u64,
+) -> Instruction {
+    let mut len = instruction.data.len();
+    // Initialize ends with the curve type, after the locked liquidity
+    if instruction.data.first() == Some(&0) {
+        len -= 1;
+    }
+    instruction.data[len - 8..len].copy_from_slice(&min_liquidity.to_le_bytes());
+    instruction
+        .accounts
+        .push(AccountMeta::new(*locked_lp_pubkey, false));
+    instruction
+}
+
+/// Turns an initialize instruction into an
+/// [InitializeWithRamp](enum.SwapInstruction.html#variant.InitializeWithRamp), the pool
+/// ramping from the amplification coefficient of the instruction to `target_amp_factor`
+/// over `ramp_duration` seconds. Composes with [with_locked_liquidity] in either order.
+/// Fails for pools of another curve type than StableSwap, see [with_curve_type].
+pub fn with_amp_ramp(
+    mut instruction: Instruction,
+    target_amp_factor: u64,
+    ramp_duration: i64,
+) -> Result<Instruction, ProgramError> {
+    let InitializeData {
+        nonce,
+        amp_factor,
+        fees,
+        min_liquidity,
+        curve_type,
+    } = match SwapInstruction::unpack(&instruction.data)? {
+        SwapInstruction::Initialize(data) => data,
+        _ => return Err(SwapError::InvalidInstruction.into()),
+    };
+    if curve_type != CurveType::StableSwap {
+        return Err(SwapError::InvalidInstruction.into());
+    }
+    instruction.data = SwapInstruction::InitializeWithRamp(InitializeWithRampData {
+        nonce,
+        initial_amp_factor: amp_factor,
+        target_amp_factor,
+        ramp_duration,
+        fees,
+        min_liquidity,
+    })
+    .pack();
+    Ok(instruction)
+}
+
+/// Sets the invariant of the pool an initialize instruction creates. Pools ramping from
+/// initialization, see [with_amp_ramp], are StableSwap pools.
+pub fn with_curve_type(
+    mut instruction: Instruction,
+    curve_type: CurveType,
+) -> Result<Instruction, ProgramError> {
+    let data = match SwapInstruction::unpack(&instruction.data)? {
+        SwapInstruction::Initialize(data) => data,
+        _ => return Err(SwapError::InvalidInstruction.into()),
+    };
+    instruction.data = SwapInstruction::Initialize(InitializeData { curve_type, ..data }).pack();
+    Ok(instruction)
+}
+
+/// Adds the fee exemption list of the swap to a deposit or withdraw instruction,
+/// so that fees are waived if its user authority is exempt.
+pub fn with_fee_exemptions(
+    mut instruction: Instruction,
+    fee_exemptions_pubkey: &Pubkey,
+) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*fee_exemptions_pubkey, false));
+    instruction
+}
+
+/// Adds the statistics account of a swap to a swap, deposit or withdraw instruction,
+/// so that the activity of the instruction is recorded in it.
+pub fn with_pool_stats(mut instruction: Instruction, pool_stats_pubkey: &Pubkey) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new(*pool_stats_pubkey, false));
+    instruction
+}
+
+/// Adds the price oracles registered by a swap with SetPriceOracles to a swap
+/// or swap route instruction, so that its price can be checked against them.
+pub fn with_price_oracles(
+    mut instruction: Instruction,
+    price_oracle_a_pubkey: &Pubkey,
+    price_oracle_b_pubkey: &Pubkey,
+) -> Instruction {
+    for price_oracle_pubkey in [price_oracle_a_pubkey, price_oracle_b_pubkey] {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(*price_oracle_pubkey, false));
+    }
+    instruction
+}
+
+/// Adds a host fee account to a swap instruction. It receives the `host_fee_bps`
+/// share of the admin trade fee, in the destination token.
+pub fn with_host_fee(mut instruction: Instruction, host_fee_pubkey: &Pubkey) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new(*host_fee_pubkey, false));
+    instruction
+}
+
+/// Adds the accounts needed to create the missing associated token accounts of the
+/// user authority to a deposit or withdraw instruction. The user authority pays for
+/// the new accounts. Withdrawals must also pass the mints of tokens A and B.
+pub fn with_associated_token_accounts(
+    mut instruction: Instruction,
+    mint_pubkeys: &[&Pubkey],
+) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(associated_token::id(), false));
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    for mint_pubkey in mint_pubkeys {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(**mint_pubkey, false));
+    }
+    instruction
+}
+
+/// Adds the accounts needed to wrap and unwrap native SOL to a swap or deposit
+/// instruction. Source token accounts of the native mint are funded with lamports of
+/// the user authority before the transfer into the pool, and the wrapped SOL accounts
+/// of the user are closed to the user authority afterwards, so these should be
+/// temporary accounts owned by the user authority. The user authority becomes writable.
+pub fn with_native_sol(
+    mut instruction: Instruction,
+    user_authority_pubkey: &Pubkey,
+) -> Instruction {
+    for account_meta in instruction.accounts.iter_mut() {
+        if account_meta.pubkey == *user_authority_pubkey {
+            account_meta.is_writable = true;
+        }
+    }
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    instruction.accounts.push(AccountMeta::new_readonly(
+        spl_token::native_mint::id(),
+        false,
+    ));
+    instruction
+}
+
+/// Adds the LP position of the user authority to a deposit or withdraw instruction,
+/// so that the fees earned by its pool tokens are checkpointed. Withdrawals of a
+/// position older than the withdraw fee grace period of the swap pay no withdraw fee,
+/// see [AdminInstruction::SetWithdrawFeeGracePeriod].
+pub fn with_lp_position(
+    mut instruction: Instruction,
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    user_authority_key: &Pubkey,
+) -> Instruction {
+    let (lp_position_pubkey, _bump_seed) =
+        find_lp_position_address(program_id, swap_pubkey, user_authority_key);
+    instruction
+        .accounts
+        .push(AccountMeta::new(lp_position_pubkey, false));
+    instruction
+}
+
+/// Adds the deposit allowlist of the swap to a deposit or zap in instruction,
+/// required once the swap enabled its deposit allowlist.
+pub fn with_deposit_allowlist(
+    mut instruction: Instruction,
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+) -> Instruction {
+    let (deposit_allowlist_pubkey, _bump_seed) =
+        find_deposit_allowlist_address(program_id, swap_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(deposit_allowlist_pubkey, false));
+    instruction
+}
+
+/// Creates a 'set_max_lp_per_owner' instruction
+pub fn set_max_lp_per_owner(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    max_lp_per_owner: u64,
+    exempt_owners: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    if exempt_owners.len() > MAX_DEPOSIT_CAP_EXEMPT_OWNERS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let data = AdminInstruction::SetMaxLpPerOwner(SetMaxLpPerOwnerData {
+        max_lp_per_owner,
+        exempt_owners: exempt_owners.iter().map(|owner| **owner).collect(),
+    })
+    .pack();
+
+    let (deposit_caps_pubkey, _bump_seed) = find_deposit_caps_address(program_id, swap_pubkey);
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(deposit_caps_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Adds the deposit caps of the swap to a deposit or zap in instruction, required
+/// while the swap caps its liquidity, see [AdminInstruction::SetMaxLpSupply] and
+/// [AdminInstruction::SetMaxLpPerOwner].
+pub fn with_deposit_caps(
+    mut instruction: Instruction,
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+) -> Instruction {
+    let (deposit_caps_pubkey, _bump_seed) = find_deposit_caps_address(program_id, swap_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(deposit_caps_pubkey, false));
+    instruction
+}
+
+/// Adds the instructions sysvar and the CPI allowlist of the swap to a swap, deposit,
+/// single-token withdrawal or zap instruction, required once the swap restricted its
+/// callers, see [AdminInstruction::SetCpiPolicy].
+pub fn with_cpi_guard(
+    mut instruction: Instruction,
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+) -> Instruction {
+    let (cpi_allowlist_pubkey, _bump_seed) = find_cpi_allowlist_address(program_id, swap_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(sysvar::instructions::id(), false));
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(cpi_allowlist_pubkey, false));
+    instruction
+}
+
+/// Adds the last trade of the swap to a swap instruction, required once the swap
+/// enabled its same-slot guard, see [AdminInstruction::SetSameSlotGuard].
+pub fn with_same_slot_guard(
+    mut instruction: Instruction,
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+) -> Instruction {
+    let (last_trade_pubkey, _bump_seed) = find_last_trade_address(program_id, swap_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new(last_trade_pubkey, false));
+    instruction
+}
+
+/// Adds the pool mint and the pool token account of the admin to a swap or withdraw
+/// instruction, required once the swap mints its admin fees as pool tokens, see
+/// [AdminInstruction::SetAdminFeesAsLp]. The admin pool token account must be owned
+/// by the owner of the admin fee account of the instruction, of token A for a
+/// withdrawal.
+pub fn with_admin_fees_as_lp(
+    mut instruction: Instruction,
+    pool_mint_pubkey: &Pubkey,
+    admin_pool_token_pubkey: &Pubkey,
+) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new(*pool_mint_pubkey, false));
+    instruction
+        .accounts
+        .push(AccountMeta::new(*admin_pool_token_pubkey, false));
+    instruction
+}
+
+/// Adds the staking accounts to a deposit instruction, so that the minted pool
+/// tokens are staked into the staking pool set with SetStakingConfig. The staking
+/// program is invoked with the user authority signing, its accounts followed by
+/// `staking_account_metas`. Must be added after any other optional account.
+pub fn with_stake_on_deposit(
+    mut instruction: Instruction,
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    staking_program_id: &Pubkey,
+    staking_pool_pubkey: &Pubkey,
+    staking_account_metas: &[AccountMeta],
+) -> Instruction {
+    let (staking_config_pubkey, _bump_seed) = find_staking_config_address(program_id, swap_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(staking_config_pubkey, false));
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*staking_program_id, false));
+    instruction
+        .accounts
+        .push(AccountMeta::new(*staking_pool_pubkey, false));
+    instruction
+        .accounts
+        .extend_from_slice(staking_account_metas);
+    instruction
+}
+
+/// Turns an admin instruction signed by a single admin key into one
+/// authorized by the signers of an admin multisig.
+pub fn with_admin_multisig_signers(
+    mut instruction: Instruction,
+    signer_pubkeys: &[&Pubkey],
+) -> Instruction {
+    if let Some(admin_meta) = instruction.accounts.get_mut(1) {
+        admin_meta.is_signer = false;
+    }
+    instruction.accounts.extend(
+        signer_pubkeys
+            .iter()
+            .map(|signer| AccountMeta::new_readonly(**signer, true)),
+    );
+    instruction
+}
+
+/// Instructions supported by the SwapInfo program.
+///
+/// The clock sysvar may be omitted from any instruction, see [omit_clock_sysvar].
+///
+/// Swaps, deposits, donations and withdrawals whose amounts are all zero succeed
+/// without checking their accounts and log a Noop event, unless the swap rejects them
+/// with ZeroAmount, see [AdminInstruction::SetRejectZeroAmounts].
+///
+/// Swaps whose CPI policy restricts their callers also need the accounts added by
+/// [with_cpi_guard] in swaps, deposits, single-token withdrawals and zaps.
+///
+/// Swaps with the same-slot guard enabled also need the account added by
+/// [with_same_slot_guard] in swaps.
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum SwapInstruction {
+    ///   Initializes a new SwapInfo.
+    ///
+    ///   0. `[writable, signer]` New StableSwap to create.
+    ///   1. `[]` $authority derived from `create_program_address(&[StableSwap account])`
+    ///   2. `[]` admin Account.
+    ///   3. `[]` admin_fee_a admin fee Account for token_a.
+    ///   4. `[]` admin_fee_b admin fee Account for token_b.
+    ///   5. `[]` token_a Account. Must be non zero, owned by $authority.
+    ///   6. `[]` token_b Account. Must be non zero, owned by $authority.
+    ///   7. `[writable]` Pool Token Mint. Must be empty, owned by $authority. Its
+    ///      freeze authority must be unset, or $authority to allow FreezeLp/ThawLp.
+    ///   8. `[]` Clock sysvar
+    ///
+    ///   $authority may instead be the program-wide authority of
+    ///   [find_shared_authority_address](../state/fn.find_shared_authority_address.html),
+    ///   with its bump seed as the nonce. The token accounts and the Pool Token Mint are
+    ///   then owned by a signer passed after the Clock sysvar, and handed over to
+    ///   $authority by the instruction, so that no other pool can claim them. Admin fee
+    ///   accounts must not be owned by $authority. See
+    ///   [initialize_with_shared_authority](fn.initialize_with_shared_authority.html).
+    ///
+    ///   If `min_liquidity` is set, that many pool tokens are minted to a Pool Token
+    ///   account owned by
+    ///   [find_locked_liquidity_address](../state/fn.find_locked_liquidity_address.html),
+    ///   passed last. See [with_locked_liquidity](fn.with_locked_liquidity.html).
+    ///
+    ///   The pool trades on the invariant of `curve_type`, stored in the SwapInfo. See
+    ///   [with_curve_type](fn.with_curve_type.html).
+    Initialize(InitializeData),
+
+    ///   Swap the tokens in the pool.
+    ///
+    ///   0. `[writable]` StableSwap, to record the LP fee growth
+    ///   1. `[]` $authority
+    ///   2. `[writable]` token_(A|B) SOURCE Account, amount is transferable by $authority,
+    ///   3. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   4. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   5. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   6. `[writable]` token_(A|B) admin fee Account. Must have same mint as DESTINATION token.
+    ///   7. `[]` Token program id
+    ///   8. `[]` Clock sysvar
+    ///   9. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   10. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   11. `[]` Price oracle of token A, required if the swap set price oracles,
+    ///       see [with_price_oracles](fn.with_price_oracles.html)
+    ///   12. `[]` Price oracle of token B, required if the swap set price oracles
+    ///   13. `[writable]` Optional host fee Account, receiving the host share of the admin fee.
+    ///       Must have same mint as DESTINATION token, see [with_host_fee](fn.with_host_fee.html)
+    ///   14. `[]` Optional system program, to wrap and unwrap native SOL,
+    ///       see [with_native_sol](fn.with_native_sol.html)
+    ///   15. `[]` Optional native mint, required with the system program. A wrapped SOL
+    ///       SOURCE Account is funded with the lamports of the user, and wrapped SOL SOURCE
+    ///       and DESTINATION Accounts are closed to the user after the swap.
+    ///
+    ///   If the swap enabled SetSplTokenSwapCompat, the accounts of an spl-token-swap
+    ///   `Swap` are accepted as well, see [spl_token_swap_swap](fn.spl_token_swap_swap.html).
+    ///   The global state must follow the token program id there. A host fee Account
+    ///   passed after it that does not hold the DESTINATION token is ignored. These swaps
+    ///   do not record the LP fee growth, since the StableSwap is not writable.
+    Swap(SwapData),
+
+    ///   Deposit some tokens into the pool.  The output is a "pool" token representing ownership
+    ///   into the pool. Inputs are converted to the current ratio.
+    ///   The token_(A|B) Base Accounts and the Pool MINT are matched by key, so they
+    ///   may be passed in each other's positions.
+    ///
+    ///   0. `[]`StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[writable]` token_a $authority can transfer amount,
+    ///   3. `[writable]` token_b $authority can transfer amount,
+    ///   4. `[writable]` token_a Base Account to deposit into.
+    ///   5. `[writable]` token_b Base Account to deposit into.
+    ///   6. `[writable]` Pool MINT account, $authority is the owner.
+    ///   7. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
+    ///   8. `[]` Token program id
+    ///   9. `[]` Clock sysvar
+    ///   10. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   11. `[]` Optional fee exemption list, see [with_fee_exemptions](fn.with_fee_exemptions.html)
+    ///   12. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   13. `[]` Optional associated token account program, to create the pool account if missing,
+    ///       see [with_associated_token_accounts](fn.with_associated_token_accounts.html)
+    ///   14. `[]` Optional system program, required with the associated token account program
+    ///   15. `[writable]` Optional LP position of the user authority, see [with_lp_position](fn.with_lp_position.html)
+    ///   16. `[]` Deposit allowlist, required if the swap enabled it, see [with_deposit_allowlist](fn.with_deposit_allowlist.html)
+    ///   17. `[]` Optional native mint, with the system program, to fund wrapped SOL token_(a|b)
+    ///       Accounts with the lamports of the user and close them to the user after the deposit,
+    ///       see [with_native_sol](fn.with_native_sol.html)
+    ///   18. `[]` Optional staking config, to stake the minted pool tokens, followed by the staking
+    ///       program, the staking pool and the accounts passed on to the staking program.
+    ///       These come after any other optional account, see [with_stake_on_deposit](fn.with_stake_on_deposit.html)
+    Deposit(DepositData),
+
+    ///   Withdraw tokens from the pool at the current ratio.
+    ///   The token_(A|B) Swap Accounts, the admin fee Accounts and the Pool mint are
+    ///   matched by key, so they may be passed in each other's positions.
+    ///   The user transfer authority may be the delegate of the SOURCE Pool account
+    ///   rather than its owner, withdrawing up to the delegated amount. The token_(A|B)
+    ///   user Accounts must then be owned by the owner of the SOURCE Pool account.
+    ///
+    ///   0. `[]`StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[writable]` Pool mint account, $authority is the owner
     ///   3. `[writable]` SOURCE Pool account, amount is transferable by $authority.
     ///   4. `[writable]` token_a Swap Account to withdraw FROM.
     ///   5. `[writable]` token_b Swap Account to withdraw FROM.
@@ -439,175 +2983,1688 @@ pub enum SwapInstruction {
     ///   9. `[writable]` admin_fee_b admin fee Account for token_b.
     ///   10. `[]` Token program id
     ///   11. `[]` Clock sysvar
+    ///   12. `[]` Optional fee exemption list, see [with_fee_exemptions](fn.with_fee_exemptions.html)
+    ///   13. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   14. `[]` Optional associated token account program, to create the token_(A|B) user
+    ///       Accounts if missing, see [with_associated_token_accounts](fn.with_associated_token_accounts.html)
+    ///   15. `[]` Optional system program, required with the associated token account program
+    ///   16. `[]` Optional token_a mint, required with the associated token account program
+    ///   17. `[]` Optional token_b mint, required with the associated token account program
+    ///   18. `[writable]` Optional LP position of the user authority, see [with_lp_position](fn.with_lp_position.html)
     Withdraw(WithdrawData),
 
-    ///   Withdraw one token from the pool at the current ratio.
-    ///
-    ///   0. `[]`StableSwap
-    ///   1. `[]` $authority
-    ///   2. `[writable]` Pool mint account, $authority is the owner
-    ///   3. `[writable]` SOURCE Pool account, amount is transferable by $authority.
-    ///   4. `[writable]` token_(A|B) BASE token Swap Account to withdraw FROM.
-    ///   5. `[writable]` token_(A|B) QUOTE token Swap Account to exchange to base token.
-    ///   6. `[writable]` token_(A|B) BASE token user Account to credit.
-    ///   7. `[writable]` token_(A|B) admin fee Account. Must have same mint as BASE token.
-    ///   8. `[]` Token program id
-    ///   9. `[]` Clock sysvar
-    WithdrawOne(WithdrawOneData),
+    ///   Withdraw one token from the pool at the current ratio.
+    ///   As with Withdraw, the user transfer authority may be the delegate of the SOURCE
+    ///   Pool account, crediting a BASE token user Account of its owner.
+    ///   Fails while the swap is paused, unless the admin keeps it open with
+    ///   [SetWithdrawOneWhilePaused](enum.AdminInstruction.html#variant.SetWithdrawOneWhilePaused),
+    ///   at twice the trade and withdraw fees.
+    ///
+    ///   0. `[]`StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[writable]` Pool mint account, $authority is the owner
+    ///   3. `[writable]` SOURCE Pool account, amount is transferable by $authority.
+    ///   4. `[writable]` token_(A|B) BASE token Swap Account to withdraw FROM.
+    ///   5. `[writable]` token_(A|B) QUOTE token Swap Account to exchange to base token.
+    ///   6. `[writable]` token_(A|B) BASE token user Account to credit.
+    ///   7. `[writable]` token_(A|B) admin fee Account. Must have same mint as BASE token.
+    ///   8. `[]` Token program id
+    ///   9. `[]` Clock sysvar
+    ///   10. `[]` Optional fee exemption list, see [with_fee_exemptions](fn.with_fee_exemptions.html)
+    ///   11. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   12. `[writable]` Optional LP position of the user authority, see [with_lp_position](fn.with_lp_position.html)
+    WithdrawOne(WithdrawOneData),
+
+    ///   Applies a completed ramp of A, setting the initial A to the target A
+    ///   and clearing the ramp timestamps. Anyone can call this once the ramp has stopped.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[]` Clock sysvar
+    SyncAmp,
+
+    ///   Swap through two pools sharing an intermediate mint, A -> B -> C.
+    ///   The intermediate tokens are transferred directly between the pools' reserves
+    ///   and `minimum_amount_out` applies to the final amount only.
+    ///
+    ///   0. `[signer]` User authority
+    ///   1. `[writable]` token_A SOURCE Account, amount is transferable by user authority.
+    ///   2. `[writable]` token_C DESTINATION Account assigned to USER as the owner.
+    ///   3. `[]` Token program id
+    ///   4. `[]` Clock sysvar
+    ///   5. `[writable]` First StableSwap
+    ///   6. `[]` First $authority
+    ///   7. `[writable]` First token_A Base Account to swap INTO.
+    ///   8. `[writable]` First token_B Base Account to swap FROM.
+    ///   9. `[writable]` First token_B admin fee Account.
+    ///   10. `[writable]` Second StableSwap
+    ///   11. `[]` Second $authority
+    ///   12. `[writable]` Second token_B Base Account to swap INTO.
+    ///   13. `[writable]` Second token_C Base Account to swap FROM.
+    ///   14. `[writable]` Second token_C admin fee Account.
+    ///   15. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   16. `[writable]` Optional pool statistics of either pool, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   17. `[writable]` Optional pool statistics of the other pool
+    ///
+    ///   The price oracles of pools that set them must be passed after the global state,
+    ///   see [with_price_oracles](fn.with_price_oracles.html).
+    SwapRoute(SwapData),
+
+    ///   Withdraw an exact amount of one token from the pool, burning as few
+    ///   pool tokens as needed. Takes the same accounts as WithdrawOne.
+    ///
+    ///   0. `[]`StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[writable]` Pool mint account, $authority is the owner
+    ///   3. `[writable]` SOURCE Pool account, amount is transferable by $authority.
+    ///   4. `[writable]` token_(A|B) BASE token Swap Account to withdraw FROM.
+    ///   5. `[writable]` token_(A|B) QUOTE token Swap Account to exchange to base token.
+    ///   6. `[writable]` token_(A|B) BASE token user Account to credit.
+    ///   7. `[writable]` token_(A|B) admin fee Account. Must have same mint as BASE token.
+    ///   8. `[]` Token program id
+    ///   9. `[]` Clock sysvar
+    ///   10. `[]` Optional fee exemption list, see [with_fee_exemptions](fn.with_fee_exemptions.html)
+    ///   11. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   12. `[writable]` Optional LP position of the user authority, see [with_lp_position](fn.with_lp_position.html)
+    WithdrawOneExactOut(WithdrawOneExactOutData),
+
+    ///   Creates the global state of the program or replaces its emergency authority.
+    ///   Only the upgrade authority of the program can call this. Swaps, deposits,
+    ///   zaps, donations and orders fail until the global state is created.
+    ///
+    ///   0. `[writable]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   1. `[signer]` Program upgrade authority
+    ///   2. `[]` Program data account of the program
+    ///   3. `[]` New emergency authority
+    ///   4. `[writable, signer]` Payer of the global state account rent
+    ///   5. `[]` System program
+    ///   6. `[]` Rent sysvar
+    SetEmergencyAuthority,
+
+    ///   Pauses swaps and deposits of every pool of the program.
+    ///
+    ///   0. `[writable]` Global state
+    ///   1. `[signer]` Emergency authority
+    GlobalPause,
+
+    ///   Lifts a [GlobalPause](enum.SwapInstruction.html).
+    ///
+    ///   0. `[writable]` Global state
+    ///   1. `[signer]` Emergency authority
+    GlobalUnpause,
+
+    ///   Adds tokens to the reserves without minting pool tokens, which raises the
+    ///   value of every pool token. Restricted to the admin if the swap was
+    ///   configured with SetDonationsAdminOnly.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[signer]` Donor, or the admin account if donations are admin only
+    ///   2. `[writable]` token_a SOURCE Account, amount is transferable by the donor
+    ///   3. `[writable]` token_b SOURCE Account, amount is transferable by the donor
+    ///   4. `[writable]` token_a Base Account to donate into
+    ///   5. `[writable]` token_b Base Account to donate into
+    ///   6. `[]` Token program id
+    ///   7. `[]` Clock sysvar
+    ///   8. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   9. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    DonateToReserves(DonateToReservesData),
+
+    ///   Creates the LP position of an owner if needed, accrues the fees earned by its
+    ///   checkpointed pool tokens and checkpoints the balance of the given pool token account.
+    ///   Pool tokens added to the position since its last checkpoint are dated now.
+    ///   Deposits and withdrawals passed the position update it as well,
+    ///   see [with_lp_position](fn.with_lp_position.html).
+    ///
+    ///   0. `[]` StableSwap
+    ///   1. `[]` Owner of the position
+    ///   2. `[writable]` LP position, from [find_lp_position_address](../state/fn.find_lp_position_address.html)
+    ///   3. `[]` Pool token account of the owner
+    ///   4. `[writable, signer]` Payer of the position account rent
+    ///   5. `[]` System program
+    ///   6. `[]` Rent sysvar
+    SyncLpPosition,
+
+    ///   Deposit a single token into the pool. The part of the input that makes the
+    ///   deposit proportional to the reserves is swapped first, then both sides are
+    ///   deposited, minting pool tokens in one instruction.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[signer]` User authority
+    ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user authority.
+    ///   4. `[writable]` token_a Base Account to deposit into.
+    ///   5. `[writable]` token_b Base Account to deposit into.
+    ///   6. `[writable]` Pool MINT account, $authority is the owner.
+    ///   7. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
+    ///   8. `[writable]` token_(A|B) admin fee Account of the other token, receives the swap admin fee.
+    ///   9. `[]` Token program id
+    ///   10. `[]` Clock sysvar
+    ///   11. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   12. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ///   13. `[]` Deposit allowlist, required if the swap enabled it, see [with_deposit_allowlist](fn.with_deposit_allowlist.html)
+    ZapIn(ZapInData),
+
+    ///   Withdraw a single token from the pool. Both tokens are withdrawn at the
+    ///   current ratio, then the unwanted token is swapped into the wanted one,
+    ///   with `minimum_amount_out` bounding the total received.
+    ///   As with Withdraw, the user authority may be the delegate of the SOURCE Pool
+    ///   account, crediting a user Account of its owner.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[signer]` User authority
+    ///   3. `[writable]` Pool mint account, $authority is the owner
+    ///   4. `[writable]` SOURCE Pool account, amount is transferable by user authority.
+    ///   5. `[writable]` token_a Swap Account to withdraw FROM.
+    ///   6. `[writable]` token_b Swap Account to withdraw FROM.
+    ///   7. `[writable]` token_(A|B) user Account of the wanted token to credit.
+    ///   8. `[writable]` admin_fee_a admin fee Account for token_a.
+    ///   9. `[writable]` admin_fee_b admin fee Account for token_b.
+    ///   10. `[]` Token program id
+    ///   11. `[]` Clock sysvar
+    ///   12. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   13. `[writable]` Optional pool statistics, see [with_pool_stats](fn.with_pool_stats.html)
+    ZapOut(ZapOutData),
+
+    ///   Redeem pool tokens of a swap migrated with MigratePool for pool tokens
+    ///   of the new swap, in proportion to the escrowed new pool tokens.
+    ///   As with Withdraw, the user authority may be the delegate of the SOURCE Pool
+    ///   account, crediting a new pool token Account of its owner.
+    ///
+    ///   0. `[writable]` StableSwap, migrated
+    ///   1. `[]` $authority
+    ///   2. `[signer]` User authority
+    ///   3. `[writable]` Pool mint account, $authority is the owner
+    ///   4. `[writable]` SOURCE Pool account, amount is transferable by user authority.
+    ///   5. `[writable]` Escrow account of the new pool tokens, $authority is the owner
+    ///   6. `[writable]` Account of the new pool tokens to credit.
+    ///   7. `[]` Pool migration, from [find_pool_migration_address](../state/fn.find_pool_migration_address.html)
+    ///   8. `[]` Token program id
+    RedeemMigratedLp(RedeemMigratedLpData),
+
+    ///   Transfers the whole balance of a token account of $authority which is not a
+    ///   reserve of the swap, e.g. tokens sent to the swap authority by mistake,
+    ///   to the admin fee account of its token. Anyone may call it. Not available to
+    ///   swaps with a shared authority.
+    ///
+    ///   0. `[]` StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[writable]` token_(A|B) stranded Account, $authority is the owner.
+    ///   3. `[writable]` token_(A|B) admin fee Account of the stranded token.
+    ///   4. `[]` Token program id
+    RecoverStrandedTokens,
+
+    ///   Swap several amounts in the same direction, each fill being quoted against
+    ///   the reserves left by the previous one and checked against its own minimum
+    ///   amount out. Takes the same accounts as Swap.
+    SwapBatch(Vec<SwapData>),
+
+    ///   Transfers the whole balance of the admin fee accounts to the token accounts
+    ///   of the rewards distributor set with SetRewardsDistributor, then notifies the
+    ///   rewards distributor program. Anyone may call it.
+    ///
+    ///   The rewards distributor program is invoked with $authority, not as a signer,
+    ///   the token_a and token_b rewards accounts and the token program, followed by
+    ///   the remaining accounts of this instruction, which must not be the reserves.
+    ///   Its instruction data is the token_a then token_b amounts harvested, as little
+    ///   endian u64.
+    ///
+    ///   0. `[]` StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[writable]` admin_fee_a admin fee Account for token_a, $authority is the owner.
+    ///   3. `[writable]` admin_fee_b admin fee Account for token_b, $authority is the owner.
+    ///   4. `[]` Rewards distributor, from [find_rewards_distributor_address](../state/fn.find_rewards_distributor_address.html)
+    ///   5. `[writable]` token_a rewards Account of the rewards distributor.
+    ///   6. `[writable]` token_b rewards Account of the rewards distributor.
+    ///   7. `[]` Rewards distributor program
+    ///   8. `[]` Token program id
+    ///   9. `[]` Clock sysvar
+    ///   10. ..10+N `[]` Accounts passed on to the rewards distributor program
+    HarvestFees,
+
+    ///   Withdraw both tokens strictly pro-rata to the reserve balances, without fees
+    ///   and without the curve math, so LPs can exit even if the invariant cannot be
+    ///   computed. Only while the pool is paused. The token_(A|B) Swap Accounts and
+    ///   the Pool mint are matched by key, so they may be passed in each other's positions.
+    ///   A delegate of the SOURCE Pool account credits token_(a|b) user Accounts of its owner.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[]` $authority
+    ///   2. `[signer]` User authority, owner or delegate of the SOURCE Pool account.
+    ///   3. `[writable]` Pool mint account, $authority is the owner
+    ///   4. `[writable]` SOURCE Pool account, amount is transferable by the user authority.
+    ///   5. `[writable]` token_a Swap Account to withdraw FROM.
+    ///   6. `[writable]` token_b Swap Account to withdraw FROM.
+    ///   7. `[writable]` token_a user Account to credit.
+    ///   8. `[writable]` token_b user Account to credit.
+    ///   9. `[]` Token program id
+    EmergencyWithdraw(WithdrawData),
+
+    ///   Records the balances of the reserves as the reserve amounts of the swap, logging
+    ///   how much they grew since the last instruction that moved them, i.e. the tokens
+    ///   transferred to the reserves directly. Anyone may call it. Not available to
+    ///   swaps in the legacy layout, which have no room for the reserve amounts.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[]` token_a Swap Account.
+    ///   2. `[]` token_b Swap Account.
+    ///   3. `[]` Clock sysvar
+    SyncReserves,
+
+    ///   Reads the peg rate of the swap from the rate account of its peg config, see
+    ///   [AdminInstruction::SetPegRateSource]. Anyone may call it. Fails if the rate is
+    ///   stale: a Pyth price older than the max price age, or a stake pool not yet
+    ///   updated this epoch.
+    ///
+    ///   0. `[writable]` StableSwap
+    ///   1. `[writable]` Peg config, program address from [find_peg_config_address](../state/fn.find_peg_config_address.html)
+    ///   2. `[]` Rate account of the peg config
+    ///   3. `[]` Clock sysvar
+    RefreshPegRate,
+
+    ///   Swap up to `amount_in`, filling only the largest part of it that stays within
+    ///   the max swap size of the swap and pays at least the limit price
+    ///   `minimum_amount_out / amount_in`. The rest is left in the SOURCE account.
+    ///   Fails like a Swap if no part can be filled. The filled amounts are written to
+    ///   the return data as a [PartialSwapReturnData](../return_data/struct.PartialSwapReturnData.html).
+    ///   Takes the same accounts as Swap.
+    SwapAllowPartial(SwapData),
+
+    ///   Creates a limit order selling `amount_in` of a token of the swap for at least
+    ///   `minimum_amount_out` of the other. The tokens sold are escrowed in a token account
+    ///   owned by the order until the order is filled, see FillOrder, or cancelled, see
+    ///   CancelOrder.
+    ///
+    ///   0. `[]` StableSwap
+    ///   1. `[signer]` Owner of the order
+    ///   2. `[writable]` Order, from [find_order_address](../state/fn.find_order_address.html)
+    ///   3. `[writable]` Escrow token_(A|B) Account, empty and owned by the order
+    ///   4. `[writable]` token_(A|B) SOURCE Account of the escrowed token, amount is
+    ///      transferable by the owner
+    ///   5. `[]` token_(A|B) DESTINATION Account receiving the other token when the order fills
+    ///   6. `[]` Token program id
+    ///   7. `[writable, signer]` Payer of the order account rent
+    ///   8. `[]` System program
+    ///   9. `[]` Rent sysvar
+    ///   10. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    CreateOrder(CreateOrderData),
+
+    ///   Fills an order in full against the curve, paying the normal trade fee, if the
+    ///   swap pays at least its limit price. Anyone may call it. The order and its escrow
+    ///   are closed to the owner of the order.
+    ///
+    ///   0. `[writable]` StableSwap, to record the LP fee growth
+    ///   1. `[]` $authority
+    ///   2. `[writable]` Order
+    ///   3. `[writable]` Escrow token_(A|B) Account of the order
+    ///   4. `[writable]` token_(A|B) Base Account to swap INTO. Must be the escrowed token.
+    ///   5. `[writable]` token_(A|B) Base Account to swap FROM.
+    ///   6. `[writable]` token_(A|B) DESTINATION Account of the order
+    ///   7. `[writable]` token_(A|B) admin fee Account. Must have same mint as DESTINATION token.
+    ///   8. `[writable]` Owner of the order, receiving the rent of the order and its escrow
+    ///   9. `[]` Token program id
+    ///   10. `[]` Clock sysvar
+    ///   11. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   12. `[writable]` Optional pool statistics, followed by the price oracles and the host
+    ///       fee Account a Swap takes
+    FillOrder,
+
+    ///   Cancels an order, returning the escrowed tokens to the owner and closing the
+    ///   order and its escrow to the owner.
+    ///
+    ///   0. `[]` StableSwap
+    ///   1. `[writable, signer]` Owner of the order
+    ///   2. `[writable]` Order
+    ///   3. `[writable]` Escrow token_(A|B) Account of the order
+    ///   4. `[writable]` token_(A|B) Account receiving the escrowed tokens
+    ///   5. `[]` Token program id
+    CancelOrder,
+
+    ///   Writes the amplification coefficient of the swap at the current time to the
+    ///   return data, as an [AmpFactorReturnData](../return_data/struct.AmpFactorReturnData.html).
+    ///   Mid-ramp this is the interpolated A rather than the initial A stored in the
+    ///   swap, so that routers quoting alongside a RampA price swaps as the program does.
+    ///
+    ///   0. `[]` StableSwap
+    ///   1. `[]` Clock sysvar
+    GetAmpFactor,
+
+    ///   Initializes a new SwapInfo as [Initialize](enum.SwapInstruction.html#variant.Initialize)
+    ///   does, its amplification coefficient starting at `initial_amp_factor` and ramping
+    ///   to `target_amp_factor` over `ramp_duration`, as a RampA would. A pool can start
+    ///   close to constant-product while its liquidity is thin and tighten on its own,
+    ///   without the admin timing a RampA. The ramp is not bound by the factor of 10 a
+    ///   RampA may move A by. The pool tokens minted to the bootstrapper are the
+    ///   invariant at `initial_amp_factor`. See [with_amp_ramp](fn.with_amp_ramp.html).
+    ///
+    ///   Takes the accounts of Initialize.
+    InitializeWithRamp(InitializeWithRampData),
+
+    ///   Swap A to B across up to [MAX_SPLIT_POOLS](constant.MAX_SPLIT_POOLS.html) pools
+    ///   of the same pair, e.g. a pool and its migrated successor. The amount is split
+    ///   in proportion to the depth of each pool at its current reserves, so that the
+    ///   deeper pools take the larger parts, and `minimum_amount_out` applies to the
+    ///   amount the pools output together. Pools too shallow to quote the whole amount
+    ///   are left out.
+    ///
+    ///   0. `[signer]` User authority
+    ///   1. `[writable]` token_A SOURCE Account, amount is transferable by user authority.
+    ///   2. `[writable]` token_B DESTINATION Account assigned to USER as the owner.
+    ///   3. `[]` Token program id
+    ///   4. `[]` Clock sysvar
+    ///   5. `[writable]` First StableSwap
+    ///   6. `[]` First $authority
+    ///   7. `[writable]` First token_A Base Account to swap INTO.
+    ///   8. `[writable]` First token_B Base Account to swap FROM.
+    ///   9. `[writable]` First token_B admin fee Account.
+    ///   10. ..`5 + 5 * pool_count` the accounts of the other pools, as for the first
+    ///   11. `[]` Global state, from [find_global_state_address](../state/fn.find_global_state_address.html)
+    ///   12. `[writable]` Optional pool statistics of any of the pools, see [with_pool_stats](fn.with_pool_stats.html)
+    ///
+    ///   The price oracles of pools that set them must be passed after the global state,
+    ///   see [with_price_oracles](fn.with_price_oracles.html).
+    SwapSplit(SwapSplitData),
+
+    ///   Runs the checks and the math of a Swap without its transfers, writing its result
+    ///   to the return data as a [SwapReturnData](../return_data/struct.SwapReturnData.html)
+    ///   and logging its event, then fails with `SimulationComplete` so that nothing is
+    ///   committed. Simulating it quotes a swap exactly, without the SOURCE Account holding
+    ///   the amount. Takes the same accounts as Swap, the user authority need not sign.
+    SimulateSwap(SwapData),
+}
+
+impl SwapInstruction {
+    /// Unpacks a byte buffer into a [SwapInstruction](enum.SwapInstruction.html).
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input.split_first().ok_or(SwapError::InstructionTooShort)?;
+        Ok(match tag {
+            0 => {
+                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InstructionTooShort)?;
+                let (amp_factor, rest) = unpack_u64(rest)?;
+                // Instructions packed before the lock end with the fees, and the ones
+                // packed before curve types with the locked liquidity
+                let (fees, min_liquidity, curve_type) = if rest.len() >= Fees::LEN + 8 {
+                    let (fees, rest) = rest.split_at(Fees::LEN);
+                    let (min_liquidity, rest) = unpack_u64(rest)?;
+                    let curve_type = match rest.first() {
+                        Some(&curve_type) => {
+                            CurveType::from_u8(curve_type).ok_or(SwapError::InvalidInstruction)?
+                        }
+                        None => CurveType::StableSwap,
+                    };
+                    (Fees::unpack_unchecked(fees)?, min_liquidity, curve_type)
+                } else {
+                    (unpack_fees(rest)?, 0, CurveType::StableSwap)
+                };
+                Self::Initialize(InitializeData {
+                    nonce,
+                    amp_factor,
+                    fees,
+                    min_liquidity,
+                    curve_type,
+                })
+            }
+            1 => {
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
+                Self::Swap(SwapData {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            2 => {
+                let (token_a_amount, rest) = unpack_u64(rest)?;
+                let (token_b_amount, rest) = unpack_u64(rest)?;
+                let (min_mint_amount, _rest) = unpack_u64(rest)?;
+                Self::Deposit(DepositData {
+                    token_a_amount,
+                    token_b_amount,
+                    min_mint_amount,
+                })
+            }
+            3 => {
+                let (pool_token_amount, rest) = unpack_u64(rest)?;
+                let (minimum_token_a_amount, rest) = unpack_u64(rest)?;
+                let (minimum_token_b_amount, _rest) = unpack_u64(rest)?;
+                Self::Withdraw(WithdrawData {
+                    pool_token_amount,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                })
+            }
+            4 => {
+                let (pool_token_amount, rest) = unpack_u64(rest)?;
+                let (minimum_token_amount, _rest) = unpack_u64(rest)?;
+                Self::WithdrawOne(WithdrawOneData {
+                    pool_token_amount,
+                    minimum_token_amount,
+                })
+            }
+            5 => Self::SyncAmp,
+            6 => {
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
+                Self::SwapRoute(SwapData {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            7 => {
+                let (token_amount, rest) = unpack_u64(rest)?;
+                let (maximum_burn_amount, _rest) = unpack_u64(rest)?;
+                Self::WithdrawOneExactOut(WithdrawOneExactOutData {
+                    token_amount,
+                    maximum_burn_amount,
+                })
+            }
+            8 => Self::SetEmergencyAuthority,
+            9 => Self::GlobalPause,
+            10 => Self::GlobalUnpause,
+            11 => {
+                let (token_a_amount, rest) = unpack_u64(rest)?;
+                let (token_b_amount, _rest) = unpack_u64(rest)?;
+                Self::DonateToReserves(DonateToReservesData {
+                    token_a_amount,
+                    token_b_amount,
+                })
+            }
+            12 => Self::SyncLpPosition,
+            13 => {
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (&input_is_a, rest) =
+                    rest.split_first().ok_or(SwapError::InstructionTooShort)?;
+                let input_is_a = match input_is_a {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(SwapError::InvalidInstruction.into()),
+                };
+                let (min_mint_amount, _rest) = unpack_u64(rest)?;
+                Self::ZapIn(ZapInData {
+                    amount_in,
+                    input_is_a,
+                    min_mint_amount,
+                })
+            }
+            14 => {
+                let (pool_token_amount, rest) = unpack_u64(rest)?;
+                let (&want_token_a, rest) =
+                    rest.split_first().ok_or(SwapError::InstructionTooShort)?;
+                let want_token_a = match want_token_a {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(SwapError::InvalidInstruction.into()),
+                };
+                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
+                Self::ZapOut(ZapOutData {
+                    pool_token_amount,
+                    want_token_a,
+                    minimum_amount_out,
+                })
+            }
+            15 => {
+                let (pool_token_amount, _rest) = unpack_u64(rest)?;
+                Self::RedeemMigratedLp(RedeemMigratedLpData { pool_token_amount })
+            }
+            16 => Self::RecoverStrandedTokens,
+            17 => {
+                let mut rest = rest;
+                let mut fills = vec![];
+                while !rest.is_empty() {
+                    let (amount_in, next) = unpack_u64(rest)?;
+                    let (minimum_amount_out, next) = unpack_u64(next)?;
+                    fills.push(SwapData {
+                        amount_in,
+                        minimum_amount_out,
+                    });
+                    rest = next;
+                }
+                if fills.len() > MAX_SWAP_BATCH_FILLS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Self::SwapBatch(fills)
+            }
+            18 => Self::HarvestFees,
+            19 => {
+                let (pool_token_amount, rest) = unpack_u64(rest)?;
+                let (minimum_token_a_amount, rest) = unpack_u64(rest)?;
+                let (minimum_token_b_amount, _rest) = unpack_u64(rest)?;
+                Self::EmergencyWithdraw(WithdrawData {
+                    pool_token_amount,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                })
+            }
+            20 => Self::SyncReserves,
+            21 => Self::RefreshPegRate,
+            22 => {
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
+                Self::SwapAllowPartial(SwapData {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            23 => {
+                let (order_id, rest) = unpack_u64(rest)?;
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
+                Self::CreateOrder(CreateOrderData {
+                    order_id,
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            24 => Self::FillOrder,
+            25 => Self::CancelOrder,
+            26 => Self::GetAmpFactor,
+            27 => {
+                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InstructionTooShort)?;
+                let (initial_amp_factor, rest) = unpack_u64(rest)?;
+                let (target_amp_factor, rest) = unpack_u64(rest)?;
+                let (ramp_duration, rest) = unpack_i64(rest)?;
+                if rest.len() < Fees::LEN {
+                    return Err(SwapError::InstructionTooShort.into());
+                }
+                let (fees, rest) = rest.split_at(Fees::LEN);
+                let (min_liquidity, _rest) = unpack_u64(rest)?;
+                Self::InitializeWithRamp(InitializeWithRampData {
+                    nonce,
+                    initial_amp_factor,
+                    target_amp_factor,
+                    ramp_duration,
+                    fees: Fees::unpack_unchecked(fees)?,
+                    min_liquidity,
+                })
+            }
+            28 => {
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (minimum_amount_out, rest) = unpack_u64(rest)?;
+                let &pool_count = rest.first().ok_or(SwapError::InstructionTooShort)?;
+                if pool_count == 0 || pool_count > MAX_SPLIT_POOLS {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Self::SwapSplit(SwapSplitData {
+                    amount_in,
+                    minimum_amount_out,
+                    pool_count,
+                })
+            }
+            29 => {
+                let (amount_in, rest) = unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
+                Self::SimulateSwap(SwapData {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            _ => return Err(SwapError::InvalidInstruction.into()),
+        })
+    }
+
+    /// Unpacks a byte buffer into a [SwapInstruction](enum.SwapInstruction.html) as
+    /// [unpack](#method.unpack), failing with `TrailingInstructionData` on bytes after
+    /// the last field of the instruction instead of ignoring them.
+    pub fn unpack_checked(input: &[u8]) -> Result<Self, ProgramError> {
+        let instruction = Self::unpack(input)?;
+        check_no_trailing_data(input, &instruction.pack())?;
+        Ok(instruction)
+    }
+
+    /// Packs a [SwapInstruction](enum.SwapInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match *self {
+            Self::Initialize(InitializeData {
+                nonce,
+                amp_factor,
+                fees,
+                min_liquidity,
+                curve_type,
+            }) => {
+                buf.push(0);
+                buf.push(nonce);
+                buf.extend_from_slice(&amp_factor.to_le_bytes());
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(&fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+                buf.extend_from_slice(&min_liquidity.to_le_bytes());
+                buf.push(curve_type as u8);
+            }
+            Self::Swap(SwapData {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(1);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::Deposit(DepositData {
+                token_a_amount,
+                token_b_amount,
+                min_mint_amount,
+            }) => {
+                buf.push(2);
+                buf.extend_from_slice(&token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&token_b_amount.to_le_bytes());
+                buf.extend_from_slice(&min_mint_amount.to_le_bytes());
+            }
+            Self::Withdraw(WithdrawData {
+                pool_token_amount,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                buf.push(3);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+            }
+            Self::WithdrawOne(WithdrawOneData {
+                pool_token_amount,
+                minimum_token_amount,
+            }) => {
+                buf.push(4);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_amount.to_le_bytes());
+            }
+            Self::SyncAmp => buf.push(5),
+            Self::SwapRoute(SwapData {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(6);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::WithdrawOneExactOut(WithdrawOneExactOutData {
+                token_amount,
+                maximum_burn_amount,
+            }) => {
+                buf.push(7);
+                buf.extend_from_slice(&token_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_burn_amount.to_le_bytes());
+            }
+            Self::SetEmergencyAuthority => buf.push(8),
+            Self::GlobalPause => buf.push(9),
+            Self::GlobalUnpause => buf.push(10),
+            Self::DonateToReserves(DonateToReservesData {
+                token_a_amount,
+                token_b_amount,
+            }) => {
+                buf.push(11);
+                buf.extend_from_slice(&token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&token_b_amount.to_le_bytes());
+            }
+            Self::SyncLpPosition => buf.push(12),
+            Self::ZapIn(ZapInData {
+                amount_in,
+                input_is_a,
+                min_mint_amount,
+            }) => {
+                buf.push(13);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.push(input_is_a as u8);
+                buf.extend_from_slice(&min_mint_amount.to_le_bytes());
+            }
+            Self::ZapOut(ZapOutData {
+                pool_token_amount,
+                want_token_a,
+                minimum_amount_out,
+            }) => {
+                buf.push(14);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.push(want_token_a as u8);
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::RedeemMigratedLp(RedeemMigratedLpData { pool_token_amount }) => {
+                buf.push(15);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            }
+            Self::RecoverStrandedTokens => buf.push(16),
+            Self::SwapBatch(ref fills) => {
+                buf.push(17);
+                for fill in fills {
+                    buf.extend_from_slice(&fill.amount_in.to_le_bytes());
+                    buf.extend_from_slice(&fill.minimum_amount_out.to_le_bytes());
+                }
+            }
+            Self::HarvestFees => buf.push(18),
+            Self::EmergencyWithdraw(WithdrawData {
+                pool_token_amount,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                buf.push(19);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+            }
+            Self::SyncReserves => buf.push(20),
+            Self::RefreshPegRate => buf.push(21),
+            Self::SwapAllowPartial(SwapData {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(22);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::CreateOrder(CreateOrderData {
+                order_id,
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(23);
+                buf.extend_from_slice(&order_id.to_le_bytes());
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::FillOrder => buf.push(24),
+            Self::CancelOrder => buf.push(25),
+            Self::GetAmpFactor => buf.push(26),
+            Self::InitializeWithRamp(InitializeWithRampData {
+                nonce,
+                initial_amp_factor,
+                target_amp_factor,
+                ramp_duration,
+                fees,
+                min_liquidity,
+            }) => {
+                buf.push(27);
+                buf.push(nonce);
+                buf.extend_from_slice(&initial_amp_factor.to_le_bytes());
+                buf.extend_from_slice(&target_amp_factor.to_le_bytes());
+                buf.extend_from_slice(&ramp_duration.to_le_bytes());
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(&fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+                buf.extend_from_slice(&min_liquidity.to_le_bytes());
+            }
+            Self::SwapSplit(SwapSplitData {
+                amount_in,
+                minimum_amount_out,
+                pool_count,
+            }) => {
+                buf.push(28);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+                buf.push(pool_count);
+            }
+            Self::SimulateSwap(SwapData {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(29);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+        }
+        buf
+    }
+}
+
+/// Creates an 'initialize' instruction.
+pub fn initialize(
+    program_id: &Pubkey,
+    pool_token_program_id: &Pubkey, // Token program used for the pool token
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    admin_pubkey: &Pubkey,
+    admin_fee_a_pubkey: &Pubkey,
+    admin_fee_b_pubkey: &Pubkey,
+    token_a_mint_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_mint_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey, // Destination to mint pool tokens for bootstrapper
+    nonce: u8,
+    amp_factor: u64,
+    fees: Fees,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Initialize(InitializeData {
+        nonce,
+        amp_factor,
+        fees,
+        min_liquidity: 0,
+        curve_type: CurveType::StableSwap,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new_readonly(*admin_pubkey, false),
+        AccountMeta::new(*admin_fee_a_pubkey, false),
+        AccountMeta::new(*admin_fee_b_pubkey, false),
+        AccountMeta::new(*token_a_mint_pubkey, false),
+        AccountMeta::new(*token_a_pubkey, false),
+        AccountMeta::new(*token_b_mint_pubkey, false),
+        AccountMeta::new(*token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'initialize' instruction of a swap whose accounts are owned by the shared
+/// authority. The token accounts and the pool mint are handed over from the vault owner.
+pub fn initialize_with_shared_authority(
+    program_id: &Pubkey,
+    pool_token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    admin_fee_a_pubkey: &Pubkey,
+    admin_fee_b_pubkey: &Pubkey,
+    token_a_mint_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_mint_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    vault_owner_pubkey: &Pubkey,
+    amp_factor: u64,
+    fees: Fees,
+) -> Result<Instruction, ProgramError> {
+    let (shared_authority_key, nonce) = find_shared_authority_address(program_id);
+    let mut instruction = initialize(
+        program_id,
+        pool_token_program_id,
+        swap_pubkey,
+        &shared_authority_key,
+        admin_pubkey,
+        admin_fee_a_pubkey,
+        admin_fee_b_pubkey,
+        token_a_mint_pubkey,
+        token_a_pubkey,
+        token_b_mint_pubkey,
+        token_b_pubkey,
+        pool_mint_pubkey,
+        destination_pubkey,
+        nonce,
+        amp_factor,
+        fees,
+    )?;
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*vault_owner_pubkey, true));
+    Ok(instruction)
+}
+
+/// Creates a 'deposit' instruction. [DepositIxBuilder](struct.DepositIxBuilder.html) takes the accounts by name.
+pub fn deposit(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    deposit_token_a_pubkey: &Pubkey,
+    deposit_token_b_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    token_a_amount: u64,
+    token_b_amount: u64,
+    min_mint_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Deposit(DepositData {
+        token_a_amount,
+        token_b_amount,
+        min_mint_amount,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*deposit_token_a_pubkey, false),
+        AccountMeta::new(*deposit_token_b_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'withdraw' instruction. [WithdrawIxBuilder](struct.WithdrawIxBuilder.html) takes the accounts by name.
+pub fn withdraw(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    destination_token_a_pubkey: &Pubkey,
+    destination_token_b_pubkey: &Pubkey,
+    admin_fee_a_pubkey: &Pubkey,
+    admin_fee_b_pubkey: &Pubkey,
+    pool_token_amount: u64,
+    minimum_token_a_amount: u64,
+    minimum_token_b_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Withdraw(WithdrawData {
+        pool_token_amount,
+        minimum_token_a_amount,
+        minimum_token_b_amount,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_token_a_pubkey, false),
+        AccountMeta::new(*destination_token_b_pubkey, false),
+        AccountMeta::new(*admin_fee_a_pubkey, false),
+        AccountMeta::new(*admin_fee_b_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap' instruction. [SwapIxBuilder](struct.SwapIxBuilder.html) takes the accounts by name.
+pub fn swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Swap(SwapData {
+        amount_in,
+        minimum_amount_out,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'simulate_swap' instruction, to simulate rather than send.
+pub fn simulate_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SimulateSwap(SwapData {
+        amount_in,
+        minimum_amount_out,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*user_authority_key, false),
+        AccountMeta::new_readonly(*source_pubkey, false),
+        AccountMeta::new_readonly(*swap_source_pubkey, false),
+        AccountMeta::new_readonly(*swap_destination_pubkey, false),
+        AccountMeta::new_readonly(*destination_pubkey, false),
+        AccountMeta::new_readonly(*admin_fee_destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap_allow_partial' instruction.
+pub fn swap_allow_partial(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SwapAllowPartial(SwapData {
+        amount_in,
+        minimum_amount_out,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap_batch' instruction.
+pub fn swap_batch(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    fills: &[SwapData],
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SwapBatch(fills.to_vec()).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'withdraw_one' instruction. [WithdrawOneIxBuilder](struct.WithdrawOneIxBuilder.html) takes the accounts by name.
+pub fn withdraw_one(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_base_token_pubkey: &Pubkey,
+    swap_quote_token_pubkey: &Pubkey,
+    base_destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    pool_token_amount: u64,
+    minimum_token_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::WithdrawOne(WithdrawOneData {
+        pool_token_amount,
+        minimum_token_amount,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_base_token_pubkey, false),
+        AccountMeta::new(*swap_quote_token_pubkey, false),
+        AccountMeta::new(*base_destination_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'withdraw_one_exact_out' instruction.
+pub fn withdraw_one_exact_out(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_base_token_pubkey: &Pubkey,
+    swap_quote_token_pubkey: &Pubkey,
+    base_destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    token_amount: u64,
+    maximum_burn_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::WithdrawOneExactOut(WithdrawOneExactOutData {
+        token_amount,
+        maximum_burn_amount,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*swap_authority_key, false),
+        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_base_token_pubkey, false),
+        AccountMeta::new(*swap_quote_token_pubkey, false),
+        AccountMeta::new(*base_destination_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new(*token_program_id, false),
+        AccountMeta::new(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'sync_amp' instruction.
+pub fn sync_amp(program_id: &Pubkey, swap_pubkey: &Pubkey) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SyncAmp.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Pool accounts of a single hop of a 'swap_route' instruction, or of a single pool of a
+/// 'swap_split' instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwapRouteHop {
+    /// StableSwap
+    pub swap: Pubkey,
+    /// $authority of the StableSwap
+    pub swap_authority: Pubkey,
+    /// Base Account to swap INTO
+    pub swap_source: Pubkey,
+    /// Base Account to swap FROM
+    pub swap_destination: Pubkey,
+    /// Admin fee Account of the destination token
+    pub admin_fee_destination: Pubkey,
+}
+
+/// Creates a 'swap_route' instruction.
+pub fn swap_route(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    first: &SwapRouteHop,
+    second: &SwapRouteHop,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SwapRoute(SwapData {
+        amount_in,
+        minimum_amount_out,
+    })
+    .pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+    for hop in [first, second].iter() {
+        accounts.push(AccountMeta::new(hop.swap, false));
+        accounts.push(AccountMeta::new_readonly(hop.swap_authority, false));
+        accounts.push(AccountMeta::new(hop.swap_source, false));
+        accounts.push(AccountMeta::new(hop.swap_destination, false));
+        accounts.push(AccountMeta::new(hop.admin_fee_destination, false));
+    }
+    accounts.push(AccountMeta::new_readonly(
+        find_global_state_address(program_id).0,
+        false,
+    ));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap_split' instruction, splitting `amount_in` across the pools.
+pub fn swap_split(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pools: &[SwapRouteHop],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let pool_count: u8 = pools
+        .len()
+        .try_into()
+        .map_err(|_| SwapError::InvalidInput)?;
+    if pool_count == 0 || pool_count > MAX_SPLIT_POOLS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let data = SwapInstruction::SwapSplit(SwapSplitData {
+        amount_in,
+        minimum_amount_out,
+        pool_count,
+    })
+    .pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+    for pool in pools.iter() {
+        accounts.push(AccountMeta::new(pool.swap, false));
+        accounts.push(AccountMeta::new_readonly(pool.swap_authority, false));
+        accounts.push(AccountMeta::new(pool.swap_source, false));
+        accounts.push(AccountMeta::new(pool.swap_destination, false));
+        accounts.push(AccountMeta::new(pool.admin_fee_destination, false));
+    }
+    accounts.push(AccountMeta::new_readonly(
+        find_global_state_address(program_id).0,
+        false,
+    ));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_emergency_authority' instruction.
+pub fn set_emergency_authority(
+    program_id: &Pubkey,
+    upgrade_authority_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    emergency_authority_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetEmergencyAuthority.pack();
+
+    let (program_data_pubkey, _bump_seed) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    let accounts = vec![
+        AccountMeta::new(find_global_state_address(program_id).0, false),
+        AccountMeta::new_readonly(*upgrade_authority_pubkey, true),
+        AccountMeta::new_readonly(program_data_pubkey, false),
+        AccountMeta::new_readonly(*emergency_authority_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap' instruction with the accounts of an spl-token-swap `Swap`.
+pub fn spl_token_swap_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Swap(SwapData {
+        amount_in,
+        minimum_amount_out,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'global_pause' instruction.
+pub fn global_pause(
+    program_id: &Pubkey,
+    emergency_authority_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::GlobalPause.pack();
+
+    let accounts = vec![
+        AccountMeta::new(find_global_state_address(program_id).0, false),
+        AccountMeta::new_readonly(*emergency_authority_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'global_unpause' instruction.
+pub fn global_unpause(
+    program_id: &Pubkey,
+    emergency_authority_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::GlobalUnpause.pack();
+
+    let accounts = vec![
+        AccountMeta::new(find_global_state_address(program_id).0, false),
+        AccountMeta::new_readonly(*emergency_authority_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
 }
 
-impl SwapInstruction {
-    /// Unpacks a byte buffer into a [SwapInstruction](enum.SwapInstruction.html).
-    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&tag, rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
-        Ok(match tag {
-            0 => {
-                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
-                let (amp_factor, rest) = unpack_u64(rest)?;
-                let fees = Fees::unpack_unchecked(rest)?;
-                Self::Initialize(InitializeData {
-                    nonce,
-                    amp_factor,
-                    fees,
-                })
-            }
-            1 => {
-                let (amount_in, rest) = unpack_u64(rest)?;
-                let (minimum_amount_out, _rest) = unpack_u64(rest)?;
-                Self::Swap(SwapData {
-                    amount_in,
-                    minimum_amount_out,
-                })
-            }
-            2 => {
-                let (token_a_amount, rest) = unpack_u64(rest)?;
-                let (token_b_amount, rest) = unpack_u64(rest)?;
-                let (min_mint_amount, _rest) = unpack_u64(rest)?;
-                Self::Deposit(DepositData {
-                    token_a_amount,
-                    token_b_amount,
-                    min_mint_amount,
-                })
-            }
-            3 => {
-                let (pool_token_amount, rest) = unpack_u64(rest)?;
-                let (minimum_token_a_amount, rest) = unpack_u64(rest)?;
-                let (minimum_token_b_amount, _rest) = unpack_u64(rest)?;
-                Self::Withdraw(WithdrawData {
-                    pool_token_amount,
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                })
-            }
-            4 => {
-                let (pool_token_amount, rest) = unpack_u64(rest)?;
-                let (minimum_token_amount, _rest) = unpack_u64(rest)?;
-                Self::WithdrawOne(WithdrawOneData {
-                    pool_token_amount,
-                    minimum_token_amount,
-                })
-            }
-            _ => return Err(SwapError::InvalidInstruction.into()),
-        })
-    }
+/// Creates a 'donate_to_reserves' instruction.
+pub fn donate_to_reserves(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    donor_pubkey: &Pubkey,
+    source_a_pubkey: &Pubkey,
+    source_b_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    token_a_amount: u64,
+    token_b_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::DonateToReserves(DonateToReservesData {
+        token_a_amount,
+        token_b_amount,
+    })
+    .pack();
 
-    /// Packs a [SwapInstruction](enum.SwapInstruction.html) into a byte buffer.
-    pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
-        match *self {
-            Self::Initialize(InitializeData {
-                nonce,
-                amp_factor,
-                fees,
-            }) => {
-                buf.push(0);
-                buf.push(nonce);
-                buf.extend_from_slice(&amp_factor.to_le_bytes());
-                let mut fees_slice = [0u8; Fees::LEN];
-                Pack::pack_into_slice(&fees, &mut fees_slice[..]);
-                buf.extend_from_slice(&fees_slice);
-            }
-            Self::Swap(SwapData {
-                amount_in,
-                minimum_amount_out,
-            }) => {
-                buf.push(1);
-                buf.extend_from_slice(&amount_in.to_le_bytes());
-                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
-            }
-            Self::Deposit(DepositData {
-                token_a_amount,
-                token_b_amount,
-                min_mint_amount,
-            }) => {
-                buf.push(2);
-                buf.extend_from_slice(&token_a_amount.to_le_bytes());
-                buf.extend_from_slice(&token_b_amount.to_le_bytes());
-                buf.extend_from_slice(&min_mint_amount.to_le_bytes());
-            }
-            Self::Withdraw(WithdrawData {
-                pool_token_amount,
-                minimum_token_a_amount,
-                minimum_token_b_amount,
-            }) => {
-                buf.push(3);
-                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
-                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
-                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
-            }
-            Self::WithdrawOne(WithdrawOneData {
-                pool_token_amount,
-                minimum_token_amount,
-            }) => {
-                buf.push(4);
-                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
-                buf.extend_from_slice(&minimum_token_amount.to_le_bytes());
-            }
-        }
-        buf
-    }
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*donor_pubkey, true),
+        AccountMeta::new(*source_a_pubkey, false),
+        AccountMeta::new(*source_b_pubkey, false),
+        AccountMeta::new(*token_a_pubkey, false),
+        AccountMeta::new(*token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
 }
 
-/// Creates an 'initialize' instruction.
-pub fn initialize(
+/// Creates a 'sync_lp_position' instruction.
+pub fn sync_lp_position(
     program_id: &Pubkey,
-    pool_token_program_id: &Pubkey, // Token program used for the pool token
+    swap_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    pool_token_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SyncLpPosition.pack();
+
+    let (lp_position_pubkey, _bump_seed) =
+        find_lp_position_address(program_id, swap_pubkey, owner_pubkey);
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, false),
+        AccountMeta::new(lp_position_pubkey, false),
+        AccountMeta::new_readonly(*pool_token_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'zap_in' instruction.
+pub fn zap_in(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
     swap_authority_key: &Pubkey,
-    admin_pubkey: &Pubkey,
+    user_authority_key: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    amount_in: u64,
+    input_is_a: bool,
+    min_mint_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::ZapIn(ZapInData {
+        amount_in,
+        input_is_a,
+        min_mint_amount,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*user_authority_key, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*admin_fee_destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'zap_out' instruction.
+pub fn zap_out(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
     admin_fee_a_pubkey: &Pubkey,
     admin_fee_b_pubkey: &Pubkey,
-    token_a_mint_pubkey: &Pubkey,
-    token_a_pubkey: &Pubkey,
-    token_b_mint_pubkey: &Pubkey,
-    token_b_pubkey: &Pubkey,
-    pool_mint_pubkey: &Pubkey,
-    destination_pubkey: &Pubkey, // Destination to mint pool tokens for bootstrapper
-    nonce: u8,
-    amp_factor: u64,
-    fees: Fees,
+    pool_token_amount: u64,
+    want_token_a: bool,
+    minimum_amount_out: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::Initialize(InitializeData {
-        nonce,
-        amp_factor,
-        fees,
+    let data = SwapInstruction::ZapOut(ZapOutData {
+        pool_token_amount,
+        want_token_a,
+        minimum_amount_out,
     })
     .pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
-        AccountMeta::new(*swap_authority_key, false),
-        AccountMeta::new_readonly(*admin_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*user_authority_key, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
         AccountMeta::new(*admin_fee_a_pubkey, false),
         AccountMeta::new(*admin_fee_b_pubkey, false),
-        AccountMeta::new(*token_a_mint_pubkey, false),
-        AccountMeta::new(*token_a_pubkey, false),
-        AccountMeta::new(*token_b_mint_pubkey, false),
-        AccountMeta::new(*token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'redeem_migrated_lp' instruction.
+pub fn redeem_migrated_lp(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    lp_escrow_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_token_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::RedeemMigratedLp(RedeemMigratedLpData { pool_token_amount }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*user_authority_key, true),
         AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*lp_escrow_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
-        AccountMeta::new(*pool_token_program_id, false),
-        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(
+            find_pool_migration_address(program_id, swap_pubkey).0,
+            false,
+        ),
+        AccountMeta::new_readonly(*token_program_id, false),
     ];
 
     Ok(Instruction {
@@ -617,42 +4674,61 @@ pub fn initialize(
     })
 }
 
-/// Creates a 'deposit' instruction.
-pub fn deposit(
+/// Creates a 'recover_stranded_tokens' instruction.
+pub fn recover_stranded_tokens(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
     swap_authority_key: &Pubkey,
-    user_authority_key: &Pubkey,
-    deposit_token_a_pubkey: &Pubkey,
-    deposit_token_b_pubkey: &Pubkey,
-    swap_token_a_pubkey: &Pubkey,
-    swap_token_b_pubkey: &Pubkey,
-    pool_mint_pubkey: &Pubkey,
-    destination_pubkey: &Pubkey,
-    token_a_amount: u64,
-    token_b_amount: u64,
-    min_mint_amount: u64,
+    stranded_pubkey: &Pubkey,
+    admin_fee_pubkey: &Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::Deposit(DepositData {
-        token_a_amount,
-        token_b_amount,
-        min_mint_amount,
+    let data = SwapInstruction::RecoverStrandedTokens.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new(*stranded_pubkey, false),
+        AccountMeta::new(*admin_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
     })
-    .pack();
+}
+
+/// Creates a 'harvest_fees' instruction. The accounts the rewards distributor
+/// program expects can be appended to the returned instruction.
+pub fn harvest_fees(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    admin_fee_a_pubkey: &Pubkey,
+    admin_fee_b_pubkey: &Pubkey,
+    rewards_program_id: &Pubkey,
+    rewards_a_pubkey: &Pubkey,
+    rewards_b_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::HarvestFees.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, false),
-        AccountMeta::new(*swap_authority_key, false),
-        AccountMeta::new(*user_authority_key, true),
-        AccountMeta::new(*deposit_token_a_pubkey, false),
-        AccountMeta::new(*deposit_token_b_pubkey, false),
-        AccountMeta::new(*swap_token_a_pubkey, false),
-        AccountMeta::new(*swap_token_b_pubkey, false),
-        AccountMeta::new(*pool_mint_pubkey, false),
-        AccountMeta::new(*destination_pubkey, false),
-        AccountMeta::new(*token_program_id, false),
-        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new(*admin_fee_a_pubkey, false),
+        AccountMeta::new(*admin_fee_b_pubkey, false),
+        AccountMeta::new_readonly(
+            find_rewards_distributor_address(program_id, swap_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(*rewards_a_pubkey, false),
+        AccountMeta::new(*rewards_b_pubkey, false),
+        AccountMeta::new_readonly(*rewards_program_id, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
     ];
 
     Ok(Instruction {
@@ -662,8 +4738,8 @@ pub fn deposit(
     })
 }
 
-/// Creates a 'withdraw' instruction.
-pub fn withdraw(
+/// Creates an 'emergency_withdraw' instruction.
+pub fn emergency_withdraw(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
@@ -675,13 +4751,11 @@ pub fn withdraw(
     swap_token_b_pubkey: &Pubkey,
     destination_token_a_pubkey: &Pubkey,
     destination_token_b_pubkey: &Pubkey,
-    admin_fee_a_pubkey: &Pubkey,
-    admin_fee_b_pubkey: &Pubkey,
     pool_token_amount: u64,
     minimum_token_a_amount: u64,
     minimum_token_b_amount: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::Withdraw(WithdrawData {
+    let data = SwapInstruction::EmergencyWithdraw(WithdrawData {
         pool_token_amount,
         minimum_token_a_amount,
         minimum_token_b_amount,
@@ -690,18 +4764,15 @@ pub fn withdraw(
 
     let accounts = vec![
         AccountMeta::new(*swap_pubkey, false),
-        AccountMeta::new(*swap_authority_key, false),
-        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new_readonly(*user_authority_key, true),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*source_pubkey, false),
         AccountMeta::new(*swap_token_a_pubkey, false),
         AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*destination_token_a_pubkey, false),
         AccountMeta::new(*destination_token_b_pubkey, false),
-        AccountMeta::new(*admin_fee_a_pubkey, false),
-        AccountMeta::new(*admin_fee_b_pubkey, false),
-        AccountMeta::new(*token_program_id, false),
-        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(*token_program_id, false),
     ];
 
     Ok(Instruction {
@@ -711,38 +4782,127 @@ pub fn withdraw(
     })
 }
 
-/// Creates a 'swap' instruction.
-pub fn swap(
+/// Creates a 'sync_reserves' instruction.
+pub fn sync_reserves(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SyncReserves.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_token_a_pubkey, false),
+        AccountMeta::new_readonly(*swap_token_b_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'refresh_peg_rate' instruction.
+pub fn refresh_peg_rate(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    peg_config_pubkey: &Pubkey,
+    rate_account_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::RefreshPegRate.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new(*peg_config_pubkey, false),
+        AccountMeta::new_readonly(*rate_account_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'create_order' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn create_order(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
-    swap_authority_key: &Pubkey,
-    user_authority_key: &Pubkey,
+    owner_pubkey: &Pubkey,
+    escrow_pubkey: &Pubkey,
     source_pubkey: &Pubkey,
-    swap_source_pubkey: &Pubkey,
-    swap_destination_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
-    admin_fee_destination_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    order_id: u64,
     amount_in: u64,
     minimum_amount_out: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::Swap(SwapData {
+    let data = SwapInstruction::CreateOrder(CreateOrderData {
+        order_id,
         amount_in,
         minimum_amount_out,
     })
     .pack();
 
+    let (order_pubkey, _bump_seed) =
+        find_order_address(program_id, swap_pubkey, owner_pubkey, order_id);
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, false),
-        AccountMeta::new(*swap_authority_key, false),
-        AccountMeta::new(*user_authority_key, true),
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new(order_pubkey, false),
+        AccountMeta::new(*escrow_pubkey, false),
         AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new_readonly(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'fill_order' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn fill_order(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_authority_key: &Pubkey,
+    order_pubkey: &Pubkey,
+    escrow_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_fee_destination_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::FillOrder.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_authority_key, false),
+        AccountMeta::new(*order_pubkey, false),
+        AccountMeta::new(*escrow_pubkey, false),
         AccountMeta::new(*swap_source_pubkey, false),
         AccountMeta::new(*swap_destination_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
         AccountMeta::new(*admin_fee_destination_pubkey, false),
-        AccountMeta::new(*token_program_id, false),
-        AccountMeta::new(clock::id(), false),
+        AccountMeta::new(*owner_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(find_global_state_address(program_id).0, false),
     ];
 
     Ok(Instruction {
@@ -752,40 +4912,44 @@ pub fn swap(
     })
 }
 
-/// Creates a 'withdraw_one' instruction.
-pub fn withdraw_one(
+/// Creates a 'cancel_order' instruction.
+pub fn cancel_order(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
-    swap_authority_key: &Pubkey,
-    user_authority_key: &Pubkey,
-    pool_mint_pubkey: &Pubkey,
-    source_pubkey: &Pubkey,
-    swap_base_token_pubkey: &Pubkey,
-    swap_quote_token_pubkey: &Pubkey,
-    base_destination_pubkey: &Pubkey,
-    admin_fee_destination_pubkey: &Pubkey,
-    pool_token_amount: u64,
-    minimum_token_amount: u64,
+    owner_pubkey: &Pubkey,
+    order_pubkey: &Pubkey,
+    escrow_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::WithdrawOne(WithdrawOneData {
-        pool_token_amount,
-        minimum_token_amount,
+    let data = SwapInstruction::CancelOrder.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*owner_pubkey, true),
+        AccountMeta::new(*order_pubkey, false),
+        AccountMeta::new(*escrow_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
     })
-    .pack();
+}
+
+/// Creates a 'get_amp_factor' instruction.
+pub fn get_amp_factor(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::GetAmpFactor.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, false),
-        AccountMeta::new(*swap_authority_key, false),
-        AccountMeta::new(*user_authority_key, true),
-        AccountMeta::new(*pool_mint_pubkey, false),
-        AccountMeta::new(*source_pubkey, false),
-        AccountMeta::new(*swap_base_token_pubkey, false),
-        AccountMeta::new(*swap_quote_token_pubkey, false),
-        AccountMeta::new(*base_destination_pubkey, false),
-        AccountMeta::new(*admin_fee_destination_pubkey, false),
-        AccountMeta::new(*token_program_id, false),
-        AccountMeta::new(clock::id(), false),
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
     ];
 
     Ok(Instruction {
@@ -795,6 +4959,273 @@ pub fn withdraw_one(
     })
 }
 
+/// Removes the clock sysvar from the accounts of an instruction. The program then
+/// reads the clock with `Clock::get`, freeing an account slot for transactions
+/// close to the account limit.
+pub fn omit_clock_sysvar(mut instruction: Instruction) -> Instruction {
+    instruction
+        .accounts
+        .retain(|account| !clock::check_id(&account.pubkey));
+    instruction
+}
+
+/// Accounts and amounts of a 'swap' instruction, named so that they cannot be passed
+/// in the wrong order. Every field is required, leaving one out does not compile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwapIxBuilder<'a> {
+    /// The StableSwap program
+    pub program_id: &'a Pubkey,
+    /// Account 8, `[]` the token program
+    pub token_program_id: &'a Pubkey,
+    /// Account 0, `[writable]` the StableSwap
+    pub swap: &'a Pubkey,
+    /// Account 1, `[]` the swap authority
+    pub swap_authority: &'a Pubkey,
+    /// Account 2, `[signer]` the user transfer authority
+    pub user_authority: &'a Pubkey,
+    /// Account 3, `[writable]` the user account of the SOURCE token
+    pub source: &'a Pubkey,
+    /// Account 4, `[writable]` the reserves of the SOURCE token
+    pub swap_source: &'a Pubkey,
+    /// Account 5, `[writable]` the reserves of the DESTINATION token
+    pub swap_destination: &'a Pubkey,
+    /// Account 6, `[writable]` the user account of the DESTINATION token
+    pub destination: &'a Pubkey,
+    /// Account 7, `[writable]` the admin fee account of the DESTINATION token
+    pub admin_fee_destination: &'a Pubkey,
+    /// Amount of SOURCE token to swap
+    pub amount_in: u64,
+    /// Minimum amount of DESTINATION token to receive
+    pub minimum_amount_out: u64,
+}
+
+impl SwapIxBuilder<'_> {
+    /// Creates the instruction, see [swap](fn.swap.html). The clock sysvar and the
+    /// global state are accounts 9 and 10.
+    pub fn build(&self) -> Result<Instruction, ProgramError> {
+        swap(
+            self.program_id,
+            self.token_program_id,
+            self.swap,
+            self.swap_authority,
+            self.user_authority,
+            self.source,
+            self.swap_source,
+            self.swap_destination,
+            self.destination,
+            self.admin_fee_destination,
+            self.amount_in,
+            self.minimum_amount_out,
+        )
+    }
+}
+
+/// Accounts and amounts of a 'deposit' instruction, named so that they cannot be passed
+/// in the wrong order. Every field is required, leaving one out does not compile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepositIxBuilder<'a> {
+    /// The StableSwap program
+    pub program_id: &'a Pubkey,
+    /// Account 9, `[]` the token program
+    pub token_program_id: &'a Pubkey,
+    /// Account 0, `[writable]` the StableSwap
+    pub swap: &'a Pubkey,
+    /// Account 1, `[]` the swap authority
+    pub swap_authority: &'a Pubkey,
+    /// Account 2, `[signer]` the user transfer authority
+    pub user_authority: &'a Pubkey,
+    /// Account 3, `[writable]` the user account of token A to deposit from
+    pub deposit_token_a: &'a Pubkey,
+    /// Account 4, `[writable]` the user account of token B to deposit from
+    pub deposit_token_b: &'a Pubkey,
+    /// Account 5, `[writable]` the reserves of token A
+    pub swap_token_a: &'a Pubkey,
+    /// Account 6, `[writable]` the reserves of token B
+    pub swap_token_b: &'a Pubkey,
+    /// Account 7, `[writable]` the pool mint
+    pub pool_mint: &'a Pubkey,
+    /// Account 8, `[writable]` the user pool token account to mint to
+    pub destination: &'a Pubkey,
+    /// Amount of token A to deposit
+    pub token_a_amount: u64,
+    /// Amount of token B to deposit
+    pub token_b_amount: u64,
+    /// Minimum amount of pool tokens to mint
+    pub min_mint_amount: u64,
+}
+
+impl DepositIxBuilder<'_> {
+    /// Creates the instruction, see [deposit](fn.deposit.html). The clock sysvar and
+    /// the global state are accounts 10 and 11.
+    pub fn build(&self) -> Result<Instruction, ProgramError> {
+        deposit(
+            self.program_id,
+            self.token_program_id,
+            self.swap,
+            self.swap_authority,
+            self.user_authority,
+            self.deposit_token_a,
+            self.deposit_token_b,
+            self.swap_token_a,
+            self.swap_token_b,
+            self.pool_mint,
+            self.destination,
+            self.token_a_amount,
+            self.token_b_amount,
+            self.min_mint_amount,
+        )
+    }
+}
+
+/// Accounts and amounts of a 'withdraw' instruction, named so that they cannot be
+/// passed in the wrong order. Every field is required, leaving one out does not compile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WithdrawIxBuilder<'a> {
+    /// The StableSwap program
+    pub program_id: &'a Pubkey,
+    /// Account 11, `[]` the token program
+    pub token_program_id: &'a Pubkey,
+    /// Account 0, `[writable]` the StableSwap
+    pub swap: &'a Pubkey,
+    /// Account 1, `[]` the swap authority
+    pub swap_authority: &'a Pubkey,
+    /// Account 2, `[signer]` the user transfer authority
+    pub user_authority: &'a Pubkey,
+    /// Account 3, `[writable]` the pool mint
+    pub pool_mint: &'a Pubkey,
+    /// Account 4, `[writable]` the user pool token account to burn from
+    pub source: &'a Pubkey,
+    /// Account 5, `[writable]` the reserves of token A
+    pub swap_token_a: &'a Pubkey,
+    /// Account 6, `[writable]` the reserves of token B
+    pub swap_token_b: &'a Pubkey,
+    /// Account 7, `[writable]` the user account of token A to credit
+    pub destination_token_a: &'a Pubkey,
+    /// Account 8, `[writable]` the user account of token B to credit
+    pub destination_token_b: &'a Pubkey,
+    /// Account 9, `[writable]` the admin fee account of token A
+    pub admin_fee_a: &'a Pubkey,
+    /// Account 10, `[writable]` the admin fee account of token B
+    pub admin_fee_b: &'a Pubkey,
+    /// Amount of pool tokens to burn
+    pub pool_token_amount: u64,
+    /// Minimum amount of token A to receive
+    pub minimum_token_a_amount: u64,
+    /// Minimum amount of token B to receive
+    pub minimum_token_b_amount: u64,
+}
+
+impl WithdrawIxBuilder<'_> {
+    /// Creates the instruction, see [withdraw](fn.withdraw.html). The clock sysvar is
+    /// account 12.
+    pub fn build(&self) -> Result<Instruction, ProgramError> {
+        withdraw(
+            self.program_id,
+            self.token_program_id,
+            self.swap,
+            self.swap_authority,
+            self.user_authority,
+            self.pool_mint,
+            self.source,
+            self.swap_token_a,
+            self.swap_token_b,
+            self.destination_token_a,
+            self.destination_token_b,
+            self.admin_fee_a,
+            self.admin_fee_b,
+            self.pool_token_amount,
+            self.minimum_token_a_amount,
+            self.minimum_token_b_amount,
+        )
+    }
+}
+
+/// Accounts and amounts of a 'withdraw_one' instruction, named so that they cannot be
+/// passed in the wrong order. Every field is required, leaving one out does not compile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WithdrawOneIxBuilder<'a> {
+    /// The StableSwap program
+    pub program_id: &'a Pubkey,
+    /// Account 9, `[]` the token program
+    pub token_program_id: &'a Pubkey,
+    /// Account 0, `[writable]` the StableSwap
+    pub swap: &'a Pubkey,
+    /// Account 1, `[]` the swap authority
+    pub swap_authority: &'a Pubkey,
+    /// Account 2, `[signer]` the user transfer authority
+    pub user_authority: &'a Pubkey,
+    /// Account 3, `[writable]` the pool mint
+    pub pool_mint: &'a Pubkey,
+    /// Account 4, `[writable]` the user pool token account to burn from
+    pub source: &'a Pubkey,
+    /// Account 5, `[writable]` the reserves of the BASE token, withdrawn from
+    pub swap_base_token: &'a Pubkey,
+    /// Account 6, `[writable]` the reserves of the QUOTE token
+    pub swap_quote_token: &'a Pubkey,
+    /// Account 7, `[writable]` the user account of the BASE token to credit
+    pub base_destination: &'a Pubkey,
+    /// Account 8, `[writable]` the admin fee account of the BASE token
+    pub admin_fee_destination: &'a Pubkey,
+    /// Amount of pool tokens to burn
+    pub pool_token_amount: u64,
+    /// Minimum amount of BASE token to receive
+    pub minimum_token_amount: u64,
+}
+
+impl WithdrawOneIxBuilder<'_> {
+    /// Creates the instruction, see [withdraw_one](fn.withdraw_one.html). The clock
+    /// sysvar is account 10.
+    pub fn build(&self) -> Result<Instruction, ProgramError> {
+        withdraw_one(
+            self.program_id,
+            self.token_program_id,
+            self.swap,
+            self.swap_authority,
+            self.user_authority,
+            self.pool_mint,
+            self.source,
+            self.swap_base_token,
+            self.swap_quote_token,
+            self.base_destination,
+            self.admin_fee_destination,
+            self.pool_token_amount,
+            self.minimum_token_amount,
+        )
+    }
+}
+
+/// Checks that instruction data is no longer than the instruction unpacked from it
+/// packs to. Legacy layouts are shorter, only trailing bytes make it longer.
+fn check_no_trailing_data(input: &[u8], packed: &[u8]) -> Result<(), ProgramError> {
+    if input.len() > packed.len() {
+        return Err(SwapError::TrailingInstructionData.into());
+    }
+    Ok(())
+}
+
+/// Checks that the data of a fixed size field is `len` bytes long.
+fn check_data_len(input: &[u8], len: usize) -> Result<(), ProgramError> {
+    if input.len() < len {
+        Err(SwapError::InstructionTooShort.into())
+    } else if input.len() > len {
+        Err(SwapError::TrailingInstructionData.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Unpacks the fees ending an instruction, in any of the layouts of
+/// [Fees::unpack_versioned].
+fn unpack_fees(input: &[u8]) -> Result<Fees, ProgramError> {
+    if input.len() < FEES_V1_LEN {
+        return Err(SwapError::InstructionTooShort.into());
+    }
+    if input.len() > Fees::LEN {
+        return Err(SwapError::TrailingInstructionData.into());
+    }
+    Fees::unpack_versioned(input)
+}
+
 fn unpack_i64(input: &[u8]) -> Result<(i64, &[u8]), ProgramError> {
     if input.len() >= 8 {
         let (amount, rest) = input.split_at(8);
@@ -805,28 +5236,619 @@ fn unpack_i64(input: &[u8]) -> Result<(i64, &[u8]), ProgramError> {
             .ok_or(SwapError::InvalidInstruction)?;
         Ok((amount, rest))
     } else {
-        Err(SwapError::InvalidInstruction.into())
+        Err(SwapError::InstructionTooShort.into())
+    }
+}
+
+fn unpack_str(input: &[u8]) -> Result<(String, &[u8]), ProgramError> {
+    let (&len, rest) = input.split_first().ok_or(SwapError::InstructionTooShort)?;
+    if rest.len() < len as usize {
+        return Err(SwapError::InstructionTooShort.into());
+    }
+    let (value, rest) = rest.split_at(len as usize);
+    let value = String::from_utf8(value.to_vec()).map_err(|_| SwapError::InvalidInstruction)?;
+    Ok((value, rest))
+}
+
+fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+    if input.len() >= 32 {
+        let (key, rest) = input.split_at(32);
+        let key = key
+            .try_into()
+            .map(Pubkey::new_from_array)
+            .map_err(|_| SwapError::InvalidInstruction)?;
+        Ok((key, rest))
+    } else {
+        Err(SwapError::InstructionTooShort.into())
+    }
+}
+
+fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+    if input.len() >= 8 {
+        let (amount, rest) = input.split_at(8);
+        let amount = amount
+            .get(..8)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u64::from_le_bytes)
+            .ok_or(SwapError::InvalidInstruction)?;
+        Ok((amount, rest))
+    } else {
+        Err(SwapError::InstructionTooShort.into())
+    }
+}
+
+/// Account roles of the admin instructions only given the swap and the admin
+const ADMIN_ROLES: &[&str] = &["swap", "admin"];
+
+/// Account roles of the admin instructions given the swap, the admin and the clock
+const ADMIN_CLOCK_ROLES: &[&str] = &["swap", "admin", "clock"];
+
+/// Account roles of the swaps of a single pool
+const SWAP_ROLES: &[&str] = &[
+    "swap",
+    "swap_authority",
+    "user_authority",
+    "source",
+    "swap_source",
+    "swap_destination",
+    "destination",
+    "admin_fee_destination",
+    "token_program",
+    "clock",
+    "global_state",
+];
+
+/// Account roles of the single-token withdrawals
+const WITHDRAW_ONE_ROLES: &[&str] = &[
+    "swap",
+    "swap_authority",
+    "user_authority",
+    "pool_mint",
+    "source",
+    "swap_base_token",
+    "swap_quote_token",
+    "base_destination",
+    "admin_fee_destination",
+    "token_program",
+    "clock",
+];
+
+/// Account roles of the initializations
+const INITIALIZE_ROLES: &[&str] = &[
+    "swap",
+    "swap_authority",
+    "admin",
+    "admin_fee_a",
+    "admin_fee_b",
+    "token_a_mint",
+    "token_a",
+    "token_b_mint",
+    "token_b",
+    "pool_mint",
+    "destination",
+    "token_program",
+    "clock",
+];
+
+impl AdminInstruction {
+    /// Roles of the accounts of the instruction, in order, as its builder passes them
+    pub fn account_roles(&self) -> &'static [&'static str] {
+        match self {
+            Self::RampA(_)
+            | Self::StopRampA
+            | Self::ApplyNewAdmin
+            | Self::CommitNewFees(_)
+            | Self::ApplyNewFees
+            | Self::RampFees(_)
+            | Self::RampAPrecise(_)
+            | Self::EmergencyAmpDown(_) => ADMIN_CLOCK_ROLES,
+            Self::Pause
+            | Self::Unpause
+            | Self::SetNewFees(_)
+            | Self::SetFeeTiers(_)
+            | Self::SetFeeMode(_)
+            | Self::CancelPending
+            | Self::SetDonationsAdminOnly(_)
+            | Self::SetSplTokenSwapCompat(_)
+            | Self::SetDepositAllowlistEnabled(_)
+            | Self::SetMaxSwapBps(_)
+            | Self::DeprecatePool
+            | Self::SetRejectZeroAmounts(_)
+            | Self::SetWithdrawFeeGracePeriod(_)
+            | Self::SetCpiPolicy(_)
+            | Self::SetQuickSwapMaxBps(_)
+            | Self::SetAdminFeesAsLp(_)
+            | Self::SetWithdrawOneWhilePaused(_)
+            | Self::SetQuietLogs(_) => ADMIN_ROLES,
+            Self::SetFeeAccount => &["swap", "admin", "new_fee_account"],
+            Self::CommitNewAdmin => &["swap", "admin", "new_admin", "clock"],
+            Self::SetAdminMultisig(_) => &[
+                "swap",
+                "admin",
+                "admin_multisig",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetFeeExemptions(_) => &[
+                "swap",
+                "admin",
+                "fee_exemptions",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::InitPoolStats => &[
+                "swap",
+                "admin",
+                "pool_stats",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetDepositAllowlist(_) => &[
+                "swap",
+                "admin",
+                "deposit_allowlist",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetPoolInfo(_) => &[
+                "swap",
+                "admin",
+                "pool_registry_entry",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetCpiAllowlist(_) => &[
+                "swap",
+                "admin",
+                "cpi_allowlist",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetSameSlotGuard(_) => &[
+                "swap",
+                "admin",
+                "last_trade",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetMaxLpSupply(_) | Self::SetMaxLpPerOwner(_) => &[
+                "swap",
+                "admin",
+                "deposit_caps",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::SetPoolMetadata(_) => &[
+                "swap",
+                "admin",
+                "swap_authority",
+                "pool_mint",
+                "metadata",
+                "payer",
+                "metadata_program",
+                "system_program",
+                "rent",
+            ],
+            Self::CollectAdminFees(_) => &[
+                "swap",
+                "admin",
+                "swap_authority",
+                "admin_fee_a",
+                "admin_fee_b",
+                "destination_a",
+                "destination_b",
+                "token_program",
+                "clock",
+                "admin_fee_owner",
+            ],
+            Self::SetPriceOracles(_) => &["swap", "admin", "price_oracle_a", "price_oracle_b"],
+            Self::MigratePool => &[
+                "swap",
+                "admin",
+                "swap_authority",
+                "swap_token_a",
+                "swap_token_b",
+                "pool_mint",
+                "new_swap",
+                "new_swap_authority",
+                "new_swap_token_a",
+                "new_swap_token_b",
+                "new_pool_mint",
+                "lp_escrow",
+                "pool_migration",
+                "payer",
+                "system_program",
+                "rent",
+                "token_program",
+                "clock",
+            ],
+            Self::SetRewardsDistributor => &[
+                "swap",
+                "admin",
+                "rewards_distributor",
+                "payer",
+                "system_program",
+                "rent",
+                "rewards_program",
+                "rewards_a",
+                "rewards_b",
+            ],
+            Self::FreezeLp | Self::ThawLp => &[
+                "swap",
+                "admin",
+                "swap_authority",
+                "pool_mint",
+                "lp_account",
+                "token_program",
+            ],
+            Self::MigrateToSharedAuthority => &[
+                "swap",
+                "admin",
+                "swap_authority",
+                "shared_authority",
+                "token_a",
+                "token_b",
+                "admin_fee_a",
+                "admin_fee_b",
+                "pool_mint",
+                "token_program",
+            ],
+            Self::SetStakingConfig(_) => &[
+                "swap",
+                "admin",
+                "staking_config",
+                "payer",
+                "system_program",
+                "rent",
+                "staking_program",
+                "staking_pool",
+            ],
+            Self::SetPegRateSource(_) => &[
+                "swap",
+                "admin",
+                "peg_config",
+                "payer",
+                "system_program",
+                "rent",
+                "rate_account",
+                "clock",
+            ],
+        }
+    }
+}
+
+impl SwapInstruction {
+    /// Roles of the accounts of the instruction, in order, as its builder passes them.
+    /// The hops of a SwapRoute and the pools of a SwapSplit follow their first five accounts.
+    pub fn account_roles(&self) -> &'static [&'static str] {
+        match self {
+            Self::Initialize(_) | Self::InitializeWithRamp(_) => INITIALIZE_ROLES,
+            Self::Swap(_)
+            | Self::SwapBatch(_)
+            | Self::SwapAllowPartial(_)
+            | Self::SimulateSwap(_) => SWAP_ROLES,
+            Self::Deposit(_) => &[
+                "swap",
+                "swap_authority",
+                "user_authority",
+                "deposit_token_a",
+                "deposit_token_b",
+                "swap_token_a",
+                "swap_token_b",
+                "pool_mint",
+                "destination",
+                "token_program",
+                "clock",
+                "global_state",
+            ],
+            Self::Withdraw(_) => &[
+                "swap",
+                "swap_authority",
+                "user_authority",
+                "pool_mint",
+                "source",
+                "swap_token_a",
+                "swap_token_b",
+                "destination_token_a",
+                "destination_token_b",
+                "admin_fee_a",
+                "admin_fee_b",
+                "token_program",
+                "clock",
+            ],
+            Self::WithdrawOne(_) | Self::WithdrawOneExactOut(_) => WITHDRAW_ONE_ROLES,
+            Self::SyncAmp | Self::GetAmpFactor => &["swap", "clock"],
+            Self::SwapRoute(_) => &[
+                "user_authority",
+                "source",
+                "destination",
+                "token_program",
+                "clock",
+                "first_swap",
+                "first_swap_authority",
+                "first_swap_source",
+                "first_swap_destination",
+                "first_admin_fee_destination",
+                "second_swap",
+                "second_swap_authority",
+                "second_swap_source",
+                "second_swap_destination",
+                "second_admin_fee_destination",
+                "global_state",
+            ],
+            Self::SwapSplit(SwapSplitData { pool_count: 1, .. }) => &[
+                "user_authority",
+                "source",
+                "destination",
+                "token_program",
+                "clock",
+                "first_swap",
+                "first_swap_authority",
+                "first_swap_source",
+                "first_swap_destination",
+                "first_admin_fee_destination",
+                "global_state",
+            ],
+            Self::SwapSplit(SwapSplitData { pool_count: 2, .. }) => &[
+                "user_authority",
+                "source",
+                "destination",
+                "token_program",
+                "clock",
+                "first_swap",
+                "first_swap_authority",
+                "first_swap_source",
+                "first_swap_destination",
+                "first_admin_fee_destination",
+                "second_swap",
+                "second_swap_authority",
+                "second_swap_source",
+                "second_swap_destination",
+                "second_admin_fee_destination",
+                "global_state",
+            ],
+            Self::SwapSplit(_) => &[
+                "user_authority",
+                "source",
+                "destination",
+                "token_program",
+                "clock",
+                "first_swap",
+                "first_swap_authority",
+                "first_swap_source",
+                "first_swap_destination",
+                "first_admin_fee_destination",
+                "second_swap",
+                "second_swap_authority",
+                "second_swap_source",
+                "second_swap_destination",
+                "second_admin_fee_destination",
+                "third_swap",
+                "third_swap_authority",
+                "third_swap_source",
+                "third_swap_destination",
+                "third_admin_fee_destination",
+                "global_state",
+            ],
+            Self::SetEmergencyAuthority => &[
+                "global_state",
+                "upgrade_authority",
+                "program_data",
+                "emergency_authority",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::GlobalPause | Self::GlobalUnpause => &["global_state", "emergency_authority"],
+            Self::DonateToReserves(_) => &[
+                "swap",
+                "donor",
+                "source_a",
+                "source_b",
+                "token_a",
+                "token_b",
+                "token_program",
+                "clock",
+                "global_state",
+            ],
+            Self::SyncLpPosition => &[
+                "swap",
+                "owner",
+                "lp_position",
+                "pool_token",
+                "payer",
+                "system_program",
+                "rent",
+            ],
+            Self::ZapIn(_) => &[
+                "swap",
+                "swap_authority",
+                "user_authority",
+                "source",
+                "swap_token_a",
+                "swap_token_b",
+                "pool_mint",
+                "destination",
+                "admin_fee_destination",
+                "token_program",
+                "clock",
+                "global_state",
+            ],
+            Self::ZapOut(_) => &[
+                "swap",
+                "swap_authority",
+                "user_authority",
+                "pool_mint",
+                "source",
+                "swap_token_a",
+                "swap_token_b",
+                "destination",
+                "admin_fee_a",
+                "admin_fee_b",
+                "token_program",
+                "clock",
+                "global_state",
+            ],
+            Self::RedeemMigratedLp(_) => &[
+                "swap",
+                "swap_authority",
+                "user_authority",
+                "pool_mint",
+                "source",
+                "lp_escrow",
+                "destination",
+                "pool_migration",
+                "token_program",
+            ],
+            Self::RecoverStrandedTokens => &[
+                "swap",
+                "swap_authority",
+                "stranded",
+                "admin_fee",
+                "token_program",
+            ],
+            Self::HarvestFees => &[
+                "swap",
+                "swap_authority",
+                "admin_fee_a",
+                "admin_fee_b",
+                "rewards_distributor",
+                "rewards_a",
+                "rewards_b",
+                "rewards_program",
+                "token_program",
+                "clock",
+            ],
+            Self::EmergencyWithdraw(_) => &[
+                "swap",
+                "swap_authority",
+                "user_authority",
+                "pool_mint",
+                "source",
+                "swap_token_a",
+                "swap_token_b",
+                "destination_token_a",
+                "destination_token_b",
+                "token_program",
+            ],
+            Self::SyncReserves => &["swap", "swap_token_a", "swap_token_b", "clock"],
+            Self::RefreshPegRate => &["swap", "peg_config", "rate_account", "clock"],
+            Self::CreateOrder(_) => &[
+                "swap",
+                "owner",
+                "order",
+                "escrow",
+                "source",
+                "destination",
+                "token_program",
+                "payer",
+                "system_program",
+                "rent",
+                "global_state",
+            ],
+            Self::FillOrder => &[
+                "swap",
+                "swap_authority",
+                "order",
+                "escrow",
+                "swap_source",
+                "swap_destination",
+                "destination",
+                "admin_fee_destination",
+                "owner",
+                "token_program",
+                "clock",
+                "global_state",
+            ],
+            Self::CancelOrder => &[
+                "swap",
+                "owner",
+                "order",
+                "escrow",
+                "destination",
+                "token_program",
+            ],
+        }
     }
 }
 
-fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
-    if input.len() >= 8 {
-        let (amount, rest) = input.split_at(8);
-        let amount = amount
-            .get(..8)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(SwapError::InvalidInstruction)?;
-        Ok((amount, rest))
-    } else {
-        Err(SwapError::InvalidInstruction.into())
+/// An instruction of the program, admin or not
+#[derive(Debug, PartialEq)]
+pub enum PoolInstruction {
+    /// An instruction any user can send
+    Swap(SwapInstruction),
+    /// An admin only instruction
+    Admin(AdminInstruction),
+}
+
+/// An account of a decoded instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedAccount {
+    /// Role of the account, named after the argument of the instruction builder, e.g.
+    /// `user_authority`. Accounts past the ones the builder passes, e.g. the optional
+    /// accounts or the signers of an admin multisig, are `extra`.
+    pub role: &'static str,
+    /// Key of the account
+    pub pubkey: Pubkey,
+}
+
+/// An instruction of a transaction decoded by [decode]
+#[derive(Debug, PartialEq)]
+pub struct ParsedSwapInstruction {
+    /// The instruction
+    pub instruction: PoolInstruction,
+    /// The accounts of the instruction, with their roles
+    pub accounts: Vec<ParsedAccount>,
+}
+
+/// Decodes an instruction of a transaction sent to the program, given the account keys
+/// of the transaction message, naming its accounts. The clock sysvar may be omitted,
+/// see [omit_clock_sysvar]. Checking that the instruction invokes the program is left
+/// to the caller, as pools may be deployed under other program ids.
+pub fn decode(
+    instruction: &CompiledInstruction,
+    account_keys: &[Pubkey],
+) -> Result<ParsedSwapInstruction, ProgramError> {
+    let instruction_data = match AdminInstruction::unpack(&instruction.data)? {
+        Some(admin_instruction) => PoolInstruction::Admin(admin_instruction),
+        None => PoolInstruction::Swap(SwapInstruction::unpack(&instruction.data)?),
+    };
+    let mut roles = match &instruction_data {
+        PoolInstruction::Swap(swap_instruction) => swap_instruction.account_roles(),
+        PoolInstruction::Admin(admin_instruction) => admin_instruction.account_roles(),
     }
+    .iter()
+    .peekable();
+    let mut accounts = Vec::with_capacity(instruction.accounts.len());
+    for index in instruction.accounts.iter() {
+        let pubkey = *account_keys
+            .get(usize::from(*index))
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if roles.peek() == Some(&&"clock") && !clock::check_id(&pubkey) {
+            roles.next();
+        }
+        let role = roles.next().copied().unwrap_or("extra");
+        accounts.push(ParsedAccount { role, pubkey });
+    }
+    Ok(ParsedSwapInstruction {
+        instruction: instruction_data,
+        accounts,
+    })
 }
 
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
+    use crate::fees::{FeeMode, NO_DYNAMIC_FEE, NO_FEE_TIERS};
 
     #[test]
     fn test_admin_instruction_packing() {
@@ -902,6 +5924,10 @@ mod tests {
             trade_fee_denominator: 6,
             withdraw_fee_numerator: 7,
             withdraw_fee_denominator: 8,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
         };
         let check = AdminInstruction::SetNewFees(new_fees);
         let packed = check.pack();
@@ -913,6 +5939,538 @@ mod tests {
         assert_eq!(packed, expect);
         let unpacked = AdminInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, Some(check));
+
+        let tiers = [
+            FeeTier {
+                threshold: 1_000,
+                trade_fee_numerator: 6,
+            },
+            FeeTier::default(),
+            FeeTier::default(),
+        ];
+        let check = AdminInstruction::SetFeeTiers(tiers);
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(108_u8);
+        expect.extend_from_slice(&1_000_u64.to_le_bytes());
+        expect.extend_from_slice(&6_u64.to_le_bytes());
+        expect.extend_from_slice(&[0u8; FeeTier::LEN * 2]);
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+
+        let dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: 1_000,
+            out_fee_numerator: 40,
+        };
+        let check = AdminInstruction::SetFeeMode(dynamic_fee);
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(109_u8);
+        expect.push(1_u8);
+        expect.extend_from_slice(&1_000_u64.to_le_bytes());
+        expect.extend_from_slice(&40_u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        expect[1] = 2;
+        assert!(AdminInstruction::unpack(&expect).is_err());
+
+        let check = AdminInstruction::CommitNewFees(new_fees);
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(110_u8);
+        expect.extend_from_slice(&new_fees_slice);
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::ApplyNewFees;
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(111_u8);
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::CancelPending;
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(112_u8);
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let threshold = 2;
+        let signers = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let check = AdminInstruction::SetAdminMultisig(SetAdminMultisigData {
+            threshold,
+            signers: signers.clone(),
+        });
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(113_u8);
+        expect.push(threshold);
+        expect.extend_from_slice(signers[0].as_ref());
+        expect.extend_from_slice(signers[1].as_ref());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+
+        let exempt_accounts = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let check = AdminInstruction::SetFeeExemptions(exempt_accounts.clone());
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(114_u8);
+        expect.extend_from_slice(exempt_accounts[0].as_ref());
+        expect.extend_from_slice(exempt_accounts[1].as_ref());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+        let check = AdminInstruction::SetFeeExemptions(vec![]);
+        assert_eq!(
+            AdminInstruction::unpack(&check.pack()).unwrap(),
+            Some(check)
+        );
+
+        let check = AdminInstruction::SetPoolMetadata(SetPoolMetadataData {
+            name: "Saber USDC-USDT LP".to_string(),
+            symbol: "USDC-USDT".to_string(),
+            uri: "".to_string(),
+        });
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(115_u8);
+        expect.push(18);
+        expect.extend_from_slice(b"Saber USDC-USDT LP");
+        expect.push(9);
+        expect.extend_from_slice(b"USDC-USDT");
+        expect.push(0);
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+        expect[2] = 0xff;
+        assert!(AdminInstruction::unpack(&expect).is_err());
+
+        let token_a_amount = 1;
+        let token_b_amount = 2;
+        let check = AdminInstruction::CollectAdminFees(CollectAdminFeesData {
+            token_a_amount,
+            token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![];
+        expect.push(116_u8);
+        expect.extend_from_slice(&token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::InitPoolStats;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![117];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetDonationsAdminOnly(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![118, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[118, 2]).is_err());
+
+        let check = AdminInstruction::SetSplTokenSwapCompat(false);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![119, 0];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let max_deviation_bps: u64 = 50;
+        let check = AdminInstruction::SetPriceOracles(max_deviation_bps);
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![120];
+        expect.extend_from_slice(&max_deviation_bps.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let target_fees = Fees {
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            ..Fees::default()
+        };
+        let stop_ramp_ts: i64 = i64::MAX;
+        let check = AdminInstruction::RampFees(RampFeesData {
+            target_fees,
+            stop_ramp_ts,
+        });
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![121];
+        let mut fees_slice = [0u8; Fees::LEN];
+        target_fees.pack_into_slice(&mut fees_slice[..]);
+        expect.extend_from_slice(&fees_slice);
+        expect.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let allowed_accounts = vec![Pubkey::new_unique()];
+        let check = AdminInstruction::SetDepositAllowlist(allowed_accounts.clone());
+        let packed = check.pack();
+        let mut expect = vec![122_u8];
+        expect.extend_from_slice(allowed_accounts[0].as_ref());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+
+        let check = AdminInstruction::SetDepositAllowlistEnabled(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![123, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::MigratePool;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![124];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetMaxSwapBps(500);
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![125];
+        expect.extend_from_slice(&500u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetPoolInfo(SetPoolInfoData {
+            name: "USDC-USDT".to_string(),
+            category: PoolCategory::Stable,
+            is_deprecated: true,
+        });
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![126, 9];
+        expect.extend_from_slice(b"USDC-USDT");
+        expect.push(0);
+        expect.push(1);
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        // name too long
+        let mut expect: Vec<u8> = vec![126, MAX_POOL_NAME_LEN as u8 + 1];
+        expect.extend_from_slice(&[b'a'; MAX_POOL_NAME_LEN + 1]);
+        expect.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            AdminInstruction::unpack(&expect),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = AdminInstruction::SetRewardsDistributor;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![127];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::FreezeLp;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![128];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::ThawLp;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![129];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::DeprecatePool;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![130];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::MigrateToSharedAuthority;
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![131];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetRejectZeroAmounts(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![132, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[132, 2]).is_err());
+
+        let check = AdminInstruction::SetStakingConfig([1, 2, 3, 4, 5, 6, 7, 8]);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![133, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..8]).is_err());
+
+        let target_amp = 10_050;
+        let stop_ramp_ts = i64::MAX;
+        let check = AdminInstruction::RampAPrecise(RampAData {
+            target_amp,
+            stop_ramp_ts,
+        });
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![134];
+        expect.extend_from_slice(&target_amp.to_le_bytes());
+        expect.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetWithdrawFeeGracePeriod(2_592_000);
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![135];
+        expect.extend_from_slice(&2_592_000i64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetCpiPolicy(CpiPolicy::TopLevelOnly);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![136, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[136, 3]).is_err());
+
+        let allowed_programs = vec![Pubkey::new_unique()];
+        let check = AdminInstruction::SetCpiAllowlist(allowed_programs.clone());
+        let packed = check.pack();
+        let mut expect = vec![137_u8];
+        expect.extend_from_slice(allowed_programs[0].as_ref());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+
+        let check = AdminInstruction::SetPegRateSource(PegRateSource::StakePool);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![138, 2];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[138, 3]).is_err());
+        assert!(AdminInstruction::unpack(&[138]).is_err());
+
+        let check = AdminInstruction::SetQuickSwapMaxBps(5);
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![139];
+        expect.extend_from_slice(&5u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+
+        let check = AdminInstruction::SetSameSlotGuard(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![140, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[140, 2]).is_err());
+
+        let check = AdminInstruction::SetAdminFeesAsLp(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![141, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[141, 2]).is_err());
+
+        let check = AdminInstruction::SetWithdrawOneWhilePaused(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![142, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[142, 2]).is_err());
+
+        let check = AdminInstruction::SetQuietLogs(true);
+        let packed = check.pack();
+        let expect: Vec<u8> = vec![143, 1];
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[143, 2]).is_err());
+
+        let check = AdminInstruction::SetMaxLpSupply(1_000_000);
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![144];
+        expect.extend_from_slice(&1_000_000u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[144]).is_err());
+
+        let check = AdminInstruction::EmergencyAmpDown(10);
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![146];
+        expect.extend_from_slice(&10u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        assert!(AdminInstruction::unpack(&[146]).is_err());
+
+        let exempt_owner = Pubkey::new_unique();
+        let check = AdminInstruction::SetMaxLpPerOwner(SetMaxLpPerOwnerData {
+            max_lp_per_owner: 1_000,
+            exempt_owners: vec![exempt_owner],
+        });
+        let packed = check.pack();
+        let mut expect: Vec<u8> = vec![145];
+        expect.extend_from_slice(&1_000u64.to_le_bytes());
+        expect.extend_from_slice(exempt_owner.as_ref());
+        assert_eq!(packed, expect);
+        let unpacked = AdminInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, Some(check));
+        // a partial key
+        assert!(AdminInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+        let mut too_many = vec![145];
+        too_many.extend_from_slice(&1_000u64.to_le_bytes());
+        for _ in 0..=MAX_DEPOSIT_CAP_EXEMPT_OWNERS {
+            too_many.extend_from_slice(exempt_owner.as_ref());
+        }
+        assert!(AdminInstruction::unpack(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_instruction_builders() {
+        let keys: Vec<Pubkey> = (0..13).map(|_| Pubkey::new_unique()).collect();
+        let swap_ix = SwapIxBuilder {
+            program_id: &keys[0],
+            token_program_id: &keys[1],
+            swap: &keys[2],
+            swap_authority: &keys[3],
+            user_authority: &keys[4],
+            source: &keys[5],
+            swap_source: &keys[6],
+            swap_destination: &keys[7],
+            destination: &keys[8],
+            admin_fee_destination: &keys[9],
+            amount_in: 100,
+            minimum_amount_out: 90,
+        }
+        .build()
+        .unwrap();
+        assert_eq!(
+            swap_ix,
+            swap(
+                &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+                &keys[8], &keys[9], 100, 90,
+            )
+            .unwrap()
+        );
+        assert_eq!(swap_ix.accounts[3].pubkey, keys[5]);
+        assert!(swap_ix.accounts[2].is_signer);
+
+        let deposit_ix = DepositIxBuilder {
+            program_id: &keys[0],
+            token_program_id: &keys[1],
+            swap: &keys[2],
+            swap_authority: &keys[3],
+            user_authority: &keys[4],
+            deposit_token_a: &keys[5],
+            deposit_token_b: &keys[6],
+            swap_token_a: &keys[7],
+            swap_token_b: &keys[8],
+            pool_mint: &keys[9],
+            destination: &keys[10],
+            token_a_amount: 100,
+            token_b_amount: 200,
+            min_mint_amount: 300,
+        }
+        .build()
+        .unwrap();
+        assert_eq!(
+            deposit_ix,
+            deposit(
+                &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+                &keys[8], &keys[9], &keys[10], 100, 200, 300,
+            )
+            .unwrap()
+        );
+        assert_eq!(deposit_ix.accounts[9].pubkey, keys[1]);
+
+        let withdraw_ix = WithdrawIxBuilder {
+            program_id: &keys[0],
+            token_program_id: &keys[1],
+            swap: &keys[2],
+            swap_authority: &keys[3],
+            user_authority: &keys[4],
+            pool_mint: &keys[5],
+            source: &keys[6],
+            swap_token_a: &keys[7],
+            swap_token_b: &keys[8],
+            destination_token_a: &keys[9],
+            destination_token_b: &keys[10],
+            admin_fee_a: &keys[11],
+            admin_fee_b: &keys[12],
+            pool_token_amount: 100,
+            minimum_token_a_amount: 40,
+            minimum_token_b_amount: 50,
+        }
+        .build()
+        .unwrap();
+        assert_eq!(
+            withdraw_ix,
+            withdraw(
+                &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+                &keys[8], &keys[9], &keys[10], &keys[11], &keys[12], 100, 40, 50,
+            )
+            .unwrap()
+        );
+        assert_eq!(withdraw_ix.accounts[11].pubkey, keys[1]);
+
+        let withdraw_one_ix = WithdrawOneIxBuilder {
+            program_id: &keys[0],
+            token_program_id: &keys[1],
+            swap: &keys[2],
+            swap_authority: &keys[3],
+            user_authority: &keys[4],
+            pool_mint: &keys[5],
+            source: &keys[6],
+            swap_base_token: &keys[7],
+            swap_quote_token: &keys[8],
+            base_destination: &keys[9],
+            admin_fee_destination: &keys[10],
+            pool_token_amount: 100,
+            minimum_token_amount: 90,
+        }
+        .build()
+        .unwrap();
+        assert_eq!(
+            withdraw_one_ix,
+            withdraw_one(
+                &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+                &keys[8], &keys[9], &keys[10], 100, 90,
+            )
+            .unwrap()
+        );
+        assert_eq!(withdraw_one_ix.accounts[9].pubkey, keys[1]);
     }
 
     #[test]
@@ -928,11 +6486,17 @@ mod tests {
             trade_fee_denominator: 6,
             withdraw_fee_numerator: 7,
             withdraw_fee_denominator: 8,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
         };
         let check = SwapInstruction::Initialize(InitializeData {
             nonce,
             amp_factor,
             fees,
+            min_liquidity: MINIMUM_LIQUIDITY,
+            curve_type: CurveType::StableSwap,
         });
         let packed = check.pack();
         let mut expect = vec![];
@@ -942,9 +6506,35 @@ mod tests {
         let mut fees_slice = [0u8; Fees::LEN];
         fees.pack_into_slice(&mut fees_slice[..]);
         expect.extend_from_slice(&fees_slice);
+        let legacy = expect.clone();
+        expect.extend_from_slice(&MINIMUM_LIQUIDITY.to_le_bytes());
+        let without_curve_type = expect.clone();
+        expect.push(CurveType::StableSwap as u8);
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
+        // instructions packed before curve types are StableSwap pools
+        let unpacked = SwapInstruction::unpack(&without_curve_type).unwrap();
+        assert_eq!(unpacked, check);
+        // instructions packed before the lock lock nothing
+        let unpacked = SwapInstruction::unpack(&legacy).unwrap();
+        assert_eq!(
+            unpacked,
+            SwapInstruction::Initialize(InitializeData {
+                nonce,
+                amp_factor,
+                fees,
+                min_liquidity: 0,
+                curve_type: CurveType::StableSwap,
+            })
+        );
+        // curve types this program does not know
+        let mut unknown_curve_type = without_curve_type;
+        unknown_curve_type.push(1);
+        assert_eq!(
+            SwapInstruction::unpack(&unknown_curve_type),
+            Err(SwapError::InvalidInstruction.into())
+        );
 
         let amount_in: u64 = 2;
         let minimum_amount_out: u64 = 10;
@@ -1007,5 +6597,814 @@ mod tests {
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::SyncAmp;
+        let packed = check.pack();
+        let expect = vec![5];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let amount_in: u64 = 2;
+        let minimum_amount_out: u64 = 10;
+        let check = SwapInstruction::SwapRoute(SwapData {
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![6];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let token_amount: u64 = 102198761982612;
+        let maximum_burn_amount: u64 = 1212438012089;
+        let check = SwapInstruction::WithdrawOneExactOut(WithdrawOneExactOutData {
+            token_amount,
+            maximum_burn_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![7];
+        expect.extend_from_slice(&token_amount.to_le_bytes());
+        expect.extend_from_slice(&maximum_burn_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::SetEmergencyAuthority;
+        let packed = check.pack();
+        let expect = vec![8];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::GlobalPause;
+        let packed = check.pack();
+        let expect = vec![9];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::GlobalUnpause;
+        let packed = check.pack();
+        let expect = vec![10];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let token_a_amount: u64 = 1_000;
+        let token_b_amount: u64 = 0;
+        let check = SwapInstruction::DonateToReserves(DonateToReservesData {
+            token_a_amount,
+            token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![11];
+        expect.extend_from_slice(&token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::SyncLpPosition;
+        let packed = check.pack();
+        let expect = vec![12];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let amount_in: u64 = 1_000;
+        let min_mint_amount: u64 = 900;
+        let check = SwapInstruction::ZapIn(ZapInData {
+            amount_in,
+            input_is_a: false,
+            min_mint_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![13];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.push(0);
+        expect.extend_from_slice(&min_mint_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        expect[9] = 2;
+        assert_eq!(
+            SwapInstruction::unpack(&expect),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let pool_token_amount: u64 = 1_000;
+        let minimum_amount_out: u64 = 990;
+        let check = SwapInstruction::ZapOut(ZapOutData {
+            pool_token_amount,
+            want_token_a: true,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![14];
+        expect.extend_from_slice(&pool_token_amount.to_le_bytes());
+        expect.push(1);
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::RedeemMigratedLp(RedeemMigratedLpData { pool_token_amount });
+        let packed = check.pack();
+        let mut expect = vec![15];
+        expect.extend_from_slice(&pool_token_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::RecoverStrandedTokens;
+        let packed = check.pack();
+        let expect = vec![16];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let fills = vec![
+            SwapData {
+                amount_in: 2,
+                minimum_amount_out: 1,
+            },
+            SwapData {
+                amount_in: 20,
+                minimum_amount_out: 10,
+            },
+        ];
+        let check = SwapInstruction::SwapBatch(fills);
+        let packed = check.pack();
+        let mut expect = vec![17];
+        expect.extend_from_slice(&2u64.to_le_bytes());
+        expect.extend_from_slice(&1u64.to_le_bytes());
+        expect.extend_from_slice(&20u64.to_le_bytes());
+        expect.extend_from_slice(&10u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        // truncated fill
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..expect.len() - 8]),
+            Err(SwapError::InstructionTooShort.into())
+        );
+        // too many fills
+        let mut expect = vec![17];
+        for _ in 0..=MAX_SWAP_BATCH_FILLS {
+            expect.extend_from_slice(&[0u8; 16]);
+        }
+        assert_eq!(
+            SwapInstruction::unpack(&expect),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = SwapInstruction::HarvestFees;
+        let packed = check.pack();
+        let expect = vec![18];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::EmergencyWithdraw(WithdrawData {
+            pool_token_amount,
+            minimum_token_a_amount,
+            minimum_token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![19];
+        expect.extend_from_slice(&pool_token_amount.to_le_bytes());
+        expect.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::SyncReserves;
+        let packed = check.pack();
+        let expect = vec![20];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::RefreshPegRate;
+        let packed = check.pack();
+        let expect = vec![21];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let amount_in: u64 = 2;
+        let minimum_amount_out: u64 = 10;
+        let check = SwapInstruction::SwapAllowPartial(SwapData {
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![22];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let order_id: u64 = 7;
+        let check = SwapInstruction::CreateOrder(CreateOrderData {
+            order_id,
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![23];
+        expect.extend_from_slice(&order_id.to_le_bytes());
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::FillOrder;
+        let packed = check.pack();
+        let expect = vec![24];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::CancelOrder;
+        let packed = check.pack();
+        let expect = vec![25];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::GetAmpFactor;
+        let packed = check.pack();
+        let expect = vec![26];
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let initial_amp_factor: u64 = 1;
+        let target_amp_factor: u64 = 100;
+        let ramp_duration: i64 = 604_800;
+        let check = SwapInstruction::InitializeWithRamp(InitializeWithRampData {
+            nonce,
+            initial_amp_factor,
+            target_amp_factor,
+            ramp_duration,
+            fees,
+            min_liquidity: MINIMUM_LIQUIDITY,
+        });
+        let packed = check.pack();
+        let mut expect = vec![27, nonce];
+        expect.extend_from_slice(&initial_amp_factor.to_le_bytes());
+        expect.extend_from_slice(&target_amp_factor.to_le_bytes());
+        expect.extend_from_slice(&ramp_duration.to_le_bytes());
+        expect.extend_from_slice(&fees_slice);
+        expect.extend_from_slice(&MINIMUM_LIQUIDITY.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        assert!(SwapInstruction::unpack(&expect[..expect.len() - 8]).is_err());
+
+        let check = SwapInstruction::SwapSplit(SwapSplitData {
+            amount_in,
+            minimum_amount_out,
+            pool_count: 2,
+        });
+        let packed = check.pack();
+        let mut expect = vec![28];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        expect.push(2);
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        for pool_count in [0, MAX_SPLIT_POOLS + 1].iter() {
+            *expect.last_mut().unwrap() = *pool_count;
+            assert!(SwapInstruction::unpack(&expect).is_err());
+        }
+        assert!(SwapInstruction::unpack(&expect[..expect.len() - 1]).is_err());
+
+        let check = SwapInstruction::SimulateSwap(SwapData {
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![29];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    /// Checks the borsh encoding of instruction data against the packed instruction
+    fn check_borsh<T: BorshSerialize + BorshDeserialize + PartialEq + std::fmt::Debug>(
+        packed: &[u8],
+        data: &T,
+    ) {
+        assert_eq!(data.try_to_vec().unwrap(), packed[1..]);
+        assert_eq!(&T::try_from_slice(&packed[1..]).unwrap(), data);
+    }
+
+    #[test]
+    fn test_instruction_data_borsh() {
+        macro_rules! check {
+            ($instruction:path, $data:expr) => {
+                check_borsh(&$instruction($data).pack(), &$data)
+            };
+        }
+        let fees = Fees::from_bps(4, 10, 5_000).unwrap();
+        check!(
+            SwapInstruction::Initialize,
+            InitializeData {
+                nonce: 255,
+                amp_factor: 100,
+                fees,
+                min_liquidity: MINIMUM_LIQUIDITY,
+                curve_type: CurveType::StableSwap,
+            }
+        );
+        check!(
+            SwapInstruction::InitializeWithRamp,
+            InitializeWithRampData {
+                nonce: 254,
+                initial_amp_factor: 10,
+                target_amp_factor: 100,
+                ramp_duration: 604_800,
+                fees,
+                min_liquidity: 0,
+            }
+        );
+        check!(
+            SwapInstruction::Swap,
+            SwapData {
+                amount_in: 1,
+                minimum_amount_out: 2,
+            }
+        );
+        check!(
+            SwapInstruction::SwapSplit,
+            SwapSplitData {
+                amount_in: 1,
+                minimum_amount_out: 2,
+                pool_count: 3,
+            }
+        );
+        check!(
+            SwapInstruction::Deposit,
+            DepositData {
+                token_a_amount: 1,
+                token_b_amount: 2,
+                min_mint_amount: 3,
+            }
+        );
+        check!(
+            SwapInstruction::Withdraw,
+            WithdrawData {
+                pool_token_amount: 1,
+                minimum_token_a_amount: 2,
+                minimum_token_b_amount: 3,
+            }
+        );
+        check!(
+            SwapInstruction::WithdrawOne,
+            WithdrawOneData {
+                pool_token_amount: 1,
+                minimum_token_amount: 2,
+            }
+        );
+        check!(
+            SwapInstruction::WithdrawOneExactOut,
+            WithdrawOneExactOutData {
+                token_amount: 1,
+                maximum_burn_amount: 2,
+            }
+        );
+        check!(
+            SwapInstruction::DonateToReserves,
+            DonateToReservesData {
+                token_a_amount: 1,
+                token_b_amount: 2,
+            }
+        );
+        check!(
+            SwapInstruction::ZapIn,
+            ZapInData {
+                amount_in: 1,
+                input_is_a: true,
+                min_mint_amount: 2,
+            }
+        );
+        check!(
+            SwapInstruction::ZapOut,
+            ZapOutData {
+                pool_token_amount: 1,
+                want_token_a: false,
+                minimum_amount_out: 2,
+            }
+        );
+        check!(
+            SwapInstruction::RedeemMigratedLp,
+            RedeemMigratedLpData {
+                pool_token_amount: 1
+            }
+        );
+        check!(
+            SwapInstruction::CreateOrder,
+            CreateOrderData {
+                order_id: 1,
+                amount_in: 2,
+                minimum_amount_out: 3,
+            }
+        );
+        check!(
+            AdminInstruction::RampA,
+            RampAData {
+                target_amp: 100,
+                stop_ramp_ts: -1,
+            }
+        );
+        check!(
+            AdminInstruction::CollectAdminFees,
+            CollectAdminFeesData {
+                token_a_amount: 1,
+                token_b_amount: 2,
+            }
+        );
+        check!(
+            AdminInstruction::RampFees,
+            RampFeesData {
+                target_fees: fees,
+                stop_ramp_ts: i64::MAX,
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_instruction_data_serde() {
+        let data = InitializeData {
+            nonce: 255,
+            amp_factor: 100,
+            fees: Fees::from_bps(4, 10, 5_000).unwrap(),
+            min_liquidity: 0,
+            curve_type: CurveType::StableSwap,
+        };
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<InitializeData>(&json).unwrap(), data);
+    }
+
+    /// Decodes an instruction compiled into a transaction message
+    fn decode_compiled(instruction: Instruction) -> ParsedSwapInstruction {
+        let message = solana_program::message::Message::new(&[instruction], None);
+        decode(&message.instructions[0], &message.account_keys).unwrap()
+    }
+
+    fn roles(parsed: &ParsedSwapInstruction) -> Vec<&'static str> {
+        parsed.accounts.iter().map(|account| account.role).collect()
+    }
+
+    #[test]
+    fn test_decode() {
+        let keys: Vec<Pubkey> = (0..13).map(|_| Pubkey::new_unique()).collect();
+        let program_id = crate::id();
+
+        let swap_ix = swap(
+            &program_id,
+            &spl_token::id(),
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            100,
+            90,
+        )
+        .unwrap();
+        let parsed = decode_compiled(swap_ix.clone());
+        assert_eq!(
+            parsed.instruction,
+            PoolInstruction::Swap(SwapInstruction::Swap(SwapData {
+                amount_in: 100,
+                minimum_amount_out: 90,
+            }))
+        );
+        assert_eq!(roles(&parsed), SWAP_ROLES);
+        assert_eq!(
+            parsed.accounts[2],
+            ParsedAccount {
+                role: "user_authority",
+                pubkey: keys[2],
+            }
+        );
+        // without the clock, with the pool statistics
+        let parsed = decode_compiled(with_pool_stats(omit_clock_sysvar(swap_ix), &keys[8]));
+        assert_eq!(parsed.accounts[8].role, "token_program");
+        assert_eq!(parsed.accounts[9].role, "global_state");
+        assert_eq!(
+            parsed.accounts[10],
+            ParsedAccount {
+                role: "extra",
+                pubkey: keys[8],
+            }
+        );
+
+        // signed by an admin multisig
+        let ramp_ix = with_admin_multisig_signers(
+            ramp_a(&program_id, &keys[0], &keys[9], 100, 1_000_000).unwrap(),
+            &[&keys[10], &keys[11]],
+        );
+        let parsed = decode_compiled(ramp_ix);
+        assert_eq!(
+            parsed.instruction,
+            PoolInstruction::Admin(AdminInstruction::RampA(RampAData {
+                target_amp: 100,
+                stop_ramp_ts: 1_000_000,
+            }))
+        );
+        assert_eq!(roles(&parsed), ["swap", "admin", "clock", "extra", "extra"]);
+        assert_eq!(parsed.accounts[1].pubkey, keys[9]);
+
+        let hop = |i: usize| SwapRouteHop {
+            swap: keys[i],
+            swap_authority: keys[i + 1],
+            swap_source: keys[i + 2],
+            swap_destination: keys[i + 3],
+            admin_fee_destination: keys[i + 4],
+        };
+        let route_ix = swap_route(
+            &program_id,
+            &spl_token::id(),
+            &keys[10],
+            &keys[11],
+            &keys[12],
+            &hop(0),
+            &hop(5),
+            100,
+            90,
+        )
+        .unwrap();
+        let parsed = decode_compiled(route_ix.clone());
+        assert_eq!(parsed.accounts.len(), route_ix.accounts.len());
+        assert_eq!(
+            parsed.accounts[10],
+            ParsedAccount {
+                role: "second_swap",
+                pubkey: keys[5],
+            }
+        );
+        assert_eq!(parsed.accounts.last().unwrap().role, "global_state");
+
+        let split_ix = swap_split(
+            &program_id,
+            &spl_token::id(),
+            &keys[10],
+            &keys[11],
+            &keys[12],
+            &[hop(0), hop(5)],
+            100,
+            90,
+        )
+        .unwrap();
+        let parsed = decode_compiled(split_ix.clone());
+        assert_eq!(parsed.accounts.len(), split_ix.accounts.len());
+        assert_eq!(parsed.accounts[14].role, "second_admin_fee_destination");
+        assert_eq!(parsed.accounts.last().unwrap().role, "global_state");
+        assert!(swap_split(
+            &program_id,
+            &spl_token::id(),
+            &keys[10],
+            &keys[11],
+            &keys[12],
+            &[hop(0); 4],
+            100,
+            90,
+        )
+        .is_err());
+
+        let message = solana_program::message::Message::new(&[route_ix], None);
+        let mut compiled = message.instructions[0].clone();
+        compiled.accounts.push(u8::MAX);
+        assert_eq!(
+            decode(&compiled, &message.account_keys),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        compiled.data = vec![u8::MAX];
+        assert!(decode(&compiled, &message.account_keys).is_err());
+    }
+
+    #[test]
+    fn test_with_amp_ramp() {
+        let keys: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let fees = Fees::from_bps(4, 0, 5_000).unwrap();
+        let initialize_ix = initialize(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+            &keys[8], &keys[9], &keys[10], &keys[11], &keys[2], 255, 1, fees,
+        )
+        .unwrap();
+        let ramp_ix = with_locked_liquidity(
+            with_amp_ramp(initialize_ix.clone(), 100, 604_800).unwrap(),
+            &keys[3],
+            MINIMUM_LIQUIDITY,
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&ramp_ix.data).unwrap(),
+            SwapInstruction::InitializeWithRamp(InitializeWithRampData {
+                nonce: 255,
+                initial_amp_factor: 1,
+                target_amp_factor: 100,
+                ramp_duration: 604_800,
+                fees,
+                min_liquidity: MINIMUM_LIQUIDITY,
+            })
+        );
+        let accounts_len = initialize_ix.accounts.len();
+        assert_eq!(ramp_ix.accounts[..accounts_len], initialize_ix.accounts[..]);
+        assert_eq!(ramp_ix.accounts.len(), accounts_len + 1);
+
+        // only initialize instructions ramp
+        let sync_ix = sync_amp(&keys[0], &keys[1]).unwrap();
+        assert_eq!(
+            with_amp_ramp(sync_ix, 100, 604_800),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_with_curve_type() {
+        let keys: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let fees = Fees::from_bps(4, 0, 5_000).unwrap();
+        let initialize_ix = initialize(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+            &keys[8], &keys[9], &keys[10], &keys[11], &keys[2], 255, 100, fees,
+        )
+        .unwrap();
+        let expected = SwapInstruction::Initialize(InitializeData {
+            nonce: 255,
+            amp_factor: 100,
+            fees,
+            min_liquidity: MINIMUM_LIQUIDITY,
+            curve_type: CurveType::StableSwap,
+        });
+        // composes with the lock in either order
+        let locked_ix = with_locked_liquidity(
+            with_curve_type(initialize_ix.clone(), CurveType::StableSwap).unwrap(),
+            &keys[3],
+            MINIMUM_LIQUIDITY,
+        );
+        assert_eq!(SwapInstruction::unpack(&locked_ix.data).unwrap(), expected);
+        let curve_ix = with_curve_type(
+            with_locked_liquidity(initialize_ix, &keys[3], MINIMUM_LIQUIDITY),
+            CurveType::StableSwap,
+        )
+        .unwrap();
+        assert_eq!(curve_ix, locked_ix);
+
+        // only initialize instructions have a curve type
+        let sync_ix = sync_amp(&keys[0], &keys[1]).unwrap();
+        assert_eq!(
+            with_curve_type(sync_ix, CurveType::StableSwap),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_unpack_short_and_trailing_data() {
+        let swap = SwapInstruction::Swap(SwapData {
+            amount_in: 100,
+            minimum_amount_out: 90,
+        })
+        .pack();
+        assert_eq!(
+            SwapInstruction::unpack(&swap[..swap.len() - 1]),
+            Err(SwapError::InstructionTooShort.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&[]),
+            Err(SwapError::InstructionTooShort.into())
+        );
+        let mut trailing = swap.clone();
+        trailing.push(0);
+        // ignored by unpack, rejected by unpack_checked
+        assert_eq!(
+            SwapInstruction::unpack(&trailing),
+            SwapInstruction::unpack(&swap)
+        );
+        assert_eq!(
+            SwapInstruction::unpack_checked(&trailing),
+            Err(SwapError::TrailingInstructionData.into())
+        );
+
+        assert_eq!(
+            AdminInstruction::unpack(&[118]),
+            Err(SwapError::InstructionTooShort.into())
+        );
+        assert_eq!(
+            AdminInstruction::unpack(&[118, 2]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            AdminInstruction::unpack_checked(&[118, 1, 0]),
+            Err(SwapError::TrailingInstructionData.into())
+        );
+        assert_eq!(
+            AdminInstruction::unpack_checked(&[118, 1]),
+            Ok(Some(AdminInstruction::SetDonationsAdminOnly(true)))
+        );
+        // swap instructions are left to SwapInstruction
+        assert_eq!(AdminInstruction::unpack_checked(&trailing), Ok(None));
+
+        // fixed size fields reject both
+        let mut tiers = vec![108];
+        tiers.extend_from_slice(&[0; FeeTier::LEN * MAX_FEE_TIERS]);
+        tiers.pop();
+        assert_eq!(
+            AdminInstruction::unpack(&tiers),
+            Err(SwapError::InstructionTooShort.into())
+        );
+        tiers.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            AdminInstruction::unpack(&tiers),
+            Err(SwapError::TrailingInstructionData.into())
+        );
+        let mut fees = vec![107];
+        fees.extend_from_slice(&[0; FEES_V1_LEN - 1]);
+        assert_eq!(
+            AdminInstruction::unpack(&fees),
+            Err(SwapError::InstructionTooShort.into())
+        );
+        fees.extend_from_slice(&[0; Fees::LEN]);
+        assert_eq!(
+            AdminInstruction::unpack(&fees),
+            Err(SwapError::TrailingInstructionData.into())
+        );
+
+        // a key cut short
+        let mut signers = AdminInstruction::SetAdminMultisig(SetAdminMultisigData {
+            threshold: 1,
+            signers: vec![Pubkey::new_unique()],
+        })
+        .pack();
+        signers.pop();
+        assert_eq!(
+            AdminInstruction::unpack_checked(&signers),
+            Err(SwapError::InstructionTooShort.into())
+        );
+
+        // legacy layouts are still accepted
+        let mut legacy = vec![107];
+        legacy.extend_from_slice(&[0; FEES_V1_LEN]);
+        assert!(AdminInstruction::unpack_checked(&legacy).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_unpack_checked_every_tag() {
+        for tag in 0..=u8::MAX {
+            for len in 0..=Fees::LEN + 100 {
+                let mut input = vec![1; len + 1];
+                input[0] = tag;
+                match SwapInstruction::unpack(&input) {
+                    Ok(instruction) => {
+                        let packed = instruction.pack();
+                        assert_eq!(
+                            SwapInstruction::unpack_checked(&packed).as_ref(),
+                            Ok(&instruction)
+                        );
+                        let expected = if input.len() > packed.len() {
+                            Err(SwapError::TrailingInstructionData.into())
+                        } else {
+                            Ok(instruction)
+                        };
+                        assert_eq!(SwapInstruction::unpack_checked(&input), expected);
+                    }
+                    Err(err) => assert_eq!(SwapInstruction::unpack_checked(&input), Err(err)),
+                }
+                match AdminInstruction::unpack(&input) {
+                    Ok(Some(instruction)) => {
+                        let packed = instruction.pack();
+                        assert_eq!(
+                            AdminInstruction::unpack_checked(&packed).unwrap().as_ref(),
+                            Some(&instruction)
+                        );
+                        let expected = if input.len() > packed.len() {
+                            Err(SwapError::TrailingInstructionData.into())
+                        } else {
+                            Ok(Some(instruction))
+                        };
+                        assert_eq!(AdminInstruction::unpack_checked(&input), expected);
+                    }
+                    result => assert_eq!(AdminInstruction::unpack_checked(&input), result),
+                }
+            }
+        }
     }
 }
@@ -2,20 +2,51 @@
 #![deny(missing_docs)]
 
 //! A Curve-like program for the Solana blockchain.
+//!
+//! On wasm32 only the math is built ([bn], [curve], [error], [fees], [math],
+//! [oracle], [pool_converter] and [presets]), without solana-program, so that web
+//! frontends quote swaps with the exact integer math of the program. The log parser
+//! of [events] is built too.
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod associated_token;
 pub mod bn;
 pub mod curve;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod entrypoint;
 pub mod error;
+pub mod events;
 pub mod fees;
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod golden;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod instruction;
-mod math;
+pub mod math;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod metadata;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline;
+pub mod oracle;
 pub mod pool_converter;
+pub mod presets;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod processor;
+#[cfg(any(test, feature = "reference"))]
+pub mod reference;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod return_data;
+#[cfg(any(test, feature = "sim"))]
+pub mod simulation;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+pub mod snapshot;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod state;
 
 // Export current solana-program types for downstream users who may also be
 // building with a different solana-program version
+#[cfg(not(target_arch = "wasm32"))]
 pub use solana_program;
 
+#[cfg(not(target_arch = "wasm32"))]
 solana_program::declare_id!("SSwpkEEcbUqx4vtoEByFjSkhKdCT862DNVb52nZg1UZ");
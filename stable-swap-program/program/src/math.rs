@@ -1,35 +1,144 @@
 //! Math helpers
+//!
+//! Every multiplication followed by a division names its rounding direction.
+//! Call sites pick the direction that favors the pool: amounts paid out of the
+//! pool round down, fees charged by the pool round up. Square roots round down,
+//! call sites correct the root they derive from them.
 
 use num_traits::ToPrimitive;
 
-const MAX: u64 = 1 << 32;
-const MAX_BIG: u64 = 1 << 48;
-const MAX_SMALL: u64 = 1 << 16;
+/// Multiplies two u64s then divides by the third number, returning the
+/// quotient and the remainder. Uses 64 bit math if the product fits.
+#[inline(always)]
+fn mul_div_rem(a: u64, b: u64, c: u64) -> Option<(u64, u64)> {
+    if c == 0 {
+        return None;
+    }
+    match a.checked_mul(b) {
+        Some(product) => Some((product / c, product % c)),
+        None => {
+            let product = u128::from(a) * u128::from(b);
+            let c = u128::from(c);
+            Some(((product / c).to_u64()?, (product % c).to_u64()?))
+        }
+    }
+}
 
-/// Multiplies two u64s then divides by the third number.
-/// This function attempts to use 64 bit math if possible.
+/// Multiplies two u64s then divides by the third number, rounding down.
 #[inline(always)]
-pub fn mul_div(a: u64, b: u64, c: u64) -> Option<u64> {
-    if a > MAX || b > MAX {
-        (a as u128)
-            .checked_mul(b as u128)?
-            .checked_div(c as u128)?
-            .to_u64()
+pub fn mul_div_floor(a: u64, b: u64, c: u64) -> Option<u64> {
+    mul_div_rem(a, b, c).map(|(quotient, _remainder)| quotient)
+}
+
+/// Multiplies two u64s then divides by the third number, rounding up.
+#[inline(always)]
+pub fn mul_div_ceil(a: u64, b: u64, c: u64) -> Option<u64> {
+    let (quotient, remainder) = mul_div_rem(a, b, c)?;
+    if remainder == 0 {
+        Some(quotient)
     } else {
-        a.checked_mul(b)?.checked_div(c)
+        quotient.checked_add(1)
     }
 }
 
-/// Multiplies two u64s then divides by the third number.
-/// This assumes that a > b.
+/// Multiplies two u64s then divides by the third number, rounding to the
+/// nearest integer. Halves round up.
 #[inline(always)]
-pub fn mul_div_imbalanced(a: u64, b: u64, c: u64) -> Option<u64> {
-    if a > MAX_BIG || b > MAX_SMALL {
-        (a as u128)
-            .checked_mul(b as u128)?
-            .checked_div(c as u128)?
-            .to_u64()
+pub fn mul_div_round(a: u64, b: u64, c: u64) -> Option<u64> {
+    let (quotient, remainder) = mul_div_rem(a, b, c)?;
+    // remainder >= c / 2, without overflowing
+    if remainder >= c - remainder {
+        quotient.checked_add(1)
     } else {
-        a.checked_mul(b)?.checked_div(c)
+        Some(quotient)
+    }
+}
+
+/// Square root of a u128, rounding down. Newton's method from a power of two above
+/// the root, which only decreases until it reaches the root.
+pub fn sqrt_floor(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let bits = 128 - n.leading_zeros();
+    let mut x = 1u128 << (bits / 2 + 1);
+    loop {
+        let next = (x + n / x) / 2;
+        if next >= x {
+            return x;
+        }
+        x = next;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div_floor(7, 3, 2), Some(10));
+        assert_eq!(mul_div_ceil(7, 3, 2), Some(11));
+        assert_eq!(mul_div_round(7, 3, 2), Some(11));
+        assert_eq!(mul_div_floor(7, 3, 4), Some(5));
+        assert_eq!(mul_div_ceil(7, 3, 4), Some(6));
+        assert_eq!(mul_div_round(7, 3, 4), Some(5));
+        // exact results are not rounded
+        assert_eq!(mul_div_floor(6, 3, 2), Some(9));
+        assert_eq!(mul_div_ceil(6, 3, 2), Some(9));
+        assert_eq!(mul_div_round(6, 3, 2), Some(9));
+
+        // products past 64 bits
+        assert_eq!(mul_div_floor(u64::MAX, u64::MAX, u64::MAX), Some(u64::MAX));
+        assert_eq!(mul_div_ceil(u64::MAX, 3, 4), Some(u64::MAX / 4 * 3 + 3));
+        assert_eq!(
+            mul_div_round(u64::MAX, u64::MAX - 1, u64::MAX),
+            Some(u64::MAX - 1)
+        );
+
+        // division by zero and quotients past 64 bits
+        assert_eq!(mul_div_floor(1, 1, 0), None);
+        assert_eq!(mul_div_ceil(1, 1, 0), None);
+        assert_eq!(mul_div_round(1, 1, 0), None);
+        assert_eq!(mul_div_floor(u64::MAX, 2, 1), None);
+        assert_eq!(mul_div_ceil(u64::MAX, u64::MAX, u64::MAX - 1), None);
+    }
+
+    #[test]
+    fn test_sqrt_floor() {
+        assert_eq!(sqrt_floor(0), 0);
+        assert_eq!(sqrt_floor(1), 1);
+        assert_eq!(sqrt_floor(3), 1);
+        assert_eq!(sqrt_floor(4), 2);
+        assert_eq!(sqrt_floor(99), 9);
+        assert_eq!(sqrt_floor(100), 10);
+        assert_eq!(sqrt_floor(u128::MAX), u128::from(u64::MAX));
+        assert_eq!(
+            sqrt_floor(u128::from(u64::MAX) * u128::from(u64::MAX)),
+            u128::from(u64::MAX)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_mul_div_rounding(a: u64, b: u64, c in 1..u64::MAX) {
+            let product = u128::from(a) * u128::from(b);
+            let c_wide = u128::from(c);
+            let floor = product / c_wide;
+            let ceil = product.div_ceil(c_wide);
+            let round = floor + u128::from(product % c_wide * 2 >= c_wide);
+            prop_assert_eq!(mul_div_floor(a, b, c), floor.to_u64());
+            prop_assert_eq!(mul_div_ceil(a, b, c), ceil.to_u64());
+            prop_assert_eq!(mul_div_round(a, b, c), round.to_u64());
+        }
+
+        #[test]
+        fn test_sqrt_floor_bounds(n: u128) {
+            let root = sqrt_floor(n);
+            prop_assert!(root * root <= n);
+            prop_assert!((root + 1).checked_mul(root + 1).filter(|square| *square <= n).is_none());
+        }
     }
 }
@@ -0,0 +1,151 @@
+//! Minimal interface to the Metaplex Token Metadata program, used to name the pool mint.
+
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+    sysvar::rent,
+};
+
+solana_program::declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// Seed prefix of metadata accounts
+pub const METADATA_PREFIX: &[u8] = b"metadata";
+/// Max length of a token name
+pub const MAX_NAME_LENGTH: usize = 32;
+/// Max length of a token symbol
+pub const MAX_SYMBOL_LENGTH: usize = 10;
+/// Max length of a token metadata URI
+pub const MAX_URI_LENGTH: usize = 200;
+
+/// `CreateMetadataAccount` instruction index
+const CREATE_METADATA_ACCOUNT: u8 = 0;
+/// `UpdateMetadataAccount` instruction index
+const UPDATE_METADATA_ACCOUNT: u8 = 1;
+
+/// Finds the metadata account address of a mint
+pub fn find_metadata_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[METADATA_PREFIX, id().as_ref(), mint.as_ref()], &id())
+}
+
+/// Whether the name, symbol and URI fit in a metadata account
+pub fn validate(name: &str, symbol: &str, uri: &str) -> bool {
+    name.len() <= MAX_NAME_LENGTH
+        && symbol.len() <= MAX_SYMBOL_LENGTH
+        && uri.len() <= MAX_URI_LENGTH
+}
+
+/// Creates a `CreateMetadataAccount` instruction. The metadata is mutable,
+/// has no creators and no seller fee.
+#[allow(clippy::too_many_arguments)]
+pub fn create_metadata_account(
+    metadata: &Pubkey,
+    mint: &Pubkey,
+    mint_authority: &Pubkey,
+    payer: &Pubkey,
+    update_authority: &Pubkey,
+    name: &str,
+    symbol: &str,
+    uri: &str,
+) -> Instruction {
+    let mut data = vec![CREATE_METADATA_ACCOUNT];
+    pack_data(&mut data, name, symbol, uri);
+    data.push(1); // is_mutable
+
+    Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*metadata, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*mint_authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*update_authority, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(rent::id(), false),
+        ],
+        data,
+    }
+}
+
+/// Creates an `UpdateMetadataAccount` instruction replacing the name, symbol and URI.
+pub fn update_metadata_account(
+    metadata: &Pubkey,
+    update_authority: &Pubkey,
+    name: &str,
+    symbol: &str,
+    uri: &str,
+) -> Instruction {
+    let mut data = vec![UPDATE_METADATA_ACCOUNT];
+    data.push(1); // Some(data)
+    pack_data(&mut data, name, symbol, uri);
+    data.push(0); // new update_authority: None
+    data.push(0); // primary_sale_happened: None
+
+    Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*metadata, false),
+            AccountMeta::new_readonly(*update_authority, true),
+        ],
+        data,
+    }
+}
+
+/// Borsh encoding of the metadata `Data` struct
+fn pack_data(buf: &mut Vec<u8>, name: &str, symbol: &str, uri: &str) {
+    for value in [name, symbol, uri].iter() {
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+    }
+    buf.extend_from_slice(&0u16.to_le_bytes()); // seller_fee_basis_points
+    buf.push(0); // creators: None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_metadata_account() {
+        let metadata = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let ix = create_metadata_account(
+            &metadata, &mint, &authority, &payer, &authority, "Saber LP", "SLP", "u",
+        );
+        assert_eq!(ix.program_id, id());
+        assert_eq!(ix.accounts.len(), 7);
+        assert!(ix.accounts[2].is_signer);
+
+        let mut expect = vec![0];
+        expect.extend_from_slice(&8u32.to_le_bytes());
+        expect.extend_from_slice(b"Saber LP");
+        expect.extend_from_slice(&3u32.to_le_bytes());
+        expect.extend_from_slice(b"SLP");
+        expect.extend_from_slice(&1u32.to_le_bytes());
+        expect.extend_from_slice(b"u");
+        expect.extend_from_slice(&[0, 0, 0, 1]);
+        assert_eq!(ix.data, expect);
+    }
+
+    #[test]
+    fn test_update_metadata_account() {
+        let metadata = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let ix = update_metadata_account(&metadata, &authority, "", "", "");
+        let mut expect = vec![1, 1];
+        expect.extend_from_slice(&[0; 12]);
+        expect.extend_from_slice(&[0, 0, 0, 0, 0]);
+        assert_eq!(ix.data, expect);
+        assert!(ix.accounts[1].is_signer);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("Saber USDC-USDT LP", "USDC-USDT", ""));
+        assert!(!validate(&"a".repeat(MAX_NAME_LENGTH + 1), "", ""));
+        assert!(!validate("", &"a".repeat(MAX_SYMBOL_LENGTH + 1), ""));
+        assert!(!validate("", "", &"a".repeat(MAX_URI_LENGTH + 1)));
+    }
+}
@@ -0,0 +1,171 @@
+//! Transactions signed offline, over a durable nonce.
+//!
+//! A transaction is only accepted for about two minutes after the blockhash it was
+//! signed over, too short for the signers of an admin multisig signing one after the
+//! other. A transaction using a durable nonce is signed over the hash stored in a
+//! nonce account instead, and stays valid until that nonce is advanced. Its first
+//! instruction must be the system `AdvanceNonceAccount` of the nonce account, which
+//! the runtime runs before the others so that the transaction cannot be replayed.
+//!
+//! No instruction of the program reads the recent blockhashes, so every one of them
+//! can be signed this way, e.g. a [RampA](../instruction/enum.AdminInstruction.html)
+//! built with [ramp_a](../instruction/fn.ramp_a.html) and
+//! [with_admin_multisig_signers](../instruction/fn.with_admin_multisig_signers.html).
+//! They are checked against the clock of the slot they run in though, not the one they
+//! were signed in:
+//! - a RampA needs its stop timestamp at least
+//!   [MIN_RAMP_DURATION](../curve/constant.MIN_RAMP_DURATION.html) after it runs, so
+//!   leave a margin for the time the signers take
+//! - the delays of CommitNewAdmin and CommitNewFees start when they run
+//! - swaps and deposits are priced with the reserves when they run, only bounded by
+//!   the minimum amounts they were signed with
+//!
+//! The signers each sign the serialized [Message], in any order. [signature_index]
+//! gives the slot of their signature in the transaction and [missing_signers] the
+//! signers still to go.
+
+use solana_program::{
+    hash::Hash, instruction::Instruction, message::Message, pubkey::Pubkey, system_instruction,
+};
+
+/// Prepends the `AdvanceNonceAccount` of the nonce account to the instructions, unless
+/// they already start with it.
+pub fn with_durable_nonce(
+    mut instructions: Vec<Instruction>,
+    nonce_pubkey: &Pubkey,
+    nonce_authority_pubkey: &Pubkey,
+) -> Vec<Instruction> {
+    let advance_nonce =
+        system_instruction::advance_nonce_account(nonce_pubkey, nonce_authority_pubkey);
+    if instructions.first() != Some(&advance_nonce) {
+        instructions.insert(0, advance_nonce);
+    }
+    instructions
+}
+
+/// Creates the message of a transaction using the durable nonce of the nonce account,
+/// signed over `nonce_hash`, the hash currently stored in the nonce account.
+pub fn durable_nonce_message(
+    instructions: Vec<Instruction>,
+    payer: &Pubkey,
+    nonce_pubkey: &Pubkey,
+    nonce_authority_pubkey: &Pubkey,
+    nonce_hash: &Hash,
+) -> Message {
+    let instructions = with_durable_nonce(instructions, nonce_pubkey, nonce_authority_pubkey);
+    let mut message = Message::new(&instructions, Some(payer));
+    message.recent_blockhash = *nonce_hash;
+    message
+}
+
+/// The signers of a message, in the order of the signatures of its transaction. The
+/// payer comes first.
+pub fn required_signers(message: &Message) -> &[Pubkey] {
+    let num_signers = usize::from(message.header.num_required_signatures);
+    &message.account_keys[..num_signers.min(message.account_keys.len())]
+}
+
+/// Position of the signature of `signer` in a transaction of the message, or `None` if
+/// the message does not need it.
+pub fn signature_index(message: &Message, signer: &Pubkey) -> Option<usize> {
+    required_signers(message)
+        .iter()
+        .position(|key| key == signer)
+}
+
+/// The signers of a message that did not sign it yet, given the ones that did.
+pub fn missing_signers(message: &Message, signed: &[Pubkey]) -> Vec<Pubkey> {
+    required_signers(message)
+        .iter()
+        .filter(|key| !signed.contains(key))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::instruction::{ramp_a, swap, with_admin_multisig_signers};
+    use solana_program::system_program;
+
+    #[test]
+    fn test_durable_nonce_message() {
+        let payer = Pubkey::new_unique();
+        let nonce = Pubkey::new_unique();
+        let nonce_authority = Pubkey::new_unique();
+        let nonce_hash = Hash::new_unique();
+        let swap_pubkey = Pubkey::new_unique();
+        let signers = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let ramp = with_admin_multisig_signers(
+            ramp_a(
+                &crate::id(),
+                &swap_pubkey,
+                &Pubkey::new_unique(),
+                100,
+                1_000_000,
+            )
+            .unwrap(),
+            &[&signers[0], &signers[1]],
+        );
+
+        let instructions = with_durable_nonce(vec![ramp.clone()], &nonce, &nonce_authority);
+        assert_eq!(
+            instructions,
+            vec![
+                system_instruction::advance_nonce_account(&nonce, &nonce_authority),
+                ramp.clone()
+            ]
+        );
+        // already advancing the nonce
+        assert_eq!(
+            with_durable_nonce(instructions.clone(), &nonce, &nonce_authority),
+            instructions
+        );
+
+        let message =
+            durable_nonce_message(vec![ramp], &payer, &nonce, &nonce_authority, &nonce_hash);
+        assert_eq!(message.recent_blockhash, nonce_hash);
+        // the swap account is not a signer of admin instructions
+        assert_eq!(
+            message.account_keys[usize::from(message.instructions[0].program_id_index)],
+            system_program::id()
+        );
+        assert_eq!(
+            required_signers(&message),
+            &[payer, nonce_authority, signers[0], signers[1]]
+        );
+        assert_eq!(signature_index(&message, &payer), Some(0));
+        assert_eq!(signature_index(&message, &signers[1]), Some(3));
+        assert_eq!(signature_index(&message, &swap_pubkey), None);
+        assert_eq!(
+            missing_signers(&message, &[payer, signers[1]]),
+            vec![nonce_authority, signers[0]]
+        );
+    }
+
+    #[test]
+    fn test_durable_nonce_message_signed_by_nonce_authority() {
+        let user = Pubkey::new_unique();
+        let nonce = Pubkey::new_unique();
+        let swap_ix = swap(
+            &crate::id(),
+            &spl_token::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &user,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            100,
+            90,
+        )
+        .unwrap();
+        // the user pays, holds the nonce and authorizes the swap
+        let message = durable_nonce_message(vec![swap_ix], &user, &nonce, &user, &Hash::default());
+        assert_eq!(required_signers(&message), &[user]);
+        assert!(missing_signers(&message, &[user]).is_empty());
+    }
+}
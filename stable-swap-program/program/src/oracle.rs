@@ -0,0 +1,307 @@
+//! Minimal readers of Pyth price accounts, used by the peg-deviation circuit breaker,
+//! and of SPL stake pools, whose exchange rate a pegged pool can follow.
+
+use crate::{bn::U256, curve::PEG_RATE_PRECISION, fees::BPS_DENOMINATOR};
+use arrayref::{array_ref, array_refs};
+use num_traits::ToPrimitive;
+
+/// Magic number of Pyth accounts
+const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+/// Version of the Pyth account layout
+const PYTH_VERSION: u32 = 2;
+/// Account type of Pyth price accounts
+const PYTH_PRICE_ACCOUNT: u32 = 3;
+/// Status of an aggregate price that can be used
+const PYTH_STATUS_TRADING: u32 = 1;
+/// Length of a Pyth price account up to the end of its aggregate price
+const PYTH_PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+/// Max number of slots since the publication of an oracle price
+pub const MAX_PRICE_AGE_SLOTS: u64 = 50;
+/// Max difference between the exponents of two oracle prices
+const MAX_EXPONENT_DIFFERENCE: u32 = 18;
+
+/// Account type of SPL stake pool accounts
+const STAKE_POOL_ACCOUNT: u8 = 1;
+/// Length of an SPL stake pool account up to the end of its last update epoch
+const STAKE_POOL_MIN_LEN: usize = 282;
+
+/// Aggregate price of a Pyth price account: `price * 10^expo`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OraclePrice {
+    /// Price, scaled by `10^expo`
+    pub price: i64,
+    /// Exponent of the price
+    pub expo: i32,
+    /// Slot of the publication of the price
+    pub pub_slot: u64,
+}
+
+/// Reads the aggregate price of a Pyth price account.
+/// Returns `None` if the account is not a Pyth price account or its price is not trading.
+pub fn read_pyth_price(data: &[u8]) -> Option<OraclePrice> {
+    let data = data.get(..PYTH_PRICE_ACCOUNT_MIN_LEN)?;
+    let data = array_ref![data, 0, PYTH_PRICE_ACCOUNT_MIN_LEN];
+    #[allow(clippy::ptr_offset_with_cast)]
+    let (
+        magic,
+        version,
+        account_type,
+        _size,
+        _price_type,
+        expo,
+        _,
+        price,
+        _conf,
+        status,
+        _,
+        pub_slot,
+    ) = array_refs![data, 4, 4, 4, 4, 4, 4, 184, 8, 8, 4, 4, 8];
+    if u32::from_le_bytes(*magic) != PYTH_MAGIC
+        || u32::from_le_bytes(*version) != PYTH_VERSION
+        || u32::from_le_bytes(*account_type) != PYTH_PRICE_ACCOUNT
+        || u32::from_le_bytes(*status) != PYTH_STATUS_TRADING
+    {
+        return None;
+    }
+    Some(OraclePrice {
+        price: i64::from_le_bytes(*price),
+        expo: i32::from_le_bytes(*expo),
+        pub_slot: u64::from_le_bytes(*pub_slot),
+    })
+}
+
+impl OraclePrice {
+    /// The price as a peg rate, in [PEG_RATE_PRECISION] units.
+    /// Returns `None` if the price is not positive or does not fit.
+    pub fn peg_rate(&self) -> Option<u64> {
+        if self.price <= 0 {
+            return None;
+        }
+        // PEG_RATE_PRECISION is 10^9
+        let exponent = self.expo.checked_add(9)?;
+        if exponent.unsigned_abs() > MAX_EXPONENT_DIFFERENCE {
+            return None;
+        }
+        let scale = 10u64.pow(exponent.unsigned_abs());
+        if exponent >= 0 {
+            (self.price as u64).checked_mul(scale)
+        } else {
+            Some(self.price as u64 / scale)
+        }
+    }
+}
+
+/// Balances of an SPL stake pool, whose pool tokens are worth
+/// `total_lamports / pool_token_supply` lamports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StakePoolBalance {
+    /// Lamports staked by the pool, as of its last update
+    pub total_lamports: u64,
+    /// Supply of the pool tokens
+    pub pool_token_supply: u64,
+    /// Epoch of the last update of the balances
+    pub last_update_epoch: u64,
+}
+
+impl StakePoolBalance {
+    /// Lamports per pool token, in [PEG_RATE_PRECISION] units, rounded down.
+    /// Returns `None` for an empty pool.
+    pub fn peg_rate(&self) -> Option<u64> {
+        if self.pool_token_supply == 0 {
+            return None;
+        }
+        (u128::from(self.total_lamports) * u128::from(PEG_RATE_PRECISION)
+            / u128::from(self.pool_token_supply))
+        .to_u64()
+    }
+}
+
+/// Reads the balances of an SPL stake pool account.
+/// Returns `None` if the account is not a stake pool.
+pub fn read_stake_pool_balance(data: &[u8]) -> Option<StakePoolBalance> {
+    let data = data.get(..STAKE_POOL_MIN_LEN)?;
+    let data = array_ref![data, 0, STAKE_POOL_MIN_LEN];
+    #[allow(clippy::ptr_offset_with_cast)]
+    let (account_type, _, total_lamports, pool_token_supply, last_update_epoch) =
+        array_refs![data, 1, 257, 8, 8, 8];
+    if account_type[0] != STAKE_POOL_ACCOUNT {
+        return None;
+    }
+    Some(StakePoolBalance {
+        total_lamports: u64::from_le_bytes(*total_lamports),
+        pool_token_supply: u64::from_le_bytes(*pool_token_supply),
+        last_update_epoch: u64::from_le_bytes(*last_update_epoch),
+    })
+}
+
+/// Deviation, in bps, of the pool price `amount_out / amount_in` from the
+/// oracle price of the input token in the output token.
+pub fn price_deviation_bps(
+    amount_in: u64,
+    amount_out: u64,
+    price_in: &OraclePrice,
+    price_out: &OraclePrice,
+) -> Option<u64> {
+    if price_in.price <= 0 || price_out.price <= 0 || amount_in == 0 {
+        return None;
+    }
+    // Scale both prices to the smaller exponent
+    let min_expo = price_in.expo.min(price_out.expo);
+    let scale = |price: &OraclePrice| -> Option<U256> {
+        let exponent = price.expo.checked_sub(min_expo)? as u32;
+        if exponent > MAX_EXPONENT_DIFFERENCE {
+            return None;
+        }
+        U256::from(price.price as u64).checked_mul(U256::from(10u64.pow(exponent)))
+    };
+    // Value of the input and of the output, in the unit of the scaled prices
+    let value_in = U256::from(amount_in).checked_mul(scale(price_in)?)?;
+    let value_out = U256::from(amount_out).checked_mul(scale(price_out)?)?;
+    let difference = if value_in > value_out {
+        value_in - value_out
+    } else {
+        value_out - value_in
+    };
+    difference
+        .checked_mul(BPS_DENOMINATOR.into())?
+        .checked_div(value_in)?
+        .to_u64()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn pyth_price_account(price: i64, expo: i32, status: u32, pub_slot: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 3312];
+        data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&PYTH_VERSION.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_PRICE_ACCOUNT.to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[224..228].copy_from_slice(&status.to_le_bytes());
+        data[232..240].copy_from_slice(&pub_slot.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_read_pyth_price() {
+        let data = pyth_price_account(100_000_000, -8, PYTH_STATUS_TRADING, 7);
+        assert_eq!(
+            read_pyth_price(&data),
+            Some(OraclePrice {
+                price: 100_000_000,
+                expo: -8,
+                pub_slot: 7,
+            })
+        );
+
+        // halted
+        let data = pyth_price_account(100_000_000, -8, 2, 7);
+        assert_eq!(read_pyth_price(&data), None);
+        // not a price account
+        let mut data = pyth_price_account(100_000_000, -8, PYTH_STATUS_TRADING, 7);
+        data[8] = 2;
+        assert_eq!(read_pyth_price(&data), None);
+        // too short
+        assert_eq!(read_pyth_price(&data[..239]), None);
+    }
+
+    #[test]
+    fn test_price_deviation_bps() {
+        let one = OraclePrice {
+            price: 100_000_000,
+            expo: -8,
+            pub_slot: 0,
+        };
+        assert_eq!(price_deviation_bps(1_000, 1_000, &one, &one), Some(0));
+        assert_eq!(price_deviation_bps(1_000, 990, &one, &one), Some(100));
+        assert_eq!(price_deviation_bps(1_000, 1_010, &one, &one), Some(100));
+
+        // the input token depegged to 0.9 with another exponent
+        let depegged = OraclePrice {
+            price: 9_000,
+            expo: -4,
+            pub_slot: 0,
+        };
+        assert_eq!(price_deviation_bps(1_000, 900, &depegged, &one), Some(0));
+        assert_eq!(
+            price_deviation_bps(1_000, 1_000, &depegged, &one),
+            Some(1_111)
+        );
+        assert_eq!(
+            price_deviation_bps(1_000, 1_000, &one, &depegged),
+            Some(1_000)
+        );
+
+        // invalid prices
+        let negative = OraclePrice {
+            price: -1,
+            expo: -8,
+            pub_slot: 0,
+        };
+        assert_eq!(price_deviation_bps(1_000, 1_000, &negative, &one), None);
+        let huge_expo = OraclePrice {
+            price: 1,
+            expo: 12,
+            pub_slot: 0,
+        };
+        assert_eq!(price_deviation_bps(1_000, 1_000, &huge_expo, &one), None);
+    }
+
+    #[test]
+    fn test_peg_rate() {
+        let price = |price: i64, expo: i32| OraclePrice {
+            price,
+            expo,
+            pub_slot: 0,
+        };
+        assert_eq!(price(105_000_000, -8).peg_rate(), Some(1_050_000_000));
+        assert_eq!(price(1_050, -3).peg_rate(), Some(1_050_000_000));
+        assert_eq!(
+            price(1_234_567_890_123, -12).peg_rate(),
+            Some(1_234_567_890)
+        );
+        assert_eq!(price(0, -8).peg_rate(), None);
+        assert_eq!(price(-1, -8).peg_rate(), None);
+        assert_eq!(price(1, 10).peg_rate(), None);
+
+        let stake_pool = StakePoolBalance {
+            total_lamports: 1_100_000,
+            pool_token_supply: 1_000_000,
+            last_update_epoch: 0,
+        };
+        assert_eq!(stake_pool.peg_rate(), Some(1_100_000_000));
+        let empty = StakePoolBalance {
+            pool_token_supply: 0,
+            ..stake_pool
+        };
+        assert_eq!(empty.peg_rate(), None);
+    }
+
+    #[test]
+    fn test_read_stake_pool_balance() {
+        let mut data = vec![0u8; 611];
+        data[0] = STAKE_POOL_ACCOUNT;
+        data[258..266].copy_from_slice(&1_100u64.to_le_bytes());
+        data[266..274].copy_from_slice(&1_000u64.to_le_bytes());
+        data[274..282].copy_from_slice(&42u64.to_le_bytes());
+        assert_eq!(
+            read_stake_pool_balance(&data),
+            Some(StakePoolBalance {
+                total_lamports: 1_100,
+                pool_token_supply: 1_000,
+                last_update_epoch: 42,
+            })
+        );
+
+        // uninitialized
+        data[0] = 0;
+        assert_eq!(read_stake_pool_balance(&data), None);
+        // too short
+        data[0] = STAKE_POOL_ACCOUNT;
+        assert_eq!(read_stake_pool_balance(&data[..281]), None);
+    }
+}
@@ -1,10 +1,17 @@
 //! Conversions for pool tokens
-use num_traits::ToPrimitive;
-
-use crate::fees::Fees;
+//!
+//! [PoolTokenConverter] computes what a proportional withdrawal pays out exactly as
+//! the program does, so that wallets can show the value of pool tokens before they
+//! are redeemed.
+use crate::{fees::Fees, math};
 
 /// Converter to determine how much to deposit / withdraw, along with
 /// proper initialization
+///
+/// The supply is the supply of the pool mint, the token amounts the balances of the
+/// reserve accounts and the fees those the swap charges at the time of the
+/// withdrawal, see [SwapInfo::current_fees](../state/struct.SwapInfo.html#method.current_fees).
+/// Users exempt from the withdraw fee are previewed with a zero withdraw fee.
 pub struct PoolTokenConverter<'a> {
     /// Total supply
     pub supply: u64,
@@ -16,36 +23,63 @@ pub struct PoolTokenConverter<'a> {
     pub fees: &'a Fees,
 }
 
+/// What a proportional withdrawal of pool tokens pays out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WithdrawPreview {
+    /// Token A paid to the user, net of the withdraw fee
+    pub a: u64,
+    /// Token B paid to the user, net of the withdraw fee
+    pub b: u64,
+    /// Withdraw fees in token A and token B, including the admin fees
+    pub fees: [u64; 2],
+    /// Shares of the withdraw fees in token A and token B sent to the admin fee
+    /// accounts. The rest stays in the pool, with the liquidity providers.
+    pub admin_fees: [u64; 2],
+}
+
 impl PoolTokenConverter<'_> {
-    /// A tokens for pool tokens
+    /// A tokens for pool tokens, as `(amount, fee, admin_fee)`: the amount paid to the
+    /// user net of the withdraw fee, the withdraw fee, and the admin share of that fee.
+    /// Rounds down, the tokens leave the pool.
     pub fn token_a_rate(&self, pool_tokens: u64) -> Option<(u64, u64, u64)> {
-        let amount = (pool_tokens as u128)
-            .checked_mul(self.token_a as u128)?
-            .checked_div(self.supply as u128)?
-            .to_u64()?;
+        let amount = math::mul_div_floor(pool_tokens, self.token_a, self.supply)?;
         let fee = self.fees.withdraw_fee(amount)?;
         let admin_fee = self.fees.admin_withdraw_fee(fee)?;
 
         Some((amount.checked_sub(fee)?, fee, admin_fee))
     }
 
-    /// B tokens for pool tokens
+    /// B tokens for pool tokens, as `(amount, fee, admin_fee)`, see
+    /// [token_a_rate](Self::token_a_rate). Rounds down, the tokens leave the pool.
     pub fn token_b_rate(&self, pool_tokens: u64) -> Option<(u64, u64, u64)> {
-        let amount = (pool_tokens as u128)
-            .checked_mul(self.token_b as u128)?
-            .checked_div(self.supply as u128)?
-            .to_u64()?;
+        let amount = math::mul_div_floor(pool_tokens, self.token_b, self.supply)?;
         let fee = self.fees.withdraw_fee(amount)?;
         let admin_fee = self.fees.admin_withdraw_fee(fee)?;
 
         Some((amount.checked_sub(fee)?, fee, admin_fee))
     }
+
+    /// Previews a proportional withdrawal of `pool_token_amount`. The amounts are
+    /// those a [Withdraw](../instruction/enum.SwapInstruction.html#variant.Withdraw)
+    /// pays out against the same reserves and fees.
+    pub fn withdraw_preview(&self, pool_token_amount: u64) -> Option<WithdrawPreview> {
+        let (a, a_fee, a_admin_fee) = self.token_a_rate(pool_token_amount)?;
+        let (b, b_fee, b_admin_fee) = self.token_b_rate(pool_token_amount)?;
+        Some(WithdrawPreview {
+            a,
+            b,
+            fees: [a_fee, b_fee],
+            admin_fees: [a_admin_fee, b_admin_fee],
+        })
+    }
 }
 
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use crate::curve;
+    use crate::fees::{BPS_DENOMINATOR, NO_DYNAMIC_FEE, NO_FEE_TIERS};
+    use proptest::prelude::*;
 
     use super::*;
 
@@ -65,6 +99,10 @@ mod tests {
             trade_fee_denominator: 1,
             withdraw_fee_numerator: 1,
             withdraw_fee_denominator: 2,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
         };
         let calculator = PoolTokenConverter {
             supply,
@@ -91,4 +129,62 @@ mod tests {
         check_pool_token_a_rate(5, 100, 5, 10, Some(2));
         check_pool_token_a_rate(5, curve::MAX_TOKENS_IN, 5, 10, Some(2));
     }
+
+    #[test]
+    fn withdraw_preview() {
+        let fees = Fees::from_bps(0, 100, 5_000).unwrap();
+        let calculator = PoolTokenConverter {
+            supply: 1_000,
+            token_a: 4_000,
+            token_b: 2_000,
+            fees: &fees,
+        };
+        assert_eq!(
+            calculator.withdraw_preview(100),
+            Some(WithdrawPreview {
+                a: 396,
+                b: 198,
+                fees: [4, 2],
+                admin_fees: [2, 1],
+            })
+        );
+        let no_supply = PoolTokenConverter {
+            supply: 0,
+            ..calculator
+        };
+        assert_eq!(no_supply.withdraw_preview(100), None);
+    }
+
+    proptest! {
+        #[test]
+        fn withdraw_rounds_in_favor_of_the_pool(
+            token_a in 1..u64::MAX,
+            supply in 1..u64::MAX,
+            pool_tokens: u64,
+            withdraw_fee_bps in 0..=BPS_DENOMINATOR,
+        ) {
+            let pool_tokens = pool_tokens % supply;
+            let fees = Fees::from_bps(0, withdraw_fee_bps, 5_000).unwrap();
+            let calculator = PoolTokenConverter {
+                supply,
+                token_a,
+                token_b: 0,
+                fees: &fees,
+            };
+            let (amount, fee, admin_fee) = calculator.token_a_rate(pool_tokens).unwrap();
+            let amount_before_fees = u128::from(amount + fee);
+            // the share of the reserves withdrawn never exceeds the share of pool tokens burnt
+            prop_assert!(
+                amount_before_fees * u128::from(supply)
+                    <= u128::from(pool_tokens) * u128::from(token_a)
+            );
+            // the fee kept by the pool is never less than the exact fee
+            prop_assert!(
+                u128::from(fee) * u128::from(BPS_DENOMINATOR)
+                    >= amount_before_fees * u128::from(withdraw_fee_bps)
+            );
+            // the admin never takes more than its exact share of the fee
+            prop_assert!(u128::from(admin_fee) * 2 <= u128::from(fee));
+        }
+    }
 }
@@ -0,0 +1,121 @@
+//! Vetted pool parameters, so that new pools do not hand-roll their fee fractions.
+//!
+//! Initialize logs a warning for pools whose amp factor and fees match no preset,
+//! see [PoolPreset::find]. Pools may still use other parameters.
+
+use crate::fees::{Fees, BPS_DENOMINATOR, NO_DYNAMIC_FEE, NO_FEE_TIERS};
+
+/// Amplification coefficient and fees of a kind of pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolPreset {
+    /// Name of the preset, as logged
+    pub name: &'static str,
+    /// Amplification coefficient (A), in whole units
+    pub amp_factor: u64,
+    /// Fees
+    pub fees: Fees,
+}
+
+/// Flat fees in basis points, half of them going to the admin
+const fn preset_fees(trade_fee_bps: u64, withdraw_fee_bps: u64) -> Fees {
+    Fees {
+        admin_trade_fee_numerator: 5_000,
+        admin_trade_fee_denominator: BPS_DENOMINATOR,
+        admin_withdraw_fee_numerator: 5_000,
+        admin_withdraw_fee_denominator: BPS_DENOMINATOR,
+        trade_fee_numerator: trade_fee_bps,
+        trade_fee_denominator: BPS_DENOMINATOR,
+        withdraw_fee_numerator: withdraw_fee_bps,
+        withdraw_fee_denominator: BPS_DENOMINATOR,
+        trade_fee_tiers: NO_FEE_TIERS,
+        dynamic_fee: NO_DYNAMIC_FEE,
+        rebalancing_rebate_bps: 0,
+        host_fee_bps: 0,
+    }
+}
+
+/// Fiat stablecoins of the same currency and issuers of similar standing, e.g. USDC/USDT
+pub const STABLE_1BP: PoolPreset = PoolPreset {
+    name: "STABLE_1BP",
+    amp_factor: 200,
+    fees: preset_fees(1, 0),
+};
+
+/// Assets pegged to each other by a bridge or a wrapper, e.g. wrapped BTC/renBTC
+pub const PEGGED_4BP: PoolPreset = PoolPreset {
+    name: "PEGGED_4BP",
+    amp_factor: 100,
+    fees: preset_fees(4, 0),
+};
+
+/// Assets whose peg drifts, e.g. a liquid staking token and its underlying without a
+/// peg rate source, or an algorithmic stablecoin
+pub const VOLATILE_PEG_10BP: PoolPreset = PoolPreset {
+    name: "VOLATILE_PEG_10BP",
+    amp_factor: 20,
+    fees: preset_fees(10, 5),
+};
+
+/// Every preset
+pub const PRESETS: [PoolPreset; 3] = [STABLE_1BP, PEGGED_4BP, VOLATILE_PEG_10BP];
+
+impl PoolPreset {
+    /// The preset with the amp factor and fees, if any
+    pub fn find(amp_factor: u64, fees: &Fees) -> Option<&'static Self> {
+        let presets: &'static [Self] = &PRESETS;
+        presets
+            .iter()
+            .find(|preset| preset.amp_factor == amp_factor && preset.fees == *fees)
+    }
+
+    /// The preset named `name`, if any
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        let presets: &'static [Self] = &PRESETS;
+        presets.iter().find(|preset| preset.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{MAX_AMP, MIN_AMP};
+
+    #[test]
+    fn test_presets_are_valid() {
+        for preset in PRESETS.iter() {
+            assert_eq!(preset.fees.validate(), Ok(()), "{}", preset.name);
+            assert!(preset.fees.validate_trade_fee_tiers(), "{}", preset.name);
+            assert!(preset.fees.validate_dynamic_fee(), "{}", preset.name);
+            assert!(preset.fees.validate_rebalancing_rebate(), "{}", preset.name);
+            assert!(
+                (MIN_AMP..=MAX_AMP).contains(&preset.amp_factor),
+                "{}",
+                preset.name
+            );
+            assert_eq!(
+                Fees::from_bps(
+                    preset.fees.trade_fee_numerator,
+                    preset.fees.withdraw_fee_numerator,
+                    5_000
+                ),
+                Ok(preset.fees)
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_preset() {
+        assert_eq!(PoolPreset::find(200, &STABLE_1BP.fees), Some(&PRESETS[0]));
+        assert_eq!(
+            PoolPreset::from_name("VOLATILE_PEG_10BP"),
+            Some(&VOLATILE_PEG_10BP)
+        );
+        assert_eq!(PoolPreset::find(100, &STABLE_1BP.fees), None);
+        // the same fee as another fraction
+        let mut fees = PEGGED_4BP.fees;
+        fees.trade_fee_numerator = 8;
+        fees.trade_fee_denominator = 2 * BPS_DENOMINATOR;
+        assert_eq!(PoolPreset::find(100, &fees), None);
+        assert_eq!(PoolPreset::from_name("STABLE_0BP"), None);
+    }
+}
@@ -1,28 +1,52 @@
 //! Module for processing admin-only instructions.
 
 use crate::{
-    curve::{StableSwap, MAX_AMP, MIN_AMP, MIN_RAMP_DURATION, ZERO_TS},
+    curve::{StableSwap, AMP_PRECISION, MAX_AMP, MIN_AMP, MIN_RAMP_DURATION, RAMP_DELAY, ZERO_TS},
     error::SwapError,
-    fees::Fees,
-    instruction::{AdminInstruction, RampAData},
+    fees::{DynamicFee, FeeTier, Fees, BPS_DENOMINATOR, MAX_FEE_TIERS},
+    instruction::{
+        AdminInstruction, CollectAdminFeesData, RampAData, RampFeesData, SetAdminMultisigData,
+        SetMaxLpPerOwnerData, SetPoolInfoData, SetPoolMetadataData,
+    },
+    metadata, oracle,
     processor::utils,
-    state::SwapInfo,
+    state::{
+        find_admin_multisig_address, find_cpi_allowlist_address, find_deposit_allowlist_address,
+        find_deposit_caps_address, find_fee_exemptions_address, find_last_trade_address,
+        find_peg_config_address, find_pool_migration_address, find_pool_registry_entry_address,
+        find_pool_stats_address, find_rewards_distributor_address, find_shared_authority_address,
+        find_staking_config_address, AdminMultisig, CpiAllowlist, CpiPolicy, DepositAllowlist,
+        DepositCaps, FeeExemptions, LastTrade, PegConfig, PegRateSource, PoolCategory,
+        PoolMigration, PoolRegistryEntry, PoolStats, RewardsDistributor, StakingConfig, SwapInfo,
+        ADMIN_MULTISIG_SEED, CPI_ALLOWLIST_SEED, DEPOSIT_ALLOWLIST_SEED, DEPOSIT_CAPS_SEED,
+        FEE_EXEMPTIONS_SEED, LAST_TRADE_SEED, MAX_ADMIN_SIGNERS, MAX_CPI_ALLOWLIST_PROGRAMS,
+        MAX_DEPOSIT_ALLOWLIST_ACCOUNTS, MAX_DEPOSIT_CAP_EXEMPT_OWNERS, MAX_FEE_EXEMPT_ACCOUNTS,
+        MAX_POOL_NAME_LEN, PEG_CONFIG_SEED, POOL_MIGRATION_SEED, POOL_REGISTRY_ENTRY_SEED,
+        POOL_STATS_SEED, REWARDS_DISTRIBUTOR_SEED, STAKING_CONFIG_SEED, SWAP_INFO_LEGACY_LEN,
+    },
 };
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_option::COption,
     program_pack::Pack,
     pubkey::Pubkey,
-    sysvar::{clock::Clock, Sysvar},
 };
+use spl_token::instruction::AuthorityType;
 
-use super::checks::check_has_admin_signer;
+use super::checks::{check_has_admin_signers, check_swap_authority, read_peg_rate};
+use super::logging::{log_event, Event};
+use super::token;
 
 const ADMIN_TRANSFER_DELAY: i64 = 259200; // 3 days
+const FEE_CHANGE_DELAY: i64 = 86400; // 1 day
 
 /// Process admin instruction
 pub fn process_admin_instruction(
+    program_id: &Pubkey,
     instruction: &AdminInstruction,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
@@ -31,7 +55,13 @@ pub fn process_admin_instruction(
     let admin_info = next_account_info(account_info_iter)?;
 
     let token_swap = &mut SwapInfo::unpack(&swap_info.data.borrow_mut())?;
-    check_has_admin_signer(&token_swap.admin_key, admin_info)?;
+    check_has_admin_signers(
+        program_id,
+        swap_info.key,
+        &token_swap.admin_key,
+        admin_info,
+        account_info_iter.as_slice(),
+    )?;
 
     (match *instruction {
         AdminInstruction::RampA(RampAData {
@@ -39,6 +69,19 @@ pub fn process_admin_instruction(
             stop_ramp_ts,
         }) => {
             msg!("Instruction: RampA");
+            // Out of range once scaled if it overflows
+            let target_amp = target_amp.saturating_mul(AMP_PRECISION);
+            ramp_a(token_swap, target_amp, stop_ramp_ts, account_info_iter)
+        }
+        AdminInstruction::RampAPrecise(RampAData {
+            target_amp,
+            stop_ramp_ts,
+        }) => {
+            msg!("Instruction: RampAPrecise");
+            // Legacy accounts only store whole coefficients
+            if swap_info.data_len() == SWAP_INFO_LEGACY_LEN && target_amp % AMP_PRECISION != 0 {
+                return Err(ProgramError::AccountDataTooSmall);
+            }
             ramp_a(token_swap, target_amp, stop_ramp_ts, account_info_iter)
         }
         AdminInstruction::StopRampA => {
@@ -55,7 +98,7 @@ pub fn process_admin_instruction(
         }
         AdminInstruction::SetFeeAccount => {
             msg!("Instruction: SetFeeAccount");
-            set_fee_account(token_swap, account_info_iter)
+            set_fee_account(program_id, swap_info.key, token_swap, account_info_iter)
         }
         AdminInstruction::ApplyNewAdmin => {
             msg!("Instruction: ApplyNewAdmin");
@@ -67,27 +110,284 @@ pub fn process_admin_instruction(
         }
         AdminInstruction::SetNewFees(new_fees) => {
             msg!("Instruction: SetNewFees");
-            set_new_fees(token_swap, &new_fees)
+            commit_new_fees(token_swap, &new_fees, account_info_iter)
+        }
+        AdminInstruction::SetFeeTiers(tiers) => {
+            msg!("Instruction: SetFeeTiers");
+            set_fee_tiers(token_swap, &tiers, account_info_iter)
+        }
+        AdminInstruction::SetFeeMode(dynamic_fee) => {
+            msg!("Instruction: SetFeeMode");
+            set_fee_mode(token_swap, &dynamic_fee, account_info_iter)
+        }
+        AdminInstruction::CommitNewFees(new_fees) => {
+            msg!("Instruction: CommitNewFees");
+            commit_new_fees(token_swap, &new_fees, account_info_iter)
+        }
+        AdminInstruction::ApplyNewFees => {
+            msg!("Instruction: ApplyNewFees");
+            apply_new_fees(token_swap, account_info_iter)
+        }
+        AdminInstruction::CancelPending => {
+            msg!("Instruction: CancelPending");
+            cancel_pending(token_swap)
+        }
+        AdminInstruction::SetAdminMultisig(SetAdminMultisigData {
+            threshold,
+            ref signers,
+        }) => {
+            msg!("Instruction: SetAdminMultisig");
+            set_admin_multisig(
+                program_id,
+                swap_info.key,
+                threshold,
+                signers,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetPoolMetadata(SetPoolMetadataData {
+            ref name,
+            ref symbol,
+            ref uri,
+        }) => {
+            msg!("Instruction: SetPoolMetadata");
+            set_pool_metadata(
+                program_id,
+                swap_info.key,
+                token_swap,
+                (name, symbol, uri),
+                account_info_iter,
+            )
+        }
+        AdminInstruction::CollectAdminFees(CollectAdminFeesData {
+            token_a_amount,
+            token_b_amount,
+        }) => {
+            msg!("Instruction: CollectAdminFees");
+            collect_admin_fees(
+                program_id,
+                swap_info.key,
+                token_swap,
+                token_a_amount,
+                token_b_amount,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetFeeExemptions(ref exempt_accounts) => {
+            msg!("Instruction: SetFeeExemptions");
+            set_fee_exemptions(
+                program_id,
+                swap_info.key,
+                exempt_accounts,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::InitPoolStats => {
+            msg!("Instruction: InitPoolStats");
+            init_pool_stats(program_id, swap_info.key, account_info_iter)
+        }
+        AdminInstruction::SetDonationsAdminOnly(admin_only) => {
+            msg!("Instruction: SetDonationsAdminOnly");
+            set_donations_admin_only(token_swap, admin_only)
+        }
+        AdminInstruction::SetSplTokenSwapCompat(enabled) => {
+            msg!("Instruction: SetSplTokenSwapCompat");
+            set_spl_token_swap_compat(token_swap, enabled)
+        }
+        AdminInstruction::SetPriceOracles(max_deviation_bps) => {
+            msg!("Instruction: SetPriceOracles");
+            set_price_oracles(token_swap, max_deviation_bps, account_info_iter)
+        }
+        AdminInstruction::RampFees(RampFeesData {
+            ref target_fees,
+            stop_ramp_ts,
+        }) => {
+            msg!("Instruction: RampFees");
+            ramp_fees(token_swap, target_fees, stop_ramp_ts, account_info_iter)
+        }
+        AdminInstruction::SetDepositAllowlist(ref allowed_accounts) => {
+            msg!("Instruction: SetDepositAllowlist");
+            set_deposit_allowlist(
+                program_id,
+                swap_info.key,
+                allowed_accounts,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetDepositAllowlistEnabled(enabled) => {
+            msg!("Instruction: SetDepositAllowlistEnabled");
+            set_deposit_allowlist_enabled(token_swap, enabled)
+        }
+        AdminInstruction::MigratePool => {
+            msg!("Instruction: MigratePool");
+            migrate_pool(program_id, swap_info.key, token_swap, account_info_iter)
+        }
+        AdminInstruction::SetMaxSwapBps(max_swap_bps) => {
+            msg!("Instruction: SetMaxSwapBps");
+            set_max_swap_bps(token_swap, max_swap_bps)
+        }
+        AdminInstruction::SetPoolInfo(SetPoolInfoData {
+            ref name,
+            category,
+            is_deprecated,
+        }) => {
+            msg!("Instruction: SetPoolInfo");
+            set_pool_info(
+                program_id,
+                swap_info.key,
+                name,
+                category,
+                is_deprecated,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetRewardsDistributor => {
+            msg!("Instruction: SetRewardsDistributor");
+            set_rewards_distributor(program_id, swap_info.key, token_swap, account_info_iter)
+        }
+        AdminInstruction::FreezeLp => {
+            msg!("Instruction: FreezeLp");
+            set_lp_frozen(
+                program_id,
+                swap_info.key,
+                token_swap,
+                true,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::ThawLp => {
+            msg!("Instruction: ThawLp");
+            set_lp_frozen(
+                program_id,
+                swap_info.key,
+                token_swap,
+                false,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::DeprecatePool => {
+            msg!("Instruction: DeprecatePool");
+            deprecate_pool(swap_info, token_swap)
+        }
+        AdminInstruction::MigrateToSharedAuthority => {
+            msg!("Instruction: MigrateToSharedAuthority");
+            migrate_to_shared_authority(program_id, swap_info, token_swap, account_info_iter)
+        }
+        AdminInstruction::SetRejectZeroAmounts(reject) => {
+            msg!("Instruction: SetRejectZeroAmounts");
+            set_reject_zero_amounts(swap_info, token_swap, reject)
+        }
+        AdminInstruction::SetStakingConfig(stake_instruction_prefix) => {
+            msg!("Instruction: SetStakingConfig");
+            set_staking_config(
+                program_id,
+                swap_info.key,
+                stake_instruction_prefix,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetWithdrawFeeGracePeriod(withdraw_fee_grace_period) => {
+            msg!("Instruction: SetWithdrawFeeGracePeriod");
+            set_withdraw_fee_grace_period(swap_info, token_swap, withdraw_fee_grace_period)
+        }
+        AdminInstruction::SetCpiPolicy(cpi_policy) => {
+            msg!("Instruction: SetCpiPolicy");
+            set_cpi_policy(swap_info, token_swap, cpi_policy)
+        }
+        AdminInstruction::SetCpiAllowlist(ref allowed_programs) => {
+            msg!("Instruction: SetCpiAllowlist");
+            set_cpi_allowlist(
+                program_id,
+                swap_info.key,
+                allowed_programs,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetPegRateSource(rate_source) => {
+            msg!("Instruction: SetPegRateSource");
+            set_peg_rate_source(
+                program_id,
+                swap_info,
+                token_swap,
+                rate_source,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetQuickSwapMaxBps(quick_swap_max_bps) => {
+            msg!("Instruction: SetQuickSwapMaxBps");
+            set_quick_swap_max_bps(swap_info, token_swap, quick_swap_max_bps)
+        }
+        AdminInstruction::SetSameSlotGuard(enabled) => {
+            msg!("Instruction: SetSameSlotGuard");
+            set_same_slot_guard(
+                program_id,
+                swap_info,
+                token_swap,
+                enabled,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetAdminFeesAsLp(enabled) => {
+            msg!("Instruction: SetAdminFeesAsLp");
+            set_admin_fees_as_lp(swap_info, token_swap, enabled)
+        }
+        AdminInstruction::SetWithdrawOneWhilePaused(enabled) => {
+            msg!("Instruction: SetWithdrawOneWhilePaused");
+            set_withdraw_one_while_paused(swap_info, token_swap, enabled)
+        }
+        AdminInstruction::SetQuietLogs(enabled) => {
+            msg!("Instruction: SetQuietLogs");
+            set_quiet_logs(swap_info, token_swap, enabled)
+        }
+        AdminInstruction::SetMaxLpSupply(max_lp_supply) => {
+            msg!("Instruction: SetMaxLpSupply");
+            set_max_lp_supply(
+                program_id,
+                swap_info,
+                token_swap,
+                max_lp_supply,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::SetMaxLpPerOwner(SetMaxLpPerOwnerData {
+            max_lp_per_owner,
+            ref exempt_owners,
+        }) => {
+            msg!("Instruction: SetMaxLpPerOwner");
+            set_max_lp_per_owner(
+                program_id,
+                swap_info,
+                token_swap,
+                max_lp_per_owner,
+                exempt_owners,
+                account_info_iter,
+            )
+        }
+        AdminInstruction::EmergencyAmpDown(divisor) => {
+            msg!("Instruction: EmergencyAmpDown");
+            emergency_amp_down(swap_info, token_swap, divisor, account_info_iter)
         }
     })?;
 
     SwapInfo::pack(*token_swap, &mut swap_info.data.borrow_mut())
 }
 
-/// Ramp to future a
-fn ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+/// Ramp to future a, in [AMP_PRECISION] units, starting after [RAMP_DELAY]
+fn ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
     token_swap: &mut SwapInfo,
     target_amp: u64,
     stop_ramp_ts: i64,
     account_info_iter: &mut I,
 ) -> ProgramResult {
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
 
-    if !(MIN_AMP..=MAX_AMP).contains(&target_amp) {
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if !(MIN_AMP * AMP_PRECISION..=MAX_AMP * AMP_PRECISION).contains(&target_amp) {
         return Err(SwapError::InvalidInput.into());
     }
 
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
     let ramp_lock_ts = token_swap
         .start_ramp_ts
         .checked_add(MIN_RAMP_DURATION)
@@ -95,8 +395,11 @@ fn ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
     if clock.unix_timestamp < ramp_lock_ts {
         return Err(SwapError::RampLocked.into());
     }
-    let min_ramp_ts = clock
+    let start_ramp_ts = clock
         .unix_timestamp
+        .checked_add(RAMP_DELAY)
+        .ok_or(SwapError::CalculationFailure)?;
+    let min_ramp_ts = start_ramp_ts
         .checked_add(MIN_RAMP_DURATION)
         .ok_or(SwapError::CalculationFailure)?;
     if stop_ramp_ts < min_ramp_ts {
@@ -126,24 +429,97 @@ fn ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
 
     token_swap.initial_amp_factor = current_amp;
     token_swap.target_amp_factor = target_amp;
-    token_swap.start_ramp_ts = clock.unix_timestamp;
+    token_swap.start_ramp_ts = start_ramp_ts;
     token_swap.stop_ramp_ts = stop_ramp_ts;
     msg!(
-        "Admin: Ramping A to {}, ending at {}",
+        "Admin: Ramping A to {} hundredths, starting at {} and ending at {}",
         target_amp,
+        start_ramp_ts,
+        stop_ramp_ts
+    );
+    Ok(())
+}
+
+/// Ramp the trade and withdraw fee numerators to those of the future fees,
+/// starting after [RAMP_DELAY]
+fn ramp_fees<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    target_fees: &Fees,
+    stop_ramp_ts: i64,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let clock = utils::next_clock(account_info_iter)?;
+
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if target_fees.trade_fee_denominator != token_swap.fees.trade_fee_denominator
+        || target_fees.withdraw_fee_denominator != token_swap.fees.withdraw_fee_denominator
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let ramp_lock_ts = token_swap
+        .start_fee_ramp_ts
+        .checked_add(MIN_RAMP_DURATION)
+        .ok_or(SwapError::CalculationFailure)?;
+    if clock.unix_timestamp < ramp_lock_ts {
+        return Err(SwapError::RampLocked.into());
+    }
+    let start_ramp_ts = clock
+        .unix_timestamp
+        .checked_add(RAMP_DELAY)
+        .ok_or(SwapError::CalculationFailure)?;
+    let min_ramp_ts = start_ramp_ts
+        .checked_add(MIN_RAMP_DURATION)
+        .ok_or(SwapError::CalculationFailure)?;
+    if stop_ramp_ts < min_ramp_ts {
+        return Err(SwapError::InsufficientRampTime.into());
+    }
+
+    let current_fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+    let initial_fees = Fees {
+        trade_fee_numerator: current_fees.trade_fee_numerator,
+        withdraw_fee_numerator: current_fees.withdraw_fee_numerator,
+        ..*target_fees
+    };
+    // Only the numerators ramp, the other fees change through CommitNewFees
+    if initial_fees != current_fees {
+        return Err(SwapError::InvalidInput.into());
+    }
+    // The fees must be valid at both ends of the ramp
+    for fees in [&initial_fees, target_fees] {
+        fees.validate()?;
+        if !fees.validate_trade_fee_tiers()
+            || !fees.validate_dynamic_fee()
+            || !fees.validate_rebalancing_rebate()
+        {
+            return Err(SwapError::InvalidInput.into());
+        }
+    }
+
+    token_swap.initial_trade_fee_numerator = current_fees.trade_fee_numerator;
+    token_swap.initial_withdraw_fee_numerator = current_fees.withdraw_fee_numerator;
+    token_swap.fees = *target_fees;
+    token_swap.start_fee_ramp_ts = start_ramp_ts;
+    token_swap.stop_fee_ramp_ts = stop_ramp_ts;
+    msg!(
+        "Admin: Ramping fees, starting at {} and ending at {}",
+        start_ramp_ts,
         stop_ramp_ts
     );
     Ok(())
 }
 
 /// Stop ramp a
-fn stop_ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+fn stop_ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
     token_swap: &mut SwapInfo,
     account_info_iter: &mut I,
 ) -> ProgramResult {
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
 
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
     let invariant = StableSwap::new(
         token_swap.initial_amp_factor,
         token_swap.target_amp_factor,
@@ -164,6 +540,57 @@ fn stop_ramp_a<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
     Ok(())
 }
 
+/// Divide the current a at once, cancelling any ramp
+fn emergency_amp_down<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    divisor: u64,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let clock = utils::next_clock(account_info_iter)?;
+
+    if divisor < 2 {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let invariant = StableSwap::new(
+        token_swap.initial_amp_factor,
+        token_swap.target_amp_factor,
+        clock.unix_timestamp,
+        token_swap.start_ramp_ts,
+        token_swap.stop_ramp_ts,
+    );
+    let current_amp = invariant
+        .compute_amp_factor()
+        .ok_or(SwapError::CalculationFailure)?;
+    let mut new_amp = current_amp / divisor;
+    // Legacy accounts only store whole coefficients
+    if swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        new_amp -= new_amp % AMP_PRECISION;
+    }
+    let new_amp = new_amp.max(MIN_AMP * AMP_PRECISION);
+
+    token_swap.initial_amp_factor = new_amp;
+    token_swap.target_amp_factor = new_amp;
+    token_swap.start_ramp_ts = clock.unix_timestamp;
+    token_swap.stop_ramp_ts = clock.unix_timestamp;
+    msg!(
+        "Admin: Emergency cut of A from {} to {} hundredths",
+        current_amp,
+        new_amp
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::EmergencyAmpDown,
+        clock.unix_timestamp,
+        current_amp,
+        new_amp,
+        0,
+        0,
+    );
+    Ok(())
+}
+
 /// Pause swap
 fn pause(token_swap: &mut SwapInfo) -> ProgramResult {
     token_swap.is_paused = true;
@@ -178,143 +605,3994 @@ fn unpause(token_swap: &mut SwapInfo) -> ProgramResult {
     Ok(())
 }
 
-/// Set fee account
-fn set_fee_account<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+/// Deprecate swap, leaving it withdraw-only
+fn deprecate_pool(swap_info: &AccountInfo, token_swap: &mut SwapInfo) -> ProgramResult {
+    // The legacy layout has no room for the flag
+    if swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.is_deprecated = true;
+    msg!("Admin: Pool deprecated");
+    Ok(())
+}
+
+/// Hand the accounts of the swap over from its own authority to the shared authority
+fn migrate_to_shared_authority<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
     token_swap: &mut SwapInfo,
     account_info_iter: &mut I,
 ) -> ProgramResult {
-    let new_fee_account_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let shared_authority_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let admin_fee_a_info = next_account_info(account_info_iter)?;
+    let admin_fee_b_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
 
-    let new_admin_fee_account =
-        utils::unpack_token_account(&new_fee_account_info.data.borrow_mut())?;
-    if new_admin_fee_account.mint == token_swap.token_a.mint {
-        token_swap.token_a.admin_fees = *new_fee_account_info.key;
-        msg!(
-            "Admin: Setting admin fee A account to {}",
-            token_swap.token_a.admin_fees
-        );
-    } else if new_admin_fee_account.mint == token_swap.token_b.mint {
-        token_swap.token_b.admin_fees = *new_fee_account_info.key;
-        msg!(
-            "Admin: Setting admin fee B account to {}",
-            token_swap.token_b.admin_fees
-        );
-    } else {
-        return Err(SwapError::InvalidAdmin.into());
+    // The legacy layout has no room for the flag
+    if swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    if token_swap.shared_authority {
+        return Err(SwapError::SharedAuthority.into());
+    }
+    check_swap_authority(
+        token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    let (shared_authority_key, shared_nonce) = find_shared_authority_address(program_id);
+    check_keys_equal!(
+        *shared_authority_info.key,
+        shared_authority_key,
+        "Shared authority",
+        SwapError::InvalidProgramAddress
+    );
+    check_keys_equal!(
+        *token_a_info.key,
+        token_swap.token_a.reserves,
+        "Token A reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *token_b_info.key,
+        token_swap.token_b.reserves,
+        "Token B reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *admin_fee_a_info.key,
+        token_swap.token_a.admin_fees,
+        "Admin fee A",
+        SwapError::InvalidAdmin
+    );
+    check_keys_equal!(
+        *admin_fee_b_info.key,
+        token_swap.token_b.admin_fees,
+        "Admin fee B",
+        SwapError::InvalidAdmin
+    );
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    // Accounts the swap authority owns belong to this pool only, so the shared
+    // authority can take them over
+    let mut handed_over = vec![
+        (token_a_info, AuthorityType::AccountOwner),
+        (token_b_info, AuthorityType::AccountOwner),
+    ];
+    for admin_fee_info in [admin_fee_a_info, admin_fee_b_info] {
+        let admin_fee_account = utils::unpack_token_account(&admin_fee_info.data.borrow())?;
+        if admin_fee_account.owner == *swap_authority_info.key {
+            handed_over.push((admin_fee_info, AuthorityType::AccountOwner));
+        }
+    }
+    handed_over.push((pool_mint_info, AuthorityType::MintTokens));
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    if pool_mint.freeze_authority == COption::Some(*swap_authority_info.key) {
+        handed_over.push((pool_mint_info, AuthorityType::FreezeAccount));
+    }
+    for (account_info, authority_type) in handed_over {
+        token::set_authority_as_swap(
+            token_swap.authority_seed(swap_info.key),
+            token_program_info.clone(),
+            account_info.clone(),
+            shared_authority_info.key,
+            authority_type,
+            swap_authority_info.clone(),
+            token_swap.nonce,
+        )?;
     }
 
+    token_swap.nonce = shared_nonce;
+    token_swap.shared_authority = true;
+    msg!(
+        "Admin: Migrated to the shared authority {}",
+        shared_authority_key
+    );
     Ok(())
 }
 
-/// Apply new admin (finalize admin transfer)
-fn apply_new_admin<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+/// Reject instructions moving zero tokens, or log them as no-ops
+fn set_reject_zero_amounts(
+    swap_info: &AccountInfo,
     token_swap: &mut SwapInfo,
-    account_info_iter: &mut I,
+    reject: bool,
 ) -> ProgramResult {
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    // The legacy layout has no room for the flag
+    if reject && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.reject_zero_amounts = reject;
+    msg!("Admin: Reject zero amounts {}", reject);
+    Ok(())
+}
 
-    if token_swap.future_admin_deadline == ZERO_TS {
-        return Err(SwapError::NoActiveTransfer.into());
+/// Waive the withdraw fees of LP positions older than the grace period
+fn set_withdraw_fee_grace_period(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    withdraw_fee_grace_period: i64,
+) -> ProgramResult {
+    if withdraw_fee_grace_period < 0 {
+        return Err(SwapError::InvalidInput.into());
     }
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
-    if clock.unix_timestamp > token_swap.future_admin_deadline {
-        return Err(SwapError::AdminDeadlineExceeded.into());
+    // The legacy layout has no room for the grace period
+    if withdraw_fee_grace_period > 0 && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
     }
+    token_swap.withdraw_fee_grace_period = withdraw_fee_grace_period;
+    msg!(
+        "Admin: Withdraw fee grace period set to {} seconds",
+        withdraw_fee_grace_period
+    );
+    Ok(())
+}
 
-    token_swap.admin_key = token_swap.future_admin_key;
-    token_swap.future_admin_key = Pubkey::default();
-    token_swap.future_admin_deadline = ZERO_TS;
-    msg!("Admin: Finalized new admin {}", token_swap.admin_key);
+/// Set which programs may invoke the swap
+fn set_cpi_policy(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    cpi_policy: CpiPolicy,
+) -> ProgramResult {
+    // The legacy layout has no room for the policy
+    if cpi_policy != CpiPolicy::Any && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.cpi_policy = cpi_policy;
+    msg!("Admin: CPI policy set to {:?}", cpi_policy);
     Ok(())
 }
 
-/// Commit new admin (initiate admin transfer)
-fn commit_new_admin<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+/// Restrict donations to the admin
+fn set_donations_admin_only(token_swap: &mut SwapInfo, admin_only: bool) -> ProgramResult {
+    token_swap.donations_admin_only = admin_only;
+    msg!("Admin: Donations admin only set to {}", admin_only);
+    Ok(())
+}
+
+/// Accept swaps with the spl-token-swap account layout
+fn set_spl_token_swap_compat(token_swap: &mut SwapInfo, enabled: bool) -> ProgramResult {
+    token_swap.spl_token_swap_compat = enabled;
+    msg!("Admin: spl-token-swap compatibility set to {}", enabled);
+    Ok(())
+}
+
+/// Restrict deposits to the deposit allowlist
+fn set_deposit_allowlist_enabled(token_swap: &mut SwapInfo, enabled: bool) -> ProgramResult {
+    token_swap.deposit_allowlist_enabled = enabled;
+    msg!("Admin: Deposit allowlist enabled set to {}", enabled);
+    Ok(())
+}
+
+/// Set the price oracles of the peg-deviation circuit breaker
+fn set_price_oracles<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
     token_swap: &mut SwapInfo,
+    max_deviation_bps: u64,
     account_info_iter: &mut I,
 ) -> ProgramResult {
-    let new_admin_info = next_account_info(account_info_iter)?;
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    let price_oracle_a_info = next_account_info(account_info_iter)?;
+    let price_oracle_b_info = next_account_info(account_info_iter)?;
 
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
-    if clock.unix_timestamp < token_swap.future_admin_deadline {
-        return Err(SwapError::ActiveTransfer.into());
+    if max_deviation_bps > BPS_DENOMINATOR {
+        return Err(SwapError::InvalidInput.into());
+    }
+    if max_deviation_bps > 0 {
+        for price_oracle_info in [price_oracle_a_info, price_oracle_b_info] {
+            if oracle::read_pyth_price(&price_oracle_info.data.borrow()).is_none() {
+                return Err(SwapError::InvalidOracle.into());
+            }
+        }
     }
 
-    token_swap.future_admin_key = *new_admin_info.key;
-    token_swap.future_admin_deadline = clock
-        .unix_timestamp
-        .checked_add(ADMIN_TRANSFER_DELAY)
-        .ok_or(SwapError::CalculationFailure)?;
+    token_swap.price_oracle_a = *price_oracle_a_info.key;
+    token_swap.price_oracle_b = *price_oracle_b_info.key;
+    token_swap.max_price_deviation_bps = max_deviation_bps;
     msg!(
-        "Admin: Starting admin transfer to {}, deadline at {}",
-        token_swap.future_admin_key,
-        token_swap.future_admin_deadline
+        "Admin: Price oracles set to {} and {}, max deviation {} bps",
+        token_swap.price_oracle_a,
+        token_swap.price_oracle_b,
+        max_deviation_bps
     );
     Ok(())
 }
 
-/// Set new fees
-fn set_new_fees(token_swap: &mut SwapInfo, new_fees: &Fees) -> ProgramResult {
-    token_swap.fees = *new_fees;
-    msg!("Admin: New fees set");
+/// Set the max share of the output reserve taken by a swap
+fn set_max_swap_bps(token_swap: &mut SwapInfo, max_swap_bps: u64) -> ProgramResult {
+    if max_swap_bps > BPS_DENOMINATOR {
+        return Err(SwapError::InvalidInput.into());
+    }
+    token_swap.max_swap_bps_of_reserves = max_swap_bps;
+    msg!(
+        "Admin: Max swap size set to {} bps of the reserves",
+        max_swap_bps
+    );
     Ok(())
 }
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use super::*;
-    use crate::{curve::ZERO_TS, processor::test_utils::*};
-    use solana_program::program_error::ProgramError;
-    use solana_sdk::clock::Epoch;
+/// Set the largest swap paid out the quick estimate of the curve
+fn set_quick_swap_max_bps(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    quick_swap_max_bps: u64,
+) -> ProgramResult {
+    if quick_swap_max_bps > BPS_DENOMINATOR {
+        return Err(SwapError::InvalidInput.into());
+    }
+    // The legacy layout has no room for the quick swap size
+    if quick_swap_max_bps > 0 && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.quick_swap_max_bps = quick_swap_max_bps as u16;
+    msg!(
+        "Admin: Quick swaps set to at most {} bps of the reserves",
+        quick_swap_max_bps
+    );
+    Ok(())
+}
 
-    const DEFAULT_TOKEN_A_AMOUNT: u64 = 1_000_000_000;
-    const DEFAULT_TOKEN_B_AMOUNT: u64 = 1_000_000_000;
-    const DEFAULT_POOL_TOKEN_AMOUNT: u64 = 0;
+/// Keep user authorities from swapping back in the slot of their last swap
+fn set_same_slot_guard<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    enabled: bool,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let last_trade_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
 
-    #[test]
-    fn test_is_admin() {
-        let admin_key = pubkey_rand();
-        let admin_owner = pubkey_rand();
-        let mut lamports = 0;
-        let mut admin_account_data = vec![];
-        let mut admin_account_info = AccountInfo::new(
-            &admin_key,
-            true,
-            false,
-            &mut lamports,
-            &mut admin_account_data,
-            &admin_owner,
-            false,
-            Epoch::default(),
-        );
+    // The legacy layout has no room for the flag
+    if enabled && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let (last_trade_key, bump_seed) = find_last_trade_address(program_id, swap_info.key);
+    check_keys_equal!(
+        last_trade_key,
+        *last_trade_info.key,
+        "Last trade",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_info.key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[LAST_TRADE_SEED, &swap_bytes[..32], &[bump_seed]],
+        LastTrade::LEN,
+        last_trade_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+    if !LastTrade::unpack_unchecked(&last_trade_info.data.borrow())?.is_initialized {
+        let last_trade = LastTrade {
+            is_initialized: true,
+            swap: *swap_info.key,
+            ..LastTrade::default()
+        };
+        LastTrade::pack(last_trade, &mut last_trade_info.data.borrow_mut())?;
+    }
 
-        // Correct admin
-        assert_eq!(
-            Ok(()),
-            check_has_admin_signer(&admin_key, &admin_account_info)
-        );
+    token_swap.same_slot_guard = enabled;
+    msg!("Admin: Same-slot guard set to {}", enabled);
+    Ok(())
+}
 
-        // Unauthorized account
-        let fake_admin_key = pubkey_rand();
+/// Mint the admin fees as pool tokens rather than transferring them
+fn set_admin_fees_as_lp(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    enabled: bool,
+) -> ProgramResult {
+    // The legacy layout has no room for the flag
+    if enabled && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.admin_fees_as_lp = enabled;
+    msg!("Admin: Admin fees as LP set to {}", enabled);
+    Ok(())
+}
+
+/// Keep WithdrawOne open while the swap is paused, at doubled fees
+fn set_withdraw_one_while_paused(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    enabled: bool,
+) -> ProgramResult {
+    // The legacy layout has no room for the flag
+    if enabled && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.withdraw_one_while_paused = enabled;
+    msg!("Admin: WithdrawOne while paused set to {}", enabled);
+    Ok(())
+}
+
+/// Leave out the detailed logs of the instructions of the swap
+fn set_quiet_logs(
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    enabled: bool,
+) -> ProgramResult {
+    // The legacy layout has no room for the flag
+    if enabled && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    token_swap.quiet_logs = enabled;
+    msg!("Admin: Quiet logs set to {}", enabled);
+    Ok(())
+}
+
+/// Cap the supply of the pool mint after deposits
+fn set_max_lp_supply<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    max_lp_supply: u64,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    update_deposit_caps(
+        program_id,
+        swap_info,
+        token_swap,
+        account_info_iter,
+        |deposit_caps| deposit_caps.max_lp_supply = max_lp_supply,
+    )?;
+    msg!("Admin: Max pool token supply set to {}", max_lp_supply);
+    Ok(())
+}
+
+/// Cap the pool token balance of depositors
+fn set_max_lp_per_owner<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    max_lp_per_owner: u64,
+    exempt_owners: &[Pubkey],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    if exempt_owners.len() > MAX_DEPOSIT_CAP_EXEMPT_OWNERS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    if exempt_owners
+        .iter()
+        .enumerate()
+        .any(|(i, owner)| exempt_owners[..i].contains(owner))
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+    update_deposit_caps(
+        program_id,
+        swap_info,
+        token_swap,
+        account_info_iter,
+        |deposit_caps| {
+            deposit_caps.max_lp_per_owner = max_lp_per_owner;
+            deposit_caps.num_exempt_owners = exempt_owners.len() as u8;
+            deposit_caps.exempt_owners = [Pubkey::default(); MAX_DEPOSIT_CAP_EXEMPT_OWNERS];
+            deposit_caps.exempt_owners[..exempt_owners.len()].copy_from_slice(exempt_owners);
+        },
+    )?;
+    msg!(
+        "Admin: Max pool token balance set to {}, {} owners exempt",
+        max_lp_per_owner,
+        exempt_owners.len()
+    );
+    Ok(())
+}
+
+/// Updates the deposit caps of the swap, creating the account if needed, and enables
+/// them on the swap while any cap is set
+fn update_deposit_caps<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    account_info_iter: &mut I,
+    update: impl FnOnce(&mut DepositCaps),
+) -> ProgramResult {
+    let deposit_caps_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let (deposit_caps_key, bump_seed) = find_deposit_caps_address(program_id, swap_info.key);
+    check_keys_equal!(
+        deposit_caps_key,
+        *deposit_caps_info.key,
+        "Deposit caps",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_info.key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[DEPOSIT_CAPS_SEED, &swap_bytes[..32], &[bump_seed]],
+        DepositCaps::LEN,
+        deposit_caps_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+    let mut deposit_caps = DepositCaps::unpack_unchecked(&deposit_caps_info.data.borrow())?;
+    if !deposit_caps.is_initialized {
+        deposit_caps = DepositCaps {
+            is_initialized: true,
+            swap: *swap_info.key,
+            ..DepositCaps::default()
+        };
+    }
+    update(&mut deposit_caps);
+    // The legacy layout has no room for the flag
+    if deposit_caps.is_capped() && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    DepositCaps::pack(deposit_caps, &mut deposit_caps_info.data.borrow_mut())?;
+    token_swap.deposit_cap_enabled = deposit_caps.is_capped();
+    Ok(())
+}
+
+/// Set fee account
+fn set_fee_account<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &mut SwapInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let new_fee_account_info = next_account_info(account_info_iter)?;
+
+    let new_admin_fee_account =
+        utils::unpack_token_account(&new_fee_account_info.data.borrow_mut())?;
+    // An account the shared authority owns may be claimed by another pool
+    if token_swap.shared_authority
+        && new_admin_fee_account.owner
+            == utils::authority_id(
+                program_id,
+                token_swap.authority_seed(swap_key),
+                token_swap.nonce,
+            )?
+    {
+        return Err(SwapError::InvalidOwner.into());
+    }
+    if new_admin_fee_account.mint == token_swap.token_a.mint {
+        token_swap.token_a.admin_fees = *new_fee_account_info.key;
+        msg!(
+            "Admin: Setting admin fee A account to {}",
+            token_swap.token_a.admin_fees
+        );
+    } else if new_admin_fee_account.mint == token_swap.token_b.mint {
+        token_swap.token_b.admin_fees = *new_fee_account_info.key;
+        msg!(
+            "Admin: Setting admin fee B account to {}",
+            token_swap.token_b.admin_fees
+        );
+    } else {
+        return Err(SwapError::InvalidAdmin.into());
+    }
+
+    Ok(())
+}
+
+/// Collect admin fees
+fn collect_admin_fees<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    token_a_amount: u64,
+    token_b_amount: u64,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let admin_fee_a_info = next_account_info(account_info_iter)?;
+    let admin_fee_b_info = next_account_info(account_info_iter)?;
+    let destination_a_info = next_account_info(account_info_iter)?;
+    let destination_b_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    check_swap_authority(token_swap, swap_key, program_id, swap_authority_info.key)?;
+
+    for (token, admin_fee_info, destination_info, amount) in [
+        (
+            &token_swap.token_a,
+            admin_fee_a_info,
+            destination_a_info,
+            token_a_amount,
+        ),
+        (
+            &token_swap.token_b,
+            admin_fee_b_info,
+            destination_b_info,
+            token_b_amount,
+        ),
+    ]
+    .iter()
+    {
+        check_keys_equal!(
+            *admin_fee_info.key,
+            token.admin_fees,
+            "Admin fee account",
+            SwapError::InvalidAdmin
+        );
+        let admin_fee_account = utils::unpack_token_account(&admin_fee_info.data.borrow())?;
+        // Admin fee accounts of another owner, e.g. a vault program address, move
+        // with its signature
+        let fee_owner_info = if admin_fee_account.owner == *swap_authority_info.key {
+            None
+        } else {
+            match account_info_iter
+                .clone()
+                .find(|account_info| *account_info.key == admin_fee_account.owner)
+            {
+                Some(fee_owner_info) if fee_owner_info.is_signer => Some(fee_owner_info),
+                _ => {
+                    msg!(
+                        "Admin fee account owner {} did not sign",
+                        admin_fee_account.owner
+                    );
+                    return Err(SwapError::InvalidOwner.into());
+                }
+            }
+        };
+        let destination = utils::unpack_token_account(&destination_info.data.borrow())?;
+        check_keys_equal!(
+            destination.mint,
+            token.mint,
+            "Admin fee destination mint",
+            SwapError::IncorrectMint
+        );
+        if *amount == 0 {
+            continue;
+        }
+        match fee_owner_info {
+            Some(fee_owner_info) => token::transfer_as_user(
+                token_program_info.clone(),
+                (*admin_fee_info).clone(),
+                (*destination_info).clone(),
+                fee_owner_info.clone(),
+                *amount,
+            )?,
+            None => token::transfer_as_swap(
+                token_swap.authority_seed(swap_key),
+                token_program_info.clone(),
+                (*admin_fee_info).clone(),
+                (*destination_info).clone(),
+                swap_authority_info.clone(),
+                token_swap.nonce,
+                *amount,
+            )?,
+        }
+    }
+
+    log_event(
+        token_swap.quiet_logs,
+        Event::CollectAdminFees,
+        clock.unix_timestamp,
+        token_a_amount,
+        token_b_amount,
+        0,
+        0,
+    );
+    Ok(())
+}
+
+/// Freeze or thaw an LP token account
+fn set_lp_frozen<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    frozen: bool,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let lp_account_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    check_swap_authority(token_swap, swap_key, program_id, swap_authority_info.key)?;
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    if pool_mint.freeze_authority != COption::Some(*swap_authority_info.key) {
+        return Err(SwapError::InvalidFreezeAuthority.into());
+    }
+    let lp_account = utils::unpack_token_account(&lp_account_info.data.borrow())?;
+    check_keys_equal!(
+        lp_account.mint,
+        token_swap.pool_mint,
+        "LP account mint",
+        SwapError::IncorrectMint
+    );
+
+    token::set_frozen_as_swap(
+        token_swap.authority_seed(swap_key),
+        token_program_info.clone(),
+        lp_account_info.clone(),
+        pool_mint_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        frozen,
+    )
+}
+
+/// Apply new admin (finalize admin transfer)
+fn apply_new_admin<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let clock = utils::next_clock(account_info_iter)?;
+
+    if token_swap.future_admin_deadline == ZERO_TS {
+        return Err(SwapError::NoActiveTransfer.into());
+    }
+    if clock.unix_timestamp > token_swap.future_admin_deadline {
+        return Err(SwapError::AdminDeadlineExceeded.into());
+    }
+
+    token_swap.admin_key = token_swap.future_admin_key;
+    token_swap.future_admin_key = Pubkey::default();
+    token_swap.future_admin_deadline = ZERO_TS;
+    msg!("Admin: Finalized new admin {}", token_swap.admin_key);
+    Ok(())
+}
+
+/// Commit new admin (initiate admin transfer)
+fn commit_new_admin<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let new_admin_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    if clock.unix_timestamp < token_swap.future_admin_deadline {
+        return Err(SwapError::ActiveTransfer.into());
+    }
+    // The transfer could never be accepted
+    if *new_admin_info.key == Pubkey::default() {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    token_swap.future_admin_key = *new_admin_info.key;
+    token_swap.future_admin_deadline = clock
+        .unix_timestamp
+        .checked_add(ADMIN_TRANSFER_DELAY)
+        .ok_or(SwapError::CalculationFailure)?;
+    msg!(
+        "Admin: Starting admin transfer to {}, deadline at {}",
+        token_swap.future_admin_key,
+        token_swap.future_admin_deadline
+    );
+    Ok(())
+}
+
+/// Set trade fee tiers, committing the new fees like [commit_new_fees]
+fn set_fee_tiers<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    tiers: &[FeeTier; MAX_FEE_TIERS],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let new_fees = Fees {
+        trade_fee_tiers: *tiers,
+        ..token_swap.fees
+    };
+    commit_new_fees(token_swap, &new_fees, account_info_iter)?;
+    msg!("Admin: New fee tiers committed");
+    Ok(())
+}
+
+/// Set fee mode, committing the new fees like [commit_new_fees]
+fn set_fee_mode<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    dynamic_fee: &DynamicFee,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let new_fees = Fees {
+        dynamic_fee: *dynamic_fee,
+        ..token_swap.fees
+    };
+    commit_new_fees(token_swap, &new_fees, account_info_iter)?;
+    msg!("Admin: Fee mode {:?} committed", dynamic_fee.mode);
+    Ok(())
+}
+
+/// Commit new fees (initiate fee change)
+fn commit_new_fees<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    new_fees: &Fees,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let clock = utils::next_clock(account_info_iter)?;
+
+    if token_swap.future_fees_deadline != ZERO_TS {
+        return Err(SwapError::ActiveFeeChange.into());
+    }
+    new_fees.validate()?;
+    if !new_fees.validate_trade_fee_tiers()
+        || !new_fees.validate_dynamic_fee()
+        || !new_fees.validate_rebalancing_rebate()
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    token_swap.future_fees = *new_fees;
+    token_swap.future_fees_deadline = clock
+        .unix_timestamp
+        .checked_add(FEE_CHANGE_DELAY)
+        .ok_or(SwapError::CalculationFailure)?;
+    msg!(
+        "Admin: Committing new fees, active at {}",
+        token_swap.future_fees_deadline
+    );
+    Ok(())
+}
+
+/// Apply new fees (finalize fee change)
+fn apply_new_fees<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    token_swap: &mut SwapInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let clock = utils::next_clock(account_info_iter)?;
+
+    if token_swap.future_fees_deadline == ZERO_TS {
+        return Err(SwapError::NoActiveFeeChange.into());
+    }
+    if clock.unix_timestamp < token_swap.future_fees_deadline {
+        return Err(SwapError::FeeChangeLocked.into());
+    }
+    // Fees cannot change under an active ramp of A or of the fees
+    if clock.unix_timestamp < token_swap.stop_ramp_ts
+        || clock.unix_timestamp < token_swap.stop_fee_ramp_ts
+    {
+        return Err(SwapError::RampLocked.into());
+    }
+
+    token_swap.fees = token_swap.future_fees;
+    token_swap.stop_fee_ramp_ts = ZERO_TS;
+    token_swap.future_fees = Fees::default();
+    token_swap.future_fees_deadline = ZERO_TS;
+    msg!("Admin: New fees applied");
+    Ok(())
+}
+
+/// Cancel pending fee change and admin transfer
+fn cancel_pending(token_swap: &mut SwapInfo) -> ProgramResult {
+    if token_swap.future_fees_deadline == ZERO_TS && token_swap.future_admin_deadline == ZERO_TS {
+        return Err(SwapError::NoPendingChange.into());
+    }
+
+    token_swap.future_fees = Fees::default();
+    token_swap.future_fees_deadline = ZERO_TS;
+    token_swap.future_admin_key = Pubkey::default();
+    token_swap.future_admin_deadline = ZERO_TS;
+    msg!("Admin: Pending changes cancelled");
+    Ok(())
+}
+
+/// Create or update the admin multisig
+fn set_admin_multisig<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    threshold: u8,
+    signers: &[Pubkey],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let multisig_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    if threshold == 0 || threshold as usize > signers.len() || signers.len() > MAX_ADMIN_SIGNERS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    if signers
+        .iter()
+        .enumerate()
+        .any(|(i, signer)| signers[..i].contains(signer))
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let (multisig_key, bump_seed) = find_admin_multisig_address(program_id, swap_key);
+    check_keys_equal!(
+        multisig_key,
+        *multisig_info.key,
+        "Admin multisig",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[ADMIN_MULTISIG_SEED, &swap_bytes[..32], &[bump_seed]],
+        AdminMultisig::LEN,
+        multisig_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut multisig = AdminMultisig {
+        is_initialized: true,
+        swap: *swap_key,
+        threshold,
+        num_signers: signers.len() as u8,
+        signers: [Pubkey::default(); MAX_ADMIN_SIGNERS],
+    };
+    multisig.signers[..signers.len()].copy_from_slice(signers);
+    AdminMultisig::pack(multisig, &mut multisig_info.data.borrow_mut())?;
+    msg!(
+        "Admin: Admin multisig set to {} of {} signers",
+        threshold,
+        signers.len()
+    );
+    Ok(())
+}
+
+/// Set the user authorities exempt from fees
+fn set_fee_exemptions<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    exempt_accounts: &[Pubkey],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let fee_exemptions_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    if exempt_accounts.len() > MAX_FEE_EXEMPT_ACCOUNTS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    if exempt_accounts
+        .iter()
+        .enumerate()
+        .any(|(i, account)| exempt_accounts[..i].contains(account))
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let (fee_exemptions_key, bump_seed) = find_fee_exemptions_address(program_id, swap_key);
+    check_keys_equal!(
+        fee_exemptions_key,
+        *fee_exemptions_info.key,
+        "Fee exemptions",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[FEE_EXEMPTIONS_SEED, &swap_bytes[..32], &[bump_seed]],
+        FeeExemptions::LEN,
+        fee_exemptions_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut fee_exemptions = FeeExemptions {
+        is_initialized: true,
+        swap: *swap_key,
+        num_accounts: exempt_accounts.len() as u8,
+        accounts: [Pubkey::default(); MAX_FEE_EXEMPT_ACCOUNTS],
+    };
+    fee_exemptions.accounts[..exempt_accounts.len()].copy_from_slice(exempt_accounts);
+    FeeExemptions::pack(fee_exemptions, &mut fee_exemptions_info.data.borrow_mut())?;
+    msg!(
+        "Admin: Fee exemptions set to {} accounts",
+        exempt_accounts.len()
+    );
+    Ok(())
+}
+
+/// Set the user authorities allowed to deposit
+fn set_deposit_allowlist<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    allowed_accounts: &[Pubkey],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let deposit_allowlist_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    if allowed_accounts.len() > MAX_DEPOSIT_ALLOWLIST_ACCOUNTS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    if allowed_accounts
+        .iter()
+        .enumerate()
+        .any(|(i, account)| allowed_accounts[..i].contains(account))
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let (deposit_allowlist_key, bump_seed) = find_deposit_allowlist_address(program_id, swap_key);
+    check_keys_equal!(
+        deposit_allowlist_key,
+        *deposit_allowlist_info.key,
+        "Deposit allowlist",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[DEPOSIT_ALLOWLIST_SEED, &swap_bytes[..32], &[bump_seed]],
+        DepositAllowlist::LEN,
+        deposit_allowlist_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut deposit_allowlist = DepositAllowlist {
+        is_initialized: true,
+        swap: *swap_key,
+        num_accounts: allowed_accounts.len() as u8,
+        accounts: [Pubkey::default(); MAX_DEPOSIT_ALLOWLIST_ACCOUNTS],
+    };
+    deposit_allowlist.accounts[..allowed_accounts.len()].copy_from_slice(allowed_accounts);
+    DepositAllowlist::pack(
+        deposit_allowlist,
+        &mut deposit_allowlist_info.data.borrow_mut(),
+    )?;
+    msg!(
+        "Admin: Deposit allowlist set to {} accounts",
+        allowed_accounts.len()
+    );
+    Ok(())
+}
+
+/// Set the programs allowed to invoke the swap
+fn set_cpi_allowlist<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    allowed_programs: &[Pubkey],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let cpi_allowlist_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    if allowed_programs.len() > MAX_CPI_ALLOWLIST_PROGRAMS {
+        return Err(SwapError::InvalidInput.into());
+    }
+    if allowed_programs
+        .iter()
+        .enumerate()
+        .any(|(i, program)| allowed_programs[..i].contains(program))
+    {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let (cpi_allowlist_key, bump_seed) = find_cpi_allowlist_address(program_id, swap_key);
+    check_keys_equal!(
+        cpi_allowlist_key,
+        *cpi_allowlist_info.key,
+        "CPI allowlist",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[CPI_ALLOWLIST_SEED, &swap_bytes[..32], &[bump_seed]],
+        CpiAllowlist::LEN,
+        cpi_allowlist_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut cpi_allowlist = CpiAllowlist {
+        is_initialized: true,
+        swap: *swap_key,
+        num_programs: allowed_programs.len() as u8,
+        programs: [Pubkey::default(); MAX_CPI_ALLOWLIST_PROGRAMS],
+    };
+    cpi_allowlist.programs[..allowed_programs.len()].copy_from_slice(allowed_programs);
+    CpiAllowlist::pack(cpi_allowlist, &mut cpi_allowlist_info.data.borrow_mut())?;
+    msg!(
+        "Admin: CPI allowlist set to {} programs",
+        allowed_programs.len()
+    );
+    Ok(())
+}
+
+/// Set the account the peg rate of the swap is refreshed from, and read the rate
+fn set_peg_rate_source<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    token_swap: &mut SwapInfo,
+    rate_source: PegRateSource,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let peg_config_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let rate_account_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    // The legacy layout has no room for the peg rate
+    if rate_source != PegRateSource::None && swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let peg_rate = read_peg_rate(rate_source, rate_account_info, &clock)?;
+
+    let (peg_config_key, bump_seed) = find_peg_config_address(program_id, swap_info.key);
+    check_keys_equal!(
+        peg_config_key,
+        *peg_config_info.key,
+        "Peg config",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_info.key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[PEG_CONFIG_SEED, &swap_bytes[..32], &[bump_seed]],
+        PegConfig::LEN,
+        peg_config_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let peg_config = PegConfig {
+        is_initialized: true,
+        swap: *swap_info.key,
+        rate_source,
+        rate_account: *rate_account_info.key,
+        rate_updated_slot: clock.slot,
+    };
+    PegConfig::pack(peg_config, &mut peg_config_info.data.borrow_mut())?;
+    token_swap.peg_rate = peg_rate;
+    msg!(
+        "Admin: Peg rate source set to {:?} {}, peg rate {}",
+        rate_source,
+        rate_account_info.key,
+        peg_rate
+    );
+    Ok(())
+}
+
+/// Set the registry entry of the swap
+fn set_pool_info<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    name: &str,
+    category: PoolCategory,
+    is_deprecated: bool,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let pool_registry_entry_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    if name.len() > MAX_POOL_NAME_LEN {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let (pool_registry_entry_key, bump_seed) =
+        find_pool_registry_entry_address(program_id, swap_key);
+    check_keys_equal!(
+        pool_registry_entry_key,
+        *pool_registry_entry_info.key,
+        "Pool registry entry",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[POOL_REGISTRY_ENTRY_SEED, &swap_bytes[..32], &[bump_seed]],
+        PoolRegistryEntry::LEN,
+        pool_registry_entry_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut pool_registry_entry = PoolRegistryEntry {
+        is_initialized: true,
+        swap: *swap_key,
+        name_len: name.len() as u8,
+        name: [0u8; MAX_POOL_NAME_LEN],
+        category,
+        is_deprecated,
+    };
+    pool_registry_entry.name[..name.len()].copy_from_slice(name.as_bytes());
+    PoolRegistryEntry::pack(
+        pool_registry_entry,
+        &mut pool_registry_entry_info.data.borrow_mut(),
+    )?;
+    msg!(
+        "Admin: Pool info set to {} ({:?}), deprecated: {}",
+        name,
+        category,
+        is_deprecated
+    );
+    Ok(())
+}
+
+/// Set the rewards distributor the admin fees are harvested into
+fn set_rewards_distributor<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let rewards_distributor_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let rewards_program_info = next_account_info(account_info_iter)?;
+    let rewards_a_info = next_account_info(account_info_iter)?;
+    let rewards_b_info = next_account_info(account_info_iter)?;
+
+    if !rewards_program_info.executable {
+        msg!("Rewards distributor program is not executable");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    for (token, rewards_info) in [
+        (&token_swap.token_a, rewards_a_info),
+        (&token_swap.token_b, rewards_b_info),
+    ] {
+        let rewards = utils::unpack_token_account(&rewards_info.data.borrow())?;
+        check_keys_equal!(
+            rewards.mint,
+            token.mint,
+            "Rewards mint",
+            SwapError::IncorrectMint
+        );
+    }
+
+    let (rewards_distributor_key, bump_seed) =
+        find_rewards_distributor_address(program_id, swap_key);
+    check_keys_equal!(
+        rewards_distributor_key,
+        *rewards_distributor_info.key,
+        "Rewards distributor",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[REWARDS_DISTRIBUTOR_SEED, &swap_bytes[..32], &[bump_seed]],
+        RewardsDistributor::LEN,
+        rewards_distributor_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let rewards_distributor = RewardsDistributor {
+        is_initialized: true,
+        swap: *swap_key,
+        program: *rewards_program_info.key,
+        rewards_a: *rewards_a_info.key,
+        rewards_b: *rewards_b_info.key,
+    };
+    RewardsDistributor::pack(
+        rewards_distributor,
+        &mut rewards_distributor_info.data.borrow_mut(),
+    )?;
+    msg!(
+        "Admin: Rewards distributor set to {}",
+        rewards_distributor.program
+    );
+    Ok(())
+}
+
+/// Set the staking program deposits can stake the minted pool tokens into
+fn set_staking_config<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    stake_instruction_prefix: [u8; 8],
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let staking_config_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let staking_program_info = next_account_info(account_info_iter)?;
+    let staking_pool_info = next_account_info(account_info_iter)?;
+
+    if !staking_program_info.executable {
+        msg!("Staking program is not executable");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if staking_pool_info.owner != staking_program_info.key {
+        msg!("Staking pool is not owned by the staking program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let (staking_config_key, bump_seed) = find_staking_config_address(program_id, swap_key);
+    check_keys_equal!(
+        staking_config_key,
+        *staking_config_info.key,
+        "Staking config",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[STAKING_CONFIG_SEED, &swap_bytes[..32], &[bump_seed]],
+        StakingConfig::LEN,
+        staking_config_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let staking_config = StakingConfig {
+        is_initialized: true,
+        swap: *swap_key,
+        program: *staking_program_info.key,
+        staking_pool: *staking_pool_info.key,
+        stake_instruction_prefix,
+    };
+    StakingConfig::pack(staking_config, &mut staking_config_info.data.borrow_mut())?;
+    msg!(
+        "Admin: Staking set to pool {} of {}",
+        staking_config.staking_pool,
+        staking_config.program
+    );
+    Ok(())
+}
+
+/// Create the statistics account of the swap
+fn init_pool_stats<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let pool_stats_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let (pool_stats_key, bump_seed) = find_pool_stats_address(program_id, swap_key);
+    check_keys_equal!(
+        pool_stats_key,
+        *pool_stats_info.key,
+        "Pool stats",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[POOL_STATS_SEED, &swap_bytes[..32], &[bump_seed]],
+        PoolStats::LEN,
+        pool_stats_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+    if PoolStats::unpack_unchecked(&pool_stats_info.data.borrow())?.is_initialized {
+        return Err(SwapError::AlreadyInUse.into());
+    }
+
+    let pool_stats = PoolStats {
+        is_initialized: true,
+        swap: *swap_key,
+        ..PoolStats::default()
+    };
+    PoolStats::pack(pool_stats, &mut pool_stats_info.data.borrow_mut())?;
+    msg!("Admin: Pool stats initialized");
+    Ok(())
+}
+
+/// Create or update the Token Metadata of the pool mint
+fn set_pool_metadata<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    (name, symbol, uri): (&str, &str, &str),
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let metadata_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let metadata_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    if !metadata::validate(name, symbol, uri) {
+        return Err(SwapError::InvalidInput.into());
+    }
+    check_swap_authority(token_swap, swap_key, program_id, swap_authority_info.key)?;
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+    check_keys_equal!(
+        *metadata_program_info.key,
+        metadata::id(),
+        "Token Metadata program",
+        ProgramError::IncorrectProgramId
+    );
+    let (metadata_key, _bump_seed) = metadata::find_metadata_address(pool_mint_info.key);
+    check_keys_equal!(
+        *metadata_info.key,
+        metadata_key,
+        "Pool mint metadata",
+        SwapError::InvalidProgramAddress
+    );
+
+    let authority_signature_seeds = [token_swap.authority_seed(swap_key), &[token_swap.nonce]];
+    if metadata_info.data_is_empty() {
+        invoke_signed(
+            &metadata::create_metadata_account(
+                metadata_info.key,
+                pool_mint_info.key,
+                swap_authority_info.key,
+                payer_info.key,
+                swap_authority_info.key,
+                name,
+                symbol,
+                uri,
+            ),
+            &[
+                metadata_info.clone(),
+                pool_mint_info.clone(),
+                swap_authority_info.clone(),
+                payer_info.clone(),
+                swap_authority_info.clone(),
+                system_program_info.clone(),
+                rent_sysvar_info.clone(),
+                metadata_program_info.clone(),
+            ],
+            &[&authority_signature_seeds[..]],
+        )?;
+    } else {
+        invoke_signed(
+            &metadata::update_metadata_account(
+                metadata_info.key,
+                swap_authority_info.key,
+                name,
+                symbol,
+                uri,
+            ),
+            &[
+                metadata_info.clone(),
+                swap_authority_info.clone(),
+                metadata_program_info.clone(),
+            ],
+            &[&authority_signature_seeds[..]],
+        )?;
+    }
+    msg!("Admin: Pool metadata set to {} ({})", name, symbol);
+    Ok(())
+}
+
+/// Move the reserves of the swap into a new swap, escrowing the new pool tokens
+fn migrate_pool<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &mut SwapInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let new_swap_info = next_account_info(account_info_iter)?;
+    let new_swap_authority_info = next_account_info(account_info_iter)?;
+    let new_token_a_info = next_account_info(account_info_iter)?;
+    let new_token_b_info = next_account_info(account_info_iter)?;
+    let new_pool_mint_info = next_account_info(account_info_iter)?;
+    let lp_escrow_info = next_account_info(account_info_iter)?;
+    let pool_migration_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    check_swap_authority(token_swap, swap_key, program_id, swap_authority_info.key)?;
+    // The LP escrow is an account of the authority, which another pool may claim
+    // if the authority is shared
+    if token_swap.shared_authority {
+        return Err(SwapError::SharedAuthority.into());
+    }
+    check_keys_equal!(
+        *token_a_info.key,
+        token_swap.token_a.reserves,
+        "Token A reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *token_b_info.key,
+        token_swap.token_b.reserves,
+        "Token B reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    // The new swap must be a live pool of the same tokens under the same admin
+    check_keys_not_equal!(
+        *new_swap_info.key,
+        *swap_key,
+        "New swap",
+        SwapError::IncorrectSwapAccount
+    );
+    if new_swap_info.owner != program_id {
+        return Err(SwapError::IncorrectSwapAccount.into());
+    }
+    let mut new_token_swap = SwapInfo::unpack(&new_swap_info.data.borrow())?;
+    if new_token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    check_keys_equal!(
+        new_token_swap.admin_key,
+        token_swap.admin_key,
+        "New swap admin",
+        SwapError::Unauthorized
+    );
+    check_keys_equal!(
+        new_token_swap.token_a.mint,
+        token_swap.token_a.mint,
+        "New swap mint A",
+        SwapError::IncorrectMint
+    );
+    check_keys_equal!(
+        new_token_swap.token_b.mint,
+        token_swap.token_b.mint,
+        "New swap mint B",
+        SwapError::IncorrectMint
+    );
+    check_swap_authority(
+        &new_token_swap,
+        new_swap_info.key,
+        program_id,
+        new_swap_authority_info.key,
+    )?;
+    check_keys_equal!(
+        *new_token_a_info.key,
+        new_token_swap.token_a.reserves,
+        "New swap token A reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *new_token_b_info.key,
+        new_token_swap.token_b.reserves,
+        "New swap token B reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *new_pool_mint_info.key,
+        new_token_swap.pool_mint,
+        "New swap pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let lp_escrow = utils::unpack_token_account(&lp_escrow_info.data.borrow())?;
+    check_keys_equal!(
+        lp_escrow.mint,
+        new_token_swap.pool_mint,
+        "LP escrow mint",
+        SwapError::IncorrectMint
+    );
+    check_keys_equal!(
+        lp_escrow.owner,
+        *swap_authority_info.key,
+        "LP escrow owner",
+        SwapError::InvalidOwner
+    );
+    if lp_escrow.delegate.is_some() {
+        return Err(SwapError::InvalidDelegate.into());
+    }
+    if lp_escrow.close_authority.is_some() {
+        return Err(SwapError::InvalidCloseAuthority.into());
+    }
+
+    let (pool_migration_key, bump_seed) = find_pool_migration_address(program_id, swap_key);
+    check_keys_equal!(
+        pool_migration_key,
+        *pool_migration_info.key,
+        "Pool migration",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[POOL_MIGRATION_SEED, &swap_bytes[..32], &[bump_seed]],
+        PoolMigration::LEN,
+        pool_migration_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+    if PoolMigration::unpack_unchecked(&pool_migration_info.data.borrow())?.is_initialized {
+        return Err(SwapError::AlreadyInUse.into());
+    }
+
+    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
+    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
+    let new_token_a = utils::unpack_token_account(&new_token_a_info.data.borrow())?;
+    let new_token_b = utils::unpack_token_account(&new_token_b_info.data.borrow())?;
+    let new_pool_mint = utils::unpack_mint(&new_pool_mint_info.data.borrow())?;
+
+    // The reserves are deposited into the new swap without fees
+    let invariant = new_token_swap.curve_calculator(clock.unix_timestamp);
+    let mint_amount = invariant
+        .mint_for_deposit_with_rates(
+            token_a.amount,
+            token_b.amount,
+            new_token_a.amount,
+            new_token_b.amount,
+            new_pool_mint.supply,
+            new_token_swap.token_peg_rate(&new_token_swap.token_a.reserves),
+            new_token_swap.token_peg_rate(&new_token_swap.token_b.reserves),
+            &Fees {
+                trade_fee_numerator: 0,
+                trade_fee_denominator: 1,
+                ..Fees::default()
+            },
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_key),
+        token_program_info.clone(),
+        token_a_info.clone(),
+        new_token_a_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        token_a.amount,
+    )?;
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_key),
+        token_program_info.clone(),
+        token_b_info.clone(),
+        new_token_b_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        token_b.amount,
+    )?;
+    token::mint_to(
+        new_token_swap.authority_seed(new_swap_info.key),
+        token_program_info.clone(),
+        new_pool_mint_info.clone(),
+        lp_escrow_info.clone(),
+        new_swap_authority_info.clone(),
+        new_token_swap.nonce,
+        mint_amount,
+    )?;
+    new_token_swap.pool_token_supply = new_pool_mint
+        .supply
+        .checked_add(mint_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    new_token_swap.reserve_amount_a = new_token_a
+        .amount
+        .checked_add(token_a.amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    new_token_swap.reserve_amount_b = new_token_b
+        .amount
+        .checked_add(token_b.amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    SwapInfo::pack(new_token_swap, &mut new_swap_info.data.borrow_mut())?;
+
+    let pool_migration = PoolMigration {
+        is_initialized: true,
+        swap: *swap_key,
+        new_swap: *new_swap_info.key,
+        lp_escrow: *lp_escrow_info.key,
+    };
+    PoolMigration::pack(pool_migration, &mut pool_migration_info.data.borrow_mut())?;
+    token_swap.is_paused = true;
+    token_swap.reserve_amount_a = 0;
+    token_swap.reserve_amount_b = 0;
+
+    msg!("Admin: Pool migrated to {}", new_swap_info.key);
+    log_event(
+        token_swap.quiet_logs,
+        Event::MigratePool,
+        clock.unix_timestamp,
+        token_a.amount,
+        token_b.amount,
+        mint_amount,
+        0,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::ZERO_TS,
+        fees::{FeeMode, FEE_GAMMA_PRECISION, NO_DYNAMIC_FEE, NO_FEE_TIERS},
+        processor::{checks::check_has_admin_signer, test_utils::*},
+        state::find_admin_fee_vault_address,
+    };
+    use solana_program::program_error::ProgramError;
+    use solana_sdk::account::Account;
+    use solana_sdk::clock::Epoch;
+    use spl_token::{
+        error::TokenError,
+        instruction::mint_to,
+        state::{Account as SplAccount, AccountState},
+    };
+
+    const DEFAULT_TOKEN_A_AMOUNT: u64 = 1_000_000_000;
+    const DEFAULT_TOKEN_B_AMOUNT: u64 = 1_000_000_000;
+    const DEFAULT_POOL_TOKEN_AMOUNT: u64 = 0;
+
+    #[test]
+    fn test_is_admin() {
+        let admin_key = pubkey_rand();
+        let admin_owner = pubkey_rand();
+        let mut lamports = 0;
+        let mut admin_account_data = vec![];
+        let mut admin_account_info = AccountInfo::new(
+            &admin_key,
+            true,
+            false,
+            &mut lamports,
+            &mut admin_account_data,
+            &admin_owner,
+            false,
+            Epoch::default(),
+        );
+
+        // Correct admin
+        assert_eq!(
+            Ok(()),
+            check_has_admin_signer(&admin_key, &admin_account_info)
+        );
+
+        // Unauthorized account
+        let fake_admin_key = pubkey_rand();
         let mut fake_admin_account = admin_account_info.clone();
         fake_admin_account.key = &fake_admin_key;
         assert_eq!(
-            Err(SwapError::Unauthorized.into()),
-            check_has_admin_signer(&admin_key, &fake_admin_account)
+            Err(SwapError::Unauthorized.into()),
+            check_has_admin_signer(&admin_key, &fake_admin_account)
+        );
+
+        // Admin did not sign
+        admin_account_info.is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            check_has_admin_signer(&admin_key, &admin_account_info)
+        );
+    }
+
+    fn init_accounts_ramp_a() -> SwapAccountInfo {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        accounts.initialize_swap().unwrap();
+        accounts
+    }
+
+    #[test]
+    fn test_ramp_a_swap_not_initialized() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        assert_eq!(
+            Err(ProgramError::UninitializedAccount),
+            accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION)
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_invalid_target_amp() {
+        let mut accounts = init_accounts_ramp_a();
+        let stop_ramp_ts = MIN_RAMP_DURATION;
+        let target_amp = 0;
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_a(target_amp, ZERO_TS, stop_ramp_ts)
+        );
+        let target_amp = MAX_AMP + 1;
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_a(target_amp, ZERO_TS, stop_ramp_ts)
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_unauthorized_account() {
+        let mut accounts = init_accounts_ramp_a();
+        let fake_admin_key = pubkey_rand();
+        accounts.admin_key = fake_admin_key;
+        assert_eq!(
+            Err(SwapError::Unauthorized.into()),
+            accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION)
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_locked() {
+        let mut accounts = init_accounts_ramp_a();
+        assert_eq!(
+            Err(SwapError::RampLocked.into()),
+            accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION / 2)
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_insufficient_ramp_time() {
+        let mut accounts = init_accounts_ramp_a();
+
+        assert_eq!(
+            Err(SwapError::InsufficientRampTime.into()),
+            accounts.ramp_a(accounts.initial_amp_factor, MIN_RAMP_DURATION, ZERO_TS)
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_invalid_amp_targets() {
+        let mut accounts = init_accounts_ramp_a();
+
+        // invalid amp targets
+        // amp target too low
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_a(
+                MIN_AMP,
+                MIN_RAMP_DURATION,
+                MIN_RAMP_DURATION * 2 + RAMP_DELAY
+            )
+        );
+        // amp target too high
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_a(
+                MAX_AMP,
+                MIN_RAMP_DURATION,
+                MIN_RAMP_DURATION * 2 + RAMP_DELAY
+            )
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_valid() {
+        // valid ramp
+        let mut accounts = init_accounts_ramp_a();
+
+        let target_amp = MIN_AMP * 200;
+        let current_ts = MIN_RAMP_DURATION;
+        let start_ramp_ts = current_ts + RAMP_DELAY;
+        let stop_ramp_ts = start_ramp_ts + MIN_RAMP_DURATION;
+
+        // the ramp cannot end before the delay and its minimum duration
+        assert_eq!(
+            Err(SwapError::InsufficientRampTime.into()),
+            accounts.ramp_a(target_amp, current_ts, stop_ramp_ts - 1)
+        );
+
+        accounts
+            .ramp_a(target_amp, current_ts, stop_ramp_ts)
+            .unwrap();
+
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            swap_info.initial_amp_factor,
+            accounts.initial_amp_factor * AMP_PRECISION
+        );
+        assert_eq!(swap_info.target_amp_factor, target_amp * AMP_PRECISION);
+        assert_eq!(swap_info.start_ramp_ts, start_ramp_ts);
+        assert_eq!(swap_info.stop_ramp_ts, stop_ramp_ts);
+
+        // A holds until the ramp starts
+        let invariant = StableSwap::new(
+            swap_info.initial_amp_factor,
+            swap_info.target_amp_factor,
+            start_ramp_ts - 1,
+            swap_info.start_ramp_ts,
+            swap_info.stop_ramp_ts,
+        );
+        assert_eq!(
+            invariant.compute_amp_factor(),
+            Some(accounts.initial_amp_factor * AMP_PRECISION)
+        );
+    }
+
+    #[test]
+    fn test_ramp_a_precise() {
+        let mut accounts = init_accounts_ramp_a();
+        let current_ts = MIN_RAMP_DURATION;
+        let stop_ramp_ts = current_ts + RAMP_DELAY + MIN_RAMP_DURATION;
+
+        // out of range, in hundredths
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_a_precise(MIN_AMP * AMP_PRECISION - 1, current_ts, stop_ramp_ts)
+        );
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_a_precise(MAX_AMP * AMP_PRECISION + 1, current_ts, stop_ramp_ts)
+        );
+
+        let target_amp = accounts.initial_amp_factor * AMP_PRECISION * 3 / 2 + 1;
+
+        // legacy accounts only store whole coefficients
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.ramp_a_precise(target_amp, current_ts, stop_ramp_ts)
+            );
+            accounts.swap_account.data = current;
+        }
+
+        accounts
+            .ramp_a_precise(target_amp, current_ts, stop_ramp_ts)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            swap_info.initial_amp_factor,
+            accounts.initial_amp_factor * AMP_PRECISION
+        );
+        assert_eq!(swap_info.target_amp_factor, target_amp);
+        assert_eq!(swap_info.start_ramp_ts, current_ts + RAMP_DELAY);
+        assert_eq!(swap_info.stop_ramp_ts, stop_ramp_ts);
+    }
+
+    #[test]
+    fn test_ramp_fees() {
+        let mut accounts = init_accounts_ramp_a();
+        let target_fees = Fees {
+            trade_fee_numerator: 2,
+            withdraw_fee_numerator: 4,
+            ..DEFAULT_TEST_FEES
+        };
+        let current_ts = MIN_RAMP_DURATION;
+        let start_ramp_ts = current_ts + RAMP_DELAY;
+        let stop_ramp_ts = start_ramp_ts + MIN_RAMP_DURATION * 2;
+
+        // denominators cannot change
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_fees(
+                Fees {
+                    trade_fee_denominator: 1_000,
+                    ..target_fees
+                },
+                current_ts,
+                stop_ramp_ts,
+            )
+        );
+        // nor the fees that do not ramp
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.ramp_fees(
+                Fees {
+                    admin_trade_fee_numerator: 0,
+                    ..target_fees
+                },
+                current_ts,
+                stop_ramp_ts,
+            )
+        );
+        // insufficient ramp time
+        assert_eq!(
+            Err(SwapError::InsufficientRampTime.into()),
+            accounts.ramp_fees(
+                target_fees,
+                current_ts,
+                start_ramp_ts + MIN_RAMP_DURATION - 1
+            )
+        );
+
+        accounts
+            .ramp_fees(target_fees, current_ts, stop_ramp_ts)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.fees, target_fees);
+        assert_eq!(swap_info.start_fee_ramp_ts, start_ramp_ts);
+        assert_eq!(swap_info.stop_fee_ramp_ts, stop_ramp_ts);
+        // the ramp starts after the delay
+        assert_eq!(
+            swap_info.current_fees(current_ts).unwrap(),
+            DEFAULT_TEST_FEES
+        );
+        assert_eq!(
+            swap_info.current_fees(start_ramp_ts).unwrap(),
+            DEFAULT_TEST_FEES
+        );
+        let fees = swap_info
+            .current_fees(start_ramp_ts + MIN_RAMP_DURATION)
+            .unwrap();
+        assert_eq!(fees.trade_fee_numerator, 4);
+        assert_eq!(fees.withdraw_fee_numerator, 5);
+        assert_eq!(swap_info.current_fees(stop_ramp_ts).unwrap(), target_fees);
+
+        // ramp locked
+        assert_eq!(
+            Err(SwapError::RampLocked.into()),
+            accounts.ramp_fees(DEFAULT_TEST_FEES, current_ts + 1, stop_ramp_ts)
+        );
+
+        // fees set during the ramp apply once it ends
+        accounts
+            .set_new_fees(DEFAULT_TEST_FEES, current_ts + 1)
+            .unwrap();
+        assert_eq!(
+            Err(SwapError::RampLocked.into()),
+            accounts.apply_new_fees(current_ts + 1 + FEE_CHANGE_DELAY)
+        );
+        accounts.apply_new_fees(stop_ramp_ts).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            swap_info.current_fees(stop_ramp_ts).unwrap(),
+            DEFAULT_TEST_FEES
+        );
+    }
+
+    #[test]
+    fn test_ramp_while_paused() {
+        let mut accounts = init_accounts_ramp_a();
+        let current_ts = MIN_RAMP_DURATION;
+        let stop_ramp_ts = MIN_RAMP_DURATION * 3;
+        let target_fees = Fees {
+            trade_fee_numerator: 2,
+            ..DEFAULT_TEST_FEES
+        };
+        accounts.pause().unwrap();
+        let paused_data = accounts.swap_account.data.clone();
+
+        assert_eq!(
+            Err(SwapError::IsPaused.into()),
+            accounts.ramp_a(MIN_AMP * 200, current_ts, stop_ramp_ts)
+        );
+        assert_eq!(
+            Err(SwapError::IsPaused.into()),
+            accounts.ramp_a_precise(MIN_AMP * 20_050, current_ts, stop_ramp_ts)
+        );
+        assert_eq!(
+            Err(SwapError::IsPaused.into()),
+            accounts.ramp_fees(target_fees, current_ts, stop_ramp_ts)
+        );
+        assert_eq!(accounts.swap_account.data, paused_data);
+
+        accounts.unpause().unwrap();
+        accounts
+            .ramp_a(MIN_AMP * 200, current_ts, stop_ramp_ts)
+            .unwrap();
+        accounts
+            .ramp_fees(target_fees, current_ts, stop_ramp_ts)
+            .unwrap();
+
+        // pausing does not stop an ongoing ramp
+        accounts.pause().unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.target_amp_factor, MIN_AMP * 200 * AMP_PRECISION);
+        assert_eq!(swap_info.stop_ramp_ts, stop_ramp_ts);
+        assert_eq!(swap_info.stop_fee_ramp_ts, stop_ramp_ts);
+
+        // but it can be stopped while paused
+        accounts.stop_ramp_a(current_ts + 1).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.stop_ramp_ts, current_ts + 1);
+    }
+
+    #[test]
+    fn test_stop_ramp_a() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.stop_ramp_a(ZERO_TS)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // without the clock sysvar, the clock is read with Clock::get
+        {
+            do_process_instruction(
+                crate::instruction::omit_clock_sysvar(
+                    crate::instruction::stop_ramp_a(
+                        &SWAP_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.admin_key,
+                    )
+                    .unwrap(),
+                ),
+                vec![&mut accounts.swap_account, &mut accounts.admin_account],
+            )
+            .unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.start_ramp_ts, ZERO_TS);
+            assert_eq!(swap_info.stop_ramp_ts, ZERO_TS);
+        }
+
+        // valid call
+        {
+            let expected_ts = MIN_RAMP_DURATION;
+            accounts.stop_ramp_a(expected_ts).unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.initial_amp_factor, amp_factor * AMP_PRECISION);
+            assert_eq!(swap_info.target_amp_factor, amp_factor * AMP_PRECISION);
+            assert_eq!(swap_info.start_ramp_ts, expected_ts);
+            assert_eq!(swap_info.stop_ramp_ts, expected_ts);
+        }
+    }
+
+    #[test]
+    fn test_emergency_amp_down() {
+        let mut accounts = init_accounts_ramp_a();
+        let amp_factor = accounts.initial_amp_factor * AMP_PRECISION;
+        let current_ts = MIN_RAMP_DURATION;
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.emergency_amp_down(10, current_ts)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+        for divisor in [0, 1] {
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.emergency_amp_down(divisor, current_ts)
+            );
+        }
+
+        // halfway through a ramp up, neither ramp locked nor paused
+        accounts
+            .ramp_a(
+                accounts.initial_amp_factor * 3,
+                current_ts,
+                current_ts + RAMP_DELAY + MIN_RAMP_DURATION * 2,
+            )
+            .unwrap();
+        accounts.pause().unwrap();
+        let ramp_ts = current_ts + RAMP_DELAY + MIN_RAMP_DURATION;
+        accounts.emergency_amp_down(10, ramp_ts).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.initial_amp_factor, amp_factor * 2 / 10);
+        assert_eq!(swap_info.target_amp_factor, amp_factor * 2 / 10);
+        assert_eq!(swap_info.start_ramp_ts, ramp_ts);
+        assert_eq!(swap_info.stop_ramp_ts, ramp_ts);
+
+        // bounded by MIN_AMP
+        accounts.emergency_amp_down(u64::MAX, ramp_ts).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.target_amp_factor, MIN_AMP * AMP_PRECISION);
+        accounts.emergency_amp_down(2, ramp_ts).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.target_amp_factor, MIN_AMP * AMP_PRECISION);
+    }
+
+    #[test]
+    fn test_emergency_amp_down_legacy_layout() {
+        let mut accounts = init_accounts_ramp_a();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+        SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+
+        // 100 / 3, rounded down to a whole coefficient
+        accounts.emergency_amp_down(3, ZERO_TS).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.target_amp_factor, 33 * AMP_PRECISION);
+    }
+
+    #[test]
+    fn test_pause() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(Err(ProgramError::UninitializedAccount), accounts.pause());
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(Err(SwapError::Unauthorized.into()), accounts.pause());
+            accounts.admin_key = old_admin_key;
+        }
+
+        // valid call
+        {
+            accounts.pause().unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(swap_info.is_paused);
+        }
+
+        // already paused
+        {
+            accounts.pause().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(swap_info.is_paused);
+        }
+    }
+
+    #[test]
+    fn test_unpause() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(Err(ProgramError::UninitializedAccount), accounts.unpause());
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(Err(SwapError::Unauthorized.into()), accounts.unpause());
+            accounts.admin_key = old_admin_key;
+        }
+
+        // not paused
+        {
+            accounts.unpause().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(!swap_info.is_paused);
+        }
+
+        // valid call
+        {
+            // Pause swap pool
+            accounts.pause().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(swap_info.is_paused);
+
+            // Unpause swap pool
+            accounts.unpause().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(!swap_info.is_paused);
+
+            // already unpaused
+            accounts.unpause().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(!swap_info.is_paused);
+        }
+    }
+
+    #[test]
+    fn test_deprecate_pool() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.deprecate_pool()
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.deprecate_pool()
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // valid call
+        {
+            accounts.deprecate_pool().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(swap_info.is_deprecated);
+            assert!(!swap_info.is_paused);
+        }
+    }
+
+    #[test]
+    fn test_migrate_to_shared_authority() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let old_authority_key = accounts.authority_key;
+        let (shared_authority_key, shared_nonce) = find_shared_authority_address(&SWAP_PROGRAM_ID);
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.migrate_to_shared_authority()
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // wrong swap authority
+        {
+            accounts.authority_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.migrate_to_shared_authority()
+            );
+            accounts.authority_key = old_authority_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.migrate_to_shared_authority()
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // valid call
+        {
+            accounts.migrate_to_shared_authority().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(swap_info.shared_authority);
+            assert_eq!(swap_info.nonce, shared_nonce);
+            for account in [
+                &accounts.token_a_account,
+                &accounts.token_b_account,
+                &accounts.admin_fee_a_account,
+                &accounts.admin_fee_b_account,
+            ] {
+                let token = utils::unpack_token_account(&account.data).unwrap();
+                assert_eq!(token.owner, shared_authority_key);
+            }
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            assert_eq!(
+                pool_mint.mint_authority,
+                COption::Some(shared_authority_key)
+            );
+        }
+
+        // the shared authority signs for the swap
+        {
+            let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+                accounts.setup_token_accounts(&user_key, &swapper_key, 1_000, 0, 0);
+            let swap_token_a_key = accounts.token_a_key;
+            let swap_token_b_key = accounts.token_b_key;
+            accounts
+                .swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    1_000,
+                    0,
+                )
+                .unwrap();
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert!(token_b.amount > 0);
+        }
+
+        // the swap already moved to the shared authority
+        assert_eq!(
+            Err(SwapError::SharedAuthority.into()),
+            accounts.migrate_to_shared_authority()
+        );
+    }
+
+    #[test]
+    fn test_set_reject_zero_amounts() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_reject_zero_amounts(true)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag, which stays unset there
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_reject_zero_amounts(true)
+            );
+            accounts.set_reject_zero_amounts(false).unwrap();
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_reject_zero_amounts(true).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.reject_zero_amounts);
+        accounts.set_reject_zero_amounts(false).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.reject_zero_amounts);
+    }
+
+    #[test]
+    fn test_set_withdraw_fee_grace_period() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_withdraw_fee_grace_period(86_400)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // negative grace period
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.set_withdraw_fee_grace_period(-1)
+        );
+
+        // legacy accounts have no room for the grace period
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_withdraw_fee_grace_period(86_400)
+            );
+            accounts.set_withdraw_fee_grace_period(0).unwrap();
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_withdraw_fee_grace_period(86_400).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.withdraw_fee_grace_period, 86_400);
+        accounts.set_withdraw_fee_grace_period(0).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.withdraw_fee_grace_period, 0);
+    }
+
+    #[test]
+    fn test_set_cpi_policy() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_cpi_policy(CpiPolicy::TopLevelOnly)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the policy
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_cpi_policy(CpiPolicy::TopLevelOnly)
+            );
+            accounts.set_cpi_policy(CpiPolicy::Any).unwrap();
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_cpi_policy(CpiPolicy::AllowedCallers).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.cpi_policy, CpiPolicy::AllowedCallers);
+        accounts.set_cpi_policy(CpiPolicy::Any).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.cpi_policy, CpiPolicy::Any);
+    }
+
+    #[test]
+    fn test_set_same_slot_guard() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let mut last_trade_account = Account::new(1, LastTrade::LEN, &SWAP_PROGRAM_ID);
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_same_slot_guard(&mut last_trade_account, true)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_same_slot_guard(&mut last_trade_account, true)
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts
+            .set_same_slot_guard(&mut last_trade_account, true)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.same_slot_guard);
+        let last_trade = LastTrade::unpack(&last_trade_account.data).unwrap();
+        assert_eq!(last_trade.swap, accounts.swap_key);
+        accounts
+            .set_same_slot_guard(&mut last_trade_account, false)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.same_slot_guard);
+    }
+
+    #[test]
+    fn test_set_admin_fees_as_lp() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_admin_fees_as_lp(true)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_admin_fees_as_lp(true)
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_admin_fees_as_lp(true).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.admin_fees_as_lp);
+        assert!(!swap_info.same_slot_guard);
+        accounts.set_admin_fees_as_lp(false).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.admin_fees_as_lp);
+    }
+
+    #[test]
+    fn test_set_withdraw_one_while_paused() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_withdraw_one_while_paused(true)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_withdraw_one_while_paused(true)
+            );
+            // disabling needs no room
+            accounts.set_withdraw_one_while_paused(false).unwrap();
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_withdraw_one_while_paused(true).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.withdraw_one_while_paused);
+        assert!(!swap_info.admin_fees_as_lp);
+        accounts.set_withdraw_one_while_paused(false).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.withdraw_one_while_paused);
+    }
+
+    #[test]
+    fn test_set_quiet_logs() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_quiet_logs(true)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_quiet_logs(true)
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_quiet_logs(true).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.quiet_logs);
+        assert!(SwapInfo::peek_quiet_logs(&accounts.swap_account.data));
+        accounts.set_quiet_logs(false).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.quiet_logs);
+    }
+
+    #[test]
+    fn test_set_max_lp_supply() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let mut deposit_caps_account = Account::new(1, DepositCaps::LEN, &SWAP_PROGRAM_ID);
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_max_lp_supply(&mut deposit_caps_account, 1_000)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // legacy accounts have no room for the flag
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_max_lp_supply(&mut deposit_caps_account, 1_000)
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts
+            .set_max_lp_supply(&mut deposit_caps_account, 1_000)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.deposit_cap_enabled);
+        let deposit_caps = DepositCaps::unpack(&deposit_caps_account.data).unwrap();
+        assert_eq!(deposit_caps.swap, accounts.swap_key);
+        assert_eq!(deposit_caps.max_lp_supply, 1_000);
+        accounts
+            .set_max_lp_supply(&mut deposit_caps_account, 0)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.deposit_cap_enabled);
+    }
+
+    #[test]
+    fn test_set_max_lp_per_owner() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let mut deposit_caps_account = Account::new(1, DepositCaps::LEN, &SWAP_PROGRAM_ID);
+        let exempt_key = pubkey_rand();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_max_lp_per_owner(&mut deposit_caps_account, 1_000, &[&exempt_key])
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // duplicate owners
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.set_max_lp_per_owner(
+                &mut deposit_caps_account,
+                1_000,
+                &[&exempt_key, &exempt_key]
+            )
+        );
+
+        // valid calls, keeping the supply cap
+        accounts
+            .set_max_lp_supply(&mut deposit_caps_account, 2_000)
+            .unwrap();
+        accounts
+            .set_max_lp_per_owner(&mut deposit_caps_account, 1_000, &[&exempt_key])
+            .unwrap();
+        let deposit_caps = DepositCaps::unpack(&deposit_caps_account.data).unwrap();
+        assert_eq!(deposit_caps.max_lp_supply, 2_000);
+        assert_eq!(deposit_caps.max_lp_per_owner, 1_000);
+        assert_eq!(deposit_caps.exempt_owners(), &[exempt_key]);
+
+        // the swap stays capped until both caps are lifted
+        accounts
+            .set_max_lp_supply(&mut deposit_caps_account, 0)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.deposit_cap_enabled);
+        accounts
+            .set_max_lp_per_owner(&mut deposit_caps_account, 0, &[])
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!swap_info.deposit_cap_enabled);
+        let deposit_caps = DepositCaps::unpack(&deposit_caps_account.data).unwrap();
+        assert!(deposit_caps.exempt_owners().is_empty());
+    }
+
+    #[test]
+    fn test_set_cpi_allowlist() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (cpi_allowlist_key, _bump_seed) =
+            find_cpi_allowlist_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut cpi_allowlist_account = Account::new(1, CpiAllowlist::LEN, &SWAP_PROGRAM_ID);
+        let program_id = pubkey_rand();
+
+        // wrong allowlist address
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            accounts.set_cpi_allowlist(&pubkey_rand(), &mut cpi_allowlist_account, &[&program_id])
+        );
+
+        // duplicate programs
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.set_cpi_allowlist(
+                &cpi_allowlist_key,
+                &mut cpi_allowlist_account,
+                &[&program_id, &program_id]
+            )
+        );
+
+        // valid call
+        accounts
+            .set_cpi_allowlist(
+                &cpi_allowlist_key,
+                &mut cpi_allowlist_account,
+                &[&program_id],
+            )
+            .unwrap();
+        let cpi_allowlist = CpiAllowlist::unpack(&cpi_allowlist_account.data).unwrap();
+        assert_eq!(cpi_allowlist.swap, accounts.swap_key);
+        assert_eq!(cpi_allowlist.programs(), &[program_id]);
+        assert!(cpi_allowlist.is_allowed(&program_id));
+        assert!(!cpi_allowlist.is_allowed(&pubkey_rand()));
+    }
+
+    #[test]
+    fn test_set_peg_rate_source() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (peg_config_key, _bump_seed) =
+            find_peg_config_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut peg_config_account = Account::new(1, PegConfig::LEN, &SWAP_PROGRAM_ID);
+        let stake_pool_key = pubkey_rand();
+        let mut stake_pool = stake_pool_account(1_050_000, 1_000_000, 0);
+        let slot = 1_000;
+
+        // wrong peg config address
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            accounts.set_peg_rate_source(
+                &pubkey_rand(),
+                &mut peg_config_account,
+                PegRateSource::StakePool,
+                &stake_pool_key,
+                &mut stake_pool,
+                slot,
+            )
+        );
+
+        // stake pool not updated this epoch
+        {
+            let mut stale_stake_pool_account = stake_pool_account(1_050_000, 1_000_000, 1);
+            assert_eq!(
+                Err(SwapError::InvalidOracle.into()),
+                accounts.set_peg_rate_source(
+                    &peg_config_key,
+                    &mut peg_config_account,
+                    PegRateSource::StakePool,
+                    &stake_pool_key,
+                    &mut stale_stake_pool_account,
+                    slot,
+                )
+            );
+        }
+
+        // a price account is not a stake pool
+        {
+            let mut price_account = pyth_price_account(105, -2, slot);
+            assert_eq!(
+                Err(SwapError::InvalidOracle.into()),
+                accounts.set_peg_rate_source(
+                    &peg_config_key,
+                    &mut peg_config_account,
+                    PegRateSource::StakePool,
+                    &stake_pool_key,
+                    &mut price_account,
+                    slot,
+                )
+            );
+        }
+
+        // legacy accounts have no room for the peg rate
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_peg_rate_source(
+                    &peg_config_key,
+                    &mut peg_config_account,
+                    PegRateSource::StakePool,
+                    &stake_pool_key,
+                    &mut stake_pool,
+                    slot,
+                )
+            );
+            accounts.swap_account.data = current;
+        }
+
+        // stake pool source
+        accounts
+            .set_peg_rate_source(
+                &peg_config_key,
+                &mut peg_config_account,
+                PegRateSource::StakePool,
+                &stake_pool_key,
+                &mut stake_pool,
+                slot,
+            )
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.peg_rate, 1_050_000_000);
+        let peg_config = PegConfig::unpack(&peg_config_account.data).unwrap();
+        assert_eq!(peg_config.swap, accounts.swap_key);
+        assert_eq!(peg_config.rate_source, PegRateSource::StakePool);
+        assert_eq!(peg_config.rate_account, stake_pool_key);
+        assert_eq!(peg_config.rate_updated_slot, slot);
+
+        // price source
+        let price_key = pubkey_rand();
+        let mut price_account = pyth_price_account(102, -2, slot);
+        accounts
+            .set_peg_rate_source(
+                &peg_config_key,
+                &mut peg_config_account,
+                PegRateSource::PythPrice,
+                &price_key,
+                &mut price_account,
+                slot,
+            )
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.peg_rate, 1_020_000_000);
+
+        // back to 1:1
+        accounts
+            .set_peg_rate_source(
+                &peg_config_key,
+                &mut peg_config_account,
+                PegRateSource::None,
+                &Pubkey::default(),
+                &mut Account::default(),
+                slot,
+            )
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.peg_rate, 0);
+        let peg_config = PegConfig::unpack(&peg_config_account.data).unwrap();
+        assert_eq!(peg_config.rate_source, PegRateSource::None);
+    }
+
+    #[test]
+    fn test_set_quick_swap_max_bps() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_quick_swap_max_bps(10)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // more than the reserves
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.set_quick_swap_max_bps(BPS_DENOMINATOR + 1)
+        );
+
+        // legacy accounts have no room for the quick swap size
+        {
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
+            assert_eq!(
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.set_quick_swap_max_bps(10)
+            );
+            accounts.set_quick_swap_max_bps(0).unwrap();
+            accounts.swap_account.data = current;
+        }
+
+        // valid calls
+        accounts.set_quick_swap_max_bps(10).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.quick_swap_max_bps, 10);
+        accounts.set_quick_swap_max_bps(0).unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.quick_swap_max_bps, 0);
+    }
+
+    #[test]
+    fn test_set_fee_account() {
+        let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        let (
+            admin_fee_key_a,
+            admin_fee_account_a,
+            admin_fee_key_b,
+            admin_fee_account_b,
+            wrong_admin_fee_key,
+            wrong_admin_fee_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &owner_key,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_POOL_TOKEN_AMOUNT,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.set_admin_fee_account(&admin_fee_key_a, &admin_fee_account_a)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_admin_fee_account(&admin_fee_key_a, &admin_fee_account_a)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // wrong admin account
+        {
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.set_admin_fee_account(&wrong_admin_fee_key, &wrong_admin_fee_account)
+            );
+        }
+
+        // valid calls
+        {
+            // set fee account a
+            accounts
+                .set_admin_fee_account(&admin_fee_key_a, &admin_fee_account_a)
+                .unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.token_a.admin_fees, admin_fee_key_a);
+            // set fee account b
+            accounts
+                .set_admin_fee_account(&admin_fee_key_b, &admin_fee_account_b)
+                .unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.token_b.admin_fees, admin_fee_key_b);
+        }
+    }
+
+    #[test]
+    fn test_apply_new_admin() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.apply_new_admin(ZERO_TS)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.apply_new_admin(ZERO_TS)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // no active transfer
+        {
+            assert_eq!(
+                Err(SwapError::NoActiveTransfer.into()),
+                accounts.apply_new_admin(ZERO_TS)
+            );
+        }
+
+        // apply new admin
+        {
+            let new_admin_key = pubkey_rand();
+            let current_ts = MIN_RAMP_DURATION;
+
+            // Commit to initiate admin transfer
+            accounts
+                .commit_new_admin(&new_admin_key, current_ts)
+                .unwrap();
+
+            // Applying transfer past deadline should fail
+            let apply_deadline = current_ts + MIN_RAMP_DURATION * 3;
+            assert_eq!(
+                Err(SwapError::AdminDeadlineExceeded.into()),
+                accounts.apply_new_admin(apply_deadline + 1)
+            );
+
+            // Apply to finalize admin transfer
+            accounts.apply_new_admin(current_ts + 1).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.admin_key, new_admin_key);
+            assert_eq!(swap_info.future_admin_key, Pubkey::default());
+            assert_eq!(swap_info.future_admin_deadline, ZERO_TS);
+        }
+    }
+
+    #[test]
+    fn test_commit_new_admin() {
+        let user_key = pubkey_rand();
+        let new_admin_key = pubkey_rand();
+        let current_ts = ZERO_TS;
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.commit_new_admin(&new_admin_key, current_ts)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.commit_new_admin(&new_admin_key, current_ts)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // default pubkey
+        {
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.commit_new_admin(&Pubkey::default(), current_ts)
+            );
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.future_admin_key, Pubkey::default());
+            assert_eq!(swap_info.future_admin_deadline, ZERO_TS);
+        }
+
+        // commit new admin
+        {
+            // valid call
+            accounts
+                .commit_new_admin(&new_admin_key, current_ts)
+                .unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.future_admin_key, new_admin_key);
+            let expected_future_ts = current_ts + MIN_RAMP_DURATION * 3;
+            assert_eq!(swap_info.future_admin_deadline, expected_future_ts);
+
+            // new commit within deadline should fail
+            assert_eq!(
+                Err(SwapError::ActiveTransfer.into()),
+                accounts.commit_new_admin(&new_admin_key, current_ts + 1),
+            );
+
+            // new commit after deadline should be valid
+            let new_admin_key = pubkey_rand();
+            let current_ts = expected_future_ts + 1;
+            accounts
+                .commit_new_admin(&new_admin_key, current_ts)
+                .unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.future_admin_key, new_admin_key);
+            let expected_future_ts = current_ts + MIN_RAMP_DURATION * 3;
+            assert_eq!(swap_info.future_admin_deadline, expected_future_ts);
+        }
+    }
+
+    #[test]
+    fn test_set_new_fees() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        let new_fees: Fees = Fees {
+            admin_trade_fee_numerator: 0,
+            admin_trade_fee_denominator: 1,
+            admin_withdraw_fee_numerator: 0,
+            admin_withdraw_fee_denominator: 1,
+            trade_fee_numerator: 0,
+            trade_fee_denominator: 1,
+            withdraw_fee_numerator: 0,
+            withdraw_fee_denominator: 1,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
+        };
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.set_new_fees(new_fees, ZERO_TS)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_new_fees(new_fees, ZERO_TS)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // zero denominator or fee over 100%
+        {
+            for invalid_fees in [
+                Fees {
+                    trade_fee_denominator: 0,
+                    ..new_fees
+                },
+                Fees {
+                    admin_withdraw_fee_denominator: 0,
+                    ..new_fees
+                },
+                Fees {
+                    withdraw_fee_numerator: 2,
+                    ..new_fees
+                },
+                Fees {
+                    admin_trade_fee_numerator: 2,
+                    ..new_fees
+                },
+            ] {
+                assert_eq!(
+                    Err(SwapError::InvalidFees.into()),
+                    accounts.set_new_fees(invalid_fees, ZERO_TS)
+                );
+            }
+        }
+
+        // admin share over the maximum
+        #[cfg(not(feature = "uncapped-admin-fee"))]
+        {
+            let capped_fees = Fees {
+                admin_trade_fee_numerator: crate::fees::MAX_ADMIN_FEE_SHARE_BPS,
+                admin_trade_fee_denominator: BPS_DENOMINATOR,
+                ..new_fees
+            };
+            assert_eq!(
+                Err(SwapError::InvalidFees.into()),
+                accounts.set_new_fees(
+                    Fees {
+                        admin_trade_fee_numerator: crate::fees::MAX_ADMIN_FEE_SHARE_BPS + 1,
+                        ..capped_fees
+                    },
+                    ZERO_TS
+                )
+            );
+            assert_eq!(
+                Err(SwapError::InvalidFees.into()),
+                accounts.commit_new_fees(
+                    Fees {
+                        admin_trade_fee_numerator: crate::fees::MAX_ADMIN_FEE_SHARE_BPS + 1,
+                        ..capped_fees
+                    },
+                    ZERO_TS
+                )
+            );
+            accounts.set_new_fees(capped_fees, ZERO_TS).unwrap();
+            accounts.apply_new_fees(FEE_CHANGE_DELAY).unwrap();
+        }
+
+        // valid call, applied after the delay
+        {
+            let current_ts = FEE_CHANGE_DELAY;
+            let old_fees = SwapInfo::unpack(&accounts.swap_account.data).unwrap().fees;
+            accounts.set_new_fees(new_fees, current_ts).unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, old_fees);
+            assert_eq!(swap_info.future_fees, new_fees);
+            assert_eq!(
+                swap_info.future_fees_deadline,
+                current_ts + FEE_CHANGE_DELAY
+            );
+
+            // fees cannot be set again during the delay
+            assert_eq!(
+                Err(SwapError::ActiveFeeChange.into()),
+                accounts.set_new_fees(old_fees, current_ts + 1)
+            );
+            assert_eq!(
+                Err(SwapError::FeeChangeLocked.into()),
+                accounts.apply_new_fees(current_ts + FEE_CHANGE_DELAY - 1)
+            );
+
+            accounts
+                .apply_new_fees(current_ts + FEE_CHANGE_DELAY)
+                .unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, new_fees);
+        }
+
+        // ramping A
+        {
+            let set_ts = FEE_CHANGE_DELAY * 2;
+            let stop_ramp_ts = set_ts + RAMP_DELAY + MIN_RAMP_DURATION;
+            accounts
+                .ramp_a(MIN_AMP * 200, set_ts, stop_ramp_ts)
+                .unwrap();
+            accounts.set_new_fees(DEFAULT_TEST_FEES, set_ts).unwrap();
+            assert_eq!(
+                Err(SwapError::RampLocked.into()),
+                accounts.apply_new_fees(set_ts + FEE_CHANGE_DELAY)
+            );
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, new_fees);
+            assert_eq!(swap_info.future_fees, DEFAULT_TEST_FEES);
+
+            accounts.apply_new_fees(stop_ramp_ts).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+        }
+    }
+
+    #[test]
+    fn test_set_fee_tiers() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        let tiers = [
+            FeeTier {
+                threshold: 1_000,
+                trade_fee_numerator: DEFAULT_TEST_FEES.trade_fee_numerator + 1,
+            },
+            FeeTier {
+                threshold: 1_000_000,
+                trade_fee_numerator: DEFAULT_TEST_FEES.trade_fee_numerator + 2,
+            },
+            FeeTier::default(),
+        ];
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.set_fee_tiers(tiers, ZERO_TS)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_fee_tiers(tiers, ZERO_TS)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // thresholds out of order
+        {
+            let invalid_tiers = [tiers[1], tiers[0], FeeTier::default()];
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_fee_tiers(invalid_tiers, ZERO_TS)
+            );
+        }
+
+        // tier cheaper than the base fee
+        {
+            let invalid_tiers = [
+                FeeTier {
+                    threshold: 1_000,
+                    trade_fee_numerator: DEFAULT_TEST_FEES.trade_fee_numerator - 1,
+                },
+                FeeTier::default(),
+                FeeTier::default(),
+            ];
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_fee_tiers(invalid_tiers, ZERO_TS)
+            );
+        }
+
+        // valid call
+        {
+            accounts.set_fee_tiers(tiers, ZERO_TS).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+            assert_eq!(
+                Err(SwapError::ActiveFeeChange.into()),
+                accounts.set_fee_tiers(tiers, ZERO_TS)
+            );
+
+            accounts.apply_new_fees(FEE_CHANGE_DELAY).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees.trade_fee_tiers, tiers);
+            assert_eq!(
+                swap_info.fees,
+                Fees {
+                    trade_fee_tiers: tiers,
+                    ..DEFAULT_TEST_FEES
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_fee_mode() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+
+        let dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: FEE_GAMMA_PRECISION / 100,
+            out_fee_numerator: DEFAULT_TEST_FEES.trade_fee_numerator * 2,
+        };
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.set_fee_mode(dynamic_fee, ZERO_TS)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_fee_mode(dynamic_fee, ZERO_TS)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // out fee lower than the flat fee
+        {
+            let invalid_dynamic_fee = DynamicFee {
+                out_fee_numerator: DEFAULT_TEST_FEES.trade_fee_numerator - 1,
+                ..dynamic_fee
+            };
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_fee_mode(invalid_dynamic_fee, ZERO_TS)
+            );
+        }
+
+        // valid call
+        {
+            accounts.set_fee_mode(dynamic_fee, ZERO_TS).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+            assert_eq!(
+                Err(SwapError::ActiveFeeChange.into()),
+                accounts.set_fee_mode(NO_DYNAMIC_FEE, ZERO_TS)
+            );
+
+            accounts.apply_new_fees(FEE_CHANGE_DELAY).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(
+                swap_info.fees,
+                Fees {
+                    dynamic_fee,
+                    ..DEFAULT_TEST_FEES
+                }
+            );
+        }
+
+        // back to flat fees
+        {
+            accounts
+                .set_fee_mode(NO_DYNAMIC_FEE, FEE_CHANGE_DELAY)
+                .unwrap();
+            accounts.apply_new_fees(FEE_CHANGE_DELAY * 2).unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+        }
+    }
+
+    #[test]
+    fn test_commit_new_fees() {
+        let user_key = pubkey_rand();
+        let current_ts = ZERO_TS;
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        let new_fees = Fees {
+            trade_fee_numerator: 1,
+            ..DEFAULT_TEST_FEES
+        };
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.commit_new_fees(new_fees, current_ts)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.commit_new_fees(new_fees, current_ts)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // invalid fees
+        {
+            let invalid_fees = Fees {
+                dynamic_fee: DynamicFee {
+                    mode: FeeMode::Dynamic,
+                    fee_gamma: 0,
+                    out_fee_numerator: 0,
+                },
+                ..new_fees
+            };
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.commit_new_fees(invalid_fees, current_ts)
+            );
+        }
+
+        // commit new fees
+        {
+            accounts.commit_new_fees(new_fees, current_ts).unwrap();
+
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+            assert_eq!(swap_info.future_fees, new_fees);
+            assert_eq!(
+                swap_info.future_fees_deadline,
+                current_ts + FEE_CHANGE_DELAY
+            );
+
+            // new commit while a change is pending should fail
+            assert_eq!(
+                Err(SwapError::ActiveFeeChange.into()),
+                accounts.commit_new_fees(new_fees, current_ts + FEE_CHANGE_DELAY + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_new_fees() {
+        let user_key = pubkey_rand();
+        let current_ts = MIN_RAMP_DURATION;
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        let new_fees = Fees {
+            trade_fee_numerator: 1,
+            ..DEFAULT_TEST_FEES
+        };
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.apply_new_fees(current_ts)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.apply_new_fees(current_ts)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // no active fee change
+        {
+            assert_eq!(
+                Err(SwapError::NoActiveFeeChange.into()),
+                accounts.apply_new_fees(current_ts)
+            );
+        }
+
+        // apply new fees
+        {
+            accounts.commit_new_fees(new_fees, current_ts).unwrap();
+
+            // Applying before the delay has passed should fail
+            let activation_ts = current_ts + FEE_CHANGE_DELAY;
+            assert_eq!(
+                Err(SwapError::FeeChangeLocked.into()),
+                accounts.apply_new_fees(activation_ts - 1)
+            );
+
+            accounts.apply_new_fees(activation_ts).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, new_fees);
+            assert_eq!(swap_info.future_fees, Fees::default());
+            assert_eq!(swap_info.future_fees_deadline, ZERO_TS);
+        }
+
+        // ramping A
+        {
+            let commit_ts = current_ts + FEE_CHANGE_DELAY;
+            let activation_ts = commit_ts + FEE_CHANGE_DELAY;
+            let stop_ramp_ts = commit_ts + RAMP_DELAY + MIN_RAMP_DURATION;
+            accounts
+                .ramp_a(MIN_AMP * 200, commit_ts, stop_ramp_ts)
+                .unwrap();
+            accounts
+                .commit_new_fees(DEFAULT_TEST_FEES, commit_ts)
+                .unwrap();
+            assert_eq!(
+                Err(SwapError::RampLocked.into()),
+                accounts.apply_new_fees(activation_ts)
+            );
+
+            accounts.apply_new_fees(stop_ramp_ts).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+        }
+
+        // ramping fees
+        {
+            let ramp_ts = current_ts + FEE_CHANGE_DELAY * 3;
+            let stop_ramp_ts = ramp_ts + FEE_CHANGE_DELAY * 2;
+            accounts.ramp_fees(new_fees, ramp_ts, stop_ramp_ts).unwrap();
+            accounts
+                .commit_new_fees(DEFAULT_TEST_FEES, ramp_ts)
+                .unwrap();
+            assert_eq!(
+                Err(SwapError::RampLocked.into()),
+                accounts.apply_new_fees(ramp_ts + FEE_CHANGE_DELAY)
+            );
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, new_fees);
+            assert_eq!(swap_info.future_fees, DEFAULT_TEST_FEES);
+
+            accounts.apply_new_fees(stop_ramp_ts).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+            assert_eq!(swap_info.stop_fee_ramp_ts, ZERO_TS);
+        }
+    }
+
+    #[test]
+    fn test_cancel_pending() {
+        let user_key = pubkey_rand();
+        let current_ts = MIN_RAMP_DURATION;
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        let new_fees = Fees {
+            trade_fee_numerator: 1,
+            ..DEFAULT_TEST_FEES
+        };
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.cancel_pending()
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.cancel_pending()
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // nothing pending
+        {
+            assert_eq!(
+                Err(SwapError::NoPendingChange.into()),
+                accounts.cancel_pending()
+            );
+        }
+
+        // cancel pending fee change and admin transfer
+        {
+            accounts.commit_new_fees(new_fees, current_ts).unwrap();
+            accounts
+                .commit_new_admin(&pubkey_rand(), current_ts)
+                .unwrap();
+
+            accounts.cancel_pending().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+            assert_eq!(swap_info.future_fees, Fees::default());
+            assert_eq!(swap_info.future_fees_deadline, ZERO_TS);
+            assert_eq!(swap_info.future_admin_key, Pubkey::default());
+            assert_eq!(swap_info.future_admin_deadline, ZERO_TS);
+
+            assert_eq!(
+                Err(SwapError::NoActiveFeeChange.into()),
+                accounts.apply_new_fees(current_ts + FEE_CHANGE_DELAY)
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_admin_multisig() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
         );
+        let (multisig_key, _bump_seed) =
+            find_admin_multisig_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut multisig_account = Account::new(1, AdminMultisig::LEN, &SWAP_PROGRAM_ID);
+        let signer_keys = [pubkey_rand(), pubkey_rand(), pubkey_rand()];
+        let signers = [&signer_keys[0], &signer_keys[1], &signer_keys[2]];
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.set_admin_multisig(&multisig_key, &mut multisig_account, 2, &signers)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_admin_multisig(&multisig_key, &mut multisig_account, 2, &signers)
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // invalid threshold
+        {
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_admin_multisig(&multisig_key, &mut multisig_account, 0, &signers)
+            );
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_admin_multisig(&multisig_key, &mut multisig_account, 4, &signers)
+            );
+        }
 
-        // Admin did not sign
-        admin_account_info.is_signer = false;
-        assert_eq!(
-            Err(ProgramError::MissingRequiredSignature),
-            check_has_admin_signer(&admin_key, &admin_account_info)
-        );
+        // duplicate signers
+        {
+            let duplicate_signers = [signers[0], signers[1], signers[0]];
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_admin_multisig(
+                    &multisig_key,
+                    &mut multisig_account,
+                    2,
+                    &duplicate_signers
+                )
+            );
+        }
+
+        // wrong multisig address
+        {
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.set_admin_multisig(&pubkey_rand(), &mut multisig_account, 2, &signers)
+            );
+        }
+
+        // valid call
+        {
+            accounts
+                .set_admin_multisig(&multisig_key, &mut multisig_account, 2, &signers)
+                .unwrap();
+
+            let multisig = AdminMultisig::unpack(&multisig_account.data).unwrap();
+            assert!(multisig.is_initialized);
+            assert_eq!(multisig.swap, accounts.swap_key);
+            assert_eq!(multisig.threshold, 2);
+            assert_eq!(multisig.signers(), &signer_keys[..]);
+
+            // the admin does not change until the admin is transferred
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.admin_key, accounts.admin_key);
+        }
     }
 
-    fn init_accounts_ramp_a() -> SwapAccountInfo {
+    #[test]
+    fn test_admin_multisig_signers() {
         let user_key = pubkey_rand();
+        let current_ts = MIN_RAMP_DURATION;
         let amp_factor = MIN_AMP * 100;
         let mut accounts = SwapAccountInfo::new(
             &user_key,
@@ -323,13 +4601,67 @@ mod tests {
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
-
         accounts.initialize_swap().unwrap();
+
+        let (multisig_key, _bump_seed) =
+            find_admin_multisig_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut multisig_account = Account::new(1, AdminMultisig::LEN, &SWAP_PROGRAM_ID);
+        let signer_keys = [pubkey_rand(), pubkey_rand(), pubkey_rand()];
+        let signers = [&signer_keys[0], &signer_keys[1], &signer_keys[2]];
+        accounts
+            .set_admin_multisig(&multisig_key, &mut multisig_account, 2, &signers)
+            .unwrap();
+
+        // transfer the admin to the multisig
         accounts
+            .commit_new_admin(&multisig_key, current_ts)
+            .unwrap();
+        accounts.apply_new_admin(current_ts + 1).unwrap();
+        let old_admin_key = accounts.admin_key;
+        let old_admin_account = accounts.admin_account.clone();
+        accounts.admin_key = multisig_key;
+        accounts.admin_account = multisig_account;
+
+        // not enough signers
+        {
+            assert_eq!(
+                Err(ProgramError::MissingRequiredSignature),
+                accounts.pause_with_admin_multisig(&signers[..1])
+            );
+            assert_eq!(
+                Err(ProgramError::MissingRequiredSignature),
+                accounts.pause_with_admin_multisig(&[signers[0], signers[0]])
+            );
+            let outsider_key = pubkey_rand();
+            assert_eq!(
+                Err(ProgramError::MissingRequiredSignature),
+                accounts.pause_with_admin_multisig(&[signers[0], &outsider_key])
+            );
+        }
+
+        // previous admin key is no longer valid
+        {
+            let multisig_key = accounts.admin_key;
+            let multisig_account = accounts.admin_account.clone();
+            accounts.admin_key = old_admin_key;
+            accounts.admin_account = old_admin_account;
+            assert_eq!(Err(SwapError::Unauthorized.into()), accounts.pause());
+            accounts.admin_key = multisig_key;
+            accounts.admin_account = multisig_account;
+        }
+
+        // threshold reached
+        {
+            accounts
+                .pause_with_admin_multisig(&[signers[2], signers[0]])
+                .unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert!(swap_info.is_paused);
+        }
     }
 
     #[test]
-    fn test_ramp_a_swap_not_initialized() {
+    fn test_set_fee_exemptions() {
         let user_key = pubkey_rand();
         let amp_factor = MIN_AMP * 100;
         let mut accounts = SwapAccountInfo::new(
@@ -339,98 +4671,366 @@ mod tests {
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
+        let (fee_exemptions_key, _bump_seed) =
+            find_fee_exemptions_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut fee_exemptions_account = Account::new(1, FeeExemptions::LEN, &SWAP_PROGRAM_ID);
+        let exempt_keys = [pubkey_rand(), pubkey_rand()];
+        let exempt = [&exempt_keys[0], &exempt_keys[1]];
 
         // swap not initialized
-        assert_eq!(
-            Err(ProgramError::UninitializedAccount),
-            accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION)
-        );
-    }
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.set_fee_exemptions(
+                    &fee_exemptions_key,
+                    &mut fee_exemptions_account,
+                    &exempt
+                )
+            );
+        }
 
-    #[test]
-    fn test_ramp_a_invalid_target_amp() {
-        let mut accounts = init_accounts_ramp_a();
-        let stop_ramp_ts = MIN_RAMP_DURATION;
-        let target_amp = 0;
-        assert_eq!(
-            Err(SwapError::InvalidInput.into()),
-            accounts.ramp_a(target_amp, ZERO_TS, stop_ramp_ts)
-        );
-        let target_amp = MAX_AMP + 1;
-        assert_eq!(
-            Err(SwapError::InvalidInput.into()),
-            accounts.ramp_a(target_amp, ZERO_TS, stop_ramp_ts)
-        );
-    }
+        accounts.initialize_swap().unwrap();
 
-    #[test]
-    fn test_ramp_a_unauthorized_account() {
-        let mut accounts = init_accounts_ramp_a();
-        let fake_admin_key = pubkey_rand();
-        accounts.admin_key = fake_admin_key;
-        assert_eq!(
-            Err(SwapError::Unauthorized.into()),
-            accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION)
-        );
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_fee_exemptions(
+                    &fee_exemptions_key,
+                    &mut fee_exemptions_account,
+                    &exempt
+                )
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // duplicate accounts
+        {
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_fee_exemptions(
+                    &fee_exemptions_key,
+                    &mut fee_exemptions_account,
+                    &[exempt[0], exempt[0]]
+                )
+            );
+        }
+
+        // wrong fee exemptions address
+        {
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.set_fee_exemptions(&pubkey_rand(), &mut fee_exemptions_account, &exempt)
+            );
+        }
+
+        // valid call
+        {
+            accounts
+                .set_fee_exemptions(&fee_exemptions_key, &mut fee_exemptions_account, &exempt)
+                .unwrap();
+
+            let fee_exemptions = FeeExemptions::unpack(&fee_exemptions_account.data).unwrap();
+            assert!(fee_exemptions.is_initialized);
+            assert_eq!(fee_exemptions.swap, accounts.swap_key);
+            assert_eq!(fee_exemptions.accounts(), &exempt_keys[..]);
+        }
+
+        // clear the list
+        {
+            accounts
+                .set_fee_exemptions(&fee_exemptions_key, &mut fee_exemptions_account, &[])
+                .unwrap();
+
+            let fee_exemptions = FeeExemptions::unpack(&fee_exemptions_account.data).unwrap();
+            assert!(fee_exemptions.accounts().is_empty());
+        }
     }
 
     #[test]
-    fn test_ramp_a_locked() {
-        let mut accounts = init_accounts_ramp_a();
-        assert_eq!(
-            Err(SwapError::RampLocked.into()),
-            accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION / 2)
+    fn test_collect_admin_fees() {
+        let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
         );
-    }
+        let (
+            destination_a_key,
+            mut destination_a_account,
+            destination_b_key,
+            mut destination_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, 0);
+        let admin_fee_a_amount = 1_000;
+        let admin_fee_b_amount = 2_000;
 
-    #[test]
-    fn test_ramp_a_insufficient_ramp_time() {
-        let mut accounts = init_accounts_ramp_a();
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.collect_admin_fees(
+                    &destination_a_key,
+                    &mut destination_a_account,
+                    &destination_b_key,
+                    &mut destination_b_account,
+                    0,
+                    0,
+                )
+            );
+        }
 
-        assert_eq!(
-            Err(SwapError::InsufficientRampTime.into()),
-            accounts.ramp_a(accounts.initial_amp_factor, MIN_RAMP_DURATION, ZERO_TS)
-        );
+        accounts.initialize_swap().unwrap();
+        do_process_instruction(
+            mint_to(
+                &spl_token::id(),
+                &accounts.token_a_mint_key,
+                &accounts.admin_fee_a_key,
+                &user_key,
+                &[],
+                admin_fee_a_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.token_a_mint_account,
+                &mut accounts.admin_fee_a_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            mint_to(
+                &spl_token::id(),
+                &accounts.token_b_mint_key,
+                &accounts.admin_fee_b_key,
+                &user_key,
+                &[],
+                admin_fee_b_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.token_b_mint_account,
+                &mut accounts.admin_fee_b_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // unauthorized account
+        {
+            let old_admin_key = accounts.admin_key;
+            let fake_admin_key = pubkey_rand();
+            accounts.admin_key = fake_admin_key;
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.collect_admin_fees(
+                    &destination_a_key,
+                    &mut destination_a_account,
+                    &destination_b_key,
+                    &mut destination_b_account,
+                    admin_fee_a_amount,
+                    admin_fee_b_amount,
+                )
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // wrong destination mint
+        {
+            let mut wrong_destination_account = destination_b_account.clone();
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.collect_admin_fees(
+                    &destination_b_key,
+                    &mut wrong_destination_account,
+                    &destination_b_key,
+                    &mut destination_b_account,
+                    admin_fee_a_amount,
+                    admin_fee_b_amount,
+                )
+            );
+        }
+
+        // admin fee account not owned by the swap authority
+        {
+            let old_admin_fee_a_key = accounts.admin_fee_a_key;
+            let old_admin_fee_a_account = accounts.admin_fee_a_account.clone();
+            accounts
+                .set_admin_fee_account(&destination_a_key, &destination_a_account)
+                .unwrap();
+            accounts.admin_fee_a_key = destination_a_key;
+            accounts.admin_fee_a_account = destination_a_account.clone();
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.collect_admin_fees(
+                    &destination_a_key,
+                    &mut destination_a_account,
+                    &destination_b_key,
+                    &mut destination_b_account,
+                    0,
+                    0,
+                )
+            );
+            accounts
+                .set_admin_fee_account(&old_admin_fee_a_key, &old_admin_fee_a_account)
+                .unwrap();
+            accounts.admin_fee_a_key = old_admin_fee_a_key;
+            accounts.admin_fee_a_account = old_admin_fee_a_account;
+        }
+
+        // valid call
+        {
+            accounts
+                .collect_admin_fees(
+                    &destination_a_key,
+                    &mut destination_a_account,
+                    &destination_b_key,
+                    &mut destination_b_account,
+                    admin_fee_a_amount,
+                    admin_fee_b_amount / 2,
+                )
+                .unwrap();
+
+            let destination_a = utils::unpack_token_account(&destination_a_account.data).unwrap();
+            assert_eq!(destination_a.amount, admin_fee_a_amount);
+            let destination_b = utils::unpack_token_account(&destination_b_account.data).unwrap();
+            assert_eq!(destination_b.amount, admin_fee_b_amount / 2);
+            let admin_fee_a =
+                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+            assert_eq!(admin_fee_a.amount, 0);
+            let admin_fee_b =
+                utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+            assert_eq!(admin_fee_b.amount, admin_fee_b_amount / 2);
+        }
+
+        // cannot collect more than accrued
+        {
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.collect_admin_fees(
+                    &destination_a_key,
+                    &mut destination_a_account,
+                    &destination_b_key,
+                    &mut destination_b_account,
+                    1,
+                    0,
+                )
+            );
+        }
     }
 
     #[test]
-    fn test_ramp_a_invalid_amp_targets() {
-        let mut accounts = init_accounts_ramp_a();
+    fn test_collect_admin_fees_from_vault() {
+        let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP * 100,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (
+            destination_a_key,
+            mut destination_a_account,
+            destination_b_key,
+            mut destination_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, 0);
 
-        // invalid amp targets
-        // amp target too low
+        // the token A admin fees go to a vault of a treasury program
+        let vault_program_id = pubkey_rand();
+        let (vault_key, _bump_seed) =
+            find_admin_fee_vault_address(&vault_program_id, &accounts.swap_key);
+        let (vault_fee_a_key, vault_fee_a_account) = mint_token(
+            &spl_token::id(),
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &vault_key,
+            1_000,
+        );
+        accounts
+            .set_admin_fee_account(&vault_fee_a_key, &vault_fee_a_account)
+            .unwrap();
+        accounts.admin_fee_a_key = vault_fee_a_key;
+        accounts.admin_fee_a_account = vault_fee_a_account;
+        do_process_instruction(
+            mint_to(
+                &spl_token::id(),
+                &accounts.token_b_mint_key,
+                &accounts.admin_fee_b_key,
+                &user_key,
+                &[],
+                2_000,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.token_b_mint_account,
+                &mut accounts.admin_fee_b_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // without the signature of the vault
         assert_eq!(
-            Err(SwapError::InvalidInput.into()),
-            accounts.ramp_a(MIN_AMP, MIN_RAMP_DURATION, MIN_RAMP_DURATION * 2)
+            Err(SwapError::InvalidOwner.into()),
+            accounts.collect_admin_fees(
+                &destination_a_key,
+                &mut destination_a_account,
+                &destination_b_key,
+                &mut destination_b_account,
+                1_000,
+                2_000,
+            )
         );
-        // amp target too high
+        // signed by another account
         assert_eq!(
-            Err(SwapError::InvalidInput.into()),
-            accounts.ramp_a(MAX_AMP, MIN_RAMP_DURATION, MIN_RAMP_DURATION * 2)
+            Err(SwapError::InvalidOwner.into()),
+            accounts.collect_admin_fees_with_fee_owner(
+                &pubkey_rand(),
+                &destination_a_key,
+                &mut destination_a_account,
+                &destination_b_key,
+                &mut destination_b_account,
+                1_000,
+                2_000,
+            )
         );
-    }
-
-    #[test]
-    fn test_ramp_a_valid() {
-        // valid ramp
-        let mut accounts = init_accounts_ramp_a();
 
-        let target_amp = MIN_AMP * 200;
-        let current_ts = MIN_RAMP_DURATION;
-        let stop_ramp_ts = MIN_RAMP_DURATION * 2;
+        // the vault signs for its account, the swap authority for the other one
         accounts
-            .ramp_a(target_amp, current_ts, stop_ramp_ts)
+            .collect_admin_fees_with_fee_owner(
+                &vault_key,
+                &destination_a_key,
+                &mut destination_a_account,
+                &destination_b_key,
+                &mut destination_b_account,
+                1_000,
+                2_000,
+            )
             .unwrap();
-
-        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-        assert_eq!(swap_info.initial_amp_factor, accounts.initial_amp_factor);
-        assert_eq!(swap_info.target_amp_factor, target_amp);
-        assert_eq!(swap_info.start_ramp_ts, current_ts);
-        assert_eq!(swap_info.stop_ramp_ts, stop_ramp_ts);
+        let destination_a = utils::unpack_token_account(&destination_a_account.data).unwrap();
+        assert_eq!(destination_a.amount, 1_000);
+        let destination_b = utils::unpack_token_account(&destination_b_account.data).unwrap();
+        assert_eq!(destination_b.amount, 2_000);
+        let vault_fee_a = utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+        assert_eq!(vault_fee_a.amount, 0);
     }
 
     #[test]
-    fn test_stop_ramp_a() {
+    fn test_set_pool_metadata() {
         let user_key = pubkey_rand();
         let amp_factor = MIN_AMP * 100;
         let mut accounts = SwapAccountInfo::new(
@@ -440,12 +5040,13 @@ mod tests {
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
+        let pool_mint_key = accounts.pool_mint_key;
 
         // swap not initialized
         {
             assert_eq!(
                 Err(ProgramError::UninitializedAccount),
-                accounts.ramp_a(MIN_AMP, ZERO_TS, MIN_RAMP_DURATION)
+                accounts.set_pool_metadata(&pool_mint_key, "Saber LP", "SLP", "")
             );
         }
 
@@ -458,26 +5059,22 @@ mod tests {
             accounts.admin_key = fake_admin_key;
             assert_eq!(
                 Err(SwapError::Unauthorized.into()),
-                accounts.stop_ramp_a(ZERO_TS)
+                accounts.set_pool_metadata(&pool_mint_key, "Saber LP", "SLP", "")
             );
             accounts.admin_key = old_admin_key;
         }
 
-        // valid call
+        // wrong pool mint
         {
-            let expected_ts = MIN_RAMP_DURATION;
-            accounts.stop_ramp_a(expected_ts).unwrap();
-
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.initial_amp_factor, amp_factor);
-            assert_eq!(swap_info.target_amp_factor, amp_factor);
-            assert_eq!(swap_info.start_ramp_ts, expected_ts);
-            assert_eq!(swap_info.stop_ramp_ts, expected_ts);
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.set_pool_metadata(&pubkey_rand(), "Saber LP", "SLP", "")
+            );
         }
     }
 
     #[test]
-    fn test_pause() {
+    fn test_set_max_swap_bps() {
         let user_key = pubkey_rand();
         let mut accounts = SwapAccountInfo::new(
             &user_key,
@@ -486,34 +5083,41 @@ mod tests {
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
-
-        // swap not initialized
-        {
-            assert_eq!(Err(ProgramError::UninitializedAccount), accounts.pause());
-        }
-
         accounts.initialize_swap().unwrap();
 
         // unauthorized account
         {
             let old_admin_key = accounts.admin_key;
-            let fake_admin_key = pubkey_rand();
-            accounts.admin_key = fake_admin_key;
-            assert_eq!(Err(SwapError::Unauthorized.into()), accounts.pause());
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_max_swap_bps(500)
+            );
             accounts.admin_key = old_admin_key;
         }
 
+        // more than the whole reserve
+        {
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.set_max_swap_bps(BPS_DENOMINATOR + 1)
+            );
+        }
+
         // valid call
         {
-            accounts.pause().unwrap();
+            accounts.set_max_swap_bps(500).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.max_swap_bps_of_reserves, 500);
 
+            accounts.set_max_swap_bps(0).unwrap();
             let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert!(swap_info.is_paused);
+            assert_eq!(swap_info.max_swap_bps_of_reserves, 0);
         }
     }
 
     #[test]
-    fn test_unpause() {
+    fn test_set_pool_info() {
         let user_key = pubkey_rand();
         let mut accounts = SwapAccountInfo::new(
             &user_key,
@@ -522,115 +5126,258 @@ mod tests {
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
-
-        // swap not initialized
-        {
-            assert_eq!(Err(ProgramError::UninitializedAccount), accounts.unpause());
-        }
-
         accounts.initialize_swap().unwrap();
+        let mut pool_registry_entry_account =
+            Account::new(1, PoolRegistryEntry::LEN, &SWAP_PROGRAM_ID);
 
         // unauthorized account
         {
             let old_admin_key = accounts.admin_key;
-            let fake_admin_key = pubkey_rand();
-            accounts.admin_key = fake_admin_key;
-            assert_eq!(Err(SwapError::Unauthorized.into()), accounts.unpause());
+            accounts.admin_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_pool_info(
+                    &mut pool_registry_entry_account,
+                    "USDC-USDT",
+                    PoolCategory::Stable,
+                    false
+                )
+            );
             accounts.admin_key = old_admin_key;
         }
 
         // valid call
         {
-            // Pause swap pool
-            accounts.pause().unwrap();
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert!(swap_info.is_paused);
+            accounts
+                .set_pool_info(
+                    &mut pool_registry_entry_account,
+                    "USDC-USDT",
+                    PoolCategory::Stable,
+                    false,
+                )
+                .unwrap();
+            let entry = PoolRegistryEntry::unpack(&pool_registry_entry_account.data).unwrap();
+            assert_eq!(entry.swap, accounts.swap_key);
+            assert_eq!(entry.name(), Some("USDC-USDT"));
+            assert_eq!(entry.category, PoolCategory::Stable);
+            assert!(!entry.is_deprecated);
+        }
 
-            // Unpause swap pool
-            accounts.unpause().unwrap();
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert!(!swap_info.is_paused);
+        // the entry is overwritten
+        {
+            accounts
+                .set_pool_info(
+                    &mut pool_registry_entry_account,
+                    "stSOL-SOL",
+                    PoolCategory::Pegged,
+                    true,
+                )
+                .unwrap();
+            let entry = PoolRegistryEntry::unpack(&pool_registry_entry_account.data).unwrap();
+            assert_eq!(entry.name(), Some("stSOL-SOL"));
+            assert_eq!(entry.category, PoolCategory::Pegged);
+            assert!(entry.is_deprecated);
         }
     }
 
     #[test]
-    fn test_set_fee_account() {
+    fn test_set_rewards_distributor() {
         let user_key = pubkey_rand();
         let owner_key = pubkey_rand();
-        let amp_factor = MIN_AMP * 100;
         let mut accounts = SwapAccountInfo::new(
             &user_key,
-            amp_factor,
+            MIN_AMP,
             DEFAULT_TOKEN_A_AMOUNT,
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
-        let (
-            admin_fee_key_a,
-            admin_fee_account_a,
-            admin_fee_key_b,
-            admin_fee_account_b,
-            wrong_admin_fee_key,
-            wrong_admin_fee_account,
-        ) = accounts.setup_token_accounts(
-            &user_key,
-            &owner_key,
-            DEFAULT_TOKEN_A_AMOUNT,
-            DEFAULT_TOKEN_B_AMOUNT,
-            DEFAULT_POOL_TOKEN_AMOUNT,
-        );
+        accounts.initialize_swap().unwrap();
+        let (rewards_a_key, mut rewards_a_account, rewards_b_key, mut rewards_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, 0);
+        let mut rewards_distributor_account =
+            Account::new(1, RewardsDistributor::LEN, &SWAP_PROGRAM_ID);
+        let mut rewards_program_account = Account {
+            executable: true,
+            ..Account::default()
+        };
 
-        // swap not initialized
+        // unauthorized account
         {
+            let old_admin_key = accounts.admin_key;
+            accounts.admin_key = pubkey_rand();
             assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.set_admin_fee_account(&admin_fee_key_a, &admin_fee_account_a)
+                Err(SwapError::Unauthorized.into()),
+                accounts.set_rewards_distributor(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                )
+            );
+            accounts.admin_key = old_admin_key;
+        }
+
+        // not a program
+        {
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                accounts.set_rewards_distributor(
+                    &mut rewards_distributor_account,
+                    &mut Account::default(),
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                )
+            );
+        }
+
+        // rewards accounts of the wrong tokens
+        {
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.set_rewards_distributor(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account.clone(),
+                    &rewards_a_key,
+                    &mut rewards_a_account.clone(),
+                )
             );
         }
 
+        // valid call
+        {
+            accounts
+                .set_rewards_distributor(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                )
+                .unwrap();
+            let rewards_distributor =
+                RewardsDistributor::unpack(&rewards_distributor_account.data).unwrap();
+            assert_eq!(rewards_distributor.swap, accounts.swap_key);
+            assert_eq!(rewards_distributor.program, REWARDS_PROGRAM_ID);
+            assert_eq!(rewards_distributor.rewards_a, rewards_a_key);
+            assert_eq!(rewards_distributor.rewards_b, rewards_b_key);
+        }
+    }
+
+    #[test]
+    fn test_set_staking_config() {
+        let user_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
         accounts.initialize_swap().unwrap();
+        let mut staking_config_account = Account::new(1, StakingConfig::LEN, &SWAP_PROGRAM_ID);
+        let mut staking_program_account = Account {
+            executable: true,
+            ..Account::default()
+        };
+        let staking_pool_key = pubkey_rand();
+        let mut staking_pool_account = Account::new(1, 0, &STAKING_PROGRAM_ID);
+        let prefix = [1, 2, 3, 4, 5, 6, 7, 8];
 
         // unauthorized account
         {
             let old_admin_key = accounts.admin_key;
-            let fake_admin_key = pubkey_rand();
-            accounts.admin_key = fake_admin_key;
+            accounts.admin_key = pubkey_rand();
             assert_eq!(
                 Err(SwapError::Unauthorized.into()),
-                accounts.set_admin_fee_account(&admin_fee_key_a, &admin_fee_account_a)
+                accounts.set_staking_config(
+                    &mut staking_config_account,
+                    &mut staking_program_account,
+                    &staking_pool_key,
+                    &mut staking_pool_account,
+                    prefix,
+                )
             );
             accounts.admin_key = old_admin_key;
         }
 
-        // wrong admin account
+        // not a program
         {
             assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                accounts.set_admin_fee_account(&wrong_admin_fee_key, &wrong_admin_fee_account)
+                Err(ProgramError::IncorrectProgramId),
+                accounts.set_staking_config(
+                    &mut staking_config_account,
+                    &mut Account::default(),
+                    &staking_pool_key,
+                    &mut staking_pool_account,
+                    prefix,
+                )
             );
         }
 
-        // valid calls
+        // staking pool of another program
+        {
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                accounts.set_staking_config(
+                    &mut staking_config_account,
+                    &mut staking_program_account,
+                    &staking_pool_key,
+                    &mut Account::new(1, 0, &pubkey_rand()),
+                    prefix,
+                )
+            );
+        }
+
+        // valid call
         {
-            // set fee account a
-            accounts
-                .set_admin_fee_account(&admin_fee_key_a, &admin_fee_account_a)
-                .unwrap();
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.token_a.admin_fees, admin_fee_key_a);
-            // set fee account b
             accounts
-                .set_admin_fee_account(&admin_fee_key_b, &admin_fee_account_b)
+                .set_staking_config(
+                    &mut staking_config_account,
+                    &mut staking_program_account,
+                    &staking_pool_key,
+                    &mut staking_pool_account,
+                    prefix,
+                )
                 .unwrap();
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.token_b.admin_fees, admin_fee_key_b);
+            let staking_config = StakingConfig::unpack(&staking_config_account.data).unwrap();
+            assert_eq!(staking_config.swap, accounts.swap_key);
+            assert_eq!(staking_config.program, STAKING_PROGRAM_ID);
+            assert_eq!(staking_config.staking_pool, staking_pool_key);
+            assert_eq!(staking_config.stake_instruction_prefix, prefix);
         }
     }
 
     #[test]
-    fn test_apply_new_admin() {
+    fn test_set_lp_frozen() {
         let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
         let amp_factor = MIN_AMP * 100;
+
+        // the pool mint has no freeze authority
+        {
+            let mut accounts = SwapAccountInfo::new(
+                &user_key,
+                amp_factor,
+                DEFAULT_TOKEN_A_AMOUNT,
+                DEFAULT_TOKEN_B_AMOUNT,
+                DEFAULT_TEST_FEES,
+            );
+            let (_, _, _, _, pool_key, mut pool_account) =
+                accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, 0);
+            accounts.initialize_swap().unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidFreezeAuthority.into()),
+                accounts.set_lp_frozen(true, &pool_key, &mut pool_account)
+            );
+        }
+
         let mut accounts = SwapAccountInfo::new(
             &user_key,
             amp_factor,
@@ -638,181 +5385,341 @@ mod tests {
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
-
-        // swap not initialized
-        {
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.apply_new_admin(ZERO_TS)
-            );
-        }
-
+        let (_, freezable_mint_account) = create_mint(
+            &spl_token::id(),
+            &accounts.authority_key,
+            DEFAULT_TOKEN_DECIMALS,
+            Some(&accounts.authority_key),
+        );
+        accounts.pool_mint_account = freezable_mint_account;
+        let (token_a_key, mut token_a_account, _, _, pool_key, mut pool_account) =
+            accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, 0);
         accounts.initialize_swap().unwrap();
 
         // unauthorized account
         {
             let old_admin_key = accounts.admin_key;
-            let fake_admin_key = pubkey_rand();
-            accounts.admin_key = fake_admin_key;
+            accounts.admin_key = pubkey_rand();
             assert_eq!(
                 Err(SwapError::Unauthorized.into()),
-                accounts.apply_new_admin(ZERO_TS)
+                accounts.set_lp_frozen(true, &pool_key, &mut pool_account)
             );
             accounts.admin_key = old_admin_key;
         }
 
-        // no active transfer
+        // not an LP token account
         {
             assert_eq!(
-                Err(SwapError::NoActiveTransfer.into()),
-                accounts.apply_new_admin(ZERO_TS)
+                Err(SwapError::IncorrectMint.into()),
+                accounts.set_lp_frozen(true, &token_a_key, &mut token_a_account)
             );
         }
 
-        // apply new admin
+        // wrong swap authority
         {
-            let new_admin_key = pubkey_rand();
-            let current_ts = MIN_RAMP_DURATION;
+            let old_authority_key = accounts.authority_key;
+            accounts.authority_key = pubkey_rand();
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.set_lp_frozen(true, &pool_key, &mut pool_account)
+            );
+            accounts.authority_key = old_authority_key;
+        }
 
-            // Commit to initiate admin transfer
+        // freeze, then thaw
+        {
             accounts
-                .commit_new_admin(&new_admin_key, current_ts)
+                .set_lp_frozen(true, &pool_key, &mut pool_account)
                 .unwrap();
+            let lp_account = SplAccount::unpack(&pool_account.data).unwrap();
+            assert_eq!(lp_account.state, AccountState::Frozen);
 
-            // Applying transfer past deadline should fail
-            let apply_deadline = current_ts + MIN_RAMP_DURATION * 3;
-            assert_eq!(
-                Err(SwapError::AdminDeadlineExceeded.into()),
-                accounts.apply_new_admin(apply_deadline + 1)
-            );
-
-            // Apply to finalize admin transfer
-            accounts.apply_new_admin(current_ts + 1).unwrap();
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.admin_key, new_admin_key);
-            assert_eq!(swap_info.future_admin_key, Pubkey::default());
-            assert_eq!(swap_info.future_admin_deadline, ZERO_TS);
+            accounts
+                .set_lp_frozen(false, &pool_key, &mut pool_account)
+                .unwrap();
+            let lp_account = SplAccount::unpack(&pool_account.data).unwrap();
+            assert_eq!(lp_account.state, AccountState::Initialized);
         }
     }
 
     #[test]
-    fn test_commit_new_admin() {
+    fn test_migrate_pool() {
         let user_key = pubkey_rand();
-        let new_admin_key = pubkey_rand();
-        let current_ts = ZERO_TS;
-        let amp_factor = MIN_AMP * 100;
         let mut accounts = SwapAccountInfo::new(
             &user_key,
-            amp_factor,
+            MIN_AMP * 100,
             DEFAULT_TOKEN_A_AMOUNT,
             DEFAULT_TOKEN_B_AMOUNT,
             DEFAULT_TEST_FEES,
         );
-
-        // swap not initialized
-        {
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.commit_new_admin(&new_admin_key, current_ts)
-            );
-        }
+        let mut new_accounts = SwapAccountInfo::new_with_token_mints(
+            &user_key,
+            MIN_AMP * 200,
+            DEFAULT_TOKEN_A_AMOUNT / 10,
+            DEFAULT_TOKEN_B_AMOUNT / 10,
+            DEFAULT_TEST_FEES,
+            (
+                &accounts.token_a_mint_key,
+                accounts.token_a_mint_account.clone(),
+            ),
+            (
+                &accounts.token_b_mint_key,
+                accounts.token_b_mint_account.clone(),
+            ),
+        );
+        let (lp_escrow_key, mut lp_escrow_account) = mint_token(
+            &spl_token::id(),
+            &new_accounts.pool_mint_key,
+            &mut new_accounts.pool_mint_account,
+            &new_accounts.authority_key,
+            &accounts.authority_key,
+            0,
+        );
+        let mut pool_migration_account = Account::new(1, PoolMigration::LEN, &SWAP_PROGRAM_ID);
 
         accounts.initialize_swap().unwrap();
+        new_accounts.initialize_swap().unwrap();
+        let new_pool_supply = utils::unpack_mint(&new_accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
 
         // unauthorized account
         {
             let old_admin_key = accounts.admin_key;
-            let fake_admin_key = pubkey_rand();
-            accounts.admin_key = fake_admin_key;
+            accounts.admin_key = pubkey_rand();
             assert_eq!(
                 Err(SwapError::Unauthorized.into()),
-                accounts.commit_new_admin(&new_admin_key, current_ts)
+                accounts.migrate_pool(
+                    &mut new_accounts,
+                    &lp_escrow_key,
+                    &mut lp_escrow_account,
+                    &mut pool_migration_account,
+                )
             );
             accounts.admin_key = old_admin_key;
         }
 
-        // commit new admin
+        // new swap of other tokens
+        {
+            let mut other_accounts = SwapAccountInfo::new(
+                &user_key,
+                MIN_AMP * 200,
+                DEFAULT_TOKEN_A_AMOUNT,
+                DEFAULT_TOKEN_B_AMOUNT,
+                DEFAULT_TEST_FEES,
+            );
+            other_accounts.initialize_swap().unwrap();
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.migrate_pool(
+                    &mut other_accounts,
+                    &lp_escrow_key,
+                    &mut lp_escrow_account,
+                    &mut pool_migration_account,
+                )
+            );
+        }
+
+        // escrow not owned by the swap authority
+        {
+            let (wrong_escrow_key, mut wrong_escrow_account) = mint_token(
+                &spl_token::id(),
+                &new_accounts.pool_mint_key,
+                &mut new_accounts.pool_mint_account,
+                &new_accounts.authority_key,
+                &user_key,
+                0,
+            );
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.migrate_pool(
+                    &mut new_accounts,
+                    &wrong_escrow_key,
+                    &mut wrong_escrow_account,
+                    &mut pool_migration_account,
+                )
+            );
+        }
+
+        // valid migration
         {
-            // valid call
             accounts
-                .commit_new_admin(&new_admin_key, current_ts)
+                .migrate_pool(
+                    &mut new_accounts,
+                    &lp_escrow_key,
+                    &mut lp_escrow_account,
+                    &mut pool_migration_account,
+                )
                 .unwrap();
 
             let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.future_admin_key, new_admin_key);
-            let expected_future_ts = current_ts + MIN_RAMP_DURATION * 3;
-            assert_eq!(swap_info.future_admin_deadline, expected_future_ts);
+            assert!(swap_info.is_paused);
+            let token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            assert_eq!(token_a.amount, 0);
+            assert_eq!(token_b.amount, 0);
+            let new_token_a =
+                utils::unpack_token_account(&new_accounts.token_a_account.data).unwrap();
+            let new_token_b =
+                utils::unpack_token_account(&new_accounts.token_b_account.data).unwrap();
+            assert_eq!(
+                new_token_a.amount,
+                DEFAULT_TOKEN_A_AMOUNT + DEFAULT_TOKEN_A_AMOUNT / 10
+            );
+            assert_eq!(
+                new_token_b.amount,
+                DEFAULT_TOKEN_B_AMOUNT + DEFAULT_TOKEN_B_AMOUNT / 10
+            );
 
-            // new commit within deadline should fail
+            // balanced deposits keep the virtual price: the escrow gets 10x the new supply
+            let lp_escrow = utils::unpack_token_account(&lp_escrow_account.data).unwrap();
+            assert_eq!(lp_escrow.amount, new_pool_supply * 10);
+            let new_swap_info = SwapInfo::unpack(&new_accounts.swap_account.data).unwrap();
             assert_eq!(
-                Err(SwapError::ActiveTransfer.into()),
-                accounts.commit_new_admin(&new_admin_key, current_ts + 1),
+                new_swap_info.pool_token_supply,
+                new_pool_supply + lp_escrow.amount
             );
 
-            // new commit after deadline should be valid
-            let new_admin_key = pubkey_rand();
-            let current_ts = expected_future_ts + 1;
-            accounts
-                .commit_new_admin(&new_admin_key, current_ts)
-                .unwrap();
-            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.future_admin_key, new_admin_key);
-            let expected_future_ts = current_ts + MIN_RAMP_DURATION * 3;
-            assert_eq!(swap_info.future_admin_deadline, expected_future_ts);
+            let pool_migration = PoolMigration::unpack(&pool_migration_account.data).unwrap();
+            assert_eq!(
+                pool_migration,
+                PoolMigration {
+                    is_initialized: true,
+                    swap: accounts.swap_key,
+                    new_swap: new_accounts.swap_key,
+                    lp_escrow: lp_escrow_key,
+                }
+            );
         }
-    }
 
-    #[test]
-    fn test_set_new_fees() {
-        let user_key = pubkey_rand();
-        let amp_factor = MIN_AMP * 100;
-        let mut accounts = SwapAccountInfo::new(
+        // already migrated
+        {
+            assert_eq!(
+                Err(SwapError::AlreadyInUse.into()),
+                accounts.migrate_pool(
+                    &mut new_accounts,
+                    &lp_escrow_key,
+                    &mut lp_escrow_account,
+                    &mut pool_migration_account,
+                )
+            );
+        }
+
+        // redeem the pool tokens of the migrated swap
+        let pool_token_amount = utils::unpack_token_account(&accounts.pool_token_account.data)
+            .unwrap()
+            .amount;
+        let escrow_amount = utils::unpack_token_account(&lp_escrow_account.data)
+            .unwrap()
+            .amount;
+        let pool_token_key = accounts.pool_token_key;
+        let mut pool_token_account = accounts.pool_token_account.clone();
+        let (destination_key, mut destination_account) = mint_token(
+            &spl_token::id(),
+            &new_accounts.pool_mint_key,
+            &mut new_accounts.pool_mint_account,
+            &new_accounts.authority_key,
             &user_key,
-            amp_factor,
-            DEFAULT_TOKEN_A_AMOUNT,
-            DEFAULT_TOKEN_B_AMOUNT,
-            DEFAULT_TEST_FEES,
+            0,
         );
 
-        let new_fees: Fees = Fees {
-            admin_trade_fee_numerator: 0,
-            admin_trade_fee_denominator: 0,
-            admin_withdraw_fee_numerator: 0,
-            admin_withdraw_fee_denominator: 0,
-            trade_fee_numerator: 0,
-            trade_fee_denominator: 0,
-            withdraw_fee_numerator: 0,
-            withdraw_fee_denominator: 0,
-        };
+        // wrong escrow
+        {
+            let mut wrong_escrow_account = destination_account.clone();
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.redeem_migrated_lp(
+                    &user_key,
+                    (&pool_token_key, &mut pool_token_account),
+                    (&destination_key, &mut wrong_escrow_account),
+                    (&destination_key, &mut destination_account.clone()),
+                    &mut pool_migration_account,
+                    pool_token_amount,
+                )
+            );
+        }
 
-        // swap not initialized
+        // swap not migrated
         {
+            let mut not_migrated_account = Account::new(1, PoolMigration::LEN, &SWAP_PROGRAM_ID);
             assert_eq!(
                 Err(ProgramError::UninitializedAccount),
-                accounts.set_new_fees(new_fees)
+                accounts.redeem_migrated_lp(
+                    &user_key,
+                    (&pool_token_key, &mut pool_token_account),
+                    (&lp_escrow_key, &mut lp_escrow_account),
+                    (&destination_key, &mut destination_account),
+                    &mut not_migrated_account,
+                    pool_token_amount,
+                )
             );
         }
 
-        accounts.initialize_swap().unwrap();
-
-        // unauthorized account
+        // a delegate cannot credit its own accounts
         {
-            let old_admin_key = accounts.admin_key;
-            let fake_admin_key = pubkey_rand();
-            accounts.admin_key = fake_admin_key;
+            let delegate_key = pubkey_rand();
+            let (delegate_destination_key, mut delegate_destination_account) = mint_token(
+                &spl_token::id(),
+                &new_accounts.pool_mint_key,
+                &mut new_accounts.pool_mint_account,
+                &new_accounts.authority_key,
+                &delegate_key,
+                0,
+            );
+            let mut delegated_pool_token_account = pool_token_account.clone();
+            approve_delegate(
+                &pool_token_key,
+                &mut delegated_pool_token_account,
+                &user_key,
+                &delegate_key,
+                pool_token_amount,
+            );
             assert_eq!(
-                Err(SwapError::Unauthorized.into()),
-                accounts.set_new_fees(new_fees)
+                Err(SwapError::InvalidWithdrawDestination.into()),
+                accounts.redeem_migrated_lp(
+                    &delegate_key,
+                    (&pool_token_key, &mut delegated_pool_token_account),
+                    (&lp_escrow_key, &mut lp_escrow_account),
+                    (&delegate_destination_key, &mut delegate_destination_account),
+                    &mut pool_migration_account,
+                    pool_token_amount,
+                )
             );
-            accounts.admin_key = old_admin_key;
         }
 
-        // valid call
+        // redeem in two parts
         {
-            accounts.set_new_fees(new_fees).unwrap();
+            accounts
+                .redeem_migrated_lp(
+                    &user_key,
+                    (&pool_token_key, &mut pool_token_account),
+                    (&lp_escrow_key, &mut lp_escrow_account),
+                    (&destination_key, &mut destination_account),
+                    &mut pool_migration_account,
+                    pool_token_amount / 4,
+                )
+                .unwrap();
+            let destination = utils::unpack_token_account(&destination_account.data).unwrap();
+            assert_eq!(destination.amount, escrow_amount / 4);
 
+            accounts
+                .redeem_migrated_lp(
+                    &user_key,
+                    (&pool_token_key, &mut pool_token_account),
+                    (&lp_escrow_key, &mut lp_escrow_account),
+                    (&destination_key, &mut destination_account),
+                    &mut pool_migration_account,
+                    pool_token_amount - pool_token_amount / 4,
+                )
+                .unwrap();
+            let destination = utils::unpack_token_account(&destination_account.data).unwrap();
+            assert_eq!(destination.amount, escrow_amount);
+            let lp_escrow = utils::unpack_token_account(&lp_escrow_account.data).unwrap();
+            assert_eq!(lp_escrow.amount, 0);
+            let pool_token = utils::unpack_token_account(&pool_token_account.data).unwrap();
+            assert_eq!(pool_token.amount, 0);
             let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-            assert_eq!(swap_info.fees, new_fees);
+            assert_eq!(swap_info.pool_token_supply, 0);
         }
     }
 }
@@ -0,0 +1,270 @@
+//! Invariant assertions of builds with the `paranoid` feature.
+//!
+//! The pool an instruction is given is read before and after the instruction, and
+//! the instruction fails with [SwapError::InvariantViolation] if it left the pool
+//! breaking one of its invariants, aborting the transaction:
+//! - the invariant D does not decrease, unless pool tokens were burned or the pool
+//!   was migrated
+//! - the reserve amounts the swap records match the reserves the instruction moved,
+//!   unless it was given the swap read-only
+//! - the pool token supply the swap records matches the pool mint once it changed,
+//!   unless the swap is stored in the legacy layout
+
+use crate::{
+    error::SwapError,
+    processor::{checks::find_account_by_key, utils},
+    state::{SwapInfo, SWAP_INFO_LEGACY_LEN},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    sysvar::{self, Sysvar},
+};
+
+/// A pool as read from the accounts of an instruction. Reserves and the pool mint
+/// are only known if the instruction was given them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolAudit {
+    /// The swap
+    pub swap: SwapInfo,
+    /// Whether the instruction can record the reserve amounts: the swap is writable and
+    /// not stored in the legacy layout
+    pub records_reserves: bool,
+    /// Whether the swap records the pool token supply, i.e. is not stored in the legacy
+    /// layout
+    pub records_pool_token_supply: bool,
+    /// Balances of the token A and token B reserves
+    pub reserves: [Option<u64>; 2],
+    /// Supply of the pool mint
+    pub pool_token_supply: Option<u64>,
+}
+
+impl PoolAudit {
+    /// Reads the pool of the first account of an instruction, if it is a swap of the
+    /// program. Instructions of the program not given a swap are not audited.
+    pub fn load(program_id: &Pubkey, accounts: &[AccountInfo]) -> Option<Self> {
+        let swap_info = accounts.first().filter(|info| info.owner == program_id)?;
+        let data = swap_info.data.borrow();
+        let swap = SwapInfo::unpack(&data).ok()?;
+        let token_amount = |key: &Pubkey| {
+            find_account_by_key(accounts, key)
+                .and_then(|info| utils::unpack_token_account(&info.data.borrow()).ok())
+                .map(|account| account.amount)
+        };
+        let reserves = [
+            token_amount(&swap.token_a.reserves),
+            token_amount(&swap.token_b.reserves),
+        ];
+        let pool_token_supply = find_account_by_key(accounts, &swap.pool_mint)
+            .and_then(|info| utils::unpack_mint(&info.data.borrow()).ok())
+            .map(|mint| mint.supply);
+        Some(Self {
+            records_reserves: swap_info.is_writable && data.len() != SWAP_INFO_LEGACY_LEN,
+            records_pool_token_supply: data.len() != SWAP_INFO_LEGACY_LEN,
+            swap,
+            reserves,
+            pool_token_supply,
+        })
+    }
+}
+
+/// Checks the pool of an instruction once it succeeded, against the pool read before
+/// the instruction. `withdraws_without_burn` is set for the instructions moving the
+/// liquidity out of the pool without burning pool tokens, i.e. MigratePool.
+pub fn check_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    before: Option<PoolAudit>,
+    withdraws_without_burn: bool,
+) -> ProgramResult {
+    let (before, after) = match (before, PoolAudit::load(program_id, accounts)) {
+        (Some(before), Some(after)) => (before, after),
+        _ => return Ok(()),
+    };
+    check_reserves(&before, &after)?;
+    check_pool_token_supply(&before, &after)?;
+    if withdraws_without_burn {
+        return Ok(());
+    }
+    let clock = match accounts
+        .iter()
+        .find(|info| sysvar::clock::check_id(info.key))
+    {
+        Some(clock_sysvar_info) => Clock::from_account_info(clock_sysvar_info)?,
+        None => Clock::get()?,
+    };
+    check_invariant(&before, &after, clock.unix_timestamp)
+}
+
+/// The reserve amounts recorded by the swap match the reserves the instruction moved.
+/// Tokens sent to the reserves directly are only recorded by SyncReserves, so
+/// reserves the instruction left alone may hold more.
+pub fn check_reserves(before: &PoolAudit, after: &PoolAudit) -> ProgramResult {
+    if !after.records_reserves {
+        return Ok(());
+    }
+    let recorded = [after.swap.reserve_amount_a, after.swap.reserve_amount_b];
+    for (i, name) in ["A", "B"].iter().enumerate() {
+        if let (Some(amount_before), Some(amount)) = (before.reserves[i], after.reserves[i]) {
+            if amount != amount_before && amount != recorded[i] {
+                msg!(
+                    "Audit: reserve {} holds {} but the swap records {}",
+                    name,
+                    amount,
+                    recorded[i]
+                );
+                return Err(SwapError::InvariantViolation.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The pool token supply recorded by the swap matches the pool mint once the
+/// instruction minted or burned pool tokens
+pub fn check_pool_token_supply(before: &PoolAudit, after: &PoolAudit) -> ProgramResult {
+    if !after.records_pool_token_supply {
+        return Ok(());
+    }
+    if let (Some(supply_before), Some(supply)) = (before.pool_token_supply, after.pool_token_supply)
+    {
+        if supply != supply_before && supply != after.swap.pool_token_supply {
+            msg!(
+                "Audit: pool mint supply is {} but the swap records {}",
+                supply,
+                after.swap.pool_token_supply
+            );
+            return Err(SwapError::InvariantViolation.into());
+        }
+    }
+    Ok(())
+}
+
+/// D does not decrease unless pool tokens were burned. Both sides are computed with
+/// the amp factor and peg rates of the pool after the instruction, so that ramps and
+/// peg rate refreshes, which do not move the reserves, leave D unchanged.
+pub fn check_invariant(before: &PoolAudit, after: &PoolAudit, current_ts: i64) -> ProgramResult {
+    let (supply_before, supply) = match (before.pool_token_supply, after.pool_token_supply) {
+        (Some(supply_before), Some(supply)) => (supply_before, supply),
+        _ => return Ok(()),
+    };
+    let (reserves_before, reserves) = match (before.reserves, after.reserves) {
+        ([Some(a_before), Some(b_before)], [Some(a), Some(b)]) => ([a_before, b_before], [a, b]),
+        _ => return Ok(()),
+    };
+    if supply < supply_before {
+        return Ok(());
+    }
+    let swap = &after.swap;
+    let invariant = swap.curve_calculator(current_ts);
+    let rate_a = swap.token_peg_rate(&swap.token_a.reserves);
+    let rate_b = swap.token_peg_rate(&swap.token_b.reserves);
+    let d_before = invariant
+        .compute_d_with_rates(reserves_before[0], reserves_before[1], rate_a, rate_b)
+        .ok_or(SwapError::CalculationFailure)?;
+    let d = invariant
+        .compute_d_with_rates(reserves[0], reserves[1], rate_a, rate_b)
+        .ok_or(SwapError::CalculationFailure)?;
+    if d < d_before {
+        msg!("Audit: D decreased from {} to {}", d_before, d);
+        return Err(SwapError::InvariantViolation.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::{MIN_AMP, ZERO_TS},
+        processor::test_utils::*,
+    };
+
+    fn pool_audit(reserves: [u64; 2], pool_token_supply: u64) -> PoolAudit {
+        let mut accounts = SwapAccountInfo::new(
+            &pubkey_rand(),
+            MIN_AMP,
+            1_000_000,
+            1_000_000,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let mut swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        swap.reserve_amount_a = reserves[0];
+        swap.reserve_amount_b = reserves[1];
+        swap.pool_token_supply = pool_token_supply;
+        PoolAudit {
+            swap,
+            records_reserves: true,
+            records_pool_token_supply: true,
+            reserves: [Some(reserves[0]), Some(reserves[1])],
+            pool_token_supply: Some(pool_token_supply),
+        }
+    }
+
+    #[test]
+    fn test_check_reserves() {
+        let before = pool_audit([1_000, 1_000], 2_000);
+        let mut after = pool_audit([1_100, 900], 2_000);
+        assert_eq!(check_reserves(&before, &after), Ok(()));
+
+        // tokens sent to the reserves directly
+        after.reserves[0] = Some(1_000);
+        after.swap.reserve_amount_a = 500;
+        assert_eq!(check_reserves(&before, &after), Ok(()));
+
+        // moved without being recorded
+        after.reserves[1] = Some(800);
+        assert_eq!(
+            check_reserves(&before, &after),
+            Err(SwapError::InvariantViolation.into())
+        );
+        after.records_reserves = false;
+        assert_eq!(check_reserves(&before, &after), Ok(()));
+    }
+
+    #[test]
+    fn test_check_pool_token_supply() {
+        let before = pool_audit([1_000, 1_000], 2_000);
+        let mut after = pool_audit([1_100, 1_100], 2_200);
+        assert_eq!(check_pool_token_supply(&before, &after), Ok(()));
+        after.swap.pool_token_supply = 2_000;
+        assert_eq!(
+            check_pool_token_supply(&before, &after),
+            Err(SwapError::InvariantViolation.into())
+        );
+        after.records_pool_token_supply = false;
+        assert_eq!(check_pool_token_supply(&before, &after), Ok(()));
+        after.records_pool_token_supply = true;
+        after.pool_token_supply = None;
+        assert_eq!(check_pool_token_supply(&before, &after), Ok(()));
+    }
+
+    #[test]
+    fn test_check_invariant() {
+        let before = pool_audit([1_000_000, 1_000_000], 2_000_000);
+        // a swap with fees
+        let after = pool_audit([1_010_000, 990_100], 2_000_000);
+        assert_eq!(check_invariant(&before, &after, ZERO_TS), Ok(()));
+        // a swap giving away tokens
+        let after = pool_audit([1_010_000, 989_000], 2_000_000);
+        assert_eq!(
+            check_invariant(&before, &after, ZERO_TS),
+            Err(SwapError::InvariantViolation.into())
+        );
+        // a withdrawal
+        let after = pool_audit([500_000, 500_000], 1_000_000);
+        assert_eq!(check_invariant(&before, &after, ZERO_TS), Ok(()));
+        // a withdrawal that burns nothing
+        let after = pool_audit([500_000, 500_000], 2_000_000);
+        assert_eq!(
+            check_invariant(&before, &after, ZERO_TS),
+            Err(SwapError::InvariantViolation.into())
+        );
+    }
+}
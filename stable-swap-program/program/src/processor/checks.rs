@@ -1,18 +1,29 @@
 //! Checks for processing instructions.
 
 use crate::{
-    error::SwapError,
+    curve::SwapResult,
+    error::{ErrorContext, SwapError},
+    fees::BPS_DENOMINATOR,
+    math,
+    oracle::{self, OraclePrice, MAX_PRICE_AGE_SLOTS},
     processor::utils,
-    state::{SwapInfo, SwapTokenInfo},
+    state::{
+        find_cpi_allowlist_address, find_deposit_allowlist_address, find_deposit_caps_address,
+        find_fee_exemptions_address, find_global_state_address, find_lp_position_address,
+        find_pool_migration_address, find_pool_stats_address, find_rewards_distributor_address,
+        find_staking_config_address, AdminMultisig, CpiAllowlist, CpiPolicy, DepositAllowlist,
+        DepositCaps, FeeExemptions, GlobalState, PegRateSource, PoolStats, StakingConfig, SwapInfo,
+        SwapTokenInfo, MAX_ADMIN_SIGNERS,
+    },
 };
 
+use arrayref::array_ref;
 use solana_program::{
-    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
-    pubkey::Pubkey,
+    account_info::AccountInfo, bpf_loader_upgradeable, clock::Clock, entrypoint::ProgramResult,
+    msg, program_error::ProgramError, program_option::COption, program_pack::Pack, pubkey::Pubkey,
+    sysvar,
 };
 
-use super::logging::log_slippage_error;
-
 /// Checks if the reserve of the swap is the given key.
 fn check_reserves_match(token: &SwapTokenInfo, reserves_info_key: &Pubkey) -> ProgramResult {
     check_token_keys_equal!(
@@ -42,6 +53,594 @@ pub fn check_has_admin_signer(
     Ok(())
 }
 
+/// Access control for admin only instructions. The admin is either a single signer
+/// or an admin multisig, in which case at least `threshold` of its signers must sign.
+pub fn check_has_admin_signers(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    expected_admin_key: &Pubkey,
+    admin_account_info: &AccountInfo,
+    signer_infos: &[AccountInfo],
+) -> ProgramResult {
+    if admin_account_info.is_signer || admin_account_info.owner != program_id {
+        return check_has_admin_signer(expected_admin_key, admin_account_info);
+    }
+    check_keys_equal!(
+        *expected_admin_key,
+        *admin_account_info.key,
+        "Admin multisig",
+        SwapError::Unauthorized
+    );
+    let multisig = AdminMultisig::unpack(&admin_account_info.data.borrow())
+        .map_err(|_| SwapError::Unauthorized)?;
+    check_keys_equal!(
+        *swap_key,
+        multisig.swap,
+        "Admin multisig swap",
+        SwapError::Unauthorized
+    );
+
+    let mut matched = [false; MAX_ADMIN_SIGNERS];
+    let mut num_signed = 0;
+    for signer_info in signer_infos.iter().filter(|info| info.is_signer) {
+        for (position, key) in multisig.signers().iter().enumerate() {
+            if key == signer_info.key && !matched[position] {
+                matched[position] = true;
+                num_signed += 1;
+            }
+        }
+    }
+    if num_signed < multisig.threshold {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// Access control for instructions of the program upgrade authority
+pub fn check_has_upgrade_authority_signer(
+    program_id: &Pubkey,
+    program_data_info: &AccountInfo,
+    upgrade_authority_info: &AccountInfo,
+) -> ProgramResult {
+    let (program_data_key, _bump_seed) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    check_keys_equal!(
+        program_data_key,
+        *program_data_info.key,
+        "Program data",
+        SwapError::InvalidProgramAddress
+    );
+    if *program_data_info.owner != bpf_loader_upgradeable::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // UpgradeableLoaderState::ProgramData { slot: u64, upgrade_authority_address: Option<Pubkey> }
+    let data = program_data_info.data.borrow();
+    let upgrade_authority = match data.get(..45) {
+        Some(metadata) if metadata[..4] == [3, 0, 0, 0] && metadata[12] == 1 => {
+            Pubkey::new_from_array(*array_ref![metadata, 13, 32])
+        }
+        _ => return Err(SwapError::Unauthorized.into()),
+    };
+    check_has_admin_signer(&upgrade_authority, upgrade_authority_info)
+}
+
+/// Checks that the account is the global state of the program, and that
+/// pools are not paused by the emergency authority. The global state must
+/// have been created with SetEmergencyAuthority.
+pub fn check_not_globally_paused(
+    program_id: &Pubkey,
+    global_state_info: &AccountInfo,
+) -> ProgramResult {
+    let (global_state_key, _bump_seed) = find_global_state_address(program_id);
+    check_keys_equal!(
+        global_state_key,
+        *global_state_info.key,
+        "Global state",
+        SwapError::InvalidProgramAddress
+    );
+    if global_state_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if GlobalState::unpack(&global_state_info.data.borrow())?.is_paused {
+        return Err(SwapError::GloballyPaused.into());
+    }
+    Ok(())
+}
+
+/// Checks that the account is the fee exemption list of the swap.
+fn check_fee_exemptions_account(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    fee_exemptions_info: &AccountInfo,
+) -> ProgramResult {
+    let (fee_exemptions_key, _bump_seed) = find_fee_exemptions_address(program_id, swap_key);
+    check_keys_equal!(
+        fee_exemptions_key,
+        *fee_exemptions_info.key,
+        "Fee exemptions",
+        SwapError::InvalidProgramAddress
+    );
+    if fee_exemptions_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Whether the user authority is on the fee exemption list of the swap, if one is given.
+pub fn is_fee_exempt(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    user_authority_key: &Pubkey,
+    fee_exemptions_info: Option<&AccountInfo>,
+) -> Result<bool, ProgramError> {
+    let fee_exemptions_info = match fee_exemptions_info {
+        Some(fee_exemptions_info) => fee_exemptions_info,
+        None => return Ok(false),
+    };
+    check_fee_exemptions_account(program_id, swap_key, fee_exemptions_info)?;
+    let fee_exemptions = FeeExemptions::unpack(&fee_exemptions_info.data.borrow())?;
+    Ok(fee_exemptions.is_exempt(user_authority_key))
+}
+
+/// Checks that the user authority may deposit, i.e. that the swap did not enable its
+/// deposit allowlist or that the user authority is on it.
+pub fn check_deposit_allowed(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    user_authority_key: &Pubkey,
+    deposit_allowlist_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    if !token_swap.deposit_allowlist_enabled {
+        return Ok(());
+    }
+    let deposit_allowlist_info =
+        deposit_allowlist_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let (deposit_allowlist_key, _bump_seed) = find_deposit_allowlist_address(program_id, swap_key);
+    check_keys_equal!(
+        deposit_allowlist_key,
+        *deposit_allowlist_info.key,
+        "Deposit allowlist",
+        SwapError::InvalidProgramAddress
+    );
+    if deposit_allowlist_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let deposit_allowlist = DepositAllowlist::unpack(&deposit_allowlist_info.data.borrow())?;
+    if !deposit_allowlist.is_allowed(user_authority_key) {
+        return Err(SwapError::NotAllowlisted.into());
+    }
+    Ok(())
+}
+
+/// Checks that a deposit leaving the pool mint with `lp_supply` pool tokens, and minting
+/// `mint_amount` of them to the pool token account `dest_info`, stays within the
+/// deposit caps of the swap, if it enabled them.
+pub fn check_deposit_caps(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    lp_supply: u64,
+    dest_info: &AccountInfo,
+    mint_amount: u64,
+    deposit_caps_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    if !token_swap.deposit_cap_enabled {
+        return Ok(());
+    }
+    let deposit_caps_info = deposit_caps_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let (deposit_caps_key, _bump_seed) = find_deposit_caps_address(program_id, swap_key);
+    check_keys_equal!(
+        deposit_caps_key,
+        *deposit_caps_info.key,
+        "Deposit caps",
+        SwapError::InvalidProgramAddress
+    );
+    if deposit_caps_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let deposit_caps = DepositCaps::unpack(&deposit_caps_info.data.borrow())?;
+    if !deposit_caps.allows_lp_supply(lp_supply) {
+        msg!(
+            "Pool token supply {} over the cap of {}",
+            lp_supply,
+            deposit_caps.max_lp_supply
+        );
+        return Err(SwapError::DepositCapExceeded.into());
+    }
+    if deposit_caps.max_lp_per_owner > 0 {
+        let dest = utils::unpack_token_account(&dest_info.data.borrow())?;
+        let balance = dest
+            .amount
+            .checked_add(mint_amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        if !deposit_caps.allows_owner_balance(&dest.owner, balance) {
+            msg!(
+                "Pool token balance {} over the cap of {}",
+                balance,
+                deposit_caps.max_lp_per_owner
+            );
+            return Err(SwapError::DepositCapExceeded.into());
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the CPI policy of the swap allows the program of the top-level instruction
+/// to invoke it. Swaps that allow any caller need neither the instructions sysvar nor the
+/// CPI allowlist, which are otherwise looked up among the accounts by key.
+pub fn check_cpi_allowed(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if token_swap.cpi_policy == CpiPolicy::Any {
+        return Ok(());
+    }
+    let instructions_info = find_account_by_key(accounts, &sysvar::instructions::id())
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let caller = sysvar::instructions::get_instruction_relative(0, instructions_info)?.program_id;
+    if caller == *program_id {
+        return Ok(());
+    }
+    if token_swap.cpi_policy == CpiPolicy::TopLevelOnly {
+        msg!("Invoked by {}", caller);
+        return Err(SwapError::CpiNotAllowed.into());
+    }
+    let (cpi_allowlist_key, _bump_seed) = find_cpi_allowlist_address(program_id, swap_key);
+    let cpi_allowlist_info = find_account_by_key(accounts, &cpi_allowlist_key)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if cpi_allowlist_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let cpi_allowlist = CpiAllowlist::unpack(&cpi_allowlist_info.data.borrow())?;
+    if !cpi_allowlist.is_allowed(&caller) {
+        msg!("Invoked by {}", caller);
+        return Err(SwapError::CpiNotAllowed.into());
+    }
+    Ok(())
+}
+
+/// Checks that the account is the statistics account of the swap.
+pub fn check_pool_stats_account(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    pool_stats_info: &AccountInfo,
+) -> ProgramResult {
+    let (pool_stats_key, _bump_seed) = find_pool_stats_address(program_id, swap_key);
+    check_keys_equal!(
+        pool_stats_key,
+        *pool_stats_info.key,
+        "Pool stats",
+        SwapError::InvalidProgramAddress
+    );
+    if pool_stats_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Checks that the account is the LP position of the owner in the swap.
+pub fn check_lp_position_account(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    owner_key: &Pubkey,
+    lp_position_info: &AccountInfo,
+) -> ProgramResult {
+    let (lp_position_key, _bump_seed) = find_lp_position_address(program_id, swap_key, owner_key);
+    check_keys_equal!(
+        lp_position_key,
+        *lp_position_info.key,
+        "LP position",
+        SwapError::InvalidProgramAddress
+    );
+    if lp_position_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Checks that the account is the migration record of the swap.
+pub fn check_pool_migration_account(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    pool_migration_info: &AccountInfo,
+) -> ProgramResult {
+    let (pool_migration_key, _bump_seed) = find_pool_migration_address(program_id, swap_key);
+    check_keys_equal!(
+        pool_migration_key,
+        *pool_migration_info.key,
+        "Pool migration",
+        SwapError::InvalidProgramAddress
+    );
+    if pool_migration_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Checks that the account is the rewards distributor of the swap.
+pub fn check_rewards_distributor_account(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    rewards_distributor_info: &AccountInfo,
+) -> ProgramResult {
+    let (rewards_distributor_key, _bump_seed) =
+        find_rewards_distributor_address(program_id, swap_key);
+    check_keys_equal!(
+        rewards_distributor_key,
+        *rewards_distributor_info.key,
+        "Rewards distributor",
+        SwapError::InvalidProgramAddress
+    );
+    if rewards_distributor_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Checks that the account is the staking config of the swap.
+pub fn check_staking_config_account(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    staking_config_info: &AccountInfo,
+) -> ProgramResult {
+    let (staking_config_key, _bump_seed) = find_staking_config_address(program_id, swap_key);
+    check_keys_equal!(
+        staking_config_key,
+        *staking_config_info.key,
+        "Staking config",
+        SwapError::InvalidProgramAddress
+    );
+    if staking_config_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Splits the trailing accounts of a deposit at the optional staking config,
+/// which comes after any other optional account.
+pub fn split_staking_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> (&'a [AccountInfo<'b>], &'a [AccountInfo<'b>]) {
+    let position = accounts.iter().position(|account| {
+        account.owner == program_id && account.data_len() == StakingConfig::LEN
+    });
+    accounts.split_at(position.unwrap_or(accounts.len()))
+}
+
+/// Finds the optional account of the given packed length among the trailing
+/// accounts of an instruction. Optional accounts can be passed in any order.
+pub fn find_optional_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    len: usize,
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account| account.data_len() == len)
+}
+
+/// Finds the optional statistics account among the trailing accounts, in the current
+/// or the legacy layout
+pub fn find_pool_stats_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+) -> Option<&'a AccountInfo<'b>> {
+    accounts
+        .iter()
+        .find(|account| PoolStats::is_valid_len(account.data_len()))
+}
+
+/// Finds the optional host fee account among the trailing accounts of a swap:
+/// the first token account of the given mint owned by the token program.
+pub fn find_host_fee_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    token_program_id: &Pubkey,
+    mint: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account| {
+        account.owner == token_program_id
+            && account.data_len() == spl_token::state::Account::LEN
+            && matches!(
+                utils::unpack_token_account(&account.data.borrow()),
+                Ok(token_account) if token_account.mint == *mint
+            )
+    })
+}
+
+/// Finds the pool token account of the admin among the trailing accounts of an
+/// instruction: an account of the pool mint owned by the owner of the admin fee
+/// account of the instruction, see [SwapInfo::admin_fees_as_lp].
+pub fn find_admin_pool_token_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    token_program_id: &Pubkey,
+    pool_mint: &Pubkey,
+    admin_fee_owner: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account| {
+        account.owner == token_program_id
+            && account.data_len() == spl_token::state::Account::LEN
+            && matches!(
+                utils::unpack_token_account(&account.data.borrow()),
+                Ok(token_account) if token_account.mint == *pool_mint
+                    && token_account.owner == *admin_fee_owner
+            )
+    })
+}
+
+/// Resolves the owner of the pool tokens a withdrawal burns from `source_info`. That is
+/// the user authority, unless it is the delegate of the SOURCE account, in which case
+/// it is the owner of the SOURCE account and the withdrawal must credit token accounts
+/// of that owner. The token program checks the delegated amount when burning.
+pub fn check_withdraw_authority(
+    user_authority_key: &Pubkey,
+    source_info: &AccountInfo,
+    destination_infos: &[&AccountInfo],
+) -> Result<Pubkey, ProgramError> {
+    let source = utils::unpack_token_account(&source_info.data.borrow())?;
+    if source.owner == *user_authority_key || source.delegate != COption::Some(*user_authority_key)
+    {
+        return Ok(*user_authority_key);
+    }
+    for destination_info in destination_infos {
+        let destination = utils::unpack_token_account(&destination_info.data.borrow())?;
+        if destination.owner != source.owner {
+            msg!("Withdrawals by a delegate credit token accounts of the owner:");
+            source.owner.log();
+            return Err(SwapError::InvalidWithdrawDestination.into());
+        }
+    }
+    Ok(source.owner)
+}
+
+/// Finds the optional account with the given key among the trailing accounts of an instruction.
+pub fn find_account_by_key<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account| account.key == key)
+}
+
+/// Resolves a pool account by the key the swap stores for it rather than by its position:
+/// the positional account if it has the key, otherwise any account of the instruction
+/// with that key. If none has it, the positional account is left to the key checks.
+pub fn resolve_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    positional: &'a AccountInfo<'b>,
+    key: &Pubkey,
+) -> &'a AccountInfo<'b> {
+    if positional.key == key {
+        return positional;
+    }
+    find_account_by_key(accounts, key).unwrap_or(positional)
+}
+
+/// Reads the price of the registered oracle among the trailing accounts of an instruction.
+fn load_oracle_price(
+    accounts: &[AccountInfo],
+    price_oracle_key: &Pubkey,
+    slot: u64,
+) -> Result<OraclePrice, ProgramError> {
+    let price_oracle_info = find_account_by_key(accounts, price_oracle_key)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let price = oracle::read_pyth_price(&price_oracle_info.data.borrow())
+        .ok_or(SwapError::InvalidOracle)?;
+    if slot.saturating_sub(price.pub_slot) > MAX_PRICE_AGE_SLOTS {
+        return Err(SwapError::InvalidOracle.into());
+    }
+    Ok(price)
+}
+
+/// Reads the peg rate of a swap from its rate account, failing if the rate is stale.
+/// The rate of a pool pegged 1:1 is 0, see [SwapInfo::peg_rate].
+pub fn read_peg_rate(
+    rate_source: PegRateSource,
+    rate_account_info: &AccountInfo,
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    let data = rate_account_info.data.borrow();
+    let peg_rate = match rate_source {
+        PegRateSource::None => return Ok(0),
+        PegRateSource::PythPrice => {
+            let price = oracle::read_pyth_price(&data).ok_or(SwapError::InvalidOracle)?;
+            if clock.slot.saturating_sub(price.pub_slot) > MAX_PRICE_AGE_SLOTS {
+                return Err(SwapError::InvalidOracle.into());
+            }
+            price.peg_rate()
+        }
+        PegRateSource::StakePool => {
+            let balance = oracle::read_stake_pool_balance(&data).ok_or(SwapError::InvalidOracle)?;
+            if balance.last_update_epoch != clock.epoch {
+                return Err(SwapError::InvalidOracle.into());
+            }
+            balance.peg_rate()
+        }
+    };
+    peg_rate
+        .filter(|&peg_rate| peg_rate > 0)
+        .ok_or_else(|| SwapError::InvalidOracle.into())
+}
+
+/// Checks that the price of a swap before fees does not deviate from the oracle prices
+/// by more than the max deviation of the swap, if it registered price oracles.
+pub fn check_price_deviation(
+    token_swap: &SwapInfo,
+    swap_source_key: &Pubkey,
+    amount_in: u64,
+    result: &SwapResult,
+    price_oracle_infos: &[AccountInfo],
+    slot: u64,
+) -> ProgramResult {
+    if token_swap.max_price_deviation_bps == 0 {
+        return Ok(());
+    }
+    let price_a = load_oracle_price(price_oracle_infos, &token_swap.price_oracle_a, slot)?;
+    let price_b = load_oracle_price(price_oracle_infos, &token_swap.price_oracle_b, slot)?;
+    let (price_in, price_out) = if *swap_source_key == token_swap.token_a.reserves {
+        (price_a, price_b)
+    } else {
+        (price_b, price_a)
+    };
+    let amount_out = result
+        .amount_swapped
+        .checked_add(result.fee)
+        .ok_or(SwapError::CalculationFailure)?;
+    let deviation_bps = oracle::price_deviation_bps(amount_in, amount_out, &price_in, &price_out)
+        .ok_or(SwapError::InvalidOracle)?;
+    if deviation_bps > token_swap.max_price_deviation_bps {
+        msg!(
+            "Price deviation of {} bps exceeds {} bps",
+            deviation_bps,
+            token_swap.max_price_deviation_bps
+        );
+        return Err(SwapError::PriceDeviation.into());
+    }
+    Ok(())
+}
+
+/// Max amount, admin fee included, a swap may take out of a reserve holding
+/// `reserve_amount`, or `None` if the swap does not cap its swaps.
+pub fn max_swap_amount_out(
+    token_swap: &SwapInfo,
+    reserve_amount: u64,
+) -> Result<Option<u64>, ProgramError> {
+    if token_swap.max_swap_bps_of_reserves == 0 {
+        return Ok(None);
+    }
+    let max_amount_out = math::mul_div_floor(
+        reserve_amount,
+        token_swap.max_swap_bps_of_reserves,
+        BPS_DENOMINATOR,
+    )
+    .ok_or(SwapError::CalculationFailure)?;
+    Ok(Some(max_amount_out))
+}
+
+/// Checks that a swap takes at most the max share of the output reserve of the swap,
+/// if it set one. The admin fee leaves the reserve along with the amount swapped.
+pub fn check_max_swap_size(
+    token_swap: &SwapInfo,
+    reserve_amount: u64,
+    result: &SwapResult,
+) -> ProgramResult {
+    let max_amount_out = match max_swap_amount_out(token_swap, reserve_amount)? {
+        Some(max_amount_out) => max_amount_out,
+        None => return Ok(()),
+    };
+    let amount_out = result
+        .amount_swapped
+        .checked_add(result.admin_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+    if amount_out > max_amount_out {
+        msg!(
+            "Swap of {} exceeds the max of {} out of the reserve",
+            amount_out,
+            max_amount_out
+        );
+        return Err(SwapError::ExceededMaxSwapSize.into());
+    }
+    Ok(())
+}
+
 pub fn check_deposit_token_accounts(
     token: &SwapTokenInfo,
     source_key: &Pubkey,
@@ -64,8 +663,12 @@ pub fn check_can_withdraw_token(
 ) -> Result<(u64, u64, u64), ProgramError> {
     let (amount, fee, admin_fee) = rate.ok_or(SwapError::CalculationFailure)?;
     if amount < minimum_token_amount {
-        log_slippage_error(minimum_token_amount, amount);
-        return Err(SwapError::ExceededSlippage.into());
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: minimum_token_amount,
+                actual: amount,
+            })
+            .into());
     }
 
     Ok((amount, fee, admin_fee))
@@ -93,7 +696,11 @@ pub fn check_swap_authority(
     program_id: &Pubkey,
     swap_authority_key: &Pubkey,
 ) -> ProgramResult {
-    let swap_authority = utils::authority_id(program_id, swap_info_key, token_swap.nonce)?;
+    let swap_authority = utils::authority_id(
+        program_id,
+        token_swap.authority_seed(swap_info_key),
+        token_swap.nonce,
+    )?;
     check_keys_equal!(
         *swap_authority_key,
         swap_authority,
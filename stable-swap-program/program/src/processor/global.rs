@@ -0,0 +1,383 @@
+//! Module for processing instructions that apply to every pool of the program.
+
+use crate::{
+    error::SwapError,
+    processor::utils,
+    state::{find_global_state_address, GlobalState, GLOBAL_STATE_SEED},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+
+use super::checks::{check_has_admin_signer, check_has_upgrade_authority_signer};
+
+/// Processes a [SetEmergencyAuthority](enum.Instruction.html).
+pub fn process_set_emergency_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let global_state_info = next_account_info(account_info_iter)?;
+    let upgrade_authority_info = next_account_info(account_info_iter)?;
+    let program_data_info = next_account_info(account_info_iter)?;
+    let emergency_authority_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    check_has_upgrade_authority_signer(program_id, program_data_info, upgrade_authority_info)?;
+
+    let (global_state_key, bump_seed) = find_global_state_address(program_id);
+    check_keys_equal!(
+        global_state_key,
+        *global_state_info.key,
+        "Global state",
+        SwapError::InvalidProgramAddress
+    );
+    utils::create_program_address_account(
+        program_id,
+        &[GLOBAL_STATE_SEED, &[bump_seed]],
+        GlobalState::LEN,
+        global_state_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut global_state = GlobalState::unpack_unchecked(&global_state_info.data.borrow())?;
+    global_state.is_initialized = true;
+    global_state.emergency_authority = *emergency_authority_info.key;
+    GlobalState::pack(global_state, &mut global_state_info.data.borrow_mut())?;
+    msg!("Global: Emergency authority set");
+    Ok(())
+}
+
+/// Processes a [GlobalPause](enum.Instruction.html) or a [GlobalUnpause](enum.Instruction.html).
+pub fn process_set_global_pause(
+    program_id: &Pubkey,
+    is_paused: bool,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let global_state_info = next_account_info(account_info_iter)?;
+    let emergency_authority_info = next_account_info(account_info_iter)?;
+
+    let (global_state_key, _bump_seed) = find_global_state_address(program_id);
+    check_keys_equal!(
+        global_state_key,
+        *global_state_info.key,
+        "Global state",
+        SwapError::InvalidProgramAddress
+    );
+    if global_state_info.owner != program_id {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    let mut global_state = GlobalState::unpack(&global_state_info.data.borrow())?;
+    check_has_admin_signer(&global_state.emergency_authority, emergency_authority_info)?;
+
+    global_state.is_paused = is_paused;
+    GlobalState::pack(global_state, &mut global_state_info.data.borrow_mut())?;
+    if is_paused {
+        msg!("Global: All pools paused");
+    } else {
+        msg!("Global: All pools unpaused");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::{MIN_AMP, ZERO_TS},
+        instruction::swap,
+        processor::test_utils::*,
+    };
+    use solana_program::{bpf_loader_upgradeable, instruction::AccountMeta};
+    use solana_sdk::account::Account;
+
+    const DEFAULT_TOKEN_A_AMOUNT: u64 = 1_000_000_000;
+    const DEFAULT_TOKEN_B_AMOUNT: u64 = 1_000_000_000;
+
+    /// Program data account of an upgradeable program
+    fn program_data_account(upgrade_authority: Option<&Pubkey>) -> Account {
+        let mut account = Account::new(1, 45, &bpf_loader_upgradeable::id());
+        account.data[0] = 3;
+        if let Some(upgrade_authority) = upgrade_authority {
+            account.data[12] = 1;
+            account.data[13..45].copy_from_slice(upgrade_authority.as_ref());
+        }
+        account
+    }
+
+    #[test]
+    fn test_global_pause() {
+        let user_key = pubkey_rand();
+        let upgrade_authority_key = pubkey_rand();
+        let emergency_authority_key = pubkey_rand();
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            DEFAULT_TOKEN_A_AMOUNT,
+            DEFAULT_TOKEN_B_AMOUNT,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        accounts.global_state_account = Account::new(1, GlobalState::LEN, &SWAP_PROGRAM_ID);
+
+        // emergency authority not set
+        assert_eq!(
+            Err(ProgramError::UninitializedAccount),
+            accounts.global_pause(&Pubkey::default())
+        );
+
+        // not the upgrade authority
+        assert_eq!(
+            Err(SwapError::Unauthorized.into()),
+            accounts.set_emergency_authority(
+                &pubkey_rand(),
+                &mut program_data_account(Some(&upgrade_authority_key)),
+                &emergency_authority_key,
+            )
+        );
+        // immutable program
+        assert_eq!(
+            Err(SwapError::Unauthorized.into()),
+            accounts.set_emergency_authority(
+                &upgrade_authority_key,
+                &mut program_data_account(None),
+                &emergency_authority_key,
+            )
+        );
+        // program data not owned by the upgradeable loader
+        let mut fake_program_data = program_data_account(Some(&upgrade_authority_key));
+        fake_program_data.owner = SWAP_PROGRAM_ID;
+        assert_eq!(
+            Err(ProgramError::IncorrectProgramId),
+            accounts.set_emergency_authority(
+                &upgrade_authority_key,
+                &mut fake_program_data,
+                &emergency_authority_key,
+            )
+        );
+
+        accounts
+            .set_emergency_authority(
+                &upgrade_authority_key,
+                &mut program_data_account(Some(&upgrade_authority_key)),
+                &emergency_authority_key,
+            )
+            .unwrap();
+        let global_state = GlobalState::unpack(&accounts.global_state_account.data).unwrap();
+        assert_eq!(global_state.emergency_authority, emergency_authority_key);
+        assert!(!global_state.is_paused);
+
+        // unauthorized account
+        assert_eq!(
+            Err(SwapError::Unauthorized.into()),
+            accounts.global_pause(&upgrade_authority_key)
+        );
+        accounts.global_pause(&emergency_authority_key).unwrap();
+        assert!(
+            GlobalState::unpack(&accounts.global_state_account.data)
+                .unwrap()
+                .is_paused
+        );
+
+        let swapper_key = pubkey_rand();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, 1_000, 1_000, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
+        // swaps and deposits are paused, in the spl-token-swap layout as well
+        assert_eq!(
+            Err(SwapError::GloballyPaused.into()),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::GloballyPaused.into()),
+            accounts.deposit(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                1_000,
+                1_000,
+                0,
+            )
+        );
+        accounts.set_spl_token_swap_compat(true).unwrap();
+        assert_eq!(
+            Err(SwapError::GloballyPaused.into()),
+            accounts.spl_token_swap_swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+        );
+
+        assert_eq!(
+            Err(SwapError::GloballyPaused.into()),
+            accounts.donate_to_reserves(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                1_000,
+            )
+        );
+
+        // the global state cannot be left out or replaced to get around the pause
+        let mut swap_instruction = swap(
+            &SWAP_PROGRAM_ID,
+            &spl_token::id(),
+            &accounts.swap_key,
+            &accounts.authority_key,
+            &swapper_key,
+            &token_a_key,
+            &swap_token_a_key,
+            &swap_token_b_key,
+            &token_b_key,
+            &accounts.admin_fee_b_key,
+            1_000,
+            0,
+        )
+        .unwrap();
+        let global_state_meta = swap_instruction.accounts.pop().unwrap();
+        let mut swap_token_a_account = accounts.token_a_account.clone();
+        let mut swap_token_b_account = accounts.token_b_account.clone();
+        let mut admin_fee_b_account = accounts.admin_fee_b_account.clone();
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            do_process_instruction(
+                swap_instruction.clone(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut token_a_account,
+                    &mut swap_token_a_account,
+                    &mut swap_token_b_account,
+                    &mut token_b_account,
+                    &mut admin_fee_b_account,
+                    &mut Account::default(),
+                    &mut clock_account(ZERO_TS),
+                ],
+            )
+        );
+        let mut fake_global_state_instruction = swap_instruction.clone();
+        fake_global_state_instruction
+            .accounts
+            .push(AccountMeta::new_readonly(pubkey_rand(), false));
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            do_process_instruction(
+                fake_global_state_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut token_a_account,
+                    &mut swap_token_a_account,
+                    &mut swap_token_b_account,
+                    &mut token_b_account,
+                    &mut admin_fee_b_account,
+                    &mut Account::default(),
+                    &mut clock_account(ZERO_TS),
+                    &mut accounts.global_state_account.clone(),
+                ],
+            )
+        );
+        swap_instruction.accounts.push(global_state_meta);
+        assert_eq!(
+            Err(ProgramError::IncorrectProgramId),
+            do_process_instruction(
+                swap_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut token_a_account,
+                    &mut swap_token_a_account,
+                    &mut swap_token_b_account,
+                    &mut token_b_account,
+                    &mut admin_fee_b_account,
+                    &mut Account::default(),
+                    &mut clock_account(ZERO_TS),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // withdrawals still work
+        let pool_token_key = accounts.pool_token_key;
+        let mut pool_token_account = accounts.pool_token_account.clone();
+        accounts
+            .withdraw(
+                &user_key,
+                &pool_token_key,
+                &mut pool_token_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(
+            Err(SwapError::Unauthorized.into()),
+            accounts.global_unpause(&upgrade_authority_key)
+        );
+        accounts.global_unpause(&emergency_authority_key).unwrap();
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+            .unwrap();
+    }
+}
@@ -1,27 +1,26 @@
 //! Logging related helpers.
 
+use crate::error::ErrorContext;
+use crate::events::{LoggedEvent, EVENT_DATA_TAG};
+use borsh::BorshSerialize;
 use solana_program::msg;
 use solana_program::pubkey::Pubkey;
 
-/// Event enum
-#[derive(Debug)]
-pub enum Event {
-    /// Burn event
-    Burn,
-    /// Deposit event
-    Deposit,
-    /// Swap event A -> B
-    SwapAToB,
-    /// Swap event B -> A
-    SwapBToA,
-    /// Withdraw event (A)
-    WithdrawA,
-    /// Withdraw event (B)
-    WithdrawB,
+pub use crate::events::Event;
+
+/// Whether the program was built with the `quiet-logs` feature, leaving out the detailed
+/// logs of every pool
+pub const QUIET_LOGS: bool = cfg!(feature = "quiet-logs");
+
+/// Whether to leave out the detailed logs of a swap, given its
+/// [quiet_logs](crate::state::SwapInfo::quiet_logs), see the `log_detail` macro
+pub fn is_quiet(quiet_logs: bool) -> bool {
+    QUIET_LOGS || quiet_logs
 }
 
-/// Log event
+/// Log event. Quiet swaps only log the borsh encoded event.
 pub fn log_event(
+    quiet_logs: bool,
     event: Event,
     timestamp: i64,
     token_a_amount: u64,
@@ -29,22 +28,54 @@ pub fn log_event(
     pool_token_amount: u64,
     fee: u64,
 ) {
-    msg!(match event {
-        Event::Burn => "Event: Burn",
-        Event::Deposit => "Event: Deposit",
-        Event::SwapAToB => "Event: SwapAToB",
-        Event::SwapBToA => "Event: SwapBToA",
-        Event::WithdrawA => "Event: WithdrawA",
-        Event::WithdrawB => "Event: WithdrawB",
-    });
-    solana_program::log::sol_log_64(
-        event as u64,
+    if !is_quiet(quiet_logs) {
+        msg!("Event: {}", event.name());
+        solana_program::log::sol_log_64(
+            event as u64,
+            token_a_amount,
+            token_b_amount,
+            pool_token_amount,
+            fee,
+        );
+        msg!("Timestamp: {}", timestamp);
+    }
+    // The same event for indexers, see crate::events
+    let logged_event = LoggedEvent {
+        event,
         token_a_amount,
         token_b_amount,
         pool_token_amount,
         fee,
+        timestamp: Some(timestamp),
+    };
+    if let Ok(data) = logged_event.try_to_vec() {
+        solana_program::log::sol_log_data(&[&EVENT_DATA_TAG, &data]);
+    }
+}
+
+/// Log the admin fees taken by an instruction, a
+/// [TradeAdminFees](Event::TradeAdminFees) or a
+/// [WithdrawAdminFees](Event::WithdrawAdminFees) event. Nothing is logged when they
+/// are both zero.
+pub fn log_admin_fees(
+    quiet_logs: bool,
+    event: Event,
+    timestamp: i64,
+    token_a_admin_fee: u64,
+    token_b_admin_fee: u64,
+) {
+    if token_a_admin_fee == 0 && token_b_admin_fee == 0 {
+        return;
+    }
+    log_event(
+        quiet_logs,
+        event,
+        timestamp,
+        token_a_admin_fee,
+        token_b_admin_fee,
+        0,
+        0,
     );
-    msg!("Timestamp: {}", timestamp);
 }
 
 pub fn log_keys_mismatch(msg: &str, left: Pubkey, right: Pubkey) {
@@ -53,6 +84,7 @@ pub fn log_keys_mismatch(msg: &str, left: Pubkey, right: Pubkey) {
     left.log();
     msg!("Right:");
     right.log();
+    ErrorContext::keys(&left, &right).log();
 }
 
 pub fn log_keys_mismatch_optional(msg: &str, left: Option<Pubkey>, right: Option<Pubkey>) {
@@ -69,9 +101,5 @@ pub fn log_keys_mismatch_optional(msg: &str, left: Option<Pubkey>, right: Option
     } else {
         msg!("right: missing");
     }
-}
-
-/// Log slippage error
-pub fn log_slippage_error(minimum_amount: u64, computed_amount: u64) {
-    msg!(0, 0, 0, minimum_amount, computed_amount);
+    ErrorContext::keys(&left.unwrap_or_default(), &right.unwrap_or_default()).log();
 }
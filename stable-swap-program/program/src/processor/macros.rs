@@ -1,3 +1,13 @@
+/// Logs a message, unless the swap leaves out its detailed logs, see
+/// [is_quiet](crate::processor::logging::is_quiet). Errors are logged with `msg!` instead.
+macro_rules! log_detail {
+    ($quiet_logs:expr, $($arg:tt)+) => {
+        if !$crate::processor::logging::is_quiet($quiet_logs) {
+            solana_program::msg!($($arg)+);
+        }
+    };
+}
+
 /// Checks if two pubkeys are equal, and if not, throws an error.
 macro_rules! check_keys_equal {
     ($left:expr, $right:expr, $msg:expr, $err:expr) => {
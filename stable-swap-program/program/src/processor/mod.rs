@@ -4,10 +4,14 @@
 mod macros;
 
 mod admin;
+#[cfg(feature = "paranoid")]
+mod audit;
 mod checks;
+mod global;
 mod logging;
 mod swap;
 mod token;
+mod transition;
 mod utils;
 
 #[cfg(test)]
@@ -25,12 +29,19 @@ impl Processor {
     /// Processes an [Instruction](enum.Instruction.html).
     pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
         let instruction = AdminInstruction::unpack(input)?;
+        #[cfg(feature = "paranoid")]
+        let before = audit::PoolAudit::load(program_id, accounts);
+        #[cfg(feature = "paranoid")]
+        let migrates = matches!(instruction, Some(AdminInstruction::MigratePool));
         match instruction {
             None => swap::process_swap_instruction(program_id, accounts, input),
             Some(admin_instruction) => {
-                admin::process_admin_instruction(&admin_instruction, accounts)
+                admin::process_admin_instruction(program_id, &admin_instruction, accounts)
             }
-        }
+        }?;
+        #[cfg(feature = "paranoid")]
+        audit::check_instruction(program_id, accounts, before, migrates)?;
+        Ok(())
     }
 }
 
@@ -1,31 +1,57 @@
 //! Module for processing non-admin pool instructions.
 
 use crate::{
-    curve::{StableSwap, MAX_AMP, MIN_AMP, ZERO_TS},
-    error::SwapError,
-    fees::Fees,
+    associated_token,
+    curve::{
+        CurveType, StableSwap, SwapResult, AMP_PRECISION, MAX_AMP, MIN_AMP, MIN_RAMP_DURATION,
+        ZERO_TS,
+    },
+    error::{ErrorContext, SwapError},
+    fees::{Fees, BPS_DENOMINATOR},
     instruction::{
-        DepositData, InitializeData, SwapData, SwapInstruction, WithdrawData, WithdrawOneData,
+        CreateOrderData, DepositData, DonateToReservesData, InitializeData, InitializeWithRampData,
+        RedeemMigratedLpData, SwapData, SwapInstruction, SwapSplitData, WithdrawData,
+        WithdrawOneData, WithdrawOneExactOutData, ZapInData, ZapOutData,
     },
+    math,
     pool_converter::PoolTokenConverter,
+    presets::PoolPreset,
     processor::utils,
-    state::{SwapInfo, SwapTokenInfo},
+    return_data::{
+        AmpFactorReturnData, DepositReturnData, PartialSwapReturnData, SwapReturnData,
+        WithdrawReturnData,
+    },
+    state::{
+        find_last_trade_address, find_locked_liquidity_address, find_lp_position_address,
+        find_order_address, find_peg_config_address, CpiPolicy, DepositAllowlist, DepositCaps,
+        FeeExemptions, LastTrade, LpPosition, Order, PegConfig, PegRateSource, PoolMigration,
+        PoolStats, RewardsDistributor, StakingConfig, SwapInfo, SwapInfoRef, SwapInfoRefMut,
+        SwapTokenInfo, LP_POSITION_SEED, MAX_SLOT_TRADERS, ORDER_SEED, SHARED_AUTHORITY_SEED,
+        SWAP_INFO_LEGACY_LEN,
+    },
 };
 
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
+    program::invoke,
     program_error::ProgramError,
     program_option::COption,
     program_pack::Pack,
     pubkey::Pubkey,
+    system_program,
     sysvar::{clock::Clock, Sysvar},
 };
+use spl_token::{instruction::AuthorityType, native_mint};
+use std::convert::TryInto;
 
 use super::checks::*;
+use super::global;
 use super::logging::*;
 use super::token;
+use super::transition::{self, PoolBalances, PoolToken, TransferIntent};
 
 pub fn process_swap_instruction(
     program_id: &Pubkey,
@@ -33,28 +59,79 @@ pub fn process_swap_instruction(
     input: &[u8],
 ) -> ProgramResult {
     let instruction = SwapInstruction::unpack(input)?;
+    // Routes and splits pass their first swap after five accounts
+    let swap_info = match instruction {
+        SwapInstruction::SwapRoute(_) | SwapInstruction::SwapSplit(_) => accounts.get(5),
+        _ => accounts.first(),
+    };
+    let quiet_logs = swap_info
+        .map(|swap_info| SwapInfo::peek_quiet_logs(&swap_info.data.borrow()))
+        .unwrap_or(false);
     match instruction {
         SwapInstruction::Initialize(InitializeData {
             nonce,
             amp_factor,
             fees,
+            min_liquidity,
+            curve_type,
         }) => {
-            msg!("Instruction: Init");
-            process_initialize(program_id, nonce, amp_factor, fees, accounts)
+            log_detail!(quiet_logs, "Instruction: Init");
+            process_initialize(
+                program_id,
+                InitializeWithRampData {
+                    nonce,
+                    initial_amp_factor: amp_factor,
+                    target_amp_factor: amp_factor,
+                    ramp_duration: 0,
+                    fees,
+                    min_liquidity,
+                },
+                curve_type,
+                accounts,
+            )
+        }
+        SwapInstruction::InitializeWithRamp(data) => {
+            log_detail!(quiet_logs, "Instruction: InitializeWithRamp");
+            process_initialize(program_id, data, CurveType::StableSwap, accounts)
         }
         SwapInstruction::Swap(SwapData {
             amount_in,
             minimum_amount_out,
         }) => {
-            msg!("Instruction: Swap");
-            process_swap(program_id, amount_in, minimum_amount_out, accounts)
+            log_detail!(quiet_logs, "Instruction: Swap");
+            if is_spl_token_swap_layout(accounts) {
+                process_spl_token_swap(program_id, amount_in, minimum_amount_out, accounts)
+            } else {
+                process_swap(program_id, amount_in, minimum_amount_out, false, accounts)
+            }
+        }
+        SwapInstruction::SwapRoute(SwapData {
+            amount_in,
+            minimum_amount_out,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: SwapRoute");
+            process_swap_route(program_id, amount_in, minimum_amount_out, accounts)
+        }
+        SwapInstruction::SwapSplit(SwapSplitData {
+            amount_in,
+            minimum_amount_out,
+            pool_count,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: SwapSplit");
+            process_swap_split(
+                program_id,
+                amount_in,
+                minimum_amount_out,
+                pool_count,
+                accounts,
+            )
         }
         SwapInstruction::Deposit(DepositData {
             token_a_amount,
             token_b_amount,
             min_mint_amount,
         }) => {
-            msg!("Instruction: Deposit");
+            log_detail!(quiet_logs, "Instruction: Deposit");
             process_deposit(
                 program_id,
                 token_a_amount,
@@ -68,7 +145,7 @@ pub fn process_swap_instruction(
             minimum_token_a_amount,
             minimum_token_b_amount,
         }) => {
-            msg!("Instruction: Withdraw");
+            log_detail!(quiet_logs, "Instruction: Withdraw");
             process_withdraw(
                 program_id,
                 pool_token_amount,
@@ -81,25 +158,178 @@ pub fn process_swap_instruction(
             pool_token_amount,
             minimum_token_amount,
         }) => {
-            msg!("Instruction: Withdraw One");
+            log_detail!(quiet_logs, "Instruction: Withdraw One");
+            process_withdraw_one(
+                program_id,
+                WithdrawOneAmounts::ExactIn {
+                    pool_token_amount,
+                    minimum_token_amount,
+                },
+                accounts,
+            )
+        }
+        SwapInstruction::WithdrawOneExactOut(WithdrawOneExactOutData {
+            token_amount,
+            maximum_burn_amount,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: Withdraw One Exact Out");
             process_withdraw_one(
+                program_id,
+                WithdrawOneAmounts::ExactOut {
+                    token_amount,
+                    maximum_burn_amount,
+                },
+                accounts,
+            )
+        }
+        SwapInstruction::SetEmergencyAuthority => {
+            log_detail!(quiet_logs, "Instruction: SetEmergencyAuthority");
+            global::process_set_emergency_authority(program_id, accounts)
+        }
+        SwapInstruction::GlobalPause => {
+            log_detail!(quiet_logs, "Instruction: GlobalPause");
+            global::process_set_global_pause(program_id, true, accounts)
+        }
+        SwapInstruction::GlobalUnpause => {
+            log_detail!(quiet_logs, "Instruction: GlobalUnpause");
+            global::process_set_global_pause(program_id, false, accounts)
+        }
+        SwapInstruction::DonateToReserves(DonateToReservesData {
+            token_a_amount,
+            token_b_amount,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: DonateToReserves");
+            process_donate_to_reserves(program_id, token_a_amount, token_b_amount, accounts)
+        }
+        SwapInstruction::SyncAmp => {
+            log_detail!(quiet_logs, "Instruction: SyncAmp");
+            process_sync_amp(accounts)
+        }
+        SwapInstruction::SyncLpPosition => {
+            log_detail!(quiet_logs, "Instruction: SyncLpPosition");
+            process_sync_lp_position(program_id, accounts)
+        }
+        SwapInstruction::ZapIn(ZapInData {
+            amount_in,
+            input_is_a,
+            min_mint_amount,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: ZapIn");
+            process_zap_in(program_id, amount_in, input_is_a, min_mint_amount, accounts)
+        }
+        SwapInstruction::ZapOut(ZapOutData {
+            pool_token_amount,
+            want_token_a,
+            minimum_amount_out,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: ZapOut");
+            process_zap_out(
+                program_id,
+                pool_token_amount,
+                want_token_a,
+                minimum_amount_out,
+                accounts,
+            )
+        }
+        SwapInstruction::RedeemMigratedLp(RedeemMigratedLpData { pool_token_amount }) => {
+            log_detail!(quiet_logs, "Instruction: RedeemMigratedLp");
+            process_redeem_migrated_lp(program_id, pool_token_amount, accounts)
+        }
+        SwapInstruction::RecoverStrandedTokens => {
+            log_detail!(quiet_logs, "Instruction: RecoverStrandedTokens");
+            process_recover_stranded_tokens(program_id, accounts)
+        }
+        SwapInstruction::SwapBatch(fills) => {
+            log_detail!(quiet_logs, "Instruction: SwapBatch");
+            process_swap_batch(program_id, &fills, accounts)
+        }
+        SwapInstruction::HarvestFees => {
+            log_detail!(quiet_logs, "Instruction: HarvestFees");
+            process_harvest_fees(program_id, accounts)
+        }
+        SwapInstruction::EmergencyWithdraw(WithdrawData {
+            pool_token_amount,
+            minimum_token_a_amount,
+            minimum_token_b_amount,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: EmergencyWithdraw");
+            process_emergency_withdraw(
                 program_id,
                 pool_token_amount,
-                minimum_token_amount,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+                accounts,
+            )
+        }
+        SwapInstruction::SyncReserves => {
+            log_detail!(quiet_logs, "Instruction: SyncReserves");
+            process_sync_reserves(accounts)
+        }
+        SwapInstruction::RefreshPegRate => {
+            log_detail!(quiet_logs, "Instruction: RefreshPegRate");
+            process_refresh_peg_rate(program_id, accounts)
+        }
+        SwapInstruction::SwapAllowPartial(SwapData {
+            amount_in,
+            minimum_amount_out,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: SwapAllowPartial");
+            process_swap(program_id, amount_in, minimum_amount_out, true, accounts)
+        }
+        SwapInstruction::CreateOrder(CreateOrderData {
+            order_id,
+            amount_in,
+            minimum_amount_out,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: CreateOrder");
+            process_create_order(
+                program_id,
+                order_id,
+                amount_in,
+                minimum_amount_out,
                 accounts,
             )
         }
+        SwapInstruction::FillOrder => {
+            log_detail!(quiet_logs, "Instruction: FillOrder");
+            process_fill_order(program_id, accounts)
+        }
+        SwapInstruction::CancelOrder => {
+            log_detail!(quiet_logs, "Instruction: CancelOrder");
+            process_cancel_order(program_id, accounts)
+        }
+        SwapInstruction::GetAmpFactor => {
+            log_detail!(quiet_logs, "Instruction: GetAmpFactor");
+            process_get_amp_factor(program_id, accounts)
+        }
+        SwapInstruction::SimulateSwap(SwapData {
+            amount_in,
+            minimum_amount_out,
+        }) => {
+            log_detail!(quiet_logs, "Instruction: SimulateSwap");
+            process_simulate_swap(program_id, amount_in, minimum_amount_out, accounts)
+        }
     }
 }
 
-/// Processes an [Initialize](enum.Instruction.html).
+/// Processes an [Initialize](enum.Instruction.html) or an
+/// [InitializeWithRamp](enum.Instruction.html). Initialize has no ramp, its initial and
+/// target amp factors being the same. Pools ramping from initialization are
+/// StableSwap pools.
 fn process_initialize(
     program_id: &Pubkey,
-    nonce: u8,
-    amp_factor: u64,
-    fees: Fees,
+    data: InitializeWithRampData,
+    curve_type: CurveType,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
+    let InitializeWithRampData {
+        nonce,
+        initial_amp_factor,
+        target_amp_factor,
+        ramp_duration,
+        fees,
+        min_liquidity,
+    } = data;
     let account_info_iter = &mut accounts.iter();
     let swap_info = next_account_info(account_info_iter)?;
     let authority_info = next_account_info(account_info_iter)?;
@@ -113,24 +343,99 @@ fn process_initialize(
     let pool_mint_info = next_account_info(account_info_iter)?;
     let destination_info = next_account_info(account_info_iter)?; // Destination account to mint LP tokens to
     let token_program_info = next_account_info(account_info_iter)?;
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
 
-    if !(MIN_AMP..=MAX_AMP).contains(&amp_factor) {
-        msg!("Invalid amp factor: {}", amp_factor);
+    for amp_factor in [initial_amp_factor, target_amp_factor] {
+        if !(MIN_AMP..=MAX_AMP).contains(&amp_factor) {
+            msg!("Invalid amp factor: {}", amp_factor);
+            return Err(SwapError::InvalidInput.into());
+        }
+    }
+    // Initialized with whole coefficients, ramps may move them by hundredths
+    let initial_amp_factor = initial_amp_factor * AMP_PRECISION;
+    let target_amp_factor = target_amp_factor * AMP_PRECISION;
+    let (start_ramp_ts, stop_ramp_ts) = if initial_amp_factor == target_amp_factor {
+        (ZERO_TS, ZERO_TS)
+    } else {
+        // The ramp moves A no faster than a RampA
+        if ramp_duration < MIN_RAMP_DURATION {
+            return Err(SwapError::InsufficientRampTime.into());
+        }
+        (
+            clock.unix_timestamp,
+            clock
+                .unix_timestamp
+                .checked_add(ramp_duration)
+                .ok_or(SwapError::CalculationFailure)?,
+        )
+    };
+    fees.validate()?;
+    if !fees.validate_trade_fee_tiers() {
+        msg!("Invalid trade fee tiers");
+        return Err(SwapError::InvalidInput.into());
+    }
+    if !fees.validate_dynamic_fee() {
+        msg!("Invalid dynamic fee");
         return Err(SwapError::InvalidInput.into());
     }
+    if !fees.validate_rebalancing_rebate() {
+        msg!("Invalid rebalancing rebate");
+        return Err(SwapError::InvalidInput.into());
+    }
+    if PoolPreset::find(target_amp_factor / AMP_PRECISION, &fees).is_none() {
+        msg!("Warning: amp factor and fees match no preset, see the presets module");
+    }
 
     let token_swap = SwapInfo::unpack_unchecked(&swap_info.data.borrow())?;
     if token_swap.is_initialized {
         return Err(SwapError::AlreadyInUse.into());
     }
-    let swap_authority = utils::authority_id(program_id, swap_info.key, nonce)?;
+    let shared_authority = utils::authority_id(program_id, SHARED_AUTHORITY_SEED, nonce).ok()
+        == Some(*authority_info.key);
+    let authority_seed = if shared_authority {
+        SHARED_AUTHORITY_SEED
+    } else {
+        swap_info.key.as_ref()
+    };
+    let swap_authority = utils::authority_id(program_id, authority_seed, nonce)?;
     check_keys_equal!(
         *authority_info.key,
         swap_authority,
         "Swap authority",
         SwapError::InvalidProgramAddress
     );
+    if shared_authority {
+        // The legacy layout has no room for the flag
+        if swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        // The shared authority only ever receives accounts from their owner, so
+        // that it never owns an account claimed by two pools
+        let vault_owner_info = next_account_info(account_info_iter)?;
+        for (account_info, authority_type) in [
+            (token_a_info, AuthorityType::AccountOwner),
+            (token_b_info, AuthorityType::AccountOwner),
+            (pool_mint_info, AuthorityType::MintTokens),
+        ] {
+            token::set_authority_as_user(
+                token_program_info.clone(),
+                account_info.clone(),
+                authority_info.key,
+                authority_type,
+                vault_owner_info.clone(),
+            )?;
+        }
+        let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+        if pool_mint.freeze_authority == COption::Some(*vault_owner_info.key) {
+            token::set_authority_as_user(
+                token_program_info.clone(),
+                pool_mint_info.clone(),
+                authority_info.key,
+                AuthorityType::FreezeAccount,
+                vault_owner_info.clone(),
+            )?;
+        }
+    }
 
     let destination = utils::unpack_token_account(&destination_info.data.borrow())?;
     let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
@@ -195,7 +500,10 @@ fn process_initialize(
         "LP mint authority",
         SwapError::InvalidOwner
     );
-    if pool_mint.freeze_authority.is_some() {
+    // pools opt into FreezeLp/ThawLp by giving the swap authority the LP freeze authority
+    if pool_mint.freeze_authority.is_some()
+        && pool_mint.freeze_authority != COption::Some(*authority_info.key)
+    {
         return Err(SwapError::InvalidFreezeAuthority.into());
     }
     if pool_mint.supply != 0 {
@@ -224,32 +532,74 @@ fn process_initialize(
         "Mint B",
         SwapError::InvalidAdmin
     );
+    // An admin fee account the shared authority owns may be claimed by another pool
+    if shared_authority
+        && (admin_fee_key_a.owner == *authority_info.key
+            || admin_fee_key_b.owner == *authority_info.key)
+    {
+        return Err(SwapError::InvalidOwner.into());
+    }
 
-    // amp_factor == initial_amp_factor == target_amp_factor on init
-    let invariant = StableSwap::new(amp_factor, amp_factor, ZERO_TS, ZERO_TS, ZERO_TS);
+    // The pool starts at its initial amp factor, ramp or not
+    let invariant = curve_type.calculator(
+        initial_amp_factor,
+        initial_amp_factor,
+        ZERO_TS,
+        ZERO_TS,
+        ZERO_TS,
+    );
     // Compute amount of LP tokens to mint for bootstrapper
-    let mint_amount_u256 = invariant
-        .compute_d(token_a.amount, token_b.amount)
+    let (mint_amount, initial_supply_scaled) = invariant
+        .initial_mint_amount(token_a.amount, token_b.amount)
         .ok_or(SwapError::CalculationFailure)?;
-    let mint_amount = (mint_amount_u256.try_to_u64())?;
+    // The bootstrapper must be left with pool tokens of its own
+    let bootstrapper_amount = mint_amount
+        .checked_sub(min_liquidity)
+        .filter(|amount| *amount > 0)
+        .ok_or(SwapError::InvalidInput)?;
+    if min_liquidity > 0 {
+        let locked_lp_info = next_account_info(account_info_iter)?;
+        let locked_lp = utils::unpack_token_account(&locked_lp_info.data.borrow())?;
+        check_keys_equal!(
+            locked_lp.owner,
+            find_locked_liquidity_address(program_id, swap_info.key).0,
+            "Locked liquidity owner",
+            SwapError::InvalidOwner
+        );
+        check_keys_equal!(
+            locked_lp.mint,
+            *pool_mint_info.key,
+            "Locked liquidity mint",
+            SwapError::IncorrectMint
+        );
+        token::mint_to(
+            authority_seed,
+            token_program_info.clone(),
+            pool_mint_info.clone(),
+            locked_lp_info.clone(),
+            authority_info.clone(),
+            nonce,
+            min_liquidity,
+        )?;
+    }
     token::mint_to(
-        swap_info.key,
+        authority_seed,
         token_program_info.clone(),
         pool_mint_info.clone(),
         destination_info.clone(),
         authority_info.clone(),
         nonce,
-        mint_amount,
+        bootstrapper_amount,
     )?;
 
     let obj = SwapInfo {
         is_initialized: true,
         is_paused: false,
         nonce,
-        initial_amp_factor: amp_factor,
-        target_amp_factor: amp_factor,
-        start_ramp_ts: ZERO_TS,
-        stop_ramp_ts: ZERO_TS,
+        initial_amp_factor,
+        target_amp_factor,
+        start_ramp_ts,
+        stop_ramp_ts,
         future_admin_deadline: ZERO_TS,
         future_admin_key: Pubkey::default(),
         admin_key: *admin_key_info.key,
@@ -267,11 +617,43 @@ fn process_initialize(
         },
         pool_mint: *pool_mint_info.key,
         fees,
+        future_fees_deadline: ZERO_TS,
+        future_fees: Fees::default(),
+        donations_admin_only: false,
+        spl_token_swap_compat: false,
+        fee_growth_a: 0,
+        fee_growth_b: 0,
+        pool_token_supply: mint_amount,
+        price_oracle_a: Pubkey::default(),
+        price_oracle_b: Pubkey::default(),
+        max_price_deviation_bps: 0,
+        initial_trade_fee_numerator: fees.trade_fee_numerator,
+        initial_withdraw_fee_numerator: fees.withdraw_fee_numerator,
+        start_fee_ramp_ts: ZERO_TS,
+        stop_fee_ramp_ts: ZERO_TS,
+        deposit_allowlist_enabled: false,
+        max_swap_bps_of_reserves: 0,
+        is_deprecated: false,
+        shared_authority,
+        reject_zero_amounts: false,
+        reserve_amount_a: token_a.amount,
+        reserve_amount_b: token_b.amount,
+        withdraw_fee_grace_period: 0,
+        cpi_policy: CpiPolicy::Any,
+        peg_rate: 0,
+        quick_swap_max_bps: 0,
+        same_slot_guard: false,
+        admin_fees_as_lp: false,
+        withdraw_one_while_paused: false,
+        quiet_logs: false,
+        deposit_cap_enabled: false,
+        curve_type,
+        initial_supply_scaled,
     };
     SwapInfo::pack(obj, &mut swap_info.data.borrow_mut())?;
 
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
     log_event(
+        token_swap.quiet_logs,
         Event::Deposit,
         clock.unix_timestamp,
         token_a.amount,
@@ -284,15 +666,35 @@ fn process_initialize(
 }
 
 /// Processes an [Swap](enum.Instruction.html).
+/// Accounts used to execute a swap, in any of the accepted instruction layouts
+struct SwapAccounts<'a, 'b> {
+    swap_info: &'a AccountInfo<'b>,
+    swap_authority_info: &'a AccountInfo<'b>,
+    user_authority_info: &'a AccountInfo<'b>,
+    /// Seeds of the user authority if it is a program address signed for by the
+    /// program, e.g. an order
+    user_authority_seeds: Option<&'a [&'a [u8]]>,
+    source_info: &'a AccountInfo<'b>,
+    swap_source_info: &'a AccountInfo<'b>,
+    swap_destination_info: &'a AccountInfo<'b>,
+    destination_info: &'a AccountInfo<'b>,
+    admin_destination_info: &'a AccountInfo<'b>,
+    token_program_info: &'a AccountInfo<'b>,
+    pool_stats_info: Option<&'a AccountInfo<'b>>,
+    trailing_infos: &'a [AccountInfo<'b>],
+}
+
+/// Processes a [Swap](enum.Instruction.html), or a [SwapAllowPartial](enum.Instruction.html)
+/// if `allow_partial` is set.
 fn process_swap(
     program_id: &Pubkey,
     amount_in: u64,
     minimum_amount_out: u64,
+    allow_partial: bool,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     if amount_in == 0 {
-        // noop
-        return Ok(());
+        return process_noop(accounts);
     }
     let account_info_iter = &mut accounts.iter();
     let swap_info = next_account_info(account_info_iter)?;
@@ -304,333 +706,351 @@ fn process_swap(
     let destination_info = next_account_info(account_info_iter)?;
     let admin_destination_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
-
-    if *swap_source_info.key == *swap_destination_info.key {
-        return Err(SwapError::InvalidInput.into());
-    }
-
-    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
-    if token_swap.is_paused {
-        return Err(SwapError::IsPaused.into());
-    }
-
-    check_token_keys_not_equal!(
-        token_swap.token_a,
-        *source_info.key,
-        token_swap.token_a.reserves,
-        "Source account cannot be one of swap's token accounts for token",
-        SwapError::InvalidInput
-    );
-
-    check_token_keys_not_equal!(
-        token_swap.token_b,
-        *source_info.key,
-        token_swap.token_b.reserves,
-        "Source account cannot be one of swap's token accounts for token",
-        SwapError::InvalidInput
-    );
-
-    check_swap_authority(
-        &token_swap,
-        swap_info.key,
-        program_id,
-        swap_authority_info.key,
-    )?;
-
-    if *swap_source_info.key == token_swap.token_a.reserves {
-        // Swap A to B
-        check_swap_token_destination_accounts(
-            &token_swap.token_b,
-            swap_destination_info.key,
-            admin_destination_info.key,
-        )?;
-    } else if *swap_source_info.key == token_swap.token_b.reserves {
-        // Swap B to A
-        check_swap_token_destination_accounts(
-            &token_swap.token_a,
-            swap_destination_info.key,
-            admin_destination_info.key,
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+    let native_sol_system_program_info =
+        find_native_sol_system_program(account_info_iter.as_slice())?;
+
+    check_not_globally_paused(program_id, global_state_info)?;
+    let (fill_amount, fill_minimum_amount_out) = if allow_partial {
+        let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+        let fill_amount = partial_fill_amount(
+            &token_swap,
+            swap_source_info,
+            swap_destination_info,
+            amount_in,
+            minimum_amount_out,
+            &clock,
         )?;
+        if fill_amount < amount_in {
+            log_detail!(
+                token_swap.quiet_logs,
+                "Filling {} of {}",
+                fill_amount,
+                amount_in
+            );
+        }
+        // The limit price of the whole amount applies to the fill
+        let fill_minimum_amount_out =
+            math::mul_div_ceil(minimum_amount_out, fill_amount, amount_in)
+                .ok_or(SwapError::CalculationFailure)?;
+        (fill_amount, fill_minimum_amount_out)
     } else {
-        return Err(SwapError::IncorrectSwapAccount.into());
-    }
-
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
-    let swap_source_account = utils::unpack_token_account(&swap_source_info.data.borrow())?;
-    let swap_destination_account =
-        utils::unpack_token_account(&swap_destination_info.data.borrow())?;
-
-    let invariant = StableSwap::new(
-        token_swap.initial_amp_factor,
-        token_swap.target_amp_factor,
-        clock.unix_timestamp,
-        token_swap.start_ramp_ts,
-        token_swap.stop_ramp_ts,
-    );
-    let result = invariant
-        .swap_to(
-            amount_in,
-            swap_source_account.amount,
-            swap_destination_account.amount,
-            &token_swap.fees,
-        )
-        .ok_or(SwapError::CalculationFailure)?;
-    let amount_swapped = result.amount_swapped;
-    if amount_swapped < minimum_amount_out {
-        log_slippage_error(minimum_amount_out, amount_swapped);
-        return Err(SwapError::ExceededSlippage.into());
-    }
-
-    // from user to swap
-    token::transfer_as_user(
-        token_program_info.clone(),
-        source_info.clone(),
-        swap_source_info.clone(),
-        user_authority_info.clone(),
-        amount_in,
-    )?;
-    // from swap to user
-    token::transfer_as_swap(
-        swap_info.key,
-        token_program_info.clone(),
-        swap_destination_info.clone(),
-        destination_info.clone(),
-        swap_authority_info.clone(),
-        token_swap.nonce,
-        amount_swapped,
+        (amount_in, minimum_amount_out)
+    };
+    wrap_native_sol_if_needed(
+        native_sol_system_program_info,
+        token_program_info,
+        source_info,
+        user_authority_info,
+        fill_amount,
     )?;
-    // from swap to fees
-    token::transfer_as_swap(
-        swap_info.key,
-        token_program_info.clone(),
-        swap_destination_info.clone(),
-        admin_destination_info.clone(),
-        swap_authority_info.clone(),
-        token_swap.nonce,
-        result.admin_fee,
+    let (token_swap, result) = execute_swap(
+        program_id,
+        fill_amount,
+        fill_minimum_amount_out,
+        &SwapAccounts {
+            swap_info,
+            swap_authority_info,
+            user_authority_info,
+            user_authority_seeds: None,
+            source_info,
+            swap_source_info,
+            swap_destination_info,
+            destination_info,
+            admin_destination_info,
+            token_program_info,
+            pool_stats_info,
+            trailing_infos: account_info_iter.as_slice(),
+        },
+        &clock,
     )?;
-
-    if *swap_source_info.key == token_swap.token_a.reserves {
-        log_event(
-            Event::SwapAToB,
-            clock.unix_timestamp,
-            amount_in,
-            amount_swapped,
-            0,
-            result.fee,
-        );
-    } else {
-        log_event(
-            Event::SwapBToA,
-            clock.unix_timestamp,
-            amount_swapped,
-            amount_in,
-            0,
-            result.fee,
+    if allow_partial {
+        // Replaces the return data of the swap
+        PartialSwapReturnData {
+            amount_in: fill_amount,
+            amount_out: result.amount_swapped,
+            fee: result.fee,
+            admin_fee: result.admin_fee,
+            price_impact_bps: result.price_impact_bps().unwrap_or(0),
+        }
+        .set();
+    }
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, swap_source_info, swap_destination_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        record_swap_lp_fee(
+            swap_info_ref,
+            &token_swap,
+            swap_destination_info.key,
+            &result,
         );
-    };
-
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+    for token_account_info in [source_info, destination_info] {
+        unwrap_native_sol_if_needed(
+            native_sol_system_program_info,
+            token_program_info,
+            token_account_info,
+            user_authority_info,
+        )?;
+    }
     Ok(())
 }
 
-/// Processes an [Deposit](enum.Instruction.html).
-fn process_deposit(
+/// Processes a [SimulateSwap](enum.Instruction.html): the checks and the math of a swap
+/// without its transfers, always failing so that nothing is committed.
+fn process_simulate_swap(
     program_id: &Pubkey,
-    token_a_amount: u64,
-    token_b_amount: u64,
-    min_mint_amount: u64,
+    amount_in: u64,
+    minimum_amount_out: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
-    if token_a_amount == 0 && token_b_amount == 0 {
-        // noop
-        return Ok(());
+    if amount_in == 0 {
+        process_noop(accounts)?;
+        return Err(SwapError::SimulationComplete.into());
     }
     let account_info_iter = &mut accounts.iter();
     let swap_info = next_account_info(account_info_iter)?;
     let swap_authority_info = next_account_info(account_info_iter)?;
     let user_authority_info = next_account_info(account_info_iter)?;
-    let source_a_info = next_account_info(account_info_iter)?;
-    let source_b_info = next_account_info(account_info_iter)?;
-    let token_a_info = next_account_info(account_info_iter)?;
-    let token_b_info = next_account_info(account_info_iter)?;
-    let pool_mint_info = next_account_info(account_info_iter)?;
-    let dest_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let swap_source_info = next_account_info(account_info_iter)?;
+    let swap_destination_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let admin_destination_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
 
-    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
-    if token_swap.is_paused {
-        return Err(SwapError::IsPaused.into());
-    }
-    check_swap_authority(
-        &token_swap,
-        swap_info.key,
+    check_not_globally_paused(program_id, global_state_info)?;
+    let (token_swap, result) = check_swap(
         program_id,
-        swap_authority_info.key,
+        amount_in,
+        minimum_amount_out,
+        &SwapAccounts {
+            swap_info,
+            swap_authority_info,
+            user_authority_info,
+            user_authority_seeds: None,
+            source_info,
+            swap_source_info,
+            swap_destination_info,
+            destination_info,
+            admin_destination_info,
+            token_program_info,
+            pool_stats_info,
+            trailing_infos: account_info_iter.as_slice(),
+        },
+        &clock,
     )?;
-
-    check_deposit_token_accounts(&token_swap.token_a, source_a_info.key, token_a_info.key)?;
-    check_deposit_token_accounts(&token_swap.token_b, source_b_info.key, token_b_info.key)?;
-
-    check_keys_equal!(
-        *pool_mint_info.key,
-        token_swap.pool_mint,
-        "Mint A",
-        SwapError::IncorrectMint
-    );
-
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
-    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
-    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
-    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
-
-    let invariant = StableSwap::new(
-        token_swap.initial_amp_factor,
-        token_swap.target_amp_factor,
+    log_swap_event(
+        &token_swap,
+        swap_source_info.key,
         clock.unix_timestamp,
-        token_swap.start_ramp_ts,
-        token_swap.stop_ramp_ts,
+        amount_in,
+        &result,
     );
-    let mint_amount = invariant
-        .compute_mint_amount_for_deposit(
-            token_a_amount,
-            token_b_amount,
-            token_a.amount,
-            token_b.amount,
-            pool_mint.supply,
-            &token_swap.fees,
-        )
+    SwapReturnData {
+        amount_out: result.amount_swapped,
+        fee: result.fee,
+        admin_fee: result.admin_fee,
+        price_impact_bps: result.price_impact_bps().unwrap_or(0),
+    }
+    .set();
+    Err(SwapError::SimulationComplete.into())
+}
+
+/// Largest part of `amount_in` a SwapAllowPartial fills: within the max swap size of
+/// the swap and at the limit price `minimum_amount_out / amount_in`. Returns the whole
+/// amount if no part fills, for the swap to fail as a Swap would. The accounts are
+/// checked by the swap of the fill.
+fn partial_fill_amount(
+    token_swap: &SwapInfo,
+    swap_source_info: &AccountInfo,
+    swap_destination_info: &AccountInfo,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    let swap_source_amount = utils::unpack_token_account(&swap_source_info.data.borrow())?.amount;
+    let swap_destination_amount =
+        utils::unpack_token_account(&swap_destination_info.data.borrow())?.amount;
+    let max_amount_out = max_swap_amount_out(token_swap, swap_destination_amount)?;
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let fees = token_swap
+        .current_fees(clock.unix_timestamp)
         .ok_or(SwapError::CalculationFailure)?;
-    if mint_amount < min_mint_amount {
-        log_slippage_error(min_mint_amount, mint_amount);
-        return Err(SwapError::ExceededSlippage.into());
+    let source_rate = token_swap.token_peg_rate(swap_source_info.key);
+    let destination_rate = token_swap.token_peg_rate(swap_destination_info.key);
+    let fills = |fill_amount: u64| -> bool {
+        let result = match invariant.swap_to_with_rates_quick(
+            fill_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            source_rate,
+            destination_rate,
+            token_swap.quick_swap_max_bps.into(),
+            &fees,
+        ) {
+            Some(result) => result,
+            None => return false,
+        };
+        let within_max_size = match max_amount_out {
+            Some(max_amount_out) => matches!(
+                result.amount_swapped.checked_add(result.admin_fee),
+                Some(amount_out) if amount_out <= max_amount_out
+            ),
+            None => true,
+        };
+        within_max_size
+            && u128::from(result.amount_swapped) * u128::from(amount_in)
+                >= u128::from(minimum_amount_out) * u128::from(fill_amount)
+    };
+    if fills(amount_in) {
+        return Ok(amount_in);
+    }
+    // Larger fills take more out of the reserve at a worse price
+    let (mut filled, mut unfilled) = (0, amount_in);
+    while unfilled - filled > 1 {
+        let fill_amount = filled + (unfilled - filled) / 2;
+        if fills(fill_amount) {
+            filled = fill_amount;
+        } else {
+            unfilled = fill_amount;
+        }
     }
+    Ok(if filled == 0 { amount_in } else { filled })
+}
 
-    // from user to swap
-    token::transfer_as_user(
-        token_program_info.clone(),
-        source_a_info.clone(),
-        token_a_info.clone(),
-        user_authority_info.clone(),
-        token_a_amount,
-    )?;
-    // from user to swap
-    token::transfer_as_user(
-        token_program_info.clone(),
-        source_b_info.clone(),
-        token_b_info.clone(),
-        user_authority_info.clone(),
-        token_b_amount,
-    )?;
-    // mint lp to user
-    token::mint_to(
-        swap_info.key,
-        token_program_info.clone(),
-        pool_mint_info.clone(),
-        dest_info.clone(),
-        swap_authority_info.clone(),
-        token_swap.nonce,
-        mint_amount,
-    )?;
+/// Processes a [SwapBatch](enum.Instruction.html).
+fn process_swap_batch(
+    program_id: &Pubkey,
+    fills: &[SwapData],
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let swap_source_info = next_account_info(account_info_iter)?;
+    let swap_destination_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let admin_destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
 
-    log_event(
-        Event::Deposit,
-        clock.unix_timestamp,
-        token_a_amount,
-        token_b_amount,
-        mint_amount,
-        0,
-    );
+    check_not_globally_paused(program_id, global_state_info)?;
+    let accounts = SwapAccounts {
+        swap_info,
+        swap_authority_info,
+        user_authority_info,
+        user_authority_seeds: None,
+        source_info,
+        swap_source_info,
+        swap_destination_info,
+        destination_info,
+        admin_destination_info,
+        token_program_info,
+        pool_stats_info,
+        trailing_infos: account_info_iter.as_slice(),
+    };
+    let mut total = SwapReturnData::default();
+    for fill in fills.iter().filter(|fill| fill.amount_in > 0) {
+        // Each fill is quoted against the state packed by the previous one
+        let (token_swap, result) = execute_swap(
+            program_id,
+            fill.amount_in,
+            fill.minimum_amount_out,
+            &accounts,
+            &clock,
+        )?;
+        let (reserve_amount_a, reserve_amount_b) =
+            reserve_amounts(&token_swap, swap_source_info, swap_destination_info)?;
+        update_swap_info(swap_info, |swap_info_ref| {
+            record_swap_lp_fee(
+                swap_info_ref,
+                &token_swap,
+                swap_destination_info.key,
+                &result,
+            );
+            swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+        })?;
 
+        total = add_swap_result(&total, &result)?;
+    }
+    // Replaces the return data of the last fill
+    total.set();
     Ok(())
 }
 
-struct WithdrawContext<'a, 'b: 'a> {
-    token_swap: SwapInfo,
-    token_program_info: &'a AccountInfo<'b>,
-    swap_authority_info: &'a AccountInfo<'b>,
-    swap_info: &'a AccountInfo<'b>,
+/// Adds the amounts of a swap to the return data of several swaps. Each swap moves the
+/// price, so they report the price impact of the worst one.
+fn add_swap_result(
+    total: &SwapReturnData,
+    result: &SwapResult,
+) -> Result<SwapReturnData, ProgramError> {
+    Ok(SwapReturnData {
+        amount_out: total
+            .amount_out
+            .checked_add(result.amount_swapped)
+            .ok_or(SwapError::CalculationFailure)?,
+        fee: total
+            .fee
+            .checked_add(result.fee)
+            .ok_or(SwapError::CalculationFailure)?,
+        admin_fee: total
+            .admin_fee
+            .checked_add(result.admin_fee)
+            .ok_or(SwapError::CalculationFailure)?,
+        price_impact_bps: total
+            .price_impact_bps
+            .max(result.price_impact_bps().unwrap_or(0)),
+    })
 }
 
-fn handle_token_withdraw<'a, 'b: 'a>(
-    ctx: &WithdrawContext<'a, 'b>,
-    (amount, admin_fee): (u64, u64),
-    reserves_info: &'a AccountInfo<'b>,
-    dest_token_info: &'a AccountInfo<'b>,
-    admin_fee_dest_info: &'a AccountInfo<'b>,
-) -> ProgramResult {
-    // from swap to user
-    token::transfer_as_swap(
-        ctx.swap_info.key,
-        ctx.token_program_info.clone(),
-        reserves_info.clone(),
-        dest_token_info.clone(),
-        ctx.swap_authority_info.clone(),
-        ctx.token_swap.nonce,
-        amount,
-    )?;
-    // from swap to fee
-    token::transfer_as_swap(
-        ctx.swap_info.key,
-        ctx.token_program_info.clone(),
-        reserves_info.clone(),
-        admin_fee_dest_info.clone(),
-        ctx.swap_authority_info.clone(),
-        ctx.token_swap.nonce,
-        admin_fee,
-    )?;
-
-    Ok(())
+/// Whether the accounts of a Swap follow the spl-token-swap layout, which
+/// passes the pool mint where the admin fee account is expected.
+fn is_spl_token_swap_layout(accounts: &[AccountInfo]) -> bool {
+    matches!(accounts.get(7), Some(info) if info.data_len() == spl_token::state::Mint::LEN)
 }
 
-/// Processes an [Withdraw](enum.Instruction.html).
-fn process_withdraw(
+/// Processes a Swap passed with the accounts of an spl-token-swap `Swap`.
+/// The pool fee account receives the admin fee of the destination token.
+fn process_spl_token_swap(
     program_id: &Pubkey,
-    pool_token_amount: u64,
-    minimum_token_a_amount: u64,
-    minimum_token_b_amount: u64,
+    amount_in: u64,
+    minimum_amount_out: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
-    if pool_token_amount == 0 {
-        // noop
-        return Ok(());
+    if amount_in == 0 {
+        return process_noop(accounts);
     }
     let account_info_iter = &mut accounts.iter();
     let swap_info = next_account_info(account_info_iter)?;
     let swap_authority_info = next_account_info(account_info_iter)?;
     let user_authority_info = next_account_info(account_info_iter)?;
-    let pool_mint_info = next_account_info(account_info_iter)?;
     let source_info = next_account_info(account_info_iter)?;
-    let token_a_info = next_account_info(account_info_iter)?;
-    let token_b_info = next_account_info(account_info_iter)?;
-    let dest_token_a_info = next_account_info(account_info_iter)?;
-    let dest_token_b_info = next_account_info(account_info_iter)?;
-    let admin_fee_dest_a_info = next_account_info(account_info_iter)?;
-    let admin_fee_dest_b_info = next_account_info(account_info_iter)?;
+    let swap_source_info = next_account_info(account_info_iter)?;
+    let swap_destination_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let pool_fee_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    // The optional host fee account is found among the trailing accounts by its mint,
+    // price oracles by key
 
+    check_not_globally_paused(program_id, global_state_info)?;
     let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
-    check_swap_authority(
-        &token_swap,
-        swap_info.key,
-        program_id,
-        swap_authority_info.key,
-    )?;
-
-    check_withdraw_token_accounts(
-        &token_swap.token_a,
-        token_a_info.key,
-        admin_fee_dest_a_info.key,
-    )?;
-
-    check_withdraw_token_accounts(
-        &token_swap.token_b,
-        token_b_info.key,
-        admin_fee_dest_b_info.key,
-    )?;
-
+    if !token_swap.spl_token_swap_compat {
+        return Err(SwapError::InvalidInstruction.into());
+    }
     check_keys_equal!(
         *pool_mint_info.key,
         token_swap.pool_mint,
@@ -638,1236 +1058,7964 @@ fn process_withdraw(
         SwapError::IncorrectMint
     );
 
-    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
-    if pool_mint.supply == 0 {
-        return Err(SwapError::EmptyPool.into());
+    let clock = Clock::get()?;
+    let (token_swap, _result) = execute_swap(
+        program_id,
+        amount_in,
+        minimum_amount_out,
+        &SwapAccounts {
+            swap_info,
+            swap_authority_info,
+            user_authority_info,
+            user_authority_seeds: None,
+            source_info,
+            swap_source_info,
+            swap_destination_info,
+            destination_info,
+            admin_destination_info: pool_fee_info,
+            token_program_info,
+            pool_stats_info: None,
+            trailing_infos: account_info_iter.as_slice(),
+        },
+        &clock,
+    )?;
+    // spl-token-swap clients pass the swap account read-only, leaving the reserve
+    // amounts for SyncReserves to catch up with
+    if swap_info.is_writable {
+        let (reserve_amount_a, reserve_amount_b) =
+            reserve_amounts(&token_swap, swap_source_info, swap_destination_info)?;
+        update_swap_info(swap_info, |swap_info_ref| {
+            swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b)
+        })?;
     }
+    Ok(())
+}
 
-    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
-    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
-
-    let converter = PoolTokenConverter {
-        supply: (pool_mint.supply),
-        token_a: (token_a.amount),
-        token_b: (token_b.amount),
-        fees: &token_swap.fees,
-    };
-    let pool_token_amount_u256 = pool_token_amount;
-
-    let ctx = WithdrawContext {
-        token_swap,
-        token_program_info,
-        swap_authority_info,
+/// Executes a swap, returning the swap state it was quoted against and its result
+fn execute_swap(
+    program_id: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    accounts: &SwapAccounts,
+    clock: &Clock,
+) -> Result<(SwapInfo, SwapResult), ProgramError> {
+    let SwapAccounts {
         swap_info,
-    };
-
-    let (a_amount, a_fee, a_admin_fee) = check_can_withdraw_token(
-        converter.token_a_rate(pool_token_amount_u256),
-        minimum_token_a_amount,
-    )?;
-    let (b_amount, b_fee, b_admin_fee) = check_can_withdraw_token(
-        converter.token_b_rate(pool_token_amount_u256),
-        minimum_token_b_amount,
-    )?;
+        swap_authority_info,
+        user_authority_info,
+        user_authority_seeds,
+        source_info,
+        swap_source_info,
+        swap_destination_info,
+        destination_info,
+        admin_destination_info,
+        token_program_info,
+        pool_stats_info,
+        trailing_infos,
+    } = *accounts;
+    let (token_swap, result) =
+        check_swap(program_id, amount_in, minimum_amount_out, accounts, clock)?;
+    let amount_swapped = result.amount_swapped;
 
-    handle_token_withdraw(
-        &ctx,
-        (a_amount, a_admin_fee),
-        token_a_info,
-        dest_token_a_info,
-        admin_fee_dest_a_info,
-    )?;
-    handle_token_withdraw(
-        &ctx,
-        (b_amount, b_admin_fee),
-        token_b_info,
-        dest_token_b_info,
-        admin_fee_dest_b_info,
+    // from user to swap
+    match user_authority_seeds {
+        Some(user_authority_seeds) => token::transfer_as_program_address(
+            user_authority_seeds,
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_source_info.clone(),
+            user_authority_info.clone(),
+            amount_in,
+        )?,
+        None => token::transfer_as_user(
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_source_info.clone(),
+            user_authority_info.clone(),
+            amount_in,
+        )?,
+    }
+    // from swap to user
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        swap_destination_info.clone(),
+        destination_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        amount_swapped,
     )?;
+    // from swap to the host fee account, out of the admin fee
+    let host_fee = token_swap
+        .fees
+        .host_fee(result.admin_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+    let host_fee_info = if host_fee > 0 {
+        find_host_fee_account(
+            trailing_infos,
+            token_program_info.key,
+            &reserves_mint(&token_swap, swap_destination_info.key),
+        )
+    } else {
+        None
+    };
+    let admin_fee = match host_fee_info {
+        Some(host_fee_info) => {
+            token::transfer_as_swap(
+                token_swap.authority_seed(swap_info.key),
+                token_program_info.clone(),
+                swap_destination_info.clone(),
+                host_fee_info.clone(),
+                swap_authority_info.clone(),
+                token_swap.nonce,
+                host_fee,
+            )?;
+            result
+                .admin_fee
+                .checked_sub(host_fee)
+                .ok_or(SwapError::CalculationFailure)?
+        }
+        None => result.admin_fee,
+    };
+    if token_swap.admin_fees_as_lp {
+        let admin_fees = if *swap_destination_info.key == token_swap.token_a.reserves {
+            [admin_fee, 0]
+        } else {
+            [0, admin_fee]
+        };
+        mint_admin_fees_as_lp(
+            &token_swap,
+            swap_info,
+            swap_authority_info,
+            token_program_info,
+            [swap_source_info, swap_destination_info],
+            admin_destination_info,
+            admin_fees,
+            clock,
+            trailing_infos,
+        )?;
+    } else {
+        // from swap to fees
+        token::transfer_as_swap(
+            token_swap.authority_seed(swap_info.key),
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            admin_destination_info.clone(),
+            swap_authority_info.clone(),
+            token_swap.nonce,
+            admin_fee,
+        )?;
+    }
 
-    // burn LP tokens withdrawn
-    token::burn(
-        token_program_info.clone(),
-        source_info.clone(),
-        pool_mint_info.clone(),
-        user_authority_info.clone(),
-        pool_token_amount,
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |pool_stats| {
+            pool_stats.record_swap(
+                *swap_source_info.key == token_swap.token_a.reserves,
+                amount_in,
+                amount_swapped,
+                result.fee,
+                admin_fee,
+            )
+        },
     )?;
 
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
-    log_event(
-        Event::WithdrawA,
+    log_swap_event(
+        &token_swap,
+        swap_source_info.key,
         clock.unix_timestamp,
-        a_amount,
-        0,
-        0,
-        a_fee,
+        amount_in,
+        &result,
     );
-    log_event(
-        Event::WithdrawB,
+    log_trade_admin_fee(
+        &token_swap,
+        swap_destination_info.key,
         clock.unix_timestamp,
-        0,
-        b_amount,
-        0,
-        b_fee,
-    );
-    log_event(
-        Event::Burn,
-        clock.unix_timestamp,
-        0,
-        0,
-        pool_token_amount,
-        0,
+        admin_fee,
     );
+    SwapReturnData {
+        amount_out: amount_swapped,
+        fee: result.fee,
+        admin_fee: result.admin_fee,
+        price_impact_bps: result.price_impact_bps().unwrap_or(0),
+    }
+    .set();
 
-    Ok(())
+    Ok((token_swap, result))
 }
 
-/// Processes an [WithdrawOne](enum.Instruction.html).
-fn process_withdraw_one(
+/// Quotes a swap and runs the checks it passes before any transfer, returning the swap
+/// state it was quoted against and its result
+fn check_swap(
     program_id: &Pubkey,
-    pool_token_amount: u64,
-    minimum_token_amount: u64,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    accounts: &SwapAccounts,
+    clock: &Clock,
+) -> Result<(SwapInfo, SwapResult), ProgramError> {
+    let SwapAccounts {
+        swap_info,
+        swap_authority_info,
+        user_authority_info,
+        source_info,
+        swap_source_info,
+        swap_destination_info,
+        admin_destination_info,
+        trailing_infos,
+        ..
+    } = *accounts;
+    let (token_swap, result) = quote_swap(
+        program_id,
+        swap_info,
+        swap_authority_info,
+        swap_source_info,
+        swap_destination_info,
+        admin_destination_info,
+        source_info.key,
+        amount_in,
+        clock,
+    )?;
+    check_cpi_allowed(program_id, swap_info.key, &token_swap, trailing_infos)?;
+    record_last_trade(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        user_authority_info.key,
+        *swap_source_info.key == token_swap.token_a.reserves,
+        clock.slot,
+        trailing_infos,
+    )?;
+    let amount_swapped = result.amount_swapped;
+    if amount_swapped < minimum_amount_out {
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: minimum_amount_out,
+                actual: amount_swapped,
+            })
+            .into());
+    }
+    check_price_deviation(
+        &token_swap,
+        swap_source_info.key,
+        amount_in,
+        &result,
+        trailing_infos,
+        clock.slot,
+    )?;
+    Ok((token_swap, result))
+}
+
+/// Finds the pool token account of the admin among the trailing accounts, required
+/// once the swap mints its admin fees as pool tokens
+fn find_admin_pool_token_info<'a, 'b>(
+    token_swap: &SwapInfo,
+    token_program_info: &AccountInfo,
+    admin_fee_info: &AccountInfo,
+    trailing_infos: &'a [AccountInfo<'b>],
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    let admin_fee_owner = utils::unpack_token_account(&admin_fee_info.data.borrow())?.owner;
+    find_admin_pool_token_account(
+        trailing_infos,
+        token_program_info.key,
+        &token_swap.pool_mint,
+        &admin_fee_owner,
+    )
+    .ok_or_else(|| {
+        msg!("Admin fees are minted as pool tokens, pass the admin pool token account");
+        ProgramError::NotEnoughAccountKeys
+    })
+}
+
+/// Mints the admin the pool tokens worth the admin fees left in the reserves, given
+/// in token A and token B, once the swap moved its tokens. See
+/// [SwapInfo::admin_fees_as_lp].
+#[allow(clippy::too_many_arguments)]
+fn mint_admin_fees_as_lp<'a>(
+    token_swap: &SwapInfo,
+    swap_info: &AccountInfo<'a>,
+    swap_authority_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    [reserves_info, other_reserves_info]: [&AccountInfo<'a>; 2],
+    admin_fee_info: &AccountInfo<'a>,
+    [admin_fee_a, admin_fee_b]: [u64; 2],
+    clock: &Clock,
+    trailing_infos: &[AccountInfo<'a>],
+) -> ProgramResult {
+    let admin_pool_token_info = find_admin_pool_token_info(
+        token_swap,
+        token_program_info,
+        admin_fee_info,
+        trailing_infos,
+    )?;
+    let pool_mint_info = find_account_by_key(trailing_infos, &token_swap.pool_mint)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(token_swap, reserves_info, other_reserves_info)?;
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let admin_pool_tokens = invariant
+        .admin_fee_mint_amount_with_rates(
+            admin_fee_a,
+            admin_fee_b,
+            reserve_amount_a,
+            reserve_amount_b,
+            pool_mint.supply,
+            token_swap.token_peg_rate(&token_swap.token_a.reserves),
+            token_swap.token_peg_rate(&token_swap.token_b.reserves),
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    token::mint_to(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        pool_mint_info.clone(),
+        admin_pool_token_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        admin_pool_tokens,
+    )?;
+    let pool_token_supply = pool_mint
+        .supply
+        .checked_add(admin_pool_tokens)
+        .ok_or(SwapError::CalculationFailure)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(pool_token_supply)
+    })
+}
+
+/// Processes a [SwapRoute](enum.Instruction.html).
+fn process_swap_route(
+    program_id: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
-    if pool_token_amount == 0 {
-        // noop
-        return Ok(());
+    if amount_in == 0 {
+        return process_noop(accounts);
     }
-
     let account_info_iter = &mut accounts.iter();
-    let swap_info = next_account_info(account_info_iter)?;
-    let swap_authority_info = next_account_info(account_info_iter)?;
     let user_authority_info = next_account_info(account_info_iter)?;
-    let pool_mint_info = next_account_info(account_info_iter)?;
     let source_info = next_account_info(account_info_iter)?;
-    let base_token_info = next_account_info(account_info_iter)?;
-    let quote_token_info = next_account_info(account_info_iter)?;
     let destination_info = next_account_info(account_info_iter)?;
-    let admin_destination_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
-    let clock_sysvar_info = next_account_info(account_info_iter)?;
-
-    if *base_token_info.key == *quote_token_info.key {
+    let clock = utils::next_clock(account_info_iter)?;
+    let first_swap_info = next_account_info(account_info_iter)?;
+    let first_authority_info = next_account_info(account_info_iter)?;
+    let first_source_info = next_account_info(account_info_iter)?;
+    let first_destination_info = next_account_info(account_info_iter)?;
+    let first_admin_destination_info = next_account_info(account_info_iter)?;
+    let second_swap_info = next_account_info(account_info_iter)?;
+    let second_authority_info = next_account_info(account_info_iter)?;
+    let second_source_info = next_account_info(account_info_iter)?;
+    let second_destination_info = next_account_info(account_info_iter)?;
+    let second_admin_destination_info = next_account_info(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    // Pool statistics and price oracles of either swap
+    let trailing_infos = account_info_iter.as_slice();
+
+    if *first_swap_info.key == *second_swap_info.key {
         return Err(SwapError::InvalidInput.into());
     }
+    check_not_globally_paused(program_id, global_state_info)?;
 
-    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
-    if token_swap.is_paused {
-        return Err(SwapError::IsPaused.into());
-    }
-    check_swap_authority(
-        &token_swap,
-        swap_info.key,
+    let (first_swap, first_result) = quote_swap(
         program_id,
-        swap_authority_info.key,
+        first_swap_info,
+        first_authority_info,
+        first_source_info,
+        first_destination_info,
+        first_admin_destination_info,
+        source_info.key,
+        amount_in,
+        &clock,
+    )?;
+    // The intermediate tokens move straight from the first pool's reserves into the second's
+    let (second_swap, second_result) = quote_swap(
+        program_id,
+        second_swap_info,
+        second_authority_info,
+        second_source_info,
+        second_destination_info,
+        second_admin_destination_info,
+        first_destination_info.key,
+        first_result.amount_swapped,
+        &clock,
     )?;
-
-    if *base_token_info.key == token_swap.token_a.reserves {
-        check_keys_equal!(
-            *quote_token_info.key,
-            token_swap.token_b.reserves,
-            "Swap A -> B reserves",
-            SwapError::IncorrectSwapAccount
-        );
-        check_keys_equal!(
-            *admin_destination_info.key,
-            token_swap.token_a.admin_fees,
-            "Swap A -> B admin fee destination",
-            SwapError::InvalidAdmin
-        );
-    } else if *base_token_info.key == token_swap.token_b.reserves {
-        check_keys_equal!(
-            *quote_token_info.key,
-            token_swap.token_a.reserves,
-            "Swap B -> A reserves",
-            SwapError::IncorrectSwapAccount
-        );
-        check_keys_equal!(
-            *admin_destination_info.key,
-            token_swap.token_b.admin_fees,
-            "Swap B -> A admin fee destination",
-            SwapError::InvalidAdmin
-        );
-    } else {
-        msg!("Unknown base token:");
-        base_token_info.key.log();
-        return Err(SwapError::IncorrectSwapAccount.into());
-    }
-
     check_keys_equal!(
-        *pool_mint_info.key,
-        token_swap.pool_mint,
-        "Pool mint",
+        reserves_mint(&first_swap, first_destination_info.key),
+        reserves_mint(&second_swap, second_source_info.key),
+        "Intermediate mint",
         SwapError::IncorrectMint
     );
+    check_cpi_allowed(program_id, first_swap_info.key, &first_swap, trailing_infos)?;
+    check_cpi_allowed(
+        program_id,
+        second_swap_info.key,
+        &second_swap,
+        trailing_infos,
+    )?;
 
-    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
-    let clock = Clock::from_account_info(clock_sysvar_info)?;
-    let base_token = utils::unpack_token_account(&base_token_info.data.borrow())?;
-    let quote_token = utils::unpack_token_account(&quote_token_info.data.borrow())?;
-
-    let invariant = StableSwap::new(
-        token_swap.initial_amp_factor,
-        token_swap.target_amp_factor,
-        clock.unix_timestamp,
-        token_swap.start_ramp_ts,
-        token_swap.stop_ramp_ts,
-    );
-    let (dy, dy_fee) = invariant
-        .compute_withdraw_one(
-            pool_token_amount,
-            pool_mint.supply,
-            base_token.amount,
-            quote_token.amount,
-            &token_swap.fees,
-        )
-        .ok_or(SwapError::CalculationFailure)?;
-    let withdraw_fee = token_swap
-        .fees
-        .withdraw_fee(dy)
-        .ok_or(SwapError::CalculationFailure)?;
-    let token_amount = dy
-        .checked_sub(withdraw_fee)
-        .ok_or(SwapError::CalculationFailure)?;
-    if token_amount < minimum_token_amount {
-        log_slippage_error(minimum_token_amount, token_amount);
-        return Err(SwapError::ExceededSlippage.into());
+    let amount_swapped = second_result.amount_swapped;
+    if amount_swapped < minimum_amount_out {
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: minimum_amount_out,
+                actual: amount_swapped,
+            })
+            .into());
     }
+    check_price_deviation(
+        &first_swap,
+        first_source_info.key,
+        amount_in,
+        &first_result,
+        trailing_infos,
+        clock.slot,
+    )?;
+    check_price_deviation(
+        &second_swap,
+        second_source_info.key,
+        first_result.amount_swapped,
+        &second_result,
+        trailing_infos,
+        clock.slot,
+    )?;
 
-    let admin_trade_fee = token_swap
-        .fees
-        .admin_trade_fee(dy_fee)
-        .ok_or(SwapError::CalculationFailure)?;
-    let admin_withdraw_fee = token_swap
-        .fees
-        .admin_withdraw_fee(withdraw_fee)
-        .ok_or(SwapError::CalculationFailure)?;
-    let admin_fee = admin_trade_fee
-        .checked_add(admin_withdraw_fee)
-        .ok_or(SwapError::CalculationFailure)?;
-
-    // from swap to user
+    // from user to first swap
+    token::transfer_as_user(
+        token_program_info.clone(),
+        source_info.clone(),
+        first_source_info.clone(),
+        user_authority_info.clone(),
+        amount_in,
+    )?;
+    // from first swap to second swap
     token::transfer_as_swap(
-        swap_info.key,
+        first_swap.authority_seed(first_swap_info.key),
         token_program_info.clone(),
-        base_token_info.clone(),
-        destination_info.clone(),
-        swap_authority_info.clone(),
-        token_swap.nonce,
-        token_amount,
+        first_destination_info.clone(),
+        second_source_info.clone(),
+        first_authority_info.clone(),
+        first_swap.nonce,
+        first_result.amount_swapped,
     )?;
-    // from swap to fee
+    // from first swap to fees
     token::transfer_as_swap(
-        swap_info.key,
+        first_swap.authority_seed(first_swap_info.key),
         token_program_info.clone(),
-        base_token_info.clone(),
-        admin_destination_info.clone(),
-        swap_authority_info.clone(),
-        token_swap.nonce,
-        admin_fee,
+        first_destination_info.clone(),
+        first_admin_destination_info.clone(),
+        first_authority_info.clone(),
+        first_swap.nonce,
+        first_result.admin_fee,
     )?;
-    token::burn(
+    // from second swap to user
+    token::transfer_as_swap(
+        second_swap.authority_seed(second_swap_info.key),
         token_program_info.clone(),
-        source_info.clone(),
-        pool_mint_info.clone(),
-        user_authority_info.clone(),
-        pool_token_amount,
+        second_destination_info.clone(),
+        destination_info.clone(),
+        second_authority_info.clone(),
+        second_swap.nonce,
+        amount_swapped,
+    )?;
+    // from second swap to fees
+    token::transfer_as_swap(
+        second_swap.authority_seed(second_swap_info.key),
+        token_program_info.clone(),
+        second_destination_info.clone(),
+        second_admin_destination_info.clone(),
+        second_authority_info.clone(),
+        second_swap.nonce,
+        second_result.admin_fee,
     )?;
 
-    if *base_token_info.key == token_swap.token_a.reserves {
-        log_event(
-            Event::WithdrawA,
-            clock.unix_timestamp,
-            token_amount,
-            0,
-            0,
-            dy_fee,
+    let first_reserve_amounts =
+        reserve_amounts(&first_swap, first_source_info, first_destination_info)?;
+    update_swap_info(first_swap_info, |swap_info_ref| {
+        record_swap_lp_fee(
+            swap_info_ref,
+            &first_swap,
+            first_destination_info.key,
+            &first_result,
         );
-    } else {
-        log_event(
-            Event::WithdrawB,
-            clock.unix_timestamp,
-            0,
-            token_amount,
-            0,
-            dy_fee,
+        swap_info_ref.set_reserve_amounts(first_reserve_amounts.0, first_reserve_amounts.1);
+    })?;
+    let second_reserve_amounts =
+        reserve_amounts(&second_swap, second_source_info, second_destination_info)?;
+    update_swap_info(second_swap_info, |swap_info_ref| {
+        record_swap_lp_fee(
+            swap_info_ref,
+            &second_swap,
+            second_destination_info.key,
+            &second_result,
         );
-    };
-    log_event(
-        Event::Burn,
+        swap_info_ref.set_reserve_amounts(second_reserve_amounts.0, second_reserve_amounts.1);
+    })?;
+
+    for pool_stats_info in trailing_infos
+        .iter()
+        .filter(|account| PoolStats::is_valid_len(account.data_len()))
+    {
+        let pool_stats_swap = PoolStats::unpack(&pool_stats_info.data.borrow())?.swap;
+        let (swap_key, token_swap, swap_source_key, hop_amount_in, result) =
+            if pool_stats_swap == *first_swap_info.key {
+                (
+                    first_swap_info.key,
+                    &first_swap,
+                    first_source_info.key,
+                    amount_in,
+                    &first_result,
+                )
+            } else {
+                (
+                    second_swap_info.key,
+                    &second_swap,
+                    second_source_info.key,
+                    first_result.amount_swapped,
+                    &second_result,
+                )
+            };
+        update_pool_stats(
+            program_id,
+            swap_key,
+            Some(pool_stats_info),
+            clock.slot,
+            |pool_stats| {
+                pool_stats.record_swap(
+                    *swap_source_key == token_swap.token_a.reserves,
+                    hop_amount_in,
+                    result.amount_swapped,
+                    result.fee,
+                    result.admin_fee,
+                )
+            },
+        )?;
+    }
+
+    log_swap_event(
+        &first_swap,
+        first_source_info.key,
         clock.unix_timestamp,
-        0,
-        0,
-        pool_token_amount,
-        0,
+        amount_in,
+        &first_result,
+    );
+    log_swap_event(
+        &second_swap,
+        second_source_info.key,
+        clock.unix_timestamp,
+        first_result.amount_swapped,
+        &second_result,
+    );
+    log_trade_admin_fee(
+        &first_swap,
+        first_destination_info.key,
+        clock.unix_timestamp,
+        first_result.admin_fee,
+    );
+    log_trade_admin_fee(
+        &second_swap,
+        second_destination_info.key,
+        clock.unix_timestamp,
+        second_result.admin_fee,
     );
+    SwapReturnData {
+        amount_out: amount_swapped,
+        fee: second_result.fee,
+        admin_fee: second_result.admin_fee,
+        price_impact_bps: compose_price_impact_bps(
+            first_result.price_impact_bps().unwrap_or(0),
+            second_result.price_impact_bps().unwrap_or(0),
+        ),
+    }
+    .set();
 
     Ok(())
 }
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use super::*;
-    use crate::{
-        instruction::{deposit, swap, withdraw, withdraw_one},
-        processor::test_utils::*,
-    };
-    use solana_program::program_error::ProgramError;
-    use solana_sdk::account::Account;
-    use spl_token::{
-        error::TokenError,
-        instruction::{set_authority, AuthorityType},
-    };
-
-    /// Initial amount of pool tokens for swap contract, hard-coded to something
-    /// "sensible" given a maximum of u64.
-    /// Note that on Ethereum, Uniswap uses the geometric mean of all provided
-    /// input amounts, and Balancer uses 100 * 10 ^ 18.
-    const INITIAL_SWAP_POOL_AMOUNT: u64 = 1_000_000_000;
+/// Accounts of a single pool of a SwapSplit
+#[derive(Clone, Copy)]
+struct SplitPoolAccounts<'a, 'b> {
+    swap_info: &'a AccountInfo<'b>,
+    swap_authority_info: &'a AccountInfo<'b>,
+    swap_source_info: &'a AccountInfo<'b>,
+    swap_destination_info: &'a AccountInfo<'b>,
+    admin_destination_info: &'a AccountInfo<'b>,
+}
 
-    #[test]
-    fn test_initialize() {
-        let user_key = pubkey_rand();
-        let amp_factor = MIN_AMP;
-        let token_a_amount = 1000;
-        let token_b_amount = 2000;
-        let pool_token_amount = 10;
-        let mut accounts = SwapAccountInfo::new(
-            &user_key,
-            amp_factor,
-            token_a_amount,
-            token_b_amount,
-            DEFAULT_TEST_FEES,
-        );
-        // wrong nonce for authority_key
-        {
-            let old_nonce = accounts.nonce;
-            accounts.nonce = old_nonce - 1;
-            assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.initialize_swap()
-            );
-            accounts.nonce = old_nonce;
-        }
-
-        // invalid amp factors
-        {
-            let old_initial_amp_factor = accounts.initial_amp_factor;
-            accounts.initial_amp_factor = MIN_AMP - 1;
-            // amp factor too low
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.initialize_swap()
-            );
-            accounts.initial_amp_factor = MAX_AMP + 1;
-            // amp factor too high
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.initialize_swap()
-            );
-            accounts.initial_amp_factor = old_initial_amp_factor;
-        }
-
-        // uninitialized token a account
+/// Processes a [SwapSplit](enum.Instruction.html).
+fn process_swap_split(
+    program_id: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    pool_count: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if amount_in == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let mut pools = Vec::with_capacity(pool_count.into());
+    for _ in 0..pool_count {
+        pools.push(SplitPoolAccounts {
+            swap_info: next_account_info(account_info_iter)?,
+            swap_authority_info: next_account_info(account_info_iter)?,
+            swap_source_info: next_account_info(account_info_iter)?,
+            swap_destination_info: next_account_info(account_info_iter)?,
+            admin_destination_info: next_account_info(account_info_iter)?,
+        });
+    }
+    let global_state_info = next_account_info(account_info_iter)?;
+    // Pool statistics and price oracles of any of the pools
+    let trailing_infos = account_info_iter.as_slice();
+
+    for (i, pool) in pools.iter().enumerate() {
+        if pools[..i]
+            .iter()
+            .any(|other| *other.swap_info.key == *pool.swap_info.key)
         {
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = Account::default();
-            assert_eq!(
-                Err(SwapError::ExpectedAccount.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
+            return Err(SwapError::InvalidInput.into());
         }
+    }
+    check_not_globally_paused(program_id, global_state_info)?;
+
+    // Every pool quotes the whole amount, the price impact of the quote tells its depth
+    let mut shortfalls = Vec::with_capacity(pools.len());
+    let mut pair = None;
+    for pool in pools.iter() {
+        let SplitPoolAccounts {
+            swap_info,
+            swap_authority_info,
+            swap_source_info,
+            swap_destination_info,
+            admin_destination_info,
+        } = *pool;
+        let token_swap = check_swap_accounts(
+            program_id,
+            swap_info,
+            swap_authority_info,
+            swap_source_info,
+            swap_destination_info,
+            admin_destination_info,
+            source_info.key,
+        )?;
+        let pool_pair = (
+            reserves_mint(&token_swap, swap_source_info.key),
+            reserves_mint(&token_swap, swap_destination_info.key),
+        );
+        let (source_mint, destination_mint) = *pair.get_or_insert(pool_pair);
+        check_keys_equal!(
+            source_mint,
+            pool_pair.0,
+            "Source mint",
+            SwapError::IncorrectMint
+        );
+        check_keys_equal!(
+            destination_mint,
+            pool_pair.1,
+            "Destination mint",
+            SwapError::IncorrectMint
+        );
+        let shortfall = price_swap(
+            &token_swap,
+            swap_source_info,
+            swap_destination_info,
+            amount_in,
+            &clock,
+        )
+        .ok()
+        .filter(|result| result.spot_amount_swapped > 0)
+        .map(|result| {
+            result
+                .spot_amount_swapped
+                .saturating_sub(result.amount_swapped.saturating_add(result.fee))
+        });
+        shortfalls.push(shortfall);
+    }
+    let amounts = split_by_depth(amount_in, &shortfalls).ok_or(SwapError::CalculationFailure)?;
 
-        // uninitialized token b account
-        {
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = Account::default();
-            assert_eq!(
-                Err(SwapError::ExpectedAccount.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
+    let mut total = SwapReturnData::default();
+    for (pool, &pool_amount_in) in pools.iter().zip(amounts.iter()) {
+        if pool_amount_in == 0 {
+            continue;
         }
-
-        // uninitialized pool mint
-        {
-            let old_account = accounts.pool_mint_account;
-            accounts.pool_mint_account = Account::default();
-            assert_eq!(
-                Err(SwapError::ExpectedMint.into()),
-                accounts.initialize_swap()
+        let SplitPoolAccounts {
+            swap_info,
+            swap_authority_info,
+            swap_source_info,
+            swap_destination_info,
+            admin_destination_info,
+        } = *pool;
+        let pool_stats_info = trailing_infos.iter().find(|account| {
+            PoolStats::is_valid_len(account.data_len())
+                && matches!(
+                    PoolStats::unpack(&account.data.borrow()),
+                    Ok(pool_stats) if pool_stats.swap == *swap_info.key
+                )
+        });
+        // The minimum amount out applies to the pools together
+        let (token_swap, result) = execute_swap(
+            program_id,
+            pool_amount_in,
+            0,
+            &SwapAccounts {
+                swap_info,
+                swap_authority_info,
+                user_authority_info,
+                user_authority_seeds: None,
+                source_info,
+                swap_source_info,
+                swap_destination_info,
+                destination_info,
+                admin_destination_info,
+                token_program_info,
+                pool_stats_info,
+                trailing_infos,
+            },
+            &clock,
+        )?;
+        let (reserve_amount_a, reserve_amount_b) =
+            reserve_amounts(&token_swap, swap_source_info, swap_destination_info)?;
+        update_swap_info(swap_info, |swap_info_ref| {
+            record_swap_lp_fee(
+                swap_info_ref,
+                &token_swap,
+                swap_destination_info.key,
+                &result,
             );
-            accounts.pool_mint_account = old_account;
-        }
+            swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+        })?;
+        total = add_swap_result(&total, &result)?;
+    }
+    if total.amount_out < minimum_amount_out {
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: minimum_amount_out,
+                actual: total.amount_out,
+            })
+            .into());
+    }
+    // Replaces the return data of the last pool
+    total.set();
+    Ok(())
+}
 
-        // token A account owner is not swap authority
-        {
-            let (_token_a_key, token_a_account) = mint_token(
-                &spl_token::id(),
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &user_key,
-                0,
-            );
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = token_a_account;
-            assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
-        }
+/// Validates the pool accounts of a single swap and computes its result.
+/// `source_key` is the account the input tokens are transferred from.
+#[allow(clippy::too_many_arguments)]
+fn quote_swap(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    swap_authority_info: &AccountInfo,
+    swap_source_info: &AccountInfo,
+    swap_destination_info: &AccountInfo,
+    admin_destination_info: &AccountInfo,
+    source_key: &Pubkey,
+    amount_in: u64,
+    clock: &Clock,
+) -> Result<(SwapInfo, SwapResult), ProgramError> {
+    let token_swap = check_swap_accounts(
+        program_id,
+        swap_info,
+        swap_authority_info,
+        swap_source_info,
+        swap_destination_info,
+        admin_destination_info,
+        source_key,
+    )?;
+    let result = price_swap(
+        &token_swap,
+        swap_source_info,
+        swap_destination_info,
+        amount_in,
+        clock,
+    )?;
+    let swap_destination_account =
+        utils::unpack_token_account(&swap_destination_info.data.borrow())?;
+    check_max_swap_size(&token_swap, swap_destination_account.amount, &result)?;
 
-        // token B account owner is not swap authority
-        {
-            let (_token_b_key, token_b_account) = mint_token(
-                &spl_token::id(),
-                &accounts.token_b_mint_key,
-                &mut accounts.token_b_mint_account,
-                &user_key,
-                &user_key,
-                0,
-            );
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_b_account;
-            assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
-        }
+    Ok((token_swap, result))
+}
 
-        // pool token account owner is swap authority
-        {
-            let (_pool_token_key, pool_token_account) = mint_token(
-                &spl_token::id(),
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.pool_token_account;
-            accounts.pool_token_account = pool_token_account;
-            assert_eq!(
-                Err(SwapError::InvalidOutputOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_token_account = old_account;
-        }
+/// Checks the pool accounts of a single swap, returning the swap.
+/// `source_key` is the account the input tokens are transferred from.
+fn check_swap_accounts(
+    program_id: &Pubkey,
+    swap_info: &AccountInfo,
+    swap_authority_info: &AccountInfo,
+    swap_source_info: &AccountInfo,
+    swap_destination_info: &AccountInfo,
+    admin_destination_info: &AccountInfo,
+    source_key: &Pubkey,
+) -> Result<SwapInfo, ProgramError> {
+    if *swap_source_info.key == *swap_destination_info.key {
+        return Err(SwapError::InvalidInput.into());
+    }
 
-        // pool mint authority is not swap authority
-        {
-            let (_pool_mint_key, pool_mint_account) =
-                create_mint(&spl_token::id(), &user_key, DEFAULT_TOKEN_DECIMALS, None);
-            let old_mint = accounts.pool_mint_account;
-            accounts.pool_mint_account = pool_mint_account;
-            assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_mint_account = old_mint;
-        }
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if token_swap.is_deprecated {
+        return Err(SwapError::PoolDeprecated.into());
+    }
 
-        // pool mint token has freeze authority
-        {
-            let (_pool_mint_key, pool_mint_account) = create_mint(
-                &spl_token::id(),
-                &accounts.authority_key,
-                DEFAULT_TOKEN_DECIMALS,
-                Some(&user_key),
-            );
-            let old_mint = accounts.pool_mint_account;
-            accounts.pool_mint_account = pool_mint_account;
-            assert_eq!(
-                Err(SwapError::InvalidFreezeAuthority.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_mint_account = old_mint;
-        }
+    check_swap_source(&token_swap, source_key)?;
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
 
-        // empty token A account
-        {
-            let (_token_a_key, token_a_account) = mint_token(
-                &spl_token::id(),
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = token_a_account;
-            assert_eq!(
-                Err(SwapError::EmptySupply.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
-        }
+    // Swap A to B, or B to A
+    let source_token = token_swap
+        .token_of_reserves(swap_source_info.key)
+        .ok_or(SwapError::IncorrectSwapAccount)?;
+    let destination_token = token_swap
+        .token(1 - source_token.index)
+        .ok_or(SwapError::IncorrectSwapAccount)?;
+    check_swap_token_destination_accounts(
+        destination_token,
+        swap_destination_info.key,
+        admin_destination_info.key,
+    )?;
 
-        // empty token B account
-        {
-            let (_token_b_key, token_b_account) = mint_token(
-                &spl_token::id(),
-                &accounts.token_b_mint_key,
-                &mut accounts.token_b_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_b_account;
-            assert_eq!(
-                Err(SwapError::EmptySupply.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
-        }
+    Ok(token_swap)
+}
 
-        // invalid pool tokens
-        {
-            let old_mint = accounts.pool_mint_account;
-            let old_pool_account = accounts.pool_token_account;
+/// Computes the result of swapping `amount_in` at the current reserves of a checked swap
+fn price_swap(
+    token_swap: &SwapInfo,
+    swap_source_info: &AccountInfo,
+    swap_destination_info: &AccountInfo,
+    amount_in: u64,
+    clock: &Clock,
+) -> Result<SwapResult, ProgramError> {
+    let swap_source_account = utils::unpack_token_account(&swap_source_info.data.borrow())?;
+    let swap_destination_account =
+        utils::unpack_token_account(&swap_destination_info.data.borrow())?;
 
-            let (_pool_mint_key, pool_mint_account) = create_mint(
-                &spl_token::id(),
-                &accounts.authority_key,
-                DEFAULT_TOKEN_DECIMALS,
-                None,
-            );
-            accounts.pool_mint_account = pool_mint_account;
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+    let result = invariant
+        .swap_to_with_rates_quick(
+            amount_in,
+            swap_source_account.amount,
+            swap_destination_account.amount,
+            token_swap.token_peg_rate(swap_source_info.key),
+            token_swap.token_peg_rate(swap_destination_info.key),
+            token_swap.quick_swap_max_bps.into(),
+            &fees,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    Ok(result)
+}
 
-            let (_empty_pool_token_key, empty_pool_token_account) = mint_token(
-                &spl_token::id(),
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &user_key,
-                0,
-            );
-
-            let (_pool_token_key, pool_token_account) = mint_token(
-                &spl_token::id(),
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &user_key,
-                pool_token_amount,
-            );
-
-            // non-empty pool token account
-            accounts.pool_token_account = pool_token_account;
-            assert_eq!(
-                Err(SwapError::InvalidSupply.into()),
-                accounts.initialize_swap()
-            );
-
-            // pool tokens already in circulation
-            accounts.pool_token_account = empty_pool_token_account;
-            assert_eq!(
-                Err(SwapError::InvalidSupply.into()),
-                accounts.initialize_swap()
-            );
-
-            accounts.pool_mint_account = old_mint;
-            accounts.pool_token_account = old_pool_account;
-        }
-
-        // token A account has close authority
-        {
-            do_process_instruction(
-                set_authority(
-                    &spl_token::id(),
-                    &accounts.token_a_key,
-                    Some(&user_key),
-                    AuthorityType::CloseAccount,
-                    &accounts.authority_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_a_account, &mut Account::default()],
-            )
-            .unwrap();
-            assert_eq!(
-                Err(SwapError::InvalidCloseAuthority.into()),
-                accounts.initialize_swap()
-            );
+/// Logs an instruction whose amounts are all zero as a no-op, or rejects it if the swap
+/// rejects zero amounts. The accounts other than the swap are not checked.
+fn process_noop(accounts: &[AccountInfo]) -> ProgramResult {
+    let reject_zero_amounts = accounts
+        .first()
+        .and_then(|swap_info| {
+            SwapInfoRef::new(&swap_info.data.borrow())
+                .ok()
+                .map(|token_swap| token_swap.reject_zero_amounts())
+        })
+        .unwrap_or(false);
+    if reject_zero_amounts {
+        return Err(SwapError::ZeroAmount.into());
+    }
+    let quiet_logs = accounts
+        .first()
+        .map(|swap_info| SwapInfo::peek_quiet_logs(&swap_info.data.borrow()))
+        .unwrap_or(false);
+    log_event(
+        quiet_logs,
+        Event::Noop,
+        Clock::get()?.unix_timestamp,
+        0,
+        0,
+        0,
+        0,
+    );
+    Ok(())
+}
 
-            do_process_instruction(
-                set_authority(
-                    &spl_token::id(),
-                    &accounts.token_a_key,
-                    None,
-                    AuthorityType::CloseAccount,
-                    &user_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_a_account, &mut Account::default()],
-            )
-            .unwrap();
-        }
+/// Checks that the swap source is not one of the swap's token accounts
+fn check_swap_source(token_swap: &SwapInfo, source_key: &Pubkey) -> ProgramResult {
+    check_token_keys_not_equal!(
+        token_swap.token_a,
+        *source_key,
+        token_swap.token_a.reserves,
+        "Source account cannot be one of swap's token accounts for token",
+        SwapError::InvalidInput
+    );
 
-        // token B account has close authority
-        {
-            do_process_instruction(
-                set_authority(
-                    &spl_token::id(),
-                    &accounts.token_b_key,
-                    Some(&user_key),
-                    AuthorityType::CloseAccount,
-                    &accounts.authority_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_b_account, &mut Account::default()],
-            )
-            .unwrap();
-            assert_eq!(
-                Err(SwapError::InvalidCloseAuthority.into()),
-                accounts.initialize_swap()
-            );
+    check_token_keys_not_equal!(
+        token_swap.token_b,
+        *source_key,
+        token_swap.token_b.reserves,
+        "Source account cannot be one of swap's token accounts for token",
+        SwapError::InvalidInput
+    );
 
-            do_process_instruction(
-                set_authority(
-                    &spl_token::id(),
-                    &accounts.token_b_key,
-                    None,
-                    AuthorityType::CloseAccount,
-                    &user_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_b_account, &mut Account::default()],
-            )
-            .unwrap();
-        }
+    Ok(())
+}
 
-        // mismatched admin mints
+/// Fees of a withdrawal, without the withdraw fee if the user authority is exempt,
+/// its LP position is past the withdraw fee grace period or the pool is deprecated
+fn withdraw_fees(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    fees: &Fees,
+    user_authority_key: &Pubkey,
+    fee_exemptions_info: Option<&AccountInfo>,
+    is_past_grace_period: bool,
+) -> Result<Fees, ProgramError> {
+    Ok(
+        if token_swap.is_deprecated
+            || is_past_grace_period
+            || is_fee_exempt(
+                program_id,
+                swap_key,
+                user_authority_key,
+                fee_exemptions_info,
+            )?
         {
-            let (wrong_admin_fee_key, wrong_admin_fee_account) = mint_token(
-                &spl_token::id(),
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &user_key,
-                0,
-            );
-
-            // wrong admin_fee_key_a
-            let old_admin_fee_account_a = accounts.admin_fee_a_account;
-            let old_admin_fee_key_a = accounts.admin_fee_a_key;
-            accounts.admin_fee_a_account = wrong_admin_fee_account.clone();
-            accounts.admin_fee_a_key = wrong_admin_fee_key;
-
-            assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                accounts.initialize_swap()
-            );
+            Fees {
+                withdraw_fee_numerator: 0,
+                ..*fees
+            }
+        } else {
+            *fees
+        },
+    )
+}
 
-            accounts.admin_fee_a_account = old_admin_fee_account_a;
-            accounts.admin_fee_a_key = old_admin_fee_key_a;
+/// Whether the LP position of the user authority, if one is given, has held its pool
+/// tokens for the withdraw fee grace period of the swap, so that withdrawing
+/// `pool_token_amount` of them is free of withdraw fees
+fn is_past_withdraw_fee_grace_period(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    user_authority_key: &Pubkey,
+    lp_position_info: Option<&AccountInfo>,
+    pool_token_amount: u64,
+    current_ts: i64,
+) -> Result<bool, ProgramError> {
+    let lp_position_info = match lp_position_info {
+        Some(lp_position_info) if token_swap.withdraw_fee_grace_period > 0 => lp_position_info,
+        _ => return Ok(false),
+    };
+    check_lp_position_account(program_id, swap_key, user_authority_key, lp_position_info)?;
+    let lp_position = LpPosition::unpack(&lp_position_info.data.borrow())?;
+    Ok(lp_position.is_past_withdraw_fee_grace_period(
+        token_swap.withdraw_fee_grace_period,
+        pool_token_amount,
+        current_ts,
+    ))
+}
 
-            // wrong admin_fee_key_b
-            let old_admin_fee_account_b = accounts.admin_fee_b_account;
-            let old_admin_fee_key_b = accounts.admin_fee_b_key;
-            accounts.admin_fee_b_account = wrong_admin_fee_account;
-            accounts.admin_fee_b_key = wrong_admin_fee_key;
+/// Records the activity of an instruction in the statistics account of the swap, if one is given
+fn update_pool_stats<F: FnOnce(&mut PoolStats)>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    pool_stats_info: Option<&AccountInfo>,
+    slot: u64,
+    update: F,
+) -> ProgramResult {
+    let pool_stats_info = match pool_stats_info {
+        Some(pool_stats_info) => pool_stats_info,
+        None => return Ok(()),
+    };
+    check_pool_stats_account(program_id, swap_key, pool_stats_info)?;
+    let mut pool_stats = PoolStats::unpack(&pool_stats_info.data.borrow())?;
+    update(&mut pool_stats);
+    pool_stats.last_activity_slot = slot;
+    PoolStats::pack(pool_stats, &mut pool_stats_info.data.borrow_mut())
+}
 
-            assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                accounts.initialize_swap()
-            );
+/// Records a swap in the last trade of a swap whose same-slot guard is enabled,
+/// rejecting it if it swaps back against a swap of the same user authority in the
+/// same slot
+fn record_last_trade(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    trader_key: &Pubkey,
+    a_to_b: bool,
+    slot: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if !token_swap.same_slot_guard {
+        return Ok(());
+    }
+    let (last_trade_key, _bump_seed) = find_last_trade_address(program_id, swap_key);
+    let last_trade_info =
+        find_account_by_key(accounts, &last_trade_key).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if last_trade_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut last_trade = LastTrade::unpack(&last_trade_info.data.borrow())?;
+    if last_trade.is_reversed_by(slot, trader_key, a_to_b) {
+        msg!("{} swapped the other way in slot {}", trader_key, slot);
+        return Err(SwapError::SameSlotReversal.into());
+    }
+    if !last_trade.record(slot, trader_key, a_to_b) {
+        msg!(
+            "{} traders already swapped in slot {}",
+            MAX_SLOT_TRADERS,
+            slot
+        );
+        return Err(SwapError::SlotTradersFull.into());
+    }
+    LastTrade::pack(last_trade, &mut last_trade_info.data.borrow_mut())
+}
 
-            accounts.admin_fee_b_account = old_admin_fee_account_b;
-            accounts.admin_fee_b_key = old_admin_fee_key_b;
-        }
+/// Writes the fields changed by an instruction into the swap account in place,
+/// instead of packing the whole [SwapInfo] back
+fn update_swap_info<F: FnOnce(&mut SwapInfoRefMut)>(
+    swap_info: &AccountInfo,
+    update: F,
+) -> ProgramResult {
+    let mut data = swap_info.data.borrow_mut();
+    update(&mut SwapInfoRefMut::new(&mut data)?);
+    Ok(())
+}
 
-        // mismatched mint decimals
-        {
-            let (bad_mint_key, mut bad_mint_account) =
-                create_mint(&spl_token::id(), &accounts.authority_key, 2, None);
+/// Balances of the token A and token B reserves of a swap, given in either order,
+/// read back once an instruction moved them
+fn reserve_amounts(
+    token_swap: &SwapInfo,
+    reserves_info: &AccountInfo,
+    other_reserves_info: &AccountInfo,
+) -> Result<(u64, u64), ProgramError> {
+    let amount = utils::unpack_token_account(&reserves_info.data.borrow())?.amount;
+    let other_amount = utils::unpack_token_account(&other_reserves_info.data.borrow())?.amount;
+    if *reserves_info.key == token_swap.token_a.reserves {
+        Ok((amount, other_amount))
+    } else {
+        Ok((other_amount, amount))
+    }
+}
 
-            // Pool mint decimal does not match
-            let old_pool_mint_key = accounts.pool_mint_key;
-            let old_pool_mint_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = bad_mint_key;
-            accounts.pool_mint_account = bad_mint_account.clone();
+/// Records the LP part of the fee of a swap into the fee growth of the swap
+fn record_swap_lp_fee(
+    swap_info_ref: &mut SwapInfoRefMut,
+    token_swap: &SwapInfo,
+    swap_destination_key: &Pubkey,
+    result: &SwapResult,
+) {
+    let lp_fee = result.fee.saturating_sub(result.admin_fee);
+    if *swap_destination_key == token_swap.token_a.reserves {
+        swap_info_ref.record_lp_fees(lp_fee, 0);
+    } else {
+        swap_info_ref.record_lp_fees(0, lp_fee);
+    }
+}
 
-            assert_eq!(
-                Err(SwapError::MismatchedDecimals.into()),
-                accounts.initialize_swap()
-            );
+/// Checkpoints the LP position of the user authority, if one is given, before
+/// its pool token amount is changed by `update`. Fee growth recorded afterwards
+/// is accrued at the new amount, and added pool tokens are dated `current_ts`.
+fn update_lp_position<F: FnOnce(u64) -> u64>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    token_swap: &SwapInfo,
+    user_authority_key: &Pubkey,
+    lp_position_info: Option<&AccountInfo>,
+    current_ts: i64,
+    update: F,
+) -> ProgramResult {
+    let lp_position_info = match lp_position_info {
+        Some(lp_position_info) => lp_position_info,
+        None => return Ok(()),
+    };
+    check_lp_position_account(program_id, swap_key, user_authority_key, lp_position_info)?;
+    let mut lp_position = LpPosition::unpack(&lp_position_info.data.borrow())?;
+    let pool_token_amount = update(lp_position.pool_token_amount);
+    lp_position.update_deposit_ts(pool_token_amount, current_ts);
+    lp_position.checkpoint(token_swap, pool_token_amount);
+    LpPosition::pack(lp_position, &mut lp_position_info.data.borrow_mut())
+}
 
-            accounts.pool_mint_key = old_pool_mint_key;
-            accounts.pool_mint_account = old_pool_mint_account;
+/// Returns the mint of the given reserves account of a swap
+fn reserves_mint(token_swap: &SwapInfo, reserves_key: &Pubkey) -> Pubkey {
+    if *reserves_key == token_swap.token_a.reserves {
+        token_swap.token_a.mint
+    } else {
+        token_swap.token_b.mint
+    }
+}
 
-            // Token a mint decimal does not match token b decimals
-            let (bad_token_key, bad_token_account) = mint_token(
-                &spl_token::id(),
-                &bad_mint_key,
-                &mut bad_mint_account,
-                &accounts.authority_key,
-                &accounts.authority_key,
-                10,
-            );
+/// Splits `amount_in` across pools in proportion to their depth, given the shortfall of
+/// a quote of the whole amount from the spot price of each pool. The shortfall grows with
+/// the square of the amount over the depth of the pool, so each part is inversely
+/// proportional to it. Pools without a quote get nothing and the rounding remainder goes
+/// to the deepest pool. `None` if no pool has a quote.
+fn split_by_depth(amount_in: u64, shortfalls: &[Option<u64>]) -> Option<Vec<u64>> {
+    let weights: Vec<u128> = shortfalls
+        .iter()
+        .map(|shortfall| shortfall.map_or(0, |shortfall| (u64::MAX / shortfall.max(1)).into()))
+        .collect();
+    let total_weight: u128 = weights.iter().sum();
+    if total_weight == 0 {
+        return None;
+    }
+    let mut amounts = weights
+        .iter()
+        .map(|weight| {
+            (u128::from(amount_in) * weight / total_weight)
+                .try_into()
+                .ok()
+        })
+        .collect::<Option<Vec<u64>>>()?;
+    let remainder = amount_in.checked_sub(amounts.iter().sum())?;
+    let (deepest, _weight) = weights
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, weight)| **weight)?;
+    amounts[deepest] = amounts[deepest].checked_add(remainder)?;
+    Some(amounts)
+}
 
-            let old_token_a_key = accounts.token_a_key;
-            let old_token_a_account = accounts.token_a_account;
-            let old_token_a_mint_key = accounts.token_a_mint_key;
-            let old_token_a_mint_account = accounts.token_a_mint_account;
-            accounts.token_a_key = bad_token_key;
-            accounts.token_a_account = bad_token_account;
-            accounts.token_a_mint_key = bad_mint_key;
-            accounts.token_a_mint_account = bad_mint_account;
+/// Price impact of two swaps done one after the other, in basis points
+fn compose_price_impact_bps(first_bps: u64, second_bps: u64) -> u64 {
+    let kept_bps = math::mul_div_floor(
+        BPS_DENOMINATOR.saturating_sub(first_bps),
+        BPS_DENOMINATOR.saturating_sub(second_bps),
+        BPS_DENOMINATOR,
+    )
+    .unwrap_or(0);
+    BPS_DENOMINATOR - kept_bps
+}
 
-            assert_eq!(
-                Err(SwapError::MismatchedDecimals.into()),
-                accounts.initialize_swap()
-            );
+/// Logs a swap event out of the given swap source reserves
+fn log_swap_event(
+    token_swap: &SwapInfo,
+    swap_source_key: &Pubkey,
+    ts: i64,
+    amount_in: u64,
+    result: &SwapResult,
+) {
+    let price_impact_bps = result.price_impact_bps().unwrap_or(0);
+    if *swap_source_key == token_swap.token_a.reserves {
+        log_event(
+            token_swap.quiet_logs,
+            Event::SwapAToB,
+            ts,
+            amount_in,
+            result.amount_swapped,
+            price_impact_bps,
+            result.fee,
+        );
+    } else {
+        log_event(
+            token_swap.quiet_logs,
+            Event::SwapBToA,
+            ts,
+            result.amount_swapped,
+            amount_in,
+            price_impact_bps,
+            result.fee,
+        );
+    };
+}
 
-            accounts.token_a_key = old_token_a_key;
-            accounts.token_a_account = old_token_a_account;
-            accounts.token_a_mint_key = old_token_a_mint_key;
-            accounts.token_a_mint_account = old_token_a_mint_account;
-        }
+/// Logs the admin fee of a swap, taken in the token of `swap_destination_key`
+fn log_trade_admin_fee(
+    token_swap: &SwapInfo,
+    swap_destination_key: &Pubkey,
+    ts: i64,
+    admin_fee: u64,
+) {
+    let (token_a_admin_fee, token_b_admin_fee) =
+        if *swap_destination_key == token_swap.token_a.reserves {
+            (admin_fee, 0)
+        } else {
+            (0, admin_fee)
+        };
+    log_admin_fees(
+        token_swap.quiet_logs,
+        Event::TradeAdminFees,
+        ts,
+        token_a_admin_fee,
+        token_b_admin_fee,
+    );
+}
 
-        // create swap with same token A and B
-        {
-            let (_token_a_repeat_key, token_a_repeat_account) = mint_token(
-                &spl_token::id(),
-                &accounts.token_a_mint_key,
+/// Processes an [Deposit](enum.Instruction.html).
+fn process_deposit(
+    program_id: &Pubkey,
+    token_a_amount: u64,
+    token_b_amount: u64,
+    min_mint_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if token_a_amount == 0 && token_b_amount == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let source_a_info = next_account_info(account_info_iter)?;
+    let source_b_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let dest_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let (optional_infos, staking_infos) =
+        split_staking_accounts(program_id, account_info_iter.as_slice());
+    let fee_exemptions_info = find_optional_account(optional_infos, FeeExemptions::LEN);
+    let pool_stats_info = find_pool_stats_account(optional_infos);
+    let lp_position_info = find_optional_account(optional_infos, LpPosition::LEN);
+    let deposit_allowlist_info = find_optional_account(optional_infos, DepositAllowlist::LEN);
+    let deposit_caps_info = find_optional_account(optional_infos, DepositCaps::LEN);
+    let native_sol_system_program_info = find_native_sol_system_program(optional_infos)?;
+
+    check_not_globally_paused(program_id, global_state_info)?;
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if token_swap.is_deprecated {
+        return Err(SwapError::PoolDeprecated.into());
+    }
+    let token_a_info = resolve_account(accounts, token_a_info, &token_swap.token_a.reserves);
+    let token_b_info = resolve_account(accounts, token_b_info, &token_swap.token_b.reserves);
+    let pool_mint_info = resolve_account(accounts, pool_mint_info, &token_swap.pool_mint);
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    check_deposit_allowed(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        user_authority_info.key,
+        deposit_allowlist_info,
+    )?;
+    check_cpi_allowed(program_id, swap_info.key, &token_swap, accounts)?;
+    let current_fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+    let fees = if is_fee_exempt(
+        program_id,
+        swap_info.key,
+        user_authority_info.key,
+        fee_exemptions_info,
+    )? {
+        // No trade fee on imbalanced deposits
+        Fees {
+            trade_fee_numerator: 0,
+            ..current_fees
+        }
+    } else {
+        current_fees
+    };
+
+    check_deposit_token_accounts(&token_swap.token_a, source_a_info.key, token_a_info.key)?;
+    check_deposit_token_accounts(&token_swap.token_b, source_b_info.key, token_b_info.key)?;
+
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Mint A",
+        SwapError::IncorrectMint
+    );
+
+    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
+    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let mint_amount = invariant
+        .mint_for_deposit_with_rates(
+            token_a_amount,
+            token_b_amount,
+            token_a.amount,
+            token_b.amount,
+            pool_mint.supply,
+            token_swap.token_peg_rate(token_a_info.key),
+            token_swap.token_peg_rate(token_b_info.key),
+            &fees,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    if mint_amount < min_mint_amount {
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: min_mint_amount,
+                actual: mint_amount,
+            })
+            .into());
+    }
+
+    create_user_token_account_if_missing(
+        optional_infos,
+        token_program_info,
+        dest_info,
+        user_authority_info,
+        Some(pool_mint_info),
+    )?;
+    let pool_token_supply = pool_mint
+        .supply
+        .checked_add(mint_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    check_deposit_caps(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        pool_token_supply,
+        dest_info,
+        mint_amount,
+        deposit_caps_info,
+    )?;
+
+    for (source_info, amount) in [
+        (source_a_info, token_a_amount),
+        (source_b_info, token_b_amount),
+    ] {
+        wrap_native_sol_if_needed(
+            native_sol_system_program_info,
+            token_program_info,
+            source_info,
+            user_authority_info,
+            amount,
+        )?;
+    }
+    // from user to swap
+    token::transfer_as_user(
+        token_program_info.clone(),
+        source_a_info.clone(),
+        token_a_info.clone(),
+        user_authority_info.clone(),
+        token_a_amount,
+    )?;
+    // from user to swap
+    token::transfer_as_user(
+        token_program_info.clone(),
+        source_b_info.clone(),
+        token_b_info.clone(),
+        user_authority_info.clone(),
+        token_b_amount,
+    )?;
+    // mint lp to user
+    token::mint_to(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        pool_mint_info.clone(),
+        dest_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        mint_amount,
+    )?;
+
+    update_lp_position(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        user_authority_info.key,
+        lp_position_info,
+        clock.unix_timestamp,
+        |amount| amount.saturating_add(mint_amount),
+    )?;
+    for source_info in [source_a_info, source_b_info] {
+        unwrap_native_sol_if_needed(
+            native_sol_system_program_info,
+            token_program_info,
+            source_info,
+            user_authority_info,
+        )?;
+    }
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(pool_token_supply);
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |_pool_stats| {},
+    )?;
+    stake_pool_tokens(
+        program_id,
+        swap_info.key,
+        staking_infos,
+        user_authority_info,
+        dest_info,
+        token_program_info,
+        mint_amount,
+    )?;
+
+    log_event(
+        token_swap.quiet_logs,
+        Event::Deposit,
+        clock.unix_timestamp,
+        token_a_amount,
+        token_b_amount,
+        mint_amount,
+        0,
+    );
+    DepositReturnData {
+        token_a_amount,
+        token_b_amount,
+        pool_token_amount: mint_amount,
+    }
+    .set();
+
+    Ok(())
+}
+
+/// Stakes the pool tokens minted by a deposit into the staking pool of the swap,
+/// if the staking accounts were passed. The user authority signs the staking.
+fn stake_pool_tokens<'a>(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    staking_infos: &[AccountInfo<'a>],
+    user_authority_info: &AccountInfo<'a>,
+    pool_token_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut staking_infos.iter();
+    let staking_config_info = match account_info_iter.next() {
+        Some(staking_config_info) => staking_config_info,
+        None => return Ok(()),
+    };
+    let staking_program_info = next_account_info(account_info_iter)?;
+    let staking_pool_info = next_account_info(account_info_iter)?;
+    let passed_on_infos = account_info_iter.as_slice();
+
+    check_staking_config_account(program_id, swap_key, staking_config_info)?;
+    let staking_config = StakingConfig::unpack(&staking_config_info.data.borrow())?;
+    check_keys_equal!(
+        *staking_program_info.key,
+        staking_config.program,
+        "Staking program",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *staking_pool_info.key,
+        staking_config.staking_pool,
+        "Staking pool",
+        SwapError::IncorrectSwapAccount
+    );
+
+    let mut data = Vec::with_capacity(16);
+    data.extend_from_slice(&staking_config.stake_instruction_prefix);
+    data.extend_from_slice(&amount.to_le_bytes());
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*user_authority_info.key, true),
+        AccountMeta::new(*pool_token_info.key, false),
+        AccountMeta::new(*staking_pool_info.key, false),
+        AccountMeta::new_readonly(*token_program_info.key, false),
+    ];
+    account_metas.extend(passed_on_infos.iter().map(|info| AccountMeta {
+        pubkey: *info.key,
+        is_signer: info.is_signer,
+        is_writable: info.is_writable,
+    }));
+    let mut account_infos = vec![
+        user_authority_info.clone(),
+        pool_token_info.clone(),
+        staking_pool_info.clone(),
+        token_program_info.clone(),
+        staking_program_info.clone(),
+    ];
+    account_infos.extend(passed_on_infos.iter().cloned());
+    invoke(
+        &Instruction {
+            program_id: staking_config.program,
+            accounts: account_metas,
+            data,
+        },
+        &account_infos,
+    )?;
+    msg!(
+        "Staked {} pool tokens into {}",
+        amount,
+        staking_config.staking_pool
+    );
+    Ok(())
+}
+
+/// Processes a [ZapIn](enum.Instruction.html).
+fn process_zap_in(
+    program_id: &Pubkey,
+    amount_in: u64,
+    input_is_a: bool,
+    min_mint_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if amount_in == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let dest_info = next_account_info(account_info_iter)?;
+    let admin_fee_dest_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+    let deposit_allowlist_info =
+        find_optional_account(account_info_iter.as_slice(), DepositAllowlist::LEN);
+    let deposit_caps_info = find_optional_account(account_info_iter.as_slice(), DepositCaps::LEN);
+
+    check_not_globally_paused(program_id, global_state_info)?;
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if token_swap.is_deprecated {
+        return Err(SwapError::PoolDeprecated.into());
+    }
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    check_deposit_allowed(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        user_authority_info.key,
+        deposit_allowlist_info,
+    )?;
+    check_cpi_allowed(program_id, swap_info.key, &token_swap, accounts)?;
+    let (input_token, output_token, input_reserves_info, output_reserves_info) = if input_is_a {
+        (
+            token_swap.token_a,
+            token_swap.token_b,
+            token_a_info,
+            token_b_info,
+        )
+    } else {
+        (
+            token_swap.token_b,
+            token_swap.token_a,
+            token_b_info,
+            token_a_info,
+        )
+    };
+    check_deposit_token_accounts(&input_token, source_info.key, input_reserves_info.key)?;
+    check_withdraw_token_accounts(
+        &output_token,
+        output_reserves_info.key,
+        admin_fee_dest_info.key,
+    )?;
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let input_reserves = utils::unpack_token_account(&input_reserves_info.data.borrow())?;
+    let output_reserves = utils::unpack_token_account(&output_reserves_info.data.borrow())?;
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+    let input_rate = token_swap.token_peg_rate(input_reserves_info.key);
+    let output_rate = token_swap.token_peg_rate(output_reserves_info.key);
+    let (swap_amount, result) = invariant
+        .zap_in_swap_with_rates(
+            amount_in,
+            input_reserves.amount,
+            output_reserves.amount,
+            input_rate,
+            output_rate,
+            &fees,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    // The rest of the input and the swapped tokens are deposited into the swapped reserves
+    let deposit_input_amount = amount_in
+        .checked_sub(swap_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    let (deposit_a, deposit_b, swap_amount_a, swap_amount_b, rate_a, rate_b) = if input_is_a {
+        (
+            deposit_input_amount,
+            result.amount_swapped,
+            result.new_source_amount,
+            result.new_destination_amount,
+            input_rate,
+            output_rate,
+        )
+    } else {
+        (
+            result.amount_swapped,
+            deposit_input_amount,
+            result.new_destination_amount,
+            result.new_source_amount,
+            output_rate,
+            input_rate,
+        )
+    };
+    let mint_amount = invariant
+        .mint_for_deposit_with_rates(
+            deposit_a,
+            deposit_b,
+            swap_amount_a,
+            swap_amount_b,
+            pool_mint.supply,
+            rate_a,
+            rate_b,
+            &fees,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    if mint_amount < min_mint_amount {
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: min_mint_amount,
+                actual: mint_amount,
+            })
+            .into());
+    }
+    let pool_token_supply = pool_mint
+        .supply
+        .checked_add(mint_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    check_deposit_caps(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        pool_token_supply,
+        dest_info,
+        mint_amount,
+        deposit_caps_info,
+    )?;
+
+    // from user to swap
+    token::transfer_as_user(
+        token_program_info.clone(),
+        source_info.clone(),
+        input_reserves_info.clone(),
+        user_authority_info.clone(),
+        amount_in,
+    )?;
+    // from swap to fees
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        output_reserves_info.clone(),
+        admin_fee_dest_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        result.admin_fee,
+    )?;
+    // mint lp to user
+    token::mint_to(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        pool_mint_info.clone(),
+        dest_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        mint_amount,
+    )?;
+
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        record_swap_lp_fee(
+            swap_info_ref,
+            &token_swap,
+            output_reserves_info.key,
+            &result,
+        );
+        swap_info_ref.set_pool_token_supply(pool_token_supply);
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |pool_stats| {
+            pool_stats.record_swap(
+                input_is_a,
+                swap_amount,
+                result.amount_swapped,
+                result.fee,
+                result.admin_fee,
+            )
+        },
+    )?;
+
+    log_swap_event(
+        &token_swap,
+        input_reserves_info.key,
+        clock.unix_timestamp,
+        swap_amount,
+        &result,
+    );
+    log_trade_admin_fee(
+        &token_swap,
+        output_reserves_info.key,
+        clock.unix_timestamp,
+        result.admin_fee,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::Deposit,
+        clock.unix_timestamp,
+        deposit_a,
+        deposit_b,
+        mint_amount,
+        0,
+    );
+    DepositReturnData {
+        token_a_amount: deposit_a,
+        token_b_amount: deposit_b,
+        pool_token_amount: mint_amount,
+    }
+    .set();
+
+    Ok(())
+}
+
+struct WithdrawContext<'a, 'b: 'a> {
+    token_swap: SwapInfo,
+    token_program_info: &'a AccountInfo<'b>,
+    swap_authority_info: &'a AccountInfo<'b>,
+    swap_info: &'a AccountInfo<'b>,
+}
+
+/// Accounts the [TransferIntent]s of a withdrawal move tokens between
+struct WithdrawAccounts<'a, 'b: 'a> {
+    reserves: [&'a AccountInfo<'b>; 2],
+    dest_tokens: [&'a AccountInfo<'b>; 2],
+    admin_fee_dests: Option<[&'a AccountInfo<'b>; 2]>,
+    admin_pool_token_info: Option<&'a AccountInfo<'b>>,
+    pool_mint_info: &'a AccountInfo<'b>,
+    source_info: &'a AccountInfo<'b>,
+    user_authority_info: &'a AccountInfo<'b>,
+}
+
+fn execute_transfer_intents<'a, 'b: 'a>(
+    ctx: &WithdrawContext<'a, 'b>,
+    accounts: &WithdrawAccounts<'a, 'b>,
+    intents: &[TransferIntent],
+) -> ProgramResult {
+    let index = |token: PoolToken| match token {
+        PoolToken::A => 0,
+        PoolToken::B => 1,
+    };
+    for intent in intents {
+        match *intent {
+            TransferIntent::ReservesToUser { token, amount } => token::transfer_as_swap(
+                ctx.token_swap.authority_seed(ctx.swap_info.key),
+                ctx.token_program_info.clone(),
+                accounts.reserves[index(token)].clone(),
+                accounts.dest_tokens[index(token)].clone(),
+                ctx.swap_authority_info.clone(),
+                ctx.token_swap.nonce,
+                amount,
+            )?,
+            TransferIntent::ReservesToAdminFees { token, amount } => {
+                let admin_fee_dests = accounts.admin_fee_dests.ok_or(SwapError::InvalidAdmin)?;
+                token::transfer_as_swap(
+                    ctx.token_swap.authority_seed(ctx.swap_info.key),
+                    ctx.token_program_info.clone(),
+                    accounts.reserves[index(token)].clone(),
+                    admin_fee_dests[index(token)].clone(),
+                    ctx.swap_authority_info.clone(),
+                    ctx.token_swap.nonce,
+                    amount,
+                )?
+            }
+            TransferIntent::BurnPoolTokens { amount } => token::burn(
+                ctx.token_program_info.clone(),
+                accounts.source_info.clone(),
+                accounts.pool_mint_info.clone(),
+                accounts.user_authority_info.clone(),
+                amount,
+            )?,
+            TransferIntent::MintPoolTokensToAdmin { amount } => token::mint_to(
+                ctx.token_swap.authority_seed(ctx.swap_info.key),
+                ctx.token_program_info.clone(),
+                accounts.pool_mint_info.clone(),
+                accounts
+                    .admin_pool_token_info
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?
+                    .clone(),
+                ctx.swap_authority_info.clone(),
+                ctx.token_swap.nonce,
+                amount,
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Processes an [Withdraw](enum.Instruction.html).
+fn process_donate_to_reserves(
+    program_id: &Pubkey,
+    token_a_amount: u64,
+    token_b_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if token_a_amount == 0 && token_b_amount == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let donor_info = next_account_info(account_info_iter)?;
+    let source_a_info = next_account_info(account_info_iter)?;
+    let source_b_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+
+    check_not_globally_paused(program_id, global_state_info)?;
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if token_swap.donations_admin_only {
+        check_has_admin_signer(&token_swap.admin_key, donor_info)?;
+    }
+    check_deposit_token_accounts(&token_swap.token_a, source_a_info.key, token_a_info.key)?;
+    check_deposit_token_accounts(&token_swap.token_b, source_b_info.key, token_b_info.key)?;
+
+    if token_a_amount > 0 {
+        token::transfer_as_user(
+            token_program_info.clone(),
+            source_a_info.clone(),
+            token_a_info.clone(),
+            donor_info.clone(),
+            token_a_amount,
+        )?;
+    }
+    if token_b_amount > 0 {
+        token::transfer_as_user(
+            token_program_info.clone(),
+            source_b_info.clone(),
+            token_b_info.clone(),
+            donor_info.clone(),
+            token_b_amount,
+        )?;
+    }
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b)
+    })?;
+
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |_pool_stats| {},
+    )?;
+    log_event(
+        token_swap.quiet_logs,
+        Event::DonateToReserves,
+        clock.unix_timestamp,
+        token_a_amount,
+        token_b_amount,
+        0,
+        0,
+    );
+
+    Ok(())
+}
+
+/// Creates the associated token account of the user authority if it does not exist
+/// yet and the associated token account program is passed among the optional accounts.
+fn create_user_token_account_if_missing<'a>(
+    optional_infos: &[AccountInfo<'a>],
+    token_program_info: &AccountInfo<'a>,
+    token_account_info: &AccountInfo<'a>,
+    user_authority_info: &AccountInfo<'a>,
+    mint_info: Option<&AccountInfo<'a>>,
+) -> ProgramResult {
+    let associated_token_program_info =
+        match find_account_by_key(optional_infos, &associated_token::id()) {
+            Some(associated_token_program_info) => associated_token_program_info,
+            None => return Ok(()),
+        };
+    let system_program_info = find_account_by_key(optional_infos, &system_program::id())
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let mint_info = mint_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    token::create_associated_token_account_if_missing(
+        associated_token_program_info.clone(),
+        system_program_info.clone(),
+        token_program_info.clone(),
+        token_account_info.clone(),
+        user_authority_info.clone(),
+        mint_info.clone(),
+    )
+}
+
+/// System program among the optional accounts if native SOL is wrapped and unwrapped
+/// for the user, see [with_native_sol](crate::instruction::with_native_sol).
+fn find_native_sol_system_program<'a, 'b>(
+    optional_infos: &'a [AccountInfo<'b>],
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    if find_account_by_key(optional_infos, &native_mint::id()).is_none() {
+        return Ok(None);
+    }
+    find_account_by_key(optional_infos, &system_program::id())
+        .map(Some)
+        .ok_or(ProgramError::NotEnoughAccountKeys)
+}
+
+fn is_native_sol_account(token_account_info: &AccountInfo) -> Result<bool, ProgramError> {
+    Ok(utils::unpack_token_account(&token_account_info.data.borrow())?.mint == native_mint::id())
+}
+
+/// Funds a wrapped SOL token account of the user with `amount` lamports of the user
+/// authority. Token accounts of other mints are left as they are.
+fn wrap_native_sol_if_needed<'a>(
+    system_program_info: Option<&AccountInfo<'a>>,
+    token_program_info: &AccountInfo<'a>,
+    token_account_info: &AccountInfo<'a>,
+    user_authority_info: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let system_program_info = match system_program_info {
+        Some(system_program_info) => system_program_info,
+        None => return Ok(()),
+    };
+    if amount == 0 || !is_native_sol_account(token_account_info)? {
+        return Ok(());
+    }
+    token::wrap_native_sol_as_user(
+        system_program_info.clone(),
+        token_program_info.clone(),
+        token_account_info.clone(),
+        user_authority_info.clone(),
+        amount,
+    )
+}
+
+/// Closes a wrapped SOL token account of the user to the user authority, unwrapping its
+/// balance. Token accounts of other mints are left as they are.
+fn unwrap_native_sol_if_needed<'a>(
+    system_program_info: Option<&AccountInfo<'a>>,
+    token_program_info: &AccountInfo<'a>,
+    token_account_info: &AccountInfo<'a>,
+    user_authority_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    if system_program_info.is_none() || !is_native_sol_account(token_account_info)? {
+        return Ok(());
+    }
+    token::close_account_as_user(
+        token_program_info.clone(),
+        token_account_info.clone(),
+        user_authority_info.clone(),
+    )
+}
+
+fn process_withdraw(
+    program_id: &Pubkey,
+    pool_token_amount: u64,
+    minimum_token_a_amount: u64,
+    minimum_token_b_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if pool_token_amount == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let dest_token_a_info = next_account_info(account_info_iter)?;
+    let dest_token_b_info = next_account_info(account_info_iter)?;
+    let admin_fee_dest_a_info = next_account_info(account_info_iter)?;
+    let admin_fee_dest_b_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let fee_exemptions_info =
+        find_optional_account(account_info_iter.as_slice(), FeeExemptions::LEN);
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+    let lp_position_info = find_optional_account(account_info_iter.as_slice(), LpPosition::LEN);
+    let optional_infos = account_info_iter.as_slice();
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    let token_a_info = resolve_account(accounts, token_a_info, &token_swap.token_a.reserves);
+    let token_b_info = resolve_account(accounts, token_b_info, &token_swap.token_b.reserves);
+    let admin_fee_dest_a_info = resolve_account(
+        accounts,
+        admin_fee_dest_a_info,
+        &token_swap.token_a.admin_fees,
+    );
+    let admin_fee_dest_b_info = resolve_account(
+        accounts,
+        admin_fee_dest_b_info,
+        &token_swap.token_b.admin_fees,
+    );
+    let pool_mint_info = resolve_account(accounts, pool_mint_info, &token_swap.pool_mint);
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    let lp_owner_key = check_withdraw_authority(
+        user_authority_info.key,
+        source_info,
+        &[dest_token_a_info, dest_token_b_info],
+    )?;
+    let current_fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+    let is_past_grace_period = is_past_withdraw_fee_grace_period(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        &lp_owner_key,
+        lp_position_info,
+        pool_token_amount,
+        clock.unix_timestamp,
+    )?;
+    let fees = withdraw_fees(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        &current_fees,
+        &lp_owner_key,
+        fee_exemptions_info,
+        is_past_grace_period,
+    )?;
+
+    check_withdraw_token_accounts(
+        &token_swap.token_a,
+        token_a_info.key,
+        admin_fee_dest_a_info.key,
+    )?;
+
+    check_withdraw_token_accounts(
+        &token_swap.token_b,
+        token_b_info.key,
+        admin_fee_dest_b_info.key,
+    )?;
+
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
+    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
+    let pool = PoolBalances {
+        pool_token_supply: pool_mint.supply,
+        token_a: token_a.amount,
+        token_b: token_b.amount,
+    };
+    let mut withdrawal = transition::withdraw(
+        &pool,
+        &fees,
+        pool_token_amount,
+        minimum_token_a_amount,
+        minimum_token_b_amount,
+    )?;
+    let admin_pool_token_info = if token_swap.admin_fees_as_lp {
+        let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+        withdrawal = transition::admin_fees_as_lp(
+            withdrawal,
+            &pool,
+            invariant.as_ref(),
+            [
+                token_swap.token_peg_rate(token_a_info.key),
+                token_swap.token_peg_rate(token_b_info.key),
+            ],
+        )?;
+        Some(find_admin_pool_token_info(
+            &token_swap,
+            token_program_info,
+            admin_fee_dest_a_info,
+            optional_infos,
+        )?)
+    } else {
+        None
+    };
+    let [a_amount, b_amount] = withdrawal.amounts;
+    let [a_fee, b_fee] = withdrawal.fees;
+    let [a_admin_fee, b_admin_fee] = withdrawal.admin_fees;
+
+    let ctx = WithdrawContext {
+        token_swap,
+        token_program_info,
+        swap_authority_info,
+        swap_info,
+    };
+
+    create_user_token_account_if_missing(
+        optional_infos,
+        token_program_info,
+        dest_token_a_info,
+        user_authority_info,
+        find_account_by_key(optional_infos, &ctx.token_swap.token_a.mint),
+    )?;
+    create_user_token_account_if_missing(
+        optional_infos,
+        token_program_info,
+        dest_token_b_info,
+        user_authority_info,
+        find_account_by_key(optional_infos, &ctx.token_swap.token_b.mint),
+    )?;
+
+    execute_transfer_intents(
+        &ctx,
+        &WithdrawAccounts {
+            reserves: [token_a_info, token_b_info],
+            dest_tokens: [dest_token_a_info, dest_token_b_info],
+            admin_fee_dests: Some([admin_fee_dest_a_info, admin_fee_dest_b_info]),
+            admin_pool_token_info,
+            pool_mint_info,
+            source_info,
+            user_authority_info,
+        },
+        &withdrawal.intents,
+    )?;
+
+    let token_swap = ctx.token_swap;
+    update_lp_position(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        &lp_owner_key,
+        lp_position_info,
+        clock.unix_timestamp,
+        |amount| amount.saturating_sub(pool_token_amount),
+    )?;
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(withdrawal.pool_token_supply);
+        swap_info_ref.record_lp_fees(
+            a_fee.saturating_sub(a_admin_fee),
+            b_fee.saturating_sub(b_admin_fee),
+        );
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |pool_stats| {
+            pool_stats.record_fees(a_fee, b_fee);
+            pool_stats.record_withdraw_admin_fees(a_admin_fee, b_admin_fee);
+        },
+    )?;
+    log_event(
+        token_swap.quiet_logs,
+        Event::WithdrawA,
+        clock.unix_timestamp,
+        a_amount,
+        0,
+        0,
+        a_fee,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::WithdrawB,
+        clock.unix_timestamp,
+        0,
+        b_amount,
+        0,
+        b_fee,
+    );
+    log_admin_fees(
+        token_swap.quiet_logs,
+        Event::WithdrawAdminFees,
+        clock.unix_timestamp,
+        a_admin_fee,
+        b_admin_fee,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::Burn,
+        clock.unix_timestamp,
+        0,
+        0,
+        pool_token_amount,
+        0,
+    );
+    WithdrawReturnData {
+        token_a_amount: a_amount,
+        token_b_amount: b_amount,
+        token_a_fee: a_fee,
+        token_b_fee: b_fee,
+    }
+    .set();
+
+    Ok(())
+}
+
+/// Processes an [EmergencyWithdraw](enum.Instruction.html).
+fn process_emergency_withdraw(
+    program_id: &Pubkey,
+    pool_token_amount: u64,
+    minimum_token_a_amount: u64,
+    minimum_token_b_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if pool_token_amount == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let dest_token_a_info = next_account_info(account_info_iter)?;
+    let dest_token_b_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if !token_swap.is_paused {
+        return Err(SwapError::NotPaused.into());
+    }
+    let token_a_info = resolve_account(accounts, token_a_info, &token_swap.token_a.reserves);
+    let token_b_info = resolve_account(accounts, token_b_info, &token_swap.token_b.reserves);
+    let pool_mint_info = resolve_account(accounts, pool_mint_info, &token_swap.pool_mint);
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    check_withdraw_authority(
+        user_authority_info.key,
+        source_info,
+        &[dest_token_a_info, dest_token_b_info],
+    )?;
+    check_keys_equal!(
+        *token_a_info.key,
+        token_swap.token_a.reserves,
+        "Token A reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *token_b_info.key,
+        token_swap.token_b.reserves,
+        "Token B reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
+    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
+    let withdrawal = transition::emergency_withdraw(
+        &PoolBalances {
+            pool_token_supply: pool_mint.supply,
+            token_a: token_a.amount,
+            token_b: token_b.amount,
+        },
+        pool_token_amount,
+        minimum_token_a_amount,
+        minimum_token_b_amount,
+    )?;
+    let [a_amount, b_amount] = withdrawal.amounts;
+
+    let ctx = WithdrawContext {
+        token_swap,
+        token_program_info,
+        swap_authority_info,
+        swap_info,
+    };
+    execute_transfer_intents(
+        &ctx,
+        &WithdrawAccounts {
+            reserves: [token_a_info, token_b_info],
+            dest_tokens: [dest_token_a_info, dest_token_b_info],
+            admin_fee_dests: None,
+            admin_pool_token_info: None,
+            pool_mint_info,
+            source_info,
+            user_authority_info,
+        },
+        &withdrawal.intents,
+    )?;
+
+    let token_swap = ctx.token_swap;
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(withdrawal.pool_token_supply);
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    let clock = Clock::get()?;
+    log_event(
+        token_swap.quiet_logs,
+        Event::WithdrawA,
+        clock.unix_timestamp,
+        a_amount,
+        0,
+        0,
+        0,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::WithdrawB,
+        clock.unix_timestamp,
+        0,
+        b_amount,
+        0,
+        0,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::Burn,
+        clock.unix_timestamp,
+        0,
+        0,
+        pool_token_amount,
+        0,
+    );
+    WithdrawReturnData {
+        token_a_amount: a_amount,
+        token_b_amount: b_amount,
+        token_a_fee: 0,
+        token_b_fee: 0,
+    }
+    .set();
+
+    Ok(())
+}
+
+/// Processes a [ZapOut](enum.Instruction.html).
+fn process_zap_out(
+    program_id: &Pubkey,
+    pool_token_amount: u64,
+    want_token_a: bool,
+    minimum_amount_out: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if pool_token_amount == 0 {
+        return process_noop(accounts);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let dest_info = next_account_info(account_info_iter)?;
+    let admin_fee_dest_a_info = next_account_info(account_info_iter)?;
+    let admin_fee_dest_b_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+
+    // The unwanted token is swapped, so zapping out is paused like swaps
+    check_not_globally_paused(program_id, global_state_info)?;
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if token_swap.is_deprecated {
+        return Err(SwapError::PoolDeprecated.into());
+    }
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    check_cpi_allowed(program_id, swap_info.key, &token_swap, accounts)?;
+    check_withdraw_authority(user_authority_info.key, source_info, &[dest_info])?;
+    check_withdraw_token_accounts(
+        &token_swap.token_a,
+        token_a_info.key,
+        admin_fee_dest_a_info.key,
+    )?;
+    check_withdraw_token_accounts(
+        &token_swap.token_b,
+        token_b_info.key,
+        admin_fee_dest_b_info.key,
+    )?;
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    if pool_mint.supply == 0 {
+        return Err(SwapError::EmptyPool.into());
+    }
+    let token_a = utils::unpack_token_account(&token_a_info.data.borrow())?;
+    let token_b = utils::unpack_token_account(&token_b_info.data.borrow())?;
+    let fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+
+    let converter = PoolTokenConverter {
+        supply: pool_mint.supply,
+        token_a: token_a.amount,
+        token_b: token_b.amount,
+        fees: &fees,
+    };
+    let (a_amount, a_fee, a_admin_fee) = converter
+        .token_a_rate(pool_token_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    let (b_amount, b_fee, b_admin_fee) = converter
+        .token_b_rate(pool_token_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    // Reserves after the withdrawal, the LP part of the withdraw fee stays in the pool
+    let reserves_a = token_a
+        .amount
+        .checked_sub(a_amount)
+        .and_then(|amount| amount.checked_sub(a_admin_fee))
+        .ok_or(SwapError::CalculationFailure)?;
+    let reserves_b = token_b
+        .amount
+        .checked_sub(b_amount)
+        .and_then(|amount| amount.checked_sub(b_admin_fee))
+        .ok_or(SwapError::CalculationFailure)?;
+
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let (wanted, unwanted) = if want_token_a {
+        (
+            (
+                a_amount,
+                a_fee,
+                a_admin_fee,
+                token_a_info,
+                admin_fee_dest_a_info,
+            ),
+            (b_amount, b_admin_fee, token_b_info, admin_fee_dest_b_info),
+        )
+    } else {
+        (
+            (
+                b_amount,
+                b_fee,
+                b_admin_fee,
+                token_b_info,
+                admin_fee_dest_b_info,
+            ),
+            (a_amount, a_admin_fee, token_a_info, admin_fee_dest_a_info),
+        )
+    };
+    let (
+        wanted_amount,
+        wanted_withdraw_fee,
+        wanted_admin_fee,
+        wanted_reserves_info,
+        wanted_admin_fee_dest_info,
+    ) = wanted;
+    let (unwanted_amount, unwanted_admin_fee, unwanted_reserves_info, unwanted_admin_fee_dest_info) =
+        unwanted;
+    let (wanted_reserves, unwanted_reserves) = if want_token_a {
+        (reserves_a, reserves_b)
+    } else {
+        (reserves_b, reserves_a)
+    };
+    let result = invariant
+        .swap_to_with_rates(
+            unwanted_amount,
+            unwanted_reserves,
+            wanted_reserves,
+            token_swap.token_peg_rate(unwanted_reserves_info.key),
+            token_swap.token_peg_rate(wanted_reserves_info.key),
+            &fees,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    let amount_out = wanted_amount
+        .checked_add(result.amount_swapped)
+        .ok_or(SwapError::CalculationFailure)?;
+    if amount_out < minimum_amount_out {
+        return Err(SwapError::ExceededSlippage
+            .with_context(ErrorContext::Amounts {
+                expected: minimum_amount_out,
+                actual: amount_out,
+            })
+            .into());
+    }
+    let admin_fee = wanted_admin_fee
+        .checked_add(result.admin_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+
+    // from swap to user, the unwanted tokens stay in the reserves
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        wanted_reserves_info.clone(),
+        dest_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        amount_out,
+    )?;
+    // from swap to fees
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        wanted_reserves_info.clone(),
+        wanted_admin_fee_dest_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        admin_fee,
+    )?;
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        unwanted_reserves_info.clone(),
+        unwanted_admin_fee_dest_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        unwanted_admin_fee,
+    )?;
+    // burn LP tokens withdrawn
+    token::burn(
+        token_program_info.clone(),
+        source_info.clone(),
+        pool_mint_info.clone(),
+        user_authority_info.clone(),
+        pool_token_amount,
+    )?;
+
+    let pool_token_supply = pool_mint
+        .supply
+        .checked_sub(pool_token_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(pool_token_supply);
+        swap_info_ref.record_lp_fees(
+            a_fee.saturating_sub(a_admin_fee),
+            b_fee.saturating_sub(b_admin_fee),
+        );
+        record_swap_lp_fee(
+            swap_info_ref,
+            &token_swap,
+            wanted_reserves_info.key,
+            &result,
+        );
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |pool_stats| {
+            pool_stats.record_fees(a_fee, b_fee);
+            pool_stats.record_withdraw_admin_fees(a_admin_fee, b_admin_fee);
+            pool_stats.record_swap(
+                !want_token_a,
+                unwanted_amount,
+                result.amount_swapped,
+                result.fee,
+                result.admin_fee,
+            );
+        },
+    )?;
+
+    log_event(
+        token_swap.quiet_logs,
+        Event::WithdrawA,
+        clock.unix_timestamp,
+        a_amount,
+        0,
+        0,
+        a_fee,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::WithdrawB,
+        clock.unix_timestamp,
+        0,
+        b_amount,
+        0,
+        b_fee,
+    );
+    log_swap_event(
+        &token_swap,
+        unwanted_reserves_info.key,
+        clock.unix_timestamp,
+        unwanted_amount,
+        &result,
+    );
+    log_admin_fees(
+        token_swap.quiet_logs,
+        Event::WithdrawAdminFees,
+        clock.unix_timestamp,
+        a_admin_fee,
+        b_admin_fee,
+    );
+    log_trade_admin_fee(
+        &token_swap,
+        wanted_reserves_info.key,
+        clock.unix_timestamp,
+        result.admin_fee,
+    );
+    log_event(
+        token_swap.quiet_logs,
+        Event::Burn,
+        clock.unix_timestamp,
+        0,
+        0,
+        pool_token_amount,
+        0,
+    );
+    // Fees are reported in the wanted token, as for a WithdrawOne
+    SwapReturnData {
+        amount_out,
+        fee: wanted_withdraw_fee
+            .checked_add(result.fee)
+            .ok_or(SwapError::CalculationFailure)?,
+        admin_fee,
+        price_impact_bps: result.price_impact_bps().unwrap_or(0),
+    }
+    .set();
+
+    Ok(())
+}
+
+/// Amounts of a withdrawal of one token
+enum WithdrawOneAmounts {
+    /// Burn exactly `pool_token_amount` for at least `minimum_token_amount`
+    ExactIn {
+        pool_token_amount: u64,
+        minimum_token_amount: u64,
+    },
+    /// Receive exactly `token_amount` by burning at most `maximum_burn_amount`
+    ExactOut {
+        token_amount: u64,
+        maximum_burn_amount: u64,
+    },
+}
+
+/// Processes an [WithdrawOne](enum.Instruction.html) or a
+/// [WithdrawOneExactOut](enum.Instruction.html).
+fn process_withdraw_one(
+    program_id: &Pubkey,
+    amounts: WithdrawOneAmounts,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if let WithdrawOneAmounts::ExactIn {
+        pool_token_amount: 0,
+        ..
+    }
+    | WithdrawOneAmounts::ExactOut {
+        token_amount: 0, ..
+    } = amounts
+    {
+        return process_noop(accounts);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let base_token_info = next_account_info(account_info_iter)?;
+    let quote_token_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let admin_destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let fee_exemptions_info =
+        find_optional_account(account_info_iter.as_slice(), FeeExemptions::LEN);
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+    let lp_position_info = find_optional_account(account_info_iter.as_slice(), LpPosition::LEN);
+
+    if *base_token_info.key == *quote_token_info.key {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    // The proportional Withdraw stays open while paused, WithdrawOne only if the
+    // admin allows it, at doubled fees
+    if token_swap.is_paused && !token_swap.withdraw_one_while_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    check_cpi_allowed(program_id, swap_info.key, &token_swap, accounts)?;
+    let lp_owner_key =
+        check_withdraw_authority(user_authority_info.key, source_info, &[destination_info])?;
+    let current_fees = token_swap
+        .current_fees(clock.unix_timestamp)
+        .ok_or(SwapError::CalculationFailure)?;
+    // The burn of exact out withdrawals is at most their maximum burn amount
+    let is_past_grace_period = is_past_withdraw_fee_grace_period(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        &lp_owner_key,
+        lp_position_info,
+        match amounts {
+            WithdrawOneAmounts::ExactIn {
+                pool_token_amount, ..
+            } => pool_token_amount,
+            WithdrawOneAmounts::ExactOut {
+                maximum_burn_amount,
+                ..
+            } => maximum_burn_amount,
+        },
+        clock.unix_timestamp,
+    )?;
+    let fees = withdraw_fees(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        &current_fees,
+        &lp_owner_key,
+        fee_exemptions_info,
+        is_past_grace_period,
+    )?;
+    let fees = if token_swap.is_paused {
+        fees.doubled()
+    } else {
+        fees
+    };
+
+    if *base_token_info.key == token_swap.token_a.reserves {
+        check_keys_equal!(
+            *quote_token_info.key,
+            token_swap.token_b.reserves,
+            "Swap A -> B reserves",
+            SwapError::IncorrectSwapAccount
+        );
+        check_keys_equal!(
+            *admin_destination_info.key,
+            token_swap.token_a.admin_fees,
+            "Swap A -> B admin fee destination",
+            SwapError::InvalidAdmin
+        );
+    } else if *base_token_info.key == token_swap.token_b.reserves {
+        check_keys_equal!(
+            *quote_token_info.key,
+            token_swap.token_a.reserves,
+            "Swap B -> A reserves",
+            SwapError::IncorrectSwapAccount
+        );
+        check_keys_equal!(
+            *admin_destination_info.key,
+            token_swap.token_b.admin_fees,
+            "Swap B -> A admin fee destination",
+            SwapError::InvalidAdmin
+        );
+    } else {
+        msg!("Unknown base token:");
+        base_token_info.key.log();
+        return Err(SwapError::IncorrectSwapAccount.into());
+    }
+
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    let base_token = utils::unpack_token_account(&base_token_info.data.borrow())?;
+    let quote_token = utils::unpack_token_account(&quote_token_info.data.borrow())?;
+
+    let invariant = token_swap.curve_calculator(clock.unix_timestamp);
+    let base_rate = token_swap.token_peg_rate(base_token_info.key);
+    let quote_rate = token_swap.token_peg_rate(quote_token_info.key);
+    let pool_token_amount = match amounts {
+        WithdrawOneAmounts::ExactIn {
+            pool_token_amount, ..
+        } => pool_token_amount,
+        WithdrawOneAmounts::ExactOut {
+            token_amount,
+            maximum_burn_amount,
+        } => {
+            let pool_token_amount = invariant
+                .withdraw_one_exact_out_with_rates(
+                    token_amount,
+                    pool_mint.supply,
+                    base_token.amount,
+                    quote_token.amount,
+                    base_rate,
+                    quote_rate,
+                    &fees,
+                )
+                .ok_or(SwapError::CalculationFailure)?;
+            if pool_token_amount > maximum_burn_amount {
+                return Err(SwapError::ExceededSlippage
+                    .with_context(ErrorContext::Amounts {
+                        expected: maximum_burn_amount,
+                        actual: pool_token_amount,
+                    })
+                    .into());
+            }
+            pool_token_amount
+        }
+    };
+    let (dy, dy_fee) = invariant
+        .withdraw_one_with_rates(
+            pool_token_amount,
+            pool_mint.supply,
+            base_token.amount,
+            quote_token.amount,
+            base_rate,
+            quote_rate,
+            &fees,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    let withdraw_fee = fees.withdraw_fee(dy).ok_or(SwapError::CalculationFailure)?;
+    let token_amount = match amounts {
+        WithdrawOneAmounts::ExactIn {
+            minimum_token_amount,
+            ..
+        } => {
+            let token_amount = dy
+                .checked_sub(withdraw_fee)
+                .ok_or(SwapError::CalculationFailure)?;
+            if token_amount < minimum_token_amount {
+                return Err(SwapError::ExceededSlippage
+                    .with_context(ErrorContext::Amounts {
+                        expected: minimum_token_amount,
+                        actual: token_amount,
+                    })
+                    .into());
+            }
+            token_amount
+        }
+        // Any rounding surplus of the burn stays in the pool
+        WithdrawOneAmounts::ExactOut { token_amount, .. } => token_amount,
+    };
+
+    let admin_trade_fee = fees
+        .admin_trade_fee(dy_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+    let admin_withdraw_fee = fees
+        .admin_withdraw_fee(withdraw_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+    let admin_fee = admin_trade_fee
+        .checked_add(admin_withdraw_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+    let fee = dy_fee
+        .checked_add(withdraw_fee)
+        .ok_or(SwapError::CalculationFailure)?;
+
+    // from swap to user
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        base_token_info.clone(),
+        destination_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        token_amount,
+    )?;
+    // from swap to fee
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        base_token_info.clone(),
+        admin_destination_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        admin_fee,
+    )?;
+    token::burn(
+        token_program_info.clone(),
+        source_info.clone(),
+        pool_mint_info.clone(),
+        user_authority_info.clone(),
+        pool_token_amount,
+    )?;
+
+    let is_token_a = *base_token_info.key == token_swap.token_a.reserves;
+    update_lp_position(
+        program_id,
+        swap_info.key,
+        &token_swap,
+        &lp_owner_key,
+        lp_position_info,
+        clock.unix_timestamp,
+        |amount| amount.saturating_sub(pool_token_amount),
+    )?;
+    let pool_token_supply = pool_mint
+        .supply
+        .checked_sub(pool_token_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    let lp_fee = fee.saturating_sub(admin_fee);
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, base_token_info, quote_token_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(pool_token_supply);
+        if is_token_a {
+            swap_info_ref.record_lp_fees(lp_fee, 0);
+        } else {
+            swap_info_ref.record_lp_fees(0, lp_fee);
+        }
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    update_pool_stats(
+        program_id,
+        swap_info.key,
+        pool_stats_info,
+        clock.slot,
+        |pool_stats| {
+            if is_token_a {
+                pool_stats.record_fees(fee, 0);
+                pool_stats.record_trade_admin_fees(admin_trade_fee, 0);
+                pool_stats.record_withdraw_admin_fees(admin_withdraw_fee, 0);
+            } else {
+                pool_stats.record_fees(0, fee);
+                pool_stats.record_trade_admin_fees(0, admin_trade_fee);
+                pool_stats.record_withdraw_admin_fees(0, admin_withdraw_fee);
+            }
+        },
+    )?;
+
+    if is_token_a {
+        log_event(
+            token_swap.quiet_logs,
+            Event::WithdrawA,
+            clock.unix_timestamp,
+            token_amount,
+            0,
+            0,
+            dy_fee,
+        );
+    } else {
+        log_event(
+            token_swap.quiet_logs,
+            Event::WithdrawB,
+            clock.unix_timestamp,
+            0,
+            token_amount,
+            0,
+            dy_fee,
+        );
+    };
+    for (event, admin_fee) in [
+        (Event::TradeAdminFees, admin_trade_fee),
+        (Event::WithdrawAdminFees, admin_withdraw_fee),
+    ] {
+        let (token_a_admin_fee, token_b_admin_fee) = if is_token_a {
+            (admin_fee, 0)
+        } else {
+            (0, admin_fee)
+        };
+        log_admin_fees(
+            token_swap.quiet_logs,
+            event,
+            clock.unix_timestamp,
+            token_a_admin_fee,
+            token_b_admin_fee,
+        );
+    }
+    log_event(
+        token_swap.quiet_logs,
+        Event::Burn,
+        clock.unix_timestamp,
+        0,
+        0,
+        pool_token_amount,
+        0,
+    );
+    SwapReturnData {
+        amount_out: token_amount,
+        fee,
+        admin_fee,
+        price_impact_bps: 0,
+    }
+    .set();
+
+    Ok(())
+}
+
+/// Processes a [SyncAmp](enum.Instruction.html).
+fn process_sync_amp(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    let mut data = swap_info.data.borrow_mut();
+    let mut token_swap = SwapInfoRefMut::new(&mut data)?;
+    if clock.unix_timestamp < token_swap.as_ref().stop_ramp_ts() {
+        return Err(SwapError::RampLocked.into());
+    }
+
+    let target_amp_factor = token_swap.as_ref().target_amp_factor();
+    token_swap.set_initial_amp_factor(target_amp_factor);
+    token_swap.set_ramp_ts(ZERO_TS, ZERO_TS);
+    msg!("Amp synced to {}", target_amp_factor);
+
+    Ok(())
+}
+
+/// Processes a [GetAmpFactor](enum.Instruction.html).
+fn process_get_amp_factor(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    // The swap is only read, so nothing else vouches for it
+    if swap_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    let amp_factor = StableSwap::new(
+        token_swap.initial_amp_factor,
+        token_swap.target_amp_factor,
+        clock.unix_timestamp,
+        token_swap.start_ramp_ts,
+        token_swap.stop_ramp_ts,
+    )
+    .compute_amp_factor()
+    .ok_or(SwapError::CalculationFailure)?;
+    log_detail!(token_swap.quiet_logs, "Amp factor {}", amp_factor);
+    AmpFactorReturnData {
+        amp_factor,
+        target_amp_factor: token_swap.target_amp_factor,
+    }
+    .set();
+
+    Ok(())
+}
+
+/// Processes a [SyncReserves](enum.Instruction.html).
+fn process_sync_reserves(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    // The legacy layout has no room for the reserve amounts
+    if swap_info.data_len() == SWAP_INFO_LEGACY_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    check_keys_equal!(
+        *token_a_info.key,
+        token_swap.token_a.reserves,
+        "Token A reserves",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *token_b_info.key,
+        token_swap.token_b.reserves,
+        "Token B reserves",
+        SwapError::IncorrectSwapAccount
+    );
+
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, token_a_info, token_b_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b)
+    })?;
+
+    // Only the swap authority moves tokens out of the reserves, and the instructions
+    // doing so record the amounts left, so the reserves can only have grown. Swaps
+    // passed the swap account read-only are the exception, their net is logged.
+    log_event(
+        token_swap.quiet_logs,
+        Event::ReservesSynced,
+        clock.unix_timestamp,
+        reserve_amount_a.saturating_sub(token_swap.reserve_amount_a),
+        reserve_amount_b.saturating_sub(token_swap.reserve_amount_b),
+        0,
+        0,
+    );
+    Ok(())
+}
+
+/// Processes a [RefreshPegRate](enum.Instruction.html).
+fn process_refresh_peg_rate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let peg_config_info = next_account_info(account_info_iter)?;
+    let rate_account_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+
+    // The legacy layout has no room for the peg rate
+    if SwapInfoRef::new(&swap_info.data.borrow())?.is_legacy() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let (peg_config_key, _bump_seed) = find_peg_config_address(program_id, swap_info.key);
+    check_keys_equal!(
+        peg_config_key,
+        *peg_config_info.key,
+        "Peg config",
+        SwapError::InvalidProgramAddress
+    );
+    if peg_config_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut peg_config = PegConfig::unpack(&peg_config_info.data.borrow())?;
+    if peg_config.rate_source == PegRateSource::None {
+        return Err(SwapError::InvalidInput.into());
+    }
+    check_keys_equal!(
+        peg_config.rate_account,
+        *rate_account_info.key,
+        "Rate account",
+        SwapError::InvalidOracle
+    );
+
+    let peg_rate = read_peg_rate(peg_config.rate_source, rate_account_info, &clock)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_peg_rate(peg_rate)
+    })?;
+    peg_config.rate_updated_slot = clock.slot;
+    PegConfig::pack(peg_config, &mut peg_config_info.data.borrow_mut())?;
+    msg!("Peg rate refreshed to {}", peg_rate);
+    Ok(())
+}
+
+/// Processes a [CreateOrder](enum.Instruction.html).
+fn process_create_order(
+    program_id: &Pubkey,
+    order_id: u64,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let escrow_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+
+    if !owner_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_not_globally_paused(program_id, global_state_info)?;
+    if amount_in == 0 {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    if token_swap.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if token_swap.is_deprecated {
+        return Err(SwapError::PoolDeprecated.into());
+    }
+
+    let (order_key, bump_seed) =
+        find_order_address(program_id, swap_info.key, owner_info.key, order_id);
+    check_keys_equal!(
+        order_key,
+        *order_info.key,
+        "Order",
+        SwapError::InvalidProgramAddress
+    );
+    let escrow = utils::unpack_token_account(&escrow_info.data.borrow())?;
+    check_keys_equal!(
+        escrow.owner,
+        order_key,
+        "Escrow owner",
+        SwapError::InvalidOwner
+    );
+    if escrow.amount != 0 {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let destination_mint = if escrow.mint == token_swap.token_a.mint {
+        token_swap.token_b.mint
+    } else if escrow.mint == token_swap.token_b.mint {
+        token_swap.token_a.mint
+    } else {
+        return Err(SwapError::IncorrectMint.into());
+    };
+    let destination = utils::unpack_token_account(&destination_info.data.borrow())?;
+    check_keys_equal!(
+        destination.mint,
+        destination_mint,
+        "Destination mint",
+        SwapError::IncorrectMint
+    );
+
+    let swap_bytes = swap_info.key.to_bytes();
+    let owner_bytes = owner_info.key.to_bytes();
+    let order_id_bytes = order_id.to_le_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[
+            ORDER_SEED,
+            &swap_bytes,
+            &owner_bytes,
+            &order_id_bytes,
+            &[bump_seed],
+        ],
+        Order::LEN,
+        order_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+    if Order::unpack_unchecked(&order_info.data.borrow())?.is_initialized {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    token::transfer_as_user(
+        token_program_info.clone(),
+        source_info.clone(),
+        escrow_info.clone(),
+        owner_info.clone(),
+        amount_in,
+    )?;
+    let order = Order {
+        is_initialized: true,
+        swap: *swap_info.key,
+        owner: *owner_info.key,
+        order_id,
+        bump_seed,
+        escrow: *escrow_info.key,
+        destination: *destination_info.key,
+        amount_in,
+        minimum_amount_out,
+    };
+    Order::pack(order, &mut order_info.data.borrow_mut())?;
+    msg!(
+        "Order {}: {} for at least {}",
+        order_id,
+        amount_in,
+        minimum_amount_out
+    );
+    Ok(())
+}
+
+/// Unpacks an order of the swap, checking its escrow and owner accounts
+fn unpack_order(
+    program_id: &Pubkey,
+    swap_key: &Pubkey,
+    order_info: &AccountInfo,
+    escrow_info: &AccountInfo,
+    owner_info: &AccountInfo,
+) -> Result<Order, ProgramError> {
+    if order_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let order = Order::unpack(&order_info.data.borrow())?;
+    check_order_keys(&order, swap_key, escrow_info, owner_info)?;
+    Ok(order)
+}
+
+/// Checks the swap, escrow and owner of an order
+fn check_order_keys(
+    order: &Order,
+    swap_key: &Pubkey,
+    escrow_info: &AccountInfo,
+    owner_info: &AccountInfo,
+) -> ProgramResult {
+    check_keys_equal!(order.swap, *swap_key, "Order swap", SwapError::InvalidInput);
+    check_keys_equal!(
+        order.escrow,
+        *escrow_info.key,
+        "Escrow",
+        SwapError::InvalidInput
+    );
+    check_keys_equal!(
+        order.owner,
+        *owner_info.key,
+        "Order owner",
+        SwapError::InvalidOwner
+    );
+    Ok(())
+}
+
+/// Processes a [FillOrder](enum.Instruction.html).
+fn process_fill_order(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let escrow_info = next_account_info(account_info_iter)?;
+    let swap_source_info = next_account_info(account_info_iter)?;
+    let swap_destination_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let admin_destination_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let global_state_info = next_account_info(account_info_iter)?;
+    let pool_stats_info = find_pool_stats_account(account_info_iter.as_slice());
+
+    check_not_globally_paused(program_id, global_state_info)?;
+    let order = unpack_order(
+        program_id,
+        swap_info.key,
+        order_info,
+        escrow_info,
+        owner_info,
+    )?;
+    check_keys_equal!(
+        order.destination,
+        *destination_info.key,
+        "Order destination",
+        SwapError::InvalidInput
+    );
+
+    let swap_bytes = swap_info.key.to_bytes();
+    let owner_bytes = order.owner.to_bytes();
+    let order_id_bytes = order.order_id.to_le_bytes();
+    let order_seeds: &[&[u8]] = &[
+        ORDER_SEED,
+        &swap_bytes,
+        &owner_bytes,
+        &order_id_bytes,
+        &[order.bump_seed],
+    ];
+    let (token_swap, result) = execute_swap(
+        program_id,
+        order.amount_in,
+        order.minimum_amount_out,
+        &SwapAccounts {
+            swap_info,
+            swap_authority_info,
+            user_authority_info: order_info,
+            user_authority_seeds: Some(order_seeds),
+            source_info: escrow_info,
+            swap_source_info,
+            swap_destination_info,
+            destination_info,
+            admin_destination_info,
+            token_program_info,
+            pool_stats_info,
+            trailing_infos: account_info_iter.as_slice(),
+        },
+        &clock,
+    )?;
+    let (reserve_amount_a, reserve_amount_b) =
+        reserve_amounts(&token_swap, swap_source_info, swap_destination_info)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        record_swap_lp_fee(
+            swap_info_ref,
+            &token_swap,
+            swap_destination_info.key,
+            &result,
+        );
+        swap_info_ref.set_reserve_amounts(reserve_amount_a, reserve_amount_b);
+    })?;
+
+    token::close_account_as_program_address(
+        order_seeds,
+        token_program_info.clone(),
+        escrow_info.clone(),
+        owner_info.clone(),
+        order_info.clone(),
+    )?;
+    utils::close_program_account(order_info, owner_info);
+    log_detail!(
+        token_swap.quiet_logs,
+        "Order {} filled: {} for {}",
+        order.order_id,
+        order.amount_in,
+        result.amount_swapped
+    );
+    Ok(())
+}
+
+/// Processes a [CancelOrder](enum.Instruction.html).
+fn process_cancel_order(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let escrow_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    if !owner_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let order = unpack_order(
+        program_id,
+        swap_info.key,
+        order_info,
+        escrow_info,
+        owner_info,
+    )?;
+
+    let swap_bytes = swap_info.key.to_bytes();
+    let owner_bytes = order.owner.to_bytes();
+    let order_id_bytes = order.order_id.to_le_bytes();
+    let order_seeds: &[&[u8]] = &[
+        ORDER_SEED,
+        &swap_bytes,
+        &owner_bytes,
+        &order_id_bytes,
+        &[order.bump_seed],
+    ];
+    let escrow = utils::unpack_token_account(&escrow_info.data.borrow())?;
+    token::transfer_as_program_address(
+        order_seeds,
+        token_program_info.clone(),
+        escrow_info.clone(),
+        destination_info.clone(),
+        order_info.clone(),
+        escrow.amount,
+    )?;
+    token::close_account_as_program_address(
+        order_seeds,
+        token_program_info.clone(),
+        escrow_info.clone(),
+        owner_info.clone(),
+        order_info.clone(),
+    )?;
+    utils::close_program_account(order_info, owner_info);
+    msg!("Order {} cancelled", order.order_id);
+    Ok(())
+}
+
+/// Processes a [SyncLpPosition](enum.Instruction.html).
+fn process_sync_lp_position(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let lp_position_info = next_account_info(account_info_iter)?;
+    let pool_token_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    let pool_token = utils::unpack_token_account(&pool_token_info.data.borrow())?;
+    check_keys_equal!(
+        pool_token.mint,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+    check_keys_equal!(
+        pool_token.owner,
+        *owner_info.key,
+        "Pool token owner",
+        SwapError::InvalidOwner
+    );
+
+    let (lp_position_key, bump_seed) =
+        find_lp_position_address(program_id, swap_info.key, owner_info.key);
+    check_keys_equal!(
+        lp_position_key,
+        *lp_position_info.key,
+        "LP position",
+        SwapError::InvalidProgramAddress
+    );
+    let swap_bytes = swap_info.key.to_bytes();
+    let owner_bytes = owner_info.key.to_bytes();
+    utils::create_program_address_account(
+        program_id,
+        &[
+            LP_POSITION_SEED,
+            &swap_bytes[..32],
+            &owner_bytes[..32],
+            &[bump_seed],
+        ],
+        LpPosition::LEN,
+        lp_position_info,
+        payer_info,
+        system_program_info,
+        rent_sysvar_info,
+    )?;
+
+    let mut lp_position = LpPosition::unpack_unchecked(&lp_position_info.data.borrow())?;
+    if !lp_position.is_initialized {
+        // Fees are earned from the first checkpoint on
+        lp_position = LpPosition {
+            is_initialized: true,
+            swap: *swap_info.key,
+            owner: *owner_info.key,
+            fee_growth_a_checkpoint: token_swap.fee_growth_a,
+            fee_growth_b_checkpoint: token_swap.fee_growth_b,
+            ..LpPosition::default()
+        };
+    }
+    lp_position.update_deposit_ts(pool_token.amount, Clock::get()?.unix_timestamp);
+    lp_position.checkpoint(&token_swap, pool_token.amount);
+    LpPosition::pack(lp_position, &mut lp_position_info.data.borrow_mut())?;
+    log_detail!(
+        token_swap.quiet_logs,
+        "LP position: {} pool tokens, earned {} A and {} B",
+        lp_position.pool_token_amount,
+        lp_position.fees_earned_a,
+        lp_position.fees_earned_b
+    );
+
+    Ok(())
+}
+
+/// Processes a [RedeemMigratedLp](enum.Instruction.html).
+fn process_redeem_migrated_lp(
+    program_id: &Pubkey,
+    pool_token_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let lp_escrow_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let pool_migration_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    check_withdraw_authority(user_authority_info.key, source_info, &[destination_info])?;
+    check_pool_migration_account(program_id, swap_info.key, pool_migration_info)?;
+    let pool_migration = PoolMigration::unpack(&pool_migration_info.data.borrow())?;
+    check_keys_equal!(
+        *lp_escrow_info.key,
+        pool_migration.lp_escrow,
+        "LP escrow",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_equal!(
+        *pool_mint_info.key,
+        token_swap.pool_mint,
+        "Pool mint",
+        SwapError::IncorrectMint
+    );
+
+    let pool_mint = utils::unpack_mint(&pool_mint_info.data.borrow())?;
+    let lp_escrow = utils::unpack_token_account(&lp_escrow_info.data.borrow())?;
+    // Pool tokens of the migrated swap are only burnt here, so each one is
+    // redeemed for the same share of the escrowed new pool tokens, rounded down
+    let redeem_amount = math::mul_div_floor(pool_token_amount, lp_escrow.amount, pool_mint.supply)
+        .ok_or(SwapError::CalculationFailure)?;
+
+    token::burn(
+        token_program_info.clone(),
+        source_info.clone(),
+        pool_mint_info.clone(),
+        user_authority_info.clone(),
+        pool_token_amount,
+    )?;
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        lp_escrow_info.clone(),
+        destination_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        redeem_amount,
+    )?;
+
+    let pool_token_supply = pool_mint
+        .supply
+        .checked_sub(pool_token_amount)
+        .ok_or(SwapError::CalculationFailure)?;
+    update_swap_info(swap_info, |swap_info_ref| {
+        swap_info_ref.set_pool_token_supply(pool_token_supply)
+    })?;
+
+    msg!(
+        "Redeemed {} pool tokens for {} new pool tokens",
+        pool_token_amount,
+        redeem_amount
+    );
+    Ok(())
+}
+
+/// Processes a [RecoverStrandedTokens](enum.Instruction.html).
+fn process_recover_stranded_tokens(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let stranded_info = next_account_info(account_info_iter)?;
+    let admin_fee_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    // A shared authority also owns the token accounts of other pools
+    if token_swap.shared_authority {
+        return Err(SwapError::SharedAuthority.into());
+    }
+    // The reserves are the only token accounts of the authority the swap accounts for
+    check_keys_not_equal!(
+        *stranded_info.key,
+        token_swap.token_a.reserves,
+        "Stranded account",
+        SwapError::IncorrectSwapAccount
+    );
+    check_keys_not_equal!(
+        *stranded_info.key,
+        token_swap.token_b.reserves,
+        "Stranded account",
+        SwapError::IncorrectSwapAccount
+    );
+
+    let stranded = utils::unpack_token_account(&stranded_info.data.borrow())?;
+    check_keys_equal!(
+        stranded.owner,
+        *swap_authority_info.key,
+        "Stranded account authority",
+        SwapError::InvalidOwner
+    );
+    let token = if stranded.mint == token_swap.token_a.mint {
+        &token_swap.token_a
+    } else if stranded.mint == token_swap.token_b.mint {
+        &token_swap.token_b
+    } else {
+        // Pool tokens and other mints have no admin fee account to go to
+        msg!("Stranded account mint: {}", stranded.mint);
+        return Err(SwapError::IncorrectMint.into());
+    };
+    check_keys_equal!(
+        *admin_fee_info.key,
+        token.admin_fees,
+        "Admin fee",
+        SwapError::InvalidAdmin
+    );
+
+    if stranded.amount == 0 {
+        // noop
+        return Ok(());
+    }
+    token::transfer_as_swap(
+        token_swap.authority_seed(swap_info.key),
+        token_program_info.clone(),
+        stranded_info.clone(),
+        admin_fee_info.clone(),
+        swap_authority_info.clone(),
+        token_swap.nonce,
+        stranded.amount,
+    )?;
+
+    log_detail!(
+        token_swap.quiet_logs,
+        "Recovered {} stranded tokens of {}",
+        stranded.amount,
+        stranded.mint
+    );
+    Ok(())
+}
+
+/// Processes a [HarvestFees](enum.Instruction.html).
+fn process_harvest_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info = next_account_info(account_info_iter)?;
+    let swap_authority_info = next_account_info(account_info_iter)?;
+    let admin_fee_a_info = next_account_info(account_info_iter)?;
+    let admin_fee_b_info = next_account_info(account_info_iter)?;
+    let rewards_distributor_info = next_account_info(account_info_iter)?;
+    let rewards_a_info = next_account_info(account_info_iter)?;
+    let rewards_b_info = next_account_info(account_info_iter)?;
+    let rewards_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let clock = utils::next_clock(account_info_iter)?;
+    let passed_on_infos = account_info_iter.as_slice();
+
+    let token_swap = SwapInfo::unpack(&swap_info.data.borrow())?;
+    check_swap_authority(
+        &token_swap,
+        swap_info.key,
+        program_id,
+        swap_authority_info.key,
+    )?;
+    // The rewards program is only notified of the harvest, never with the
+    // signature of the authority, so it gets no way to the reserves
+    for passed_on_info in passed_on_infos {
+        if *passed_on_info.key == token_swap.token_a.reserves
+            || *passed_on_info.key == token_swap.token_b.reserves
+        {
+            msg!("Reserves passed on to the rewards distributor program");
+            return Err(SwapError::IncorrectSwapAccount.into());
+        }
+    }
+    check_rewards_distributor_account(program_id, swap_info.key, rewards_distributor_info)?;
+    let rewards_distributor = RewardsDistributor::unpack(&rewards_distributor_info.data.borrow())?;
+    check_keys_equal!(
+        *rewards_program_info.key,
+        rewards_distributor.program,
+        "Rewards distributor program",
+        SwapError::IncorrectSwapAccount
+    );
+
+    let mut amounts = [0u64; 2];
+    for ((token, admin_fee_info, rewards_key, rewards_info), amount) in [
+        (
+            &token_swap.token_a,
+            admin_fee_a_info,
+            &rewards_distributor.rewards_a,
+            rewards_a_info,
+        ),
+        (
+            &token_swap.token_b,
+            admin_fee_b_info,
+            &rewards_distributor.rewards_b,
+            rewards_b_info,
+        ),
+    ]
+    .iter()
+    .zip(amounts.iter_mut())
+    {
+        check_keys_equal!(
+            *admin_fee_info.key,
+            token.admin_fees,
+            "Admin fee",
+            SwapError::InvalidAdmin
+        );
+        check_keys_equal!(
+            *rewards_info.key,
+            **rewards_key,
+            "Rewards",
+            SwapError::IncorrectSwapAccount
+        );
+        let admin_fee_account = utils::unpack_token_account(&admin_fee_info.data.borrow())?;
+        // Admin fees kept by the admin cannot be moved without their signature
+        check_keys_equal!(
+            admin_fee_account.owner,
+            *swap_authority_info.key,
+            "Admin fee account owner",
+            SwapError::InvalidOwner
+        );
+        *amount = admin_fee_account.amount;
+        if *amount > 0 {
+            token::transfer_as_swap(
+                token_swap.authority_seed(swap_info.key),
+                token_program_info.clone(),
+                (*admin_fee_info).clone(),
+                (*rewards_info).clone(),
+                swap_authority_info.clone(),
+                token_swap.nonce,
+                *amount,
+            )?;
+        }
+    }
+    let [token_a_amount, token_b_amount] = amounts;
+    if token_a_amount == 0 && token_b_amount == 0 {
+        // noop
+        return Ok(());
+    }
+
+    let mut data = Vec::with_capacity(16);
+    data.extend_from_slice(&token_a_amount.to_le_bytes());
+    data.extend_from_slice(&token_b_amount.to_le_bytes());
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*swap_authority_info.key, false),
+        AccountMeta::new(*rewards_a_info.key, false),
+        AccountMeta::new(*rewards_b_info.key, false),
+        AccountMeta::new_readonly(*token_program_info.key, false),
+    ];
+    account_metas.extend(passed_on_infos.iter().map(|info| AccountMeta {
+        pubkey: *info.key,
+        is_signer: info.is_signer,
+        is_writable: info.is_writable,
+    }));
+    let mut account_infos = vec![
+        swap_authority_info.clone(),
+        rewards_a_info.clone(),
+        rewards_b_info.clone(),
+        token_program_info.clone(),
+        rewards_program_info.clone(),
+    ];
+    account_infos.extend(passed_on_infos.iter().cloned());
+    invoke(
+        &Instruction {
+            program_id: rewards_distributor.program,
+            accounts: account_metas,
+            data,
+        },
+        &account_infos,
+    )?;
+
+    log_event(
+        token_swap.quiet_logs,
+        Event::HarvestFees,
+        clock.unix_timestamp,
+        token_a_amount,
+        token_b_amount,
+        0,
+        0,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        associated_token::find_associated_token_address,
+        curve::{INITIAL_SUPPLY_SCALE, MAX_TOKENS_IN, MIN_RAMP_DURATION, RAMP_DELAY},
+        instruction::{
+            deposit, omit_clock_sysvar, swap, withdraw, withdraw_one, MAX_SWAP_BATCH_FILLS,
+            MINIMUM_LIQUIDITY,
+        },
+        oracle::MAX_PRICE_AGE_SLOTS,
+        processor::test_utils::*,
+        return_data::SwapReturnData,
+        state::{
+            find_cpi_allowlist_address, find_deposit_allowlist_address,
+            find_fee_exemptions_address, find_pool_stats_address, find_shared_authority_address,
+            CpiAllowlist, FeeExemptions, GlobalState, PoolStats, SlotTrade, POOL_STATS_LEGACY_LEN,
+        },
+    };
+    use solana_program::{program::get_return_data, program_error::ProgramError};
+    use solana_sdk::account::Account;
+    use spl_token::{
+        error::TokenError,
+        instruction::{set_authority, AuthorityType},
+        state::{Account as SplAccount, Mint},
+    };
+
+    /// Initial amount of pool tokens for swap contract, hard-coded to something
+    /// "sensible" given a maximum of u64.
+    /// Note that on Ethereum, Uniswap uses the geometric mean of all provided
+    /// input amounts, and Balancer uses 100 * 10 ^ 18.
+    const INITIAL_SWAP_POOL_AMOUNT: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_initialize() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP;
+        let token_a_amount = 1000;
+        let token_b_amount = 2000;
+        let pool_token_amount = 10;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        // wrong nonce for authority_key
+        {
+            let old_nonce = accounts.nonce;
+            accounts.nonce = old_nonce - 1;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.initialize_swap()
+            );
+            accounts.nonce = old_nonce;
+        }
+
+        // invalid amp factors
+        {
+            let old_initial_amp_factor = accounts.initial_amp_factor;
+            accounts.initial_amp_factor = MIN_AMP - 1;
+            // amp factor too low
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.initialize_swap()
+            );
+            accounts.initial_amp_factor = MAX_AMP + 1;
+            // amp factor too high
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.initialize_swap()
+            );
+            accounts.initial_amp_factor = old_initial_amp_factor;
+        }
+
+        // invalid fees
+        {
+            let old_fees = accounts.fees;
+            accounts.fees.trade_fee_denominator = 0;
+            assert_eq!(
+                Err(SwapError::InvalidFees.into()),
+                accounts.initialize_swap()
+            );
+            accounts.fees = old_fees;
+            accounts.fees.admin_trade_fee_numerator = accounts.fees.admin_trade_fee_denominator + 1;
+            assert_eq!(
+                Err(SwapError::InvalidFees.into()),
+                accounts.initialize_swap()
+            );
+            accounts.fees = old_fees;
+            // admin share over the maximum
+            #[cfg(not(feature = "uncapped-admin-fee"))]
+            {
+                accounts.fees.admin_withdraw_fee_numerator =
+                    crate::fees::MAX_ADMIN_FEE_SHARE_BPS + 1;
+                accounts.fees.admin_withdraw_fee_denominator = BPS_DENOMINATOR;
+                assert_eq!(
+                    Err(SwapError::InvalidFees.into()),
+                    accounts.initialize_swap()
+                );
+                accounts.fees = old_fees;
+            }
+        }
+
+        // uninitialized token a account
+        {
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = Account::default();
+            assert_eq!(
+                Err(SwapError::ExpectedAccount.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // uninitialized token b account
+        {
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = Account::default();
+            assert_eq!(
+                Err(SwapError::ExpectedAccount.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // uninitialized pool mint
+        {
+            let old_account = accounts.pool_mint_account;
+            accounts.pool_mint_account = Account::default();
+            assert_eq!(
+                Err(SwapError::ExpectedMint.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_mint_account = old_account;
+        }
+
+        // token A account owner is not swap authority
+        {
+            let (_token_a_key, token_a_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &user_key,
+                0,
+            );
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = token_a_account;
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // token B account owner is not swap authority
+        {
+            let (_token_b_key, token_b_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &user_key,
+                0,
+            );
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_b_account;
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // pool token account owner is swap authority
+        {
+            let (_pool_token_key, pool_token_account) = mint_token(
+                &spl_token::id(),
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.pool_token_account;
+            accounts.pool_token_account = pool_token_account;
+            assert_eq!(
+                Err(SwapError::InvalidOutputOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_token_account = old_account;
+        }
+
+        // pool mint authority is not swap authority
+        {
+            let (_pool_mint_key, pool_mint_account) =
+                create_mint(&spl_token::id(), &user_key, DEFAULT_TOKEN_DECIMALS, None);
+            let old_mint = accounts.pool_mint_account;
+            accounts.pool_mint_account = pool_mint_account;
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_mint_account = old_mint;
+        }
+
+        // pool mint token has freeze authority
+        {
+            let (_pool_mint_key, pool_mint_account) = create_mint(
+                &spl_token::id(),
+                &accounts.authority_key,
+                DEFAULT_TOKEN_DECIMALS,
+                Some(&user_key),
+            );
+            let old_mint = accounts.pool_mint_account;
+            accounts.pool_mint_account = pool_mint_account;
+            assert_eq!(
+                Err(SwapError::InvalidFreezeAuthority.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_mint_account = old_mint;
+        }
+
+        // empty token A account
+        {
+            let (_token_a_key, token_a_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = token_a_account;
+            assert_eq!(
+                Err(SwapError::EmptySupply.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // empty token B account
+        {
+            let (_token_b_key, token_b_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_b_account;
+            assert_eq!(
+                Err(SwapError::EmptySupply.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // invalid pool tokens
+        {
+            let old_mint = accounts.pool_mint_account;
+            let old_pool_account = accounts.pool_token_account;
+
+            let (_pool_mint_key, pool_mint_account) = create_mint(
+                &spl_token::id(),
+                &accounts.authority_key,
+                DEFAULT_TOKEN_DECIMALS,
+                None,
+            );
+            accounts.pool_mint_account = pool_mint_account;
+
+            let (_empty_pool_token_key, empty_pool_token_account) = mint_token(
+                &spl_token::id(),
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &user_key,
+                0,
+            );
+
+            let (_pool_token_key, pool_token_account) = mint_token(
+                &spl_token::id(),
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &user_key,
+                pool_token_amount,
+            );
+
+            // non-empty pool token account
+            accounts.pool_token_account = pool_token_account;
+            assert_eq!(
+                Err(SwapError::InvalidSupply.into()),
+                accounts.initialize_swap()
+            );
+
+            // pool tokens already in circulation
+            accounts.pool_token_account = empty_pool_token_account;
+            assert_eq!(
+                Err(SwapError::InvalidSupply.into()),
+                accounts.initialize_swap()
+            );
+
+            accounts.pool_mint_account = old_mint;
+            accounts.pool_token_account = old_pool_account;
+        }
+
+        // token A account has close authority
+        {
+            do_process_instruction(
+                set_authority(
+                    &spl_token::id(),
+                    &accounts.token_a_key,
+                    Some(&user_key),
+                    AuthorityType::CloseAccount,
+                    &accounts.authority_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_a_account, &mut Account::default()],
+            )
+            .unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidCloseAuthority.into()),
+                accounts.initialize_swap()
+            );
+
+            do_process_instruction(
+                set_authority(
+                    &spl_token::id(),
+                    &accounts.token_a_key,
+                    None,
+                    AuthorityType::CloseAccount,
+                    &user_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_a_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // token B account has close authority
+        {
+            do_process_instruction(
+                set_authority(
+                    &spl_token::id(),
+                    &accounts.token_b_key,
+                    Some(&user_key),
+                    AuthorityType::CloseAccount,
+                    &accounts.authority_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_b_account, &mut Account::default()],
+            )
+            .unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidCloseAuthority.into()),
+                accounts.initialize_swap()
+            );
+
+            do_process_instruction(
+                set_authority(
+                    &spl_token::id(),
+                    &accounts.token_b_key,
+                    None,
+                    AuthorityType::CloseAccount,
+                    &user_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_b_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // mismatched admin mints
+        {
+            let (wrong_admin_fee_key, wrong_admin_fee_account) = mint_token(
+                &spl_token::id(),
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &user_key,
+                0,
+            );
+
+            // wrong admin_fee_key_a
+            let old_admin_fee_account_a = accounts.admin_fee_a_account;
+            let old_admin_fee_key_a = accounts.admin_fee_a_key;
+            accounts.admin_fee_a_account = wrong_admin_fee_account.clone();
+            accounts.admin_fee_a_key = wrong_admin_fee_key;
+
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.initialize_swap()
+            );
+
+            accounts.admin_fee_a_account = old_admin_fee_account_a;
+            accounts.admin_fee_a_key = old_admin_fee_key_a;
+
+            // wrong admin_fee_key_b
+            let old_admin_fee_account_b = accounts.admin_fee_b_account;
+            let old_admin_fee_key_b = accounts.admin_fee_b_key;
+            accounts.admin_fee_b_account = wrong_admin_fee_account;
+            accounts.admin_fee_b_key = wrong_admin_fee_key;
+
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.initialize_swap()
+            );
+
+            accounts.admin_fee_b_account = old_admin_fee_account_b;
+            accounts.admin_fee_b_key = old_admin_fee_key_b;
+        }
+
+        // mismatched mint decimals
+        {
+            let (bad_mint_key, mut bad_mint_account) =
+                create_mint(&spl_token::id(), &accounts.authority_key, 2, None);
+
+            // Pool mint decimal does not match
+            let old_pool_mint_key = accounts.pool_mint_key;
+            let old_pool_mint_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = bad_mint_key;
+            accounts.pool_mint_account = bad_mint_account.clone();
+
+            assert_eq!(
+                Err(SwapError::MismatchedDecimals.into()),
+                accounts.initialize_swap()
+            );
+
+            accounts.pool_mint_key = old_pool_mint_key;
+            accounts.pool_mint_account = old_pool_mint_account;
+
+            // Token a mint decimal does not match token b decimals
+            let (bad_token_key, bad_token_account) = mint_token(
+                &spl_token::id(),
+                &bad_mint_key,
+                &mut bad_mint_account,
+                &accounts.authority_key,
+                &accounts.authority_key,
+                10,
+            );
+
+            let old_token_a_key = accounts.token_a_key;
+            let old_token_a_account = accounts.token_a_account;
+            let old_token_a_mint_key = accounts.token_a_mint_key;
+            let old_token_a_mint_account = accounts.token_a_mint_account;
+            accounts.token_a_key = bad_token_key;
+            accounts.token_a_account = bad_token_account;
+            accounts.token_a_mint_key = bad_mint_key;
+            accounts.token_a_mint_account = bad_mint_account;
+
+            assert_eq!(
+                Err(SwapError::MismatchedDecimals.into()),
+                accounts.initialize_swap()
+            );
+
+            accounts.token_a_key = old_token_a_key;
+            accounts.token_a_account = old_token_a_account;
+            accounts.token_a_mint_key = old_token_a_mint_key;
+            accounts.token_a_mint_account = old_token_a_mint_account;
+        }
+
+        // create swap with same token A and B
+        {
+            let (_token_a_repeat_key, token_a_repeat_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_a_mint_key,
                 &mut accounts.token_a_mint_account,
                 &user_key,
                 &accounts.authority_key,
-                10,
+                10,
+            );
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_a_repeat_account;
+            assert_eq!(
+                Err(SwapError::RepeatedMint.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // create valid swap
+        accounts.initialize_swap().unwrap();
+
+        // create again
+        {
+            assert_eq!(
+                Err(SwapError::AlreadyInUse.into()),
+                accounts.initialize_swap()
+            );
+        }
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.is_initialized, true);
+        assert_eq!(swap_info.is_paused, false);
+        assert_eq!(swap_info.nonce, accounts.nonce);
+        assert_eq!(swap_info.initial_amp_factor, amp_factor * AMP_PRECISION);
+        assert_eq!(swap_info.target_amp_factor, amp_factor * AMP_PRECISION);
+        assert_eq!(swap_info.start_ramp_ts, ZERO_TS);
+        assert_eq!(swap_info.stop_ramp_ts, ZERO_TS);
+        assert_eq!(swap_info.future_admin_deadline, ZERO_TS);
+        assert_eq!(swap_info.future_admin_key, Pubkey::default());
+        assert_eq!(swap_info.future_fees_deadline, ZERO_TS);
+        assert_eq!(swap_info.future_fees, Fees::default());
+        assert_eq!(swap_info.admin_key, accounts.admin_key);
+        assert_eq!(swap_info.token_a.reserves, accounts.token_a_key);
+        assert_eq!(swap_info.token_b.reserves, accounts.token_b_key);
+        assert_eq!(swap_info.pool_mint, accounts.pool_mint_key);
+        assert_eq!(swap_info.token_a.mint, accounts.token_a_mint_key);
+        assert_eq!(swap_info.token_b.mint, accounts.token_b_mint_key);
+        assert_eq!(swap_info.token_a.admin_fees, accounts.admin_fee_a_key);
+        assert_eq!(swap_info.token_b.admin_fees, accounts.admin_fee_b_key);
+        assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
+        let token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, token_a_amount);
+        let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, token_b_amount);
+        let pool_account = utils::unpack_token_account(&accounts.pool_token_account.data).unwrap();
+        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.supply, pool_account.amount);
+    }
+
+    #[test]
+    fn test_deposit() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let amp_factor = MIN_AMP;
+        let token_a_amount = 1000;
+        let token_b_amount = 9000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+
+        let deposit_a = token_a_amount / 10;
+        let deposit_b = token_b_amount / 10;
+        let min_mint_amount = 0;
+
+        // swap not initialized
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong nonce for authority_key
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &spl_token::id(),
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
+
+        // not enough token A
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &depositor_key,
+                deposit_a / 2,
+                deposit_b,
+                0,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+        }
+
+        // not enough token B
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &depositor_key,
+                deposit_a,
+                deposit_b / 2,
+                0,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+        }
+
+        // swap account as source account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                do_process_instruction(
+                    deposit(
+                        &SWAP_PROGRAM_ID,
+                        &spl_token::id(),
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &depositor_key,
+                        &accounts.token_a_key,
+                        &token_b_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &pool_key,
+                        deposit_a,
+                        deposit_b,
+                        min_mint_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut accounts.token_a_account.clone(),
+                        &mut token_b_account,
+                        &mut accounts.token_a_account.clone(),
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                        &mut accounts.global_state_account,
+                    ],
+                )
+            );
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                do_process_instruction(
+                    deposit(
+                        &SWAP_PROGRAM_ID,
+                        &spl_token::id(),
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &depositor_key,
+                        &token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &pool_key,
+                        deposit_a,
+                        deposit_b,
+                        min_mint_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut accounts.token_b_account.clone(),
+                        &mut accounts.token_a_account.clone(),
+                        &mut accounts.token_b_account.clone(),
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                        &mut accounts.global_state_account,
+                    ],
+                )
+            );
+        }
+
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+        }
+
+        // wrong pool token account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let (
+                wrong_token_key,
+                mut wrong_token_account,
+                _token_b_key,
+                mut _token_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &wrong_token_key,
+                    &mut wrong_token_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+        }
+
+        // wrong token program id
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let wrong_key = pubkey_rand();
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                do_process_instruction(
+                    deposit(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_key,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &depositor_key,
+                        &token_a_key,
+                        &token_b_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &pool_key,
+                        deposit_a,
+                        deposit_b,
+                        min_mint_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut token_b_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                        &mut accounts.global_state_account,
+                    ],
+                )
+            );
+        }
+
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+
+            let old_a_key = accounts.token_a_key;
+            let old_a_account = accounts.token_a_account;
+
+            accounts.token_a_key = token_a_key;
+            accounts.token_a_account = token_a_account.clone();
+
+            // wrong swap token a account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+
+            accounts.token_a_key = old_a_key;
+            accounts.token_a_account = old_a_account;
+
+            let old_b_key = accounts.token_b_key;
+            let old_b_account = accounts.token_b_account;
+
+            accounts.token_b_key = token_b_key;
+            accounts.token_b_account = token_b_account.clone();
+
+            // wrong swap token b account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+
+            accounts.token_b_key = old_b_key;
+            accounts.token_b_account = old_b_account;
+        }
+
+        // wrong mint
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let (pool_mint_key, pool_mint_account) = create_mint(
+                &spl_token::id(),
+                &accounts.authority_key,
+                DEFAULT_TOKEN_DECIMALS,
+                None,
+            );
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // slippage exceeded
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            // min mint_amount in too high
+            let high_min_mint_amount = 10000000000000;
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    deposit_a,
+                    deposit_b,
+                    high_min_mint_amount,
+                )
+            );
+        }
+
+        // correctly deposit
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            accounts
+                .deposit(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+                .unwrap();
+
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            assert_eq!(swap_token_a.amount, deposit_a + token_a_amount);
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_token_b.amount, deposit_b + token_b_amount);
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, 0);
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, 0);
+            let pool_account = utils::unpack_token_account(&pool_account.data).unwrap();
+            let swap_pool_account =
+                utils::unpack_token_account(&accounts.pool_token_account.data).unwrap();
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            // XXX: Revisit and make sure amount of LP tokens minted is corrected.
+            assert_eq!(
+                pool_mint.supply,
+                pool_account.amount + swap_pool_account.amount
+            );
+
+            let (_program_id, return_data) = get_return_data().unwrap();
+            assert_eq!(
+                DepositReturnData::unpack(&return_data).unwrap(),
+                DepositReturnData {
+                    token_a_amount: deposit_a,
+                    token_b_amount: deposit_b,
+                    pool_token_amount: pool_account.amount,
+                }
+            );
+        }
+
+        // Pool is paused
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            // Pause pool
+            accounts.pause().unwrap();
+
+            assert_eq!(
+                Err(SwapError::IsPaused.into()),
+                accounts.deposit(
+                    &depositor_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    deposit_a,
+                    deposit_b,
+                    min_mint_amount,
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP;
+        let token_a_amount = 1000;
+        let token_b_amount = 2000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        let withdrawer_key = pubkey_rand();
+        let initial_a = token_a_amount / 10;
+        let initial_b = token_b_amount / 10;
+        let initial_pool = INITIAL_SWAP_POOL_AMOUNT;
+        let withdraw_amount = initial_pool / 4;
+        let minimum_a_amount = initial_a / 40;
+        let minimum_b_amount = initial_b / 40;
+
+        // swap not initialized
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong nonce for authority_key
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &spl_token::id(),
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
+
+        // not enough pool tokens
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount / 2,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount / 2,
+                    minimum_b_amount / 2,
+                )
+            );
+        }
+
+        // wrong token a / b accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+        }
+
+        // wrong admin a / b accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+            let (
+                wrong_admin_a_key,
+                wrong_admin_a_account,
+                wrong_admin_b_key,
+                wrong_admin_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+
+            let old_admin_a_key = accounts.admin_fee_a_key;
+            let old_admin_a_account = accounts.admin_fee_a_account;
+            accounts.admin_fee_a_key = wrong_admin_a_key;
+            accounts.admin_fee_a_account = wrong_admin_a_account;
+
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+
+            accounts.admin_fee_a_key = old_admin_a_key;
+            accounts.admin_fee_a_account = old_admin_a_account;
+
+            let old_admin_b_key = accounts.admin_fee_b_key;
+            let old_admin_b_account = accounts.admin_fee_b_account;
+            accounts.admin_fee_b_key = wrong_admin_b_key;
+            accounts.admin_fee_b_account = wrong_admin_b_account;
+
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+
+            accounts.admin_fee_b_key = old_admin_b_key;
+            accounts.admin_fee_b_account = old_admin_b_account;
+        }
+
+        // wrong pool token account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+            let (
+                wrong_pool_key,
+                mut wrong_pool_account,
+                _token_b_key,
+                _token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                withdraw_amount,
+                initial_b,
+                withdraw_amount,
+            );
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &wrong_pool_key,
+                    &mut wrong_pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+        }
+
+        // wrong token program id
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+            let wrong_key = pubkey_rand();
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                do_process_instruction(
+                    withdraw(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_key,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &withdrawer_key,
+                        &accounts.pool_mint_key,
+                        &pool_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_a_key,
+                        &token_b_key,
+                        &accounts.admin_fee_a_key,
+                        &accounts.admin_fee_b_key,
+                        withdraw_amount,
+                        minimum_a_amount,
+                        minimum_b_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_a_account,
+                        &mut token_b_account,
+                        &mut accounts.admin_fee_a_account,
+                        &mut accounts.admin_fee_b_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                    ],
+                )
+            );
+        }
+
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+
+            let old_a_key = accounts.token_a_key;
+            let old_a_account = accounts.token_a_account;
+
+            accounts.token_a_key = token_a_key;
+            accounts.token_a_account = token_a_account.clone();
+
+            // wrong swap token a account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+
+            accounts.token_a_key = old_a_key;
+            accounts.token_a_account = old_a_account;
+
+            let old_b_key = accounts.token_b_key;
+            let old_b_account = accounts.token_b_account;
+
+            accounts.token_b_key = token_b_key;
+            accounts.token_b_account = token_b_account.clone();
+
+            // wrong swap token b account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+
+            accounts.token_b_key = old_b_key;
+            accounts.token_b_account = old_b_account;
+        }
+
+        // wrong mint
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+            let (pool_mint_key, pool_mint_account) = create_mint(
+                &spl_token::id(),
+                &accounts.authority_key,
+                DEFAULT_TOKEN_DECIMALS,
+                None,
+            );
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // slippage exceeded
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+            // minimum A amount out too high
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount * 30, // XXX: 10 -> 30: Revisit this slippage multiplier
+                    minimum_b_amount,
+                )
+            );
+            // minimum B amount out too high
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount * 30, // XXX: 10 -> 30; Revisit this slippage multiplier
+                )
+            );
+        }
+
+        // correct withdrawal
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+
+            let original_reserve_a = utils::unpack_token_account(&accounts.token_a_account.data)
+                .unwrap()
+                .amount;
+            let original_reserve_b = utils::unpack_token_account(&accounts.token_b_account.data)
+                .unwrap()
+                .amount;
+            let original_lp_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
+                .unwrap()
+                .supply;
+
+            accounts
+                .withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+                .unwrap();
+
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let pool_converter = PoolTokenConverter {
+                supply: original_lp_supply.into(),
+                token_a: original_reserve_a.into(),
+                token_b: original_reserve_b.into(),
+                fees: &DEFAULT_TEST_FEES,
+            };
+
+            let (withdrawn_a, fee_a, admin_fee_a) =
+                pool_converter.token_a_rate(withdraw_amount).unwrap();
+            let withdrawn_total_a = withdrawn_a + admin_fee_a;
+            assert_eq!(swap_token_a.amount, token_a_amount - withdrawn_total_a);
+            let (withdrawn_b, fee_b, admin_fee_b) =
+                pool_converter.token_b_rate(withdraw_amount).unwrap();
+            let withdrawn_total_b = withdrawn_b + admin_fee_b;
+            assert_eq!(swap_token_b.amount, token_b_amount - withdrawn_total_b);
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a + (withdrawn_a));
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, initial_b + (withdrawn_b));
+            let pool_account = utils::unpack_token_account(&pool_account.data).unwrap();
+            assert_eq!(pool_account.amount, initial_pool - withdraw_amount);
+            let admin_fee_key_a =
+                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+            assert_eq!(admin_fee_key_a.amount, (admin_fee_a));
+            let admin_fee_key_b =
+                utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+            assert_eq!(admin_fee_key_b.amount, (admin_fee_b));
+
+            let (_program_id, return_data) = get_return_data().unwrap();
+            assert_eq!(
+                WithdrawReturnData::unpack(&return_data).unwrap(),
+                WithdrawReturnData {
+                    token_a_amount: withdrawn_a,
+                    token_b_amount: withdrawn_b,
+                    token_a_fee: fee_a,
+                    token_b_fee: fee_b,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_swap() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        let initial_a = token_a_amount / 5;
+        let initial_b = token_b_amount / 5;
+        let minimum_b_amount = initial_b / 2;
+
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
+        // swap not initialized
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a,
+                    minimum_b_amount,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong nonce
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &spl_token::id(),
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a,
+                    minimum_b_amount,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
+
+        // wrong token program id
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let wrong_program_id = pubkey_rand();
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                do_process_instruction(
+                    swap(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_program_id,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &user_key,
+                        &token_a_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_b_key,
+                        &accounts.admin_fee_b_key,
+                        initial_a,
+                        minimum_b_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_b_account,
+                        &mut accounts.admin_fee_b_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                        &mut accounts.global_state_account,
+                    ],
+                ),
+            );
+        }
+
+        // not enough token a to swap
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a * 2,
+                    minimum_b_amount * 2,
+                )
+            );
+        }
+
+        // wrong swap token A / B accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                do_process_instruction(
+                    swap(
+                        &SWAP_PROGRAM_ID,
+                        &spl_token::id(),
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &swapper_key,
+                        &token_a_key,
+                        &token_a_key,
+                        &token_b_key,
+                        &token_b_key,
+                        &accounts.admin_fee_b_key,
+                        initial_a,
+                        minimum_b_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account.clone(),
+                        &mut token_a_account,
+                        &mut token_b_account.clone(),
+                        &mut token_b_account,
+                        &mut accounts.admin_fee_b_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                        &mut accounts.global_state_account,
+                    ],
+                ),
+            );
+        }
+
+        // wrong admin account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                wrong_admin_key,
+                mut wrong_admin_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                do_process_instruction(
+                    swap(
+                        &SWAP_PROGRAM_ID,
+                        &spl_token::id(),
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &swapper_key,
+                        &token_a_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_b_key,
+                        &wrong_admin_key,
+                        initial_a,
+                        minimum_b_amount,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_b_account,
+                        &mut wrong_admin_account,
+                        &mut Account::default(),
+                        &mut clock_account(ZERO_TS),
+                        &mut accounts.global_state_account,
+                    ],
+                ),
+            );
+        }
+
+        // wrong user token A / B accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    initial_a,
+                    minimum_b_amount,
+                )
+            );
+        }
+
+        // swap from a to a
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account.clone(),
+                    &swap_token_a_key,
+                    &swap_token_a_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    initial_a,
+                    minimum_b_amount,
+                )
+            );
+        }
+
+        // swap source same as swap account
+        {
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.swap(
+                    &accounts.authority_key.clone(),
+                    &swap_token_a_key,
+                    &mut accounts.token_a_account.clone(),
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &swap_token_b_key,
+                    &mut accounts.token_b_account.clone(),
+                    initial_a,
+                    minimum_b_amount,
+                )
+            );
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.swap(
+                    &accounts.authority_key.clone(),
+                    &swap_token_b_key,
+                    &mut accounts.token_b_account.clone(),
+                    &swap_token_b_key,
+                    &swap_token_a_key,
+                    &swap_token_a_key,
+                    &mut accounts.token_a_account.clone(),
+                    initial_b,
+                    initial_a / 2,
+                )
+            );
+        }
+
+        // slippage exceeded: minimum out amount too high
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a,
+                    minimum_b_amount * 2,
+                )
+            );
+        }
+
+        // correct swap
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            // swap one way
+            let a_to_b_amount = initial_a / 10;
+            let minimum_b_amount = initial_b / 20;
+            accounts
+                .swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    a_to_b_amount,
+                    minimum_b_amount,
+                )
+                .unwrap();
+
+            let invariant = StableSwap::new(
+                accounts.initial_amp_factor * AMP_PRECISION,
+                accounts.target_amp_factor * AMP_PRECISION,
+                ZERO_TS,
+                ZERO_TS,
+                ZERO_TS,
+            );
+            let result = invariant
+                .swap_to(
+                    a_to_b_amount,
+                    token_a_amount,
+                    token_b_amount,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let token_a_amount = swap_token_a.amount;
+            assert_eq!(token_a_amount, 5100);
+            assert_eq!(token_a_amount, (result.new_source_amount));
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a - a_to_b_amount);
+
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let token_b_amount = swap_token_b.amount;
+            assert_eq!(token_b_amount, 4904);
+            assert_eq!(token_b_amount, (result.new_destination_amount));
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, 1093);
+            assert_eq!(token_b.amount, initial_b + (result.amount_swapped));
+            let admin_fee_b_account =
+                utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+            assert_eq!(admin_fee_b_account.amount, (result.admin_fee));
+
+            let (program_id, return_data) = get_return_data().unwrap();
+            assert_eq!(program_id, SWAP_PROGRAM_ID);
+            assert_eq!(
+                SwapReturnData::unpack(&return_data).unwrap(),
+                SwapReturnData {
+                    amount_out: result.amount_swapped,
+                    fee: result.fee,
+                    admin_fee: result.admin_fee,
+                    price_impact_bps: result.price_impact_bps().unwrap(),
+                }
+            );
+
+            let first_swap_amount = result.amount_swapped;
+
+            // swap the other way
+            let b_to_a_amount = initial_b / 10;
+            let minimum_a_amount = initial_a / 20;
+            accounts
+                .swap(
+                    &swapper_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &swap_token_b_key,
+                    &swap_token_a_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    b_to_a_amount,
+                    minimum_a_amount,
+                )
+                .unwrap();
+
+            let invariant = StableSwap::new(
+                accounts.initial_amp_factor * AMP_PRECISION,
+                accounts.target_amp_factor * AMP_PRECISION,
+                ZERO_TS,
+                ZERO_TS,
+                ZERO_TS,
+            );
+            let result = invariant
+                .swap_to(
+                    b_to_a_amount,
+                    token_b_amount,
+                    token_a_amount,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            assert_eq!(swap_token_a.amount, 5003);
+            assert_eq!(swap_token_a.amount, (result.new_destination_amount));
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, 994);
+            assert_eq!(
+                token_a.amount,
+                initial_a - a_to_b_amount + (result.amount_swapped)
+            );
+
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_token_b.amount, 5004);
+            assert_eq!(swap_token_b.amount, (result.new_source_amount));
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, 993);
+            assert_eq!(
+                token_b.amount,
+                initial_b + (first_swap_amount) - b_to_a_amount
+            );
+            let admin_fee_a_account =
+                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+            assert_eq!(admin_fee_a_account.amount, (result.admin_fee));
+        }
+
+        // Pool is paused
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            // Pause pool
+            accounts.pause().unwrap();
+
+            assert_eq!(
+                Err(SwapError::IsPaused.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a,
+                    minimum_b_amount,
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_swap_without_clock_sysvar() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let initial_a = token_a_amount / 5;
+        let initial_b = token_b_amount / 5;
+
+        // legacy layout
+        let mut legacy_accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        legacy_accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = legacy_accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+        let swap_token_a_key = legacy_accounts.token_a_key;
+        let swap_token_b_key = legacy_accounts.token_b_key;
+        legacy_accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                initial_a,
+                0,
+            )
+            .unwrap();
+        let legacy_token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+
+        // without the clock sysvar
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+        let instruction = omit_clock_sysvar(
+            swap(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &accounts.swap_key,
+                &accounts.authority_key,
+                &swapper_key,
+                &token_a_key,
+                &accounts.token_a_key,
+                &accounts.token_b_key,
+                &token_b_key,
+                &accounts.admin_fee_b_key,
+                initial_a,
+                0,
+            )
+            .unwrap(),
+        );
+        assert!(!instruction
+            .accounts
+            .iter()
+            .any(|account| account.pubkey == solana_program::sysvar::clock::id()));
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut token_a_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut token_b_account,
+                &mut accounts.admin_fee_b_account,
+                &mut Account::default(),
+                &mut accounts.global_state_account,
+            ],
+        )
+        .unwrap();
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, legacy_token_b.amount);
+        let admin_fee_b = utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+        let legacy_admin_fee_b =
+            utils::unpack_token_account(&legacy_accounts.admin_fee_b_account.data).unwrap();
+        assert_eq!(admin_fee_b.amount, legacy_admin_fee_b.amount);
+    }
+
+    #[test]
+    fn test_swap_with_host_fee() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let host_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let amount_in = token_a_amount / 10;
+        let fees = Fees {
+            host_fee_bps: 4_000,
+            ..DEFAULT_TEST_FEES
+        };
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, amp_factor, token_a_amount, token_b_amount, fees);
+        accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, amount_in * 2, 0, 0);
+        let (
+            host_fee_a_key,
+            mut host_fee_a_account,
+            host_fee_b_key,
+            mut host_fee_b_account,
+            _host_pool_key,
+            _host_pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &host_key, 0, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
+        // the host gets its share of the admin fee in the destination token
+        accounts
+            .swap_with_host_fee(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                (&host_fee_b_key, &mut host_fee_b_account),
+                amount_in,
+                0,
+            )
+            .unwrap();
+        let host_fee = utils::unpack_token_account(&host_fee_b_account.data)
+            .unwrap()
+            .amount;
+        let admin_fee = utils::unpack_token_account(&accounts.admin_fee_b_account.data)
+            .unwrap()
+            .amount;
+        assert!(host_fee > 0);
+        assert_eq!(host_fee, (host_fee + admin_fee) * 4_000 / BPS_DENOMINATOR);
+
+        // a host fee account of the source token is ignored
+        accounts
+            .swap_with_host_fee(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                (&host_fee_a_key, &mut host_fee_a_account),
+                amount_in,
+                0,
+            )
+            .unwrap();
+        let host_fee_a = utils::unpack_token_account(&host_fee_a_account.data).unwrap();
+        assert_eq!(host_fee_a.amount, 0);
+        let second_admin_fee = utils::unpack_token_account(&accounts.admin_fee_b_account.data)
+            .unwrap()
+            .amount
+            - admin_fee;
+        assert!(second_admin_fee > admin_fee);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_swap_snapshot_replay() {
+        use crate::snapshot::PoolSnapshot;
+        use solana_sdk::account::create_is_signer_account_infos;
+
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let amount_in = token_a_amount / 10;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, amount_in * 2, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+            .unwrap();
+
+        let mut captured = [
+            (accounts.swap_key, accounts.swap_account.clone()),
+            (accounts.token_a_key, accounts.token_a_account.clone()),
+            (accounts.token_b_key, accounts.token_b_account.clone()),
+            (
+                accounts.token_a_mint_key,
+                accounts.token_a_mint_account.clone(),
+            ),
+            (
+                accounts.token_b_mint_key,
+                accounts.token_b_mint_account.clone(),
+            ),
+            (accounts.pool_mint_key, accounts.pool_mint_account.clone()),
+            (
+                accounts.admin_fee_a_key,
+                accounts.admin_fee_a_account.clone(),
+            ),
+            (
+                accounts.admin_fee_b_key,
+                accounts.admin_fee_b_account.clone(),
+            ),
+        ];
+        let mut captured_infos = captured
+            .iter_mut()
+            .map(|(key, account)| (&*key, false, account))
+            .collect::<Vec<_>>();
+        let account_infos = create_is_signer_account_infos(&mut captured_infos);
+        // a token account is not a swap
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            SwapInfo::snapshot(&account_infos[1], &account_infos)
+        );
+        let snapshot = SwapInfo::snapshot(&account_infos[0], &account_infos).unwrap();
+        assert_eq!(snapshot.accounts.len(), account_infos.len() - 1);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains(&accounts.swap_key.to_string()));
+        let snapshot = serde_json::from_str::<PoolSnapshot>(&json).unwrap();
+
+        // the replayed swap is byte for byte the original, and swaps the same
+        let mut replayed = SwapAccountInfo::from_snapshot(&snapshot);
+        assert_eq!(replayed.swap_account, accounts.swap_account);
+        assert_eq!(replayed.token_a_account, accounts.token_a_account);
+        assert_eq!(replayed.authority_key, accounts.authority_key);
+        for swap in [&mut accounts, &mut replayed] {
+            let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+                swap.setup_token_accounts(&user_key, &swapper_key, amount_in, 0, 0);
+            swap.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+            .unwrap();
+        }
+        assert_eq!(replayed.swap_account, accounts.swap_account);
+        assert_eq!(replayed.token_b_account, accounts.token_b_account);
+        assert_eq!(replayed.admin_fee_b_account, accounts.admin_fee_b_account);
+    }
+
+    #[test]
+    fn test_swap_native_sol() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let amount_in = token_a_amount / 10;
+        let (native_mint_key, native_mint_account) = create_native_mint();
+        let mut accounts = SwapAccountInfo::new_with_token_a_mint(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+            &native_mint_key,
+            native_mint_account,
+        );
+        accounts.initialize_swap().unwrap();
+        let swapper_lamports = 10_000;
+        let mut swapper_account = Account::new(swapper_lamports, 0, &system_program::id());
+        let (
+            wsol_key,
+            mut wsol_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let wsol_rent = wsol_account.lamports;
+
+        // lamports are wrapped into the source account, which is closed after the swap
+        accounts
+            .swap_with_native_sol(
+                (&swapper_key, &mut swapper_account),
+                (&wsol_key, &mut wsol_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                amount_in,
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            swapper_account.lamports,
+            swapper_lamports - amount_in + wsol_rent
+        );
+        assert_eq!(wsol_account.lamports, 0);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount + amount_in);
+        let amount_out = utils::unpack_token_account(&token_b_account.data)
+            .unwrap()
+            .amount;
+        assert!(amount_out > 0);
+
+        // the destination account is closed, unwrapping the lamports swapped
+        let swapper_lamports = swapper_account.lamports;
+        let (wsol_key, mut wsol_account, _, _, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
+        accounts
+            .swap_with_native_sol(
+                (&swapper_key, &mut swapper_account),
+                (&token_b_key, &mut token_b_account),
+                &swap_token_b_key,
+                &swap_token_a_key,
+                (&wsol_key, &mut wsol_account),
+                amount_out,
+                0,
+            )
+            .unwrap();
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        let admin_fee_a = utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+        let sol_out = token_a_amount + amount_in - swap_token_a.amount - admin_fee_a.amount;
+        assert!(sol_out > 0);
+        assert_eq!(wsol_account.lamports, 0);
+        assert_eq!(
+            swapper_account.lamports,
+            swapper_lamports + sol_out + wsol_rent
+        );
+        // the token B account is not closed
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, 0);
+        assert_eq!(token_b.mint, accounts.token_b_mint_key);
+
+        // the user cannot wrap more lamports than it has
+        let (wsol_key, mut wsol_account, _, _, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
+        let too_many_lamports = swapper_account.lamports + 1;
+        assert_eq!(
+            Err(ProgramError::InsufficientFunds),
+            accounts.swap_with_native_sol(
+                (&swapper_key, &mut swapper_account),
+                (&wsol_key, &mut wsol_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                too_many_lamports,
+                0,
+            )
+        );
+    }
+
+    #[test]
+    fn test_swap_batch() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let initial_a = token_a_amount / 5;
+        let fills = [
+            SwapData {
+                amount_in: initial_a / 4,
+                minimum_amount_out: 0,
+            },
+            SwapData {
+                amount_in: 0,
+                minimum_amount_out: 0,
+            },
+            SwapData {
+                amount_in: initial_a / 2,
+                minimum_amount_out: 0,
+            },
+        ];
+
+        // sequential swaps
+        let mut sequential_accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        sequential_accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = sequential_accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
+        let swap_token_a_key = sequential_accounts.token_a_key;
+        let swap_token_b_key = sequential_accounts.token_b_key;
+        for fill in fills.iter() {
+            sequential_accounts
+                .swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    fill.amount_in,
+                    fill.minimum_amount_out,
+                )
+                .unwrap();
+        }
+        let sequential_token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
+        // slippage of the last fill reverts the batch
+        {
+            let mut last_fill_slippage = fills.clone();
+            last_fill_slippage[2].minimum_amount_out = initial_a;
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.swap_batch(
+                    &swapper_key,
+                    (&token_a_key, &mut token_a_account),
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    (&token_b_key, &mut token_b_account),
+                    &last_fill_slippage,
+                )
             );
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_a_repeat_account;
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a);
+        }
+
+        // too many fills
+        {
+            let too_many_fills = vec![fills[0].clone(); MAX_SWAP_BATCH_FILLS + 1];
             assert_eq!(
-                Err(SwapError::RepeatedMint.into()),
-                accounts.initialize_swap()
+                Err(SwapError::InvalidInstruction.into()),
+                accounts.swap_batch(
+                    &swapper_key,
+                    (&token_a_key, &mut token_a_account),
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    (&token_b_key, &mut token_b_account),
+                    &too_many_fills,
+                )
             );
-            accounts.token_b_account = old_account;
         }
 
-        // create valid swap
-        accounts.initialize_swap().unwrap();
-
-        // create again
+        // valid batch
         {
+            accounts
+                .swap_batch(
+                    &swapper_key,
+                    (&token_a_key, &mut token_a_account),
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    (&token_b_key, &mut token_b_account),
+                    &fills,
+                )
+                .unwrap();
+
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, sequential_token_b.amount);
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
             assert_eq!(
-                Err(SwapError::AlreadyInUse.into()),
-                accounts.initialize_swap()
+                token_a.amount,
+                initial_a - fills.iter().map(|fill| fill.amount_in).sum::<u64>()
             );
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let sequential_swap_token_b =
+                utils::unpack_token_account(&sequential_accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_token_b.amount, sequential_swap_token_b.amount);
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            let sequential_swap_info =
+                SwapInfo::unpack(&sequential_accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.fee_growth_b, sequential_swap_info.fee_growth_b);
+
+            let (_, data) = get_return_data().unwrap();
+            let return_data = SwapReturnData::unpack(&data).unwrap();
+            assert_eq!(return_data.amount_out, token_b.amount);
         }
-        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
-        assert_eq!(swap_info.is_initialized, true);
-        assert_eq!(swap_info.is_paused, false);
-        assert_eq!(swap_info.nonce, accounts.nonce);
-        assert_eq!(swap_info.initial_amp_factor, amp_factor);
-        assert_eq!(swap_info.target_amp_factor, amp_factor);
-        assert_eq!(swap_info.start_ramp_ts, ZERO_TS);
-        assert_eq!(swap_info.stop_ramp_ts, ZERO_TS);
-        assert_eq!(swap_info.future_admin_deadline, ZERO_TS);
-        assert_eq!(swap_info.future_admin_key, Pubkey::default());
-        assert_eq!(swap_info.admin_key, accounts.admin_key);
-        assert_eq!(swap_info.token_a.reserves, accounts.token_a_key);
-        assert_eq!(swap_info.token_b.reserves, accounts.token_b_key);
-        assert_eq!(swap_info.pool_mint, accounts.pool_mint_key);
-        assert_eq!(swap_info.token_a.mint, accounts.token_a_mint_key);
-        assert_eq!(swap_info.token_b.mint, accounts.token_b_mint_key);
-        assert_eq!(swap_info.token_a.admin_fees, accounts.admin_fee_a_key);
-        assert_eq!(swap_info.token_b.admin_fees, accounts.admin_fee_b_key);
-        assert_eq!(swap_info.fees, DEFAULT_TEST_FEES);
-        let token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-        assert_eq!(token_a.amount, token_a_amount);
-        let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-        assert_eq!(token_b.amount, token_b_amount);
-        let pool_account = utils::unpack_token_account(&accounts.pool_token_account.data).unwrap();
-        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
-        assert_eq!(pool_mint.supply, pool_account.amount);
     }
 
     #[test]
-    fn test_deposit() {
+    fn test_quick_swap() {
         let user_key = pubkey_rand();
-        let depositor_key = pubkey_rand();
-        let amp_factor = MIN_AMP;
-        let token_a_amount = 1000;
-        let token_b_amount = 9000;
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000_000;
+        let token_b_amount = 1_000_000_000;
         let mut accounts = SwapAccountInfo::new(
             &user_key,
-            amp_factor,
+            MIN_AMP * 100,
             token_a_amount,
             token_b_amount,
             DEFAULT_TEST_FEES,
         );
+        accounts.initialize_swap().unwrap();
+        accounts.set_quick_swap_max_bps(10).unwrap();
 
-        let deposit_a = token_a_amount / 10;
-        let deposit_b = token_b_amount / 10;
-        let min_mint_amount = 0;
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &swapper_key,
+            token_a_amount,
+            token_b_amount,
+            0,
+        );
+        let balance =
+            |account: &Account| utils::unpack_token_account(&account.data).unwrap().amount;
+        let invariant = StableSwap::new(
+            accounts.initial_amp_factor * AMP_PRECISION,
+            accounts.target_amp_factor * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
 
-        // swap not initialized
+        // small swaps are paid out the quick estimate
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.deposit(
-                    &depositor_key,
+            let amount_in = 100_000;
+            let quick = invariant
+                .quick_swap_to(
+                    amount_in,
+                    token_a_amount,
+                    token_b_amount,
+                    10,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+            let full = invariant
+                .swap_to(
+                    amount_in,
+                    token_a_amount,
+                    token_b_amount,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+            assert!(quick.amount_swapped <= full.amount_swapped);
+
+            accounts
+                .swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    amount_in,
+                    0,
                 )
+                .unwrap();
+            assert_eq!(
+                balance(&token_b_account),
+                token_b_amount + quick.amount_swapped
             );
+            assert_eq!(
+                balance(&accounts.token_b_account),
+                quick.new_destination_amount
+            );
+        }
+
+        // larger swaps solve the curve
+        {
+            let amount_in = 10_000_000;
+            let swap_token_a_amount = balance(&accounts.token_a_account);
+            let swap_token_b_amount = balance(&accounts.token_b_account);
+            let before = balance(&token_b_account);
+            let full = invariant
+                .swap_to(
+                    amount_in,
+                    swap_token_a_amount,
+                    swap_token_b_amount,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+
+            accounts
+                .swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    amount_in,
+                    0,
+                )
+                .unwrap();
+            assert_eq!(balance(&token_b_account), before + full.amount_swapped);
         }
+    }
+
+    #[test]
+    fn test_simulate_swap() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP * 100,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
+        let swap_account = accounts.swap_account.clone();
+
+        // quoted without the user holding the tokens, nothing changes
+        let amount_in = 10_000;
+        assert_eq!(
+            Err(SwapError::SimulationComplete.into()),
+            accounts.simulate_swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+        );
+        let (_, data) = get_return_data().unwrap();
+        let simulated = SwapReturnData::unpack(&data).unwrap();
+        assert_eq!(accounts.swap_account, swap_account);
+        assert_eq!(
+            utils::unpack_token_account(&token_b_account.data)
+                .unwrap()
+                .amount,
+            0
+        );
+
+        // checked as a swap
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.simulate_swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                simulated.amount_out + 1,
+            )
+        );
+
+        // the swap it quotes
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, amount_in, 0, 0);
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                simulated.amount_out,
+            )
+            .unwrap();
+        let (_, data) = get_return_data().unwrap();
+        assert_eq!(SwapReturnData::unpack(&data).unwrap(), simulated);
+    }
 
+    #[test]
+    fn test_swap_allow_partial() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP * 100,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
         accounts.initialize_swap().unwrap();
+        // at most 10% of the output reserve per swap
+        accounts.set_max_swap_bps(1_000).unwrap();
 
-        // wrong nonce for authority_key
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &swapper_key,
+            token_a_amount,
+            token_b_amount,
+            0,
+        );
+        let balance =
+            |account: &Account| utils::unpack_token_account(&account.data).unwrap().amount;
+
+        // filled up to the max swap size, the rest stays with the user
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
-                &spl_token::id(),
+            let amount_in = token_a_amount / 5;
+            accounts
+                .swap_allow_partial(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    amount_in,
+                    0,
+                )
+                .unwrap();
+            let (_, data) = get_return_data().unwrap();
+            let return_data = PartialSwapReturnData::unpack(&data).unwrap();
+            assert!(return_data.amount_in < amount_in);
+            assert_eq!(
+                balance(&token_a_account),
+                token_a_amount - return_data.amount_in
             );
-            accounts.authority_key = bad_authority_key;
             assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.deposit(
-                    &depositor_key,
+                balance(&token_b_account),
+                token_b_amount + return_data.amount_out
+            );
+            let taken = return_data.amount_out + return_data.admin_fee;
+            assert!(taken <= token_b_amount / 10 && taken > token_b_amount / 10 - 100);
+        }
+
+        // filled in full within the cap
+        {
+            let amount_in = 10_000;
+            let before = balance(&token_a_account);
+            accounts
+                .swap_allow_partial(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    amount_in,
+                    0,
                 )
+                .unwrap();
+            let (_, data) = get_return_data().unwrap();
+            assert_eq!(
+                PartialSwapReturnData::unpack(&data).unwrap().amount_in,
+                amount_in
             );
-            accounts.authority_key = old_authority;
+            assert_eq!(balance(&token_a_account), before - amount_in);
         }
 
-        // not enough token A
+        // no part pays the limit price
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.swap_allow_partial(
+                &swapper_key,
+                &token_b_key,
+                &mut token_b_account,
+                &swap_token_b_key,
+                &swap_token_a_key,
+                &token_a_key,
+                &mut token_a_account,
+                10_000,
+                10_000,
+            )
+        );
+
+        // filled down to the limit price
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &depositor_key,
-                deposit_a / 2,
-                deposit_b,
-                0,
+            accounts.set_max_swap_bps(0).unwrap();
+            let amount_in = token_a_amount / 2;
+            let minimum_amount_out = amount_in * 933 / 1_000;
+            let before = balance(&token_a_account);
+            accounts
+                .swap_allow_partial(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    amount_in,
+                    minimum_amount_out,
+                )
+                .unwrap();
+            let (_, data) = get_return_data().unwrap();
+            let return_data = PartialSwapReturnData::unpack(&data).unwrap();
+            assert!(return_data.amount_in > 0 && return_data.amount_in < amount_in);
+            assert_eq!(balance(&token_a_account), before - return_data.amount_in);
+            assert!(
+                u128::from(return_data.amount_out) * u128::from(amount_in)
+                    >= u128::from(minimum_amount_out) * u128::from(return_data.amount_in)
             );
+        }
+    }
+
+    #[test]
+    fn test_max_swap_size() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP * 100,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        // at most 10% of the output reserve per swap
+        accounts.set_max_swap_bps(1_000).unwrap();
+
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &swapper_key,
+            token_a_amount,
+            token_b_amount,
+            0,
+        );
+
+        // too large
+        {
             assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.deposit(
-                    &depositor_key,
+                Err(SwapError::ExceededMaxSwapSize.into()),
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    token_a_amount / 5,
+                    0,
                 )
             );
         }
 
-        // not enough token B
+        // within the cap
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &depositor_key,
-                deposit_a,
-                deposit_b / 2,
-                0,
-            );
-            assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.deposit(
-                    &depositor_key,
+            accounts
+                .swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
-                )
-            );
-        }
-
-        // swap account as source account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                do_process_instruction(
-                    deposit(
-                        &SWAP_PROGRAM_ID,
-                        &spl_token::id(),
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &depositor_key,
-                        &accounts.token_a_key,
-                        &token_b_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        deposit_a,
-                        deposit_b,
-                        min_mint_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut accounts.token_a_account.clone(),
-                        &mut token_b_account,
-                        &mut accounts.token_a_account.clone(),
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
-                )
-            );
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                do_process_instruction(
-                    deposit(
-                        &SWAP_PROGRAM_ID,
-                        &spl_token::id(),
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &depositor_key,
-                        &token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        deposit_a,
-                        deposit_b,
-                        min_mint_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_b_account.clone(),
-                        &mut accounts.token_a_account.clone(),
-                        &mut accounts.token_b_account.clone(),
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
+                    token_a_amount / 20,
+                    0,
                 )
-            );
+                .unwrap();
         }
 
-        // wrong swap token accounts
+        // the cap is relative to the reserve of the destination
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
             assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.deposit(
-                    &depositor_key,
+                Err(SwapError::ExceededMaxSwapSize.into()),
+                accounts.swap(
+                    &swapper_key,
                     &token_b_key,
                     &mut token_b_account,
+                    &swap_token_b_key,
+                    &swap_token_a_key,
                     &token_a_key,
                     &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    token_b_amount / 5,
+                    0,
                 )
             );
         }
 
-        // wrong pool token account
+        // disabled
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let (
-                wrong_token_key,
-                mut wrong_token_account,
-                _token_b_key,
-                mut _token_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.deposit(
-                    &depositor_key,
+            accounts.set_max_swap_bps(0).unwrap();
+            accounts
+                .swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    &wrong_token_key,
-                    &mut wrong_token_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    token_a_amount / 5,
+                    0,
                 )
-            );
+                .unwrap();
         }
+    }
 
-        // wrong token program id
+    #[test]
+    fn test_swap_route() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let mut first = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        let mut second = SwapAccountInfo::new_with_token_a_mint(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+            &first.token_b_mint_key,
+            first.token_b_mint_account.clone(),
+        );
+        let mut unrelated = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        first.initialize_swap().unwrap();
+        second.initialize_swap().unwrap();
+        unrelated.initialize_swap().unwrap();
+
+        let initial_a = token_a_amount / 5;
+        let amount_in = initial_a / 10;
+
+        // pools not sharing an intermediate mint
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let wrong_key = pubkey_rand();
+            let (token_a_key, mut token_a_account, _, _, _, _) =
+                first.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
+            let (_, _, token_c_key, mut token_c_account, _, _) =
+                unrelated.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
             assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                do_process_instruction(
-                    deposit(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_key,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &depositor_key,
-                        &token_a_key,
-                        &token_b_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        deposit_a,
-                        deposit_b,
-                        min_mint_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut token_b_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
+                Err(SwapError::IncorrectMint.into()),
+                first.swap_route(
+                    &mut unrelated,
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_c_key,
+                    &mut token_c_account,
+                    amount_in,
+                    0,
                 )
             );
         }
 
-        // wrong swap token accounts
+        let invariant = StableSwap::new(
+            amp_factor * AMP_PRECISION,
+            amp_factor * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let first_result = invariant
+            .swap_to(
+                amount_in,
+                token_a_amount,
+                token_b_amount,
+                &DEFAULT_TEST_FEES,
+            )
+            .unwrap();
+        let second_result = invariant
+            .swap_to(
+                first_result.amount_swapped,
+                token_a_amount,
+                token_b_amount,
+                &DEFAULT_TEST_FEES,
+            )
+            .unwrap();
+
+        // slippage exceeded on the final amount
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let (token_a_key, mut token_a_account, _, _, _, _) =
+                first.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
+            let (_, _, token_c_key, mut token_c_account, _, _) =
+                second.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                first.swap_route(
+                    &mut second,
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_c_key,
+                    &mut token_c_account,
+                    amount_in,
+                    second_result.amount_swapped + 1,
+                )
+            );
+        }
 
-            let old_a_key = accounts.token_a_key;
-            let old_a_account = accounts.token_a_account;
+        // correct route
+        {
+            let (token_a_key, mut token_a_account, _, _, _, _) =
+                first.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
+            let (_, _, token_c_key, mut token_c_account, _, _) =
+                second.setup_token_accounts(&user_key, &swapper_key, 0, 0, 0);
+            first
+                .swap_route(
+                    &mut second,
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_c_key,
+                    &mut token_c_account,
+                    amount_in,
+                    second_result.amount_swapped,
+                )
+                .unwrap();
 
-            accounts.token_a_key = token_a_key;
-            accounts.token_a_account = token_a_account.clone();
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a - amount_in);
+            let token_c = utils::unpack_token_account(&token_c_account.data).unwrap();
+            assert_eq!(token_c.amount, second_result.amount_swapped);
+
+            let first_a = utils::unpack_token_account(&first.token_a_account.data).unwrap();
+            assert_eq!(first_a.amount, first_result.new_source_amount);
+            let first_b = utils::unpack_token_account(&first.token_b_account.data).unwrap();
+            assert_eq!(first_b.amount, first_result.new_destination_amount);
+            let first_fee_b = utils::unpack_token_account(&first.admin_fee_b_account.data).unwrap();
+            assert_eq!(first_fee_b.amount, first_result.admin_fee);
+
+            let second_a = utils::unpack_token_account(&second.token_a_account.data).unwrap();
+            assert_eq!(second_a.amount, second_result.new_source_amount);
+            let second_b = utils::unpack_token_account(&second.token_b_account.data).unwrap();
+            assert_eq!(second_b.amount, second_result.new_destination_amount);
+            let second_fee_b =
+                utils::unpack_token_account(&second.admin_fee_b_account.data).unwrap();
+            assert_eq!(second_fee_b.amount, second_result.admin_fee);
+        }
+    }
 
-            // wrong swap token a account
+    #[test]
+    fn test_split_by_depth() {
+        assert_eq!(
+            split_by_depth(100, &[Some(10), Some(10), Some(10)]),
+            Some(vec![33, 33, 34])
+        );
+        // a shortfall of zero counts as one
+        assert_eq!(split_by_depth(100, &[Some(0), Some(3)]), Some(vec![75, 25]));
+        assert_eq!(split_by_depth(100, &[None, Some(7)]), Some(vec![0, 100]));
+        assert_eq!(split_by_depth(100, &[None, None]), None);
+        assert_eq!(
+            split_by_depth(u64::MAX, &[Some(1), Some(u64::MAX)]),
+            Some(vec![u64::MAX, 0])
+        );
+    }
+
+    #[test]
+    fn test_swap_split() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = 85;
+        let mut shallow =
+            SwapAccountInfo::new(&user_key, amp_factor, 5_000, 5_000, DEFAULT_TEST_FEES);
+        let mut deep = SwapAccountInfo::new_with_token_mints(
+            &user_key,
+            amp_factor,
+            20_000,
+            20_000,
+            DEFAULT_TEST_FEES,
+            (
+                &shallow.token_a_mint_key,
+                shallow.token_a_mint_account.clone(),
+            ),
+            (
+                &shallow.token_b_mint_key,
+                shallow.token_b_mint_account.clone(),
+            ),
+        );
+        let mut unrelated =
+            SwapAccountInfo::new(&user_key, amp_factor, 20_000, 20_000, DEFAULT_TEST_FEES);
+        shallow.initialize_swap().unwrap();
+        deep.initialize_swap().unwrap();
+        unrelated.initialize_swap().unwrap();
+
+        let initial_a = 2_000;
+        let amount_in = 1_000;
+
+        // pools of different pairs
+        {
+            let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+                shallow.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
             assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.deposit(
-                    &depositor_key,
+                Err(SwapError::IncorrectMint.into()),
+                shallow.swap_split(
+                    &mut unrelated,
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    amount_in,
+                    0,
                 )
             );
+        }
 
-            accounts.token_a_key = old_a_key;
-            accounts.token_a_account = old_a_account;
-
-            let old_b_key = accounts.token_b_key;
-            let old_b_account = accounts.token_b_account;
-
-            accounts.token_b_key = token_b_key;
-            accounts.token_b_account = token_b_account.clone();
+        let invariant = StableSwap::new(
+            amp_factor * AMP_PRECISION,
+            amp_factor * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let shortfall = |reserves: u64| {
+            let result = invariant
+                .swap_to(amount_in, reserves, reserves, &DEFAULT_TEST_FEES)
+                .unwrap();
+            Some(result.spot_amount_swapped - result.amount_swapped - result.fee)
+        };
+        let amounts = split_by_depth(amount_in, &[shortfall(5_000), shortfall(20_000)]).unwrap();
+        assert!(amounts[1] > amounts[0]);
+        let shallow_result = invariant
+            .swap_to(amounts[0], 5_000, 5_000, &DEFAULT_TEST_FEES)
+            .unwrap();
+        let deep_result = invariant
+            .swap_to(amounts[1], 20_000, 20_000, &DEFAULT_TEST_FEES)
+            .unwrap();
+        let amount_out = shallow_result.amount_swapped + deep_result.amount_swapped;
 
-            // wrong swap token b account
+        // slippage exceeded on the amount of both pools
+        {
+            let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+                shallow.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
             assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.deposit(
-                    &depositor_key,
+                Err(SwapError::ExceededSlippage.into()),
+                shallow.swap_split(
+                    &mut deep,
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
+                    amount_in,
+                    amount_out + 1,
                 )
             );
+        }
 
-            accounts.token_b_key = old_b_key;
-            accounts.token_b_account = old_b_account;
+        // correct split
+        {
+            let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+                shallow.setup_token_accounts(&user_key, &swapper_key, initial_a, 0, 0);
+            shallow
+                .swap_split(
+                    &mut deep,
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    amount_in,
+                    amount_out,
+                )
+                .unwrap();
+
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a - amount_in);
+            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, amount_out);
+            let (_program_id, return_data) = get_return_data().unwrap();
+            let return_data = SwapReturnData::unpack(&return_data).unwrap();
+            assert_eq!(return_data.amount_out, amount_out);
+            assert_eq!(
+                return_data.admin_fee,
+                shallow_result.admin_fee + deep_result.admin_fee
+            );
+
+            for (pool, result) in [(&shallow, shallow_result), (&deep, deep_result)].iter() {
+                let swap_a = utils::unpack_token_account(&pool.token_a_account.data).unwrap();
+                assert_eq!(swap_a.amount, result.new_source_amount);
+                let swap_b = utils::unpack_token_account(&pool.token_b_account.data).unwrap();
+                assert_eq!(swap_b.amount, result.new_destination_amount);
+                let fee_b = utils::unpack_token_account(&pool.admin_fee_b_account.data).unwrap();
+                assert_eq!(fee_b.amount, result.admin_fee);
+            }
         }
+    }
+
+    #[test]
+    fn test_same_slot_guard() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let other_swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let amount_in = 1_000;
+        let slot = 10;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, amount_in, amount_in, 0);
+        let (
+            other_token_a_key,
+            mut other_token_a_account,
+            other_token_b_key,
+            mut other_token_b_account,
+            _,
+            _,
+        ) = accounts.setup_token_accounts(&user_key, &other_swapper_key, amount_in, amount_in, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let mut last_trade_account = Account::new(1, LastTrade::LEN, &SWAP_PROGRAM_ID);
+        accounts
+            .set_same_slot_guard(&mut last_trade_account, true)
+            .unwrap();
+
+        // the last trade must be passed
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in / 2,
+                0,
+            )
+        );
+
+        accounts
+            .swap_with_same_slot_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &mut last_trade_account,
+                slot,
+                amount_in / 2,
+            )
+            .unwrap();
+        let last_trade = LastTrade::unpack(&last_trade_account.data).unwrap();
+        assert_eq!(last_trade.slot, slot);
+        assert_eq!(
+            last_trade.trades(),
+            &[SlotTrade {
+                trader: swapper_key,
+                a_to_b: true,
+            }]
+        );
+
+        // the same trader swaps again the same way
+        accounts
+            .swap_with_same_slot_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &mut last_trade_account,
+                slot,
+                amount_in / 2,
+            )
+            .unwrap();
+
+        // but not back in the same slot
+        assert_eq!(
+            Err(SwapError::SameSlotReversal.into()),
+            accounts.swap_with_same_slot_guard(
+                &swapper_key,
+                (&token_b_key, &mut token_b_account),
+                &swap_token_b_key,
+                &swap_token_a_key,
+                (&token_a_key, &mut token_a_account),
+                &mut last_trade_account,
+                slot,
+                amount_in / 2,
+            )
+        );
+
+        // other traders swap back in the same slot
+        accounts
+            .swap_with_same_slot_guard(
+                &other_swapper_key,
+                (&other_token_b_key, &mut other_token_b_account),
+                &swap_token_b_key,
+                &swap_token_a_key,
+                (&other_token_a_key, &mut other_token_a_account),
+                &mut last_trade_account,
+                slot,
+                amount_in / 2,
+            )
+            .unwrap();
+        assert_eq!(
+            LastTrade::unpack(&last_trade_account.data)
+                .unwrap()
+                .trades()
+                .len(),
+            2
+        );
+
+        // a swap of another trader between the two legs keeps the first leg recorded
+        assert_eq!(
+            Err(SwapError::SameSlotReversal.into()),
+            accounts.swap_with_same_slot_guard(
+                &swapper_key,
+                (&token_b_key, &mut token_b_account),
+                &swap_token_b_key,
+                &swap_token_a_key,
+                (&token_a_key, &mut token_a_account),
+                &mut last_trade_account,
+                slot,
+                amount_in / 2,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::SameSlotReversal.into()),
+            accounts.swap_with_same_slot_guard(
+                &other_swapper_key,
+                (&other_token_a_key, &mut other_token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&other_token_b_key, &mut other_token_b_account),
+                &mut last_trade_account,
+                slot,
+                amount_in / 2,
+            )
+        );
+
+        // and the trader swaps back in a later slot
+        accounts
+            .swap_with_same_slot_guard(
+                &swapper_key,
+                (&token_b_key, &mut token_b_account),
+                &swap_token_b_key,
+                &swap_token_a_key,
+                (&token_a_key, &mut token_a_account),
+                &mut last_trade_account,
+                slot + 1,
+                amount_in / 2,
+            )
+            .unwrap();
+
+        // the guard is off once disabled
+        accounts
+            .set_same_slot_guard(&mut last_trade_account, false)
+            .unwrap();
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in / 4,
+                0,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_admin_fees_as_lp() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let amount_in = 10_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, amount_in, 0, 0);
+        // owned by the owner of the admin fee accounts
+        let (admin_pool_key, mut admin_pool_account) = mint_token(
+            &spl_token::id(),
+            &accounts.pool_mint_key,
+            &mut accounts.pool_mint_account,
+            &accounts.authority_key,
+            &accounts.authority_key,
+            0,
+        );
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts.set_admin_fees_as_lp(true).unwrap();
+
+        // the admin pool token account must be passed
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+        );
+
+        // swap: the admin fee stays in the reserves
+        let initial_supply = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+        accounts
+            .swap_with_admin_fees_as_lp(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                (&admin_pool_key, &mut admin_pool_account),
+                amount_in,
+            )
+            .unwrap();
+        let amount_out = SplAccount::unpack(&token_b_account.data).unwrap().amount;
+        let result = SwapReturnData::unpack(&get_return_data().unwrap().1).unwrap();
+        assert_eq!(result.amount_out, amount_out);
+        assert!(result.admin_fee > 0);
+        let admin_fee_b = SplAccount::unpack(&accounts.admin_fee_b_account.data).unwrap();
+        assert_eq!(admin_fee_b.amount, 0);
+        let swap_token_b = SplAccount::unpack(&accounts.token_b_account.data).unwrap();
+        assert_eq!(swap_token_b.amount, token_b_amount - amount_out);
+        let admin_pool_tokens = SplAccount::unpack(&admin_pool_account.data).unwrap().amount;
+        // the pool tokens the fee adds to the invariant
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        let invariant = StableSwap::new(
+            swap_info.initial_amp_factor,
+            swap_info.target_amp_factor,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        assert_eq!(
+            Some(admin_pool_tokens),
+            invariant.compute_admin_fee_mint_amount(
+                0,
+                result.admin_fee,
+                token_a_amount + amount_in,
+                swap_token_b.amount,
+                initial_supply
+            )
+        );
+        let supply = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+        assert_eq!(supply, initial_supply + admin_pool_tokens);
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.pool_token_supply, supply);
+
+        // withdraw: the admin withdraw fees stay in the reserves too
+        let withdraw_amount = initial_supply / 10;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, 0, 0, withdraw_amount);
+        let swap_token_a = SplAccount::unpack(&accounts.token_a_account.data).unwrap();
+        accounts
+            .withdraw_with_admin_fees_as_lp(
+                &swapper_key,
+                (&pool_key, &mut pool_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                (&admin_pool_key, &mut admin_pool_account),
+                withdraw_amount,
+            )
+            .unwrap();
+        let withdrawn = WithdrawReturnData::unpack(&get_return_data().unwrap().1).unwrap();
+        assert!(withdrawn.token_a_fee > 0);
+        let admin_fee_a = SplAccount::unpack(&accounts.admin_fee_a_account.data).unwrap();
+        assert_eq!(admin_fee_a.amount, 0);
+        assert_eq!(
+            SplAccount::unpack(&accounts.token_a_account.data)
+                .unwrap()
+                .amount,
+            swap_token_a.amount - withdrawn.token_a_amount
+        );
+        assert!(SplAccount::unpack(&admin_pool_account.data).unwrap().amount > admin_pool_tokens);
+        let supply_after_withdraw =
+            spl_token::state::Mint::unpack(&accounts.pool_mint_account.data)
+                .unwrap()
+                .supply;
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.pool_token_supply, supply_after_withdraw);
+
+        // back to transferring the admin fees
+        accounts.set_admin_fees_as_lp(false).unwrap();
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, amount_in, 0, 0);
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+            .unwrap();
+        let admin_fee_b = SplAccount::unpack(&accounts.admin_fee_b_account.data).unwrap();
+        assert!(admin_fee_b.amount > 0);
+    }
+
+    #[test]
+    fn test_cpi_guard() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let amount_in = 1_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, amount_in * 4, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let allowed_program_id = pubkey_rand();
+        let other_program_id = pubkey_rand();
+        let (cpi_allowlist_key, _bump_seed) =
+            find_cpi_allowlist_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut cpi_allowlist_account = Account::new(1, CpiAllowlist::LEN, &SWAP_PROGRAM_ID);
+
+        // any program may invoke the swap by default
+        accounts
+            .swap_with_cpi_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &other_program_id,
+                &mut Account::default(),
+                amount_in,
+            )
+            .unwrap();
+
+        accounts.set_cpi_policy(CpiPolicy::TopLevelOnly).unwrap();
+
+        // the instructions sysvar must be passed
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+        );
+
+        // other programs cannot invoke the swap
+        assert_eq!(
+            Err(SwapError::CpiNotAllowed.into()),
+            accounts.swap_with_cpi_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &allowed_program_id,
+                &mut Account::default(),
+                amount_in,
+            )
+        );
+
+        // top-level instructions can
+        accounts
+            .swap_with_cpi_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &SWAP_PROGRAM_ID,
+                &mut Account::default(),
+                amount_in,
+            )
+            .unwrap();
+
+        accounts
+            .set_cpi_allowlist(
+                &cpi_allowlist_key,
+                &mut cpi_allowlist_account,
+                &[&allowed_program_id],
+            )
+            .unwrap();
+        accounts.set_cpi_policy(CpiPolicy::AllowedCallers).unwrap();
+
+        // the allowlist must be owned by the swap program
+        assert_eq!(
+            Err(ProgramError::IncorrectProgramId),
+            accounts.swap_with_cpi_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &allowed_program_id,
+                &mut Account::default(),
+                amount_in,
+            )
+        );
+
+        // programs not on the allowlist cannot invoke the swap
+        assert_eq!(
+            Err(SwapError::CpiNotAllowed.into()),
+            accounts.swap_with_cpi_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &other_program_id,
+                &mut cpi_allowlist_account,
+                amount_in,
+            )
+        );
+
+        // allowed programs can
+        accounts
+            .swap_with_cpi_guard(
+                &swapper_key,
+                (&token_a_key, &mut token_a_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                &allowed_program_id,
+                &mut cpi_allowlist_account,
+                amount_in,
+            )
+            .unwrap();
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, amount_in);
+    }
+
+    #[test]
+    fn test_deposit_native_sol() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let (native_mint_key, native_mint_account) = create_native_mint();
+        let mut accounts = SwapAccountInfo::new_with_token_a_mint(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+            &native_mint_key,
+            native_mint_account,
+        );
+        accounts.initialize_swap().unwrap();
+
+        let deposit_a = token_a_amount / 10;
+        let deposit_b = token_b_amount / 10;
+        let depositor_lamports = deposit_a * 2;
+        let mut depositor_account = Account::new(depositor_lamports, 0, &system_program::id());
+        let (
+            wsol_key,
+            mut wsol_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, 0, deposit_b, 0);
+        let wsol_rent = wsol_account.lamports;
+
+        accounts
+            .deposit_with_native_sol(
+                (&depositor_key, &mut depositor_account),
+                (&wsol_key, &mut wsol_account),
+                (&token_b_key, &mut token_b_account),
+                (&pool_key, &mut pool_account),
+                deposit_a,
+                deposit_b,
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            depositor_account.lamports,
+            depositor_lamports - deposit_a + wsol_rent
+        );
+        assert_eq!(wsol_account.lamports, 0);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount + deposit_a);
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, 0);
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert!(pool_token.amount > 0);
+    }
 
-        // wrong mint
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let (pool_mint_key, pool_mint_account) = create_mint(
-                &spl_token::id(),
-                &accounts.authority_key,
-                DEFAULT_TOKEN_DECIMALS,
-                None,
-            );
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
+    #[test]
+    fn test_deposit_allowlist() {
+        let user_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
 
-            assert_eq!(
-                Err(SwapError::IncorrectMint.into()),
-                accounts.deposit(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
-                )
-            );
+        let allowed_key = pubkey_rand();
+        let other_key = pubkey_rand();
+        let (deposit_allowlist_key, _bump_seed) =
+            find_deposit_allowlist_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut deposit_allowlist_account =
+            Account::new(1, DepositAllowlist::LEN, &SWAP_PROGRAM_ID);
+        accounts
+            .set_deposit_allowlist(
+                &deposit_allowlist_key,
+                &mut deposit_allowlist_account,
+                &[&allowed_key],
+            )
+            .unwrap();
+        accounts.set_deposit_allowlist_enabled(true).unwrap();
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
-        }
+        let deposit_a = token_a_amount / 10;
+        let deposit_b = token_b_amount / 10;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &other_key, deposit_a, deposit_b, 0);
+
+        // the allowlist must be passed
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.deposit(
+                &other_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                deposit_a,
+                deposit_b,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.zap_in(
+                &other_key,
+                &token_a_key,
+                &mut token_a_account,
+                true,
+                &pool_key,
+                &mut pool_account,
+                deposit_a,
+                0,
+            )
+        );
 
-        // slippage exceeded
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            // min mint_amount in too high
-            let high_min_mint_amount = 10000000000000;
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.deposit(
-                    &depositor_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    deposit_a,
-                    deposit_b,
-                    high_min_mint_amount,
-                )
-            );
-        }
+        // depositors not on the list cannot deposit
+        assert_eq!(
+            Err(SwapError::NotAllowlisted.into()),
+            accounts.deposit_with_deposit_allowlist(
+                &other_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut deposit_allowlist_account,
+                deposit_a,
+                deposit_b,
+                0,
+            )
+        );
 
-        // correctly deposit
+        // allowed depositors can
         {
             let (
                 token_a_key,
@@ -1876,123 +9024,264 @@ mod tests {
                 mut token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            ) = accounts.setup_token_accounts(&user_key, &allowed_key, deposit_a, deposit_b, 0);
             accounts
-                .deposit(
-                    &depositor_key,
+                .deposit_with_deposit_allowlist(
+                    &allowed_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
                     &pool_key,
                     &mut pool_account,
+                    &mut deposit_allowlist_account,
                     deposit_a,
                     deposit_b,
-                    min_mint_amount,
+                    0,
                 )
                 .unwrap();
-
-            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-            assert_eq!(swap_token_a.amount, deposit_a + token_a_amount);
-            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-            assert_eq!(swap_token_b.amount, deposit_b + token_b_amount);
-            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, 0);
-            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
-            assert_eq!(token_b.amount, 0);
-            let pool_account = utils::unpack_token_account(&pool_account.data).unwrap();
-            let swap_pool_account =
-                utils::unpack_token_account(&accounts.pool_token_account.data).unwrap();
-            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
-            // XXX: Revisit and make sure amount of LP tokens minted is corrected.
-            assert_eq!(
-                pool_mint.supply,
-                pool_account.amount + swap_pool_account.amount
-            );
         }
 
-        // Pool is paused
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            // Pause pool
-            accounts.pause().unwrap();
+        // swaps stay permissionless
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &other_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                deposit_a / 2,
+                0,
+            )
+            .unwrap();
 
-            assert_eq!(
-                Err(SwapError::IsPaused.into()),
-                accounts.deposit(
-                    &depositor_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    deposit_a,
-                    deposit_b,
-                    min_mint_amount,
-                )
-            );
-        }
+        accounts.set_deposit_allowlist_enabled(false).unwrap();
+        accounts
+            .deposit(
+                &other_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                deposit_a / 2,
+                deposit_b / 2,
+                0,
+            )
+            .unwrap();
     }
 
     #[test]
-    fn test_withdraw() {
+    fn test_deposit_caps() {
         let user_key = pubkey_rand();
-        let amp_factor = MIN_AMP;
-        let token_a_amount = 1000;
-        let token_b_amount = 2000;
+        let depositor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
         let mut accounts = SwapAccountInfo::new(
             &user_key,
-            amp_factor,
+            MIN_AMP,
             token_a_amount,
             token_b_amount,
             DEFAULT_TEST_FEES,
         );
-        let withdrawer_key = pubkey_rand();
-        let initial_a = token_a_amount / 10;
-        let initial_b = token_b_amount / 10;
-        let initial_pool = INITIAL_SWAP_POOL_AMOUNT;
-        let withdraw_amount = initial_pool / 4;
-        let minimum_a_amount = initial_a / 40;
-        let minimum_b_amount = initial_b / 40;
+        accounts.initialize_swap().unwrap();
 
-        // swap not initialized
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
-            );
-        }
+        let pool_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+        let mut deposit_caps_account = Account::new(1, DepositCaps::LEN, &SWAP_PROGRAM_ID);
+        // room for about a tenth of the liquidity
+        accounts
+            .set_max_lp_supply(&mut deposit_caps_account, pool_supply + pool_supply / 10)
+            .unwrap();
+
+        let deposit_a = token_a_amount / 5;
+        let deposit_b = token_b_amount / 5;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+
+        // the deposit caps must be passed
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                deposit_a,
+                deposit_b,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.zap_in(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                true,
+                &pool_key,
+                &mut pool_account,
+                deposit_a,
+                0,
+            )
+        );
+
+        // deposits over the cap fail
+        assert_eq!(
+            Err(SwapError::DepositCapExceeded.into()),
+            accounts.deposit_with_deposit_caps(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut deposit_caps_account,
+                deposit_a,
+                deposit_b,
+                0,
+            )
+        );
+
+        // deposits under it go through
+        accounts
+            .deposit_with_deposit_caps(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut deposit_caps_account,
+                deposit_a / 4,
+                deposit_b / 4,
+                0,
+            )
+            .unwrap();
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert!(pool_token.amount > 0);
+
+        // capping the balance of depositors instead
+        accounts
+            .set_max_lp_supply(&mut deposit_caps_account, 0)
+            .unwrap();
+        accounts
+            .set_max_lp_per_owner(&mut deposit_caps_account, pool_token.amount + 1, &[])
+            .unwrap();
+        assert_eq!(
+            Err(SwapError::DepositCapExceeded.into()),
+            accounts.deposit_with_deposit_caps(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut deposit_caps_account,
+                deposit_a / 4,
+                deposit_b / 4,
+                0,
+            )
+        );
+        // unless the depositor is exempt
+        accounts
+            .set_max_lp_per_owner(
+                &mut deposit_caps_account,
+                pool_token.amount + 1,
+                &[&depositor_key],
+            )
+            .unwrap();
+        accounts
+            .deposit_with_deposit_caps(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut deposit_caps_account,
+                deposit_a / 4,
+                deposit_b / 4,
+                0,
+            )
+            .unwrap();
+
+        // lifting the caps
+        accounts
+            .set_max_lp_per_owner(&mut deposit_caps_account, 0, &[])
+            .unwrap();
+        accounts
+            .deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                deposit_a / 2,
+                deposit_b / 2,
+                0,
+            )
+            .unwrap();
+    }
 
+    #[test]
+    fn test_fee_exemptions() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP;
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
         accounts.initialize_swap().unwrap();
 
-        // wrong nonce for authority_key
+        let exempt_key = pubkey_rand();
+        let charged_key = pubkey_rand();
+        let (fee_exemptions_key, _bump_seed) =
+            find_fee_exemptions_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut fee_exemptions_account = Account::new(1, FeeExemptions::LEN, &SWAP_PROGRAM_ID);
+        accounts
+            .set_fee_exemptions(
+                &fee_exemptions_key,
+                &mut fee_exemptions_account,
+                &[&exempt_key],
+            )
+            .unwrap();
+        let invariant = StableSwap::new(
+            amp_factor * AMP_PRECISION,
+            amp_factor * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let deposit_a = token_a_amount / 10;
+
+        // wrong fee exemptions account
         {
             let (
                 token_a_key,
@@ -2001,32 +9290,27 @@ mod tests {
                 mut token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
-                &spl_token::id(),
-            );
-            accounts.authority_key = bad_authority_key;
+            ) = accounts.setup_token_accounts(&user_key, &exempt_key, deposit_a, 0, 0);
             assert_eq!(
                 Err(SwapError::InvalidProgramAddress.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.deposit_with_fee_exemptions(
+                    &exempt_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
+                    &pool_key,
+                    &mut pool_account,
+                    &pubkey_rand(),
+                    &mut fee_exemptions_account.clone(),
+                    deposit_a,
+                    0,
+                    0,
                 )
             );
-            accounts.authority_key = old_authority;
         }
 
-        // not enough pool tokens
+        // accounts not on the list pay the trade fee on imbalanced deposits
         {
             let (
                 token_a_key,
@@ -2035,906 +9319,2342 @@ mod tests {
                 mut token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount / 2,
-            );
-            assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &charged_key, deposit_a, 0, 0);
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            let mint_amount = invariant
+                .compute_mint_amount_for_deposit(
+                    deposit_a,
+                    0,
+                    token_a_amount,
+                    token_b_amount,
+                    pool_mint.supply,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+            accounts
+                .deposit_with_fee_exemptions(
+                    &charged_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount / 2,
-                    minimum_b_amount / 2,
+                    &pool_key,
+                    &mut pool_account,
+                    &fee_exemptions_key,
+                    &mut fee_exemptions_account,
+                    deposit_a,
+                    0,
+                    0,
                 )
-            );
+                .unwrap();
+            let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+            assert_eq!(pool_token.amount, mint_amount);
         }
 
-        // wrong token a / b accounts
+        // exempt accounts don't
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &exempt_key, deposit_a, 0, 0);
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            let mint_amount = invariant
+                .compute_mint_amount_for_deposit(
+                    deposit_a,
+                    0,
+                    swap_token_a.amount,
+                    swap_token_b.amount,
+                    pool_mint.supply,
+                    &Fees {
+                        trade_fee_numerator: 0,
+                        ..DEFAULT_TEST_FEES
+                    },
+                )
+                .unwrap();
+            accounts
+                .deposit_with_fee_exemptions(
+                    &exempt_key,
                     &token_a_key,
                     &mut token_a_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    &fee_exemptions_key,
+                    &mut fee_exemptions_account,
+                    deposit_a,
+                    0,
+                    0,
                 )
-            );
+                .unwrap();
+            let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+            assert_eq!(pool_token.amount, mint_amount);
         }
 
-        // wrong admin a / b accounts
+        // exempt accounts don't pay the withdraw fee
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-            let (
-                wrong_admin_a_key,
-                wrong_admin_a_account,
-                wrong_admin_b_key,
-                wrong_admin_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-
-            let old_admin_a_key = accounts.admin_fee_a_key;
-            let old_admin_a_account = accounts.admin_fee_a_account;
-            accounts.admin_fee_a_key = wrong_admin_a_key;
-            accounts.admin_fee_a_account = wrong_admin_a_account;
-
-            assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
+            let withdraw_amount = utils::unpack_token_account(&pool_account.data)
+                .unwrap()
+                .amount;
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            let (dy, _dy_fee) = invariant
+                .compute_withdraw_one(
+                    withdraw_amount,
+                    pool_mint.supply,
+                    swap_token_a.amount,
+                    swap_token_b.amount,
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+            accounts
+                .withdraw_one_with_fee_exemptions(
+                    &exempt_key,
                     &pool_key,
                     &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
                     &token_a_key,
                     &mut token_a_account,
+                    &fee_exemptions_key,
+                    &mut fee_exemptions_account,
                     withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
+                    dy,
                 )
-            );
+                .unwrap();
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, dy);
+        }
+    }
 
-            accounts.admin_fee_a_key = old_admin_a_key;
-            accounts.admin_fee_a_account = old_admin_a_account;
+    #[test]
+    fn test_associated_token_accounts() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, MIN_AMP, 1_000_000, 1_000_000, DEFAULT_TEST_FEES);
+        accounts.initialize_swap().unwrap();
 
-            let old_admin_b_key = accounts.admin_fee_b_key;
-            let old_admin_b_account = accounts.admin_fee_b_account;
-            accounts.admin_fee_b_key = wrong_admin_b_key;
-            accounts.admin_fee_b_account = wrong_admin_b_account;
+        let deposit_amount = 10_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &depositor_key,
+            deposit_amount * 2,
+            deposit_amount * 2,
+            0,
+        );
+        let missing_token_account = || Account::new(0, SplAccount::LEN, &system_program::id());
 
-            assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
-            );
+        // not the associated token account of the depositor
+        assert_eq!(
+            Err(SwapError::InvalidAssociatedTokenAccount.into()),
+            accounts.deposit_with_associated_token_accounts(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pubkey_rand(),
+                &mut missing_token_account(),
+                deposit_amount,
+                deposit_amount,
+                0,
+            )
+        );
+
+        let (pool_key, _bump_seed) =
+            find_associated_token_address(&depositor_key, &accounts.pool_mint_key);
+        let mut pool_account = missing_token_account();
+        accounts
+            .deposit_with_associated_token_accounts(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                deposit_amount,
+                deposit_amount,
+                0,
+            )
+            .unwrap();
+        assert_eq!(pool_account.owner, spl_token::id());
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.mint, accounts.pool_mint_key);
+        assert_eq!(pool_token.owner, depositor_key);
+        assert!(pool_token.amount > 0);
+
+        // existing accounts are used as is
+        accounts
+            .deposit_with_associated_token_accounts(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                deposit_amount,
+                deposit_amount,
+                0,
+            )
+            .unwrap();
+        let pool_amount = utils::unpack_token_account(&pool_account.data)
+            .unwrap()
+            .amount;
+        assert!(pool_amount > pool_token.amount);
+
+        let (dest_a_key, _bump_seed) =
+            find_associated_token_address(&depositor_key, &accounts.token_a_mint_key);
+        let (dest_b_key, _bump_seed) =
+            find_associated_token_address(&depositor_key, &accounts.token_b_mint_key);
+        let mut dest_a_account = missing_token_account();
+        let mut dest_b_account = missing_token_account();
+        accounts
+            .withdraw_with_associated_token_accounts(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &dest_a_key,
+                &mut dest_a_account,
+                &dest_b_key,
+                &mut dest_b_account,
+                pool_amount,
+                0,
+                0,
+            )
+            .unwrap();
+        let dest_a = utils::unpack_token_account(&dest_a_account.data).unwrap();
+        let dest_b = utils::unpack_token_account(&dest_b_account.data).unwrap();
+        assert_eq!(dest_a.mint, accounts.token_a_mint_key);
+        assert_eq!(dest_b.owner, depositor_key);
+        assert!(dest_a.amount > 0);
+        assert!(dest_b.amount > 0);
+    }
+
+    #[test]
+    fn test_donate_to_reserves() {
+        let user_key = pubkey_rand();
+        let donor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        let donation = 10_000;
+        let (
+            source_a_key,
+            mut source_a_account,
+            source_b_key,
+            mut source_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &donor_key, donation, donation, 0);
+        let pool_mint_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+
+        // reserves cannot be the source
+        let swap_token_a_key = accounts.token_a_key;
+        let mut swap_token_a_account = accounts.token_a_account.clone();
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.donate_to_reserves(
+                &donor_key,
+                &swap_token_a_key,
+                &mut swap_token_a_account,
+                &source_b_key,
+                &mut source_b_account,
+                donation,
+                0,
+            )
+        );
+
+        accounts
+            .donate_to_reserves(
+                &donor_key,
+                &source_a_key,
+                &mut source_a_account,
+                &source_b_key,
+                &mut source_b_account,
+                donation,
+                0,
+            )
+            .unwrap();
+        let token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert_eq!(token_a.amount, token_a_amount + donation);
+        assert_eq!(token_b.amount, token_b_amount);
+        assert_eq!(
+            utils::unpack_mint(&accounts.pool_mint_account.data)
+                .unwrap()
+                .supply,
+            pool_mint_supply
+        );
+
+        // admin only
+        accounts.set_donations_admin_only(true).unwrap();
+        assert!(
+            SwapInfo::unpack(&accounts.swap_account.data)
+                .unwrap()
+                .donations_admin_only
+        );
+        assert_eq!(
+            Err(SwapError::Unauthorized.into()),
+            accounts.donate_to_reserves(
+                &donor_key,
+                &source_a_key,
+                &mut source_a_account,
+                &source_b_key,
+                &mut source_b_account,
+                0,
+                donation,
+            )
+        );
+
+        let admin_key = accounts.admin_key;
+        let (
+            admin_a_key,
+            mut admin_a_account,
+            admin_b_key,
+            mut admin_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &admin_key, 0, donation, 0);
+        accounts
+            .donate_to_reserves(
+                &admin_key,
+                &admin_a_key,
+                &mut admin_a_account,
+                &admin_b_key,
+                &mut admin_b_account,
+                0,
+                donation,
+            )
+            .unwrap();
+        let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, token_b_amount + donation);
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.reserve_amount_a, token_a_amount + donation);
+        assert_eq!(swap_info.reserve_amount_b, token_b_amount + donation);
+    }
+
+    #[test]
+    fn test_sync_reserves() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let assert_reserves_tracked = |accounts: &SwapAccountInfo| {
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            let token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_info.reserve_amount_a, token_a.amount);
+            assert_eq!(swap_info.reserve_amount_b, token_b.amount);
+        };
+        assert_reserves_tracked(&accounts);
+
+        // instructions moving the reserves keep them tracked
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, 100_000, 100_000, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                10_000,
+                0,
+            )
+            .unwrap();
+        assert_reserves_tracked(&accounts);
+        accounts
+            .deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                10_000,
+                10_000,
+                0,
+            )
+            .unwrap();
+        assert_reserves_tracked(&accounts);
+        let pool_amount = utils::unpack_token_account(&pool_account.data)
+            .unwrap()
+            .amount;
+        accounts
+            .withdraw(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                pool_amount / 2,
+                0,
+                0,
+            )
+            .unwrap();
+        assert_reserves_tracked(&accounts);
+
+        // tokens sent straight to a reserve are only counted once synced
+        let transferred = 5_000;
+        let tracked = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        let mut token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        token_a.amount += transferred;
+        SplAccount::pack(token_a, &mut accounts.token_a_account.data).unwrap();
+        assert_eq!(
+            SwapInfo::unpack(&accounts.swap_account.data)
+                .unwrap()
+                .reserve_amount_a,
+            tracked.reserve_amount_a
+        );
 
-            accounts.admin_fee_b_key = old_admin_b_key;
-            accounts.admin_fee_b_account = old_admin_b_account;
+        // wrong reserves
+        {
+            let old_key = accounts.token_a_key;
+            accounts.token_a_key = accounts.token_b_key;
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.sync_reserves()
+            );
+            accounts.token_a_key = old_key;
         }
 
-        // wrong pool token account
+        // legacy accounts have no room for the reserve amounts
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-            let (
-                wrong_pool_key,
-                mut wrong_pool_account,
-                _token_b_key,
-                _token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                withdraw_amount,
-                initial_b,
-                withdraw_amount,
-            );
+            let current = accounts.swap_account.data.clone();
+            let swap_info = SwapInfo::unpack(&current).unwrap();
+            accounts.swap_account.data = vec![0; SWAP_INFO_LEGACY_LEN];
+            SwapInfo::pack(swap_info, &mut accounts.swap_account.data).unwrap();
             assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &wrong_pool_key,
-                    &mut wrong_pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
+                Err(ProgramError::AccountDataTooSmall),
+                accounts.sync_reserves()
             );
+            accounts.swap_account.data = current;
         }
 
-        // wrong token program id
+        accounts.sync_reserves().unwrap();
+        assert_reserves_tracked(&accounts);
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            swap_info.reserve_amount_a,
+            tracked.reserve_amount_a + transferred
+        );
+        assert_eq!(swap_info.reserve_amount_b, tracked.reserve_amount_b);
+    }
+
+    #[test]
+    fn test_refresh_peg_rate() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let fees = Fees {
+            trade_fee_numerator: 0,
+            withdraw_fee_numerator: 0,
+            ..DEFAULT_TEST_FEES
+        };
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, 1_000, 1_050_000_000, 1_000_000_000, fees);
+        accounts.initialize_swap().unwrap();
+        let (peg_config_key, _bump_seed) =
+            find_peg_config_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut peg_config_account = Account::new(1, PegConfig::LEN, &SWAP_PROGRAM_ID);
+        let price_key = pubkey_rand();
+        let slot = 1_000;
+        let mut price_account = pyth_price_account(105, -2, slot);
+
+        // no peg config yet
+        assert_eq!(
+            Err(ProgramError::UninitializedAccount),
+            accounts.refresh_peg_rate(
+                &peg_config_key,
+                &mut peg_config_account,
+                &price_key,
+                &mut price_account,
+                slot,
+            )
+        );
+
+        accounts
+            .set_peg_rate_source(
+                &peg_config_key,
+                &mut peg_config_account,
+                PegRateSource::PythPrice,
+                &price_key,
+                &mut price_account,
+                slot,
+            )
+            .unwrap();
+
+        // token B is worth 1.05 token A, so the reserves are balanced
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, 0, 1_000_000, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &swapper_key,
+                &token_b_key,
+                &mut token_b_account,
+                &swap_token_b_key,
+                &swap_token_a_key,
+                &token_a_key,
+                &mut token_a_account,
+                1_000_000,
+                0,
+            )
+            .unwrap();
+        let amount_out = utils::unpack_token_account(&token_a_account.data)
+            .unwrap()
+            .amount;
+        assert!(
+            (1_049_000..=1_050_000).contains(&amount_out),
+            "{}",
+            amount_out
+        );
+
+        // wrong rate account
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-            let wrong_key = pubkey_rand();
+            let mut other_price_account = pyth_price_account(110, -2, slot);
             assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                do_process_instruction(
-                    withdraw(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_key,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &withdrawer_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_a_key,
-                        &token_b_key,
-                        &accounts.admin_fee_a_key,
-                        &accounts.admin_fee_b_key,
-                        withdraw_amount,
-                        minimum_a_amount,
-                        minimum_b_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_a_account,
-                        &mut token_b_account,
-                        &mut accounts.admin_fee_a_account,
-                        &mut accounts.admin_fee_b_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
+                Err(SwapError::InvalidOracle.into()),
+                accounts.refresh_peg_rate(
+                    &peg_config_key,
+                    &mut peg_config_account,
+                    &pubkey_rand(),
+                    &mut other_price_account,
+                    slot,
                 )
             );
         }
 
-        // wrong swap token accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
-            );
+        // wrong peg config address
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            accounts.refresh_peg_rate(
+                &pubkey_rand(),
+                &mut peg_config_account,
+                &price_key,
+                &mut price_account,
+                slot,
+            )
+        );
 
-            let old_a_key = accounts.token_a_key;
-            let old_a_account = accounts.token_a_account;
+        // stale price
+        assert_eq!(
+            Err(SwapError::InvalidOracle.into()),
+            accounts.refresh_peg_rate(
+                &peg_config_key,
+                &mut peg_config_account,
+                &price_key,
+                &mut price_account,
+                slot + MAX_PRICE_AGE_SLOTS + 1,
+            )
+        );
+
+        // anyone refreshes the rate once the price moves
+        let mut price_account = pyth_price_account(106, -2, slot + 10);
+        accounts
+            .refresh_peg_rate(
+                &peg_config_key,
+                &mut peg_config_account,
+                &price_key,
+                &mut price_account,
+                slot + 10,
+            )
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.peg_rate, 1_060_000_000);
+        let peg_config = PegConfig::unpack(&peg_config_account.data).unwrap();
+        assert_eq!(peg_config.rate_updated_slot, slot + 10);
+    }
+
+    #[test]
+    fn test_lp_position_fees() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let initial_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+        assert_eq!(
+            SwapInfo::unpack(&accounts.swap_account.data)
+                .unwrap()
+                .pool_token_supply,
+            initial_supply
+        );
+
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &depositor_key,
+            token_a_amount,
+            token_b_amount,
+            0,
+        );
+        let mut lp_position_account = Account::new(1, LpPosition::LEN, &SWAP_PROGRAM_ID);
+
+        // the position must belong to the owner of the pool tokens
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            accounts.sync_lp_position(
+                &swapper_key,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+            )
+        );
+        accounts
+            .sync_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+            )
+            .unwrap();
+        accounts
+            .deposit_with_lp_position(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+                token_a_amount,
+                token_b_amount,
+                0,
+            )
+            .unwrap();
+        let minted = utils::unpack_token_account(&pool_account.data)
+            .unwrap()
+            .amount;
+        let lp_position = LpPosition::unpack(&lp_position_account.data).unwrap();
+        assert_eq!(lp_position.pool_token_amount, minted);
+        assert_eq!(lp_position.fees_earned_b, 0);
+        let token_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(token_swap.pool_token_supply, initial_supply + minted);
+
+        // the depositor owns half of the pool and earns half of the LP fees of a swap
+        let swap_amount = 100_000;
+        let (
+            swapper_a_key,
+            mut swapper_a_account,
+            swapper_b_key,
+            mut swapper_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, swap_amount, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &swapper_key,
+                &swapper_a_key,
+                &mut swapper_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &swapper_b_key,
+                &mut swapper_b_account,
+                swap_amount,
+                0,
+            )
+            .unwrap();
+        let result = SwapReturnData::unpack(&get_return_data().unwrap().1).unwrap();
+        let lp_fee = result.fee - result.admin_fee;
+        let token_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(token_swap.fee_growth_a, 0);
+        assert_eq!(
+            token_swap.fee_growth_b,
+            (u128::from(lp_fee) << 64) / u128::from(initial_supply + minted)
+        );
+
+        accounts
+            .sync_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+            )
+            .unwrap();
+        let lp_position = LpPosition::unpack(&lp_position_account.data).unwrap();
+        assert_eq!(lp_position.fees_earned_a, 0);
+        assert!(lp_position.fees_earned_b <= lp_fee * minted / (initial_supply + minted));
+        assert!(lp_position.fees_earned_b + 1 >= lp_fee * minted / (initial_supply + minted));
+        assert_eq!(lp_position.fee_growth_b_checkpoint, token_swap.fee_growth_b);
+
+        // syncing again earns nothing more
+        accounts
+            .sync_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+            )
+            .unwrap();
+        assert_eq!(
+            LpPosition::unpack(&lp_position_account.data).unwrap(),
+            lp_position
+        );
+    }
+
+    #[test]
+    fn test_withdraw_fee_grace_period() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let grace_period = 86_400;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        accounts
+            .set_withdraw_fee_grace_period(grace_period)
+            .unwrap();
+
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &depositor_key,
+            token_a_amount,
+            token_b_amount,
+            0,
+        );
+        let mut lp_position_account = Account::new(1, LpPosition::LEN, &SWAP_PROGRAM_ID);
+        accounts
+            .sync_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+            )
+            .unwrap();
+        accounts
+            .deposit_with_lp_position(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                &mut lp_position_account,
+                token_a_amount,
+                token_b_amount,
+                0,
+            )
+            .unwrap();
+        let lp_position = LpPosition::unpack(&lp_position_account.data).unwrap();
+        assert_eq!(lp_position.deposit_ts, ZERO_TS);
+        let withdraw_amount = lp_position.pool_token_amount / 4;
+
+        let admin_fees = |accounts: &SwapAccountInfo| {
+            utils::unpack_token_account(&accounts.admin_fee_a_account.data)
+                .unwrap()
+                .amount
+        };
+
+        // young positions pay withdraw fees
+        let admin_fee_before = admin_fees(&accounts);
+        accounts
+            .withdraw_with_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &mut lp_position_account,
+                withdraw_amount,
+                ZERO_TS + grace_period - 1,
+            )
+            .unwrap();
+        assert!(admin_fees(&accounts) > admin_fee_before);
+
+        // positions past the grace period do not
+        let admin_fee_before = admin_fees(&accounts);
+        accounts
+            .withdraw_with_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &mut lp_position_account,
+                withdraw_amount,
+                ZERO_TS + grace_period,
+            )
+            .unwrap();
+        assert_eq!(admin_fees(&accounts), admin_fee_before);
+        let lp_position = LpPosition::unpack(&lp_position_account.data).unwrap();
+        assert_eq!(lp_position.deposit_ts, ZERO_TS);
+
+        // nor is the waiver for more pool tokens than the position holds
+        let mut lp_position = lp_position;
+        lp_position.pool_token_amount = withdraw_amount - 1;
+        LpPosition::pack(lp_position, &mut lp_position_account.data).unwrap();
+        accounts
+            .withdraw_with_lp_position(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &mut lp_position_account,
+                withdraw_amount,
+                ZERO_TS + grace_period,
+            )
+            .unwrap();
+        assert!(admin_fees(&accounts) > admin_fee_before);
+    }
+
+    #[test]
+    fn test_zap_in() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP * 100,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
 
-            accounts.token_a_key = token_a_key;
-            accounts.token_a_account = token_a_account.clone();
+        let amount_in = 100_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, amount_in, amount_in, 0);
+
+        // the source cannot be the reserves
+        let swap_token_a_key = accounts.token_a_key;
+        let mut swap_token_a_account = accounts.token_a_account.clone();
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.zap_in(
+                &depositor_key,
+                &swap_token_a_key,
+                &mut swap_token_a_account,
+                true,
+                &pool_key,
+                &mut pool_account,
+                amount_in,
+                0,
+            )
+        );
+        // the source must hold the input token
+        assert_eq!(
+            Err(TokenError::MintMismatch.into()),
+            accounts.zap_in(
+                &depositor_key,
+                &token_b_key,
+                &mut token_b_account,
+                true,
+                &pool_key,
+                &mut pool_account,
+                amount_in,
+                0,
+            )
+        );
 
-            // wrong swap token a account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
-            );
+        let old_pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        let old_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        let invariant = StableSwap::new(
+            old_swap.initial_amp_factor,
+            old_swap.target_amp_factor,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let (swap_amount, result) = invariant
+            .compute_zap_in_swap(amount_in, token_a_amount, token_b_amount, &old_swap.fees)
+            .unwrap();
+        let expected_mint_amount = invariant
+            .compute_mint_amount_for_deposit(
+                amount_in - swap_amount,
+                result.amount_swapped,
+                result.new_source_amount,
+                result.new_destination_amount,
+                old_pool_mint.supply,
+                &old_swap.fees,
+            )
+            .unwrap();
 
-            accounts.token_a_key = old_a_key;
-            accounts.token_a_account = old_a_account;
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.zap_in(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                true,
+                &pool_key,
+                &mut pool_account,
+                amount_in,
+                expected_mint_amount + 1,
+            )
+        );
+        accounts
+            .zap_in(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                true,
+                &pool_key,
+                &mut pool_account,
+                amount_in,
+                expected_mint_amount,
+            )
+            .unwrap();
 
-            let old_b_key = accounts.token_b_key;
-            let old_b_account = accounts.token_b_account;
+        // the whole input is deposited, minus the admin fee of the swap
+        let deposited = DepositReturnData::unpack(&get_return_data().unwrap().1).unwrap();
+        assert_eq!(deposited.token_a_amount, amount_in - swap_amount);
+        assert_eq!(deposited.token_b_amount, result.amount_swapped);
+        assert_eq!(deposited.pool_token_amount, expected_mint_amount);
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, 0);
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.amount, expected_mint_amount);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount + amount_in);
+        let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert_eq!(swap_token_b.amount, token_b_amount - result.admin_fee);
+        let admin_fee_b = utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+        assert_eq!(admin_fee_b.amount, result.admin_fee);
+        let token_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            token_swap.pool_token_supply,
+            old_pool_mint.supply + expected_mint_amount
+        );
+        assert!(token_swap.fee_growth_b > 0);
+
+        // a zap in is worth a balanced deposit of the same value, minus the fee of its swap
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(
+            &user_key,
+            &depositor_key,
+            amount_in / 2,
+            amount_in / 2,
+            0,
+        );
+        accounts
+            .deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                amount_in / 2,
+                amount_in / 2,
+                0,
+            )
+            .unwrap();
+        let balanced = utils::unpack_token_account(&pool_account.data)
+            .unwrap()
+            .amount;
+        assert!(expected_mint_amount < balanced);
+        assert!(expected_mint_amount > balanced * 95 / 100);
+    }
 
-            accounts.token_b_key = token_b_key;
-            accounts.token_b_account = token_b_account.clone();
+    #[test]
+    fn test_zap_out() {
+        let user_key = pubkey_rand();
+        let withdrawer_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP * 100,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
 
-            // wrong swap token b account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
-            );
+        let pool_amount = 100_000;
+        let (
+            _token_a_key,
+            _token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, pool_amount);
+
+        let old_pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        let old_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        let converter = PoolTokenConverter {
+            supply: old_pool_mint.supply,
+            token_a: token_a_amount,
+            token_b: token_b_amount,
+            fees: &old_swap.fees,
+        };
+        let (a_amount, a_fee, a_admin_fee) = converter.token_a_rate(pool_amount).unwrap();
+        let (b_amount, b_fee, b_admin_fee) = converter.token_b_rate(pool_amount).unwrap();
+        let result = StableSwap::new(
+            old_swap.initial_amp_factor,
+            old_swap.target_amp_factor,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        )
+        .swap_to(
+            a_amount,
+            token_a_amount - a_amount - a_admin_fee,
+            token_b_amount - b_amount - b_admin_fee,
+            &old_swap.fees,
+        )
+        .unwrap();
+        let amount_out = b_amount + result.amount_swapped;
 
-            accounts.token_b_key = old_b_key;
-            accounts.token_b_account = old_b_account;
-        }
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.zap_out(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                false,
+                &token_b_key,
+                &mut token_b_account,
+                pool_amount,
+                amount_out + 1,
+            )
+        );
+        accounts
+            .zap_out(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                false,
+                &token_b_key,
+                &mut token_b_account,
+                pool_amount,
+                amount_out,
+            )
+            .unwrap();
 
-        // wrong mint
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
+        let returned = SwapReturnData::unpack(&get_return_data().unwrap().1).unwrap();
+        assert_eq!(
+            returned,
+            SwapReturnData {
+                amount_out,
+                fee: b_fee + result.fee,
+                admin_fee: b_admin_fee + result.admin_fee,
+                price_impact_bps: result.price_impact_bps().unwrap(),
+            }
+        );
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, amount_out);
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.amount, 0);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount - a_admin_fee);
+        let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert_eq!(
+            swap_token_b.amount,
+            token_b_amount - amount_out - b_admin_fee - result.admin_fee
+        );
+        let admin_fee_a = utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+        assert_eq!(admin_fee_a.amount, a_admin_fee);
+        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.supply, old_pool_mint.supply - pool_amount);
+        let token_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(token_swap.pool_token_supply, pool_mint.supply);
+        assert!(token_swap.fee_growth_a > 0 && token_swap.fee_growth_b > 0);
+        assert!(a_fee > 0);
+
+        // zapping out is paused like swaps
+        accounts.pause().unwrap();
+        assert_eq!(
+            Err(SwapError::IsPaused.into()),
+            accounts.zap_out(
                 &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
-            );
-            let (pool_mint_key, pool_mint_account) = create_mint(
-                &spl_token::id(),
-                &accounts.authority_key,
-                DEFAULT_TOKEN_DECIMALS,
-                None,
-            );
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
+                &pool_key,
+                &mut pool_account,
+                false,
+                &token_b_key,
+                &mut token_b_account,
+                1,
+                0,
+            )
+        );
+    }
 
-            assert_eq!(
-                Err(SwapError::IncorrectMint.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
-            );
+    #[test]
+    fn test_spl_token_swap_swap() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
-        }
+        let amount_in = 10_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, amount_in, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
 
-        // slippage exceeded
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
-            );
-            // minimum A amount out too high
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount * 30, // XXX: 10 -> 30: Revisit this slippage multiplier
-                    minimum_b_amount,
-                )
-            );
-            // minimum B amount out too high
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount * 30, // XXX: 10 -> 30; Revisit this slippage multiplier
-                )
-            );
-        }
+        // compatibility mode disabled
+        assert_eq!(
+            Err(SwapError::InvalidInstruction.into()),
+            accounts.spl_token_swap_swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+        );
 
-        // correct withdrawal
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
-            );
+        accounts.set_spl_token_swap_compat(true).unwrap();
+        accounts
+            .spl_token_swap_swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+            .unwrap();
 
-            let original_reserve_a = utils::unpack_token_account(&accounts.token_a_account.data)
-                .unwrap()
-                .amount;
-            let original_reserve_b = utils::unpack_token_account(&accounts.token_b_account.data)
-                .unwrap()
-                .amount;
-            let original_lp_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
-                .unwrap()
-                .supply;
+        let result = SwapReturnData::unpack(&get_return_data().unwrap().1).unwrap();
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, result.amount_out);
+        let admin_fee_b = utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+        assert_eq!(admin_fee_b.amount, result.admin_fee);
+        assert!(result.admin_fee > 0);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount + amount_in);
+    }
+
+    #[test]
+    fn test_price_oracles() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        let amount_in = 10_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, 3 * amount_in, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let price_oracle_a_key = pubkey_rand();
+        let price_oracle_b_key = pubkey_rand();
+        let slot = 100;
+        let mut price_oracle_a_account = pyth_price_account(100_000_000, -8, slot);
+        let mut price_oracle_b_account = pyth_price_account(1_000_000, -6, slot);
+
+        // oracles must be Pyth price accounts
+        assert_eq!(
+            Err(SwapError::InvalidOracle.into()),
+            accounts.set_price_oracles(
+                &price_oracle_a_key,
+                &mut price_oracle_a_account,
+                &price_oracle_b_key,
+                &mut Account::default(),
+                200,
+            )
+        );
+        accounts
+            .set_price_oracles(
+                &price_oracle_a_key,
+                &mut price_oracle_a_account,
+                &price_oracle_b_key,
+                &mut price_oracle_b_account,
+                200,
+            )
+            .unwrap();
+        let token_swap = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(token_swap.price_oracle_a, price_oracle_a_key);
+        assert_eq!(token_swap.price_oracle_b, price_oracle_b_key);
+        assert_eq!(token_swap.max_price_deviation_bps, 200);
+
+        // oracles must be passed
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+        );
 
-            accounts
-                .withdraw(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount,
-                    minimum_a_amount,
-                    minimum_b_amount,
-                )
-                .unwrap();
+        // pool price at peg
+        accounts
+            .swap_with_price_oracles(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                &price_oracle_a_key,
+                &mut price_oracle_a_account,
+                &price_oracle_b_key,
+                &mut price_oracle_b_account,
+                amount_in,
+                0,
+                slot,
+            )
+            .unwrap();
 
-            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-            let pool_converter = PoolTokenConverter {
-                supply: original_lp_supply.into(),
-                token_a: original_reserve_a.into(),
-                token_b: original_reserve_b.into(),
-                fees: &DEFAULT_TEST_FEES,
-            };
+        // stale oracle price
+        assert_eq!(
+            Err(SwapError::InvalidOracle.into()),
+            accounts.swap_with_price_oracles(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                &price_oracle_a_key,
+                &mut price_oracle_a_account,
+                &price_oracle_b_key,
+                &mut price_oracle_b_account,
+                amount_in,
+                0,
+                slot + MAX_PRICE_AGE_SLOTS + 1,
+            )
+        );
 
-            let (withdrawn_a, _, admin_fee_a) =
-                pool_converter.token_a_rate(withdraw_amount).unwrap();
-            let withdrawn_total_a = withdrawn_a + admin_fee_a;
-            assert_eq!(swap_token_a.amount, token_a_amount - withdrawn_total_a);
-            let (withdrawn_b, _, admin_fee_b) =
-                pool_converter.token_b_rate(withdraw_amount).unwrap();
-            let withdrawn_total_b = withdrawn_b + admin_fee_b;
-            assert_eq!(swap_token_b.amount, token_b_amount - withdrawn_total_b);
-            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, initial_a + (withdrawn_a));
-            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
-            assert_eq!(token_b.amount, initial_b + (withdrawn_b));
-            let pool_account = utils::unpack_token_account(&pool_account.data).unwrap();
-            assert_eq!(pool_account.amount, initial_pool - withdraw_amount);
-            let admin_fee_key_a =
-                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
-            assert_eq!(admin_fee_key_a.amount, (admin_fee_a));
-            let admin_fee_key_b =
-                utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
-            assert_eq!(admin_fee_key_b.amount, (admin_fee_b));
-        }
+        // token A depegged to 0.9
+        let mut depegged_account = pyth_price_account(90_000_000, -8, slot);
+        assert_eq!(
+            Err(SwapError::PriceDeviation.into()),
+            accounts.swap_with_price_oracles(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                &price_oracle_a_key,
+                &mut depegged_account,
+                &price_oracle_b_key,
+                &mut price_oracle_b_account,
+                amount_in,
+                0,
+                slot,
+            )
+        );
+
+        // the check can be disabled
+        accounts
+            .set_price_oracles(
+                &price_oracle_a_key,
+                &mut Account::default(),
+                &price_oracle_b_key,
+                &mut Account::default(),
+                0,
+            )
+            .unwrap();
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                amount_in,
+                0,
+            )
+            .unwrap();
     }
 
     #[test]
-    fn test_swap() {
+    fn test_pool_stats() {
         let user_key = pubkey_rand();
         let swapper_key = pubkey_rand();
-        let amp_factor = 85;
-        let token_a_amount = 5000;
-        let token_b_amount = 5000;
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
         let mut accounts = SwapAccountInfo::new(
             &user_key,
-            amp_factor,
+            MIN_AMP,
             token_a_amount,
             token_b_amount,
             DEFAULT_TEST_FEES,
         );
-        let initial_a = token_a_amount / 5;
-        let initial_b = token_b_amount / 5;
-        let minimum_b_amount = initial_b / 2;
+        accounts.initialize_swap().unwrap();
 
+        let (pool_stats_key, _bump_seed) =
+            find_pool_stats_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let mut pool_stats_account = Account::new(1, PoolStats::LEN, &SWAP_PROGRAM_ID);
+        accounts
+            .init_pool_stats(&pool_stats_key, &mut pool_stats_account)
+            .unwrap();
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            accounts.init_pool_stats(&pool_stats_key, &mut pool_stats_account)
+        );
+        let pool_stats = PoolStats::unpack(&pool_stats_account.data).unwrap();
+        assert_eq!(pool_stats.swap, accounts.swap_key);
+        assert_eq!(pool_stats.swap_count, 0);
+
+        let amount_in = 10_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, amount_in, 0, 0);
         let swap_token_a_key = accounts.token_a_key;
         let swap_token_b_key = accounts.token_b_key;
 
-        // swap not initialized
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_b_amount,
-                )
-            );
-        }
+        // wrong pool stats account
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            accounts.swap_with_pool_stats(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                &pubkey_rand(),
+                &mut pool_stats_account.clone(),
+                amount_in,
+                0,
+                1,
+            )
+        );
+
+        accounts
+            .swap_with_pool_stats(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_stats_key,
+                &mut pool_stats_account,
+                amount_in,
+                0,
+                7,
+            )
+            .unwrap();
+        let (_program_id, return_data) = get_return_data().unwrap();
+        let result = SwapReturnData::unpack(&return_data).unwrap();
+        let pool_stats = PoolStats::unpack(&pool_stats_account.data).unwrap();
+        assert_eq!(pool_stats.token_a_volume, amount_in.into());
+        assert_eq!(pool_stats.token_b_volume, result.amount_out.into());
+        assert_eq!(pool_stats.token_a_fees, 0);
+        assert_eq!(pool_stats.token_b_fees, result.fee.into());
+        assert_eq!(pool_stats.token_a_trade_admin_fees, 0);
+        assert_eq!(pool_stats.token_b_trade_admin_fees, result.admin_fee.into());
+        assert_eq!(pool_stats.token_b_withdraw_admin_fees, 0);
+        assert_eq!(pool_stats.swap_count, 1);
+        assert_eq!(pool_stats.last_activity_slot, 7);
+
+        // withdraw fees are recorded, but not as swaps
+        let withdraw_amount = 1_000;
+        let (
+            _token_a_key,
+            _token_a_account,
+            _token_b_key,
+            _token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, 0, 0, withdraw_amount);
+        accounts
+            .withdraw_one_with_pool_stats(
+                &swapper_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &pool_stats_key,
+                &mut pool_stats_account,
+                withdraw_amount,
+                0,
+                9,
+            )
+            .unwrap();
+        let (_program_id, return_data) = get_return_data().unwrap();
+        let withdraw_result = SwapReturnData::unpack(&return_data).unwrap();
+        let pool_stats = PoolStats::unpack(&pool_stats_account.data).unwrap();
+        assert_eq!(pool_stats.token_a_fees, withdraw_result.fee.into());
+        assert_eq!(pool_stats.token_a_volume, amount_in.into());
+        // the admin fee of a withdrawal in one token is split by source
+        assert!(pool_stats.token_a_withdraw_admin_fees > 0);
+        assert_eq!(
+            pool_stats.token_a_trade_admin_fees + pool_stats.token_a_withdraw_admin_fees,
+            withdraw_result.admin_fee.into()
+        );
+        assert_eq!(pool_stats.token_b_withdraw_admin_fees, 0);
+        assert_eq!(pool_stats.swap_count, 1);
+        assert_eq!(pool_stats.last_activity_slot, 9);
+
+        // accounts created before the admin fees were tracked by source are still updated
+        let mut legacy_pool_stats_account =
+            Account::new(1, POOL_STATS_LEGACY_LEN, &SWAP_PROGRAM_ID);
+        PoolStats::pack(pool_stats, &mut legacy_pool_stats_account.data).unwrap();
+        accounts
+            .swap_with_pool_stats(
+                &swapper_key,
+                &token_b_key,
+                &mut token_b_account,
+                &swap_token_b_key,
+                &swap_token_a_key,
+                &token_a_key,
+                &mut token_a_account,
+                &pool_stats_key,
+                &mut legacy_pool_stats_account,
+                result.amount_out,
+                0,
+                11,
+            )
+            .unwrap();
+        let legacy_pool_stats = PoolStats::unpack(&legacy_pool_stats_account.data).unwrap();
+        assert_eq!(legacy_pool_stats.swap_count, 2);
+        assert_eq!(legacy_pool_stats.last_activity_slot, 11);
+        assert_eq!(legacy_pool_stats.token_a_trade_admin_fees, 0);
+        assert_eq!(legacy_pool_stats.token_a_withdraw_admin_fees, 0);
+    }
 
+    #[test]
+    fn test_withdraw_one_exact_out() {
+        let user_key = pubkey_rand();
+        let withdrawer_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
         accounts.initialize_swap().unwrap();
 
-        // wrong nonce
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
-                &spl_token::id(),
-            );
-            accounts.authority_key = bad_authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_b_amount,
-                )
-            );
-            accounts.authority_key = old_authority;
-        }
+        let pool_amount = 100_000;
+        let token_amount = 40_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            _token_b_key,
+            _token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, pool_amount);
+        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        let invariant = StableSwap::new(
+            MIN_AMP * AMP_PRECISION,
+            MIN_AMP * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let burn_amount = invariant
+            .compute_withdraw_one_exact_out(
+                token_amount,
+                pool_mint.supply,
+                token_a_amount,
+                token_b_amount,
+                &DEFAULT_TEST_FEES,
+            )
+            .unwrap();
 
-        // wrong token program id
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let wrong_program_id = pubkey_rand();
-            assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                do_process_instruction(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_program_id,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &user_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_b_key,
-                        &accounts.admin_fee_b_key,
-                        initial_a,
-                        minimum_b_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_b_account,
-                        &mut accounts.admin_fee_b_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
-                ),
-            );
-        }
+        // burns more than the maximum
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.withdraw_one_exact_out(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                token_amount,
+                burn_amount - 1,
+            )
+        );
+
+        accounts
+            .withdraw_one_exact_out(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                token_amount,
+                burn_amount,
+            )
+            .unwrap();
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, token_amount);
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.amount, pool_amount - burn_amount);
+        let (_program_id, return_data) = get_return_data().unwrap();
+        let result = SwapReturnData::unpack(&return_data).unwrap();
+        assert_eq!(result.amount_out, token_amount);
+
+        // more than the pool token balance
+        assert_eq!(
+            Err(TokenError::InsufficientFunds.into()),
+            accounts.withdraw_one_exact_out(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                token_amount * 2,
+                u64::MAX,
+            )
+        );
+    }
+
+    #[test]
+    fn test_deprecated_pool() {
+        let user_key = pubkey_rand();
+        let withdrawer_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let pool_amount = 100_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 1_000, 1_000, pool_amount);
+        let pool_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+        accounts.deprecate_pool().unwrap();
+
+        // swaps and deposits are rejected
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        assert_eq!(
+            Err(SwapError::PoolDeprecated.into()),
+            accounts.swap(
+                &withdrawer_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::PoolDeprecated.into()),
+            accounts.deposit(
+                &withdrawer_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                1_000,
+                1_000,
+                0,
+            )
+        );
+
+        // withdrawals are free of withdraw fees
+        let withdraw_amount = pool_amount / 2;
+        accounts
+            .withdraw(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                withdraw_amount,
+                0,
+                0,
+            )
+            .unwrap();
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(
+            token_a.amount,
+            1_000 + withdraw_amount * token_a_amount / pool_supply
+        );
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(
+            token_b.amount,
+            1_000 + withdraw_amount * token_b_amount / pool_supply
+        );
+        let admin_fee_a = utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+        assert_eq!(admin_fee_a.amount, 0);
+
+        // LPs can still leave in one token
+        accounts
+            .withdraw_one(
+                &withdrawer_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                pool_amount - withdraw_amount,
+                0,
+            )
+            .unwrap();
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.amount, 0);
+    }
+
+    #[test]
+    fn test_emergency_withdraw() {
+        let user_key = pubkey_rand();
+        let withdrawer_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 3_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
 
-        // not enough token a to swap
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a * 2,
-                    minimum_b_amount * 2,
-                )
-            );
-        }
+        let pool_amount = 100_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, pool_amount);
+        let pool_supply = utils::unpack_mint(&accounts.pool_mint_account.data)
+            .unwrap()
+            .supply;
+        let expected_a = pool_amount * token_a_amount / pool_supply;
+        let expected_b = pool_amount * token_b_amount / pool_supply;
+
+        // pool not paused
+        assert_eq!(
+            Err(SwapError::NotPaused.into()),
+            accounts.emergency_withdraw(
+                &withdrawer_key,
+                (&pool_key, &mut pool_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                pool_amount,
+                0,
+                0,
+            )
+        );
 
-        // wrong swap token A / B accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                do_process_instruction(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &spl_token::id(),
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &swapper_key,
-                        &token_a_key,
-                        &token_a_key,
-                        &token_b_key,
-                        &token_b_key,
-                        &accounts.admin_fee_b_key,
-                        initial_a,
-                        minimum_b_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account.clone(),
-                        &mut token_a_account,
-                        &mut token_b_account.clone(),
-                        &mut token_b_account,
-                        &mut accounts.admin_fee_b_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
-                ),
-            );
-        }
+        accounts.pause().unwrap();
 
-        // wrong admin account
+        // a delegate cannot credit its own accounts
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                wrong_admin_key,
-                mut wrong_admin_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                do_process_instruction(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &spl_token::id(),
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &swapper_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_b_key,
-                        &wrong_admin_key,
-                        initial_a,
-                        minimum_b_amount,
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_b_account,
-                        &mut wrong_admin_account,
-                        &mut Account::default(),
-                        &mut clock_account(ZERO_TS),
-                    ],
-                ),
+            let delegate_key = pubkey_rand();
+            let (delegate_token_a_key, mut delegate_token_a_account, _, _, _, _) =
+                accounts.setup_token_accounts(&user_key, &delegate_key, 0, 0, 0);
+            let mut delegated_pool_account = pool_account.clone();
+            approve_delegate(
+                &pool_key,
+                &mut delegated_pool_account,
+                &withdrawer_key,
+                &delegate_key,
+                pool_amount,
             );
-        }
-
-        // wrong user token A / B accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    initial_a,
-                    minimum_b_amount,
+                Err(SwapError::InvalidWithdrawDestination.into()),
+                accounts.emergency_withdraw(
+                    &delegate_key,
+                    (&pool_key, &mut delegated_pool_account),
+                    (&delegate_token_a_key, &mut delegate_token_a_account),
+                    (&token_b_key, &mut token_b_account.clone()),
+                    pool_amount,
+                    0,
+                    0,
                 )
             );
         }
 
-        // swap from a to a
+        // wrong reserves
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let old_token_b_key = accounts.token_b_key;
+            accounts.token_b_key = token_b_key;
             assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account.clone(),
-                    &swap_token_a_key,
-                    &swap_token_a_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    initial_a,
-                    minimum_b_amount,
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.emergency_withdraw(
+                    &withdrawer_key,
+                    (&pool_key, &mut pool_account),
+                    (&token_a_key, &mut token_a_account),
+                    (&token_b_key, &mut token_b_account.clone()),
+                    pool_amount,
+                    0,
+                    0,
                 )
             );
+            accounts.token_b_key = old_token_b_key;
         }
 
-        // swap source same as swap account
+        // slippage
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.emergency_withdraw(
+                &withdrawer_key,
+                (&pool_key, &mut pool_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                pool_amount,
+                expected_a,
+                expected_b + 1,
+            )
+        );
+
+        // pro-rata to the reserves, without fees
+        accounts
+            .emergency_withdraw(
+                &withdrawer_key,
+                (&pool_key, &mut pool_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                pool_amount,
+                expected_a,
+                expected_b,
+            )
+            .unwrap();
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, expected_a);
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, expected_b);
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.amount, 0);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount - expected_a);
+        let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert_eq!(swap_token_b.amount, token_b_amount - expected_b);
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.pool_token_supply, pool_supply - pool_amount);
+        let (_program_id, return_data) = get_return_data().unwrap();
+        let result = WithdrawReturnData::unpack(&return_data).unwrap();
+        assert_eq!(result.token_a_amount, expected_a);
+        assert_eq!(result.token_b_fee, 0);
+    }
+
+    #[test]
+    fn test_pool_accounts_resolved_by_key() {
+        let user_key = pubkey_rand();
+        let withdrawer_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 3_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+
+        let pool_amount = 100_000;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, pool_amount);
+
+        // reserves and admin fee accounts passed in each other's positions
+        let mut instruction = withdraw(
+            &SWAP_PROGRAM_ID,
+            &spl_token::id(),
+            &accounts.swap_key,
+            &accounts.authority_key,
+            &withdrawer_key,
+            &accounts.pool_mint_key,
+            &pool_key,
+            &accounts.token_a_key,
+            &accounts.token_b_key,
+            &token_a_key,
+            &token_b_key,
+            &accounts.admin_fee_a_key,
+            &accounts.admin_fee_b_key,
+            pool_amount,
+            0,
+            0,
+        )
+        .unwrap();
+        instruction.accounts.swap(5, 6);
+        instruction.accounts.swap(9, 10);
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut accounts.pool_mint_account,
+                &mut pool_account,
+                &mut accounts.token_b_account,
+                &mut accounts.token_a_account,
+                &mut token_a_account,
+                &mut token_b_account,
+                &mut accounts.admin_fee_b_account,
+                &mut accounts.admin_fee_a_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+            ],
+        )
+        .unwrap();
+
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert!(token_a.amount > 0);
+        // the pool holds three times as much B as A
+        assert!(token_b.amount > token_a.amount * 2);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        let admin_fee_a = utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+        assert_eq!(
+            swap_token_a.amount + admin_fee_a.amount + token_a.amount,
+            token_a_amount
+        );
+    }
+
+    #[test]
+    fn test_zero_amounts() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, 1_000, 1_000, 1_000);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let swap_account = accounts.swap_account.clone();
+
+        // no-ops by default
+        accounts
+            .swap(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                0,
+                0,
+            )
+            .unwrap();
+        accounts
+            .deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+        accounts
+            .withdraw(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+        assert_eq!(accounts.swap_account, swap_account);
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, 1_000);
+        let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert_eq!(pool_token.amount, 1_000);
+
+        // rejected once the swap opts in
+        accounts.set_reject_zero_amounts(true).unwrap();
+        assert_eq!(
+            Err(SwapError::ZeroAmount.into()),
+            accounts.swap(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                0,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::ZeroAmount.into()),
+            accounts.deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                0,
+                0,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::ZeroAmount.into()),
+            accounts.withdraw(
+                &depositor_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                0,
+                0,
+                0,
+            )
+        );
+
+        // other amounts are unaffected
+        accounts
+            .swap(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_initialize_with_locked_liquidity() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        // tiny reserves, as a first depositor inflating the pool token would deposit
+        let token_a_amount = 1_000;
+        let token_b_amount = 1_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        let (locked_owner_key, _) =
+            find_locked_liquidity_address(&SWAP_PROGRAM_ID, &accounts.swap_key);
+        let (locked_lp_key, mut locked_lp_account) = mint_token(
+            &spl_token::id(),
+            &accounts.pool_mint_key,
+            &mut accounts.pool_mint_account,
+            &accounts.authority_key,
+            &locked_owner_key,
+            0,
+        );
+
+        // the locked pool tokens must not be held by anyone
         {
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.swap(
-                    &accounts.authority_key.clone(),
-                    &swap_token_a_key,
-                    &mut accounts.token_a_account.clone(),
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &swap_token_b_key,
-                    &mut accounts.token_b_account.clone(),
-                    initial_a,
-                    minimum_b_amount,
-                )
+            let (user_lp_key, mut user_lp_account) = mint_token(
+                &spl_token::id(),
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &user_key,
+                0,
             );
             assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.swap(
-                    &accounts.authority_key.clone(),
-                    &swap_token_b_key,
-                    &mut accounts.token_b_account.clone(),
-                    &swap_token_b_key,
-                    &swap_token_a_key,
-                    &swap_token_a_key,
-                    &mut accounts.token_a_account.clone(),
-                    initial_b,
-                    initial_a / 2,
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap_with_locked_liquidity(
+                    (&user_lp_key, &mut user_lp_account),
+                    MINIMUM_LIQUIDITY,
                 )
             );
         }
 
-        // slippage exceeded: minimum out amount too high
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_b_amount * 2,
-                )
-            );
-        }
+        // the bootstrapper must keep some of the pool tokens
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.initialize_swap_with_locked_liquidity(
+                (&locked_lp_key, &mut locked_lp_account),
+                token_a_amount + token_b_amount,
+            )
+        );
 
-        // correct swap
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            // swap one way
-            let a_to_b_amount = initial_a / 10;
-            let minimum_b_amount = initial_b / 20;
-            accounts
-                .swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    a_to_b_amount,
-                    minimum_b_amount,
-                )
-                .unwrap();
+        accounts
+            .initialize_swap_with_locked_liquidity(
+                (&locked_lp_key, &mut locked_lp_account),
+                MINIMUM_LIQUIDITY,
+            )
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        let locked_lp = utils::unpack_token_account(&locked_lp_account.data).unwrap();
+        assert_eq!(locked_lp.amount, MINIMUM_LIQUIDITY);
+        let pool_token = utils::unpack_token_account(&accounts.pool_token_account.data).unwrap();
+        assert_eq!(
+            pool_token.amount,
+            swap_info.pool_token_supply - MINIMUM_LIQUIDITY
+        );
+        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.supply, swap_info.pool_token_supply);
+
+        // the bootstrapper can not drain the pool down to a few base units
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &user_key, 0, 0, 0);
+        let pool_token_key = accounts.pool_token_key;
+        let mut pool_token_account = accounts.pool_token_account.clone();
+        accounts
+            .withdraw(
+                &user_key,
+                &pool_token_key,
+                &mut pool_token_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                pool_token.amount,
+                0,
+                0,
+            )
+            .unwrap();
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+        assert!(swap_token_a.amount + swap_token_b.amount >= MINIMUM_LIQUIDITY);
+
+        // later deposits are still minted pool tokens
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, 10, 10, 0);
+        accounts
+            .deposit(
+                &depositor_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                10,
+                10,
+                1,
+            )
+            .unwrap();
+        let pool = utils::unpack_token_account(&pool_account.data).unwrap();
+        assert!(pool.amount > 0);
+    }
 
-            let invariant = StableSwap::new(
-                accounts.initial_amp_factor,
-                accounts.target_amp_factor,
-                ZERO_TS,
-                ZERO_TS,
-                ZERO_TS,
-            );
-            let result = invariant
-                .swap_to(
-                    a_to_b_amount,
-                    token_a_amount,
-                    token_b_amount,
-                    &DEFAULT_TEST_FEES,
-                )
-                .unwrap();
+    #[test]
+    fn test_initialize_with_amp_ramp() {
+        let user_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 3_000_000;
+        let target_amp = 1_000;
+        let ramp_duration = 7 * MIN_RAMP_DURATION;
+        let current_ts = 1_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
 
-            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-            let token_a_amount = swap_token_a.amount;
-            assert_eq!(token_a_amount, 5100);
-            assert_eq!(token_a_amount, (result.new_source_amount));
-            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, initial_a - a_to_b_amount);
+        // the target must be a valid amp factor
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            accounts.initialize_swap_with_amp_ramp(MAX_AMP + 1, ramp_duration, current_ts)
+        );
+        // the ramp moves A no faster than a RampA
+        assert_eq!(
+            Err(SwapError::InsufficientRampTime.into()),
+            accounts.initialize_swap_with_amp_ramp(target_amp, MIN_RAMP_DURATION - 1, current_ts)
+        );
 
-            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-            let token_b_amount = swap_token_b.amount;
-            assert_eq!(token_b_amount, 4903);
-            assert_eq!(token_b_amount, (result.new_destination_amount));
-            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
-            assert_eq!(token_b.amount, 1094);
-            assert_eq!(token_b.amount, initial_b + (result.amount_swapped));
-            let admin_fee_b_account =
-                utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
-            assert_eq!(admin_fee_b_account.amount, (result.admin_fee));
+        accounts
+            .initialize_swap_with_amp_ramp(target_amp, ramp_duration, current_ts)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.initial_amp_factor, MIN_AMP * AMP_PRECISION);
+        assert_eq!(swap_info.target_amp_factor, target_amp * AMP_PRECISION);
+        assert_eq!(swap_info.start_ramp_ts, current_ts);
+        assert_eq!(swap_info.stop_ramp_ts, current_ts + ramp_duration);
+
+        // the bootstrapper is minted the invariant at the initial amp factor
+        let invariant = StableSwap::new(
+            MIN_AMP * AMP_PRECISION,
+            MIN_AMP * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(
+            pool_mint.supply,
+            invariant
+                .compute_d(token_a_amount, token_b_amount)
+                .unwrap()
+                .to_u64()
+                .unwrap()
+        );
 
-            let first_swap_amount = result.amount_swapped;
+        // A tightens on its own, without a RampA
+        let get_amp_factor = |accounts: &mut SwapAccountInfo, current_ts| {
+            accounts.get_amp_factor(current_ts).unwrap();
+            AmpFactorReturnData::unpack(&get_return_data().unwrap().1)
+                .unwrap()
+                .amp_factor
+        };
+        assert_eq!(
+            get_amp_factor(&mut accounts, current_ts),
+            MIN_AMP * AMP_PRECISION
+        );
+        let halfway_amp = get_amp_factor(&mut accounts, current_ts + ramp_duration / 2);
+        assert!(halfway_amp > MIN_AMP * AMP_PRECISION && halfway_amp < target_amp * AMP_PRECISION);
+        assert_eq!(
+            get_amp_factor(&mut accounts, current_ts + ramp_duration),
+            target_amp * AMP_PRECISION
+        );
+    }
 
-            // swap the other way
-            let b_to_a_amount = initial_b / 10;
-            let minimum_a_amount = initial_a / 20;
+    #[test]
+    fn test_initialize_with_huge_reserves() {
+        let user_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let invariant = StableSwap::new(
+            amp_factor * AMP_PRECISION,
+            amp_factor * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        for (token_a_amount, token_b_amount, initial_supply_scaled) in [
+            (MAX_TOKENS_IN, MAX_TOKENS_IN, false),
+            (MAX_TOKENS_IN + 1, MAX_TOKENS_IN, true),
+            (u64::MAX / 2, u64::MAX / 2, true),
+            (u64::MAX, u64::MAX, true),
+            (u64::MAX, 1_000_000_000, true),
+        ] {
+            let mut accounts = SwapAccountInfo::new(
+                &user_key,
+                amp_factor,
+                token_a_amount,
+                token_b_amount,
+                DEFAULT_TEST_FEES,
+            );
+            accounts.initialize_swap().unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.initial_supply_scaled, initial_supply_scaled);
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            let scale = if initial_supply_scaled {
+                INITIAL_SUPPLY_SCALE
+            } else {
+                1
+            };
+            assert_eq!(
+                pool_mint.supply,
+                invariant
+                    .compute_d(token_a_amount / scale, token_b_amount / scale)
+                    .unwrap()
+                    .to_u64()
+                    .unwrap()
+            );
+            let pool_account =
+                utils::unpack_token_account(&accounts.pool_token_account.data).unwrap();
+            assert_eq!(pool_account.amount, pool_mint.supply);
+
+            // the pool trades from the start, on reserves scaled like its supply
+            let amount_in = u64::MAX - token_b_amount;
+            if amount_in == 0 {
+                continue;
+            }
+            let amount_in = amount_in.min(MAX_TOKENS_IN);
+            let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+                accounts.setup_token_accounts(&user_key, &swapper_key, 0, amount_in, 0);
+            let swap_token_a_key = accounts.token_a_key;
+            let swap_token_b_key = accounts.token_b_key;
             accounts
                 .swap(
                     &swapper_key,
@@ -2944,79 +11664,292 @@ mod tests {
                     &swap_token_a_key,
                     &token_a_key,
                     &mut token_a_account,
-                    b_to_a_amount,
-                    minimum_a_amount,
+                    amount_in,
+                    0,
                 )
                 .unwrap();
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert!(token_a.amount > 0);
+        }
+    }
 
-            let invariant = StableSwap::new(
-                accounts.initial_amp_factor,
-                accounts.target_amp_factor,
-                ZERO_TS,
-                ZERO_TS,
-                ZERO_TS,
-            );
-            let result = invariant
-                .swap_to(
-                    b_to_a_amount,
-                    token_b_amount,
-                    token_a_amount,
-                    &DEFAULT_TEST_FEES,
-                )
-                .unwrap();
+    #[test]
+    fn test_initialize_with_shared_authority() {
+        let user_key = pubkey_rand();
+        let vault_owner_key = pubkey_rand();
+        let swapper_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 2_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        let (shared_authority_key, shared_nonce) = find_shared_authority_address(&SWAP_PROGRAM_ID);
+        // the vault owner hands the reserves and the pool mint over at initialization
+        for account in [&mut accounts.token_a_account, &mut accounts.token_b_account] {
+            let mut token = utils::unpack_token_account(&account.data).unwrap();
+            token.owner = vault_owner_key;
+            SplAccount::pack(token, &mut account.data).unwrap();
+        }
+        let mut pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        pool_mint.mint_authority = COption::Some(vault_owner_key);
+        Mint::pack(pool_mint, &mut accounts.pool_mint_account.data).unwrap();
 
-            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-            assert_eq!(swap_token_a.amount, 5002);
-            assert_eq!(swap_token_a.amount, (result.new_destination_amount));
-            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, 995);
+        // reserves not owned by the vault owner, e.g. those of another pool
+        {
+            let old_account = accounts.token_a_account.clone();
+            let mut token = utils::unpack_token_account(&old_account.data).unwrap();
+            token.owner = shared_authority_key;
+            SplAccount::pack(token, &mut accounts.token_a_account.data).unwrap();
             assert_eq!(
-                token_a.amount,
-                initial_a - a_to_b_amount + (result.amount_swapped)
+                Err(TokenError::OwnerMismatch.into()),
+                accounts.initialize_swap_with_shared_authority(&vault_owner_key)
             );
+            accounts.token_a_account = old_account;
+        }
 
-            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-            assert_eq!(swap_token_b.amount, 5003);
-            assert_eq!(swap_token_b.amount, (result.new_source_amount));
-            let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
-            assert_eq!(token_b.amount, 994);
+        // admin fee account owned by the shared authority
+        {
+            let old_account = accounts.admin_fee_a_account.clone();
+            let mut token = utils::unpack_token_account(&old_account.data).unwrap();
+            token.owner = shared_authority_key;
+            SplAccount::pack(token, &mut accounts.admin_fee_a_account.data).unwrap();
             assert_eq!(
-                token_b.amount,
-                initial_b + (first_swap_amount) - b_to_a_amount
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap_with_shared_authority(&vault_owner_key)
             );
-            let admin_fee_a_account =
-                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
-            assert_eq!(admin_fee_a_account.amount, (result.admin_fee));
+            accounts.admin_fee_a_account = old_account;
         }
 
-        // Pool is paused
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            // Pause pool
-            accounts.pause().unwrap();
+        // valid call
+        accounts
+            .initialize_swap_with_shared_authority(&vault_owner_key)
+            .unwrap();
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert!(swap_info.shared_authority);
+        assert_eq!(swap_info.nonce, shared_nonce);
+        let token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(token_a.owner, shared_authority_key);
+        let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(
+            pool_mint.mint_authority,
+            COption::Some(shared_authority_key)
+        );
+        assert_eq!(pool_mint.supply, swap_info.pool_token_supply);
+
+        // the pool trades like any other
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &swapper_key, 1_000, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+            .unwrap();
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert!(token_b.amount > 0);
+
+        // other accounts of the shared authority may belong to other pools
+        let (stranded_key, mut stranded_account) = mint_token(
+            &spl_token::id(),
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &shared_authority_key,
+            1_000,
+        );
+        assert_eq!(
+            Err(SwapError::SharedAuthority.into()),
+            accounts.recover_stranded_tokens(&stranded_key, &mut stranded_account, true)
+        );
+    }
+
+    #[test]
+    fn test_delegated_withdraw() {
+        let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
+        let delegate_key = pubkey_rand();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, MIN_AMP, 1_000_000, 1_000_000, DEFAULT_TEST_FEES);
+        accounts.initialize_swap().unwrap();
+        let pool_amount = 100_000;
+        let (
+            owner_token_a_key,
+            mut owner_token_a_account,
+            owner_token_b_key,
+            mut owner_token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, pool_amount);
+        let (delegate_token_a_key, mut delegate_token_a_account, _, delegate_token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &delegate_key, 0, 0, 0);
+
+        // not a delegate
+        assert_eq!(
+            Err(TokenError::OwnerMismatch.into()),
+            accounts.withdraw(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                &owner_token_a_key,
+                &mut owner_token_a_account,
+                &owner_token_b_key,
+                &mut owner_token_b_account,
+                pool_amount / 4,
+                0,
+                0,
+            )
+        );
 
-            assert_eq!(
-                Err(SwapError::IsPaused.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_b_amount,
-                )
-            );
-        }
+        approve_delegate(
+            &pool_key,
+            &mut pool_account,
+            &owner_key,
+            &delegate_key,
+            pool_amount / 2,
+        );
+
+        // the delegate cannot credit its own accounts
+        assert_eq!(
+            Err(SwapError::InvalidWithdrawDestination.into()),
+            accounts.withdraw(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                &delegate_token_a_key,
+                &mut delegate_token_a_account,
+                &owner_token_b_key,
+                &mut owner_token_b_account,
+                pool_amount / 4,
+                0,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidWithdrawDestination.into()),
+            accounts.withdraw_one(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                &delegate_token_a_key,
+                &mut delegate_token_a_account,
+                pool_amount / 4,
+                0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidWithdrawDestination.into()),
+            accounts.zap_out(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                true,
+                &delegate_token_a_key,
+                &mut delegate_token_a_account,
+                pool_amount / 4,
+                0,
+            )
+        );
+
+        // withdraws to the owner
+        accounts
+            .withdraw(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                &owner_token_a_key,
+                &mut owner_token_a_account,
+                &owner_token_b_key,
+                &mut owner_token_b_account,
+                pool_amount / 4,
+                0,
+                0,
+            )
+            .unwrap();
+        let owner_token_a = SplAccount::unpack(&owner_token_a_account.data).unwrap();
+        let owner_token_b = SplAccount::unpack(&owner_token_b_account.data).unwrap();
+        assert!(owner_token_a.amount > 0);
+        assert!(owner_token_b.amount > 0);
+        accounts
+            .withdraw_one(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                &owner_token_a_key,
+                &mut owner_token_a_account,
+                pool_amount / 8,
+                0,
+            )
+            .unwrap();
+        let owner_token_a_amount = SplAccount::unpack(&owner_token_a_account.data)
+            .unwrap()
+            .amount;
+        assert!(owner_token_a_amount > owner_token_a.amount);
+        accounts
+            .zap_out(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                true,
+                &owner_token_a_key,
+                &mut owner_token_a_account,
+                pool_amount / 16,
+                0,
+            )
+            .unwrap();
+        assert!(
+            SplAccount::unpack(&owner_token_a_account.data)
+                .unwrap()
+                .amount
+                > owner_token_a_amount
+        );
+        let pool = SplAccount::unpack(&pool_account.data).unwrap();
+        assert_eq!(
+            pool.amount,
+            pool_amount - pool_amount / 4 - pool_amount / 8 - pool_amount / 16
+        );
+        assert_eq!(pool.delegated_amount, pool_amount / 16);
+
+        // up to the delegated amount
+        assert_eq!(
+            Err(TokenError::InsufficientFunds.into()),
+            accounts.withdraw(
+                &delegate_key,
+                &pool_key,
+                &mut pool_account,
+                &owner_token_a_key,
+                &mut owner_token_a_account,
+                &owner_token_b_key,
+                &mut owner_token_b_account,
+                pool_amount / 4,
+                0,
+                0,
+            )
+        );
+        assert_eq!(
+            SplAccount::unpack(&delegate_token_a_account.data)
+                .unwrap()
+                .amount,
+            0
+        );
+        assert_eq!(
+            SplAccount::unpack(&delegate_token_b_account.data)
+                .unwrap()
+                .amount,
+            0
+        );
     }
 
     #[test]
@@ -3294,7 +12227,241 @@ mod tests {
             );
         }
 
-        // wrong mint
+        // wrong mint
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+            let (pool_mint_key, pool_mint_account) = create_mint(
+                &spl_token::id(),
+                &accounts.authority_key,
+                DEFAULT_TOKEN_DECIMALS,
+                None,
+            );
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    minimum_amount,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // wrong destination account
+        {
+            let (
+                _token_a_key,
+                _token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_amount,
+                )
+            );
+        }
+
+        // wrong admin account
+        {
+            let (
+                wrong_admin_key,
+                wrong_admin_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount,
+            );
+
+            let old_admin_a_key = accounts.admin_fee_a_key;
+            let old_admin_a_account = accounts.admin_fee_a_account;
+            accounts.admin_fee_a_key = wrong_admin_key;
+            accounts.admin_fee_a_account = wrong_admin_account;
+
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    minimum_amount,
+                )
+            );
+
+            accounts.admin_fee_a_key = old_admin_a_key;
+            accounts.admin_fee_a_account = old_admin_a_account;
+        }
+
+        // slippage exceeded
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+
+            let high_minimum_amount = 100000;
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    high_minimum_amount,
+                )
+            );
+        }
+
+        // correct withdraw
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool,
+            );
+
+            let old_swap_token_a =
+                utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let old_swap_token_b =
+                utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let old_pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+
+            let invariant = StableSwap::new(
+                accounts.initial_amp_factor * AMP_PRECISION,
+                accounts.target_amp_factor * AMP_PRECISION,
+                ZERO_TS,
+                ZERO_TS,
+                ZERO_TS,
+            );
+            let (withdraw_one_amount_before_fees, withdraw_one_trade_fee) = invariant
+                .compute_withdraw_one(
+                    withdraw_amount.into(),
+                    old_pool_mint.supply.into(),
+                    old_swap_token_a.amount.into(),
+                    old_swap_token_b.amount.into(),
+                    &DEFAULT_TEST_FEES,
+                )
+                .unwrap();
+            let withdraw_one_withdraw_fee = DEFAULT_TEST_FEES
+                .withdraw_fee(withdraw_one_amount_before_fees)
+                .unwrap();
+            let expected_withdraw_one_amount =
+                withdraw_one_amount_before_fees - withdraw_one_withdraw_fee;
+            let expected_admin_fee = DEFAULT_TEST_FEES
+                .admin_trade_fee(withdraw_one_trade_fee)
+                .unwrap()
+                + DEFAULT_TEST_FEES
+                    .admin_withdraw_fee(withdraw_one_withdraw_fee)
+                    .unwrap();
+
+            accounts
+                .withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    minimum_amount,
+                )
+                .unwrap();
+
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            assert_eq!(
+                old_swap_token_a.amount - swap_token_a.amount - expected_admin_fee,
+                (expected_withdraw_one_amount)
+            );
+            let admin_fee_key_a =
+                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
+            assert_eq!(admin_fee_key_a.amount, expected_admin_fee);
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_token_b.amount, old_swap_token_b.amount);
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            assert_eq!(pool_mint.supply, old_pool_mint.supply - withdraw_amount);
+
+            let (_program_id, return_data) = get_return_data().unwrap();
+            assert_eq!(
+                SwapReturnData::unpack(&return_data).unwrap(),
+                SwapReturnData {
+                    amount_out: expected_withdraw_one_amount,
+                    fee: withdraw_one_trade_fee + withdraw_one_withdraw_fee,
+                    admin_fee: expected_admin_fee,
+                    price_impact_bps: 0,
+                }
+            );
+        }
+
+        // pool is paused
         {
             let (
                 token_a_key,
@@ -3310,19 +12477,11 @@ mod tests {
                 initial_b,
                 initial_pool,
             );
-            let (pool_mint_key, pool_mint_account) = create_mint(
-                &spl_token::id(),
-                &accounts.authority_key,
-                DEFAULT_TOKEN_DECIMALS,
-                None,
-            );
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
+            // pause pool
+            accounts.pause().unwrap();
 
             assert_eq!(
-                Err(SwapError::IncorrectMint.into()),
+                Err(SwapError::IsPaused.into()),
                 accounts.withdraw_one(
                     &withdrawer_key,
                     &pool_key,
@@ -3333,221 +12492,915 @@ mod tests {
                     minimum_amount,
                 )
             );
+        }
+    }
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
+    #[test]
+    fn test_withdraw_one_while_paused() {
+        let user_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let withdrawer_key = pubkey_rand();
+        let withdraw_amount = 10_000;
+        let invariant = StableSwap::new(
+            accounts.initial_amp_factor * AMP_PRECISION,
+            accounts.target_amp_factor * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let expected_withdraw_one_amount = |accounts: &SwapAccountInfo, fees: &Fees| {
+            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
+            let (dy, _dy_fee) = invariant
+                .compute_withdraw_one(
+                    withdraw_amount,
+                    pool_mint.supply,
+                    swap_token_a.amount,
+                    swap_token_b.amount,
+                    fees,
+                )
+                .unwrap();
+            dy - fees.withdraw_fee(dy).unwrap()
+        };
+
+        // not paused: both exits at the pool fees
+        {
+            let (token_a_key, mut token_a_account, _, _, pool_key, mut pool_account) =
+                accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, withdraw_amount);
+            let expected = expected_withdraw_one_amount(&accounts, &DEFAULT_TEST_FEES);
+            accounts
+                .withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    0,
+                )
+                .unwrap();
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, expected);
         }
 
-        // wrong destination account
+        // paused: only the proportional withdraw is open
+        accounts.pause().unwrap();
         {
             let (
-                _token_a_key,
-                _token_a_account,
+                token_a_key,
+                mut token_a_account,
                 token_b_key,
                 mut token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, withdraw_amount);
             assert_eq!(
-                Err(TokenError::MintMismatch.into()),
+                Err(SwapError::IsPaused.into()),
                 accounts.withdraw_one(
                     &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    0,
+                )
+            );
+            accounts
+                .withdraw(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount,
+                    0,
+                    0,
+                )
+                .unwrap();
+            let pool = utils::unpack_token_account(&pool_account.data).unwrap();
+            assert_eq!(pool.amount, 0);
+        }
+
+        // paused with WithdrawOne allowed: both exits, WithdrawOne at doubled fees
+        accounts.set_withdraw_one_while_paused(true).unwrap();
+        {
+            let (token_a_key, mut token_a_account, _, _, pool_key, mut pool_account) =
+                accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, withdraw_amount);
+            let expected = expected_withdraw_one_amount(&accounts, &DEFAULT_TEST_FEES.doubled());
+            assert!(expected < expected_withdraw_one_amount(&accounts, &DEFAULT_TEST_FEES));
+            accounts
+                .withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    0,
+                )
+                .unwrap();
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, expected);
+        }
+
+        // not paused with WithdrawOne allowed: the pool fees again
+        accounts.unpause().unwrap();
+        {
+            let (token_a_key, mut token_a_account, _, _, pool_key, mut pool_account) =
+                accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, withdraw_amount);
+            let expected = expected_withdraw_one_amount(&accounts, &DEFAULT_TEST_FEES);
+            accounts
+                .withdraw_one(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount,
+                    0,
+                )
+                .unwrap();
+            let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, expected);
+        }
+    }
+
+    #[test]
+    fn test_sync_amp() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let target_amp = MIN_AMP * 200;
+        let token_a_amount = 1000;
+        let token_b_amount = 1000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+
+        // swap not initialized
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.sync_amp(ZERO_TS)
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // nothing to sync
+        {
+            accounts.sync_amp(ZERO_TS).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.initial_amp_factor, amp_factor * AMP_PRECISION);
+            assert_eq!(swap_info.target_amp_factor, amp_factor * AMP_PRECISION);
+        }
+
+        let current_ts = MIN_RAMP_DURATION;
+        let stop_ramp_ts = current_ts + RAMP_DELAY + MIN_RAMP_DURATION;
+        accounts
+            .ramp_a(target_amp, current_ts, stop_ramp_ts)
+            .unwrap();
+
+        // ramp in progress
+        {
+            assert_eq!(
+                Err(SwapError::RampLocked.into()),
+                accounts.sync_amp(stop_ramp_ts - 1)
+            );
+        }
+
+        // ramp completed
+        {
+            accounts.sync_amp(stop_ramp_ts).unwrap();
+            let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+            assert_eq!(swap_info.initial_amp_factor, target_amp * AMP_PRECISION);
+            assert_eq!(swap_info.target_amp_factor, target_amp * AMP_PRECISION);
+            assert_eq!(swap_info.start_ramp_ts, ZERO_TS);
+            assert_eq!(swap_info.stop_ramp_ts, ZERO_TS);
+
+            // a new ramp can start right away
+            accounts
+                .ramp_a(
+                    amp_factor,
+                    stop_ramp_ts,
+                    stop_ramp_ts + RAMP_DELAY + MIN_RAMP_DURATION,
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_get_amp_factor() {
+        let user_key = pubkey_rand();
+        let amp_factor = MIN_AMP * 100;
+        let target_amp = MIN_AMP * 200;
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, amp_factor, 1000, 1000, DEFAULT_TEST_FEES);
+        accounts.initialize_swap().unwrap();
+        let get_amp_factor = |accounts: &mut SwapAccountInfo, current_ts| {
+            accounts.get_amp_factor(current_ts).unwrap();
+            AmpFactorReturnData::unpack(&get_return_data().unwrap().1).unwrap()
+        };
+
+        assert_eq!(
+            get_amp_factor(&mut accounts, ZERO_TS),
+            AmpFactorReturnData {
+                amp_factor: amp_factor * AMP_PRECISION,
+                target_amp_factor: amp_factor * AMP_PRECISION,
+            }
+        );
+
+        let current_ts = MIN_RAMP_DURATION;
+        let start_ramp_ts = current_ts + RAMP_DELAY;
+        let stop_ramp_ts = start_ramp_ts + MIN_RAMP_DURATION;
+        accounts
+            .ramp_a(target_amp, current_ts, stop_ramp_ts)
+            .unwrap();
+
+        // scheduled ramp not started yet
+        assert_eq!(
+            get_amp_factor(&mut accounts, start_ramp_ts - 1),
+            AmpFactorReturnData {
+                amp_factor: amp_factor * AMP_PRECISION,
+                target_amp_factor: target_amp * AMP_PRECISION,
+            }
+        );
+
+        // halfway through the ramp, the stored initial A is stale
+        let swap_info = SwapInfo::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_info.initial_amp_factor, amp_factor * AMP_PRECISION);
+        assert_eq!(
+            get_amp_factor(&mut accounts, start_ramp_ts + MIN_RAMP_DURATION / 2),
+            AmpFactorReturnData {
+                amp_factor: (amp_factor + target_amp) / 2 * AMP_PRECISION,
+                target_amp_factor: target_amp * AMP_PRECISION,
+            }
+        );
+        assert_eq!(
+            get_amp_factor(&mut accounts, stop_ramp_ts).amp_factor,
+            target_amp * AMP_PRECISION
+        );
+
+        // not a swap of the program
+        accounts.swap_account.owner = pubkey_rand();
+        assert_eq!(
+            Err(ProgramError::IncorrectProgramId),
+            accounts.get_amp_factor(stop_ramp_ts)
+        );
+    }
+
+    #[test]
+    fn test_recover_stranded_tokens() {
+        let user_key = pubkey_rand();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 1_000_000;
+        let stranded_amount = 1_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            MIN_AMP,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let authority_key = accounts.authority_key;
+        let (stranded_key, mut stranded_account) = mint_token(
+            &spl_token::id(),
+            &accounts.token_b_mint_key,
+            &mut accounts.token_b_mint_account,
+            &user_key,
+            &authority_key,
+            stranded_amount,
+        );
+
+        // reserves are not stranded
+        {
+            let token_a_key = accounts.token_a_key;
+            let mut token_a_account = accounts.token_a_account.clone();
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.recover_stranded_tokens(&token_a_key, &mut token_a_account, true)
+            );
+            let token_b_key = accounts.token_b_key;
+            let mut token_b_account = accounts.token_b_account.clone();
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.recover_stranded_tokens(&token_b_key, &mut token_b_account, false)
+            );
+        }
+
+        // account not owned by the swap authority
+        {
+            let (user_token_key, mut user_token_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &user_key,
+                stranded_amount,
+            );
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.recover_stranded_tokens(&user_token_key, &mut user_token_account, false)
+            );
+        }
+
+        // pool tokens have no admin fee account
+        {
+            let (pool_token_key, mut pool_token_account) = mint_token(
+                &spl_token::id(),
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &authority_key,
+                &authority_key,
+                stranded_amount,
+            );
+            assert_eq!(
+                Err(SwapError::IncorrectMint.into()),
+                accounts.recover_stranded_tokens(&pool_token_key, &mut pool_token_account, false)
+            );
+        }
+
+        // admin fee account of the other token
+        {
+            assert_eq!(
+                Err(SwapError::InvalidAdmin.into()),
+                accounts.recover_stranded_tokens(&stranded_key, &mut stranded_account, true)
+            );
+        }
+
+        // valid recovery
+        {
+            let admin_fee_before = utils::unpack_token_account(&accounts.admin_fee_b_account.data)
+                .unwrap()
+                .amount;
+            accounts
+                .recover_stranded_tokens(&stranded_key, &mut stranded_account, false)
+                .unwrap();
+            let stranded = utils::unpack_token_account(&stranded_account.data).unwrap();
+            assert_eq!(stranded.amount, 0);
+            let admin_fee =
+                utils::unpack_token_account(&accounts.admin_fee_b_account.data).unwrap();
+            assert_eq!(admin_fee.amount, admin_fee_before + stranded_amount);
+            let token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, token_b_amount);
+
+            // nothing left to recover
+            accounts
+                .recover_stranded_tokens(&stranded_key, &mut stranded_account, false)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_deposit_and_stake() {
+        let user_key = pubkey_rand();
+        let depositor_key = pubkey_rand();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, MIN_AMP, 1_000_000, 1_000_000, DEFAULT_TEST_FEES);
+        accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &depositor_key, 1_000, 1_000, 0);
+        let mut staking_config_account = Account::new(1, StakingConfig::LEN, &SWAP_PROGRAM_ID);
+        let mut staking_program_account = Account {
+            executable: true,
+            ..Account::default()
+        };
+        let staking_pool_key = pubkey_rand();
+        let mut staking_pool_account = Account::new(1, 0, &STAKING_PROGRAM_ID);
+        let passed_on_key = pubkey_rand();
+        let mut passed_on_account = Account::default();
+        let prefix = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        // no staking config set
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.deposit_and_stake(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount,
-                    minimum_amount,
+                    &pool_key,
+                    &mut pool_account,
+                    &mut staking_config_account,
+                    &mut staking_program_account,
+                    &staking_pool_key,
+                    &mut staking_pool_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
+                    1_000,
+                    1_000,
+                    0,
                 )
             );
         }
 
-        // wrong admin account
-        {
-            let (
-                wrong_admin_key,
-                wrong_admin_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount,
-            );
-
-            let old_admin_a_key = accounts.admin_fee_a_key;
-            let old_admin_a_account = accounts.admin_fee_a_account;
-            accounts.admin_fee_a_key = wrong_admin_key;
-            accounts.admin_fee_a_account = wrong_admin_account;
+        accounts
+            .set_staking_config(
+                &mut staking_config_account,
+                &mut staking_program_account,
+                &staking_pool_key,
+                &mut staking_pool_account,
+                prefix,
+            )
+            .unwrap();
 
+        // staking pool other than the one set by the admin
+        {
+            let other_staking_pool_key = pubkey_rand();
             assert_eq!(
-                Err(SwapError::InvalidAdmin.into()),
-                accounts.withdraw_one(
-                    &withdrawer_key,
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit_and_stake(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
                     &pool_key,
                     &mut pool_account,
+                    &mut staking_config_account,
+                    &mut staking_program_account,
+                    &other_staking_pool_key,
+                    &mut staking_pool_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
+                    1_000,
+                    1_000,
+                    0,
+                )
+            );
+        }
+
+        // valid call
+        {
+            take_staking_program_invocations();
+            accounts
+                .deposit_and_stake(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount,
-                    minimum_amount,
+                    &pool_key,
+                    &mut pool_account,
+                    &mut staking_config_account,
+                    &mut staking_program_account,
+                    &staking_pool_key,
+                    &mut staking_pool_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
+                    1_000,
+                    1_000,
+                    0,
                 )
+                .unwrap();
+            let pool_token = utils::unpack_token_account(&pool_account.data).unwrap();
+            assert!(pool_token.amount > 0);
+
+            let invocations = take_staking_program_invocations();
+            assert_eq!(invocations.len(), 1);
+            let mut data = prefix.to_vec();
+            data.extend_from_slice(&pool_token.amount.to_le_bytes());
+            assert_eq!(invocations[0].data, data);
+            assert_eq!(
+                invocations[0].accounts,
+                vec![
+                    AccountMeta::new_readonly(depositor_key, true),
+                    AccountMeta::new(pool_key, false),
+                    AccountMeta::new(staking_pool_key, false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new_readonly(passed_on_key, false),
+                ]
             );
+        }
+    }
 
-            accounts.admin_fee_a_key = old_admin_a_key;
-            accounts.admin_fee_a_account = old_admin_a_account;
+    #[test]
+    fn test_harvest_fees() {
+        let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
+        let admin_fee_a_amount = 1_000;
+        let admin_fee_b_amount = 2_000;
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, MIN_AMP, 1_000_000, 1_000_000, DEFAULT_TEST_FEES);
+        accounts.initialize_swap().unwrap();
+        let (rewards_a_key, mut rewards_a_account, rewards_b_key, mut rewards_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &owner_key, 0, 0, 0);
+        let mut rewards_distributor_account =
+            Account::new(1, RewardsDistributor::LEN, &SWAP_PROGRAM_ID);
+        let mut rewards_program_account = Account {
+            executable: true,
+            ..Account::default()
+        };
+        let passed_on_key = pubkey_rand();
+        let mut passed_on_account = Account::default();
+
+        // no rewards distributor set
+        {
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.harvest_fees(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
+                )
+            );
         }
 
-        // slippage exceeded
+        accounts
+            .set_rewards_distributor(
+                &mut rewards_distributor_account,
+                &mut rewards_program_account,
+                &rewards_a_key,
+                &mut rewards_a_account,
+                &rewards_b_key,
+                &mut rewards_b_account,
+            )
+            .unwrap();
+        for (mint_key, mint_account, admin_fee_key, admin_fee_account, amount) in [
+            (
+                accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                accounts.admin_fee_a_key,
+                &mut accounts.admin_fee_a_account,
+                admin_fee_a_amount,
+            ),
+            (
+                accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                accounts.admin_fee_b_key,
+                &mut accounts.admin_fee_b_account,
+                admin_fee_b_amount,
+            ),
+        ] {
+            do_process_instruction(
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &mint_key,
+                    &admin_fee_key,
+                    &user_key,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+                vec![mint_account, admin_fee_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // rewards accounts other than the ones set by the admin
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.harvest_fees(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account.clone(),
+                    &rewards_a_key,
+                    &mut rewards_a_account.clone(),
+                    &passed_on_key,
+                    &mut passed_on_account,
+                )
             );
+        }
 
-            let high_minimum_amount = 100000;
+        // reserves passed on to the rewards program
+        {
+            let token_a_key = accounts.token_a_key;
+            let mut token_a_account = accounts.token_a_account.clone();
             assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw_one(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.harvest_fees(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account.clone(),
+                    &rewards_b_key,
+                    &mut rewards_b_account.clone(),
                     &token_a_key,
                     &mut token_a_account,
-                    withdraw_amount,
-                    high_minimum_amount,
                 )
             );
         }
 
-        // correct withdraw
+        // valid call
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
-            );
-
-            let old_swap_token_a =
-                utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-            let old_swap_token_b =
-                utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-            let old_pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
-
-            let invariant = StableSwap::new(
-                accounts.initial_amp_factor,
-                accounts.target_amp_factor,
-                ZERO_TS,
-                ZERO_TS,
-                ZERO_TS,
-            );
-            let (withdraw_one_amount_before_fees, withdraw_one_trade_fee) = invariant
-                .compute_withdraw_one(
-                    withdraw_amount.into(),
-                    old_pool_mint.supply.into(),
-                    old_swap_token_a.amount.into(),
-                    old_swap_token_b.amount.into(),
-                    &DEFAULT_TEST_FEES,
+            take_rewards_program_invocations();
+            accounts
+                .harvest_fees(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
                 )
                 .unwrap();
-            let withdraw_one_withdraw_fee = DEFAULT_TEST_FEES
-                .withdraw_fee(withdraw_one_amount_before_fees)
-                .unwrap();
-            let expected_withdraw_one_amount =
-                withdraw_one_amount_before_fees - withdraw_one_withdraw_fee;
-            let expected_admin_fee = DEFAULT_TEST_FEES
-                .admin_trade_fee(withdraw_one_trade_fee)
-                .unwrap()
-                + DEFAULT_TEST_FEES
-                    .admin_withdraw_fee(withdraw_one_withdraw_fee)
-                    .unwrap();
+            for (admin_fee_account, rewards_account, amount) in [
+                (
+                    &accounts.admin_fee_a_account,
+                    &rewards_a_account,
+                    admin_fee_a_amount,
+                ),
+                (
+                    &accounts.admin_fee_b_account,
+                    &rewards_b_account,
+                    admin_fee_b_amount,
+                ),
+            ] {
+                assert_eq!(
+                    SplAccount::unpack(&admin_fee_account.data).unwrap().amount,
+                    0
+                );
+                assert_eq!(
+                    SplAccount::unpack(&rewards_account.data).unwrap().amount,
+                    amount
+                );
+            }
+
+            let invocations = take_rewards_program_invocations();
+            assert_eq!(invocations.len(), 1);
+            let invocation = &invocations[0];
+            assert_eq!(invocation.program_id, REWARDS_PROGRAM_ID);
+            // the authority does not sign for the rewards program
+            assert_eq!(
+                invocation.accounts[0],
+                AccountMeta::new_readonly(accounts.authority_key, false)
+            );
+            assert_eq!(invocation.accounts[1].pubkey, rewards_a_key);
+            assert_eq!(invocation.accounts[2].pubkey, rewards_b_key);
+            assert_eq!(invocation.accounts.last().unwrap().pubkey, passed_on_key);
+            let mut data = admin_fee_a_amount.to_le_bytes().to_vec();
+            data.extend_from_slice(&admin_fee_b_amount.to_le_bytes());
+            assert_eq!(invocation.data, data);
+        }
 
+        // nothing left to harvest
+        {
             accounts
-                .withdraw_one(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    withdraw_amount,
-                    minimum_amount,
+                .harvest_fees(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
                 )
                 .unwrap();
-
-            let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
-            assert_eq!(
-                old_swap_token_a.amount - swap_token_a.amount - expected_admin_fee,
-                (expected_withdraw_one_amount)
-            );
-            let admin_fee_key_a =
-                utils::unpack_token_account(&accounts.admin_fee_a_account.data).unwrap();
-            assert_eq!(admin_fee_key_a.amount, expected_admin_fee);
-            let swap_token_b = utils::unpack_token_account(&accounts.token_b_account.data).unwrap();
-            assert_eq!(swap_token_b.amount, old_swap_token_b.amount);
-            let pool_mint = utils::unpack_mint(&accounts.pool_mint_account.data).unwrap();
-            assert_eq!(pool_mint.supply, old_pool_mint.supply - withdraw_amount);
+            assert!(take_rewards_program_invocations().is_empty());
         }
 
-        // pool is paused
+        // admin fees kept by the admin cannot be harvested
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+            let (admin_owned_key, admin_owned_account) = mint_token(
+                &spl_token::id(),
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
                 &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool,
+                &user_key,
+                admin_fee_a_amount,
             );
-            // pause pool
-            accounts.pause().unwrap();
-
+            accounts
+                .set_admin_fee_account(&admin_owned_key, &admin_owned_account)
+                .unwrap();
+            accounts.admin_fee_a_key = admin_owned_key;
+            accounts.admin_fee_a_account = admin_owned_account;
             assert_eq!(
-                Err(SwapError::IsPaused.into()),
-                accounts.withdraw_one(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    withdraw_amount,
-                    minimum_amount,
+                Err(SwapError::InvalidOwner.into()),
+                accounts.harvest_fees(
+                    &mut rewards_distributor_account,
+                    &mut rewards_program_account,
+                    &rewards_a_key,
+                    &mut rewards_a_account,
+                    &rewards_b_key,
+                    &mut rewards_b_account,
+                    &passed_on_key,
+                    &mut passed_on_account,
                 )
             );
         }
     }
+
+    #[test]
+    fn test_limit_order() {
+        let user_key = pubkey_rand();
+        let owner_key = pubkey_rand();
+        let amp_factor = 85;
+        let token_a_amount = 5000;
+        let token_b_amount = 5000;
+        let amount_in = 500;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            DEFAULT_TEST_FEES,
+        );
+        accounts.initialize_swap().unwrap();
+        let mut owner_account = Account::default();
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &owner_key, 2 * amount_in, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
+        // an order asking more than the pool pays rests in its escrow
+        let order_id = 7;
+        let (order_key, _) =
+            find_order_address(&SWAP_PROGRAM_ID, &accounts.swap_key, &owner_key, order_id);
+        let mut order_account = Account::new(1, Order::LEN, &SWAP_PROGRAM_ID);
+        let (escrow_key, mut escrow_account) = mint_token(
+            &spl_token::id(),
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &order_key,
+            0,
+        );
+        let escrow_lamports = escrow_account.lamports;
+
+        // no order is opened while the pools are globally paused
+        let mut global_state = GlobalState::unpack(&accounts.global_state_account.data).unwrap();
+        global_state.is_paused = true;
+        GlobalState::pack(global_state, &mut accounts.global_state_account.data).unwrap();
+        assert_eq!(
+            Err(SwapError::GloballyPaused.into()),
+            accounts.create_order(
+                (&owner_key, &mut owner_account),
+                &mut order_account,
+                (&escrow_key, &mut escrow_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                order_id,
+                amount_in,
+                amount_in,
+            )
+        );
+        global_state.is_paused = false;
+        GlobalState::pack(global_state, &mut accounts.global_state_account.data).unwrap();
+
+        accounts
+            .create_order(
+                (&owner_key, &mut owner_account),
+                &mut order_account,
+                (&escrow_key, &mut escrow_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                order_id,
+                amount_in,
+                amount_in,
+            )
+            .unwrap();
+        let order = Order::unpack(&order_account.data).unwrap();
+        assert_eq!(order.owner, owner_key);
+        assert_eq!(order.escrow, escrow_key);
+        assert_eq!(order.destination, token_b_key);
+        assert_eq!(order.amount_in, amount_in);
+        assert_eq!(order.minimum_amount_out, amount_in);
+        let escrow = utils::unpack_token_account(&escrow_account.data).unwrap();
+        assert_eq!(escrow.amount, amount_in);
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, amount_in);
+
+        // the order id cannot be reused while the order is open
+        let (other_escrow_key, mut other_escrow_account) = mint_token(
+            &spl_token::id(),
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &order_key,
+            0,
+        );
+        assert_eq!(
+            Err(ProgramError::AccountAlreadyInitialized),
+            accounts.create_order(
+                (&owner_key, &mut owner_account),
+                &mut order_account,
+                (&other_escrow_key, &mut other_escrow_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                order_id,
+                amount_in,
+                amount_in,
+            )
+        );
+
+        // the order cannot be filled below its price
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            accounts.fill_order(
+                &order_key,
+                &mut order_account,
+                (&escrow_key, &mut escrow_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                (&owner_key, &mut owner_account),
+            )
+        );
+
+        // only its owner cancels the order
+        let other_key = pubkey_rand();
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            accounts.cancel_order(
+                (&other_key, &mut Account::default()),
+                (&order_key, &mut order_account),
+                (&escrow_key, &mut escrow_account),
+                (&token_a_key, &mut token_a_account),
+            )
+        );
+        accounts
+            .cancel_order(
+                (&owner_key, &mut owner_account),
+                (&order_key, &mut order_account),
+                (&escrow_key, &mut escrow_account),
+                (&token_a_key, &mut token_a_account),
+            )
+            .unwrap();
+        let token_a = utils::unpack_token_account(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, 2 * amount_in);
+        assert_eq!(escrow_account.lamports, 0);
+        assert_eq!(order_account.lamports, 0);
+        assert_eq!(owner_account.lamports, escrow_lamports + 1);
+
+        // an order the pool can pay is filled by anyone, closing it to its owner
+        let order_id = 8;
+        let (order_key, _) =
+            find_order_address(&SWAP_PROGRAM_ID, &accounts.swap_key, &owner_key, order_id);
+        let mut order_account = Account::new(1, Order::LEN, &SWAP_PROGRAM_ID);
+        let (escrow_key, mut escrow_account) = mint_token(
+            &spl_token::id(),
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &order_key,
+            0,
+        );
+        accounts
+            .create_order(
+                (&owner_key, &mut owner_account),
+                &mut order_account,
+                (&escrow_key, &mut escrow_account),
+                (&token_a_key, &mut token_a_account),
+                (&token_b_key, &mut token_b_account),
+                order_id,
+                amount_in,
+                amount_in / 2,
+            )
+            .unwrap();
+        let owner_lamports = owner_account.lamports;
+        accounts
+            .fill_order(
+                &order_key,
+                &mut order_account,
+                (&escrow_key, &mut escrow_account),
+                &swap_token_a_key,
+                &swap_token_b_key,
+                (&token_b_key, &mut token_b_account),
+                (&owner_key, &mut owner_account),
+            )
+            .unwrap();
+        let token_b = utils::unpack_token_account(&token_b_account.data).unwrap();
+        assert!(token_b.amount >= amount_in / 2);
+        let swap_token_a = utils::unpack_token_account(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount + amount_in);
+        assert_eq!(escrow_account.lamports, 0);
+        assert_eq!(order_account.lamports, 0);
+        assert_eq!(owner_account.lamports, owner_lamports + escrow_lamports + 1);
+        assert!(order_account.data.iter().all(|byte| *byte == 0));
+    }
 }
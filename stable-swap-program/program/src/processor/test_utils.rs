@@ -1,22 +1,52 @@
 //! Test utility methods
 
-use crate::{curve::ZERO_TS, fees::Fees, instruction::*, processor::Processor, state::SwapInfo};
+use crate::{
+    associated_token,
+    curve::ZERO_TS,
+    fees::{DynamicFee, FeeTier, Fees, MAX_FEE_TIERS, NO_DYNAMIC_FEE, NO_FEE_TIERS},
+    instruction::*,
+    processor::Processor,
+    state::{
+        find_shared_authority_address, CpiPolicy, GlobalState, PegRateSource, PoolCategory,
+        SwapInfo,
+    },
+};
 use solana_program::{
-    account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction,
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
 };
 use solana_program::{
-    clock::Clock, msg, program_pack::Pack, program_stubs, pubkey::Pubkey, rent::Rent, sysvar::id,
+    clock::Clock, msg, program_pack::Pack, program_stubs, pubkey::Pubkey, rent::Rent,
+    system_program, sysvar::id,
 };
 use solana_sdk::account::{create_account_for_test, create_is_signer_account_infos, Account};
 use spl_token::{
-    instruction::{initialize_account, initialize_mint, mint_to},
-    state::{Account as SplAccount, Mint as SplMint},
+    instruction::{approve, initialize_account, initialize_mint, mint_to},
+    native_mint,
+    state::{Account as SplAccount, AccountState, Mint as SplMint},
+};
+use std::cell::RefCell;
+
+#[cfg(feature = "serde")]
+use crate::{
+    processor::utils,
+    snapshot::{AccountSnapshot, PoolSnapshot},
+    state::find_global_state_address,
 };
 
 /// Test program id for the swap program.
 pub const SWAP_PROGRAM_ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
 
+/// Test program id for the rewards distributor program. Its invocations are
+/// recorded instead of being processed, see [take_rewards_program_invocations].
+pub const REWARDS_PROGRAM_ID: Pubkey = Pubkey::new_from_array([3u8; 32]);
+
+/// Test program id for the staking program. Its invocations are recorded
+/// instead of being processed, see [take_staking_program_invocations].
+pub const STAKING_PROGRAM_ID: Pubkey = Pubkey::new_from_array([4u8; 32]);
+
 /// Fees for testing
 pub const DEFAULT_TEST_FEES: Fees = Fees {
     admin_trade_fee_numerator: 1,
@@ -27,6 +57,10 @@ pub const DEFAULT_TEST_FEES: Fees = Fees {
     trade_fee_denominator: 100,
     withdraw_fee_numerator: 6,
     withdraw_fee_denominator: 100,
+    trade_fee_tiers: NO_FEE_TIERS,
+    dynamic_fee: NO_DYNAMIC_FEE,
+    rebalancing_rebate_bps: 0,
+    host_fee_bps: 0,
 };
 
 /// Default token decimals
@@ -40,6 +74,58 @@ pub fn clock_account(ts: i64) -> Account {
     Account::new_data(1, &clock, &id()).unwrap()
 }
 
+pub fn clock_account_at_slot(ts: i64, slot: u64) -> Account {
+    let clock = Clock {
+        unix_timestamp: ts,
+        slot,
+        ..Default::default()
+    };
+    Account::new_data(1, &clock, &id()).unwrap()
+}
+
+/// Trading Pyth price account, with the aggregate price `price * 10^expo`
+pub fn pyth_price_account(price: i64, expo: i32, pub_slot: u64) -> Account {
+    let mut account = Account::new(1, 3312, &Pubkey::new_unique());
+    account.data[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes()); // magic
+    account.data[4..8].copy_from_slice(&2u32.to_le_bytes()); // version
+    account.data[8..12].copy_from_slice(&3u32.to_le_bytes()); // price account
+    account.data[20..24].copy_from_slice(&expo.to_le_bytes());
+    account.data[208..216].copy_from_slice(&price.to_le_bytes());
+    account.data[224..228].copy_from_slice(&1u32.to_le_bytes()); // trading
+    account.data[232..240].copy_from_slice(&pub_slot.to_le_bytes());
+    account
+}
+
+/// SPL stake pool account staking `total_lamports` for `pool_token_supply` pool tokens,
+/// last updated at `last_update_epoch`
+pub fn stake_pool_account(
+    total_lamports: u64,
+    pool_token_supply: u64,
+    last_update_epoch: u64,
+) -> Account {
+    let mut account = Account::new(1, 611, &Pubkey::new_unique());
+    account.data[0] = 1; // stake pool
+    account.data[258..266].copy_from_slice(&total_lamports.to_le_bytes());
+    account.data[266..274].copy_from_slice(&pool_token_supply.to_le_bytes());
+    account.data[274..282].copy_from_slice(&last_update_epoch.to_le_bytes());
+    account
+}
+
+/// Instructions sysvar of a transaction whose only instruction, the current one,
+/// invokes `program_id` without accounts or data
+pub fn instructions_sysvar_account(program_id: &Pubkey) -> Account {
+    let mut data = vec![];
+    data.extend_from_slice(&1u16.to_le_bytes()); // number of instructions
+    data.extend_from_slice(&4u16.to_le_bytes()); // offset of the instruction
+    data.extend_from_slice(&0u16.to_le_bytes()); // number of accounts
+    data.extend_from_slice(program_id.as_ref());
+    data.extend_from_slice(&0u16.to_le_bytes()); // data length
+    data.extend_from_slice(&0u16.to_le_bytes()); // current instruction index
+    let mut account = Account::new(1, data.len(), &id());
+    account.data = data;
+    account
+}
+
 pub fn pubkey_rand() -> Pubkey {
     Pubkey::new_unique()
 }
@@ -69,6 +155,7 @@ pub struct SwapAccountInfo {
     pub admin_fee_a_account: Account,
     pub admin_fee_b_key: Pubkey,
     pub admin_fee_b_account: Account,
+    pub global_state_account: Account,
     pub fees: Fees,
 }
 
@@ -79,6 +166,52 @@ impl SwapAccountInfo {
         token_a_amount: u64,
         token_b_amount: u64,
         fees: Fees,
+    ) -> Self {
+        let (token_a_mint_key, token_a_mint_account) =
+            create_mint(&spl_token::id(), user_key, DEFAULT_TOKEN_DECIMALS, None);
+        Self::new_with_token_a_mint(
+            user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            fees,
+            &token_a_mint_key,
+            token_a_mint_account,
+        )
+    }
+
+    /// Creates a swap whose token A is an existing mint, e.g. to chain pools
+    pub fn new_with_token_a_mint(
+        user_key: &Pubkey,
+        amp_factor: u64,
+        token_a_amount: u64,
+        token_b_amount: u64,
+        fees: Fees,
+        token_a_mint_key: &Pubkey,
+        token_a_mint_account: Account,
+    ) -> Self {
+        let (token_b_mint_key, token_b_mint_account) =
+            create_mint(&spl_token::id(), &user_key, DEFAULT_TOKEN_DECIMALS, None);
+        Self::new_with_token_mints(
+            user_key,
+            amp_factor,
+            token_a_amount,
+            token_b_amount,
+            fees,
+            (token_a_mint_key, token_a_mint_account),
+            (&token_b_mint_key, token_b_mint_account),
+        )
+    }
+
+    /// Creates a swap of existing token A and token B mints, e.g. to migrate pools
+    pub fn new_with_token_mints(
+        user_key: &Pubkey,
+        amp_factor: u64,
+        token_a_amount: u64,
+        token_b_amount: u64,
+        fees: Fees,
+        (token_a_mint_key, mut token_a_mint_account): (&Pubkey, Account),
+        (token_b_mint_key, mut token_b_mint_account): (&Pubkey, Account),
     ) -> Self {
         let swap_key = pubkey_rand();
         let swap_account = Account::new(0, SwapInfo::get_packed_len(), &SWAP_PROGRAM_ID);
@@ -99,8 +232,7 @@ impl SwapAccountInfo {
             &user_key,
             0,
         );
-        let (token_a_mint_key, mut token_a_mint_account) =
-            create_mint(&spl_token::id(), &user_key, DEFAULT_TOKEN_DECIMALS, None);
+        let token_a_mint_key = *token_a_mint_key;
         let (token_a_key, token_a_account) = mint_token(
             &spl_token::id(),
             &token_a_mint_key,
@@ -117,8 +249,7 @@ impl SwapAccountInfo {
             &authority_key,
             0,
         );
-        let (token_b_mint_key, mut token_b_mint_account) =
-            create_mint(&spl_token::id(), &user_key, DEFAULT_TOKEN_DECIMALS, None);
+        let token_b_mint_key = *token_b_mint_key;
         let (token_b_key, token_b_account) = mint_token(
             &spl_token::id(),
             &token_b_mint_key,
@@ -163,10 +294,79 @@ impl SwapAccountInfo {
             admin_fee_a_account,
             admin_fee_b_key,
             admin_fee_b_account,
+            global_state_account: global_state_account(&SWAP_PROGRAM_ID),
             fees,
         }
     }
 
+    /// Reconstructs a swap byte for byte from a snapshot, e.g. of a live pool. Accounts
+    /// of the swap missing from the snapshot are left empty, except the pool mint which
+    /// is required. The pool token account is a new empty account of the admin. Swaps of
+    /// other program ids than [SWAP_PROGRAM_ID] replay through [do_process_instruction]
+    /// with their program id.
+    #[cfg(feature = "serde")]
+    pub fn from_snapshot(snapshot: &PoolSnapshot) -> Self {
+        let swap_info = snapshot.swap_info().unwrap();
+        let program_id = snapshot.swap.owner;
+        let swap_key = snapshot.swap.pubkey;
+        let account = |pubkey: &Pubkey| {
+            snapshot
+                .account(pubkey)
+                .map(snapshot_account)
+                .unwrap_or_default()
+        };
+        let authority_key = utils::authority_id(
+            &program_id,
+            swap_info.authority_seed(&swap_key),
+            swap_info.nonce,
+        )
+        .unwrap();
+
+        let mut pool_mint_account = account(&swap_info.pool_mint);
+        let (pool_token_key, pool_token_account) = mint_token(
+            &spl_token::id(),
+            &swap_info.pool_mint,
+            &mut pool_mint_account,
+            &authority_key,
+            &swap_info.admin_key,
+            0,
+        );
+        let (global_state_key, _bump_seed) = find_global_state_address(&program_id);
+        let global_state_account = snapshot
+            .account(&global_state_key)
+            .map(snapshot_account)
+            .unwrap_or_else(|| global_state_account(&program_id));
+
+        SwapAccountInfo {
+            nonce: swap_info.nonce,
+            authority_key,
+            initial_amp_factor: swap_info.initial_amp_factor,
+            target_amp_factor: swap_info.target_amp_factor,
+            swap_key,
+            swap_account: snapshot_account(&snapshot.swap),
+            pool_mint_key: swap_info.pool_mint,
+            pool_mint_account,
+            pool_token_key,
+            pool_token_account,
+            token_a_key: swap_info.token_a.reserves,
+            token_a_account: account(&swap_info.token_a.reserves),
+            token_a_mint_key: swap_info.token_a.mint,
+            token_a_mint_account: account(&swap_info.token_a.mint),
+            token_b_key: swap_info.token_b.reserves,
+            token_b_account: account(&swap_info.token_b.reserves),
+            token_b_mint_key: swap_info.token_b.mint,
+            token_b_mint_account: account(&swap_info.token_b.mint),
+            admin_key: swap_info.admin_key,
+            admin_account: Account::default(),
+            admin_fee_a_key: swap_info.token_a.admin_fees,
+            admin_fee_a_account: account(&swap_info.token_a.admin_fees),
+            admin_fee_b_key: swap_info.token_b.admin_fees,
+            admin_fee_b_account: account(&swap_info.token_b.admin_fees),
+            global_state_account,
+            fees: swap_info.fees,
+        }
+    }
+
     pub fn initialize_swap(&mut self) -> ProgramResult {
         do_process_instruction(
             initialize(
@@ -206,6 +406,155 @@ impl SwapAccountInfo {
         )
     }
 
+    /// Initializes the swap, locking `min_liquidity` of the pool tokens minted in the
+    /// locked liquidity account
+    pub fn initialize_swap_with_locked_liquidity(
+        &mut self,
+        (locked_lp_key, locked_lp_account): (&Pubkey, &mut Account),
+        min_liquidity: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_locked_liquidity(
+                initialize(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.admin_key,
+                    &self.admin_fee_a_key,
+                    &self.admin_fee_b_key,
+                    &self.token_a_mint_key,
+                    &self.token_a_key,
+                    &self.token_b_mint_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.pool_token_key,
+                    self.nonce,
+                    self.initial_amp_factor,
+                    self.fees,
+                )
+                .unwrap(),
+                locked_lp_key,
+                min_liquidity,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut self.admin_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut self.token_a_mint_account,
+                &mut self.token_a_account,
+                &mut self.token_b_mint_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                &mut self.pool_token_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                locked_lp_account,
+            ],
+        )
+    }
+
+    /// Initializes the swap at `current_ts`, its amp factor ramping from the initial amp
+    /// factor of the accounts to `target_amp_factor` over `ramp_duration`
+    pub fn initialize_swap_with_amp_ramp(
+        &mut self,
+        target_amp_factor: u64,
+        ramp_duration: i64,
+        current_ts: i64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_amp_ramp(
+                initialize(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.admin_key,
+                    &self.admin_fee_a_key,
+                    &self.admin_fee_b_key,
+                    &self.token_a_mint_key,
+                    &self.token_a_key,
+                    &self.token_b_mint_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.pool_token_key,
+                    self.nonce,
+                    self.initial_amp_factor,
+                    self.fees,
+                )
+                .unwrap(),
+                target_amp_factor,
+                ramp_duration,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut self.admin_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut self.token_a_mint_account,
+                &mut self.token_a_account,
+                &mut self.token_b_mint_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                &mut self.pool_token_account,
+                &mut Account::default(),
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    /// Initializes the swap with the shared authority, taking over the token accounts and
+    /// the pool mint from the vault owner
+    pub fn initialize_swap_with_shared_authority(
+        &mut self,
+        vault_owner_key: &Pubkey,
+    ) -> ProgramResult {
+        do_process_instruction(
+            initialize_with_shared_authority(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.admin_key,
+                &self.admin_fee_a_key,
+                &self.admin_fee_b_key,
+                &self.token_a_mint_key,
+                &self.token_a_key,
+                &self.token_b_mint_key,
+                &self.token_b_key,
+                &self.pool_mint_key,
+                &self.pool_token_key,
+                vault_owner_key,
+                self.initial_amp_factor,
+                self.fees,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut self.admin_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut self.token_a_mint_account,
+                &mut self.token_a_account,
+                &mut self.token_b_mint_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                &mut self.pool_token_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut Account::default(),
+            ],
+        )?;
+        let (authority_key, nonce) = find_shared_authority_address(&SWAP_PROGRAM_ID);
+        self.authority_key = authority_key;
+        self.nonce = nonce;
+        Ok(())
+    }
+
     pub fn setup_token_accounts(
         &mut self,
         mint_owner: &Pubkey,
@@ -343,6 +692,7 @@ impl SwapAccountInfo {
                 &mut admin_destination_account,
                 &mut Account::default(),
                 &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
             ],
         )?;
 
@@ -353,253 +703,3233 @@ impl SwapAccountInfo {
         Ok(())
     }
 
-    pub fn deposit(
+    /// Simulates a swap, which fails with `SimulationComplete` and changes no account
+    #[allow(clippy::too_many_arguments)]
+    pub fn simulate_swap(
         &mut self,
-        depositor_key: &Pubkey,
-        depositor_token_a_key: &Pubkey,
-        mut depositor_token_a_account: &mut Account,
-        depositor_token_b_key: &Pubkey,
-        mut depositor_token_b_account: &mut Account,
-        depositor_pool_key: &Pubkey,
-        mut depositor_pool_account: &mut Account,
-        amount_a: u64,
-        amount_b: u64,
-        min_mint_amount: u64,
+        user_key: &Pubkey,
+        user_source_key: &Pubkey,
+        user_source_account: &mut Account,
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        user_destination_key: &Pubkey,
+        user_destination_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
     ) -> ProgramResult {
-        // perform deposit
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
         do_process_instruction(
-            deposit(
+            simulate_swap(
                 &SWAP_PROGRAM_ID,
                 &spl_token::id(),
                 &self.swap_key,
                 &self.authority_key,
-                &depositor_key,
-                &depositor_token_a_key,
-                &depositor_token_b_key,
-                &self.token_a_key,
-                &self.token_b_key,
-                &self.pool_mint_key,
-                &depositor_pool_key,
-                amount_a,
-                amount_b,
-                min_mint_amount,
+                user_key,
+                user_source_key,
+                swap_source_key,
+                swap_destination_key,
+                user_destination_key,
+                &admin_destination_key,
+                amount_in,
+                minimum_amount_out,
             )
             .unwrap(),
             vec![
                 &mut self.swap_account,
                 &mut Account::default(),
                 &mut Account::default(),
-                &mut depositor_token_a_account,
-                &mut depositor_token_b_account,
-                &mut self.token_a_account,
-                &mut self.token_b_account,
-                &mut self.pool_mint_account,
-                &mut depositor_pool_account,
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
                 &mut Account::default(),
                 &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
             ],
         )
     }
 
-    pub fn withdraw(
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_allow_partial(
         &mut self,
         user_key: &Pubkey,
-        pool_key: &Pubkey,
-        mut pool_account: &mut Account,
-        token_a_key: &Pubkey,
-        mut token_a_account: &mut Account,
-        token_b_key: &Pubkey,
-        mut token_b_account: &mut Account,
-        pool_amount: u64,
-        minimum_a_amount: u64,
-        minimum_b_amount: u64,
+        user_source_key: &Pubkey,
+        user_source_account: &mut Account,
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        user_destination_key: &Pubkey,
+        user_destination_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
     ) -> ProgramResult {
-        // perform withdraw
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
         do_process_instruction(
-            withdraw(
+            swap_allow_partial(
                 &SWAP_PROGRAM_ID,
                 &spl_token::id(),
                 &self.swap_key,
                 &self.authority_key,
-                &user_key,
-                &self.pool_mint_key,
-                &pool_key,
-                &self.token_a_key,
-                &self.token_b_key,
-                &token_a_key,
-                &token_b_key,
-                &self.admin_fee_a_key,
-                &self.admin_fee_b_key,
-                pool_amount,
-                minimum_a_amount,
-                minimum_b_amount,
+                user_key,
+                user_source_key,
+                swap_source_key,
+                swap_destination_key,
+                user_destination_key,
+                &admin_destination_key,
+                amount_in,
+                minimum_amount_out,
             )
             .unwrap(),
             vec![
                 &mut self.swap_account,
                 &mut Account::default(),
                 &mut Account::default(),
-                &mut self.pool_mint_account,
-                &mut pool_account,
-                &mut self.token_a_account,
-                &mut self.token_b_account,
-                &mut token_a_account,
-                &mut token_b_account,
-                &mut self.admin_fee_a_account,
-                &mut self.admin_fee_b_account,
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
                 &mut Account::default(),
                 &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
             ],
         )?;
 
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
         Ok(())
     }
 
-    pub fn withdraw_one(
+    pub fn swap_batch(
         &mut self,
         user_key: &Pubkey,
-        pool_key: &Pubkey,
-        mut pool_account: &mut Account,
-        dest_token_key: &Pubkey,
-        mut dest_token_account: &mut Account,
+        (user_source_key, user_source_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (user_destination_key, user_destination_account): (&Pubkey, &mut Account),
+        fills: &[SwapData],
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            swap_batch(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                user_source_key,
+                swap_source_key,
+                swap_destination_key,
+                user_destination_key,
+                &admin_destination_key,
+                fills,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    /// Swaps with the accounts of an spl-token-swap `Swap`
+    pub fn spl_token_swap_swap(
+        &mut self,
+        user_key: &Pubkey,
+        user_source_key: &Pubkey,
+        user_source_account: &mut Account,
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        user_destination_key: &Pubkey,
+        user_destination_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            spl_token_swap_swap(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                user_source_key,
+                swap_source_key,
+                swap_destination_key,
+                user_destination_key,
+                &self.pool_mint_key,
+                &admin_destination_key,
+                amount_in,
+                minimum_amount_out,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut self.pool_mint_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut self.global_state_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    /// Swaps token A of this swap into token B of `second`, whose token A is this swap's token B
+    pub fn swap_route(
+        &mut self,
+        second: &mut SwapAccountInfo,
+        user_key: &Pubkey,
+        user_source_key: &Pubkey,
+        mut user_source_account: &mut Account,
+        user_destination_key: &Pubkey,
+        mut user_destination_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
+        let first_hop = SwapRouteHop {
+            swap: self.swap_key,
+            swap_authority: self.authority_key,
+            swap_source: self.token_a_key,
+            swap_destination: self.token_b_key,
+            admin_fee_destination: self.admin_fee_b_key,
+        };
+        let second_hop = SwapRouteHop {
+            swap: second.swap_key,
+            swap_authority: second.authority_key,
+            swap_source: second.token_a_key,
+            swap_destination: second.token_b_key,
+            admin_fee_destination: second.admin_fee_b_key,
+        };
+
+        do_process_instruction(
+            swap_route(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                user_key,
+                user_source_key,
+                user_destination_key,
+                &first_hop,
+                &second_hop,
+                amount_in,
+                minimum_amount_out,
+            )
+            .unwrap(),
+            vec![
+                &mut Account::default(),
+                &mut user_source_account,
+                &mut user_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.admin_fee_b_account,
+                &mut second.swap_account,
+                &mut Account::default(),
+                &mut second.token_a_account,
+                &mut second.token_b_account,
+                &mut second.admin_fee_b_account,
+                &mut self.global_state_account,
+            ],
+        )
+    }
+
+    /// Swaps token A into token B, split across this swap and `second`, a swap of the same
+    /// mints
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_split(
+        &mut self,
+        second: &mut SwapAccountInfo,
+        user_key: &Pubkey,
+        user_source_key: &Pubkey,
+        mut user_source_account: &mut Account,
+        user_destination_key: &Pubkey,
+        mut user_destination_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
+        let pools = [&*self, &*second].map(|pool| SwapRouteHop {
+            swap: pool.swap_key,
+            swap_authority: pool.authority_key,
+            swap_source: pool.token_a_key,
+            swap_destination: pool.token_b_key,
+            admin_fee_destination: pool.admin_fee_b_key,
+        });
+
+        do_process_instruction(
+            swap_split(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                user_key,
+                user_source_key,
+                user_destination_key,
+                &pools,
+                amount_in,
+                minimum_amount_out,
+            )
+            .unwrap(),
+            vec![
+                &mut Account::default(),
+                &mut user_source_account,
+                &mut user_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.admin_fee_b_account,
+                &mut second.swap_account,
+                &mut Account::default(),
+                &mut second.token_a_account,
+                &mut second.token_b_account,
+                &mut second.admin_fee_b_account,
+                &mut self.global_state_account,
+            ],
+        )
+    }
+
+    pub fn deposit(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        mut depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        mut depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        mut depositor_pool_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        // perform deposit
+        do_process_instruction(
+            deposit(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                &depositor_key,
+                &depositor_token_a_key,
+                &depositor_token_b_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                &self.pool_mint_key,
+                &depositor_pool_key,
+                amount_a,
+                amount_b,
+                min_mint_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut depositor_token_a_account,
+                &mut depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                &mut depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+            ],
+        )
+    }
+
+    pub fn withdraw(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        mut pool_account: &mut Account,
+        token_a_key: &Pubkey,
+        mut token_a_account: &mut Account,
+        token_b_key: &Pubkey,
+        mut token_b_account: &mut Account,
         pool_amount: u64,
-        minimum_amount: u64,
+        minimum_a_amount: u64,
+        minimum_b_amount: u64,
+    ) -> ProgramResult {
+        // perform withdraw
+        do_process_instruction(
+            withdraw(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                &user_key,
+                &self.pool_mint_key,
+                &pool_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                &token_a_key,
+                &token_b_key,
+                &self.admin_fee_a_key,
+                &self.admin_fee_b_key,
+                pool_amount,
+                minimum_a_amount,
+                minimum_b_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                &mut pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut token_a_account,
+                &mut token_b_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Withdraws from a swap minting its admin fees as pool tokens, passing the admin
+    /// pool token account
+    #[allow(clippy::too_many_arguments)]
+    pub fn withdraw_with_admin_fees_as_lp(
+        &mut self,
+        user_key: &Pubkey,
+        (pool_key, pool_account): (&Pubkey, &mut Account),
+        (token_a_key, token_a_account): (&Pubkey, &mut Account),
+        (token_b_key, token_b_account): (&Pubkey, &mut Account),
+        (admin_pool_key, admin_pool_account): (&Pubkey, &mut Account),
+        pool_amount: u64,
+    ) -> ProgramResult {
+        let mut trailing_pool_mint_account = self.pool_mint_account.clone();
+        do_process_instruction(
+            with_admin_fees_as_lp(
+                withdraw(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    &self.pool_mint_key,
+                    pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    token_a_key,
+                    token_b_key,
+                    &self.admin_fee_a_key,
+                    &self.admin_fee_b_key,
+                    pool_amount,
+                    0,
+                    0,
+                )
+                .unwrap(),
+                &self.pool_mint_key,
+                admin_pool_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                token_a_account,
+                token_b_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut trailing_pool_mint_account,
+                admin_pool_account,
+            ],
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn emergency_withdraw(
+        &mut self,
+        user_key: &Pubkey,
+        (pool_key, pool_account): (&Pubkey, &mut Account),
+        (token_a_key, token_a_account): (&Pubkey, &mut Account),
+        (token_b_key, token_b_account): (&Pubkey, &mut Account),
+        pool_amount: u64,
+        minimum_a_amount: u64,
+        minimum_b_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            emergency_withdraw(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                &self.pool_mint_key,
+                pool_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                token_a_key,
+                token_b_key,
+                pool_amount,
+                minimum_a_amount,
+                minimum_b_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                token_a_account,
+                token_b_account,
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn withdraw_one(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        mut pool_account: &mut Account,
+        dest_token_key: &Pubkey,
+        mut dest_token_account: &mut Account,
+        pool_amount: u64,
+        minimum_amount: u64,
+    ) -> ProgramResult {
+        // perform withdraw_one
+        do_process_instruction(
+            withdraw_one(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                &user_key,
+                &self.pool_mint_key,
+                &pool_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                &dest_token_key,
+                &self.admin_fee_a_key,
+                pool_amount,
+                minimum_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                &mut pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut dest_token_account,
+                &mut self.admin_fee_a_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+            ],
+        )
+    }
+
+    pub fn withdraw_one_exact_out(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        dest_token_key: &Pubkey,
+        dest_token_account: &mut Account,
+        token_amount: u64,
+        maximum_burn_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            withdraw_one_exact_out(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                &self.pool_mint_key,
+                pool_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                dest_token_key,
+                &self.admin_fee_a_key,
+                token_amount,
+                maximum_burn_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                dest_token_account,
+                &mut self.admin_fee_a_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+            ],
+        )
+    }
+
+    /// Deposits and stakes the minted pool tokens, passing one more account on
+    /// to the staking program
+    #[allow(clippy::too_many_arguments)]
+    pub fn deposit_and_stake(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        depositor_pool_account: &mut Account,
+        staking_config_account: &mut Account,
+        staking_program_account: &mut Account,
+        staking_pool_key: &Pubkey,
+        staking_pool_account: &mut Account,
+        passed_on_key: &Pubkey,
+        passed_on_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_stake_on_deposit(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &STAKING_PROGRAM_ID,
+                staking_pool_key,
+                &[AccountMeta::new_readonly(*passed_on_key, false)],
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                staking_config_account,
+                staking_program_account,
+                staking_pool_account,
+                passed_on_account,
+            ],
+        )
+    }
+
+    pub fn deposit_with_associated_token_accounts(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        depositor_pool_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_associated_token_accounts(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                &[],
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn withdraw_with_associated_token_accounts(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        token_a_key: &Pubkey,
+        token_a_account: &mut Account,
+        token_b_key: &Pubkey,
+        token_b_account: &mut Account,
+        pool_amount: u64,
+        minimum_a_amount: u64,
+        minimum_b_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_associated_token_accounts(
+                withdraw(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    &self.pool_mint_key,
+                    pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    token_a_key,
+                    token_b_key,
+                    &self.admin_fee_a_key,
+                    &self.admin_fee_b_key,
+                    pool_amount,
+                    minimum_a_amount,
+                    minimum_b_amount,
+                )
+                .unwrap(),
+                &[&self.token_a_mint_key, &self.token_b_mint_key],
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                token_a_account,
+                token_b_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.token_a_mint_account,
+                &mut self.token_b_mint_account,
+            ],
+        )
+    }
+
+    pub fn deposit_with_fee_exemptions(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        depositor_pool_account: &mut Account,
+        fee_exemptions_key: &Pubkey,
+        fee_exemptions_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_fee_exemptions(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                fee_exemptions_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                fee_exemptions_account,
+            ],
+        )
+    }
+
+    pub fn deposit_with_deposit_allowlist(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        depositor_pool_account: &mut Account,
+        deposit_allowlist_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_deposit_allowlist(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                deposit_allowlist_account,
+            ],
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn deposit_with_deposit_caps(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        depositor_pool_account: &mut Account,
+        deposit_caps_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_deposit_caps(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                deposit_caps_account,
+            ],
+        )
+    }
+
+    pub fn withdraw_one_with_fee_exemptions(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        dest_token_key: &Pubkey,
+        dest_token_account: &mut Account,
+        fee_exemptions_key: &Pubkey,
+        fee_exemptions_account: &mut Account,
+        pool_amount: u64,
+        minimum_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_fee_exemptions(
+                withdraw_one(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    &self.pool_mint_key,
+                    pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    dest_token_key,
+                    &self.admin_fee_a_key,
+                    pool_amount,
+                    minimum_amount,
+                )
+                .unwrap(),
+                fee_exemptions_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                dest_token_account,
+                &mut self.admin_fee_a_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                fee_exemptions_account,
+            ],
+        )
+    }
+
+    pub fn swap_with_pool_stats(
+        &mut self,
+        user_key: &Pubkey,
+        user_source_key: &Pubkey,
+        user_source_account: &mut Account,
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        user_destination_key: &Pubkey,
+        user_destination_account: &mut Account,
+        pool_stats_key: &Pubkey,
+        pool_stats_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        slot: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_pool_stats(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    minimum_amount_out,
+                )
+                .unwrap(),
+                pool_stats_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account_at_slot(ZERO_TS, slot),
+                &mut self.global_state_account,
+                pool_stats_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    /// Swaps as if invoked by `caller_program_id`, passing the accounts of the CPI guard
+    /// Swaps on a swap minting its admin fees as pool tokens, passing the pool mint
+    /// and the admin pool token account
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_with_admin_fees_as_lp(
+        &mut self,
+        user_key: &Pubkey,
+        (user_source_key, user_source_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (user_destination_key, user_destination_account): (&Pubkey, &mut Account),
+        (admin_pool_key, admin_pool_account): (&Pubkey, &mut Account),
+        amount_in: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_admin_fees_as_lp(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    0,
+                )
+                .unwrap(),
+                &self.pool_mint_key,
+                admin_pool_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                &mut self.pool_mint_account,
+                admin_pool_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_with_same_slot_guard(
+        &mut self,
+        user_key: &Pubkey,
+        (user_source_key, user_source_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (user_destination_key, user_destination_account): (&Pubkey, &mut Account),
+        last_trade_account: &mut Account,
+        slot: u64,
+        amount_in: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_same_slot_guard(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    0,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account_at_slot(ZERO_TS, slot),
+                &mut self.global_state_account,
+                last_trade_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_with_cpi_guard(
+        &mut self,
+        user_key: &Pubkey,
+        (user_source_key, user_source_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (user_destination_key, user_destination_account): (&Pubkey, &mut Account),
+        caller_program_id: &Pubkey,
+        cpi_allowlist_account: &mut Account,
+        amount_in: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_cpi_guard(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    0,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                &mut instructions_sysvar_account(caller_program_id),
+                cpi_allowlist_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    pub fn swap_with_price_oracles(
+        &mut self,
+        user_key: &Pubkey,
+        user_source_key: &Pubkey,
+        user_source_account: &mut Account,
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        user_destination_key: &Pubkey,
+        user_destination_account: &mut Account,
+        price_oracle_a_key: &Pubkey,
+        price_oracle_a_account: &mut Account,
+        price_oracle_b_key: &Pubkey,
+        price_oracle_b_account: &mut Account,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        slot: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_price_oracles(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    minimum_amount_out,
+                )
+                .unwrap(),
+                price_oracle_a_key,
+                price_oracle_b_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account_at_slot(ZERO_TS, slot),
+                &mut self.global_state_account,
+                price_oracle_a_account,
+                price_oracle_b_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_with_host_fee(
+        &mut self,
+        user_key: &Pubkey,
+        (user_source_key, user_source_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (user_destination_key, user_destination_account): (&Pubkey, &mut Account),
+        (host_fee_key, host_fee_account): (&Pubkey, &mut Account),
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_host_fee(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    minimum_amount_out,
+                )
+                .unwrap(),
+                host_fee_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                host_fee_account,
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_with_native_sol(
+        &mut self,
+        (user_key, user_account): (&Pubkey, &mut Account),
+        (user_source_key, user_source_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (user_destination_key, user_destination_account): (&Pubkey, &mut Account),
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+        do_process_instruction(
+            with_native_sol(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    user_source_key,
+                    swap_source_key,
+                    swap_destination_key,
+                    user_destination_key,
+                    &admin_destination_key,
+                    amount_in,
+                    minimum_amount_out,
+                )
+                .unwrap(),
+                user_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                user_account,
+                user_source_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                user_destination_account,
+                &mut admin_destination_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn deposit_with_native_sol(
+        &mut self,
+        (depositor_key, depositor_account): (&Pubkey, &mut Account),
+        (depositor_token_a_key, depositor_token_a_account): (&Pubkey, &mut Account),
+        (depositor_token_b_key, depositor_token_b_account): (&Pubkey, &mut Account),
+        (depositor_pool_key, depositor_pool_account): (&Pubkey, &mut Account),
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_native_sol(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                depositor_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                depositor_account,
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn deposit_with_lp_position(
+        &mut self,
+        depositor_key: &Pubkey,
+        depositor_token_a_key: &Pubkey,
+        depositor_token_a_account: &mut Account,
+        depositor_token_b_key: &Pubkey,
+        depositor_token_b_account: &mut Account,
+        depositor_pool_key: &Pubkey,
+        depositor_pool_account: &mut Account,
+        lp_position_account: &mut Account,
+        amount_a: u64,
+        amount_b: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_lp_position(
+                deposit(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    depositor_key,
+                    depositor_token_a_key,
+                    depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    depositor_pool_key,
+                    amount_a,
+                    amount_b,
+                    min_mint_amount,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                depositor_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                depositor_token_a_account,
+                depositor_token_b_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                depositor_pool_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+                lp_position_account,
+            ],
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn withdraw_with_lp_position(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        token_a_key: &Pubkey,
+        token_a_account: &mut Account,
+        token_b_key: &Pubkey,
+        token_b_account: &mut Account,
+        lp_position_account: &mut Account,
+        pool_amount: u64,
+        current_ts: i64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_lp_position(
+                withdraw(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    &self.pool_mint_key,
+                    pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    token_a_key,
+                    token_b_key,
+                    &self.admin_fee_a_key,
+                    &self.admin_fee_b_key,
+                    pool_amount,
+                    0,
+                    0,
+                )
+                .unwrap(),
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                user_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                token_a_account,
+                token_b_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut Account::default(),
+                &mut clock_account(current_ts),
+                lp_position_account,
+            ],
+        )
+    }
+
+    pub fn zap_in(
+        &mut self,
+        user_key: &Pubkey,
+        source_key: &Pubkey,
+        source_account: &mut Account,
+        input_is_a: bool,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        amount_in: u64,
+        min_mint_amount: u64,
+    ) -> ProgramResult {
+        let (admin_fee_key, admin_fee_account) = if input_is_a {
+            (&self.admin_fee_b_key, &mut self.admin_fee_b_account)
+        } else {
+            (&self.admin_fee_a_key, &mut self.admin_fee_a_account)
+        };
+        do_process_instruction(
+            zap_in(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                source_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                &self.pool_mint_key,
+                pool_key,
+                admin_fee_key,
+                amount_in,
+                input_is_a,
+                min_mint_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                source_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                pool_account,
+                admin_fee_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+            ],
+        )
+    }
+
+    pub fn zap_out(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        want_token_a: bool,
+        dest_token_key: &Pubkey,
+        dest_token_account: &mut Account,
+        pool_amount: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            zap_out(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                &self.pool_mint_key,
+                pool_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                dest_token_key,
+                &self.admin_fee_a_key,
+                &self.admin_fee_b_key,
+                pool_amount,
+                want_token_a,
+                minimum_amount_out,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                dest_token_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
+            ],
+        )
+    }
+
+    pub fn withdraw_one_with_pool_stats(
+        &mut self,
+        user_key: &Pubkey,
+        pool_key: &Pubkey,
+        pool_account: &mut Account,
+        dest_token_key: &Pubkey,
+        dest_token_account: &mut Account,
+        pool_stats_key: &Pubkey,
+        pool_stats_account: &mut Account,
+        pool_amount: u64,
+        minimum_amount: u64,
+        slot: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_pool_stats(
+                withdraw_one(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    user_key,
+                    &self.pool_mint_key,
+                    pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    dest_token_key,
+                    &self.admin_fee_a_key,
+                    pool_amount,
+                    minimum_amount,
+                )
+                .unwrap(),
+                pool_stats_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                dest_token_account,
+                &mut self.admin_fee_a_account,
+                &mut Account::default(),
+                &mut clock_account_at_slot(ZERO_TS, slot),
+                pool_stats_account,
+            ],
+        )
+    }
+
+    /** Admin functions **/
+
+    pub fn ramp_a(&mut self, target_amp: u64, current_ts: i64, stop_ramp_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            ramp_a(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                target_amp,
+                stop_ramp_ts,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn ramp_a_precise(
+        &mut self,
+        target_amp: u64,
+        current_ts: i64,
+        stop_ramp_ts: i64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            ramp_a_precise(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                target_amp,
+                stop_ramp_ts,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn ramp_fees(
+        &mut self,
+        target_fees: Fees,
+        current_ts: i64,
+        stop_ramp_ts: i64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            ramp_fees(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                target_fees,
+                stop_ramp_ts,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn stop_ramp_a(&mut self, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            stop_ramp_a(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn emergency_amp_down(&mut self, divisor: u64, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            emergency_amp_down(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, divisor).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn sync_amp(&mut self, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            sync_amp(&SWAP_PROGRAM_ID, &self.swap_key).unwrap(),
+            vec![&mut self.swap_account, &mut clock_account(current_ts)],
+        )
+    }
+
+    pub fn get_amp_factor(&mut self, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            get_amp_factor(&SWAP_PROGRAM_ID, &self.swap_key).unwrap(),
+            vec![&mut self.swap_account, &mut clock_account(current_ts)],
+        )
+    }
+
+    pub fn sync_reserves(&mut self) -> ProgramResult {
+        do_process_instruction(
+            sync_reserves(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.token_a_key,
+                &self.token_b_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut clock_account(ZERO_TS),
+            ],
+        )
+    }
+
+    pub fn pause(&mut self) -> ProgramResult {
+        do_process_instruction(
+            pause(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn deprecate_pool(&mut self) -> ProgramResult {
+        do_process_instruction(
+            deprecate_pool(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn migrate_to_shared_authority(&mut self) -> ProgramResult {
+        do_process_instruction(
+            migrate_to_shared_authority(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.admin_key,
+                &self.authority_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                &self.admin_fee_a_key,
+                &self.admin_fee_b_key,
+                &self.pool_mint_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                &mut self.pool_mint_account,
+                &mut Account::default(),
+            ],
+        )?;
+        let (authority_key, nonce) = find_shared_authority_address(&SWAP_PROGRAM_ID);
+        self.authority_key = authority_key;
+        self.nonce = nonce;
+        Ok(())
+    }
+
+    pub fn pause_with_admin_multisig(&mut self, signer_keys: &[&Pubkey]) -> ProgramResult {
+        let mut signer_accounts = vec![Account::default(); signer_keys.len()];
+        let mut accounts = vec![&mut self.swap_account, &mut self.admin_account];
+        accounts.extend(signer_accounts.iter_mut());
+        do_process_instruction(
+            with_admin_multisig_signers(
+                pause(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+                signer_keys,
+            ),
+            accounts,
+        )
+    }
+
+    pub fn unpause(&mut self) -> ProgramResult {
+        do_process_instruction(
+            unpause(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_admin_fee_account(
+        &mut self,
+        new_admin_fee_key: &Pubkey,
+        new_admin_fee_account: &Account,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_fee_account(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                new_admin_fee_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut new_admin_fee_account.clone(),
+            ],
+        )
+    }
+
+    pub fn collect_admin_fees(
+        &mut self,
+        destination_a_key: &Pubkey,
+        destination_a_account: &mut Account,
+        destination_b_key: &Pubkey,
+        destination_b_account: &mut Account,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            collect_admin_fees(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.admin_key,
+                &self.authority_key,
+                &self.admin_fee_a_key,
+                &self.admin_fee_b_key,
+                destination_a_key,
+                destination_b_key,
+                token_a_amount,
+                token_b_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                destination_a_account,
+                destination_b_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+            ],
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn collect_admin_fees_with_fee_owner(
+        &mut self,
+        fee_owner_key: &Pubkey,
+        destination_a_key: &Pubkey,
+        destination_a_account: &mut Account,
+        destination_b_key: &Pubkey,
+        destination_b_account: &mut Account,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            with_admin_fee_owner(
+                collect_admin_fees(
+                    &SWAP_PROGRAM_ID,
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.admin_key,
+                    &self.authority_key,
+                    &self.admin_fee_a_key,
+                    &self.admin_fee_b_key,
+                    destination_a_key,
+                    destination_b_key,
+                    token_a_amount,
+                    token_b_amount,
+                )
+                .unwrap(),
+                fee_owner_key,
+            ),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                destination_a_account,
+                destination_b_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn set_lp_frozen(
+        &mut self,
+        frozen: bool,
+        lp_key: &Pubkey,
+        lp_account: &mut Account,
+    ) -> ProgramResult {
+        let build = if frozen { freeze_lp } else { thaw_lp };
+        do_process_instruction(
+            build(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                &self.admin_key,
+                &self.pool_mint_key,
+                lp_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                lp_account,
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn apply_new_admin(&mut self, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            apply_new_admin(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn commit_new_admin(&mut self, new_admin_key: &Pubkey, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            commit_new_admin(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                new_admin_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn set_new_fees(&mut self, new_fees: Fees, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            set_new_fees(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, new_fees).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn set_fee_tiers(
+        &mut self,
+        tiers: [FeeTier; MAX_FEE_TIERS],
+        current_ts: i64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_fee_tiers(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, tiers).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn commit_new_fees(&mut self, new_fees: Fees, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            commit_new_fees(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, new_fees).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn apply_new_fees(&mut self, current_ts: i64) -> ProgramResult {
+        do_process_instruction(
+            apply_new_fees(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut clock_account(current_ts),
+            ],
+        )
+    }
+
+    pub fn cancel_pending(&mut self) -> ProgramResult {
+        do_process_instruction(
+            cancel_pending(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_admin_multisig(
+        &mut self,
+        multisig_key: &Pubkey,
+        multisig_account: &mut Account,
+        threshold: u8,
+        signer_keys: &[&Pubkey],
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_admin_multisig(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                multisig_key,
+                &pubkey_rand(),
+                threshold,
+                signer_keys,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                multisig_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_fee_exemptions(
+        &mut self,
+        fee_exemptions_key: &Pubkey,
+        fee_exemptions_account: &mut Account,
+        exempt_keys: &[&Pubkey],
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_fee_exemptions(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                fee_exemptions_key,
+                &pubkey_rand(),
+                exempt_keys,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                fee_exemptions_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_deposit_allowlist(
+        &mut self,
+        deposit_allowlist_key: &Pubkey,
+        deposit_allowlist_account: &mut Account,
+        allowed_keys: &[&Pubkey],
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_deposit_allowlist(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                deposit_allowlist_key,
+                &pubkey_rand(),
+                allowed_keys,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                deposit_allowlist_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_cpi_policy(&mut self, cpi_policy: CpiPolicy) -> ProgramResult {
+        do_process_instruction(
+            set_cpi_policy(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                cpi_policy,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_cpi_allowlist(
+        &mut self,
+        cpi_allowlist_key: &Pubkey,
+        cpi_allowlist_account: &mut Account,
+        allowed_program_ids: &[&Pubkey],
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_cpi_allowlist(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                cpi_allowlist_key,
+                &pubkey_rand(),
+                allowed_program_ids,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                cpi_allowlist_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_same_slot_guard(
+        &mut self,
+        last_trade_account: &mut Account,
+        enabled: bool,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_same_slot_guard(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &pubkey_rand(),
+                enabled,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                last_trade_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_admin_fees_as_lp(&mut self, enabled: bool) -> ProgramResult {
+        do_process_instruction(
+            set_admin_fees_as_lp(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, enabled)
+                .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_quiet_logs(&mut self, enabled: bool) -> ProgramResult {
+        do_process_instruction(
+            set_quiet_logs(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, enabled).unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_max_lp_per_owner(
+        &mut self,
+        deposit_caps_account: &mut Account,
+        max_lp_per_owner: u64,
+        exempt_owners: &[&Pubkey],
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_max_lp_per_owner(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &pubkey_rand(),
+                max_lp_per_owner,
+                exempt_owners,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                deposit_caps_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_max_lp_supply(
+        &mut self,
+        deposit_caps_account: &mut Account,
+        max_lp_supply: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_max_lp_supply(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &pubkey_rand(),
+                max_lp_supply,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                deposit_caps_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_withdraw_one_while_paused(&mut self, enabled: bool) -> ProgramResult {
+        do_process_instruction(
+            set_withdraw_one_while_paused(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                enabled,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_peg_rate_source(
+        &mut self,
+        peg_config_key: &Pubkey,
+        peg_config_account: &mut Account,
+        rate_source: PegRateSource,
+        rate_account_key: &Pubkey,
+        rate_account: &mut Account,
+        slot: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_peg_rate_source(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                peg_config_key,
+                &pubkey_rand(),
+                rate_source,
+                rate_account_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                peg_config_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+                rate_account,
+                &mut clock_account_at_slot(ZERO_TS, slot),
+            ],
+        )
+    }
+
+    pub fn refresh_peg_rate(
+        &mut self,
+        peg_config_key: &Pubkey,
+        peg_config_account: &mut Account,
+        rate_account_key: &Pubkey,
+        rate_account: &mut Account,
+        slot: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            refresh_peg_rate(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                peg_config_key,
+                rate_account_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                peg_config_account,
+                rate_account,
+                &mut clock_account_at_slot(ZERO_TS, slot),
+            ],
+        )
+    }
+
+    pub fn set_deposit_allowlist_enabled(&mut self, enabled: bool) -> ProgramResult {
+        do_process_instruction(
+            set_deposit_allowlist_enabled(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                enabled,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_quick_swap_max_bps(&mut self, quick_swap_max_bps: u64) -> ProgramResult {
+        do_process_instruction(
+            set_quick_swap_max_bps(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                quick_swap_max_bps,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_max_swap_bps(&mut self, max_swap_bps: u64) -> ProgramResult {
+        do_process_instruction(
+            set_max_swap_bps(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                max_swap_bps,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_pool_info(
+        &mut self,
+        pool_registry_entry_account: &mut Account,
+        name: &str,
+        category: PoolCategory,
+        is_deprecated: bool,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_pool_info(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &pubkey_rand(),
+                name,
+                category,
+                is_deprecated,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                pool_registry_entry_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_rewards_distributor(
+        &mut self,
+        rewards_distributor_account: &mut Account,
+        rewards_program_account: &mut Account,
+        rewards_a_key: &Pubkey,
+        rewards_a_account: &mut Account,
+        rewards_b_key: &Pubkey,
+        rewards_b_account: &mut Account,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_rewards_distributor(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &pubkey_rand(),
+                &REWARDS_PROGRAM_ID,
+                rewards_a_key,
+                rewards_b_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                rewards_distributor_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+                rewards_program_account,
+                rewards_a_account,
+                rewards_b_account,
+            ],
+        )
+    }
+
+    pub fn set_staking_config(
+        &mut self,
+        staking_config_account: &mut Account,
+        staking_program_account: &mut Account,
+        staking_pool_key: &Pubkey,
+        staking_pool_account: &mut Account,
+        stake_instruction_prefix: [u8; 8],
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_staking_config(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &pubkey_rand(),
+                &STAKING_PROGRAM_ID,
+                staking_pool_key,
+                stake_instruction_prefix,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                staking_config_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+                staking_program_account,
+                staking_pool_account,
+            ],
+        )
+    }
+
+    /// Harvests the admin fees, passing one more account on to the rewards program
+    #[allow(clippy::too_many_arguments)]
+    pub fn harvest_fees(
+        &mut self,
+        rewards_distributor_account: &mut Account,
+        rewards_program_account: &mut Account,
+        rewards_a_key: &Pubkey,
+        rewards_a_account: &mut Account,
+        rewards_b_key: &Pubkey,
+        rewards_b_account: &mut Account,
+        passed_on_key: &Pubkey,
+        passed_on_account: &mut Account,
+    ) -> ProgramResult {
+        let mut instruction = harvest_fees(
+            &SWAP_PROGRAM_ID,
+            &spl_token::id(),
+            &self.swap_key,
+            &self.authority_key,
+            &self.admin_fee_a_key,
+            &self.admin_fee_b_key,
+            &REWARDS_PROGRAM_ID,
+            rewards_a_key,
+            rewards_b_key,
+        )
+        .unwrap();
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(*passed_on_key, false));
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut self.admin_fee_a_account,
+                &mut self.admin_fee_b_account,
+                rewards_distributor_account,
+                rewards_a_account,
+                rewards_b_account,
+                rewards_program_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                passed_on_account,
+            ],
+        )
+    }
+
+    pub fn migrate_pool(
+        &mut self,
+        new_swap: &mut SwapAccountInfo,
+        lp_escrow_key: &Pubkey,
+        lp_escrow_account: &mut Account,
+        pool_migration_account: &mut Account,
+    ) -> ProgramResult {
+        do_process_instruction(
+            migrate_pool(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.admin_key,
+                &self.authority_key,
+                &self.token_a_key,
+                &self.token_b_key,
+                &self.pool_mint_key,
+                &new_swap.swap_key,
+                &new_swap.authority_key,
+                &new_swap.token_a_key,
+                &new_swap.token_b_key,
+                &new_swap.pool_mint_key,
+                lp_escrow_key,
+                &pubkey_rand(),
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut self.token_a_account,
+                &mut self.token_b_account,
+                &mut self.pool_mint_account,
+                &mut new_swap.swap_account,
+                &mut Account::default(),
+                &mut new_swap.token_a_account,
+                &mut new_swap.token_b_account,
+                &mut new_swap.pool_mint_account,
+                lp_escrow_account,
+                pool_migration_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+            ],
+        )
+    }
+
+    pub fn redeem_migrated_lp(
+        &mut self,
+        user_key: &Pubkey,
+        (pool_key, pool_account): (&Pubkey, &mut Account),
+        (lp_escrow_key, lp_escrow_account): (&Pubkey, &mut Account),
+        (destination_key, destination_account): (&Pubkey, &mut Account),
+        pool_migration_account: &mut Account,
+        pool_token_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            redeem_migrated_lp(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                user_key,
+                &self.pool_mint_key,
+                pool_key,
+                lp_escrow_key,
+                destination_key,
+                pool_token_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                pool_account,
+                lp_escrow_account,
+                destination_account,
+                pool_migration_account,
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn recover_stranded_tokens(
+        &mut self,
+        stranded_key: &Pubkey,
+        stranded_account: &mut Account,
+        recover_token_a: bool,
+    ) -> ProgramResult {
+        let (admin_fee_key, admin_fee_account) = if recover_token_a {
+            (&self.admin_fee_a_key, &mut self.admin_fee_a_account)
+        } else {
+            (&self.admin_fee_b_key, &mut self.admin_fee_b_account)
+        };
+        do_process_instruction(
+            recover_stranded_tokens(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                &self.authority_key,
+                stranded_key,
+                admin_fee_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut Account::default(),
+                stranded_account,
+                admin_fee_account,
+                &mut Account::default(),
+            ],
+        )
+    }
+
+    pub fn init_pool_stats(
+        &mut self,
+        pool_stats_key: &Pubkey,
+        pool_stats_account: &mut Account,
+    ) -> ProgramResult {
+        do_process_instruction(
+            init_pool_stats(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                pool_stats_key,
+                &pubkey_rand(),
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                pool_stats_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_pool_metadata(
+        &mut self,
+        pool_mint_key: &Pubkey,
+        name: &str,
+        symbol: &str,
+        uri: &str,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_pool_metadata(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                &self.authority_key,
+                pool_mint_key,
+                &pubkey_rand(),
+                name,
+                symbol,
+                uri,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                &mut self.admin_account,
+                &mut Account::default(),
+                &mut self.pool_mint_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn set_emergency_authority(
+        &mut self,
+        upgrade_authority_key: &Pubkey,
+        program_data_account: &mut Account,
+        emergency_authority_key: &Pubkey,
     ) -> ProgramResult {
-        // perform withdraw_one
         do_process_instruction(
-            withdraw_one(
+            set_emergency_authority(
+                &SWAP_PROGRAM_ID,
+                upgrade_authority_key,
+                &pubkey_rand(),
+                emergency_authority_key,
+            )
+            .unwrap(),
+            vec![
+                &mut self.global_state_account,
+                &mut Account::default(),
+                program_data_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+    }
+
+    pub fn global_pause(&mut self, emergency_authority_key: &Pubkey) -> ProgramResult {
+        do_process_instruction(
+            global_pause(&SWAP_PROGRAM_ID, emergency_authority_key).unwrap(),
+            vec![&mut self.global_state_account, &mut Account::default()],
+        )
+    }
+
+    pub fn global_unpause(&mut self, emergency_authority_key: &Pubkey) -> ProgramResult {
+        do_process_instruction(
+            global_unpause(&SWAP_PROGRAM_ID, emergency_authority_key).unwrap(),
+            vec![&mut self.global_state_account, &mut Account::default()],
+        )
+    }
+
+    pub fn set_reject_zero_amounts(&mut self, reject: bool) -> ProgramResult {
+        do_process_instruction(
+            set_reject_zero_amounts(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, reject)
+                .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_withdraw_fee_grace_period(
+        &mut self,
+        withdraw_fee_grace_period: i64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            set_withdraw_fee_grace_period(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                withdraw_fee_grace_period,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn set_donations_admin_only(&mut self, admin_only: bool) -> ProgramResult {
+        do_process_instruction(
+            set_donations_admin_only(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                &self.admin_key,
+                admin_only,
+            )
+            .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
+
+    pub fn donate_to_reserves(
+        &mut self,
+        donor_key: &Pubkey,
+        source_a_key: &Pubkey,
+        source_a_account: &mut Account,
+        source_b_key: &Pubkey,
+        source_b_account: &mut Account,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> ProgramResult {
+        do_process_instruction(
+            donate_to_reserves(
                 &SWAP_PROGRAM_ID,
                 &spl_token::id(),
                 &self.swap_key,
-                &self.authority_key,
-                &user_key,
-                &self.pool_mint_key,
-                &pool_key,
+                donor_key,
+                source_a_key,
+                source_b_key,
                 &self.token_a_key,
                 &self.token_b_key,
-                &dest_token_key,
-                &self.admin_fee_a_key,
-                pool_amount,
-                minimum_amount,
+                token_a_amount,
+                token_b_amount,
             )
             .unwrap(),
             vec![
                 &mut self.swap_account,
                 &mut Account::default(),
-                &mut Account::default(),
-                &mut self.pool_mint_account,
-                &mut pool_account,
+                source_a_account,
+                source_b_account,
                 &mut self.token_a_account,
                 &mut self.token_b_account,
-                &mut dest_token_account,
-                &mut self.admin_fee_a_account,
                 &mut Account::default(),
                 &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
             ],
         )
     }
 
-    /** Admin functions **/
+    pub fn set_spl_token_swap_compat(&mut self, enabled: bool) -> ProgramResult {
+        do_process_instruction(
+            set_spl_token_swap_compat(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, enabled)
+                .unwrap(),
+            vec![&mut self.swap_account, &mut self.admin_account],
+        )
+    }
 
-    pub fn ramp_a(&mut self, target_amp: u64, current_ts: i64, stop_ramp_ts: i64) -> ProgramResult {
+    pub fn set_price_oracles(
+        &mut self,
+        price_oracle_a_key: &Pubkey,
+        price_oracle_a_account: &mut Account,
+        price_oracle_b_key: &Pubkey,
+        price_oracle_b_account: &mut Account,
+        max_deviation_bps: u64,
+    ) -> ProgramResult {
         do_process_instruction(
-            ramp_a(
+            set_price_oracles(
                 &SWAP_PROGRAM_ID,
                 &self.swap_key,
                 &self.admin_key,
-                target_amp,
-                stop_ramp_ts,
+                price_oracle_a_key,
+                price_oracle_b_key,
+                max_deviation_bps,
             )
             .unwrap(),
             vec![
                 &mut self.swap_account,
                 &mut self.admin_account,
-                &mut clock_account(current_ts),
+                price_oracle_a_account,
+                price_oracle_b_account,
             ],
         )
     }
 
-    pub fn stop_ramp_a(&mut self, current_ts: i64) -> ProgramResult {
+    pub fn sync_lp_position(
+        &mut self,
+        owner_key: &Pubkey,
+        pool_token_key: &Pubkey,
+        pool_token_account: &mut Account,
+        lp_position_account: &mut Account,
+    ) -> ProgramResult {
         do_process_instruction(
-            stop_ramp_a(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            sync_lp_position(
+                &SWAP_PROGRAM_ID,
+                &self.swap_key,
+                owner_key,
+                pool_token_key,
+                &pubkey_rand(),
+            )
+            .unwrap(),
             vec![
                 &mut self.swap_account,
-                &mut self.admin_account,
-                &mut clock_account(current_ts),
+                &mut Account::default(),
+                lp_position_account,
+                pool_token_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
             ],
         )
     }
 
-    pub fn pause(&mut self) -> ProgramResult {
-        do_process_instruction(
-            pause(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
-            vec![&mut self.swap_account, &mut self.admin_account],
-        )
-    }
-
-    pub fn unpause(&mut self) -> ProgramResult {
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_order(
+        &mut self,
+        (owner_key, owner_account): (&Pubkey, &mut Account),
+        order_account: &mut Account,
+        (escrow_key, escrow_account): (&Pubkey, &mut Account),
+        (source_key, source_account): (&Pubkey, &mut Account),
+        (destination_key, destination_account): (&Pubkey, &mut Account),
+        order_id: u64,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> ProgramResult {
         do_process_instruction(
-            unpause(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
-            vec![&mut self.swap_account, &mut self.admin_account],
+            create_order(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                owner_key,
+                escrow_key,
+                source_key,
+                destination_key,
+                &pubkey_rand(),
+                order_id,
+                amount_in,
+                minimum_amount_out,
+            )
+            .unwrap(),
+            vec![
+                &mut self.swap_account,
+                owner_account,
+                order_account,
+                escrow_account,
+                source_account,
+                destination_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+                &mut self.global_state_account,
+            ],
         )
     }
 
-    pub fn set_admin_fee_account(
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_order(
         &mut self,
-        new_admin_fee_key: &Pubkey,
-        new_admin_fee_account: &Account,
+        order_key: &Pubkey,
+        order_account: &mut Account,
+        (escrow_key, escrow_account): (&Pubkey, &mut Account),
+        swap_source_key: &Pubkey,
+        swap_destination_key: &Pubkey,
+        (destination_key, destination_account): (&Pubkey, &mut Account),
+        (owner_key, owner_account): (&Pubkey, &mut Account),
     ) -> ProgramResult {
+        let admin_destination_key = self.get_admin_fee_key(swap_destination_key);
+        let mut admin_destination_account =
+            self.get_admin_fee_account(&admin_destination_key).clone();
+        let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+        let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
         do_process_instruction(
-            set_fee_account(
+            fill_order(
                 &SWAP_PROGRAM_ID,
+                &spl_token::id(),
                 &self.swap_key,
-                &self.admin_key,
-                new_admin_fee_key,
+                &self.authority_key,
+                order_key,
+                escrow_key,
+                swap_source_key,
+                swap_destination_key,
+                destination_key,
+                &admin_destination_key,
+                owner_key,
             )
             .unwrap(),
             vec![
                 &mut self.swap_account,
-                &mut self.admin_account,
-                &mut new_admin_fee_account.clone(),
+                &mut Account::default(),
+                order_account,
+                escrow_account,
+                &mut swap_source_account,
+                &mut swap_destination_account,
+                destination_account,
+                &mut admin_destination_account,
+                owner_account,
+                &mut Account::default(),
+                &mut clock_account(ZERO_TS),
+                &mut self.global_state_account,
             ],
-        )
+        )?;
+
+        self.set_admin_fee_account_(&admin_destination_key, admin_destination_account);
+        self.set_token_account(swap_source_key, swap_source_account);
+        self.set_token_account(swap_destination_key, swap_destination_account);
+
+        Ok(())
     }
 
-    pub fn apply_new_admin(&mut self, current_ts: i64) -> ProgramResult {
+    pub fn cancel_order(
+        &mut self,
+        (owner_key, owner_account): (&Pubkey, &mut Account),
+        (order_key, order_account): (&Pubkey, &mut Account),
+        (escrow_key, escrow_account): (&Pubkey, &mut Account),
+        (destination_key, destination_account): (&Pubkey, &mut Account),
+    ) -> ProgramResult {
         do_process_instruction(
-            apply_new_admin(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key).unwrap(),
+            cancel_order(
+                &SWAP_PROGRAM_ID,
+                &spl_token::id(),
+                &self.swap_key,
+                owner_key,
+                order_key,
+                escrow_key,
+                destination_key,
+            )
+            .unwrap(),
             vec![
                 &mut self.swap_account,
-                &mut self.admin_account,
-                &mut clock_account(current_ts),
+                owner_account,
+                order_account,
+                escrow_account,
+                destination_account,
+                &mut Account::default(),
             ],
         )
     }
 
-    pub fn commit_new_admin(&mut self, new_admin_key: &Pubkey, current_ts: i64) -> ProgramResult {
+    pub fn set_fee_mode(&mut self, dynamic_fee: DynamicFee, current_ts: i64) -> ProgramResult {
         do_process_instruction(
-            commit_new_admin(
+            set_fee_mode(
                 &SWAP_PROGRAM_ID,
                 &self.swap_key,
                 &self.admin_key,
-                new_admin_key,
+                dynamic_fee,
             )
             .unwrap(),
             vec![
                 &mut self.swap_account,
                 &mut self.admin_account,
-                &mut Account::default(),
                 &mut clock_account(current_ts),
             ],
         )
     }
+}
 
-    pub fn set_new_fees(&mut self, new_fees: Fees) -> ProgramResult {
-        do_process_instruction(
-            set_new_fees(&SWAP_PROGRAM_ID, &self.swap_key, &self.admin_key, new_fees).unwrap(),
-            vec![&mut self.swap_account, &mut self.admin_account],
-        )
+thread_local! {
+    /// Return data set by the last instruction processed on this thread
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = RefCell::new(None);
+    /// Swap program id of the last instruction processed on this thread, which signs
+    /// its invocations. [SWAP_PROGRAM_ID] unless a snapshot of another program is replayed.
+    static PROGRAM_ID: RefCell<Pubkey> = const { RefCell::new(SWAP_PROGRAM_ID) };
+    /// Instructions invoked on the rewards distributor program on this thread
+    static REWARDS_PROGRAM_INVOCATIONS: RefCell<Vec<Instruction>> = const { RefCell::new(Vec::new()) };
+    /// Instructions invoked on the staking program on this thread
+    static STAKING_PROGRAM_INVOCATIONS: RefCell<Vec<Instruction>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Takes the instructions invoked on [REWARDS_PROGRAM_ID] so far on this thread
+pub fn take_rewards_program_invocations() -> Vec<Instruction> {
+    REWARDS_PROGRAM_INVOCATIONS.with(|invocations| invocations.take())
+}
+
+/// Takes the instructions invoked on [STAKING_PROGRAM_ID] so far on this thread
+pub fn take_staking_program_invocations() -> Vec<Instruction> {
+    STAKING_PROGRAM_INVOCATIONS.with(|invocations| invocations.take())
+}
+
+/// Mimics the associated token account program. The token account must
+/// already be allocated, since account data cannot be resized here.
+fn create_associated_token_account(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+) -> ProgramResult {
+    let token_account_info = account_infos
+        .iter()
+        .find(|info| *info.key == instruction.accounts[1].pubkey)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    token_account_info.assign(&spl_token::id());
+    SplAccount::pack(
+        SplAccount {
+            mint: instruction.accounts[3].pubkey,
+            owner: instruction.accounts[2].pubkey,
+            state: AccountState::Initialized,
+            ..SplAccount::default()
+        },
+        &mut token_account_info.data.borrow_mut(),
+    )
+}
+
+/// Moves the lamports of a system `Transfer`
+fn transfer_lamports(instruction: &Instruction, account_infos: &[AccountInfo]) -> ProgramResult {
+    let find_account_info = |index: usize| {
+        account_infos
+            .iter()
+            .find(|info| *info.key == instruction.accounts[index].pubkey)
+            .ok_or(ProgramError::NotEnoughAccountKeys)
+    };
+    let from_info = find_account_info(0)?;
+    let to_info = find_account_info(1)?;
+    if !from_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
     }
+    // Bincode of SystemInstruction::Transfer, whose variant index is 2
+    if instruction.data.len() != 12 || instruction.data[..4] != 2u32.to_le_bytes() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let mut lamports = [0u8; 8];
+    lamports.copy_from_slice(&instruction.data[4..]);
+    let lamports = u64::from_le_bytes(lamports);
+    let from_lamports = from_info
+        .lamports()
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    **from_info.lamports.borrow_mut() = from_lamports;
+    **to_info.lamports.borrow_mut() += lamports;
+    Ok(())
+}
+
+/// Credits the lamports of a wrapped SOL account above its rent exempt reserve as
+/// tokens, as the spl_token `SyncNative` instruction, which the spl_token version
+/// of the tests may not process
+fn sync_native(instruction: &Instruction, account_infos: &[AccountInfo]) -> ProgramResult {
+    let native_account_info = account_infos
+        .iter()
+        .find(|info| *info.key == instruction.accounts[0].pubkey)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let mut native_account = SplAccount::unpack(&native_account_info.data.borrow())?;
+    let rent_exempt_reserve = native_account
+        .is_native
+        .ok_or(ProgramError::InvalidAccountData)?;
+    native_account.amount = native_account_info
+        .lamports()
+        .checked_sub(rent_exempt_reserve)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    SplAccount::pack(native_account, &mut native_account_info.data.borrow_mut())
 }
 
 struct TestSyscallStubs {}
@@ -614,18 +3944,48 @@ impl program_stubs::SyscallStubs for TestSyscallStubs {
 
         let mut new_account_infos = vec![];
 
+        if instruction.program_id == REWARDS_PROGRAM_ID {
+            if !account_infos.iter().any(|x| *x.key == REWARDS_PROGRAM_ID) {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            REWARDS_PROGRAM_INVOCATIONS
+                .with(|invocations| invocations.borrow_mut().push(instruction.clone()));
+            return Ok(());
+        }
+
+        if instruction.program_id == STAKING_PROGRAM_ID {
+            if !account_infos.iter().any(|x| *x.key == STAKING_PROGRAM_ID) {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            STAKING_PROGRAM_INVOCATIONS
+                .with(|invocations| invocations.borrow_mut().push(instruction.clone()));
+            return Ok(());
+        }
+
+        if instruction.program_id == system_program::id() {
+            return transfer_lamports(instruction, account_infos);
+        }
+
         // mimic check for token program in accounts
         if !account_infos.iter().any(|x| *x.key == spl_token::id()) {
             return Err(ProgramError::InvalidAccountData);
         }
 
+        if instruction.program_id == associated_token::id() {
+            return create_associated_token_account(instruction, account_infos);
+        }
+
+        if instruction.data == [17] {
+            return sync_native(instruction, account_infos);
+        }
+
         for meta in instruction.accounts.iter() {
             for account_info in account_infos.iter() {
                 if meta.pubkey == *account_info.key {
                     let mut new_account_info = account_info.clone();
                     for seeds in signers_seeds.iter() {
-                        let signer =
-                            Pubkey::create_program_address(&seeds, &SWAP_PROGRAM_ID).unwrap();
+                        let program_id = PROGRAM_ID.with(|program_id| *program_id.borrow());
+                        let signer = Pubkey::create_program_address(seeds, &program_id).unwrap();
                         if *account_info.key == signer {
                             new_account_info.is_signer = true;
                         }
@@ -641,9 +4001,37 @@ impl program_stubs::SyscallStubs for TestSyscallStubs {
             &instruction.data,
         )
     }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe {
+            *(var_addr as *mut Clock) = Clock {
+                unix_timestamp: ZERO_TS,
+                ..Clock::default()
+            };
+        }
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RETURN_DATA.with(|return_data| {
+            *return_data.borrow_mut() = if data.is_empty() {
+                None
+            } else {
+                Some((
+                    PROGRAM_ID.with(|program_id| *program_id.borrow()),
+                    data.to_vec(),
+                ))
+            }
+        });
+    }
 }
 
-fn test_syscall_stubs() {
+/// Routes the invocations of the program under test to the stubs of the tests
+pub fn test_syscall_stubs() {
     use std::sync::Once;
     static ONCE: Once = Once::new();
 
@@ -657,6 +4045,7 @@ pub fn do_process_instruction(
     accounts: Vec<&mut Account>,
 ) -> ProgramResult {
     test_syscall_stubs();
+    RETURN_DATA.with(|return_data| *return_data.borrow_mut() = None);
 
     // approximate the logic in the actual runtime which runs the instruction
     // and only updates accounts if the instruction is successful
@@ -668,14 +4057,17 @@ pub fn do_process_instruction(
         .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
         .collect::<Vec<_>>();
     let mut account_infos = create_is_signer_account_infos(&mut meta);
-    let res = if instruction.program_id == SWAP_PROGRAM_ID {
-        Processor::process(&instruction.program_id, &account_infos, &instruction.data)
-    } else {
+    // any program other than the token program is taken as the swap program, so that
+    // snapshots of pools of other program ids can be replayed
+    let res = if instruction.program_id == spl_token::id() {
         spl_token::processor::Processor::process(
             &instruction.program_id,
             &account_infos,
             &instruction.data,
         )
+    } else {
+        PROGRAM_ID.with(|program_id| *program_id.borrow_mut() = instruction.program_id);
+        Processor::process(&instruction.program_id, &account_infos, &instruction.data)
     };
 
     if res.is_ok() {
@@ -685,7 +4077,9 @@ pub fn do_process_instruction(
             .zip(accounts)
             .map(|(account_meta, account)| (&account_meta.pubkey, account))
             .collect::<Vec<_>>();
-        for account_info in account_infos.iter_mut() {
+        // the runtime shares the data of an account passed twice, here the program writes
+        // through its first copy
+        for account_info in account_infos.iter_mut().rev() {
             for account_meta in account_metas.iter_mut() {
                 if account_info.key == account_meta.0 {
                     let account = &mut account_meta.1;
@@ -703,6 +4097,18 @@ fn mint_minimum_balance() -> u64 {
     Rent::default().minimum_balance(SplMint::get_packed_len())
 }
 
+/// Global state of a program whose emergency authority has been set, which
+/// swaps and deposits require
+pub fn global_state_account(program_id: &Pubkey) -> Account {
+    let mut account = Account::new(1, GlobalState::LEN, program_id);
+    let global_state = GlobalState {
+        is_initialized: true,
+        ..GlobalState::default()
+    };
+    GlobalState::pack(global_state, &mut account.data).unwrap();
+    account
+}
+
 fn account_minimum_balance() -> u64 {
     Rent::default().minimum_balance(SplAccount::get_packed_len())
 }
@@ -716,13 +4122,19 @@ pub fn mint_token(
     amount: u64,
 ) -> (Pubkey, Account) {
     let account_key = pubkey_rand();
+    // Wrapped SOL is held as lamports above the rent exempt reserve rather than minted
+    let is_native = *mint_key == native_mint::id();
     let mut account_account = Account::new(
-        account_minimum_balance(),
+        account_minimum_balance() + if is_native { amount } else { 0 },
         SplAccount::get_packed_len(),
         &program_id,
     );
     let mut mint_authority_account = Account::default();
-    let mut rent_sysvar_account = create_account_for_test(&Rent::free());
+    let mut rent_sysvar_account = create_account_for_test(&if is_native {
+        Rent::default()
+    } else {
+        Rent::free()
+    });
 
     do_process_instruction(
         initialize_account(&program_id, &account_key, &mint_key, account_owner_key).unwrap(),
@@ -735,7 +4147,7 @@ pub fn mint_token(
     )
     .unwrap();
 
-    if amount > 0 {
+    if amount > 0 && !is_native {
         do_process_instruction(
             mint_to(
                 &program_id,
@@ -758,6 +4170,61 @@ pub fn mint_token(
     (account_key, account_account)
 }
 
+/// Lets `delegate_key` transfer or burn `amount` of the tokens of an account
+pub fn approve_delegate(
+    account_key: &Pubkey,
+    account: &mut Account,
+    owner_key: &Pubkey,
+    delegate_key: &Pubkey,
+    amount: u64,
+) {
+    do_process_instruction(
+        approve(
+            &spl_token::id(),
+            account_key,
+            delegate_key,
+            owner_key,
+            &[],
+            amount,
+        )
+        .unwrap(),
+        vec![account, &mut Account::default(), &mut Account::default()],
+    )
+    .unwrap();
+}
+
+/// Account of a snapshot, byte for byte
+#[cfg(feature = "serde")]
+pub fn snapshot_account(snapshot: &AccountSnapshot) -> Account {
+    Account {
+        lamports: snapshot.lamports,
+        data: snapshot.data.clone(),
+        owner: snapshot.owner,
+        executable: snapshot.executable,
+        rent_epoch: snapshot.rent_epoch,
+    }
+}
+
+/// Creates the wrapped SOL mint, with the decimals of the other test mints so that
+/// it can be paired with them
+pub fn create_native_mint() -> (Pubkey, Account) {
+    let mut mint_account = Account::new(
+        mint_minimum_balance(),
+        SplMint::get_packed_len(),
+        &spl_token::id(),
+    );
+    SplMint::pack(
+        SplMint {
+            decimals: DEFAULT_TOKEN_DECIMALS,
+            is_initialized: true,
+            ..SplMint::default()
+        },
+        &mut mint_account.data,
+    )
+    .unwrap();
+    (native_mint::id(), mint_account)
+}
+
 pub fn create_mint(
     program_id: &Pubkey,
     authority_key: &Pubkey,
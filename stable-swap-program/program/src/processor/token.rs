@@ -1,8 +1,98 @@
 //! Token helpers
 
+use crate::{
+    associated_token,
+    error::{ErrorContext, SwapError},
+};
 use solana_program::{
-    account_info::AccountInfo, program::invoke_signed, program_error::ProgramError, pubkey::Pubkey,
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction, system_program,
 };
+use spl_token::instruction::AuthorityType;
+use std::convert::TryInto;
+
+/// Offset of the amount in the data of a token account, after its mint and owner
+const TOKEN_ACCOUNT_AMOUNT_OFFSET: usize = 64;
+
+/// The amount held by a token account, read from its data without unpacking the rest,
+/// or `None` if the account is not a token account
+fn token_amount(account: &AccountInfo) -> Option<u64> {
+    let data = account.try_borrow_data().ok()?;
+    let amount = data.get(TOKEN_ACCOUNT_AMOUNT_OFFSET..TOKEN_ACCOUNT_AMOUNT_OFFSET + 8)?;
+    Some(u64::from_le_bytes(amount.try_into().ok()?))
+}
+
+/// Passes through an error of the token program instruction `ix`, after logging the
+/// instruction and its first account, a reserve or the pool mint of the swap in most of
+/// them, see [ErrorContext::TokenProgram]. The runtime aborts the transaction on most
+/// errors of an invoked program, whose own logs then tell what failed, so this only
+/// covers the errors it hands back to the swap.
+pub fn map_token_error(ix: &Instruction, error: ProgramError) -> ProgramError {
+    ErrorContext::token_program(ix, &error).log();
+    error
+}
+
+/// Invokes a token program instruction, see [map_token_error]
+fn invoke_token(
+    ix: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_signed(ix, account_infos, signers_seeds).map_err(|error| map_token_error(ix, error))
+}
+
+/// Checks a token account reloaded after a transfer holds the `expected` amount
+fn check_balance_change(name: &str, account: &AccountInfo, expected: Option<u64>) -> ProgramResult {
+    let amount = token_amount(account);
+    if amount.is_none() || amount != expected {
+        msg!(
+            "{} token account holds {:?} after the transfer, expected {:?}",
+            name,
+            amount,
+            expected
+        );
+        return Err(SwapError::UnexpectedBalanceChange.into());
+    }
+    Ok(())
+}
+
+/// Invokes a spl_token `Transfer` of `amount`, then reloads the source and destination
+/// and checks they moved exactly `amount`. The swap prices its instructions on the
+/// amounts it transfers, so a token program moving other amounts, e.g. a mint charging
+/// transfer fees, fails the instruction instead of mispricing the pool.
+fn invoke_transfer(
+    ix: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+    source: &AccountInfo,
+    destination: &AccountInfo,
+    amount: u64,
+) -> ProgramResult {
+    let balances = token_amount(source).zip(token_amount(destination));
+    invoke_token(ix, account_infos, signers_seeds)?;
+    // accounts that are not token accounts fail the transfer itself
+    let (source_before, destination_before) = match balances {
+        Some(balances) => balances,
+        None => return Ok(()),
+    };
+    let moved = if source.key == destination.key {
+        0
+    } else {
+        amount
+    };
+    check_balance_change("Source", source, source_before.checked_sub(moved))?;
+    check_balance_change(
+        "Destination",
+        destination,
+        destination_before.checked_add(moved),
+    )
+}
 
 /// Issue a spl_token `Burn` instruction.
 pub fn burn<'a>(
@@ -20,12 +110,16 @@ pub fn burn<'a>(
         &[],
         amount,
     )?;
-    solana_program::program::invoke(&ix, &[token_program, burn_account, mint, user_authority])
+    invoke_token(
+        &ix,
+        &[token_program, burn_account, mint, user_authority],
+        &[],
+    )
 }
 
 /// Issue a spl_token `MintTo` instruction.
 pub fn mint_to<'a>(
-    swap: &Pubkey,
+    authority_seed: &[u8],
     token_program: AccountInfo<'a>,
     mint: AccountInfo<'a>,
     destination: AccountInfo<'a>,
@@ -33,8 +127,7 @@ pub fn mint_to<'a>(
     nonce: u8,
     amount: u64,
 ) -> Result<(), ProgramError> {
-    let swap_bytes = swap.to_bytes();
-    let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
+    let authority_signature_seeds = [authority_seed, &[nonce]];
     let signers = &[&authority_signature_seeds[..]];
     let ix = spl_token::instruction::mint_to(
         token_program.key,
@@ -45,12 +138,12 @@ pub fn mint_to<'a>(
         amount,
     )?;
 
-    invoke_signed(&ix, &[mint, destination, authority, token_program], signers)
+    invoke_token(&ix, &[mint, destination, authority, token_program], signers)
 }
 
 /// Issue a spl_token `Transfer` instruction signed by the authority.
 pub fn transfer_as_swap<'a>(
-    swap: &Pubkey,
+    authority_seed: &[u8],
     token_program: AccountInfo<'a>,
     source: AccountInfo<'a>,
     destination: AccountInfo<'a>,
@@ -58,8 +151,7 @@ pub fn transfer_as_swap<'a>(
     nonce: u8,
     amount: u64,
 ) -> Result<(), ProgramError> {
-    let swap_bytes = swap.to_bytes();
-    let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
+    let authority_signature_seeds = [authority_seed, &[nonce]];
     let signers = &[&authority_signature_seeds[..]];
     let ix = spl_token::instruction::transfer(
         token_program.key,
@@ -70,13 +162,101 @@ pub fn transfer_as_swap<'a>(
         amount,
     )?;
 
-    invoke_signed(
+    invoke_transfer(
+        &ix,
+        &[
+            token_program,
+            source.clone(),
+            destination.clone(),
+            program_authority,
+        ],
+        signers,
+        &source,
+        &destination,
+        amount,
+    )
+}
+
+/// Issue a spl_token `FreezeAccount` or `ThawAccount` instruction signed by the authority.
+pub fn set_frozen_as_swap<'a>(
+    authority_seed: &[u8],
+    token_program: AccountInfo<'a>,
+    account: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    program_authority: AccountInfo<'a>,
+    nonce: u8,
+    frozen: bool,
+) -> Result<(), ProgramError> {
+    let authority_signature_seeds = [authority_seed, &[nonce]];
+    let signers = &[&authority_signature_seeds[..]];
+    let ix = if frozen {
+        spl_token::instruction::freeze_account(
+            token_program.key,
+            account.key,
+            mint.key,
+            program_authority.key,
+            &[],
+        )?
+    } else {
+        spl_token::instruction::thaw_account(
+            token_program.key,
+            account.key,
+            mint.key,
+            program_authority.key,
+            &[],
+        )?
+    };
+
+    invoke_token(
         &ix,
-        &[token_program, source, destination, program_authority],
+        &[token_program, account, mint, program_authority],
         signers,
     )
 }
 
+/// Issue a spl_token `SetAuthority` instruction signed by the authority.
+pub fn set_authority_as_swap<'a>(
+    authority_seed: &[u8],
+    token_program: AccountInfo<'a>,
+    account: AccountInfo<'a>,
+    new_authority: &Pubkey,
+    authority_type: AuthorityType,
+    program_authority: AccountInfo<'a>,
+    nonce: u8,
+) -> Result<(), ProgramError> {
+    let authority_signature_seeds = [authority_seed, &[nonce]];
+    let signers = &[&authority_signature_seeds[..]];
+    let ix = spl_token::instruction::set_authority(
+        token_program.key,
+        account.key,
+        Some(new_authority),
+        authority_type,
+        program_authority.key,
+        &[],
+    )?;
+
+    invoke_token(&ix, &[token_program, account, program_authority], signers)
+}
+
+/// Issue a spl_token `SetAuthority` instruction as the user.
+pub fn set_authority_as_user<'a>(
+    token_program: AccountInfo<'a>,
+    account: AccountInfo<'a>,
+    new_authority: &Pubkey,
+    authority_type: AuthorityType,
+    user_authority: AccountInfo<'a>,
+) -> Result<(), ProgramError> {
+    let ix = spl_token::instruction::set_authority(
+        token_program.key,
+        account.key,
+        Some(new_authority),
+        authority_type,
+        user_authority.key,
+        &[],
+    )?;
+    invoke_token(&ix, &[token_program, account, user_authority], &[])
+}
+
 /// Issue a spl_token `Transfer` instruction as the user.
 pub fn transfer_as_user<'a>(
     token_program: AccountInfo<'a>,
@@ -93,5 +273,217 @@ pub fn transfer_as_user<'a>(
         &[],
         amount,
     )?;
-    solana_program::program::invoke(&ix, &[token_program, source, destination, user_authority])
+    invoke_transfer(
+        &ix,
+        &[
+            token_program,
+            source.clone(),
+            destination.clone(),
+            user_authority,
+        ],
+        &[],
+        &source,
+        &destination,
+        amount,
+    )
+}
+
+/// Issue a spl_token `Transfer` instruction signed by a program address of the
+/// swap program other than the swap authority, e.g. an order holding an escrow.
+pub fn transfer_as_program_address<'a>(
+    signer_seeds: &[&[u8]],
+    token_program: AccountInfo<'a>,
+    source: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    program_address: AccountInfo<'a>,
+    amount: u64,
+) -> Result<(), ProgramError> {
+    let ix = spl_token::instruction::transfer(
+        token_program.key,
+        source.key,
+        destination.key,
+        program_address.key,
+        &[],
+        amount,
+    )?;
+    invoke_transfer(
+        &ix,
+        &[
+            token_program,
+            source.clone(),
+            destination.clone(),
+            program_address,
+        ],
+        &[signer_seeds],
+        &source,
+        &destination,
+        amount,
+    )
+}
+
+/// Issue a spl_token `CloseAccount` instruction signed by a program address of the
+/// swap program, paying the lamports of the account out to the destination.
+pub fn close_account_as_program_address<'a>(
+    signer_seeds: &[&[u8]],
+    token_program: AccountInfo<'a>,
+    account: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    program_address: AccountInfo<'a>,
+) -> Result<(), ProgramError> {
+    let ix = spl_token::instruction::close_account(
+        token_program.key,
+        account.key,
+        destination.key,
+        program_address.key,
+        &[],
+    )?;
+    invoke_token(
+        &ix,
+        &[token_program, account, destination, program_address],
+        &[signer_seeds],
+    )
+}
+
+/// Issue a system `Transfer` of lamports from the user to a wrapped SOL account,
+/// then a spl_token `SyncNative` instruction crediting them as tokens.
+pub fn wrap_native_sol_as_user<'a>(
+    system_program: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    native_account: AccountInfo<'a>,
+    user_authority: AccountInfo<'a>,
+    lamports: u64,
+) -> Result<(), ProgramError> {
+    let ix = system_instruction::transfer(user_authority.key, native_account.key, lamports);
+    solana_program::program::invoke(
+        &ix,
+        &[user_authority, native_account.clone(), system_program],
+    )?;
+    // The spl_token version this program builds against predates the `SyncNative`
+    // builder, so its instruction is packed by hand
+    let ix = Instruction {
+        program_id: *token_program.key,
+        accounts: vec![AccountMeta::new(*native_account.key, false)],
+        data: vec![17],
+    };
+    invoke_token(&ix, &[token_program, native_account], &[])
+}
+
+/// Issue a spl_token `CloseAccount` instruction as the user, paying the lamports of the
+/// account out to the user.
+pub fn close_account_as_user<'a>(
+    token_program: AccountInfo<'a>,
+    account: AccountInfo<'a>,
+    user_authority: AccountInfo<'a>,
+) -> Result<(), ProgramError> {
+    let ix = spl_token::instruction::close_account(
+        token_program.key,
+        account.key,
+        user_authority.key,
+        user_authority.key,
+        &[],
+    )?;
+    invoke_token(&ix, &[token_program, account, user_authority], &[])
+}
+
+/// Issue an associated token account `Create` instruction paid by the wallet,
+/// unless the token account already exists.
+pub fn create_associated_token_account_if_missing<'a>(
+    associated_token_program: AccountInfo<'a>,
+    system_program: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    token_account: AccountInfo<'a>,
+    wallet: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+) -> Result<(), ProgramError> {
+    if *token_account.owner != system_program::id() {
+        return Ok(());
+    }
+    let (associated_token_key, _bump_seed) =
+        associated_token::find_associated_token_address(wallet.key, mint.key);
+    if *token_account.key != associated_token_key {
+        return Err(SwapError::InvalidAssociatedTokenAccount.into());
+    }
+    let ix = associated_token::create_associated_token_account(wallet.key, wallet.key, mint.key);
+    solana_program::program::invoke(
+        &ix,
+        &[
+            wallet,
+            token_account,
+            mint,
+            system_program,
+            token_program,
+            associated_token_program,
+        ],
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::processor::test_utils::*;
+    use solana_sdk::account::create_is_signer_account_infos;
+
+    #[test]
+    fn test_transfer_checks_balance_changes() {
+        test_syscall_stubs();
+        let user_key = pubkey_rand();
+        let (mint_key, mut mint_account) = create_mint(&spl_token::id(), &user_key, 6, None);
+        let (source_key, mut source_account) = mint_token(
+            &spl_token::id(),
+            &mint_key,
+            &mut mint_account,
+            &user_key,
+            &user_key,
+            1_000,
+        );
+        let (destination_key, mut destination_account) = mint_token(
+            &spl_token::id(),
+            &mint_key,
+            &mut mint_account,
+            &user_key,
+            &user_key,
+            0,
+        );
+        let token_program_key = spl_token::id();
+        let mut token_program_account = Default::default();
+        let mut user_account = Default::default();
+        let mut accounts = [
+            (&token_program_key, false, &mut token_program_account),
+            (&source_key, false, &mut source_account),
+            (&destination_key, false, &mut destination_account),
+            (&user_key, true, &mut user_account),
+        ];
+        let infos = create_is_signer_account_infos(&mut accounts);
+        let transfer = |source: usize, destination: usize, amount| {
+            transfer_as_user(
+                infos[0].clone(),
+                infos[source].clone(),
+                infos[destination].clone(),
+                infos[3].clone(),
+                amount,
+            )
+        };
+
+        assert_eq!(transfer(1, 2, 400), Ok(()));
+        assert_eq!(token_amount(&infos[1]), Some(600));
+        assert_eq!(token_amount(&infos[2]), Some(400));
+        // token program errors are passed through
+        assert_eq!(
+            transfer(1, 2, 1_000),
+            Err(spl_token::error::TokenError::InsufficientFunds.into())
+        );
+        assert_eq!(token_amount(&infos[1]), Some(600));
+
+        // as left by a mint withholding a transfer fee of 1
+        assert_eq!(
+            check_balance_change("Destination", &infos[2], Some(401)),
+            Err(SwapError::UnexpectedBalanceChange.into())
+        );
+        assert_eq!(check_balance_change("Source", &infos[1], Some(600)), Ok(()));
+        assert_eq!(
+            check_balance_change("Token program", &infos[0], Some(0)),
+            Err(SwapError::UnexpectedBalanceChange.into())
+        );
+    }
 }
@@ -0,0 +1,412 @@
+//! State transitions of the instructions, free of accounts.
+//!
+//! A processor parses and validates its accounts, reads the balances it needs into
+//! plain values and hands them to a transition. The transition returns the new
+//! state and the token movements as [TransferIntent]s, which the processor executes
+//! once the whole transition succeeded. Transitions never touch accounts, so the
+//! economic logic is tested here without any account plumbing.
+
+use crate::{
+    curve::CurveCalculator, error::SwapError, fees::Fees, math, pool_converter::PoolTokenConverter,
+};
+
+use solana_program::program_error::ProgramError;
+
+use super::checks::check_can_withdraw_token;
+
+/// Token of the pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PoolToken {
+    /// Token A
+    A,
+    /// Token B
+    B,
+}
+
+/// Token movement requested by a transition
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferIntent {
+    /// Moves `amount` from the reserves of `token` to the user
+    ReservesToUser {
+        /// Reserves the tokens leave
+        token: PoolToken,
+        /// Amount moved
+        amount: u64,
+    },
+    /// Moves `amount` from the reserves of `token` to its admin fee account
+    ReservesToAdminFees {
+        /// Reserves the tokens leave
+        token: PoolToken,
+        /// Amount moved
+        amount: u64,
+    },
+    /// Burns `amount` pool tokens of the user
+    BurnPoolTokens {
+        /// Amount burnt
+        amount: u64,
+    },
+    /// Mints `amount` pool tokens to the pool token account of the admin
+    MintPoolTokensToAdmin {
+        /// Amount minted
+        amount: u64,
+    },
+}
+
+/// Balances of the pool before a transition
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolBalances {
+    /// Supply of the pool mint
+    pub pool_token_supply: u64,
+    /// Balance of the token A reserves
+    pub token_a: u64,
+    /// Balance of the token B reserves
+    pub token_b: u64,
+}
+
+/// Outcome of a withdrawal. Withdrawals of more pool tokens than the user holds
+/// are left to the burn to reject.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Withdrawal {
+    /// Token A and token B paid out to the user
+    pub amounts: [u64; 2],
+    /// Withdraw fees in token A and token B, admin share included
+    pub fees: [u64; 2],
+    /// Admin shares of the withdraw fees
+    pub admin_fees: [u64; 2],
+    /// Pool tokens minted to the admin for the admin fees, see [admin_fees_as_lp]
+    pub admin_pool_tokens: u64,
+    /// Supply of the pool mint after the withdrawal
+    pub pool_token_supply: u64,
+    /// Token movements, in execution order
+    pub intents: Vec<TransferIntent>,
+}
+
+/// Proportional withdrawal of `pool_token_amount` against the curve balances, net of
+/// the withdraw fee. The admin fees leave the reserves with the withdrawal.
+pub fn withdraw(
+    pool: &PoolBalances,
+    fees: &Fees,
+    pool_token_amount: u64,
+    minimum_token_a_amount: u64,
+    minimum_token_b_amount: u64,
+) -> Result<Withdrawal, ProgramError> {
+    if pool.pool_token_supply == 0 {
+        return Err(SwapError::EmptyPool.into());
+    }
+    let converter = PoolTokenConverter {
+        supply: pool.pool_token_supply,
+        token_a: pool.token_a,
+        token_b: pool.token_b,
+        fees,
+    };
+    let (a_amount, a_fee, a_admin_fee) = check_can_withdraw_token(
+        converter.token_a_rate(pool_token_amount),
+        minimum_token_a_amount,
+    )?;
+    let (b_amount, b_fee, b_admin_fee) = check_can_withdraw_token(
+        converter.token_b_rate(pool_token_amount),
+        minimum_token_b_amount,
+    )?;
+
+    Ok(Withdrawal {
+        amounts: [a_amount, b_amount],
+        fees: [a_fee, b_fee],
+        admin_fees: [a_admin_fee, b_admin_fee],
+        admin_pool_tokens: 0,
+        pool_token_supply: pool.pool_token_supply.saturating_sub(pool_token_amount),
+        intents: vec![
+            TransferIntent::ReservesToUser {
+                token: PoolToken::A,
+                amount: a_amount,
+            },
+            TransferIntent::ReservesToAdminFees {
+                token: PoolToken::A,
+                amount: a_admin_fee,
+            },
+            TransferIntent::ReservesToUser {
+                token: PoolToken::B,
+                amount: b_amount,
+            },
+            TransferIntent::ReservesToAdminFees {
+                token: PoolToken::B,
+                amount: b_admin_fee,
+            },
+            TransferIntent::BurnPoolTokens {
+                amount: pool_token_amount,
+            },
+        ],
+    })
+}
+
+/// Withdrawal of a paused pool, against the raw balances rather than the curve and
+/// without fees. Rounds down in favor of the pool.
+pub fn emergency_withdraw(
+    pool: &PoolBalances,
+    pool_token_amount: u64,
+    minimum_token_a_amount: u64,
+    minimum_token_b_amount: u64,
+) -> Result<Withdrawal, ProgramError> {
+    if pool.pool_token_supply == 0 {
+        return Err(SwapError::EmptyPool.into());
+    }
+    let a_amount = math::mul_div_floor(pool_token_amount, pool.token_a, pool.pool_token_supply)
+        .ok_or(SwapError::CalculationFailure)?;
+    let b_amount = math::mul_div_floor(pool_token_amount, pool.token_b, pool.pool_token_supply)
+        .ok_or(SwapError::CalculationFailure)?;
+    if a_amount < minimum_token_a_amount || b_amount < minimum_token_b_amount {
+        return Err(SwapError::ExceededSlippage.into());
+    }
+
+    Ok(Withdrawal {
+        amounts: [a_amount, b_amount],
+        fees: [0, 0],
+        admin_fees: [0, 0],
+        admin_pool_tokens: 0,
+        pool_token_supply: pool.pool_token_supply.saturating_sub(pool_token_amount),
+        intents: vec![
+            TransferIntent::ReservesToUser {
+                token: PoolToken::A,
+                amount: a_amount,
+            },
+            TransferIntent::ReservesToUser {
+                token: PoolToken::B,
+                amount: b_amount,
+            },
+            TransferIntent::BurnPoolTokens {
+                amount: pool_token_amount,
+            },
+        ],
+    })
+}
+
+/// Leaves the admin fees of a withdrawal in the reserves and mints the admin their
+/// value in pool tokens instead, `rates` being the peg rates of token A and token B.
+/// The fees of a withdrawal of the whole supply are still transferred, no pool token
+/// would be left to value them.
+pub fn admin_fees_as_lp(
+    withdrawal: Withdrawal,
+    pool: &PoolBalances,
+    invariant: &dyn CurveCalculator,
+    [rate_a, rate_b]: [u64; 2],
+) -> Result<Withdrawal, ProgramError> {
+    if withdrawal.pool_token_supply == 0 {
+        return Ok(withdrawal);
+    }
+    let reserve_a = pool
+        .token_a
+        .checked_sub(withdrawal.amounts[0])
+        .ok_or(SwapError::CalculationFailure)?;
+    let reserve_b = pool
+        .token_b
+        .checked_sub(withdrawal.amounts[1])
+        .ok_or(SwapError::CalculationFailure)?;
+    let admin_pool_tokens = invariant
+        .admin_fee_mint_amount_with_rates(
+            withdrawal.admin_fees[0],
+            withdrawal.admin_fees[1],
+            reserve_a,
+            reserve_b,
+            withdrawal.pool_token_supply,
+            rate_a,
+            rate_b,
+        )
+        .ok_or(SwapError::CalculationFailure)?;
+    let pool_token_supply = withdrawal
+        .pool_token_supply
+        .checked_add(admin_pool_tokens)
+        .ok_or(SwapError::CalculationFailure)?;
+
+    let mut intents: Vec<TransferIntent> = withdrawal
+        .intents
+        .into_iter()
+        .filter(|intent| !matches!(intent, TransferIntent::ReservesToAdminFees { .. }))
+        .collect();
+    intents.push(TransferIntent::MintPoolTokensToAdmin {
+        amount: admin_pool_tokens,
+    });
+    Ok(Withdrawal {
+        admin_pool_tokens,
+        pool_token_supply,
+        intents,
+        ..withdrawal
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::{StableSwap, AMP_PRECISION, PEG_RATE_PRECISION, ZERO_TS},
+        fees::{NO_DYNAMIC_FEE, NO_FEE_TIERS},
+    };
+    use proptest::prelude::*;
+
+    const POOL: PoolBalances = PoolBalances {
+        pool_token_supply: 1_000_000,
+        token_a: 3_000_000,
+        token_b: 1_000_000,
+    };
+
+    fn withdraw_fees() -> Fees {
+        Fees {
+            admin_trade_fee_numerator: 0,
+            admin_trade_fee_denominator: 1,
+            admin_withdraw_fee_numerator: 1,
+            admin_withdraw_fee_denominator: 2,
+            trade_fee_numerator: 0,
+            trade_fee_denominator: 1,
+            withdraw_fee_numerator: 1,
+            withdraw_fee_denominator: 100,
+            trade_fee_tiers: NO_FEE_TIERS,
+            dynamic_fee: NO_DYNAMIC_FEE,
+            rebalancing_rebate_bps: 0,
+            host_fee_bps: 0,
+        }
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let withdrawal = withdraw(&POOL, &withdraw_fees(), 100_000, 0, 0).unwrap();
+        assert_eq!(withdrawal.amounts, [297_000, 99_000]);
+        assert_eq!(withdrawal.fees, [3_000, 1_000]);
+        assert_eq!(withdrawal.admin_fees, [1_500, 500]);
+        assert_eq!(withdrawal.pool_token_supply, 900_000);
+        assert_eq!(
+            withdrawal.intents,
+            vec![
+                TransferIntent::ReservesToUser {
+                    token: PoolToken::A,
+                    amount: 297_000
+                },
+                TransferIntent::ReservesToAdminFees {
+                    token: PoolToken::A,
+                    amount: 1_500
+                },
+                TransferIntent::ReservesToUser {
+                    token: PoolToken::B,
+                    amount: 99_000
+                },
+                TransferIntent::ReservesToAdminFees {
+                    token: PoolToken::B,
+                    amount: 500
+                },
+                TransferIntent::BurnPoolTokens { amount: 100_000 },
+            ]
+        );
+
+        assert_eq!(
+            withdraw(&POOL, &withdraw_fees(), 100_000, 297_001, 0),
+            Err(SwapError::ExceededSlippage.into())
+        );
+        assert_eq!(
+            withdraw(&POOL, &withdraw_fees(), 100_000, 0, 99_001),
+            Err(SwapError::ExceededSlippage.into())
+        );
+        let empty = PoolBalances {
+            pool_token_supply: 0,
+            ..POOL
+        };
+        assert_eq!(
+            withdraw(&empty, &withdraw_fees(), 1, 0, 0),
+            Err(SwapError::EmptyPool.into())
+        );
+    }
+
+    #[test]
+    fn test_admin_fees_as_lp() {
+        let invariant = StableSwap::new(
+            100 * AMP_PRECISION,
+            100 * AMP_PRECISION,
+            ZERO_TS,
+            ZERO_TS,
+            ZERO_TS,
+        );
+        let pool = PoolBalances {
+            pool_token_supply: 2_000_000,
+            token_a: 1_000_000,
+            token_b: 1_000_000,
+        };
+        let rates = [PEG_RATE_PRECISION, PEG_RATE_PRECISION];
+        let withdrawal = withdraw(&pool, &withdraw_fees(), 1_000_000, 0, 0).unwrap();
+        assert_eq!(withdrawal.admin_fees, [2_500, 2_500]);
+        let withdrawal = admin_fees_as_lp(withdrawal, &pool, &invariant, rates).unwrap();
+        assert_eq!(withdrawal.amounts, [495_000, 495_000]);
+        // the 5_000 tokens of fees kept in the 1_010_000 tokens left
+        assert_eq!(withdrawal.admin_pool_tokens, 4_975);
+        assert_eq!(withdrawal.pool_token_supply, 1_004_975);
+        assert_eq!(
+            withdrawal.intents,
+            vec![
+                TransferIntent::ReservesToUser {
+                    token: PoolToken::A,
+                    amount: 495_000
+                },
+                TransferIntent::ReservesToUser {
+                    token: PoolToken::B,
+                    amount: 495_000
+                },
+                TransferIntent::BurnPoolTokens { amount: 1_000_000 },
+                TransferIntent::MintPoolTokensToAdmin { amount: 4_975 },
+            ]
+        );
+
+        // nobody left to share the fees with
+        let withdrawal = withdraw(&pool, &withdraw_fees(), 2_000_000, 0, 0).unwrap();
+        assert_eq!(
+            admin_fees_as_lp(withdrawal.clone(), &pool, &invariant, rates).unwrap(),
+            withdrawal
+        );
+    }
+
+    #[test]
+    fn test_emergency_withdraw() {
+        let withdrawal = emergency_withdraw(&POOL, 333, 0, 0).unwrap();
+        assert_eq!(withdrawal.amounts, [999, 333]);
+        assert_eq!(withdrawal.fees, [0, 0]);
+        assert_eq!(withdrawal.pool_token_supply, 999_667);
+        assert_eq!(
+            withdrawal.intents,
+            vec![
+                TransferIntent::ReservesToUser {
+                    token: PoolToken::A,
+                    amount: 999
+                },
+                TransferIntent::ReservesToUser {
+                    token: PoolToken::B,
+                    amount: 333
+                },
+                TransferIntent::BurnPoolTokens { amount: 333 },
+            ]
+        );
+        assert_eq!(
+            emergency_withdraw(&POOL, 333, 1_000, 0),
+            Err(SwapError::ExceededSlippage.into())
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn withdrawals_never_pay_out_more_than_their_share(
+            pool_token_supply in 1..u64::MAX,
+            token_a in 0..u64::MAX,
+            token_b in 0..u64::MAX,
+            pool_token_amount in 0..u64::MAX,
+        ) {
+            let pool = PoolBalances { pool_token_supply, token_a, token_b };
+            let pool_token_amount = pool_token_amount % pool_token_supply + 1;
+            for withdrawal in [
+                withdraw(&pool, &withdraw_fees(), pool_token_amount, 0, 0),
+                emergency_withdraw(&pool, pool_token_amount, 0, 0),
+            ] {
+                let withdrawal = withdrawal.unwrap();
+                let paid_a = u128::from(withdrawal.amounts[0] + withdrawal.admin_fees[0]);
+                let paid_b = u128::from(withdrawal.amounts[1] + withdrawal.admin_fees[1]);
+                // paid / reserves <= burnt / supply
+                prop_assert!(paid_a * u128::from(pool_token_supply) <= u128::from(pool_token_amount) * u128::from(token_a));
+                prop_assert!(paid_b * u128::from(pool_token_supply) <= u128::from(pool_token_amount) * u128::from(token_b));
+                prop_assert_eq!(withdrawal.pool_token_supply, pool_token_supply - pool_token_amount);
+            }
+        }
+    }
+}
@@ -1,13 +1,21 @@
 //! Utility methods
 
 use crate::error::SwapError;
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
 use solana_program::program_pack::Pack;
 use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction;
+use solana_program::sysvar::{self, rent::Rent, Sysvar};
 use spl_token::state::{Account, Mint};
 
-/// Calculates the authority id by generating a program address.
-pub fn authority_id(program_id: &Pubkey, my_info: &Pubkey, nonce: u8) -> Result<Pubkey, SwapError> {
-    Pubkey::create_program_address(&[&my_info.to_bytes()[..32], &[nonce]], program_id)
+/// Calculates the authority id by generating a program address from its seed, see
+/// [SwapInfo::authority_seed](../../state/struct.SwapInfo.html#method.authority_seed).
+pub fn authority_id(program_id: &Pubkey, seed: &[u8], nonce: u8) -> Result<Pubkey, SwapError> {
+    Pubkey::create_program_address(&[seed, &[nonce]], program_id)
         .or(Err(SwapError::InvalidProgramAddress))
 }
 
@@ -20,3 +28,89 @@ pub fn unpack_token_account(data: &[u8]) -> Result<Account, SwapError> {
 pub fn unpack_mint(data: &[u8]) -> Result<Mint, SwapError> {
     Mint::unpack(data).map_err(|_| SwapError::ExpectedMint)
 }
+
+/// Returns the clock, consuming the next account if it is the clock sysvar.
+/// Instructions may omit the clock sysvar, in which case it is read with `Clock::get`,
+/// while the legacy layouts passing it keep working.
+pub fn next_clock<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    account_info_iter: &mut I,
+) -> Result<Clock, ProgramError> {
+    match account_info_iter.clone().next() {
+        Some(clock_sysvar_info) if sysvar::clock::check_id(clock_sysvar_info.key) => {
+            account_info_iter.next();
+            Clock::from_account_info(clock_sysvar_info)
+        }
+        _ => Clock::get(),
+    }
+}
+
+/// Creates a program owned account at a program address, unless it already exists.
+/// Anyone can send lamports to the address beforehand, which `CreateAccount` rejects,
+/// so a funded address is topped up to the rent exempt balance, then allocated and
+/// assigned instead.
+pub fn create_program_address_account<'a>(
+    program_id: &Pubkey,
+    signer_seeds: &[&[u8]],
+    len: usize,
+    account_info: &AccountInfo<'a>,
+    payer_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent_sysvar_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    if account_info.owner == program_id {
+        return Ok(());
+    }
+    let rent = Rent::from_account_info(rent_sysvar_info)?;
+    let required_lamports = rent.minimum_balance(len);
+    if account_info.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                account_info.key,
+                required_lamports,
+                len as u64,
+                program_id,
+            ),
+            &[
+                payer_info.clone(),
+                account_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[signer_seeds],
+        );
+    }
+
+    let top_up_lamports = required_lamports.saturating_sub(account_info.lamports());
+    if top_up_lamports > 0 {
+        invoke_signed(
+            &system_instruction::transfer(payer_info.key, account_info.key, top_up_lamports),
+            &[
+                payer_info.clone(),
+                account_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[signer_seeds],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account_info.key, len as u64),
+        &[account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account_info.key, program_id),
+        &[account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Closes a program owned account, paying its lamports out to the destination and
+/// clearing its data
+pub fn close_program_account(account_info: &AccountInfo, destination_info: &AccountInfo) {
+    let destination_lamports = destination_info
+        .lamports()
+        .saturating_add(account_info.lamports());
+    **account_info.lamports.borrow_mut() = 0;
+    **destination_info.lamports.borrow_mut() = destination_lamports;
+    account_info.data.borrow_mut().fill(0);
+}
@@ -0,0 +1,240 @@
+//! Integer port of the StableSwap math of Curve's Vyper contracts (`StableSwap3Pool.vy`),
+//! restricted to two coins of equal precision. Used to fuzz [curve](../curve/index.html)
+//! against the reference implementation, so rounding regressions are caught on the host.
+//!
+//! Vyper reverts on overflow and division by zero, which maps to `None` here.
+
+use crate::bn::U256;
+
+/// Number of coins
+const N_COINS: u64 = 2;
+/// Max number of Newton iterations, as in Vyper's `for _i in range(255)`
+const MAX_ITERATIONS: usize = 255;
+
+/// Result of `exchange` in the reference implementation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReferenceExchange {
+    /// Amount of destination token sent to the user
+    pub dy: u64,
+    /// Fee for the swap
+    pub dy_fee: u64,
+    /// Admin share of the fee
+    pub dy_admin_fee: u64,
+}
+
+/// `get_D(xp, amp)`
+pub fn get_d(xp: [u64; 2], amp: u64) -> Option<U256> {
+    let s = U256::from(xp[0]).checked_add(xp[1].into())?;
+    if s.is_zero() {
+        return Some(U256::zero());
+    }
+
+    let ann = U256::from(amp).checked_mul(N_COINS.into())?;
+    let mut d = s;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = d;
+        for x in xp.iter() {
+            // If division by 0, this will be borked: only withdrawal will work. And that is good
+            d_p = d_p
+                .checked_mul(d)?
+                .checked_div(U256::from(*x).checked_mul(N_COINS.into())?)?;
+        }
+        let d_prev = d;
+        d = ann
+            .checked_mul(s)?
+            .checked_add(d_p.checked_mul(N_COINS.into())?)?
+            .checked_mul(d)?
+            .checked_div(
+                ann.checked_sub(1.into())?
+                    .checked_mul(d)?
+                    .checked_add(U256::from(N_COINS + 1).checked_mul(d_p)?)?,
+            )?;
+        // Equality with the precision of 1
+        if d > d_prev {
+            if d - d_prev <= 1.into() {
+                break;
+            }
+        } else if d_prev - d <= 1.into() {
+            break;
+        }
+    }
+    Some(d)
+}
+
+/// `get_y(i, j, x, xp)`: balance of coin `j` once the balance of coin `i` is `x`
+pub fn get_y(i: usize, j: usize, x: u64, xp: [u64; 2], amp: u64) -> Option<U256> {
+    if i == j || i >= xp.len() || j >= xp.len() {
+        return None;
+    }
+
+    let d = get_d(xp, amp)?;
+    let ann = U256::from(amp).checked_mul(N_COINS.into())?;
+    let mut c = d;
+    let mut s = U256::zero();
+    for (index, balance) in xp.iter().enumerate() {
+        let x_index = if index == i {
+            x
+        } else if index != j {
+            *balance
+        } else {
+            continue;
+        };
+        s = s.checked_add(x_index.into())?;
+        c = c
+            .checked_mul(d)?
+            .checked_div(U256::from(x_index).checked_mul(N_COINS.into())?)?;
+    }
+    c = c
+        .checked_mul(d)?
+        .checked_div(ann.checked_mul(N_COINS.into())?)?;
+    let b = s.checked_add(d.checked_div(ann)?)?; // - D
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        y = y
+            .checked_mul(y)?
+            .checked_add(c)?
+            .checked_div(y.checked_mul(2.into())?.checked_add(b)?.checked_sub(d)?)?;
+        // Equality with the precision of 1
+        if y > y_prev {
+            if y - y_prev <= 1.into() {
+                break;
+            }
+        } else if y_prev - y <= 1.into() {
+            break;
+        }
+    }
+    Some(y)
+}
+
+/// `exchange(i, j, dx, min_dy)` with a flat fee of `fee_numerator / fee_denominator`
+/// and an admin fee of `admin_fee_numerator / admin_fee_denominator` of the fee.
+pub fn exchange(
+    i: usize,
+    j: usize,
+    dx: u64,
+    xp: [u64; 2],
+    amp: u64,
+    (fee_numerator, fee_denominator): (u64, u64),
+    (admin_fee_numerator, admin_fee_denominator): (u64, u64),
+) -> Option<ReferenceExchange> {
+    let x = xp.get(i)?.checked_add(dx)?;
+    let y = get_y(i, j, x, xp, amp)?;
+    // -1 just in case there were some rounding errors
+    let dy = U256::from(*xp.get(j)?)
+        .checked_sub(y)?
+        .checked_sub(1.into())?;
+    let dy_fee = dy
+        .checked_mul(fee_numerator.into())?
+        .checked_div(fee_denominator.into())?;
+    let dy_admin_fee = dy_fee
+        .checked_mul(admin_fee_numerator.into())?
+        .checked_div(admin_fee_denominator.into())?;
+    Some(ReferenceExchange {
+        dy: dy.checked_sub(dy_fee)?.to_u64()?,
+        dy_fee: dy_fee.to_u64()?,
+        dy_admin_fee: dy_admin_fee.to_u64()?,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::curve::{StableSwap, AMP_PRECISION, MAX_AMP, MAX_TOKENS_IN, MIN_AMP, ZERO_TS};
+    use crate::fees::{Fees, NO_DYNAMIC_FEE, NO_FEE_TIERS};
+    use proptest::prelude::*;
+
+    const FEES: Fees = Fees {
+        admin_trade_fee_numerator: 1,
+        admin_trade_fee_denominator: 2,
+        admin_withdraw_fee_numerator: 0,
+        admin_withdraw_fee_denominator: 1,
+        trade_fee_numerator: 4,
+        trade_fee_denominator: 10_000,
+        withdraw_fee_numerator: 0,
+        withdraw_fee_denominator: 1,
+        trade_fee_tiers: NO_FEE_TIERS,
+        dynamic_fee: NO_DYNAMIC_FEE,
+        rebalancing_rebate_bps: 0,
+        host_fee_bps: 0,
+    };
+
+    fn assert_within_one(actual: u64, expected: u64) {
+        assert!(
+            actual.max(expected) - actual.min(expected) <= 1,
+            "actual: {}, reference: {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_balanced_pool() {
+        let xp = [1_000_000, 1_000_000];
+        assert_eq!(get_d(xp, 100), Some(2_000_000.into()));
+        assert_eq!(get_y(0, 1, 1_000_000, xp, 100), Some(1_000_000.into()));
+        assert_eq!(get_d([0, 0], 100), Some(U256::zero()));
+        assert_eq!(get_y(0, 0, 1_000_000, xp, 100), None);
+
+        let result = exchange(0, 1, 1_000, xp, 100, (4, 10_000), (1, 2)).unwrap();
+        assert_eq!(result.dy + result.dy_fee, 999);
+        assert_eq!(result.dy_admin_fee, result.dy_fee / 2);
+    }
+
+    proptest! {
+        #[test]
+        fn test_compute_d_matches_reference(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in 1..MAX_TOKENS_IN,
+            amount_b in 1..MAX_TOKENS_IN,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let d = invariant.compute_d(amount_a, amount_b).unwrap();
+            let expected = get_d([amount_a, amount_b], amp_factor).unwrap();
+            assert_within_one(d.to_u64().unwrap(), expected.to_u64().unwrap());
+        }
+
+        #[test]
+        fn test_compute_y_matches_reference(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in 1..MAX_TOKENS_IN,
+            amount_b in 1..MAX_TOKENS_IN,
+            amount_in in 0..MAX_TOKENS_IN,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let d = invariant.compute_d(amount_a, amount_b).unwrap();
+            let x = amount_a + amount_in;
+            if let Some(expected) = get_y(0, 1, x, [amount_a, amount_b], amp_factor) {
+                let y = invariant.compute_y(x, d).unwrap();
+                assert_within_one(y, expected.to_u64().unwrap());
+            }
+        }
+
+        #[test]
+        fn test_swap_to_matches_reference(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in 1..MAX_TOKENS_IN,
+            amount_b in 1..MAX_TOKENS_IN,
+            amount_in in 0..MAX_TOKENS_IN,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let expected = exchange(
+                0,
+                1,
+                amount_in,
+                [amount_a, amount_b],
+                amp_factor,
+                (FEES.trade_fee_numerator, FEES.trade_fee_denominator),
+                (FEES.admin_trade_fee_numerator, FEES.admin_trade_fee_denominator),
+            );
+            // The reference reverts when the output rounds below its 1 unit margin
+            if let Some(expected) = expected {
+                let result = invariant.swap_to(amount_in, amount_a, amount_b, &FEES).unwrap();
+                assert_within_one(result.amount_swapped, expected.dy);
+                assert_within_one(result.fee, expected.dy_fee);
+                assert_within_one(result.admin_fee, expected.dy_admin_fee);
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Results written to the transaction return data, so that CPI callers and
+//! simulations can read them without diffing token balances.
+//!
+//! Each struct is encoded as its `u64` fields in order, little endian, which
+//! is also its borsh encoding.
+
+use arrayref::{array_ref, array_refs};
+use solana_program::{program::set_return_data, program_error::ProgramError};
+
+/// Result of a [Swap](../instruction/enum.SwapInstruction.html), a
+/// [SwapRoute](../instruction/enum.SwapInstruction.html) or a
+/// [WithdrawOne](../instruction/enum.SwapInstruction.html).
+/// A [SwapBatch](../instruction/enum.SwapInstruction.html) returns the totals of its fills.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwapReturnData {
+    /// Amount of destination token received by the user
+    pub amount_out: u64,
+    /// Fee paid, in destination token
+    pub fee: u64,
+    /// Part of the fee sent to the admin fee account
+    pub admin_fee: u64,
+    /// Price impact in basis points, see
+    /// [SwapResult::price_impact_bps](../curve/struct.SwapResult.html#method.price_impact_bps).
+    /// Zero for a WithdrawOne.
+    pub price_impact_bps: u64,
+}
+
+impl SwapReturnData {
+    /// Length of the encoded data
+    pub const LEN: usize = 32;
+
+    /// Decodes the return data of a swap
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, SwapReturnData::LEN];
+        let (amount_out, fee, admin_fee, price_impact_bps) = array_refs![input, 8, 8, 8, 8];
+        Ok(Self {
+            amount_out: u64::from_le_bytes(*amount_out),
+            fee: u64::from_le_bytes(*fee),
+            admin_fee: u64::from_le_bytes(*admin_fee),
+            price_impact_bps: u64::from_le_bytes(*price_impact_bps),
+        })
+    }
+
+    /// Encodes the return data of a swap
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(&self.amount_out.to_le_bytes());
+        buf.extend_from_slice(&self.fee.to_le_bytes());
+        buf.extend_from_slice(&self.admin_fee.to_le_bytes());
+        buf.extend_from_slice(&self.price_impact_bps.to_le_bytes());
+        buf
+    }
+
+    /// Sets the return data of the transaction
+    pub fn set(&self) {
+        set_return_data(&self.pack());
+    }
+}
+
+/// Result of a [SwapAllowPartial](../instruction/enum.SwapInstruction.html), whose
+/// fill may be less than the amount in of the instruction
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PartialSwapReturnData {
+    /// Amount of source token swapped, the rest stays with the user
+    pub amount_in: u64,
+    /// Amount of destination token received by the user
+    pub amount_out: u64,
+    /// Fee paid, in destination token
+    pub fee: u64,
+    /// Part of the fee sent to the admin fee account
+    pub admin_fee: u64,
+    /// Price impact in basis points, see [SwapReturnData::price_impact_bps]
+    pub price_impact_bps: u64,
+}
+
+impl PartialSwapReturnData {
+    /// Length of the encoded data
+    pub const LEN: usize = 40;
+
+    /// Decodes the return data of a partial swap
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, PartialSwapReturnData::LEN];
+        let (amount_in, amount_out, fee, admin_fee, price_impact_bps) =
+            array_refs![input, 8, 8, 8, 8, 8];
+        Ok(Self {
+            amount_in: u64::from_le_bytes(*amount_in),
+            amount_out: u64::from_le_bytes(*amount_out),
+            fee: u64::from_le_bytes(*fee),
+            admin_fee: u64::from_le_bytes(*admin_fee),
+            price_impact_bps: u64::from_le_bytes(*price_impact_bps),
+        })
+    }
+
+    /// Encodes the return data of a partial swap
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(&self.amount_in.to_le_bytes());
+        buf.extend_from_slice(&self.amount_out.to_le_bytes());
+        buf.extend_from_slice(&self.fee.to_le_bytes());
+        buf.extend_from_slice(&self.admin_fee.to_le_bytes());
+        buf.extend_from_slice(&self.price_impact_bps.to_le_bytes());
+        buf
+    }
+
+    /// Sets the return data of the transaction
+    pub fn set(&self) {
+        set_return_data(&self.pack());
+    }
+}
+
+/// Result of a [Deposit](../instruction/enum.SwapInstruction.html)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepositReturnData {
+    /// Amount of token A deposited
+    pub token_a_amount: u64,
+    /// Amount of token B deposited
+    pub token_b_amount: u64,
+    /// Amount of pool tokens minted
+    pub pool_token_amount: u64,
+}
+
+impl DepositReturnData {
+    /// Length of the encoded data
+    pub const LEN: usize = 24;
+
+    /// Decodes the return data of a deposit
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, DepositReturnData::LEN];
+        let (token_a_amount, token_b_amount, pool_token_amount) = array_refs![input, 8, 8, 8];
+        Ok(Self {
+            token_a_amount: u64::from_le_bytes(*token_a_amount),
+            token_b_amount: u64::from_le_bytes(*token_b_amount),
+            pool_token_amount: u64::from_le_bytes(*pool_token_amount),
+        })
+    }
+
+    /// Encodes the return data of a deposit
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(&self.token_a_amount.to_le_bytes());
+        buf.extend_from_slice(&self.token_b_amount.to_le_bytes());
+        buf.extend_from_slice(&self.pool_token_amount.to_le_bytes());
+        buf
+    }
+
+    /// Sets the return data of the transaction
+    pub fn set(&self) {
+        set_return_data(&self.pack());
+    }
+}
+
+/// Result of a [Withdraw](../instruction/enum.SwapInstruction.html)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WithdrawReturnData {
+    /// Amount of token A received by the user
+    pub token_a_amount: u64,
+    /// Amount of token B received by the user
+    pub token_b_amount: u64,
+    /// Withdraw fee paid in token A
+    pub token_a_fee: u64,
+    /// Withdraw fee paid in token B
+    pub token_b_fee: u64,
+}
+
+impl WithdrawReturnData {
+    /// Length of the encoded data
+    pub const LEN: usize = 32;
+
+    /// Decodes the return data of a withdrawal
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, WithdrawReturnData::LEN];
+        let (token_a_amount, token_b_amount, token_a_fee, token_b_fee) =
+            array_refs![input, 8, 8, 8, 8];
+        Ok(Self {
+            token_a_amount: u64::from_le_bytes(*token_a_amount),
+            token_b_amount: u64::from_le_bytes(*token_b_amount),
+            token_a_fee: u64::from_le_bytes(*token_a_fee),
+            token_b_fee: u64::from_le_bytes(*token_b_fee),
+        })
+    }
+
+    /// Encodes the return data of a withdrawal
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(&self.token_a_amount.to_le_bytes());
+        buf.extend_from_slice(&self.token_b_amount.to_le_bytes());
+        buf.extend_from_slice(&self.token_a_fee.to_le_bytes());
+        buf.extend_from_slice(&self.token_b_fee.to_le_bytes());
+        buf
+    }
+
+    /// Sets the return data of the transaction
+    pub fn set(&self) {
+        set_return_data(&self.pack());
+    }
+}
+
+/// Result of a [GetAmpFactor](../instruction/enum.SwapInstruction.html), in
+/// [AMP_PRECISION](../curve/constant.AMP_PRECISION.html) units
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AmpFactorReturnData {
+    /// Amplification coefficient at the time of the instruction
+    pub amp_factor: u64,
+    /// Amplification coefficient at the end of the ramp, if any
+    pub target_amp_factor: u64,
+}
+
+impl AmpFactorReturnData {
+    /// Length of the encoded data
+    pub const LEN: usize = 16;
+
+    /// Decodes the return data of an amp factor query
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, AmpFactorReturnData::LEN];
+        let (amp_factor, target_amp_factor) = array_refs![input, 8, 8];
+        Ok(Self {
+            amp_factor: u64::from_le_bytes(*amp_factor),
+            target_amp_factor: u64::from_le_bytes(*target_amp_factor),
+        })
+    }
+
+    /// Encodes the return data of an amp factor query
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(&self.amp_factor.to_le_bytes());
+        buf.extend_from_slice(&self.target_amp_factor.to_le_bytes());
+        buf
+    }
+
+    /// Sets the return data of the transaction
+    pub fn set(&self) {
+        set_return_data(&self.pack());
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_return_data_packing() {
+        let swap = SwapReturnData {
+            amount_out: 1,
+            fee: 2,
+            admin_fee: 3,
+            price_impact_bps: 11,
+        };
+        let packed = swap.pack();
+        let mut expect = vec![];
+        expect.extend_from_slice(&1u64.to_le_bytes());
+        expect.extend_from_slice(&2u64.to_le_bytes());
+        expect.extend_from_slice(&3u64.to_le_bytes());
+        expect.extend_from_slice(&11u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapReturnData::unpack(&packed).unwrap(), swap);
+
+        let partial_swap = PartialSwapReturnData {
+            amount_in: 12,
+            amount_out: 1,
+            fee: 2,
+            admin_fee: 3,
+            price_impact_bps: 11,
+        };
+        let packed = partial_swap.pack();
+        assert_eq!(packed.len(), PartialSwapReturnData::LEN);
+        assert_eq!(&packed[..8], &12u64.to_le_bytes());
+        assert_eq!(
+            PartialSwapReturnData::unpack(&packed).unwrap(),
+            partial_swap
+        );
+
+        let deposit = DepositReturnData {
+            token_a_amount: 4,
+            token_b_amount: 5,
+            pool_token_amount: 6,
+        };
+        assert_eq!(DepositReturnData::unpack(&deposit.pack()).unwrap(), deposit);
+
+        let withdraw = WithdrawReturnData {
+            token_a_amount: 7,
+            token_b_amount: 8,
+            token_a_fee: 9,
+            token_b_fee: 10,
+        };
+        let packed = withdraw.pack();
+        assert_eq!(packed.len(), WithdrawReturnData::LEN);
+        assert_eq!(WithdrawReturnData::unpack(&packed).unwrap(), withdraw);
+
+        assert_eq!(
+            WithdrawReturnData::unpack(&packed[..WithdrawReturnData::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let amp_factor = AmpFactorReturnData {
+            amp_factor: 15_000,
+            target_amp_factor: 20_000,
+        };
+        let packed = amp_factor.pack();
+        assert_eq!(packed.len(), AmpFactorReturnData::LEN);
+        assert_eq!(AmpFactorReturnData::unpack(&packed).unwrap(), amp_factor);
+    }
+}
@@ -0,0 +1,313 @@
+//! Floating point reference model of the StableSwap curve, mirroring Curve's
+//! `simulation.py`. Only meant to run on the host, to quantify the rounding of
+//! the integer math in [curve](../curve/index.html).
+
+use crate::fees::Fees;
+
+/// Number of coins
+const N_COINS: f64 = 2.0;
+/// Max number of Newton iterations
+const MAX_ITERATIONS: usize = 256;
+/// Relative precision at which Newton's method stops
+const PRECISION: f64 = 1e-15;
+
+/// Result of a swap in the reference model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReferenceSwapResult {
+    /// Amount of destination token swapped
+    pub amount_swapped: f64,
+    /// Admin fee for the swap
+    pub admin_fee: f64,
+    /// Fee for the swap
+    pub fee: f64,
+}
+
+/// Floating point StableSwap pool with flat fees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReferenceModel {
+    /// Amplification coefficient (A)
+    pub amp_factor: f64,
+    /// Reserves of token A and token B
+    pub balances: [f64; 2],
+    /// Trade fee as a fraction of the trade
+    pub trade_fee: f64,
+    /// Admin share of the trade fee
+    pub admin_trade_fee: f64,
+}
+
+impl ReferenceModel {
+    /// New reference model. Fee tiers and the dynamic fee are not modeled.
+    pub fn new(amp_factor: u64, amount_a: u64, amount_b: u64, fees: &Fees) -> Self {
+        Self {
+            amp_factor: amp_factor as f64,
+            balances: [amount_a as f64, amount_b as f64],
+            trade_fee: fraction(fees.trade_fee_numerator, fees.trade_fee_denominator),
+            admin_trade_fee: fraction(
+                fees.admin_trade_fee_numerator,
+                fees.admin_trade_fee_denominator,
+            ),
+        }
+    }
+
+    /// Compute stable swap invariant (D) of the current balances
+    pub fn d(&self) -> f64 {
+        self.compute_d(self.balances[0], self.balances[1])
+    }
+
+    /// Compute stable swap invariant (D) of the given balances
+    pub fn compute_d(&self, amount_a: f64, amount_b: f64) -> f64 {
+        let sum_x = amount_a + amount_b;
+        if sum_x == 0.0 {
+            return 0.0;
+        }
+        let ann = self.amp_factor * N_COINS;
+        let mut d = sum_x;
+        for _ in 0..MAX_ITERATIONS {
+            let d_prod = d * d / (amount_a * N_COINS) * d / (amount_b * N_COINS);
+            let d_prev = d;
+            d = (ann * sum_x + d_prod * N_COINS) * d / ((ann - 1.0) * d + (N_COINS + 1.0) * d_prod);
+            if converged(d, d_prev) {
+                break;
+            }
+        }
+        d
+    }
+
+    /// Compute the balance `y` of one token given the balance `x` of the other
+    pub fn compute_y(&self, x: f64, d: f64) -> f64 {
+        let ann = self.amp_factor * N_COINS;
+        let c = d * d / (x * N_COINS) * d / (ann * N_COINS);
+        let b = x + d / ann;
+        let mut y = d;
+        for _ in 0..MAX_ITERATIONS {
+            let y_prev = y;
+            y = (y * y + c) / (2.0 * y + b - d);
+            if converged(y, y_prev) {
+                break;
+            }
+        }
+        y
+    }
+
+    /// Swap `amount_in` of the token at `source_index` for the other token
+    pub fn swap_to(&self, source_index: usize, amount_in: f64) -> ReferenceSwapResult {
+        let source = self.balances[source_index];
+        let destination = self.balances[1 - source_index];
+        let y = self.compute_y(source + amount_in, self.d());
+        let dy = destination - y;
+        let fee = dy * self.trade_fee;
+        ReferenceSwapResult {
+            amount_swapped: dy - fee,
+            admin_fee: fee * self.admin_trade_fee,
+            fee,
+        }
+    }
+
+    /// Amount of the token at `base_index` received, and the fee paid, when
+    /// withdrawing `pool_token_amount` out of `pool_token_supply` as that token only
+    pub fn compute_withdraw_one(
+        &self,
+        pool_token_amount: f64,
+        pool_token_supply: f64,
+        base_index: usize,
+    ) -> (f64, f64) {
+        let base = self.balances[base_index];
+        let quote = self.balances[1 - base_index];
+        let fee = self.normalized_trade_fee();
+
+        let d_0 = self.d();
+        let d_1 = d_0 - pool_token_amount * d_0 / pool_token_supply;
+        let new_y = self.compute_y(quote, d_1);
+        let expected_base = base * d_1 / d_0 - new_y;
+        let expected_quote = quote - quote * d_1 / d_0;
+        let new_base = base - expected_base * fee;
+        let new_quote = quote - expected_quote * fee;
+
+        let dy = new_base - self.compute_y(new_quote, d_1);
+        let dy_0 = base - new_y;
+        (dy, dy_0 - dy)
+    }
+
+    /// Amount of pool tokens minted for a deposit
+    pub fn compute_mint_amount_for_deposit(
+        &self,
+        deposit_amount_a: f64,
+        deposit_amount_b: f64,
+        pool_token_supply: f64,
+    ) -> f64 {
+        let fee = self.normalized_trade_fee();
+        let d_0 = self.d();
+        let mut new_balances = [
+            self.balances[0] + deposit_amount_a,
+            self.balances[1] + deposit_amount_b,
+        ];
+        let d_1 = self.compute_d(new_balances[0], new_balances[1]);
+        for (new_balance, old_balance) in new_balances.iter_mut().zip(self.balances.iter()) {
+            let ideal_balance = d_1 * old_balance / d_0;
+            *new_balance -= (ideal_balance - *new_balance).abs() * fee;
+        }
+        let d_2 = self.compute_d(new_balances[0], new_balances[1]);
+        pool_token_supply * (d_2 - d_0) / d_0
+    }
+
+    fn normalized_trade_fee(&self) -> f64 {
+        self.trade_fee * N_COINS / (4.0 * (N_COINS - 1.0))
+    }
+}
+
+/// Signed difference between an integer result and its reference value.
+/// Positive when the integer math pays out more than the model.
+pub fn rounding_error(actual: u64, expected: f64) -> f64 {
+    actual as f64 - expected
+}
+
+fn fraction(numerator: u64, denominator: u64) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn converged(value: f64, prev: f64) -> bool {
+    (value - prev).abs() <= value.abs() * PRECISION
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::curve::{StableSwap, AMP_PRECISION, MAX_AMP, MIN_AMP, ZERO_TS};
+    use crate::fees::{NO_DYNAMIC_FEE, NO_FEE_TIERS};
+    use proptest::prelude::*;
+
+    /// Balances are kept well below 2^53 so the model itself is exact to a fraction of a token
+    const MAX_BALANCE: u64 = 1 << 40;
+    /// Pools thinner than this are dominated by integer rounding
+    const MIN_BALANCE: u64 = 1_000_000;
+
+    const FEES: Fees = Fees {
+        admin_trade_fee_numerator: 1,
+        admin_trade_fee_denominator: 2,
+        admin_withdraw_fee_numerator: 0,
+        admin_withdraw_fee_denominator: 1,
+        trade_fee_numerator: 4,
+        trade_fee_denominator: 10_000,
+        withdraw_fee_numerator: 0,
+        withdraw_fee_denominator: 1,
+        trade_fee_tiers: NO_FEE_TIERS,
+        dynamic_fee: NO_DYNAMIC_FEE,
+        rebalancing_rebate_bps: 0,
+        host_fee_bps: 0,
+    };
+
+    fn tolerance(expected: f64) -> f64 {
+        5.0 + expected.abs() * 1e-10
+    }
+
+    #[test]
+    fn test_balanced_pool() {
+        let model = ReferenceModel::new(100, 1_000_000, 1_000_000, &FEES);
+        assert!((model.d() - 2_000_000.0).abs() < 1e-6);
+        assert!((model.compute_y(1_000_000.0, model.d()) - 1_000_000.0).abs() < 1e-6);
+    }
+
+    proptest! {
+        #[test]
+        fn test_compute_d_matches_model(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in 1..MAX_BALANCE,
+            amount_b in 1..MAX_BALANCE,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let model = ReferenceModel::new(amp_factor, amount_a, amount_b, &FEES);
+            let d = invariant.compute_d(amount_a, amount_b).unwrap().as_u128() as f64;
+            let expected = model.d();
+            prop_assert!((d - expected).abs() <= tolerance(expected), "d: {}, model: {}", d, expected);
+        }
+
+        #[test]
+        fn test_swap_to_matches_model(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in MIN_BALANCE..MAX_BALANCE,
+            amount_b in MIN_BALANCE..MAX_BALANCE,
+            amount_in in 1..MAX_BALANCE,
+        ) {
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let model = ReferenceModel::new(amp_factor, amount_a, amount_b, &FEES);
+            let result = invariant.swap_to(amount_in, amount_a, amount_b, &FEES).unwrap();
+            let expected = model.swap_to(0, amount_in as f64);
+            let error = rounding_error(result.amount_swapped, expected.amount_swapped);
+            prop_assert!(
+                error.abs() <= tolerance(expected.amount_swapped),
+                "swapped: {}, model: {}", result.amount_swapped, expected.amount_swapped
+            );
+        }
+
+        #[test]
+        fn test_withdraw_one_matches_model(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in MIN_BALANCE..MAX_BALANCE,
+            amount_b in MIN_BALANCE..MAX_BALANCE,
+            pool_token_share in 1..100u64,
+        ) {
+            // Pool token supply tracks D, as it does for a pool bootstrapped by Initialize
+            let pool_token_supply = amount_a + amount_b;
+            let pool_token_amount = pool_token_supply / 100 * pool_token_share;
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let model = ReferenceModel::new(amp_factor, amount_a, amount_b, &FEES);
+            let expected = model.compute_withdraw_one(
+                pool_token_amount as f64,
+                pool_token_supply as f64,
+                0,
+            );
+            if let Some((amount, _fee)) = invariant.compute_withdraw_one(
+                pool_token_amount,
+                pool_token_supply,
+                amount_a,
+                amount_b,
+                &FEES,
+            ) {
+                // Truncated intermediates of heavily imbalanced pools leave a larger relative error
+                let error = rounding_error(amount, expected.0);
+                prop_assert!(
+                    error.abs() <= tolerance(expected.0) + expected.0 * 1e-6,
+                    "withdrawn: {}, model: {}", amount, expected.0
+                );
+            }
+        }
+
+        #[test]
+        fn test_deposit_matches_model(
+            amp_factor in MIN_AMP..MAX_AMP,
+            amount_a in MIN_BALANCE..MAX_BALANCE,
+            amount_b in MIN_BALANCE..MAX_BALANCE,
+            deposit_amount_a in 0..MAX_BALANCE,
+            deposit_amount_b in 1..MAX_BALANCE,
+        ) {
+            let pool_token_supply = amount_a + amount_b;
+            let invariant = StableSwap::new(amp_factor * AMP_PRECISION, amp_factor * AMP_PRECISION, ZERO_TS, ZERO_TS, ZERO_TS);
+            let model = ReferenceModel::new(amp_factor, amount_a, amount_b, &FEES);
+            let mint_amount = invariant
+                .compute_mint_amount_for_deposit(
+                    deposit_amount_a,
+                    deposit_amount_b,
+                    amount_a,
+                    amount_b,
+                    pool_token_supply,
+                    &FEES,
+                )
+                .unwrap();
+            let expected = model.compute_mint_amount_for_deposit(
+                deposit_amount_a as f64,
+                deposit_amount_b as f64,
+                pool_token_supply as f64,
+            );
+            let error = rounding_error(mint_amount, expected);
+            prop_assert!(
+                error.abs() <= tolerance(expected),
+                "minted: {}, model: {}", mint_amount, expected
+            );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Snapshots of a pool and its accounts, to reproduce the state of a live pool
+//! byte for byte in local tests.
+//!
+//! Snapshots serialize with serde, with addresses base58 encoded so that they can be
+//! compared with an explorer.
+
+use crate::state::SwapInfo;
+use serde::{Deserialize, Serialize};
+use solana_program::{
+    account_info::AccountInfo, clock::Epoch, program_error::ProgramError, program_pack::Pack,
+    pubkey::Pubkey,
+};
+
+/// An account of a pool, as read from a cluster
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    /// Address of the account
+    #[serde(with = "base58")]
+    pub pubkey: Pubkey,
+    /// Program owning the account
+    #[serde(with = "base58")]
+    pub owner: Pubkey,
+    /// Lamports of the account
+    pub lamports: u64,
+    /// Data of the account
+    pub data: Vec<u8>,
+    /// Whether the account is a loaded program
+    pub executable: bool,
+    /// Next epoch the account owes rent
+    pub rent_epoch: Epoch,
+}
+
+impl AccountSnapshot {
+    /// Copies an account
+    pub fn new(account_info: &AccountInfo) -> Self {
+        Self {
+            pubkey: *account_info.key,
+            owner: *account_info.owner,
+            lamports: account_info.lamports(),
+            data: account_info.data.borrow().to_vec(),
+            executable: account_info.executable,
+            rent_epoch: account_info.rent_epoch,
+        }
+    }
+}
+
+/// A StableSwap and the accounts it was captured with, e.g. its reserves, mints
+/// and admin fee accounts
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    /// The StableSwap account
+    pub swap: AccountSnapshot,
+    /// The other accounts, in the order they were captured
+    pub accounts: Vec<AccountSnapshot>,
+}
+
+impl PoolSnapshot {
+    /// The swap state of the snapshot
+    pub fn swap_info(&self) -> Result<SwapInfo, ProgramError> {
+        SwapInfo::unpack(&self.swap.data)
+    }
+
+    /// The account of the snapshot with the given address, including the StableSwap
+    pub fn account(&self, pubkey: &Pubkey) -> Option<&AccountSnapshot> {
+        std::iter::once(&self.swap)
+            .chain(self.accounts.iter())
+            .find(|account| account.pubkey == *pubkey)
+    }
+}
+
+impl SwapInfo {
+    /// Captures a StableSwap and the given accounts. Accounts passed more than once, or
+    /// the StableSwap itself, are captured once. Fails if the StableSwap does not hold
+    /// a valid swap.
+    pub fn snapshot(
+        swap_info: &AccountInfo,
+        accounts: &[AccountInfo],
+    ) -> Result<PoolSnapshot, ProgramError> {
+        SwapInfo::unpack(&swap_info.data.borrow())?;
+        let mut snapshot = PoolSnapshot {
+            swap: AccountSnapshot::new(swap_info),
+            accounts: vec![],
+        };
+        for account_info in accounts {
+            if snapshot.account(account_info.key).is_none() {
+                snapshot.accounts.push(AccountSnapshot::new(account_info));
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Serializes addresses as base58 strings
+mod base58 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use solana_program::pubkey::Pubkey;
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        Pubkey::from_str(&encoded).map_err(D::Error::custom)
+    }
+}
@@ -1,8 +1,15 @@
 //! State transition types
 
-use crate::fees::Fees;
+use crate::{
+    bn::U192,
+    curve::{CurveCalculator, CurveType, AMP_PRECISION, INITIAL_SUPPLY_SCALE, PEG_RATE_PRECISION},
+    error::SwapError,
+    fees::{Fees, FEES_V4_LEN},
+};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use num_traits::ToPrimitive;
 use solana_program::{
+    hash::hashv,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
@@ -25,9 +32,9 @@ pub struct SwapInfo {
     /// token mint.
     pub nonce: u8,
 
-    /// Initial amplification coefficient (A)
+    /// Initial amplification coefficient (A), in [AMP_PRECISION] units
     pub initial_amp_factor: u64,
-    /// Target amplification coefficient (A)
+    /// Target amplification coefficient (A), in [AMP_PRECISION] units
     pub target_amp_factor: u64,
     /// Ramp A start timestamp
     pub start_ramp_ts: i64,
@@ -49,8 +56,271 @@ pub struct SwapInfo {
     /// Pool tokens are issued when A or B tokens are deposited.
     /// Pool tokens can be withdrawn back to the original A or B token.
     pub pool_mint: Pubkey,
-    /// Fees
+    /// Fees. Legacy accounts only store the fields of the first fee layout, of
+    /// [FEES_V1_LEN](crate::fees::FEES_V1_LEN) bytes.
     pub fees: Fees,
+
+    /// Time after which future_fees can be applied. Not stored in legacy accounts.
+    pub future_fees_deadline: i64,
+    /// Fees to be applied. Not stored in legacy accounts.
+    pub future_fees: Fees,
+
+    /// Whether only the admin can donate to the reserves. Not stored in legacy accounts.
+    pub donations_admin_only: bool,
+    /// Whether swaps can also be passed with the accounts of an spl-token-swap `Swap`.
+    /// Not stored in legacy accounts.
+    pub spl_token_swap_compat: bool,
+
+    /// LP fees of token A earned per pool token since initialization, as a Q64.64
+    /// fixed point number. Wraps around, only differences are meaningful. Not stored
+    /// in legacy accounts.
+    pub fee_growth_a: u128,
+    /// LP fees of token B earned per pool token since initialization, as a Q64.64
+    /// fixed point number. Wraps around, only differences are meaningful. Not stored
+    /// in legacy accounts.
+    pub fee_growth_b: u128,
+    /// Supply of the pool mint, as of the last instruction that minted or burned pool
+    /// tokens. Not stored in legacy accounts.
+    pub pool_token_supply: u64,
+
+    /// Pyth price account of token A. Not stored in legacy accounts.
+    pub price_oracle_a: Pubkey,
+    /// Pyth price account of token B. Not stored in legacy accounts.
+    pub price_oracle_b: Pubkey,
+    /// Max deviation of the swap price from the oracle price, in bps. 0 disables the check.
+    /// Not stored in legacy accounts.
+    pub max_price_deviation_bps: u64,
+
+    /// Trade fee numerator at the start of the fee ramp. Not stored in legacy accounts.
+    pub initial_trade_fee_numerator: u64,
+    /// Withdraw fee numerator at the start of the fee ramp. Not stored in legacy accounts.
+    pub initial_withdraw_fee_numerator: u64,
+    /// Ramp fees start timestamp. Not stored in legacy accounts.
+    pub start_fee_ramp_ts: i64,
+    /// Ramp fees stop timestamp. Not stored in legacy accounts.
+    pub stop_fee_ramp_ts: i64,
+
+    /// Whether deposits are restricted to the user authorities of the deposit allowlist.
+    /// Not stored in legacy accounts.
+    pub deposit_allowlist_enabled: bool,
+
+    /// Max amount taken out of the output reserve by a swap, in bps of that reserve.
+    /// 0 disables the check. Not stored in legacy accounts.
+    pub max_swap_bps_of_reserves: u64,
+
+    /// Whether the pool is being wound down: swaps and deposits are rejected and
+    /// withdrawals are free of withdraw fees. Not stored in legacy accounts.
+    pub is_deprecated: bool,
+
+    /// Whether the token accounts of the pool are owned by the program-wide authority
+    /// of [find_shared_authority_address] instead of one derived from the swap.
+    /// Not stored in legacy accounts.
+    pub shared_authority: bool,
+
+    /// Whether instructions moving zero tokens fail with ZeroAmount instead of
+    /// succeeding as a logged no-op. Not stored in legacy accounts.
+    pub reject_zero_amounts: bool,
+
+    /// Balance of the token A reserves as of the last instruction that moved them.
+    /// Tokens sent to the reserves directly are only counted by SyncReserves.
+    /// Not stored in legacy accounts.
+    pub reserve_amount_a: u64,
+    /// Balance of the token B reserves as of the last instruction that moved them.
+    /// Tokens sent to the reserves directly are only counted by SyncReserves.
+    /// Not stored in legacy accounts.
+    pub reserve_amount_b: u64,
+
+    /// Age of an LP position, in seconds, after which its pool tokens are withdrawn
+    /// without withdraw fees. 0 disables the waiver. Not stored in legacy accounts.
+    pub withdraw_fee_grace_period: i64,
+
+    /// Which programs may invoke swaps, deposits, single-token withdrawals and zaps
+    /// of the pool. Not stored in legacy accounts.
+    pub cpi_policy: CpiPolicy,
+
+    /// Value of token B in token A the curve is pegged to, in [PEG_RATE_PRECISION] units,
+    /// refreshed from the rate source of the [PegConfig] of the swap. 0 for pools pegged
+    /// 1:1. Not stored in legacy accounts.
+    pub peg_rate: u64,
+
+    /// Largest swap, in bps of the source reserves, paid out the conservative estimate
+    /// of [StableSwap::quick_swap_to](crate::curve::StableSwap::quick_swap_to) instead of
+    /// solving the curve. 0 disables the quick path. Not stored in legacy accounts.
+    pub quick_swap_max_bps: u16,
+
+    /// Whether a user authority is kept from swapping back, in the same slot, against
+    /// its own swaps, as sandwiches do. Swaps then record the direction of their user
+    /// authority in the [LastTrade] of the swap. Not stored in legacy accounts.
+    pub same_slot_guard: bool,
+
+    /// Whether the admin fees of swaps and withdrawals stay in the reserves, the admin
+    /// receiving their value in pool tokens minted to a pool token account of the owner
+    /// of the admin fee accounts. Not stored in legacy accounts.
+    pub admin_fees_as_lp: bool,
+
+    /// Whether WithdrawOne stays open while the swap is paused, at twice the trade and
+    /// withdraw fees, next to the proportional Withdraw which always does. Not stored
+    /// in legacy accounts.
+    pub withdraw_one_while_paused: bool,
+
+    /// Whether the instructions of the swap leave out their detailed logs, e.g. the
+    /// instruction name and the `msg!` form of events, logging each event once with
+    /// `sol_log_data` only. Saves compute units for routers at the compute limit.
+    /// Errors are logged in full either way. Not stored in legacy accounts.
+    pub quiet_logs: bool,
+
+    /// Whether deposits are held to the [DepositCaps] of the swap, and need the account
+    /// added by [with_deposit_caps](crate::instruction::with_deposit_caps). Not stored
+    /// in legacy accounts.
+    pub deposit_cap_enabled: bool,
+
+    /// Invariant the pool trades on, chosen at initialization. Legacy accounts are
+    /// [CurveType::StableSwap] pools.
+    pub curve_type: CurveType,
+
+    /// Whether the initial pool token supply was computed on reserves divided by
+    /// [INITIAL_SUPPLY_SCALE](crate::curve::INITIAL_SUPPLY_SCALE), for a pool initialized
+    /// with a reserve above [MAX_TOKENS_IN](crate::curve::MAX_TOKENS_IN). The curve of
+    /// such a pool runs on its reserves divided by the same scale, see
+    /// [SwapInfo::token_peg_rate]. Not stored in legacy accounts.
+    pub initial_supply_scaled: bool,
+}
+
+impl SwapInfo {
+    /// Seed of the swap authority, signed with the nonce: the swap key, or
+    /// [SHARED_AUTHORITY_SEED] for pools with a shared authority
+    pub fn authority_seed<'a>(&self, swap_key: &'a Pubkey) -> &'a [u8] {
+        if self.shared_authority {
+            SHARED_AUTHORITY_SEED
+        } else {
+            swap_key.as_ref()
+        }
+    }
+
+    /// Records fees that stay in the pool, i.e. trade and withdraw fees net of admin fees,
+    /// into the fee growth of the swap
+    pub fn record_lp_fees(&mut self, token_a_fee: u64, token_b_fee: u64) {
+        self.fee_growth_a =
+            fee_growth_after(self.fee_growth_a, token_a_fee, self.pool_token_supply);
+        self.fee_growth_b =
+            fee_growth_after(self.fee_growth_b, token_b_fee, self.pool_token_supply);
+    }
+
+    /// Reads [quiet_logs](#structfield.quiet_logs) from a packed account without checking
+    /// the account, e.g. to log the name of the instruction before unpacking it. Only fit
+    /// for deciding what to log. False for legacy accounts and other accounts.
+    pub fn peek_quiet_logs(data: &[u8]) -> bool {
+        data.len() == Self::LEN && data[Self::LEN - 1] & EXTRA_FLAG_QUIET_LOGS != 0
+    }
+
+    /// The token of the given index, token A being 0 and token B 1, as
+    /// [SwapTokenInfo::index] numbers them
+    pub fn token(&self, index: u8) -> Option<&SwapTokenInfo> {
+        match index {
+            0 => Some(&self.token_a),
+            1 => Some(&self.token_b),
+            _ => None,
+        }
+    }
+
+    /// The token of the given reserves, if they are reserves of the swap
+    pub fn token_of_reserves(&self, reserves: &Pubkey) -> Option<&SwapTokenInfo> {
+        [&self.token_a, &self.token_b]
+            .iter()
+            .copied()
+            .find(|token| token.reserves == *reserves)
+    }
+
+    /// Peg rate of the token of the given reserves, in [PEG_RATE_PRECISION] units:
+    /// the value of token B in token A for token B, and [PEG_RATE_PRECISION] for token A.
+    /// Both are divided by [INITIAL_SUPPLY_SCALE] for pools with a scaled initial supply,
+    /// so that the curve runs on reserves scaled like the ones the supply was minted on.
+    pub fn token_peg_rate(&self, reserves: &Pubkey) -> u64 {
+        let rate = if *reserves == self.token_b.reserves && self.peg_rate != 0 {
+            self.peg_rate
+        } else {
+            PEG_RATE_PRECISION
+        };
+        if self.initial_supply_scaled {
+            rate / INITIAL_SUPPLY_SCALE
+        } else {
+            rate
+        }
+    }
+
+    /// Calculator of the [curve_type](#structfield.curve_type) of the pool at
+    /// `current_ts`, which the swap, deposit and withdraw math goes through
+    pub fn curve_calculator(&self, current_ts: i64) -> Box<dyn CurveCalculator> {
+        self.curve_type.calculator(
+            self.initial_amp_factor,
+            self.target_amp_factor,
+            current_ts,
+            self.start_ramp_ts,
+            self.stop_ramp_ts,
+        )
+    }
+
+    /// Fees at the given time. While fees are ramping, the trade and withdraw fee
+    /// numerators are interpolated linearly towards those of `fees`, and they stay
+    /// at their initial values until a scheduled ramp starts.
+    pub fn current_fees(&self, current_ts: i64) -> Option<Fees> {
+        if current_ts >= self.stop_fee_ramp_ts {
+            // when stop_fee_ramp_ts == 0 or current_ts >= stop_fee_ramp_ts
+            return Some(self.fees);
+        }
+        let time_range = self.stop_fee_ramp_ts.checked_sub(self.start_fee_ramp_ts)?;
+        let time_delta = current_ts.checked_sub(self.start_fee_ramp_ts)?.max(0);
+        Some(Fees {
+            trade_fee_numerator: interpolate(
+                self.initial_trade_fee_numerator,
+                self.fees.trade_fee_numerator,
+                time_delta,
+                time_range,
+            )?,
+            withdraw_fee_numerator: interpolate(
+                self.initial_withdraw_fee_numerator,
+                self.fees.withdraw_fee_numerator,
+                time_delta,
+                time_range,
+            )?,
+            ..self.fees
+        })
+    }
+}
+
+/// Fee growth after `fee` is shared by `pool_token_supply` pool tokens
+fn fee_growth_after(fee_growth: u128, fee: u64, pool_token_supply: u64) -> u128 {
+    if pool_token_supply == 0 {
+        return fee_growth;
+    }
+    fee_growth.wrapping_add((u128::from(fee) << 64) / u128::from(pool_token_supply))
+}
+
+/// Linear interpolation from `initial` to `target` after `time_delta` of `time_range`
+fn interpolate(initial: u64, target: u64, time_delta: i64, time_range: i64) -> Option<u64> {
+    let time_delta = time_delta.to_u128()?;
+    let time_range = time_range.to_u128()?;
+    if target >= initial {
+        let delta = u128::from(target - initial)
+            .checked_mul(time_delta)?
+            .checked_div(time_range)?;
+        initial.checked_add(delta.to_u64()?)
+    } else {
+        let delta = u128::from(initial - target)
+            .checked_mul(time_delta)?
+            .checked_div(time_range)?;
+        initial.checked_sub(delta.to_u64()?)
+    }
+}
+
+/// Factor between the amplification coefficients of [SwapInfo] and those stored by
+/// accounts of the given layout version, see [SWAP_INFO_VERSION_WHOLE_AMP]
+fn amp_scale(version: u8) -> u64 {
+    if version < SWAP_INFO_VERSION {
+        AMP_PRECISION
+    } else {
+        1
+    }
 }
 
 /// Information about one of the tokens.
@@ -63,10 +333,177 @@ pub struct SwapTokenInfo {
     pub mint: Pubkey,
     /// Public key of the admin token account to receive trading and / or withdrawal fees for token
     pub admin_fees: Pubkey,
-    /// The index of the token. Token A = 0, Token B = 1.
+    /// The index of the token. Token A = 0, Token B = 1. It is not stored: unpacking
+    /// sets it from the position of the token, so it cannot go stale. See [SwapInfo::token].
     pub index: u8,
 }
 
+/// Layout version of [SwapInfo] accounts of [SwapInfo::LEN] bytes. It is stored in
+/// the second byte, where legacy layouts stored `is_paused`, so versions start at 2.
+pub const SWAP_INFO_VERSION: u8 = 4;
+/// Layout version of [SwapInfo] accounts written before the amplification coefficients
+/// were stored in [AMP_PRECISION] units. These accounts store whole coefficients, like
+/// the earlier versions and the legacy layout, and are still read, and written back in
+/// the current version.
+pub const SWAP_INFO_VERSION_WHOLE_AMP: u8 = 3;
+/// Layout version of [SwapInfo] accounts written before the magic bytes and the
+/// checksum. These accounts are still read, and written back in the current version.
+pub const SWAP_INFO_VERSION_WITHOUT_CHECKSUM: u8 = 2;
+/// Magic bytes of [SwapInfo] accounts since [SWAP_INFO_VERSION_WHOLE_AMP]
+pub const SWAP_INFO_MAGIC: [u8; 4] = *b"SSWP";
+/// Offset of the reserve amounts of [SwapInfo] accounts, which follow the checksum
+/// and come before the withdraw fee grace period, the CPI policy, the peg rate, the
+/// quick swap size and the extra flags
+const SWAP_INFO_RESERVE_AMOUNTS_OFFSET: usize = SwapInfo::LEN - 1 - 2 - 8 - 1 - 8 - 16;
+/// Offset of the checksum of [SwapInfo] accounts, the first 8 bytes of the sha256 of
+/// every other byte of the account
+const SWAP_INFO_CHECKSUM_OFFSET: usize = SWAP_INFO_RESERVE_AMOUNTS_OFFSET - 8;
+/// Offset of the magic bytes of [SwapInfo] accounts
+const SWAP_INFO_MAGIC_OFFSET: usize = SWAP_INFO_CHECKSUM_OFFSET - 4;
+/// Length of [SwapInfo] accounts in the legacy layout of the first deployed pools, which
+/// stored each flag in its own byte, the fees in their first layout and nothing after
+/// them. These accounts are still read and written.
+pub const SWAP_INFO_LEGACY_LEN: usize = 395;
+
+/// Flags of [SwapInfo], packed in its first byte. The other bits are reserved.
+const FLAG_INITIALIZED: u8 = 1;
+const FLAG_PAUSED: u8 = 1 << 1;
+const FLAG_DONATIONS_ADMIN_ONLY: u8 = 1 << 2;
+const FLAG_SPL_TOKEN_SWAP_COMPAT: u8 = 1 << 3;
+const FLAG_DEPOSIT_ALLOWLIST_ENABLED: u8 = 1 << 4;
+const FLAG_DEPRECATED: u8 = 1 << 5;
+const FLAG_SHARED_AUTHORITY: u8 = 1 << 6;
+/// The last free bit of the flags byte; further flags need a field of their own
+const FLAG_REJECT_ZERO_AMOUNTS: u8 = 1 << 7;
+
+/// Extra flags of [SwapInfo], packed in its last byte. The other bits are reserved.
+const EXTRA_FLAG_SAME_SLOT_GUARD: u8 = 1;
+const EXTRA_FLAG_ADMIN_FEES_AS_LP: u8 = 1 << 1;
+const EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED: u8 = 1 << 2;
+const EXTRA_FLAG_QUIET_LOGS: u8 = 1 << 3;
+const EXTRA_FLAG_DEPOSIT_CAP_ENABLED: u8 = 1 << 4;
+/// The last free bit of the extra flags
+const EXTRA_FLAG_INITIAL_SUPPLY_SCALED: u8 = 1 << 5;
+/// The [CurveType] of the pool, in the two highest bits of the extra flags
+const EXTRA_FLAGS_CURVE_TYPE_SHIFT: u8 = 6;
+
+impl SwapInfo {
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        for (is_set, flag) in [
+            (self.is_initialized, FLAG_INITIALIZED),
+            (self.is_paused, FLAG_PAUSED),
+            (self.donations_admin_only, FLAG_DONATIONS_ADMIN_ONLY),
+            (self.spl_token_swap_compat, FLAG_SPL_TOKEN_SWAP_COMPAT),
+            (
+                self.deposit_allowlist_enabled,
+                FLAG_DEPOSIT_ALLOWLIST_ENABLED,
+            ),
+            (self.is_deprecated, FLAG_DEPRECATED),
+            (self.shared_authority, FLAG_SHARED_AUTHORITY),
+            (self.reject_zero_amounts, FLAG_REJECT_ZERO_AMOUNTS),
+        ] {
+            if is_set {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+
+    /// Checks the version of a packed account in the current layout, and its
+    /// magic bytes and checksum if the version has them
+    fn check_version(input: &[u8; 829]) -> Result<(), ProgramError> {
+        let magic = array_ref![input, SWAP_INFO_MAGIC_OFFSET, 4];
+        let checksum = array_ref![input, SWAP_INFO_CHECKSUM_OFFSET, 8];
+        match input[1] {
+            SWAP_INFO_VERSION | SWAP_INFO_VERSION_WHOLE_AMP if *magic == SWAP_INFO_MAGIC => {
+                if *checksum != Self::checksum(input) {
+                    return Err(SwapError::InvalidChecksum.into());
+                }
+            }
+            SWAP_INFO_VERSION_WITHOUT_CHECKSUM if *magic == [0u8; 4] && *checksum == [0u8; 8] => {}
+            // Never packed
+            0 if input.iter().all(|byte| *byte == 0) => {}
+            _ => return Err(ProgramError::InvalidAccountData),
+        }
+        Ok(())
+    }
+
+    /// Checksum of a packed account, see [SWAP_INFO_CHECKSUM_OFFSET]
+    fn checksum(input: &[u8; 829]) -> [u8; 8] {
+        let hash = hashv(&[
+            &input[..SWAP_INFO_CHECKSUM_OFFSET],
+            &input[SWAP_INFO_CHECKSUM_OFFSET + 8..],
+        ]);
+        *array_ref![hash.as_ref(), 0, 8]
+    }
+
+    /// Converts an account in the legacy layout to the current layout. The legacy
+    /// layout stores the fields of the current one up to the fees, with the flags in
+    /// bytes of their own and the fees in their first layout.
+    fn legacy_to_current(input: &[u8; SWAP_INFO_LEGACY_LEN]) -> Result<[u8; 829], ProgramError> {
+        let (is_initialized, is_paused, state) = array_refs![input, 1, 1, 393];
+        let mut flags = 0;
+        for (byte, flag) in [(is_initialized, FLAG_INITIALIZED), (is_paused, FLAG_PAUSED)] {
+            match byte {
+                [0] => {}
+                [1] => flags |= flag,
+                _ => return Err(ProgramError::InvalidAccountData),
+            }
+        }
+
+        // Fields after the first layout of the fees are left unset, which is how
+        // unpacking reads them
+        let mut output = [0u8; 829];
+        output[0] = flags;
+        output[1] = SWAP_INFO_VERSION_WITHOUT_CHECKSUM;
+        output[2..SWAP_INFO_LEGACY_LEN].copy_from_slice(state);
+        Ok(output)
+    }
+
+    /// Packs into an account in the legacy layout, which stores whole amplification
+    /// coefficients, rounded down. The pool token supply, the fee growth, the reserve
+    /// amounts and the peg rate are only recorded by the current layout and are
+    /// dropped, as are the fee numerators the fee ramp starts from, unused without a
+    /// ramp. Other fields the legacy layout has no room for must be unset.
+    fn pack_into_legacy_slice(
+        &self,
+        output: &mut [u8; SWAP_INFO_LEGACY_LEN],
+    ) -> Result<(), ProgramError> {
+        let mut current = [0u8; 829];
+        Self {
+            initial_amp_factor: self.initial_amp_factor / AMP_PRECISION,
+            target_amp_factor: self.target_amp_factor / AMP_PRECISION,
+            ..*self
+        }
+        .pack_into_slice(&mut current);
+        let mut legacy = [0u8; SWAP_INFO_LEGACY_LEN];
+        legacy[0] = self.is_initialized as u8;
+        legacy[1] = self.is_paused as u8;
+        legacy[2..].copy_from_slice(&current[2..SWAP_INFO_LEGACY_LEN]);
+
+        let stored = Self::unpack_from_slice(&Self::legacy_to_current(&legacy)?)?;
+        let expected = Self {
+            initial_amp_factor: self.initial_amp_factor - self.initial_amp_factor % AMP_PRECISION,
+            target_amp_factor: self.target_amp_factor - self.target_amp_factor % AMP_PRECISION,
+            fee_growth_a: 0,
+            fee_growth_b: 0,
+            pool_token_supply: 0,
+            initial_trade_fee_numerator: 0,
+            initial_withdraw_fee_numerator: 0,
+            reserve_amount_a: 0,
+            reserve_amount_b: 0,
+            peg_rate: 0,
+            ..*self
+        };
+        if stored != expected {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        *output = legacy;
+        Ok(())
+    }
+}
+
 impl Sealed for SwapInfo {}
 impl IsInitialized for SwapInfo {
     fn is_initialized(&self) -> bool {
@@ -75,15 +512,40 @@ impl IsInitialized for SwapInfo {
 }
 
 impl Pack for SwapInfo {
-    const LEN: usize = 395;
+    const LEN: usize = 829;
+
+    /// Unpacks an account in the current or the legacy layout.
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() == SWAP_INFO_LEGACY_LEN {
+            let input = array_ref![input, 0, SWAP_INFO_LEGACY_LEN];
+            return Self::unpack_from_slice(&Self::legacy_to_current(input)?);
+        }
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Self::unpack_from_slice(input)
+    }
+
+    /// Packs into an account in the current or the legacy layout, see
+    /// [SWAP_INFO_LEGACY_LEN] for the fields legacy accounts have room for.
+    fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() == SWAP_INFO_LEGACY_LEN {
+            return src.pack_into_legacy_slice(array_mut_ref![dst, 0, SWAP_INFO_LEGACY_LEN]);
+        }
+        if dst.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        src.pack_into_slice(dst);
+        Ok(())
+    }
 
     /// Unpacks a byte buffer into a [SwapInfo](struct.SwapInfo.html).
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
-        let input = array_ref![input, 0, 395];
+        let input = array_ref![input, 0, 829];
         #[allow(clippy::ptr_offset_with_cast)]
         let (
-            is_initialized,
-            is_paused,
+            flags,
+            version,
             nonce,
             initial_amp_factor,
             target_amp_factor,
@@ -100,21 +562,90 @@ impl Pack for SwapInfo {
             admin_fee_key_a,
             admin_fee_key_b,
             fees,
-        ) = array_refs![input, 1, 1, 1, 8, 8, 8, 8, 8, 32, 32, 32, 32, 32, 32, 32, 32, 32, 64];
+            future_fees_deadline,
+            future_fees,
+            fee_growth_a,
+            fee_growth_b,
+            pool_token_supply,
+            price_oracle_a,
+            price_oracle_b,
+            max_price_deviation_bps,
+            initial_trade_fee_numerator,
+            initial_withdraw_fee_numerator,
+            start_fee_ramp_ts,
+            stop_fee_ramp_ts,
+            max_swap_bps_of_reserves,
+            host_fee_bps,
+            future_host_fee_bps,
+            _magic,
+            _checksum,
+            reserve_amount_a,
+            reserve_amount_b,
+            withdraw_fee_grace_period,
+            cpi_policy,
+            peg_rate,
+            quick_swap_max_bps,
+            extra_flags,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            1,
+            8,
+            8,
+            8,
+            8,
+            8,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            FEES_V4_LEN,
+            8,
+            FEES_V4_LEN,
+            16,
+            16,
+            8,
+            32,
+            32,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            4,
+            8,
+            8,
+            8,
+            8,
+            1,
+            8,
+            2,
+            1
+        ];
+        let flags = flags[0];
+        let is_initialized = flags & FLAG_INITIALIZED != 0;
+        Self::check_version(input)?;
+        let amp_scale = amp_scale(version[0]);
+        let unpack_amp_factor = |packed: &[u8; 8]| {
+            u64::from_le_bytes(*packed)
+                .checked_mul(amp_scale)
+                .ok_or(ProgramError::InvalidAccountData)
+        };
         Ok(Self {
-            is_initialized: match is_initialized {
-                [0] => false,
-                [1] => true,
-                _ => return Err(ProgramError::InvalidAccountData),
-            },
-            is_paused: match is_paused {
-                [0] => false,
-                [1] => true,
-                _ => return Err(ProgramError::InvalidAccountData),
-            },
+            is_initialized,
+            is_paused: flags & FLAG_PAUSED != 0,
             nonce: nonce[0],
-            initial_amp_factor: u64::from_le_bytes(*initial_amp_factor),
-            target_amp_factor: u64::from_le_bytes(*target_amp_factor),
+            initial_amp_factor: unpack_amp_factor(initial_amp_factor)?,
+            target_amp_factor: unpack_amp_factor(target_amp_factor)?,
             start_ramp_ts: i64::from_le_bytes(*start_ramp_ts),
             stop_ramp_ts: i64::from_le_bytes(*stop_ramp_ts),
             future_admin_deadline: i64::from_le_bytes(*future_admin_deadline),
@@ -133,15 +664,55 @@ impl Pack for SwapInfo {
                 index: 1,
             },
             pool_mint: Pubkey::new_from_array(*pool_mint),
-            fees: Fees::unpack_from_slice(fees)?,
+            fees: Fees {
+                host_fee_bps: u64::from_le_bytes(*host_fee_bps),
+                ..Fees::unpack_versioned(fees)?
+            },
+            future_fees_deadline: i64::from_le_bytes(*future_fees_deadline),
+            future_fees: Fees {
+                host_fee_bps: u64::from_le_bytes(*future_host_fee_bps),
+                ..Fees::unpack_versioned(future_fees)?
+            },
+            donations_admin_only: flags & FLAG_DONATIONS_ADMIN_ONLY != 0,
+            spl_token_swap_compat: flags & FLAG_SPL_TOKEN_SWAP_COMPAT != 0,
+            fee_growth_a: u128::from_le_bytes(*fee_growth_a),
+            fee_growth_b: u128::from_le_bytes(*fee_growth_b),
+            pool_token_supply: u64::from_le_bytes(*pool_token_supply),
+            price_oracle_a: Pubkey::new_from_array(*price_oracle_a),
+            price_oracle_b: Pubkey::new_from_array(*price_oracle_b),
+            max_price_deviation_bps: u64::from_le_bytes(*max_price_deviation_bps),
+            initial_trade_fee_numerator: u64::from_le_bytes(*initial_trade_fee_numerator),
+            initial_withdraw_fee_numerator: u64::from_le_bytes(*initial_withdraw_fee_numerator),
+            start_fee_ramp_ts: i64::from_le_bytes(*start_fee_ramp_ts),
+            stop_fee_ramp_ts: i64::from_le_bytes(*stop_fee_ramp_ts),
+            deposit_allowlist_enabled: flags & FLAG_DEPOSIT_ALLOWLIST_ENABLED != 0,
+            max_swap_bps_of_reserves: u64::from_le_bytes(*max_swap_bps_of_reserves),
+            is_deprecated: flags & FLAG_DEPRECATED != 0,
+            shared_authority: flags & FLAG_SHARED_AUTHORITY != 0,
+            reject_zero_amounts: flags & FLAG_REJECT_ZERO_AMOUNTS != 0,
+            reserve_amount_a: u64::from_le_bytes(*reserve_amount_a),
+            reserve_amount_b: u64::from_le_bytes(*reserve_amount_b),
+            withdraw_fee_grace_period: i64::from_le_bytes(*withdraw_fee_grace_period),
+            cpi_policy: CpiPolicy::from_u8(cpi_policy[0])
+                .ok_or(ProgramError::InvalidAccountData)?,
+            peg_rate: u64::from_le_bytes(*peg_rate),
+            quick_swap_max_bps: u16::from_le_bytes(*quick_swap_max_bps),
+            same_slot_guard: extra_flags[0] & EXTRA_FLAG_SAME_SLOT_GUARD != 0,
+            admin_fees_as_lp: extra_flags[0] & EXTRA_FLAG_ADMIN_FEES_AS_LP != 0,
+            withdraw_one_while_paused: extra_flags[0] & EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED != 0,
+            quiet_logs: extra_flags[0] & EXTRA_FLAG_QUIET_LOGS != 0,
+            deposit_cap_enabled: extra_flags[0] & EXTRA_FLAG_DEPOSIT_CAP_ENABLED != 0,
+            curve_type: CurveType::from_u8(extra_flags[0] >> EXTRA_FLAGS_CURVE_TYPE_SHIFT)
+                .ok_or(ProgramError::InvalidAccountData)?,
+            initial_supply_scaled: extra_flags[0] & EXTRA_FLAG_INITIAL_SUPPLY_SCALED != 0,
         })
     }
 
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, 395];
+        let output = array_mut_ref![output, 0, 829];
         let (
-            is_initialized,
-            is_paused,
+            flags,
+            version,
             nonce,
             initial_amp_factor,
             target_amp_factor,
@@ -158,9 +729,77 @@ impl Pack for SwapInfo {
             admin_fee_key_a,
             admin_fee_key_b,
             fees,
-        ) = mut_array_refs![output, 1, 1, 1, 8, 8, 8, 8, 8, 32, 32, 32, 32, 32, 32, 32, 32, 32, 64];
-        is_initialized[0] = self.is_initialized as u8;
-        is_paused[0] = self.is_paused as u8;
+            future_fees_deadline,
+            future_fees,
+            fee_growth_a,
+            fee_growth_b,
+            pool_token_supply,
+            price_oracle_a,
+            price_oracle_b,
+            max_price_deviation_bps,
+            initial_trade_fee_numerator,
+            initial_withdraw_fee_numerator,
+            start_fee_ramp_ts,
+            stop_fee_ramp_ts,
+            max_swap_bps_of_reserves,
+            host_fee_bps,
+            future_host_fee_bps,
+            magic,
+            _checksum,
+            reserve_amount_a,
+            reserve_amount_b,
+            withdraw_fee_grace_period,
+            cpi_policy,
+            peg_rate,
+            quick_swap_max_bps,
+            extra_flags,
+        ) = mut_array_refs![
+            &mut *output,
+            1,
+            1,
+            1,
+            8,
+            8,
+            8,
+            8,
+            8,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            32,
+            FEES_V4_LEN,
+            8,
+            FEES_V4_LEN,
+            16,
+            16,
+            8,
+            32,
+            32,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            4,
+            8,
+            8,
+            8,
+            8,
+            1,
+            8,
+            2,
+            1
+        ];
+        flags[0] = self.flags();
+        version[0] = SWAP_INFO_VERSION;
         nonce[0] = self.nonce;
         *initial_amp_factor = self.initial_amp_factor.to_le_bytes();
         *target_amp_factor = self.target_amp_factor.to_le_bytes();
@@ -176,121 +815,3453 @@ impl Pack for SwapInfo {
         token_b_mint.copy_from_slice(self.token_b.mint.as_ref());
         admin_fee_key_a.copy_from_slice(self.token_a.admin_fees.as_ref());
         admin_fee_key_b.copy_from_slice(self.token_b.admin_fees.as_ref());
-        self.fees.pack_into_slice(&mut fees[..]);
+        // The host fee was added after the fees, it is stored after the other fields
+        let mut packed_fees = [0u8; Fees::LEN];
+        self.fees.pack_into_slice(&mut packed_fees);
+        fees.copy_from_slice(&packed_fees[..FEES_V4_LEN]);
+        *future_fees_deadline = self.future_fees_deadline.to_le_bytes();
+        self.future_fees.pack_into_slice(&mut packed_fees);
+        future_fees.copy_from_slice(&packed_fees[..FEES_V4_LEN]);
+        *fee_growth_a = self.fee_growth_a.to_le_bytes();
+        *fee_growth_b = self.fee_growth_b.to_le_bytes();
+        *pool_token_supply = self.pool_token_supply.to_le_bytes();
+        price_oracle_a.copy_from_slice(self.price_oracle_a.as_ref());
+        price_oracle_b.copy_from_slice(self.price_oracle_b.as_ref());
+        *max_price_deviation_bps = self.max_price_deviation_bps.to_le_bytes();
+        *initial_trade_fee_numerator = self.initial_trade_fee_numerator.to_le_bytes();
+        *initial_withdraw_fee_numerator = self.initial_withdraw_fee_numerator.to_le_bytes();
+        *start_fee_ramp_ts = self.start_fee_ramp_ts.to_le_bytes();
+        *stop_fee_ramp_ts = self.stop_fee_ramp_ts.to_le_bytes();
+        *max_swap_bps_of_reserves = self.max_swap_bps_of_reserves.to_le_bytes();
+        *host_fee_bps = self.fees.host_fee_bps.to_le_bytes();
+        *future_host_fee_bps = self.future_fees.host_fee_bps.to_le_bytes();
+        *magic = SWAP_INFO_MAGIC;
+        *reserve_amount_a = self.reserve_amount_a.to_le_bytes();
+        *reserve_amount_b = self.reserve_amount_b.to_le_bytes();
+        *withdraw_fee_grace_period = self.withdraw_fee_grace_period.to_le_bytes();
+        cpi_policy[0] = self.cpi_policy as u8;
+        *peg_rate = self.peg_rate.to_le_bytes();
+        *quick_swap_max_bps = self.quick_swap_max_bps.to_le_bytes();
+        extra_flags[0] = 0;
+        if self.same_slot_guard {
+            extra_flags[0] |= EXTRA_FLAG_SAME_SLOT_GUARD;
+        }
+        if self.admin_fees_as_lp {
+            extra_flags[0] |= EXTRA_FLAG_ADMIN_FEES_AS_LP;
+        }
+        if self.withdraw_one_while_paused {
+            extra_flags[0] |= EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED;
+        }
+        if self.quiet_logs {
+            extra_flags[0] |= EXTRA_FLAG_QUIET_LOGS;
+        }
+        if self.deposit_cap_enabled {
+            extra_flags[0] |= EXTRA_FLAG_DEPOSIT_CAP_ENABLED;
+        }
+        if self.initial_supply_scaled {
+            extra_flags[0] |= EXTRA_FLAG_INITIAL_SUPPLY_SCALED;
+        }
+        extra_flags[0] |= (self.curve_type as u8) << EXTRA_FLAGS_CURVE_TYPE_SHIFT;
+        let checksum = Self::checksum(output);
+        output[SWAP_INFO_CHECKSUM_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8].copy_from_slice(&checksum);
     }
 }
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use super::*;
+/// Offsets of the fields read and written in place by [SwapInfoRef] and
+/// [SwapInfoRefMut]. Fields up to the fees are at the same offsets in the legacy
+/// layout, which has no room for the fee state.
+const NONCE_OFFSET: usize = 2;
+const INITIAL_AMP_FACTOR_OFFSET: usize = 3;
+const TARGET_AMP_FACTOR_OFFSET: usize = 11;
+const START_RAMP_TS_OFFSET: usize = 19;
+const STOP_RAMP_TS_OFFSET: usize = 27;
+const ADMIN_KEY_OFFSET: usize = 75;
+const FEE_STATE_OFFSET: usize = 613;
+const FEE_GROWTH_A_OFFSET: usize = 0;
+const FEE_GROWTH_B_OFFSET: usize = 16;
+const POOL_TOKEN_SUPPLY_OFFSET: usize = 32;
+const RESERVE_AMOUNT_A_OFFSET: usize = SWAP_INFO_RESERVE_AMOUNTS_OFFSET;
+const RESERVE_AMOUNT_B_OFFSET: usize = SWAP_INFO_RESERVE_AMOUNTS_OFFSET + 8;
+const PEG_RATE_OFFSET: usize = SWAP_INFO_RESERVE_AMOUNTS_OFFSET + 16 + 8 + 1;
+/// Offsets of the flag bytes of the legacy layout. Flags added since are not stored there.
+const LEGACY_FLAG_OFFSETS: [(u8, usize); 2] = [(FLAG_INITIALIZED, 0), (FLAG_PAUSED, 1)];
 
-    #[test]
-    fn test_swap_info_packing() {
-        let nonce = 255;
-        let initial_amp_factor: u64 = 1;
-        let target_amp_factor: u64 = 1;
-        let start_ramp_ts: i64 = i64::MAX;
-        let stop_ramp_ts: i64 = i64::MAX;
-        let future_admin_deadline: i64 = i64::MAX;
-        let future_admin_key_raw = [1u8; 32];
-        let admin_key_raw = [2u8; 32];
-        let token_a_raw = [3u8; 32];
-        let token_b_raw = [4u8; 32];
-        let pool_mint_raw = [5u8; 32];
-        let token_a_mint_raw = [6u8; 32];
-        let token_b_mint_raw = [7u8; 32];
-        let admin_fee_key_a_raw = [8u8; 32];
-        let admin_fee_key_b_raw = [9u8; 32];
-        let admin_key = Pubkey::new_from_array(admin_key_raw);
-        let future_admin_key = Pubkey::new_from_array(future_admin_key_raw);
-        let token_a = Pubkey::new_from_array(token_a_raw);
-        let token_b = Pubkey::new_from_array(token_b_raw);
-        let pool_mint = Pubkey::new_from_array(pool_mint_raw);
-        let token_a_mint = Pubkey::new_from_array(token_a_mint_raw);
-        let token_b_mint = Pubkey::new_from_array(token_b_mint_raw);
-        let admin_fee_key_a = Pubkey::new_from_array(admin_fee_key_a_raw);
-        let admin_fee_key_b = Pubkey::new_from_array(admin_fee_key_b_raw);
-        let admin_trade_fee_numerator = 1;
-        let admin_trade_fee_denominator = 2;
-        let admin_withdraw_fee_numerator = 3;
-        let admin_withdraw_fee_denominator = 4;
-        let trade_fee_numerator = 5;
-        let trade_fee_denominator = 6;
-        let withdraw_fee_numerator = 7;
-        let withdraw_fee_denominator = 8;
-        let fees = Fees {
-            admin_trade_fee_numerator,
-            admin_trade_fee_denominator,
-            admin_withdraw_fee_numerator,
-            admin_withdraw_fee_denominator,
-            trade_fee_numerator,
-            trade_fee_denominator,
-            withdraw_fee_numerator,
-            withdraw_fee_denominator,
-        };
+/// Checks a packed account in the current or the legacy layout as strictly as
+/// [SwapInfo::unpack], returning whether it is in the legacy layout.
+fn check_packed_swap_info(input: &[u8]) -> Result<bool, ProgramError> {
+    let is_legacy = match input.len() {
+        SWAP_INFO_LEGACY_LEN => {
+            if LEGACY_FLAG_OFFSETS
+                .iter()
+                .any(|(_flag, offset)| input[*offset] > 1)
+            {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            true
+        }
+        SwapInfo::LEN => {
+            SwapInfo::check_version(array_ref![input, 0, 829])?;
+            false
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+    // Both layouts start with the initialized flag
+    if input[0] & FLAG_INITIALIZED == 0 {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(is_legacy)
+}
 
-        let is_initialized = true;
-        let is_paused = false;
-        let swap_info = SwapInfo {
-            is_initialized,
-            is_paused,
-            nonce,
-            initial_amp_factor,
-            target_amp_factor,
-            start_ramp_ts,
-            stop_ramp_ts,
-            future_admin_deadline,
-            future_admin_key,
-            admin_key,
-            token_a: SwapTokenInfo {
-                reserves: token_a,
-                mint: token_a_mint,
-                admin_fees: admin_fee_key_a,
-                index: 0,
-            },
-            token_b: SwapTokenInfo {
-                reserves: token_b,
-                mint: token_b_mint,
-                admin_fees: admin_fee_key_b,
-                index: 1,
-            },
-            pool_mint,
-            fees,
-        };
+/// Read-only view of a packed [SwapInfo] account, reading fields in place instead
+/// of unpacking the whole account.
+#[derive(Clone, Copy, Debug)]
+pub struct SwapInfoRef<'a> {
+    data: &'a [u8],
+    is_legacy: bool,
+}
 
-        let mut packed = [0u8; SwapInfo::LEN];
-        SwapInfo::pack(swap_info, &mut packed).unwrap();
-        let unpacked = SwapInfo::unpack(&packed).unwrap();
-        assert_eq!(swap_info, unpacked);
+impl<'a> SwapInfoRef<'a> {
+    /// Views an initialized account in the current or the legacy layout
+    pub fn new(data: &'a [u8]) -> Result<Self, ProgramError> {
+        let is_legacy = check_packed_swap_info(data)?;
+        Ok(Self { data, is_legacy })
+    }
 
-        let mut packed = vec![];
-        packed.push(1_u8); // is_initialized
-        packed.push(0_u8); // is_paused
-        packed.push(nonce);
-        packed.extend_from_slice(&initial_amp_factor.to_le_bytes());
-        packed.extend_from_slice(&target_amp_factor.to_le_bytes());
-        packed.extend_from_slice(&start_ramp_ts.to_le_bytes());
-        packed.extend_from_slice(&stop_ramp_ts.to_le_bytes());
-        packed.extend_from_slice(&future_admin_deadline.to_le_bytes());
-        packed.extend_from_slice(&future_admin_key_raw);
-        packed.extend_from_slice(&admin_key_raw);
-        packed.extend_from_slice(&token_a_raw);
-        packed.extend_from_slice(&token_b_raw);
-        packed.extend_from_slice(&pool_mint_raw);
-        packed.extend_from_slice(&token_a_mint_raw);
-        packed.extend_from_slice(&token_b_mint_raw);
-        packed.extend_from_slice(&admin_fee_key_a_raw);
-        packed.extend_from_slice(&admin_fee_key_b_raw);
-        packed.extend_from_slice(&admin_trade_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&admin_trade_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&admin_withdraw_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&admin_withdraw_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&trade_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&trade_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&withdraw_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&withdraw_fee_denominator.to_le_bytes());
-        let unpacked = SwapInfo::unpack(&packed).unwrap();
-        assert_eq!(swap_info, unpacked);
+    fn flag(&self, flag: u8) -> bool {
+        if self.is_legacy {
+            LEGACY_FLAG_OFFSETS
+                .iter()
+                .any(|(legacy_flag, offset)| *legacy_flag == flag && self.data[*offset] == 1)
+        } else {
+            self.data[0] & flag != 0
+        }
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(*array_ref![self.data, offset, 8])
+    }
+
+    fn read_u128(&self, offset: usize) -> u128 {
+        u128::from_le_bytes(*array_ref![self.data, offset, 16])
+    }
+
+    /// See [amp_scale], legacy accounts are read as the version they unpack to
+    fn amp_scale(&self) -> u64 {
+        if self.is_legacy {
+            amp_scale(SWAP_INFO_VERSION_WITHOUT_CHECKSUM)
+        } else {
+            amp_scale(self.data[1])
+        }
+    }
+
+    /// See [SwapInfo::is_paused]
+    pub fn is_paused(&self) -> bool {
+        self.flag(FLAG_PAUSED)
+    }
+
+    /// See [SwapInfo::is_deprecated]
+    pub fn is_deprecated(&self) -> bool {
+        self.flag(FLAG_DEPRECATED)
+    }
+
+    /// See [SwapInfo::reject_zero_amounts]
+    pub fn reject_zero_amounts(&self) -> bool {
+        self.flag(FLAG_REJECT_ZERO_AMOUNTS)
+    }
+
+    /// See [SwapInfo::nonce]
+    pub fn nonce(&self) -> u8 {
+        self.data[NONCE_OFFSET]
+    }
+
+    /// See [SwapInfo::initial_amp_factor]
+    pub fn initial_amp_factor(&self) -> u64 {
+        self.read_u64(INITIAL_AMP_FACTOR_OFFSET)
+            .saturating_mul(self.amp_scale())
+    }
+
+    /// See [SwapInfo::target_amp_factor]
+    pub fn target_amp_factor(&self) -> u64 {
+        self.read_u64(TARGET_AMP_FACTOR_OFFSET)
+            .saturating_mul(self.amp_scale())
+    }
+
+    /// See [SwapInfo::start_ramp_ts]
+    pub fn start_ramp_ts(&self) -> i64 {
+        i64::from_le_bytes(*array_ref![self.data, START_RAMP_TS_OFFSET, 8])
+    }
+
+    /// See [SwapInfo::stop_ramp_ts]
+    pub fn stop_ramp_ts(&self) -> i64 {
+        i64::from_le_bytes(*array_ref![self.data, STOP_RAMP_TS_OFFSET, 8])
+    }
+
+    /// See [SwapInfo::admin_key]
+    pub fn admin_key(&self) -> Pubkey {
+        Pubkey::new_from_array(*array_ref![self.data, ADMIN_KEY_OFFSET, 32])
+    }
+
+    /// See [SwapInfo::fee_growth_a], zero for legacy accounts
+    pub fn fee_growth_a(&self) -> u128 {
+        if self.is_legacy {
+            return 0;
+        }
+        self.read_u128(FEE_STATE_OFFSET + FEE_GROWTH_A_OFFSET)
+    }
+
+    /// See [SwapInfo::fee_growth_b], zero for legacy accounts
+    pub fn fee_growth_b(&self) -> u128 {
+        if self.is_legacy {
+            return 0;
+        }
+        self.read_u128(FEE_STATE_OFFSET + FEE_GROWTH_B_OFFSET)
+    }
+
+    /// See [SwapInfo::pool_token_supply], zero for legacy accounts
+    pub fn pool_token_supply(&self) -> u64 {
+        if self.is_legacy {
+            return 0;
+        }
+        self.read_u64(FEE_STATE_OFFSET + POOL_TOKEN_SUPPLY_OFFSET)
+    }
+
+    /// See [SwapInfo::reserve_amount_a], zero for legacy accounts
+    pub fn reserve_amount_a(&self) -> u64 {
+        if self.is_legacy {
+            return 0;
+        }
+        self.read_u64(RESERVE_AMOUNT_A_OFFSET)
+    }
+
+    /// See [SwapInfo::reserve_amount_b], zero for legacy accounts
+    pub fn reserve_amount_b(&self) -> u64 {
+        if self.is_legacy {
+            return 0;
+        }
+        self.read_u64(RESERVE_AMOUNT_B_OFFSET)
+    }
+
+    /// See [SwapInfo::peg_rate], zero for legacy accounts
+    pub fn peg_rate(&self) -> u64 {
+        if self.is_legacy {
+            return 0;
+        }
+        self.read_u64(PEG_RATE_OFFSET)
+    }
+
+    /// Whether the account is in the legacy layout
+    pub fn is_legacy(&self) -> bool {
+        self.is_legacy
+    }
+}
+
+/// Writable view of a packed [SwapInfo] account, writing only the fields that
+/// change. The checksum is refreshed once, when the view is dropped.
+#[derive(Debug)]
+pub struct SwapInfoRefMut<'a> {
+    data: &'a mut [u8],
+    is_legacy: bool,
+    is_modified: bool,
+}
+
+impl<'a> SwapInfoRefMut<'a> {
+    /// Views an initialized account in the current or the legacy layout
+    pub fn new(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        let is_legacy = check_packed_swap_info(data)?;
+        Ok(Self {
+            data,
+            is_legacy,
+            is_modified: false,
+        })
+    }
+
+    /// Reads the fields of the account
+    pub fn as_ref(&self) -> SwapInfoRef<'_> {
+        SwapInfoRef {
+            data: self.data,
+            is_legacy: self.is_legacy,
+        }
+    }
+
+    fn write(&mut self, offset: usize, bytes: &[u8]) {
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.is_modified = true;
+    }
+
+    /// Sets [SwapInfo::initial_amp_factor]. Accounts storing whole coefficients keep
+    /// storing them, rounded down.
+    pub fn set_initial_amp_factor(&mut self, initial_amp_factor: u64) {
+        let stored = initial_amp_factor / self.as_ref().amp_scale();
+        self.write(INITIAL_AMP_FACTOR_OFFSET, &stored.to_le_bytes());
+    }
+
+    /// Sets [SwapInfo::start_ramp_ts] and [SwapInfo::stop_ramp_ts]
+    pub fn set_ramp_ts(&mut self, start_ramp_ts: i64, stop_ramp_ts: i64) {
+        self.write(START_RAMP_TS_OFFSET, &start_ramp_ts.to_le_bytes());
+        self.write(STOP_RAMP_TS_OFFSET, &stop_ramp_ts.to_le_bytes());
+    }
+
+    /// Sets [SwapInfo::pool_token_supply]. Legacy accounts have no room for it and
+    /// are left unchanged.
+    pub fn set_pool_token_supply(&mut self, pool_token_supply: u64) {
+        if self.is_legacy {
+            return;
+        }
+        self.write(
+            FEE_STATE_OFFSET + POOL_TOKEN_SUPPLY_OFFSET,
+            &pool_token_supply.to_le_bytes(),
+        );
+    }
+
+    /// Sets [SwapInfo::reserve_amount_a] and [SwapInfo::reserve_amount_b].
+    /// Legacy accounts have no room for them and are left unchanged.
+    pub fn set_reserve_amounts(&mut self, reserve_amount_a: u64, reserve_amount_b: u64) {
+        if self.is_legacy {
+            return;
+        }
+        self.write(RESERVE_AMOUNT_A_OFFSET, &reserve_amount_a.to_le_bytes());
+        self.write(RESERVE_AMOUNT_B_OFFSET, &reserve_amount_b.to_le_bytes());
+    }
+
+    /// Sets [SwapInfo::peg_rate]. Legacy accounts have no room for it and are left
+    /// unchanged.
+    pub fn set_peg_rate(&mut self, peg_rate: u64) {
+        if self.is_legacy {
+            return;
+        }
+        self.write(PEG_RATE_OFFSET, &peg_rate.to_le_bytes());
+    }
+
+    /// See [SwapInfo::record_lp_fees]. Legacy accounts have no room for the fee
+    /// growth and are left unchanged.
+    pub fn record_lp_fees(&mut self, token_a_fee: u64, token_b_fee: u64) {
+        if self.is_legacy {
+            return;
+        }
+        let swap_info = self.as_ref();
+        let pool_token_supply = swap_info.pool_token_supply();
+        let fee_growth_a =
+            fee_growth_after(swap_info.fee_growth_a(), token_a_fee, pool_token_supply);
+        let fee_growth_b =
+            fee_growth_after(swap_info.fee_growth_b(), token_b_fee, pool_token_supply);
+        self.write(
+            FEE_STATE_OFFSET + FEE_GROWTH_A_OFFSET,
+            &fee_growth_a.to_le_bytes(),
+        );
+        self.write(
+            FEE_STATE_OFFSET + FEE_GROWTH_B_OFFSET,
+            &fee_growth_b.to_le_bytes(),
+        );
+    }
+}
+
+impl Drop for SwapInfoRefMut<'_> {
+    fn drop(&mut self) {
+        // Accounts of the version without checksum keep it until fully packed
+        if !self.is_modified || self.is_legacy || self.data[1] == SWAP_INFO_VERSION_WITHOUT_CHECKSUM
+        {
+            return;
+        }
+        let checksum = SwapInfo::checksum(array_ref![self.data, 0, 829]);
+        self.data[SWAP_INFO_CHECKSUM_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8]
+            .copy_from_slice(&checksum);
+    }
+}
+
+/// Seed of the program address owning the token accounts of every pool with a shared
+/// authority
+pub const SHARED_AUTHORITY_SEED: &[u8] = b"shared_authority";
+
+/// Finds the authority shared by the pools that opted into it, see
+/// [SwapInfo::shared_authority]
+pub fn find_shared_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SHARED_AUTHORITY_SEED], program_id)
+}
+
+/// Seed of the program address owning the liquidity locked on initialization.
+/// The program never signs for it, so that the locked pool tokens can never move.
+pub const LOCKED_LIQUIDITY_SEED: &[u8] = b"locked_liquidity";
+
+/// Finds the owner of the pool tokens locked on the initialization of a swap
+pub fn find_locked_liquidity_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LOCKED_LIQUIDITY_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Seed of the program address of another program, e.g. a DAO treasury, owning the
+/// admin fee accounts of a swap, see [find_admin_fee_vault_address]
+pub const ADMIN_FEE_VAULT_SEED: &[u8] = b"admin_fee_vault";
+
+/// Finds the owner of the admin fee accounts of a swap held in a vault of
+/// `vault_program_id`. The vault program collects the admin fees by signing for it.
+pub fn find_admin_fee_vault_address(vault_program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ADMIN_FEE_VAULT_SEED, &swap.to_bytes()], vault_program_id)
+}
+
+/// Maximum number of admin multisig signers
+pub const MAX_ADMIN_SIGNERS: usize = 11;
+
+/// Seed of the admin multisig program address
+pub const ADMIN_MULTISIG_SEED: &[u8] = b"admin_multisig";
+
+/// Finds the address of the admin multisig of a swap
+pub fn find_admin_multisig_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ADMIN_MULTISIG_SEED, &swap.to_bytes()], program_id)
+}
+
+/// M-of-N admin signer set, stored in a program address derived from the swap.
+/// The swap is controlled by the signer set once its admin key is set to that address.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdminMultisig {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap controlled by the signer set
+    pub swap: Pubkey,
+    /// Number of signers required
+    pub threshold: u8,
+    /// Number of valid signers
+    pub num_signers: u8,
+    /// Signer public keys
+    pub signers: [Pubkey; MAX_ADMIN_SIGNERS],
+}
+
+impl AdminMultisig {
+    /// Valid signers
+    pub fn signers(&self) -> &[Pubkey] {
+        &self.signers[..(self.num_signers as usize).min(MAX_ADMIN_SIGNERS)]
+    }
+}
+
+impl Sealed for AdminMultisig {}
+impl IsInitialized for AdminMultisig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for AdminMultisig {
+    const LEN: usize = 387;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 387];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, threshold, num_signers, signers_flat) =
+            array_refs![input, 1, 32, 1, 1, 32 * MAX_ADMIN_SIGNERS];
+        let mut signers = [Pubkey::default(); MAX_ADMIN_SIGNERS];
+        for (signer, packed) in signers.iter_mut().zip(signers_flat.chunks(32)) {
+            *signer = Pubkey::new_from_array(*array_ref![packed, 0, 32]);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            threshold: threshold[0],
+            num_signers: num_signers[0],
+            signers,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 387];
+        let (is_initialized, swap, threshold, num_signers, signers_flat) =
+            mut_array_refs![output, 1, 32, 1, 1, 32 * MAX_ADMIN_SIGNERS];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        threshold[0] = self.threshold;
+        num_signers[0] = self.num_signers;
+        for (signer, packed) in self.signers.iter().zip(signers_flat.chunks_mut(32)) {
+            packed.copy_from_slice(signer.as_ref());
+        }
+    }
+}
+
+/// Maximum number of fee exempt accounts
+pub const MAX_FEE_EXEMPT_ACCOUNTS: usize = 16;
+
+/// Seed of the fee exemption list program address
+pub const FEE_EXEMPTIONS_SEED: &[u8] = b"fee_exemptions";
+
+/// Finds the address of the fee exemption list of a swap
+pub fn find_fee_exemptions_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FEE_EXEMPTIONS_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Admin-managed list of user authorities (e.g. protocol-owned vaults) that pay
+/// neither the withdraw fee nor the trade fee on imbalanced deposits.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeExemptions {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the exemptions apply to
+    pub swap: Pubkey,
+    /// Number of valid exempt accounts
+    pub num_accounts: u8,
+    /// Exempt user authorities
+    pub accounts: [Pubkey; MAX_FEE_EXEMPT_ACCOUNTS],
+}
+
+impl FeeExemptions {
+    /// Valid exempt accounts
+    pub fn accounts(&self) -> &[Pubkey] {
+        &self.accounts[..(self.num_accounts as usize).min(MAX_FEE_EXEMPT_ACCOUNTS)]
+    }
+
+    /// Whether the given user authority is exempt from fees
+    pub fn is_exempt(&self, key: &Pubkey) -> bool {
+        self.accounts().contains(key)
+    }
+}
+
+impl Sealed for FeeExemptions {}
+impl IsInitialized for FeeExemptions {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for FeeExemptions {
+    const LEN: usize = 546;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 546];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, num_accounts, accounts_flat) =
+            array_refs![input, 1, 32, 1, 32 * MAX_FEE_EXEMPT_ACCOUNTS];
+        let mut accounts = [Pubkey::default(); MAX_FEE_EXEMPT_ACCOUNTS];
+        for (account, packed) in accounts.iter_mut().zip(accounts_flat.chunks(32)) {
+            *account = Pubkey::new_from_array(*array_ref![packed, 0, 32]);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            num_accounts: num_accounts[0],
+            accounts,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 546];
+        let (is_initialized, swap, num_accounts, accounts_flat) =
+            mut_array_refs![output, 1, 32, 1, 32 * MAX_FEE_EXEMPT_ACCOUNTS];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        num_accounts[0] = self.num_accounts;
+        for (account, packed) in self.accounts.iter().zip(accounts_flat.chunks_mut(32)) {
+            packed.copy_from_slice(account.as_ref());
+        }
+    }
+}
+
+/// Maximum number of user authorities on the deposit allowlist
+pub const MAX_DEPOSIT_ALLOWLIST_ACCOUNTS: usize = 24;
+
+/// Seed of the deposit allowlist program address
+pub const DEPOSIT_ALLOWLIST_SEED: &[u8] = b"deposit_allowlist";
+
+/// Finds the address of the deposit allowlist of a swap
+pub fn find_deposit_allowlist_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEPOSIT_ALLOWLIST_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Admin-managed list of the user authorities allowed to deposit into a swap,
+/// enforced once the swap enables its deposit allowlist. Swaps stay permissionless.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepositAllowlist {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the allowlist applies to
+    pub swap: Pubkey,
+    /// Number of valid allowed accounts
+    pub num_accounts: u8,
+    /// Allowed user authorities
+    pub accounts: [Pubkey; MAX_DEPOSIT_ALLOWLIST_ACCOUNTS],
+}
+
+impl DepositAllowlist {
+    /// Valid allowed accounts
+    pub fn accounts(&self) -> &[Pubkey] {
+        &self.accounts[..(self.num_accounts as usize).min(MAX_DEPOSIT_ALLOWLIST_ACCOUNTS)]
+    }
+
+    /// Whether the given user authority is allowed to deposit
+    pub fn is_allowed(&self, key: &Pubkey) -> bool {
+        self.accounts().contains(key)
+    }
+}
+
+impl Sealed for DepositAllowlist {}
+impl IsInitialized for DepositAllowlist {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for DepositAllowlist {
+    const LEN: usize = 802;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 802];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, num_accounts, accounts_flat) =
+            array_refs![input, 1, 32, 1, 32 * MAX_DEPOSIT_ALLOWLIST_ACCOUNTS];
+        let mut accounts = [Pubkey::default(); MAX_DEPOSIT_ALLOWLIST_ACCOUNTS];
+        for (account, packed) in accounts.iter_mut().zip(accounts_flat.chunks(32)) {
+            *account = Pubkey::new_from_array(*array_ref![packed, 0, 32]);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            num_accounts: num_accounts[0],
+            accounts,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 802];
+        let (is_initialized, swap, num_accounts, accounts_flat) =
+            mut_array_refs![output, 1, 32, 1, 32 * MAX_DEPOSIT_ALLOWLIST_ACCOUNTS];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        num_accounts[0] = self.num_accounts;
+        for (account, packed) in self.accounts.iter().zip(accounts_flat.chunks_mut(32)) {
+            packed.copy_from_slice(account.as_ref());
+        }
+    }
+}
+
+/// Maximum number of owners exempt from the per-owner deposit cap
+pub const MAX_DEPOSIT_CAP_EXEMPT_OWNERS: usize = 8;
+
+/// Seed of the deposit caps program address
+pub const DEPOSIT_CAPS_SEED: &[u8] = b"deposit_caps";
+
+/// Finds the address of the deposit caps of a swap
+pub fn find_deposit_caps_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEPOSIT_CAPS_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Admin-set limits on the liquidity of a swap, enforced on deposits once the swap
+/// enables them, see [SwapInfo::deposit_cap_enabled]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepositCaps {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the caps apply to
+    pub swap: Pubkey,
+    /// Max supply of the pool mint after a deposit. 0 disables the cap.
+    pub max_lp_supply: u64,
+    /// Max balance of the pool token account a deposit mints to, after the deposit.
+    /// Owners holding pool tokens in several accounts are capped per account. 0
+    /// disables the cap.
+    pub max_lp_per_owner: u64,
+    /// Number of valid exempt owners
+    pub num_exempt_owners: u8,
+    /// Owners of pool token accounts the per-owner cap does not apply to
+    pub exempt_owners: [Pubkey; MAX_DEPOSIT_CAP_EXEMPT_OWNERS],
+}
+
+impl DepositCaps {
+    /// Whether a deposit leaving the pool mint with `lp_supply` pool tokens stays
+    /// within the caps
+    pub fn allows_lp_supply(&self, lp_supply: u64) -> bool {
+        self.max_lp_supply == 0 || lp_supply <= self.max_lp_supply
+    }
+
+    /// Valid exempt owners
+    pub fn exempt_owners(&self) -> &[Pubkey] {
+        &self.exempt_owners[..(self.num_exempt_owners as usize).min(MAX_DEPOSIT_CAP_EXEMPT_OWNERS)]
+    }
+
+    /// Whether a deposit leaving a pool token account of `owner` with `balance` pool
+    /// tokens stays within the caps
+    pub fn allows_owner_balance(&self, owner: &Pubkey, balance: u64) -> bool {
+        self.max_lp_per_owner == 0
+            || balance <= self.max_lp_per_owner
+            || self.exempt_owners().contains(owner)
+    }
+
+    /// Whether any of the caps is set
+    pub fn is_capped(&self) -> bool {
+        self.max_lp_supply > 0 || self.max_lp_per_owner > 0
+    }
+}
+
+impl Sealed for DepositCaps {}
+impl IsInitialized for DepositCaps {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for DepositCaps {
+    const LEN: usize = 306;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 306];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            swap,
+            max_lp_supply,
+            max_lp_per_owner,
+            num_exempt_owners,
+            exempt_owners_flat,
+        ) = array_refs![input, 1, 32, 8, 8, 1, 32 * MAX_DEPOSIT_CAP_EXEMPT_OWNERS];
+        let mut exempt_owners = [Pubkey::default(); MAX_DEPOSIT_CAP_EXEMPT_OWNERS];
+        for (owner, packed) in exempt_owners.iter_mut().zip(exempt_owners_flat.chunks(32)) {
+            *owner = Pubkey::new_from_array(*array_ref![packed, 0, 32]);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            max_lp_supply: u64::from_le_bytes(*max_lp_supply),
+            max_lp_per_owner: u64::from_le_bytes(*max_lp_per_owner),
+            num_exempt_owners: num_exempt_owners[0],
+            exempt_owners,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 306];
+        let (
+            is_initialized,
+            swap,
+            max_lp_supply,
+            max_lp_per_owner,
+            num_exempt_owners,
+            exempt_owners_flat,
+        ) = mut_array_refs![output, 1, 32, 8, 8, 1, 32 * MAX_DEPOSIT_CAP_EXEMPT_OWNERS];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        *max_lp_supply = self.max_lp_supply.to_le_bytes();
+        *max_lp_per_owner = self.max_lp_per_owner.to_le_bytes();
+        num_exempt_owners[0] = self.num_exempt_owners;
+        for (owner, packed) in self
+            .exempt_owners
+            .iter()
+            .zip(exempt_owners_flat.chunks_mut(32))
+        {
+            packed.copy_from_slice(owner.as_ref());
+        }
+    }
+}
+
+/// Which programs may invoke the swaps, deposits, single-token withdrawals and zaps
+/// of a pool, checked against the top-level instruction of the transaction
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum CpiPolicy {
+    /// Any program, the default
+    Any = 0,
+    /// No program, the pool must be called by top-level instructions
+    TopLevelOnly = 1,
+    /// The programs of the CPI allowlist of the pool, see [CpiAllowlist]
+    AllowedCallers = 2,
+}
+
+impl CpiPolicy {
+    /// Converts a packed CPI policy
+    pub fn from_u8(policy: u8) -> Option<Self> {
+        match policy {
+            0 => Some(CpiPolicy::Any),
+            1 => Some(CpiPolicy::TopLevelOnly),
+            2 => Some(CpiPolicy::AllowedCallers),
+            _ => None,
+        }
+    }
+}
+
+/// Maximum number of programs on the CPI allowlist
+pub const MAX_CPI_ALLOWLIST_PROGRAMS: usize = 8;
+
+/// Seed of the CPI allowlist program address
+pub const CPI_ALLOWLIST_SEED: &[u8] = b"cpi_allowlist";
+
+/// Finds the address of the CPI allowlist of a swap
+pub fn find_cpi_allowlist_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CPI_ALLOWLIST_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Admin-managed list of the programs allowed to invoke a swap whose CPI policy
+/// is [CpiPolicy::AllowedCallers]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpiAllowlist {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the allowlist applies to
+    pub swap: Pubkey,
+    /// Number of valid allowed programs
+    pub num_programs: u8,
+    /// Allowed programs
+    pub programs: [Pubkey; MAX_CPI_ALLOWLIST_PROGRAMS],
+}
+
+impl CpiAllowlist {
+    /// Valid allowed programs
+    pub fn programs(&self) -> &[Pubkey] {
+        &self.programs[..(self.num_programs as usize).min(MAX_CPI_ALLOWLIST_PROGRAMS)]
+    }
+
+    /// Whether the given program may invoke the swap
+    pub fn is_allowed(&self, program_id: &Pubkey) -> bool {
+        self.programs().contains(program_id)
+    }
+}
+
+impl Sealed for CpiAllowlist {}
+impl IsInitialized for CpiAllowlist {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for CpiAllowlist {
+    const LEN: usize = 290;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 290];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, num_programs, programs_flat) =
+            array_refs![input, 1, 32, 1, 32 * MAX_CPI_ALLOWLIST_PROGRAMS];
+        let mut programs = [Pubkey::default(); MAX_CPI_ALLOWLIST_PROGRAMS];
+        for (program, packed) in programs.iter_mut().zip(programs_flat.chunks(32)) {
+            *program = Pubkey::new_from_array(*array_ref![packed, 0, 32]);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            num_programs: num_programs[0],
+            programs,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 290];
+        let (is_initialized, swap, num_programs, programs_flat) =
+            mut_array_refs![output, 1, 32, 1, 32 * MAX_CPI_ALLOWLIST_PROGRAMS];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        num_programs[0] = self.num_programs;
+        for (program, packed) in self.programs.iter().zip(programs_flat.chunks_mut(32)) {
+            packed.copy_from_slice(program.as_ref());
+        }
+    }
+}
+
+/// Source the peg rate of a swap is refreshed from
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum PegRateSource {
+    /// No source, the pool is pegged 1:1
+    None = 0,
+    /// A Pyth price account quoting token B in token A
+    PythPrice = 1,
+    /// An SPL stake pool whose pool token is token B, valued in lamports
+    StakePool = 2,
+}
+
+impl PegRateSource {
+    /// Converts a packed peg rate source
+    pub fn from_u8(source: u8) -> Option<Self> {
+        match source {
+            0 => Some(PegRateSource::None),
+            1 => Some(PegRateSource::PythPrice),
+            2 => Some(PegRateSource::StakePool),
+            _ => None,
+        }
+    }
+}
+
+/// Seed of the peg config program address
+pub const PEG_CONFIG_SEED: &[u8] = b"peg_config";
+
+/// Finds the address of the peg config of a swap
+pub fn find_peg_config_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PEG_CONFIG_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Admin-set source of the peg rate of a swap, see [SwapInfo::peg_rate]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PegConfig {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the peg rate is refreshed for
+    pub swap: Pubkey,
+    /// Kind of the rate account
+    pub rate_source: PegRateSource,
+    /// Account the peg rate is read from
+    pub rate_account: Pubkey,
+    /// Slot the peg rate was last refreshed at
+    pub rate_updated_slot: u64,
+}
+
+impl Sealed for PegConfig {}
+impl IsInitialized for PegConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PegConfig {
+    const LEN: usize = 74;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 74];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, rate_source, rate_account, rate_updated_slot) =
+            array_refs![input, 1, 32, 1, 32, 8];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            rate_source: PegRateSource::from_u8(rate_source[0])
+                .ok_or(ProgramError::InvalidAccountData)?,
+            rate_account: Pubkey::new_from_array(*rate_account),
+            rate_updated_slot: u64::from_le_bytes(*rate_updated_slot),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 74];
+        let (is_initialized, swap, rate_source, rate_account, rate_updated_slot) =
+            mut_array_refs![output, 1, 32, 1, 32, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        rate_source[0] = self.rate_source as u8;
+        rate_account.copy_from_slice(self.rate_account.as_ref());
+        *rate_updated_slot = self.rate_updated_slot.to_le_bytes();
+    }
+}
+
+/// Seed of the pool statistics program address
+pub const POOL_STATS_SEED: &[u8] = b"pool_stats";
+
+/// Finds the address of the statistics account of a swap
+pub fn find_pool_stats_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_STATS_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Lifetime statistics of a swap, updated by the instructions it is passed to.
+/// Counters saturate instead of failing the instruction.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the statistics are tracked for
+    pub swap: Pubkey,
+    /// Amount of token A swapped into or out of the pool
+    pub token_a_volume: u128,
+    /// Amount of token B swapped into or out of the pool
+    pub token_b_volume: u128,
+    /// Trade and withdraw fees paid in token A
+    pub token_a_fees: u128,
+    /// Trade and withdraw fees paid in token B
+    pub token_b_fees: u128,
+    /// Number of swaps
+    pub swap_count: u64,
+    /// Slot of the last instruction that updated the statistics
+    pub last_activity_slot: u64,
+    /// Admin share of the trade fees paid in token A, also when minted as pool
+    /// tokens. Not tracked in legacy accounts.
+    pub token_a_trade_admin_fees: u128,
+    /// Admin share of the trade fees paid in token B. Not tracked in legacy accounts.
+    pub token_b_trade_admin_fees: u128,
+    /// Admin share of the withdraw fees paid in token A. Not tracked in legacy
+    /// accounts.
+    pub token_a_withdraw_admin_fees: u128,
+    /// Admin share of the withdraw fees paid in token B. Not tracked in legacy
+    /// accounts.
+    pub token_b_withdraw_admin_fees: u128,
+}
+
+/// Length of the pool statistics accounts created before admin fees were tracked by
+/// source, which still take the other statistics
+pub const POOL_STATS_LEGACY_LEN: usize = 113;
+
+impl PoolStats {
+    /// Records a swap of `amount_in` of one token for `amount_out` of the
+    /// other, paying `fee` in the output token, `admin_fee` of it to the admin
+    pub fn record_swap(
+        &mut self,
+        a_to_b: bool,
+        amount_in: u64,
+        amount_out: u64,
+        fee: u64,
+        admin_fee: u64,
+    ) {
+        let (volume_in, volume_out, fees_out) = if a_to_b {
+            (
+                &mut self.token_a_volume,
+                &mut self.token_b_volume,
+                &mut self.token_b_fees,
+            )
+        } else {
+            (
+                &mut self.token_b_volume,
+                &mut self.token_a_volume,
+                &mut self.token_a_fees,
+            )
+        };
+        *volume_in = volume_in.saturating_add(amount_in.into());
+        *volume_out = volume_out.saturating_add(amount_out.into());
+        *fees_out = fees_out.saturating_add(fee.into());
+        self.swap_count = self.swap_count.saturating_add(1);
+        if a_to_b {
+            self.record_trade_admin_fees(0, admin_fee);
+        } else {
+            self.record_trade_admin_fees(admin_fee, 0);
+        }
+    }
+
+    /// Records fees paid outside of swaps
+    pub fn record_fees(&mut self, token_a_fee: u64, token_b_fee: u64) {
+        self.token_a_fees = self.token_a_fees.saturating_add(token_a_fee.into());
+        self.token_b_fees = self.token_b_fees.saturating_add(token_b_fee.into());
+    }
+
+    /// Records the admin share of trade fees
+    pub fn record_trade_admin_fees(&mut self, token_a_admin_fee: u64, token_b_admin_fee: u64) {
+        self.token_a_trade_admin_fees = self
+            .token_a_trade_admin_fees
+            .saturating_add(token_a_admin_fee.into());
+        self.token_b_trade_admin_fees = self
+            .token_b_trade_admin_fees
+            .saturating_add(token_b_admin_fee.into());
+    }
+
+    /// Records the admin share of withdraw fees
+    pub fn record_withdraw_admin_fees(&mut self, token_a_admin_fee: u64, token_b_admin_fee: u64) {
+        self.token_a_withdraw_admin_fees = self
+            .token_a_withdraw_admin_fees
+            .saturating_add(token_a_admin_fee.into());
+        self.token_b_withdraw_admin_fees = self
+            .token_b_withdraw_admin_fees
+            .saturating_add(token_b_admin_fee.into());
+    }
+
+    /// Whether an account of `len` bytes may hold pool statistics, in the current or
+    /// the legacy layout
+    pub fn is_valid_len(len: usize) -> bool {
+        len == Self::LEN || len == POOL_STATS_LEGACY_LEN
+    }
+}
+
+impl Sealed for PoolStats {}
+impl IsInitialized for PoolStats {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PoolStats {
+    const LEN: usize = 177;
+
+    /// Unpacks an account in the current or the legacy layout, whose admin fees by
+    /// source are zero.
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() == POOL_STATS_LEGACY_LEN {
+            let mut current = [0u8; 177];
+            current[..POOL_STATS_LEGACY_LEN].copy_from_slice(input);
+            return Self::unpack_from_slice(&current);
+        }
+        if input.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Self::unpack_from_slice(input)
+    }
+
+    /// Packs into an account in the current or the legacy layout, dropping the admin
+    /// fees by source.
+    fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() == POOL_STATS_LEGACY_LEN {
+            let mut current = [0u8; 177];
+            src.pack_into_slice(&mut current);
+            dst.copy_from_slice(&current[..POOL_STATS_LEGACY_LEN]);
+            return Ok(());
+        }
+        if dst.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        src.pack_into_slice(dst);
+        Ok(())
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 177];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            swap,
+            token_a_volume,
+            token_b_volume,
+            token_a_fees,
+            token_b_fees,
+            swap_count,
+            last_activity_slot,
+            token_a_trade_admin_fees,
+            token_b_trade_admin_fees,
+            token_a_withdraw_admin_fees,
+            token_b_withdraw_admin_fees,
+        ) = array_refs![input, 1, 32, 16, 16, 16, 16, 8, 8, 16, 16, 16, 16];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            token_a_volume: u128::from_le_bytes(*token_a_volume),
+            token_b_volume: u128::from_le_bytes(*token_b_volume),
+            token_a_fees: u128::from_le_bytes(*token_a_fees),
+            token_b_fees: u128::from_le_bytes(*token_b_fees),
+            swap_count: u64::from_le_bytes(*swap_count),
+            last_activity_slot: u64::from_le_bytes(*last_activity_slot),
+            token_a_trade_admin_fees: u128::from_le_bytes(*token_a_trade_admin_fees),
+            token_b_trade_admin_fees: u128::from_le_bytes(*token_b_trade_admin_fees),
+            token_a_withdraw_admin_fees: u128::from_le_bytes(*token_a_withdraw_admin_fees),
+            token_b_withdraw_admin_fees: u128::from_le_bytes(*token_b_withdraw_admin_fees),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 177];
+        let (
+            is_initialized,
+            swap,
+            token_a_volume,
+            token_b_volume,
+            token_a_fees,
+            token_b_fees,
+            swap_count,
+            last_activity_slot,
+            token_a_trade_admin_fees,
+            token_b_trade_admin_fees,
+            token_a_withdraw_admin_fees,
+            token_b_withdraw_admin_fees,
+        ) = mut_array_refs![output, 1, 32, 16, 16, 16, 16, 8, 8, 16, 16, 16, 16];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        *token_a_volume = self.token_a_volume.to_le_bytes();
+        *token_b_volume = self.token_b_volume.to_le_bytes();
+        *token_a_fees = self.token_a_fees.to_le_bytes();
+        *token_b_fees = self.token_b_fees.to_le_bytes();
+        *swap_count = self.swap_count.to_le_bytes();
+        *last_activity_slot = self.last_activity_slot.to_le_bytes();
+        *token_a_trade_admin_fees = self.token_a_trade_admin_fees.to_le_bytes();
+        *token_b_trade_admin_fees = self.token_b_trade_admin_fees.to_le_bytes();
+        *token_a_withdraw_admin_fees = self.token_a_withdraw_admin_fees.to_le_bytes();
+        *token_b_withdraw_admin_fees = self.token_b_withdraw_admin_fees.to_le_bytes();
+    }
+}
+
+/// Seed of the last trade program address
+pub const LAST_TRADE_SEED: &[u8] = b"last_trade";
+
+/// Finds the address of the last trade of a swap
+pub fn find_last_trade_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LAST_TRADE_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Maximum number of user authorities recorded per slot in a [LastTrade]
+pub const MAX_SLOT_TRADERS: usize = 32;
+
+/// Direction of the swaps of a user authority in the slot of a [LastTrade]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SlotTrade {
+    /// User authority of the swaps
+    pub trader: Pubkey,
+    /// Whether the swaps sold token A for token B
+    pub a_to_b: bool,
+}
+
+/// Swaps of the last slot of a swap whose same-slot guard is enabled, one entry per
+/// user authority so that the swaps of others do not hide a reversal, see
+/// [SwapInfo::same_slot_guard]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LastTrade {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the trades are recorded for
+    pub swap: Pubkey,
+    /// Slot of the recorded trades
+    pub slot: u64,
+    /// Number of valid trades
+    pub num_trades: u8,
+    /// Trades of the slot, one per user authority
+    pub trades: [SlotTrade; MAX_SLOT_TRADERS],
+}
+
+impl LastTrade {
+    /// Valid trades of the slot
+    pub fn trades(&self) -> &[SlotTrade] {
+        &self.trades[..(self.num_trades as usize).min(MAX_SLOT_TRADERS)]
+    }
+
+    /// Whether a swap by `trader` in `slot` would undo one of its swaps, i.e. the
+    /// same user authority swaps back in the same slot
+    pub fn is_reversed_by(&self, slot: u64, trader: &Pubkey, a_to_b: bool) -> bool {
+        self.slot == slot
+            && self
+                .trades()
+                .iter()
+                .any(|trade| trade.trader == *trader && trade.a_to_b != a_to_b)
+    }
+
+    /// Records a swap by `trader` in `slot`, clearing the trades of an older slot.
+    /// Returns false if the slot already holds [MAX_SLOT_TRADERS] other traders.
+    pub fn record(&mut self, slot: u64, trader: &Pubkey, a_to_b: bool) -> bool {
+        if self.slot != slot {
+            self.slot = slot;
+            self.num_trades = 0;
+        }
+        if self.trades().iter().any(|trade| trade.trader == *trader) {
+            return true;
+        }
+        let index = self.num_trades as usize;
+        if index >= MAX_SLOT_TRADERS {
+            return false;
+        }
+        self.trades[index] = SlotTrade {
+            trader: *trader,
+            a_to_b,
+        };
+        self.num_trades += 1;
+        true
+    }
+}
+
+impl Sealed for LastTrade {}
+impl IsInitialized for LastTrade {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for LastTrade {
+    const LEN: usize = 1098;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 1098];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, slot, num_trades, trades_flat) =
+            array_refs![input, 1, 32, 8, 1, 33 * MAX_SLOT_TRADERS];
+        let mut trades = [SlotTrade::default(); MAX_SLOT_TRADERS];
+        for (trade, packed) in trades.iter_mut().zip(trades_flat.chunks(33)) {
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (trader, a_to_b) = array_refs![array_ref![packed, 0, 33], 32, 1];
+            *trade = SlotTrade {
+                trader: Pubkey::new_from_array(*trader),
+                a_to_b: match a_to_b {
+                    [0] => false,
+                    [1] => true,
+                    _ => return Err(ProgramError::InvalidAccountData),
+                },
+            };
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            slot: u64::from_le_bytes(*slot),
+            num_trades: num_trades[0],
+            trades,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 1098];
+        let (is_initialized, swap, slot, num_trades, trades_flat) =
+            mut_array_refs![output, 1, 32, 8, 1, 33 * MAX_SLOT_TRADERS];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        *slot = self.slot.to_le_bytes();
+        num_trades[0] = self.num_trades;
+        for (trade, packed) in self.trades.iter().zip(trades_flat.chunks_mut(33)) {
+            let (trader, a_to_b) = mut_array_refs![array_mut_ref![packed, 0, 33], 32, 1];
+            trader.copy_from_slice(trade.trader.as_ref());
+            a_to_b[0] = trade.a_to_b as u8;
+        }
+    }
+}
+
+/// Seed of the global state of the program
+pub const GLOBAL_STATE_SEED: &[u8] = b"global_state";
+
+/// Finds the address of the global state of the program
+pub fn find_global_state_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GLOBAL_STATE_SEED], program_id)
+}
+
+/// Program-wide state, holding the emergency authority that can pause
+/// every pool of the program at once.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GlobalState {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Authority allowed to pause and unpause all pools
+    pub emergency_authority: Pubkey,
+    /// Whether all pools are paused
+    pub is_paused: bool,
+}
+
+impl Sealed for GlobalState {}
+impl IsInitialized for GlobalState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for GlobalState {
+    const LEN: usize = 34;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 34];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, emergency_authority, is_paused) = array_refs![input, 1, 32, 1];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            emergency_authority: Pubkey::new_from_array(*emergency_authority),
+            is_paused: match is_paused {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 34];
+        let (is_initialized, emergency_authority, is_paused) = mut_array_refs![output, 1, 32, 1];
+        is_initialized[0] = self.is_initialized as u8;
+        emergency_authority.copy_from_slice(self.emergency_authority.as_ref());
+        is_paused[0] = self.is_paused as u8;
+    }
+}
+
+/// Seed of the LP position program addresses
+pub const LP_POSITION_SEED: &[u8] = b"lp_position";
+
+/// Finds the address of the LP position of an owner in a swap
+pub fn find_lp_position_address(
+    program_id: &Pubkey,
+    swap: &Pubkey,
+    owner: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[LP_POSITION_SEED, &swap.to_bytes(), &owner.to_bytes()],
+        program_id,
+    )
+}
+
+/// Fees earned by the pool tokens of an owner, checkpointed against the
+/// fee growth of the swap each time the position is updated.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LpPosition {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap of the position
+    pub swap: Pubkey,
+    /// Owner of the pool token account tracked by the position
+    pub owner: Pubkey,
+    /// Pool tokens held at the last checkpoint
+    pub pool_token_amount: u64,
+    /// Fee growth of token A at the last checkpoint
+    pub fee_growth_a_checkpoint: u128,
+    /// Fee growth of token B at the last checkpoint
+    pub fee_growth_b_checkpoint: u128,
+    /// Token A fees earned since the position was created
+    pub fees_earned_a: u64,
+    /// Token B fees earned since the position was created
+    pub fees_earned_b: u64,
+    /// Deposit timestamp of the checkpointed pool tokens, averaged over the pool
+    /// tokens added to the position. Withdrawals leave it unchanged.
+    pub deposit_ts: i64,
+}
+
+impl LpPosition {
+    /// Accrues the fees earned by the checkpointed pool tokens up to the
+    /// current fee growth of the swap, then checkpoints the new pool token amount
+    pub fn checkpoint(&mut self, token_swap: &SwapInfo, pool_token_amount: u64) {
+        self.fees_earned_a = self.fees_earned_a.saturating_add(Self::earned(
+            self.pool_token_amount,
+            token_swap
+                .fee_growth_a
+                .wrapping_sub(self.fee_growth_a_checkpoint),
+        ));
+        self.fees_earned_b = self.fees_earned_b.saturating_add(Self::earned(
+            self.pool_token_amount,
+            token_swap
+                .fee_growth_b
+                .wrapping_sub(self.fee_growth_b_checkpoint),
+        ));
+        self.fee_growth_a_checkpoint = token_swap.fee_growth_a;
+        self.fee_growth_b_checkpoint = token_swap.fee_growth_b;
+        self.pool_token_amount = pool_token_amount;
+    }
+
+    /// Averages the deposit timestamp of the pool tokens the new pool token amount adds
+    /// to the checkpointed ones in, to be called before [LpPosition::checkpoint]
+    pub fn update_deposit_ts(&mut self, pool_token_amount: u64, current_ts: i64) {
+        if self.pool_token_amount == 0 {
+            self.deposit_ts = current_ts;
+            return;
+        }
+        if pool_token_amount <= self.pool_token_amount {
+            return;
+        }
+        let age = i128::from(current_ts.saturating_sub(self.deposit_ts));
+        let added = i128::from(pool_token_amount - self.pool_token_amount);
+        let delta = age * added / i128::from(pool_token_amount);
+        self.deposit_ts = self
+            .deposit_ts
+            .saturating_add(delta.to_i64().unwrap_or_default());
+    }
+
+    /// Whether withdrawing up to `pool_token_amount` pool tokens of the position is free
+    /// of withdraw fees, see [SwapInfo::withdraw_fee_grace_period]
+    pub fn is_past_withdraw_fee_grace_period(
+        &self,
+        withdraw_fee_grace_period: i64,
+        pool_token_amount: u64,
+        current_ts: i64,
+    ) -> bool {
+        withdraw_fee_grace_period > 0
+            && pool_token_amount <= self.pool_token_amount
+            && current_ts.saturating_sub(self.deposit_ts) >= withdraw_fee_grace_period
+    }
+
+    fn earned(pool_token_amount: u64, fee_growth: u128) -> u64 {
+        ((U192::from(pool_token_amount) * U192::from(fee_growth)) >> 64)
+            .to_u64()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+impl Sealed for LpPosition {}
+impl IsInitialized for LpPosition {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for LpPosition {
+    const LEN: usize = 129;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 129];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            swap,
+            owner,
+            pool_token_amount,
+            fee_growth_a_checkpoint,
+            fee_growth_b_checkpoint,
+            fees_earned_a,
+            fees_earned_b,
+            deposit_ts,
+        ) = array_refs![input, 1, 32, 32, 8, 16, 16, 8, 8, 8];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            owner: Pubkey::new_from_array(*owner),
+            pool_token_amount: u64::from_le_bytes(*pool_token_amount),
+            fee_growth_a_checkpoint: u128::from_le_bytes(*fee_growth_a_checkpoint),
+            fee_growth_b_checkpoint: u128::from_le_bytes(*fee_growth_b_checkpoint),
+            fees_earned_a: u64::from_le_bytes(*fees_earned_a),
+            fees_earned_b: u64::from_le_bytes(*fees_earned_b),
+            deposit_ts: i64::from_le_bytes(*deposit_ts),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 129];
+        let (
+            is_initialized,
+            swap,
+            owner,
+            pool_token_amount,
+            fee_growth_a_checkpoint,
+            fee_growth_b_checkpoint,
+            fees_earned_a,
+            fees_earned_b,
+            deposit_ts,
+        ) = mut_array_refs![output, 1, 32, 32, 8, 16, 16, 8, 8, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        *pool_token_amount = self.pool_token_amount.to_le_bytes();
+        *fee_growth_a_checkpoint = self.fee_growth_a_checkpoint.to_le_bytes();
+        *fee_growth_b_checkpoint = self.fee_growth_b_checkpoint.to_le_bytes();
+        *fees_earned_a = self.fees_earned_a.to_le_bytes();
+        *fees_earned_b = self.fees_earned_b.to_le_bytes();
+        *deposit_ts = self.deposit_ts.to_le_bytes();
+    }
+}
+
+/// Seed of the order program addresses
+pub const ORDER_SEED: &[u8] = b"order";
+
+/// Finds the address of an order of an owner in a swap, numbered by the owner
+pub fn find_order_address(
+    program_id: &Pubkey,
+    swap: &Pubkey,
+    owner: &Pubkey,
+    order_id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            ORDER_SEED,
+            &swap.to_bytes(),
+            &owner.to_bytes(),
+            &order_id.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// A limit order resting against a swap: tokens of the owner escrowed in a token
+/// account of the order, swapped in full by any FillOrder once the curve pays at
+/// least the limit price `minimum_amount_out / amount_in`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Order {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the order trades against
+    pub swap: Pubkey,
+    /// Owner of the order, who may cancel it
+    pub owner: Pubkey,
+    /// Number of the order among the orders of the owner
+    pub order_id: u64,
+    /// Bump seed of the order address, signing for the escrow
+    pub bump_seed: u8,
+    /// Token account of the order holding the tokens sold
+    pub escrow: Pubkey,
+    /// Token account of the owner receiving the tokens bought
+    pub destination: Pubkey,
+    /// Amount of tokens sold
+    pub amount_in: u64,
+    /// Minimum amount of tokens bought
+    pub minimum_amount_out: u64,
+}
+
+impl Sealed for Order {}
+impl IsInitialized for Order {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Order {
+    const LEN: usize = 154;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 154];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            swap,
+            owner,
+            order_id,
+            bump_seed,
+            escrow,
+            destination,
+            amount_in,
+            minimum_amount_out,
+        ) = array_refs![input, 1, 32, 32, 8, 1, 32, 32, 8, 8];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            owner: Pubkey::new_from_array(*owner),
+            order_id: u64::from_le_bytes(*order_id),
+            bump_seed: bump_seed[0],
+            escrow: Pubkey::new_from_array(*escrow),
+            destination: Pubkey::new_from_array(*destination),
+            amount_in: u64::from_le_bytes(*amount_in),
+            minimum_amount_out: u64::from_le_bytes(*minimum_amount_out),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 154];
+        let (
+            is_initialized,
+            swap,
+            owner,
+            order_id,
+            bump_seed,
+            escrow,
+            destination,
+            amount_in,
+            minimum_amount_out,
+        ) = mut_array_refs![output, 1, 32, 32, 8, 1, 32, 32, 8, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        *order_id = self.order_id.to_le_bytes();
+        bump_seed[0] = self.bump_seed;
+        escrow.copy_from_slice(self.escrow.as_ref());
+        destination.copy_from_slice(self.destination.as_ref());
+        *amount_in = self.amount_in.to_le_bytes();
+        *minimum_amount_out = self.minimum_amount_out.to_le_bytes();
+    }
+}
+
+/// Seed of the pool migration program address
+pub const POOL_MIGRATION_SEED: &[u8] = b"pool_migration";
+
+/// Finds the address of the migration record of a swap
+pub fn find_pool_migration_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_MIGRATION_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Record of the migration of the reserves of a swap into a new swap.
+/// The pool tokens of the new swap minted for the reserves are held in escrow
+/// by the authority of the old swap, and redeemed pro rata for old pool tokens.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolMigration {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the reserves were migrated from
+    pub swap: Pubkey,
+    /// Swap the reserves were migrated to
+    pub new_swap: Pubkey,
+    /// Token account of the old swap authority holding the new pool tokens
+    pub lp_escrow: Pubkey,
+}
+
+impl Sealed for PoolMigration {}
+impl IsInitialized for PoolMigration {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PoolMigration {
+    const LEN: usize = 97;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 97];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, new_swap, lp_escrow) = array_refs![input, 1, 32, 32, 32];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            new_swap: Pubkey::new_from_array(*new_swap),
+            lp_escrow: Pubkey::new_from_array(*lp_escrow),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 97];
+        let (is_initialized, swap, new_swap, lp_escrow) = mut_array_refs![output, 1, 32, 32, 32];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        new_swap.copy_from_slice(self.new_swap.as_ref());
+        lp_escrow.copy_from_slice(self.lp_escrow.as_ref());
+    }
+}
+
+/// Seed of the pool registry entry program address
+pub const POOL_REGISTRY_ENTRY_SEED: &[u8] = b"pool_registry_entry";
+
+/// Maximum length of the name of a pool registry entry, in bytes
+pub const MAX_POOL_NAME_LEN: usize = 32;
+
+/// Finds the address of the registry entry of a swap
+pub fn find_pool_registry_entry_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_REGISTRY_ENTRY_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Category of a pool, for front-ends listing pools
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum PoolCategory {
+    /// Pool of two stablecoins
+    Stable = 0,
+    /// Pool of an asset and a token pegged to it
+    Pegged = 1,
+}
+
+impl PoolCategory {
+    /// Converts a packed pool category
+    pub fn from_u8(category: u8) -> Option<Self> {
+        match category {
+            0 => Some(PoolCategory::Stable),
+            1 => Some(PoolCategory::Pegged),
+            _ => None,
+        }
+    }
+}
+
+/// Admin-managed description of a swap for front-ends, replacing off-chain registries
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolRegistryEntry {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap the entry describes
+    pub swap: Pubkey,
+    /// Length of the name, in bytes
+    pub name_len: u8,
+    /// UTF-8 name of the pool, padded with zeros
+    pub name: [u8; MAX_POOL_NAME_LEN],
+    /// Category of the pool
+    pub category: PoolCategory,
+    /// Whether front-ends should stop listing the pool
+    pub is_deprecated: bool,
+}
+
+impl PoolRegistryEntry {
+    /// Name of the pool, or `None` if it is not valid UTF-8
+    pub fn name(&self) -> Option<&str> {
+        let name = self.name.get(..self.name_len as usize)?;
+        std::str::from_utf8(name).ok()
+    }
+}
+
+impl Sealed for PoolRegistryEntry {}
+impl IsInitialized for PoolRegistryEntry {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PoolRegistryEntry {
+    const LEN: usize = 68;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 68];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, name_len, name, category, is_deprecated) =
+            array_refs![input, 1, 32, 1, MAX_POOL_NAME_LEN, 1, 1];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            name_len: name_len[0],
+            name: *name,
+            category: PoolCategory::from_u8(category[0]).ok_or(ProgramError::InvalidAccountData)?,
+            is_deprecated: match is_deprecated {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 68];
+        let (is_initialized, swap, name_len, name, category, is_deprecated) =
+            mut_array_refs![output, 1, 32, 1, MAX_POOL_NAME_LEN, 1, 1];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        name_len[0] = self.name_len;
+        *name = self.name;
+        category[0] = self.category as u8;
+        is_deprecated[0] = self.is_deprecated as u8;
+    }
+}
+
+/// Seed of the rewards distributor program address
+pub const REWARDS_DISTRIBUTOR_SEED: &[u8] = b"rewards_distributor";
+
+/// Finds the address of the rewards distributor of a swap
+pub fn find_rewards_distributor_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REWARDS_DISTRIBUTOR_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Program the admin fees of a swap are harvested into, and the token accounts
+/// receiving them. Set by the admin, harvested by anyone with HarvestFees.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RewardsDistributor {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap whose admin fees are harvested
+    pub swap: Pubkey,
+    /// Rewards distributor program notified of every harvest
+    pub program: Pubkey,
+    /// Token account receiving the token A admin fees
+    pub rewards_a: Pubkey,
+    /// Token account receiving the token B admin fees
+    pub rewards_b: Pubkey,
+}
+
+impl Sealed for RewardsDistributor {}
+impl IsInitialized for RewardsDistributor {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for RewardsDistributor {
+    const LEN: usize = 129;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 129];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, program, rewards_a, rewards_b) =
+            array_refs![input, 1, 32, 32, 32, 32];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            program: Pubkey::new_from_array(*program),
+            rewards_a: Pubkey::new_from_array(*rewards_a),
+            rewards_b: Pubkey::new_from_array(*rewards_b),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 129];
+        let (is_initialized, swap, program, rewards_a, rewards_b) =
+            mut_array_refs![output, 1, 32, 32, 32, 32];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        program.copy_from_slice(self.program.as_ref());
+        rewards_a.copy_from_slice(self.rewards_a.as_ref());
+        rewards_b.copy_from_slice(self.rewards_b.as_ref());
+    }
+}
+
+/// Seed of the staking config program address
+pub const STAKING_CONFIG_SEED: &[u8] = b"staking_config";
+
+/// Finds the address of the staking config of a swap
+pub fn find_staking_config_address(program_id: &Pubkey, swap: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKING_CONFIG_SEED, &swap.to_bytes()], program_id)
+}
+
+/// Staking program a Deposit can stake the minted pool tokens into, and the
+/// staking pool it must stake into. Set by the admin.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StakingConfig {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Swap whose pool tokens are staked
+    pub swap: Pubkey,
+    /// Staking program invoked by deposits, e.g. a quarry mine program
+    pub program: Pubkey,
+    /// Account of the staking program the pool tokens are staked into, e.g. a quarry
+    pub staking_pool: Pubkey,
+    /// Prefix of the staking instruction data, e.g. an Anchor discriminator,
+    /// followed by the staked amount
+    pub stake_instruction_prefix: [u8; 8],
+}
+
+impl Sealed for StakingConfig {}
+impl IsInitialized for StakingConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for StakingConfig {
+    const LEN: usize = 105;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 105];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, program, staking_pool, stake_instruction_prefix) =
+            array_refs![input, 1, 32, 32, 32, 8];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            program: Pubkey::new_from_array(*program),
+            staking_pool: Pubkey::new_from_array(*staking_pool),
+            stake_instruction_prefix: *stake_instruction_prefix,
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 105];
+        let (is_initialized, swap, program, staking_pool, stake_instruction_prefix) =
+            mut_array_refs![output, 1, 32, 32, 32, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        program.copy_from_slice(self.program.as_ref());
+        staking_pool.copy_from_slice(self.staking_pool.as_ref());
+        *stake_instruction_prefix = self.stake_instruction_prefix;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::fees::{DynamicFee, FeeMode, FeeTier, MAX_FEE_TIERS, NO_FEE_TIERS};
+
+    #[test]
+    fn test_swap_info_packing() {
+        let nonce = 255;
+        let initial_amp_factor: u64 = AMP_PRECISION;
+        let target_amp_factor: u64 = AMP_PRECISION;
+        let start_ramp_ts: i64 = i64::MAX;
+        let stop_ramp_ts: i64 = i64::MAX;
+        let future_admin_deadline: i64 = i64::MAX;
+        let future_admin_key_raw = [1u8; 32];
+        let admin_key_raw = [2u8; 32];
+        let token_a_raw = [3u8; 32];
+        let token_b_raw = [4u8; 32];
+        let pool_mint_raw = [5u8; 32];
+        let token_a_mint_raw = [6u8; 32];
+        let token_b_mint_raw = [7u8; 32];
+        let admin_fee_key_a_raw = [8u8; 32];
+        let admin_fee_key_b_raw = [9u8; 32];
+        let admin_key = Pubkey::new_from_array(admin_key_raw);
+        let future_admin_key = Pubkey::new_from_array(future_admin_key_raw);
+        let token_a = Pubkey::new_from_array(token_a_raw);
+        let token_b = Pubkey::new_from_array(token_b_raw);
+        let pool_mint = Pubkey::new_from_array(pool_mint_raw);
+        let token_a_mint = Pubkey::new_from_array(token_a_mint_raw);
+        let token_b_mint = Pubkey::new_from_array(token_b_mint_raw);
+        let admin_fee_key_a = Pubkey::new_from_array(admin_fee_key_a_raw);
+        let admin_fee_key_b = Pubkey::new_from_array(admin_fee_key_b_raw);
+        let admin_trade_fee_numerator = 1;
+        let admin_trade_fee_denominator = 2;
+        let admin_withdraw_fee_numerator = 3;
+        let admin_withdraw_fee_denominator = 4;
+        let trade_fee_numerator = 5;
+        let trade_fee_denominator = 6;
+        let withdraw_fee_numerator = 7;
+        let withdraw_fee_denominator = 8;
+        let tier_threshold: u64 = 9;
+        let tier_trade_fee_numerator: u64 = 10;
+        let mut trade_fee_tiers = [FeeTier::default(); MAX_FEE_TIERS];
+        trade_fee_tiers[0] = FeeTier {
+            threshold: tier_threshold,
+            trade_fee_numerator: tier_trade_fee_numerator,
+        };
+        let dynamic_fee = DynamicFee {
+            mode: FeeMode::Dynamic,
+            fee_gamma: 11,
+            out_fee_numerator: 12,
+        };
+        let rebalancing_rebate_bps: u64 = 13;
+        let fees = Fees {
+            admin_trade_fee_numerator,
+            admin_trade_fee_denominator,
+            admin_withdraw_fee_numerator,
+            admin_withdraw_fee_denominator,
+            trade_fee_numerator,
+            trade_fee_denominator,
+            withdraw_fee_numerator,
+            withdraw_fee_denominator,
+            trade_fee_tiers,
+            dynamic_fee,
+            rebalancing_rebate_bps,
+            host_fee_bps: 0,
+        };
+        let future_fees_deadline: i64 = i64::MAX;
+        let future_fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 2,
+            ..Fees::default()
+        };
+
+        let is_initialized = true;
+        let is_paused = false;
+        let swap_info = SwapInfo {
+            is_initialized,
+            is_paused,
+            nonce,
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+            future_admin_deadline,
+            future_admin_key,
+            admin_key,
+            token_a: SwapTokenInfo {
+                reserves: token_a,
+                mint: token_a_mint,
+                admin_fees: admin_fee_key_a,
+                index: 0,
+            },
+            token_b: SwapTokenInfo {
+                reserves: token_b,
+                mint: token_b_mint,
+                admin_fees: admin_fee_key_b,
+                index: 1,
+            },
+            pool_mint,
+            fees,
+            future_fees_deadline,
+            future_fees,
+            donations_admin_only: true,
+            spl_token_swap_compat: false,
+            fee_growth_a: 1 << 64,
+            fee_growth_b: u128::MAX,
+            pool_token_supply: 7,
+            price_oracle_a: Pubkey::new_from_array([9u8; 32]),
+            price_oracle_b: Pubkey::new_from_array([10u8; 32]),
+            max_price_deviation_bps: 50,
+            initial_trade_fee_numerator: 11,
+            initial_withdraw_fee_numerator: 12,
+            start_fee_ramp_ts: 13,
+            stop_fee_ramp_ts: 14,
+            deposit_allowlist_enabled: true,
+            max_swap_bps_of_reserves: 15,
+            is_deprecated: false,
+            shared_authority: false,
+            reject_zero_amounts: false,
+            reserve_amount_a: 0,
+            reserve_amount_b: 0,
+            withdraw_fee_grace_period: 0,
+            cpi_policy: CpiPolicy::Any,
+            peg_rate: 0,
+            quick_swap_max_bps: 0,
+            same_slot_guard: false,
+            admin_fees_as_lp: false,
+            withdraw_one_while_paused: false,
+            quiet_logs: false,
+            deposit_cap_enabled: false,
+            curve_type: CurveType::StableSwap,
+            initial_supply_scaled: false,
+        };
+
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut packed).unwrap();
+        let unpacked = SwapInfo::unpack(&packed).unwrap();
+        assert_eq!(swap_info, unpacked);
+
+        let mut current = vec![];
+        current.push(0b1_0101); // initialized, donations_admin_only, deposit_allowlist_enabled
+        current.push(SWAP_INFO_VERSION);
+        current.push(nonce);
+        // amplification coefficients in hundredths
+        current.extend_from_slice(&initial_amp_factor.to_le_bytes());
+        current.extend_from_slice(&target_amp_factor.to_le_bytes());
+        current.extend_from_slice(&start_ramp_ts.to_le_bytes());
+        current.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+        current.extend_from_slice(&future_admin_deadline.to_le_bytes());
+        current.extend_from_slice(&future_admin_key_raw);
+        current.extend_from_slice(&admin_key_raw);
+        current.extend_from_slice(&token_a_raw);
+        current.extend_from_slice(&token_b_raw);
+        current.extend_from_slice(&pool_mint_raw);
+        current.extend_from_slice(&token_a_mint_raw);
+        current.extend_from_slice(&token_b_mint_raw);
+        current.extend_from_slice(&admin_fee_key_a_raw);
+        current.extend_from_slice(&admin_fee_key_b_raw);
+        current.extend_from_slice(&admin_trade_fee_numerator.to_le_bytes());
+        current.extend_from_slice(&admin_trade_fee_denominator.to_le_bytes());
+        current.extend_from_slice(&admin_withdraw_fee_numerator.to_le_bytes());
+        current.extend_from_slice(&admin_withdraw_fee_denominator.to_le_bytes());
+        current.extend_from_slice(&trade_fee_numerator.to_le_bytes());
+        current.extend_from_slice(&trade_fee_denominator.to_le_bytes());
+        current.extend_from_slice(&withdraw_fee_numerator.to_le_bytes());
+        current.extend_from_slice(&withdraw_fee_denominator.to_le_bytes());
+        current.extend_from_slice(&tier_threshold.to_le_bytes());
+        current.extend_from_slice(&tier_trade_fee_numerator.to_le_bytes());
+        current.extend_from_slice(&[0u8; FeeTier::LEN * (MAX_FEE_TIERS - 1)]);
+        current.push(FeeMode::Dynamic as u8);
+        current.extend_from_slice(&dynamic_fee.fee_gamma.to_le_bytes());
+        current.extend_from_slice(&dynamic_fee.out_fee_numerator.to_le_bytes());
+        current.extend_from_slice(&rebalancing_rebate_bps.to_le_bytes());
+        current.extend_from_slice(&future_fees_deadline.to_le_bytes());
+        let mut future_fees_slice = [0u8; Fees::LEN];
+        future_fees.pack_into_slice(&mut future_fees_slice[..]);
+        current.extend_from_slice(&future_fees_slice[..FEES_V4_LEN]);
+        current.extend_from_slice(&(1u128 << 64).to_le_bytes());
+        current.extend_from_slice(&u128::MAX.to_le_bytes());
+        current.extend_from_slice(&7u64.to_le_bytes());
+        current.extend_from_slice(&[9u8; 32]);
+        current.extend_from_slice(&[10u8; 32]);
+        current.extend_from_slice(&50u64.to_le_bytes());
+        current.extend_from_slice(&11u64.to_le_bytes());
+        current.extend_from_slice(&12u64.to_le_bytes());
+        current.extend_from_slice(&13i64.to_le_bytes());
+        current.extend_from_slice(&14i64.to_le_bytes());
+        current.extend_from_slice(&15u64.to_le_bytes());
+        current.extend_from_slice(&[0u8; 16]); // host_fee_bps of fees and future_fees
+        current.extend_from_slice(&SWAP_INFO_MAGIC);
+        current.extend_from_slice(&[0u8; 8]); // checksum
+        current.extend_from_slice(&[0u8; 16]); // reserve_amount_a and reserve_amount_b
+        current.extend_from_slice(&[0u8; 8]); // withdraw_fee_grace_period
+        current.push(CpiPolicy::Any as u8);
+        current.extend_from_slice(&[0u8; 8]); // peg_rate
+        current.extend_from_slice(&[0u8; 2]); // quick_swap_max_bps
+        current.push(0); // extra flags
+        let checksum = SwapInfo::checksum(array_ref![current, 0, SwapInfo::LEN]);
+        current[SWAP_INFO_CHECKSUM_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8]
+            .copy_from_slice(&checksum);
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut packed).unwrap();
+        assert_eq!(packed.to_vec(), current);
+        let unpacked = SwapInfo::unpack(&current).unwrap();
+        assert_eq!(swap_info, unpacked);
+
+        // accounts of the previous version store whole coefficients, and are written
+        // back in the current version
+        let mut whole_amp = current.clone();
+        whole_amp[1] = SWAP_INFO_VERSION_WHOLE_AMP;
+        whole_amp[INITIAL_AMP_FACTOR_OFFSET..INITIAL_AMP_FACTOR_OFFSET + 8]
+            .copy_from_slice(&1u64.to_le_bytes());
+        whole_amp[TARGET_AMP_FACTOR_OFFSET..TARGET_AMP_FACTOR_OFFSET + 8]
+            .copy_from_slice(&1u64.to_le_bytes());
+        let checksum = SwapInfo::checksum(array_ref![whole_amp, 0, SwapInfo::LEN]);
+        whole_amp[SWAP_INFO_CHECKSUM_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8]
+            .copy_from_slice(&checksum);
+        let unpacked = SwapInfo::unpack(&whole_amp).unwrap();
+        assert_eq!(swap_info, unpacked);
+        SwapInfo::pack(unpacked, &mut whole_amp).unwrap();
+        assert_eq!(whole_amp, current);
+
+        // accounts in the legacy layout of the first deployed pools store each flag in
+        // its own byte, whole coefficients, and the fields up to the first layout of
+        // the fees
+        let legacy_swap_info = SwapInfo {
+            fees: Fees {
+                admin_trade_fee_numerator,
+                admin_trade_fee_denominator,
+                admin_withdraw_fee_numerator,
+                admin_withdraw_fee_denominator,
+                trade_fee_numerator,
+                trade_fee_denominator,
+                withdraw_fee_numerator,
+                withdraw_fee_denominator,
+                ..Fees::default()
+            },
+            future_fees_deadline: 0,
+            future_fees: Fees::default(),
+            donations_admin_only: false,
+            fee_growth_a: 0,
+            fee_growth_b: 0,
+            pool_token_supply: 0,
+            price_oracle_a: Pubkey::default(),
+            price_oracle_b: Pubkey::default(),
+            max_price_deviation_bps: 0,
+            initial_trade_fee_numerator: 0,
+            initial_withdraw_fee_numerator: 0,
+            start_fee_ramp_ts: 0,
+            stop_fee_ramp_ts: 0,
+            deposit_allowlist_enabled: false,
+            max_swap_bps_of_reserves: 0,
+            ..swap_info
+        };
+        let mut baseline = vec![];
+        baseline.push(1_u8); // is_initialized
+        baseline.push(0_u8); // is_paused
+        baseline.push(nonce);
+        baseline.extend_from_slice(&1u64.to_le_bytes());
+        baseline.extend_from_slice(&1u64.to_le_bytes());
+        baseline.extend_from_slice(&start_ramp_ts.to_le_bytes());
+        baseline.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+        baseline.extend_from_slice(&future_admin_deadline.to_le_bytes());
+        baseline.extend_from_slice(&future_admin_key_raw);
+        baseline.extend_from_slice(&admin_key_raw);
+        baseline.extend_from_slice(&token_a_raw);
+        baseline.extend_from_slice(&token_b_raw);
+        baseline.extend_from_slice(&pool_mint_raw);
+        baseline.extend_from_slice(&token_a_mint_raw);
+        baseline.extend_from_slice(&token_b_mint_raw);
+        baseline.extend_from_slice(&admin_fee_key_a_raw);
+        baseline.extend_from_slice(&admin_fee_key_b_raw);
+        baseline.extend_from_slice(&admin_trade_fee_numerator.to_le_bytes());
+        baseline.extend_from_slice(&admin_trade_fee_denominator.to_le_bytes());
+        baseline.extend_from_slice(&admin_withdraw_fee_numerator.to_le_bytes());
+        baseline.extend_from_slice(&admin_withdraw_fee_denominator.to_le_bytes());
+        baseline.extend_from_slice(&trade_fee_numerator.to_le_bytes());
+        baseline.extend_from_slice(&trade_fee_denominator.to_le_bytes());
+        baseline.extend_from_slice(&withdraw_fee_numerator.to_le_bytes());
+        baseline.extend_from_slice(&withdraw_fee_denominator.to_le_bytes());
+        assert_eq!(baseline.len(), SWAP_INFO_LEGACY_LEN);
+        assert_eq!(SwapInfo::unpack(&baseline).unwrap(), legacy_swap_info);
+
+        // legacy accounts are written back in the legacy layout
+        let mut legacy = [0u8; SWAP_INFO_LEGACY_LEN];
+        SwapInfo::pack(legacy_swap_info, &mut legacy).unwrap();
+        assert_eq!(legacy.to_vec(), baseline);
+        let paused = SwapInfo {
+            is_paused: true,
+            ..legacy_swap_info
+        };
+        SwapInfo::pack(paused, &mut legacy).unwrap();
+        assert_eq!(legacy[..2], [1, 1]);
+        assert_eq!(SwapInfo::unpack(&legacy).unwrap(), paused);
+
+        // which has no room for the fields added since, left unchanged
+        SwapInfo::pack(legacy_swap_info, &mut legacy).unwrap();
+        assert_eq!(
+            SwapInfo::pack(swap_info, &mut legacy),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        assert_eq!(legacy.to_vec(), baseline);
+        let committed = SwapInfo {
+            future_fees_deadline,
+            future_fees,
+            ..legacy_swap_info
+        };
+        assert_eq!(
+            SwapInfo::pack(committed, &mut legacy),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        // except the fields the current layout records, which are dropped
+        let recorded = SwapInfo {
+            fee_growth_a: 1 << 64,
+            pool_token_supply: 7,
+            initial_trade_fee_numerator: 11,
+            ..legacy_swap_info
+        };
+        SwapInfo::pack(recorded, &mut legacy).unwrap();
+        assert_eq!(legacy.to_vec(), baseline);
+
+        // coefficients between whole ones are rounded down in legacy accounts
+        let fractional = SwapInfo {
+            initial_amp_factor: AMP_PRECISION * 3 / 2,
+            ..swap_info
+        };
+        SwapInfo::pack(fractional, &mut packed).unwrap();
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), fractional);
+        let fractional = SwapInfo {
+            initial_amp_factor: AMP_PRECISION * 3 / 2,
+            ..legacy_swap_info
+        };
+        SwapInfo::pack(fractional, &mut legacy).unwrap();
+        assert_eq!(SwapInfo::unpack(&legacy).unwrap(), legacy_swap_info);
+
+        // host fees are stored after the other fields, legacy accounts have no room for them
+        let mut with_host_fees = swap_info;
+        with_host_fees.fees.host_fee_bps = 16;
+        with_host_fees.future_fees.host_fee_bps = 17;
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(with_host_fees, &mut packed).unwrap();
+        let host_fees_offset = SWAP_INFO_CHECKSUM_OFFSET - 4 - 16;
+        assert_eq!(packed[..host_fees_offset], current[..host_fees_offset]);
+        assert_eq!(
+            packed[host_fees_offset..host_fees_offset + 8],
+            16u64.to_le_bytes()
+        );
+        assert_eq!(
+            packed[host_fees_offset + 8..host_fees_offset + 16],
+            17u64.to_le_bytes()
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), with_host_fees);
+        let mut with_host_fees = legacy_swap_info;
+        with_host_fees.fees.host_fee_bps = 16;
+        assert_eq!(
+            SwapInfo::pack(with_host_fees, &mut legacy),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        // so are the reserve amounts, which follow the checksum
+        let with_reserve_amounts = SwapInfo {
+            reserve_amount_a: 18,
+            reserve_amount_b: 19,
+            ..swap_info
+        };
+        SwapInfo::pack(with_reserve_amounts, &mut packed).unwrap();
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET + 8..SWAP_INFO_CHECKSUM_OFFSET + 16],
+            18u64.to_le_bytes()
+        );
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET + 16..SWAP_INFO_CHECKSUM_OFFSET + 24],
+            19u64.to_le_bytes()
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), with_reserve_amounts);
+        let with_reserve_amounts = SwapInfo {
+            reserve_amount_a: 18,
+            reserve_amount_b: 19,
+            ..legacy_swap_info
+        };
+        SwapInfo::pack(with_reserve_amounts, &mut legacy).unwrap();
+        assert_eq!(SwapInfo::unpack(&legacy).unwrap(), legacy_swap_info);
+
+        // and the withdraw fee grace period, which follows them
+        let with_grace_period = SwapInfo {
+            withdraw_fee_grace_period: 20,
+            ..swap_info
+        };
+        SwapInfo::pack(with_grace_period, &mut packed).unwrap();
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET + 24..SWAP_INFO_CHECKSUM_OFFSET + 32],
+            20i64.to_le_bytes()
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), with_grace_period);
+        let with_grace_period = SwapInfo {
+            withdraw_fee_grace_period: 20,
+            ..legacy_swap_info
+        };
+        assert_eq!(
+            SwapInfo::pack(with_grace_period, &mut legacy),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        // then the CPI policy, whose unknown values are rejected
+        let top_level_only = SwapInfo {
+            cpi_policy: CpiPolicy::TopLevelOnly,
+            ..swap_info
+        };
+        SwapInfo::pack(top_level_only, &mut packed).unwrap();
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET + 32],
+            CpiPolicy::TopLevelOnly as u8
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), top_level_only);
+        let top_level_only = SwapInfo {
+            cpi_policy: CpiPolicy::TopLevelOnly,
+            ..legacy_swap_info
+        };
+        assert_eq!(
+            SwapInfo::pack(top_level_only, &mut legacy),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        packed[SWAP_INFO_CHECKSUM_OFFSET + 32] = 3;
+        let checksum = SwapInfo::checksum(&packed);
+        packed[SWAP_INFO_CHECKSUM_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8].copy_from_slice(&checksum);
+        assert_eq!(
+            SwapInfo::unpack(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // and the peg rate
+        let pegged = SwapInfo {
+            peg_rate: 1_050_000_000,
+            ..swap_info
+        };
+        SwapInfo::pack(pegged, &mut packed).unwrap();
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET + 33..SWAP_INFO_CHECKSUM_OFFSET + 41],
+            1_050_000_000u64.to_le_bytes()
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), pegged);
+
+        // and the quick swap size
+        let quick = SwapInfo {
+            quick_swap_max_bps: 5,
+            ..swap_info
+        };
+        SwapInfo::pack(quick, &mut packed).unwrap();
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET + 41..SWAP_INFO_CHECKSUM_OFFSET + 43],
+            5u16.to_le_bytes()
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), quick);
+
+        // and the extra flags
+        let guarded = SwapInfo {
+            same_slot_guard: true,
+            ..swap_info
+        };
+        SwapInfo::pack(guarded, &mut packed).unwrap();
+        assert_eq!(packed[SwapInfo::LEN - 1], EXTRA_FLAG_SAME_SLOT_GUARD);
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), guarded);
+        let admin_fees_as_lp = SwapInfo {
+            admin_fees_as_lp: true,
+            ..guarded
+        };
+        SwapInfo::pack(admin_fees_as_lp, &mut packed).unwrap();
+        assert_eq!(
+            packed[SwapInfo::LEN - 1],
+            EXTRA_FLAG_SAME_SLOT_GUARD | EXTRA_FLAG_ADMIN_FEES_AS_LP
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), admin_fees_as_lp);
+        let withdraw_one_while_paused = SwapInfo {
+            withdraw_one_while_paused: true,
+            ..admin_fees_as_lp
+        };
+        SwapInfo::pack(withdraw_one_while_paused, &mut packed).unwrap();
+        assert_eq!(
+            packed[SwapInfo::LEN - 1],
+            EXTRA_FLAG_SAME_SLOT_GUARD
+                | EXTRA_FLAG_ADMIN_FEES_AS_LP
+                | EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED
+        );
+        assert_eq!(
+            SwapInfo::unpack(&packed).unwrap(),
+            withdraw_one_while_paused
+        );
+        let quiet_logs = SwapInfo {
+            quiet_logs: true,
+            ..withdraw_one_while_paused
+        };
+        SwapInfo::pack(quiet_logs, &mut packed).unwrap();
+        assert_eq!(
+            packed[SwapInfo::LEN - 1],
+            EXTRA_FLAG_SAME_SLOT_GUARD
+                | EXTRA_FLAG_ADMIN_FEES_AS_LP
+                | EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED
+                | EXTRA_FLAG_QUIET_LOGS
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), quiet_logs);
+        assert!(SwapInfo::peek_quiet_logs(&packed));
+        assert!(!SwapInfo::peek_quiet_logs(&packed[..SWAP_INFO_LEGACY_LEN]));
+        let deposit_cap_enabled = SwapInfo {
+            deposit_cap_enabled: true,
+            ..quiet_logs
+        };
+        SwapInfo::pack(deposit_cap_enabled, &mut packed).unwrap();
+        assert_eq!(
+            packed[SwapInfo::LEN - 1],
+            EXTRA_FLAG_SAME_SLOT_GUARD
+                | EXTRA_FLAG_ADMIN_FEES_AS_LP
+                | EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED
+                | EXTRA_FLAG_QUIET_LOGS
+                | EXTRA_FLAG_DEPOSIT_CAP_ENABLED
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), deposit_cap_enabled);
+        let initial_supply_scaled = SwapInfo {
+            initial_supply_scaled: true,
+            ..deposit_cap_enabled
+        };
+        SwapInfo::pack(initial_supply_scaled, &mut packed).unwrap();
+        assert_eq!(
+            packed[SwapInfo::LEN - 1],
+            EXTRA_FLAG_SAME_SLOT_GUARD
+                | EXTRA_FLAG_ADMIN_FEES_AS_LP
+                | EXTRA_FLAG_WITHDRAW_ONE_WHILE_PAUSED
+                | EXTRA_FLAG_QUIET_LOGS
+                | EXTRA_FLAG_DEPOSIT_CAP_ENABLED
+                | EXTRA_FLAG_INITIAL_SUPPLY_SCALED
+        );
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), initial_supply_scaled);
+        // curve types this program does not know
+        packed[SwapInfo::LEN - 1] |= 1 << EXTRA_FLAGS_CURVE_TYPE_SHIFT;
+        let checksum = SwapInfo::checksum(&packed);
+        packed[SWAP_INFO_CHECKSUM_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8].copy_from_slice(&checksum);
+        assert_eq!(
+            SwapInfo::unpack(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            pegged.token_peg_rate(&pegged.token_b.reserves),
+            1_050_000_000
+        );
+        assert_eq!(
+            pegged.token_peg_rate(&pegged.token_a.reserves),
+            PEG_RATE_PRECISION
+        );
+        assert_eq!(
+            swap_info.token_peg_rate(&swap_info.token_b.reserves),
+            PEG_RATE_PRECISION
+        );
+        let pegged = SwapInfo {
+            peg_rate: 1_050_000_000,
+            ..legacy_swap_info
+        };
+        SwapInfo::pack(pegged, &mut legacy).unwrap();
+        assert_eq!(SwapInfo::unpack(&legacy).unwrap(), legacy_swap_info);
+
+        // deprecation is a flag of the current layout
+        let deprecated = SwapInfo {
+            is_deprecated: true,
+            ..swap_info
+        };
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(deprecated, &mut packed).unwrap();
+        assert_eq!(packed[0], 0b11_0101);
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), deprecated);
+
+        // so is the shared authority
+        let shared = SwapInfo {
+            shared_authority: true,
+            ..swap_info
+        };
+        SwapInfo::pack(shared, &mut packed).unwrap();
+        assert_eq!(packed[0], 0b101_0101);
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), shared);
+        assert_eq!(
+            shared.authority_seed(&Pubkey::new_from_array([1u8; 32])),
+            SHARED_AUTHORITY_SEED
+        );
+
+        // and the strict handling of zero amounts, the last flag bit
+        let strict = SwapInfo {
+            reject_zero_amounts: true,
+            ..swap_info
+        };
+        SwapInfo::pack(strict, &mut packed).unwrap();
+        assert_eq!(packed[0], 0b1001_0101);
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), strict);
+
+        // unknown versions are rejected; every flag bit is now assigned
+        let mut invalid = current.clone();
+        invalid[1] = SWAP_INFO_VERSION + 1;
+        assert_eq!(
+            SwapInfo::unpack(&invalid),
+            Err(ProgramError::InvalidAccountData)
+        );
+        let mut legacy = legacy.to_vec();
+        legacy[1] = 2;
+        assert_eq!(
+            SwapInfo::unpack(&legacy),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapInfo::unpack(&current[..SwapInfo::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_swap_info_checksum() {
+        let swap_info = SwapInfo {
+            is_initialized: true,
+            nonce: 255,
+            admin_key: Pubkey::new_from_array([1u8; 32]),
+            pool_mint: Pubkey::new_from_array([2u8; 32]),
+            ..SwapInfo::unpack_unchecked(&[0u8; SwapInfo::LEN]).unwrap()
+        };
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut packed).unwrap();
+        assert_eq!(
+            packed[SWAP_INFO_CHECKSUM_OFFSET - 4..SWAP_INFO_CHECKSUM_OFFSET],
+            SWAP_INFO_MAGIC
+        );
+
+        // a partially written or corrupted account
+        let mut corrupted = packed;
+        corrupted[2] ^= 1;
+        assert_eq!(
+            SwapInfo::unpack(&corrupted),
+            Err(SwapError::InvalidChecksum.into())
+        );
+        let mut corrupted = packed;
+        corrupted[SwapInfo::LEN - 1] = 1;
+        assert_eq!(
+            SwapInfo::unpack(&corrupted),
+            Err(SwapError::InvalidChecksum.into())
+        );
+
+        // garbage of the right size, even flagged as initialized
+        let mut garbage = [7u8; SwapInfo::LEN];
+        garbage[0] = 1;
+        garbage[1] = SWAP_INFO_VERSION;
+        assert_eq!(
+            SwapInfo::unpack(&garbage),
+            Err(ProgramError::InvalidAccountData)
+        );
+        let mut garbage = packed;
+        garbage[SWAP_INFO_CHECKSUM_OFFSET - 1] ^= 1;
+        assert_eq!(
+            SwapInfo::unpack(&garbage),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // never packed accounts are all zeros
+        let uninitialized = [0u8; SwapInfo::LEN];
+        assert_eq!(
+            SwapInfo::unpack(&uninitialized),
+            Err(ProgramError::UninitializedAccount)
+        );
+        assert!(
+            !SwapInfo::unpack_unchecked(&uninitialized)
+                .unwrap()
+                .is_initialized
+        );
+        let mut garbage = uninitialized;
+        garbage[100] = 1;
+        assert_eq!(
+            SwapInfo::unpack_unchecked(&garbage),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // accounts written before the checksum are read and written back with it
+        let mut without_checksum = packed;
+        without_checksum[1] = SWAP_INFO_VERSION_WITHOUT_CHECKSUM;
+        without_checksum[SWAP_INFO_CHECKSUM_OFFSET - 4..SWAP_INFO_CHECKSUM_OFFSET + 8]
+            .copy_from_slice(&[0u8; 12]);
+        let unpacked = SwapInfo::unpack(&without_checksum).unwrap();
+        assert_eq!(unpacked, swap_info);
+        SwapInfo::pack(unpacked, &mut without_checksum).unwrap();
+        assert_eq!(without_checksum, packed);
+    }
+
+    #[test]
+    fn test_legacy_swap_info_round_trip() {
+        // packed by the program of the first deployed pools, paused
+        let hex = include_str!("../fixtures/legacy_swap_info.txt").trim();
+        let deployed: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        assert_eq!(deployed.len(), SWAP_INFO_LEGACY_LEN);
+
+        let swap_info = SwapInfo::unpack(&deployed).unwrap();
+        assert!(swap_info.is_paused);
+        assert_eq!(swap_info.nonce, 254);
+        assert_eq!(swap_info.initial_amp_factor, 100 * AMP_PRECISION);
+        assert_eq!(swap_info.target_amp_factor, 200 * AMP_PRECISION);
+        assert_eq!(swap_info.stop_ramp_ts, 1_600_086_400);
+        assert_eq!(swap_info.admin_key, Pubkey::new_from_array([2u8; 32]));
+        assert_eq!(
+            swap_info.token_b.admin_fees,
+            Pubkey::new_from_array([9u8; 32])
+        );
+        assert_eq!(swap_info.fees.trade_fee_numerator, 4);
+        assert_eq!(swap_info.fees.withdraw_fee_denominator, 10_000);
+        assert_eq!(swap_info.fees.trade_fee_tiers, NO_FEE_TIERS);
+        assert_eq!(swap_info.future_fees, Fees::default());
+        let swap_info_ref = SwapInfoRef::new(&deployed).unwrap();
+        assert!(swap_info_ref.is_legacy());
+        assert!(swap_info_ref.is_paused());
+        assert_eq!(swap_info_ref.target_amp_factor(), 200 * AMP_PRECISION);
+
+        // written back byte for byte
+        let mut packed = deployed.clone();
+        SwapInfo::pack(swap_info, &mut packed).unwrap();
+        assert_eq!(packed, deployed);
+
+        // and after an update, e.g. unpausing
+        let unpaused = SwapInfo {
+            is_paused: false,
+            ..swap_info
+        };
+        SwapInfo::pack(unpaused, &mut packed).unwrap();
+        assert_eq!(packed[1], 0);
+        assert_eq!(packed[2..], deployed[2..]);
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), unpaused);
+        SwapInfo::pack(swap_info, &mut packed).unwrap();
+        assert_eq!(packed, deployed);
+
+        // the same pool in the current layout
+        let mut current = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut current).unwrap();
+        assert_eq!(SwapInfo::unpack(&current).unwrap(), swap_info);
+    }
+
+    #[test]
+    fn test_swap_info_ref() {
+        let mut swap_info = SwapInfo::unpack_unchecked(&[0u8; SwapInfo::LEN]).unwrap();
+        swap_info.is_initialized = true;
+        swap_info.is_paused = true;
+        swap_info.nonce = 7;
+        swap_info.initial_amp_factor = 100;
+        swap_info.target_amp_factor = 200;
+        swap_info.start_ramp_ts = 300;
+        swap_info.stop_ramp_ts = 400;
+        swap_info.admin_key = Pubkey::new_from_array([1u8; 32]);
+        swap_info.fee_growth_a = 500;
+        swap_info.fee_growth_b = u128::MAX;
+        swap_info.pool_token_supply = 1_024;
+        swap_info.reserve_amount_a = 2_000;
+        swap_info.reserve_amount_b = 3_000;
+        swap_info.peg_rate = 1_100_000_000;
+
+        for len in [SwapInfo::LEN, SWAP_INFO_LEGACY_LEN] {
+            let mut packed = vec![0u8; len];
+            SwapInfo::pack(swap_info, &mut packed).unwrap();
+            let swap_info_ref = SwapInfoRef::new(&packed).unwrap();
+            assert!(swap_info_ref.is_paused());
+            assert!(!swap_info_ref.is_deprecated());
+            assert!(!swap_info_ref.reject_zero_amounts());
+            assert_eq!(swap_info_ref.nonce(), 7);
+            assert_eq!(swap_info_ref.initial_amp_factor(), 100);
+            assert_eq!(swap_info_ref.target_amp_factor(), 200);
+            assert_eq!(swap_info_ref.start_ramp_ts(), 300);
+            assert_eq!(swap_info_ref.stop_ramp_ts(), 400);
+            assert_eq!(swap_info_ref.admin_key(), swap_info.admin_key);
+            // legacy accounts have no room for the fee state nor the reserve amounts
+            let is_legacy = len == SWAP_INFO_LEGACY_LEN;
+            assert_eq!(swap_info_ref.is_legacy(), is_legacy);
+            assert_eq!(
+                swap_info_ref.fee_growth_a(),
+                if is_legacy { 0 } else { 500 }
+            );
+            assert_eq!(
+                swap_info_ref.fee_growth_b(),
+                if is_legacy { 0 } else { u128::MAX }
+            );
+            assert_eq!(
+                swap_info_ref.pool_token_supply(),
+                if is_legacy { 0 } else { 1_024 }
+            );
+            assert_eq!(
+                swap_info_ref.reserve_amount_a(),
+                if is_legacy { 0 } else { 2_000 }
+            );
+            assert_eq!(
+                swap_info_ref.reserve_amount_b(),
+                if is_legacy { 0 } else { 3_000 }
+            );
+            assert_eq!(
+                swap_info_ref.peg_rate(),
+                if is_legacy { 0 } else { 1_100_000_000 }
+            );
+
+            // writes in place match packing the updated struct
+            {
+                let mut swap_info_ref = SwapInfoRefMut::new(&mut packed).unwrap();
+                swap_info_ref.set_initial_amp_factor(200);
+                swap_info_ref.set_ramp_ts(0, 0);
+                swap_info_ref.record_lp_fees(128, 16);
+                swap_info_ref.set_pool_token_supply(2_048);
+                swap_info_ref.set_reserve_amounts(4_000, 5_000);
+                swap_info_ref.set_peg_rate(1_200_000_000);
+            }
+            let mut expected = swap_info;
+            expected.initial_amp_factor = 200;
+            expected.start_ramp_ts = 0;
+            expected.stop_ramp_ts = 0;
+            if is_legacy {
+                expected.fee_growth_a = 0;
+                expected.fee_growth_b = 0;
+                expected.pool_token_supply = 0;
+            } else {
+                expected.record_lp_fees(128, 16);
+                expected.pool_token_supply = 2_048;
+            }
+            (expected.reserve_amount_a, expected.reserve_amount_b) =
+                if is_legacy { (0, 0) } else { (4_000, 5_000) };
+            expected.peg_rate = if is_legacy { 0 } else { 1_200_000_000 };
+            let mut expected_packed = vec![0u8; len];
+            SwapInfo::pack(expected, &mut expected_packed).unwrap();
+            assert_eq!(packed, expected_packed);
+            assert_eq!(SwapInfo::unpack(&packed).unwrap(), expected);
+        }
+
+        // accounts of the version without checksum are written as such
+        let mut packed = vec![0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut packed).unwrap();
+        packed[1] = SWAP_INFO_VERSION_WITHOUT_CHECKSUM;
+        packed[SWAP_INFO_MAGIC_OFFSET..SWAP_INFO_CHECKSUM_OFFSET + 8].copy_from_slice(&[0u8; 12]);
+        SwapInfoRefMut::new(&mut packed)
+            .unwrap()
+            .set_pool_token_supply(2_048);
+        assert_eq!(packed[1], SWAP_INFO_VERSION_WITHOUT_CHECKSUM);
+        assert_eq!(SwapInfo::unpack(&packed).unwrap().pool_token_supply, 2_048);
+
+        // accounts rejected by unpack are rejected
+        let mut garbage = vec![0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info, &mut garbage).unwrap();
+        garbage[NONCE_OFFSET] ^= 1;
+        assert_eq!(
+            SwapInfoRef::new(&garbage).unwrap_err(),
+            SwapError::InvalidChecksum.into()
+        );
+        assert_eq!(
+            SwapInfoRef::new(&[0u8; SwapInfo::LEN]).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+        assert_eq!(
+            SwapInfoRef::new(&[0u8; SwapInfo::LEN - 1]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        let mut legacy = vec![0u8; SWAP_INFO_LEGACY_LEN];
+        SwapInfo::pack(swap_info, &mut legacy).unwrap();
+        legacy[LEGACY_FLAG_OFFSETS[1].1] = 2;
+        assert_eq!(
+            SwapInfoRef::new(&legacy).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_admin_multisig_packing() {
+        let swap_raw = [1u8; 32];
+        let threshold = 2;
+        let num_signers = 3;
+        let mut signers = [Pubkey::default(); MAX_ADMIN_SIGNERS];
+        for (i, signer) in signers.iter_mut().take(num_signers).enumerate() {
+            *signer = Pubkey::new_from_array([i as u8 + 2; 32]);
+        }
+        let multisig = AdminMultisig {
+            is_initialized: true,
+            swap: Pubkey::new_from_array(swap_raw),
+            threshold,
+            num_signers: num_signers as u8,
+            signers,
+        };
+        assert_eq!(multisig.signers(), &signers[..num_signers]);
+
+        let mut packed = [0u8; AdminMultisig::LEN];
+        AdminMultisig::pack(multisig, &mut packed).unwrap();
+        let unpacked = AdminMultisig::unpack(&packed).unwrap();
+        assert_eq!(multisig, unpacked);
+
+        let mut packed = vec![];
+        packed.push(1_u8); // is_initialized
+        packed.extend_from_slice(&swap_raw);
+        packed.push(threshold);
+        packed.push(num_signers as u8);
+        for signer in signers.iter() {
+            packed.extend_from_slice(signer.as_ref());
+        }
+        let unpacked = AdminMultisig::unpack(&packed).unwrap();
+        assert_eq!(multisig, unpacked);
+    }
+
+    #[test]
+    fn test_fee_exemptions_packing() {
+        let swap_raw = [1u8; 32];
+        let num_accounts = 2;
+        let mut accounts = [Pubkey::default(); MAX_FEE_EXEMPT_ACCOUNTS];
+        for (i, account) in accounts.iter_mut().take(num_accounts).enumerate() {
+            *account = Pubkey::new_from_array([i as u8 + 2; 32]);
+        }
+        let exemptions = FeeExemptions {
+            is_initialized: true,
+            swap: Pubkey::new_from_array(swap_raw),
+            num_accounts: num_accounts as u8,
+            accounts,
+        };
+        assert_eq!(exemptions.accounts(), &accounts[..num_accounts]);
+        assert!(exemptions.is_exempt(&accounts[1]));
+        assert!(!exemptions.is_exempt(&Pubkey::default()));
+
+        let mut packed = [0u8; FeeExemptions::LEN];
+        FeeExemptions::pack(exemptions, &mut packed).unwrap();
+        let unpacked = FeeExemptions::unpack(&packed).unwrap();
+        assert_eq!(exemptions, unpacked);
+
+        let mut packed = vec![];
+        packed.push(1_u8); // is_initialized
+        packed.extend_from_slice(&swap_raw);
+        packed.push(num_accounts as u8);
+        for account in accounts.iter() {
+            packed.extend_from_slice(account.as_ref());
+        }
+        let unpacked = FeeExemptions::unpack(&packed).unwrap();
+        assert_eq!(exemptions, unpacked);
+    }
+
+    #[test]
+    fn test_deposit_allowlist_packing() {
+        let swap_raw = [1u8; 32];
+        let mut accounts = [Pubkey::default(); MAX_DEPOSIT_ALLOWLIST_ACCOUNTS];
+        accounts[0] = Pubkey::new_from_array([2u8; 32]);
+        let allowlist = DepositAllowlist {
+            is_initialized: true,
+            swap: Pubkey::new_from_array(swap_raw),
+            num_accounts: 1,
+            accounts,
+        };
+        assert!(allowlist.is_allowed(&accounts[0]));
+        assert!(!allowlist.is_allowed(&Pubkey::default()));
+
+        let mut packed = vec![];
+        packed.push(1_u8); // is_initialized
+        packed.extend_from_slice(&swap_raw);
+        packed.push(1_u8); // num_accounts
+        for account in accounts.iter() {
+            packed.extend_from_slice(account.as_ref());
+        }
+        assert_eq!(packed.len(), DepositAllowlist::LEN);
+        let unpacked = DepositAllowlist::unpack(&packed).unwrap();
+        assert_eq!(allowlist, unpacked);
+
+        let mut repacked = [0u8; DepositAllowlist::LEN];
+        DepositAllowlist::pack(allowlist, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+    }
+
+    #[test]
+    fn test_cpi_allowlist_packing() {
+        let swap_raw = [1u8; 32];
+        let mut programs = [Pubkey::default(); MAX_CPI_ALLOWLIST_PROGRAMS];
+        programs[0] = Pubkey::new_from_array([2u8; 32]);
+        let allowlist = CpiAllowlist {
+            is_initialized: true,
+            swap: Pubkey::new_from_array(swap_raw),
+            num_programs: 1,
+            programs,
+        };
+        assert!(allowlist.is_allowed(&programs[0]));
+        assert!(!allowlist.is_allowed(&Pubkey::default()));
+
+        let mut packed = vec![];
+        packed.push(1_u8); // is_initialized
+        packed.extend_from_slice(&swap_raw);
+        packed.push(1_u8); // num_programs
+        for program in programs.iter() {
+            packed.extend_from_slice(program.as_ref());
+        }
+        assert_eq!(packed.len(), CpiAllowlist::LEN);
+        let unpacked = CpiAllowlist::unpack(&packed).unwrap();
+        assert_eq!(allowlist, unpacked);
+
+        let mut repacked = [0u8; CpiAllowlist::LEN];
+        CpiAllowlist::pack(allowlist, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+    }
+
+    #[test]
+    fn test_peg_config_packing() {
+        let config = PegConfig {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            rate_source: PegRateSource::StakePool,
+            rate_account: Pubkey::new_from_array([2u8; 32]),
+            rate_updated_slot: 42,
+        };
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.push(PegRateSource::StakePool as u8);
+        packed.extend_from_slice(&[2u8; 32]);
+        packed.extend_from_slice(&42u64.to_le_bytes());
+        assert_eq!(packed.len(), PegConfig::LEN);
+        assert_eq!(PegConfig::unpack(&packed).unwrap(), config);
+
+        let mut repacked = [0u8; PegConfig::LEN];
+        PegConfig::pack(config, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+
+        packed[33] = 3;
+        assert_eq!(
+            PegConfig::unpack(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_pool_migration_packing() {
+        let migration = PoolMigration {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            new_swap: Pubkey::new_from_array([2u8; 32]),
+            lp_escrow: Pubkey::new_from_array([3u8; 32]),
+        };
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&[2u8; 32]);
+        packed.extend_from_slice(&[3u8; 32]);
+        assert_eq!(packed.len(), PoolMigration::LEN);
+        let unpacked = PoolMigration::unpack(&packed).unwrap();
+        assert_eq!(migration, unpacked);
+
+        let mut repacked = [0u8; PoolMigration::LEN];
+        PoolMigration::pack(migration, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+    }
+
+    #[test]
+    fn test_pool_registry_entry_packing() {
+        let mut name = [0u8; MAX_POOL_NAME_LEN];
+        name[..9].copy_from_slice(b"USDC-USDT");
+        let entry = PoolRegistryEntry {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            name_len: 9,
+            name,
+            category: PoolCategory::Pegged,
+            is_deprecated: true,
+        };
+        assert_eq!(entry.name(), Some("USDC-USDT"));
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.push(9);
+        packed.extend_from_slice(&name);
+        packed.push(1); // category
+        packed.push(1); // is_deprecated
+        assert_eq!(packed.len(), PoolRegistryEntry::LEN);
+        let unpacked = PoolRegistryEntry::unpack(&packed).unwrap();
+        assert_eq!(entry, unpacked);
+
+        let mut repacked = [0u8; PoolRegistryEntry::LEN];
+        PoolRegistryEntry::pack(entry, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+
+        packed[66] = 2;
+        assert_eq!(
+            PoolRegistryEntry::unpack(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_rewards_distributor_packing() {
+        let rewards_distributor = RewardsDistributor {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            program: Pubkey::new_from_array([2u8; 32]),
+            rewards_a: Pubkey::new_from_array([3u8; 32]),
+            rewards_b: Pubkey::new_from_array([4u8; 32]),
+        };
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&[2u8; 32]);
+        packed.extend_from_slice(&[3u8; 32]);
+        packed.extend_from_slice(&[4u8; 32]);
+        assert_eq!(packed.len(), RewardsDistributor::LEN);
+        let unpacked = RewardsDistributor::unpack(&packed).unwrap();
+        assert_eq!(rewards_distributor, unpacked);
+
+        let mut repacked = [0u8; RewardsDistributor::LEN];
+        RewardsDistributor::pack(rewards_distributor, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+    }
+
+    #[test]
+    fn test_staking_config_packing() {
+        let staking_config = StakingConfig {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            program: Pubkey::new_from_array([2u8; 32]),
+            staking_pool: Pubkey::new_from_array([3u8; 32]),
+            stake_instruction_prefix: [4u8; 8],
+        };
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&[2u8; 32]);
+        packed.extend_from_slice(&[3u8; 32]);
+        packed.extend_from_slice(&[4u8; 8]);
+        assert_eq!(packed.len(), StakingConfig::LEN);
+        let unpacked = StakingConfig::unpack(&packed).unwrap();
+        assert_eq!(staking_config, unpacked);
+
+        let mut repacked = [0u8; StakingConfig::LEN];
+        StakingConfig::pack(staking_config, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+    }
+
+    #[test]
+    fn test_deposit_caps_packing() {
+        let mut exempt_owners = [Pubkey::default(); MAX_DEPOSIT_CAP_EXEMPT_OWNERS];
+        exempt_owners[0] = Pubkey::new_from_array([4u8; 32]);
+        let deposit_caps = DepositCaps {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            max_lp_supply: 2,
+            max_lp_per_owner: 3,
+            num_exempt_owners: 1,
+            exempt_owners,
+        };
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&2u64.to_le_bytes());
+        packed.extend_from_slice(&3u64.to_le_bytes());
+        packed.push(1);
+        packed.extend_from_slice(&[4u8; 32]);
+        packed.extend_from_slice(&[0u8; 32 * (MAX_DEPOSIT_CAP_EXEMPT_OWNERS - 1)]);
+        assert_eq!(packed.len(), DepositCaps::LEN);
+        let unpacked = DepositCaps::unpack(&packed).unwrap();
+        assert_eq!(deposit_caps, unpacked);
+
+        let mut repacked = [0u8; DepositCaps::LEN];
+        DepositCaps::pack(deposit_caps, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+
+        assert!(deposit_caps.allows_lp_supply(2));
+        assert!(!deposit_caps.allows_lp_supply(3));
+        assert!(deposit_caps.allows_owner_balance(&Pubkey::default(), 3));
+        assert!(!deposit_caps.allows_owner_balance(&Pubkey::default(), 4));
+        // the exempt owner, but not the unused slots
+        assert!(deposit_caps.allows_owner_balance(&exempt_owners[0], 4));
+        assert_eq!(deposit_caps.exempt_owners(), &exempt_owners[..1]);
+        let uncapped = DepositCaps {
+            max_lp_supply: 0,
+            max_lp_per_owner: 0,
+            ..deposit_caps
+        };
+        assert!(uncapped.allows_lp_supply(u64::MAX));
+        assert!(uncapped.allows_owner_balance(&Pubkey::default(), u64::MAX));
+        assert!(!uncapped.is_capped());
+    }
+
+    #[test]
+    fn test_order_packing() {
+        let order = Order {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            owner: Pubkey::new_from_array([2u8; 32]),
+            order_id: 3,
+            bump_seed: 4,
+            escrow: Pubkey::new_from_array([5u8; 32]),
+            destination: Pubkey::new_from_array([6u8; 32]),
+            amount_in: 7,
+            minimum_amount_out: 8,
+        };
+
+        let mut packed = vec![1_u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&[2u8; 32]);
+        packed.extend_from_slice(&3u64.to_le_bytes());
+        packed.push(4);
+        packed.extend_from_slice(&[5u8; 32]);
+        packed.extend_from_slice(&[6u8; 32]);
+        packed.extend_from_slice(&7u64.to_le_bytes());
+        packed.extend_from_slice(&8u64.to_le_bytes());
+        assert_eq!(packed.len(), Order::LEN);
+        let unpacked = Order::unpack(&packed).unwrap();
+        assert_eq!(order, unpacked);
+
+        let mut repacked = [0u8; Order::LEN];
+        Order::pack(order, &mut repacked).unwrap();
+        assert_eq!(repacked.to_vec(), packed);
+    }
+
+    #[test]
+    fn test_pool_stats_packing() {
+        let pool_stats = PoolStats {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            token_a_volume: 2,
+            token_b_volume: 3,
+            token_a_fees: 4,
+            token_b_fees: 5,
+            swap_count: 6,
+            last_activity_slot: 7,
+            token_a_trade_admin_fees: 8,
+            token_b_trade_admin_fees: 9,
+            token_a_withdraw_admin_fees: 10,
+            token_b_withdraw_admin_fees: 11,
+        };
+
+        let mut packed = [0u8; PoolStats::LEN];
+        PoolStats::pack(pool_stats, &mut packed).unwrap();
+        let unpacked = PoolStats::unpack(&packed).unwrap();
+        assert_eq!(pool_stats, unpacked);
+
+        let mut packed = vec![1u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&2u128.to_le_bytes());
+        packed.extend_from_slice(&3u128.to_le_bytes());
+        packed.extend_from_slice(&4u128.to_le_bytes());
+        packed.extend_from_slice(&5u128.to_le_bytes());
+        packed.extend_from_slice(&6u64.to_le_bytes());
+        packed.extend_from_slice(&7u64.to_le_bytes());
+        let legacy = packed.clone();
+        packed.extend_from_slice(&8u128.to_le_bytes());
+        packed.extend_from_slice(&9u128.to_le_bytes());
+        packed.extend_from_slice(&10u128.to_le_bytes());
+        packed.extend_from_slice(&11u128.to_le_bytes());
+        let unpacked = PoolStats::unpack(&packed).unwrap();
+        assert_eq!(pool_stats, unpacked);
+
+        // legacy accounts do not track admin fees by source
+        assert_eq!(legacy.len(), POOL_STATS_LEGACY_LEN);
+        let legacy_stats = PoolStats {
+            token_a_trade_admin_fees: 0,
+            token_b_trade_admin_fees: 0,
+            token_a_withdraw_admin_fees: 0,
+            token_b_withdraw_admin_fees: 0,
+            ..pool_stats
+        };
+        assert_eq!(PoolStats::unpack(&legacy).unwrap(), legacy_stats);
+        let mut repacked = vec![0u8; POOL_STATS_LEGACY_LEN];
+        PoolStats::pack(pool_stats, &mut repacked).unwrap();
+        assert_eq!(repacked, legacy);
+        assert!(PoolStats::is_valid_len(PoolStats::LEN));
+        assert!(PoolStats::is_valid_len(POOL_STATS_LEGACY_LEN));
+        assert!(!PoolStats::is_valid_len(PoolStats::LEN - 1));
+    }
+
+    #[test]
+    fn test_last_trade_packing() {
+        let mut trades = [SlotTrade::default(); MAX_SLOT_TRADERS];
+        trades[0] = SlotTrade {
+            trader: Pubkey::new_from_array([3u8; 32]),
+            a_to_b: true,
+        };
+        let mut last_trade = LastTrade {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            slot: 2,
+            num_trades: 1,
+            trades,
+        };
+
+        let mut packed = [0u8; LastTrade::LEN];
+        LastTrade::pack(last_trade, &mut packed).unwrap();
+        let unpacked = LastTrade::unpack(&packed).unwrap();
+        assert_eq!(last_trade, unpacked);
+
+        let mut packed = vec![1u8];
+        packed.extend_from_slice(&[1u8; 32]);
+        packed.extend_from_slice(&2u64.to_le_bytes());
+        packed.push(1);
+        packed.extend_from_slice(&[3u8; 32]);
+        packed.push(1);
+        packed.extend_from_slice(&[0u8; 33 * (MAX_SLOT_TRADERS - 1)]);
+        let unpacked = LastTrade::unpack(&packed).unwrap();
+        assert_eq!(last_trade, unpacked);
+
+        // only the same trader swapping back in the same slot reverses the trade
+        let trader = Pubkey::new_from_array([3u8; 32]);
+        let other = Pubkey::new_from_array([4u8; 32]);
+        assert!(last_trade.is_reversed_by(2, &trader, false));
+        assert!(!last_trade.is_reversed_by(2, &trader, true));
+        assert!(!last_trade.is_reversed_by(3, &trader, false));
+        assert!(!last_trade.is_reversed_by(2, &other, false));
+
+        // the trades of others keep the trade of the trader
+        assert!(last_trade.record(2, &other, false));
+        assert_eq!(last_trade.trades().len(), 2);
+        assert!(last_trade.is_reversed_by(2, &trader, false));
+        assert!(last_trade.is_reversed_by(2, &other, true));
+
+        // a new slot clears the trades
+        assert!(last_trade.record(3, &other, true));
+        assert_eq!(last_trade.trades().len(), 1);
+        assert!(!last_trade.is_reversed_by(3, &trader, false));
+
+        for i in 1..MAX_SLOT_TRADERS {
+            assert!(last_trade.record(3, &Pubkey::new_from_array([i as u8 + 4; 32]), true));
+        }
+        assert!(last_trade.record(3, &other, true));
+        assert!(!last_trade.record(3, &trader, true));
+    }
+
+    #[test]
+    fn test_global_state_packing() {
+        let global_state = GlobalState {
+            is_initialized: true,
+            emergency_authority: Pubkey::new_from_array([1u8; 32]),
+            is_paused: true,
+        };
+
+        let mut packed = [0u8; GlobalState::LEN];
+        GlobalState::pack(global_state, &mut packed).unwrap();
+        let mut expect = vec![1u8];
+        expect.extend_from_slice(&[1u8; 32]);
+        expect.push(1);
+        assert_eq!(packed.to_vec(), expect);
+        let unpacked = GlobalState::unpack(&packed).unwrap();
+        assert_eq!(global_state, unpacked);
+
+        let packed = [0u8; GlobalState::LEN];
+        assert_eq!(
+            GlobalState::unpack(&packed),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_lp_position_packing() {
+        let position = LpPosition {
+            is_initialized: true,
+            swap: Pubkey::new_from_array([1u8; 32]),
+            owner: Pubkey::new_from_array([2u8; 32]),
+            pool_token_amount: 3,
+            fee_growth_a_checkpoint: 4,
+            fee_growth_b_checkpoint: 5,
+            fees_earned_a: 6,
+            fees_earned_b: 7,
+            deposit_ts: 8,
+        };
+
+        let mut packed = [0u8; LpPosition::LEN];
+        LpPosition::pack(position, &mut packed).unwrap();
+        let mut expect = vec![1u8];
+        expect.extend_from_slice(&[1u8; 32]);
+        expect.extend_from_slice(&[2u8; 32]);
+        expect.extend_from_slice(&3u64.to_le_bytes());
+        expect.extend_from_slice(&4u128.to_le_bytes());
+        expect.extend_from_slice(&5u128.to_le_bytes());
+        expect.extend_from_slice(&6u64.to_le_bytes());
+        expect.extend_from_slice(&7u64.to_le_bytes());
+        expect.extend_from_slice(&8i64.to_le_bytes());
+        assert_eq!(packed.to_vec(), expect);
+        assert_eq!(LpPosition::unpack(&packed).unwrap(), position);
+    }
+
+    #[test]
+    fn test_lp_position_deposit_ts() {
+        let mut position = LpPosition::default();
+
+        // the first pool tokens set the deposit timestamp
+        position.update_deposit_ts(100, 1_000);
+        position.pool_token_amount = 100;
+        assert_eq!(position.deposit_ts, 1_000);
+
+        // added pool tokens are averaged in
+        position.update_deposit_ts(400, 2_000);
+        position.pool_token_amount = 400;
+        assert_eq!(position.deposit_ts, 1_750);
+
+        // withdrawals keep it
+        position.update_deposit_ts(200, 3_000);
+        position.pool_token_amount = 200;
+        assert_eq!(position.deposit_ts, 1_750);
+
+        // the waiver needs a grace period, an old enough position and enough pool tokens
+        assert!(!position.is_past_withdraw_fee_grace_period(0, 200, 3_000));
+        assert!(!position.is_past_withdraw_fee_grace_period(1_251, 200, 3_000));
+        assert!(position.is_past_withdraw_fee_grace_period(1_250, 200, 3_000));
+        assert!(!position.is_past_withdraw_fee_grace_period(1_250, 201, 3_000));
+
+        // an emptied position starts over
+        position.update_deposit_ts(0, 4_000);
+        position.pool_token_amount = 0;
+        position.update_deposit_ts(50, 5_000);
+        assert_eq!(position.deposit_ts, 5_000);
+    }
+
+    #[test]
+    fn test_lp_position_checkpoint() {
+        let mut token_swap = SwapInfo::unpack_unchecked(&[0u8; SwapInfo::LEN]).unwrap();
+        token_swap.pool_token_supply = 1_024;
+
+        // no pool tokens, no fees
+        let mut position = LpPosition::default();
+        token_swap.record_lp_fees(128, 16);
+        position.checkpoint(&token_swap, 256);
+        assert_eq!((position.fees_earned_a, position.fees_earned_b), (0, 0));
+
+        // a quarter of the supply earns a quarter of the fees
+        token_swap.record_lp_fees(128, 16);
+        position.checkpoint(&token_swap, 256);
+        assert_eq!((position.fees_earned_a, position.fees_earned_b), (32, 4));
+
+        // fee growth wraps around
+        token_swap.fee_growth_a = u128::MAX;
+        position.fee_growth_a_checkpoint = u128::MAX;
+        token_swap.record_lp_fees(1_024, 0);
+        position.checkpoint(&token_swap, 0);
+        assert_eq!(position.fees_earned_a, 32 + 256);
+
+        // no supply, no growth
+        token_swap.pool_token_supply = 0;
+        token_swap.record_lp_fees(1_000, 1_000);
+        assert_eq!(token_swap.fee_growth_b, position.fee_growth_b_checkpoint);
+    }
+
+    #[test]
+    fn test_current_fees() {
+        let mut token_swap = SwapInfo::unpack_unchecked(&[0u8; SwapInfo::LEN]).unwrap();
+        token_swap.fees.trade_fee_numerator = 10;
+        token_swap.fees.withdraw_fee_numerator = 50;
+        token_swap.initial_trade_fee_numerator = 30;
+        token_swap.initial_withdraw_fee_numerator = 10;
+        token_swap.start_fee_ramp_ts = 1_000;
+        token_swap.stop_fee_ramp_ts = 1_100;
+
+        let fees = token_swap.current_fees(1_000).unwrap();
+        assert_eq!(
+            (fees.trade_fee_numerator, fees.withdraw_fee_numerator),
+            (30, 10)
+        );
+        let fees = token_swap.current_fees(1_025).unwrap();
+        assert_eq!(
+            (fees.trade_fee_numerator, fees.withdraw_fee_numerator),
+            (25, 20)
+        );
+        let fees = token_swap.current_fees(1_099).unwrap();
+        assert_eq!(
+            (fees.trade_fee_numerator, fees.withdraw_fee_numerator),
+            (11, 49)
+        );
+        assert_eq!(token_swap.current_fees(1_100).unwrap(), token_swap.fees);
+
+        // no ramp
+        token_swap.stop_fee_ramp_ts = 0;
+        assert_eq!(token_swap.current_fees(1_050).unwrap(), token_swap.fees);
+    }
+
+    #[test]
+    fn test_swap_info_tokens() {
+        let mut token_swap = SwapInfo::unpack_unchecked(&[0u8; SwapInfo::LEN]).unwrap();
+        token_swap.token_a.reserves = Pubkey::new_unique();
+        token_swap.token_b.reserves = Pubkey::new_unique();
+        // indices are not stored, unpacking restores them
+        token_swap.token_a.index = 1;
+        token_swap.token_b.index = 7;
+        let mut packed = [0u8; SwapInfo::LEN];
+        token_swap.pack_into_slice(&mut packed);
+        let token_swap = SwapInfo::unpack_unchecked(&packed).unwrap();
+        assert_eq!(token_swap.token_a.index, 0);
+        assert_eq!(token_swap.token_b.index, 1);
+
+        for index in 0..2 {
+            let token = token_swap.token(index).unwrap();
+            assert_eq!(token.index, index);
+            assert_eq!(token_swap.token_of_reserves(&token.reserves), Some(token));
+        }
+        assert_eq!(token_swap.token(2), None);
+        assert_eq!(token_swap.token_of_reserves(&Pubkey::new_unique()), None);
+    }
+
+    #[test]
+    fn test_pool_stats_record() {
+        let mut pool_stats = PoolStats::default();
+        pool_stats.record_swap(true, 100, 99, 1, 0);
+        pool_stats.record_swap(false, 50, 49, 2, 1);
+        pool_stats.record_fees(3, 4);
+        pool_stats.record_withdraw_admin_fees(1, 2);
+        assert_eq!(pool_stats.token_a_volume, 149);
+        assert_eq!(pool_stats.token_b_volume, 149);
+        assert_eq!(pool_stats.token_a_fees, 5);
+        assert_eq!(pool_stats.token_b_fees, 5);
+        assert_eq!(pool_stats.swap_count, 2);
+        // admin fees are paid in the output token
+        assert_eq!(pool_stats.token_a_trade_admin_fees, 1);
+        assert_eq!(pool_stats.token_b_trade_admin_fees, 0);
+        assert_eq!(pool_stats.token_a_withdraw_admin_fees, 1);
+        assert_eq!(pool_stats.token_b_withdraw_admin_fees, 2);
+
+        pool_stats.token_a_volume = u128::MAX;
+        pool_stats.swap_count = u64::MAX;
+        pool_stats.token_b_trade_admin_fees = u128::MAX;
+        pool_stats.record_swap(true, 1, 1, 0, 1);
+        assert_eq!(pool_stats.token_b_trade_admin_fees, u128::MAX);
+        assert_eq!(pool_stats.token_a_volume, u128::MAX);
+        assert_eq!(pool_stats.swap_count, u64::MAX);
     }
 }
@@ -0,0 +1,422 @@
+//! Pool fixture shared by the integration tests and the compute unit benches
+
+#![allow(clippy::unwrap_used, dead_code)]
+
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+use stable_swap::{
+    fees::Fees,
+    instruction,
+    processor::Processor,
+    state::{find_global_state_address, GlobalState, SwapInfo},
+};
+
+/// Compute units any single instruction of the program must fit in
+pub const MAX_COMPUTE_UNITS: u64 = 200_000;
+
+pub const AMP_FACTOR: u64 = 100;
+pub const INITIAL_TOKEN_A_AMOUNT: u64 = 1_000_000_000;
+pub const INITIAL_TOKEN_B_AMOUNT: u64 = 1_000_000_000;
+pub const USER_TOKEN_AMOUNT: u64 = 100_000_000;
+
+pub struct TestPool {
+    pub banks_client: BanksClient,
+    pub payer: Keypair,
+    pub recent_blockhash: Hash,
+    pub swap: Keypair,
+    pub authority: Pubkey,
+    pub amp_factor: u64,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub pool_mint: Pubkey,
+    pub admin_fee_a: Pubkey,
+    pub admin_fee_b: Pubkey,
+    pub user_token_a: Pubkey,
+    pub user_token_b: Pubkey,
+    pub user_pool_token: Pubkey,
+}
+
+impl TestPool {
+    /// Creates the mints and token accounts of a pool, and the user's token accounts
+    pub async fn new() -> Self {
+        Self::with_config(
+            AMP_FACTOR,
+            INITIAL_TOKEN_A_AMOUNT,
+            INITIAL_TOKEN_B_AMOUNT,
+            USER_TOKEN_AMOUNT,
+        )
+        .await
+    }
+
+    /// Same as [`TestPool::new`] with the given amplification and token amounts
+    pub async fn with_config(
+        amp_factor: u64,
+        initial_token_a_amount: u64,
+        initial_token_b_amount: u64,
+        user_token_amount: u64,
+    ) -> Self {
+        let mut program_test = ProgramTest::new(
+            "stable_swap",
+            stable_swap::id(),
+            processor!(Processor::process),
+        );
+        program_test.set_compute_max_units(MAX_COMPUTE_UNITS);
+        // Swaps and deposits require the global state, created by SetEmergencyAuthority
+        let mut global_state_data = vec![0; GlobalState::LEN];
+        let global_state = GlobalState {
+            is_initialized: true,
+            ..GlobalState::default()
+        };
+        GlobalState::pack(global_state, &mut global_state_data).unwrap();
+        program_test.add_account(
+            find_global_state_address(&stable_swap::id()).0,
+            Account {
+                lamports: 1_000_000_000,
+                data: global_state_data,
+                owner: stable_swap::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let swap = Keypair::new();
+        let (authority, _nonce) =
+            Pubkey::find_program_address(&[swap.pubkey().as_ref()], &stable_swap::id());
+        let mut pool = Self {
+            banks_client,
+            payer,
+            recent_blockhash,
+            swap,
+            authority,
+            amp_factor,
+            token_a_mint: Pubkey::default(),
+            token_b_mint: Pubkey::default(),
+            token_a: Pubkey::default(),
+            token_b: Pubkey::default(),
+            pool_mint: Pubkey::default(),
+            admin_fee_a: Pubkey::default(),
+            admin_fee_b: Pubkey::default(),
+            user_token_a: Pubkey::default(),
+            user_token_b: Pubkey::default(),
+            user_pool_token: Pubkey::default(),
+        };
+
+        let payer = pool.payer.pubkey();
+        pool.token_a_mint = pool.create_mint(&payer).await;
+        pool.token_b_mint = pool.create_mint(&payer).await;
+        pool.pool_mint = pool.create_mint(&authority).await;
+        pool.token_a = pool
+            .create_token_account(
+                &pool.token_a_mint.clone(),
+                &authority,
+                initial_token_a_amount,
+            )
+            .await;
+        pool.token_b = pool
+            .create_token_account(
+                &pool.token_b_mint.clone(),
+                &authority,
+                initial_token_b_amount,
+            )
+            .await;
+        pool.admin_fee_a = pool
+            .create_token_account(&pool.token_a_mint.clone(), &payer, 0)
+            .await;
+        pool.admin_fee_b = pool
+            .create_token_account(&pool.token_b_mint.clone(), &payer, 0)
+            .await;
+        pool.user_token_a = pool
+            .create_token_account(&pool.token_a_mint.clone(), &payer, user_token_amount)
+            .await;
+        pool.user_token_b = pool
+            .create_token_account(&pool.token_b_mint.clone(), &payer, user_token_amount)
+            .await;
+        pool.user_pool_token = pool
+            .create_token_account(&pool.pool_mint.clone(), &payer, 0)
+            .await;
+        pool
+    }
+
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), TransactionError> {
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &all_signers,
+            self.recent_blockhash,
+        );
+        self.banks_client
+            .process_transaction(transaction)
+            .await
+            .map_err(|err| err.unwrap())
+    }
+
+    /// Compute units used by a single instruction, without committing it
+    pub async fn compute_units(&mut self, instruction: Instruction, signers: &[&Keypair]) -> u64 {
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.payer.pubkey()),
+            &all_signers,
+            self.recent_blockhash,
+        );
+        let simulation = self
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        simulation.result.unwrap().unwrap();
+        simulation.simulation_details.unwrap().units_consumed
+    }
+
+    pub async fn create_mint(&mut self, mint_authority: &Pubkey) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.banks_client.get_rent().await.unwrap();
+        self.process(
+            &[
+                system_instruction::create_account(
+                    &self.payer.pubkey(),
+                    &mint.pubkey(),
+                    rent.minimum_balance(Mint::LEN),
+                    Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    mint_authority,
+                    None,
+                    6,
+                )
+                .unwrap(),
+            ],
+            &[&mint],
+        )
+        .await
+        .unwrap();
+        mint.pubkey()
+    }
+
+    pub async fn create_token_account(
+        &mut self,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let mut instructions = vec![
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.pubkey(),
+                rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ];
+        if amount > 0 {
+            instructions.push(
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    mint,
+                    &account.pubkey(),
+                    &self.payer.pubkey(),
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+            );
+        }
+        self.process(&instructions, &[&account]).await.unwrap();
+        account.pubkey()
+    }
+
+    pub async fn get_account(&mut self, key: &Pubkey) -> Account {
+        self.banks_client.get_account(*key).await.unwrap().unwrap()
+    }
+
+    pub async fn token_balance(&mut self, key: &Pubkey) -> u64 {
+        let account = self.get_account(key).await;
+        TokenAccount::unpack(&account.data).unwrap().amount
+    }
+
+    pub async fn swap_info(&mut self) -> SwapInfo {
+        let account = self.get_account(&self.swap.pubkey()).await;
+        SwapInfo::unpack(&account.data).unwrap()
+    }
+
+    pub fn initialize_instruction(&self) -> Instruction {
+        let (_authority, nonce) =
+            Pubkey::find_program_address(&[self.swap.pubkey().as_ref()], &stable_swap::id());
+        instruction::initialize(
+            &stable_swap::id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.payer.pubkey(),
+            &self.admin_fee_a,
+            &self.admin_fee_b,
+            &self.token_a_mint,
+            &self.token_a,
+            &self.token_b_mint,
+            &self.token_b,
+            &self.pool_mint,
+            &self.user_pool_token,
+            nonce,
+            self.amp_factor,
+            Fees::from_bps(4, 0, 5_000).unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Creates the swap account and initializes the swap
+    pub async fn initialize(&mut self) {
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let create_swap = system_instruction::create_account(
+            &self.payer.pubkey(),
+            &self.swap.pubkey(),
+            rent.minimum_balance(SwapInfo::LEN),
+            SwapInfo::LEN as u64,
+            &stable_swap::id(),
+        );
+        self.process(&[create_swap], &[&self.swap.insecure_clone()])
+            .await
+            .unwrap();
+
+        let initialize = self.initialize_instruction();
+        let units = self
+            .compute_units(initialize.clone(), &[&self.swap.insecure_clone()])
+            .await;
+        assert!(units <= MAX_COMPUTE_UNITS, "initialize used {} CUs", units);
+        self.process(&[initialize], &[&self.swap.insecure_clone()])
+            .await
+            .unwrap();
+    }
+
+    pub fn swap_instruction(
+        &self,
+        a_to_b: bool,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Instruction {
+        let (source, swap_source, swap_destination, destination, admin_fee) = if a_to_b {
+            (
+                self.user_token_a,
+                self.token_a,
+                self.token_b,
+                self.user_token_b,
+                self.admin_fee_b,
+            )
+        } else {
+            (
+                self.user_token_b,
+                self.token_b,
+                self.token_a,
+                self.user_token_a,
+                self.admin_fee_a,
+            )
+        };
+        instruction::swap(
+            &stable_swap::id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.payer.pubkey(),
+            &source,
+            &swap_source,
+            &swap_destination,
+            &destination,
+            &admin_fee,
+            amount_in,
+            minimum_amount_out,
+        )
+        .unwrap()
+    }
+
+    pub fn deposit_instruction(&self, token_a_amount: u64, token_b_amount: u64) -> Instruction {
+        instruction::deposit(
+            &stable_swap::id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.payer.pubkey(),
+            &self.user_token_a,
+            &self.user_token_b,
+            &self.token_a,
+            &self.token_b,
+            &self.pool_mint,
+            &self.user_pool_token,
+            token_a_amount,
+            token_b_amount,
+            0,
+        )
+        .unwrap()
+    }
+
+    pub fn withdraw_instruction(&self, pool_token_amount: u64) -> Instruction {
+        instruction::withdraw(
+            &stable_swap::id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.payer.pubkey(),
+            &self.pool_mint,
+            &self.user_pool_token,
+            &self.token_a,
+            &self.token_b,
+            &self.user_token_a,
+            &self.user_token_b,
+            &self.admin_fee_a,
+            &self.admin_fee_b,
+            pool_token_amount,
+            0,
+            0,
+        )
+        .unwrap()
+    }
+
+    pub fn withdraw_one_instruction(&self, pool_token_amount: u64) -> Instruction {
+        instruction::withdraw_one(
+            &stable_swap::id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.payer.pubkey(),
+            &self.pool_mint,
+            &self.user_pool_token,
+            &self.token_a,
+            &self.token_b,
+            &self.user_token_a,
+            &self.admin_fee_a,
+            pool_token_amount,
+            0,
+        )
+        .unwrap()
+    }
+}
@@ -0,0 +1,200 @@
+//! End-to-end tests running the program in a bank through `solana-program-test`.
+//!
+//! `cargo test` runs the program natively. `./do.sh test` (`cargo test-bpf`) runs the
+//! deployed BPF program instead, which is what the compute unit assertions are about.
+
+#![allow(clippy::unwrap_used)]
+
+mod common;
+
+use common::{TestPool, AMP_FACTOR, MAX_COMPUTE_UNITS, USER_TOKEN_AMOUNT};
+use solana_program_test::{tokio, ProgramTestBanksClientExt};
+use solana_sdk::{
+    instruction::InstructionError,
+    program_pack::Pack,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::TransactionError,
+};
+use stable_swap::{
+    curve::AMP_PRECISION,
+    error::SwapError,
+    instruction,
+    state::{find_lp_position_address, LpPosition},
+};
+
+#[tokio::test]
+async fn test_initialize() {
+    let mut pool = TestPool::new().await;
+    pool.initialize().await;
+
+    let swap_info = pool.swap_info().await;
+    assert!(swap_info.is_initialized);
+    assert!(!swap_info.is_paused);
+    assert_eq!(swap_info.admin_key, pool.payer.pubkey());
+    assert_eq!(swap_info.token_a.reserves, pool.token_a);
+    assert_eq!(swap_info.token_b.reserves, pool.token_b);
+    assert_eq!(swap_info.pool_mint, pool.pool_mint);
+    assert_eq!(swap_info.target_amp_factor, AMP_FACTOR * AMP_PRECISION);
+    let user_pool_tokens = pool.token_balance(&pool.user_pool_token.clone()).await;
+    assert!(user_pool_tokens > 0);
+
+    // the swap cannot be initialized twice; a fresh blockhash keeps the
+    // retry from being deduplicated as the same transaction
+    pool.recent_blockhash = pool
+        .banks_client
+        .get_new_latest_blockhash(&pool.recent_blockhash)
+        .await
+        .unwrap();
+    let initialize = pool.initialize_instruction();
+    assert_eq!(
+        pool.process(&[initialize], &[&pool.swap.insecure_clone()])
+            .await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(SwapError::AlreadyInUse as u32)
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_deposit_swap_withdraw() {
+    let mut pool = TestPool::new().await;
+    pool.initialize().await;
+    let user_token_a = pool.user_token_a;
+    let user_token_b = pool.user_token_b;
+    let user_pool_token = pool.user_pool_token;
+    let initial_pool_tokens = pool.token_balance(&user_pool_token).await;
+
+    // deposit
+    let deposit_amount = 10_000_000;
+    let deposit = pool.deposit_instruction(deposit_amount, deposit_amount);
+    let units = pool.compute_units(deposit.clone(), &[]).await;
+    assert!(units <= MAX_COMPUTE_UNITS, "deposit used {} CUs", units);
+    pool.process(&[deposit], &[]).await.unwrap();
+    assert_eq!(
+        pool.token_balance(&user_token_a).await,
+        USER_TOKEN_AMOUNT - deposit_amount
+    );
+    assert_eq!(
+        pool.token_balance(&user_token_b).await,
+        USER_TOKEN_AMOUNT - deposit_amount
+    );
+    let pool_tokens = pool.token_balance(&user_pool_token).await;
+    assert!(pool_tokens > initial_pool_tokens);
+
+    // swap A to B
+    let amount_in = 1_000_000;
+    let swap = pool.swap_instruction(true, amount_in, 0);
+    let units = pool.compute_units(swap.clone(), &[]).await;
+    assert!(units <= MAX_COMPUTE_UNITS, "swap used {} CUs", units);
+    pool.process(&[swap], &[]).await.unwrap();
+    let token_a_balance = pool.token_balance(&user_token_a).await;
+    let token_b_balance = pool.token_balance(&user_token_b).await;
+    assert_eq!(
+        token_a_balance,
+        USER_TOKEN_AMOUNT - deposit_amount - amount_in
+    );
+    let amount_out = token_b_balance - (USER_TOKEN_AMOUNT - deposit_amount);
+    // balanced pool with a low fee, close to 1:1
+    assert!(amount_out < amount_in && amount_out > amount_in * 99 / 100);
+    assert!(pool.token_balance(&pool.admin_fee_b.clone()).await > 0);
+
+    // slippage
+    let swap = pool.swap_instruction(false, amount_in, amount_in * 2);
+    assert_eq!(
+        pool.process(&[swap], &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(SwapError::ExceededSlippage as u32)
+        ))
+    );
+
+    // withdraw everything deposited
+    let withdraw_amount = pool_tokens - initial_pool_tokens;
+    let withdraw = pool.withdraw_instruction(withdraw_amount);
+    let units = pool.compute_units(withdraw.clone(), &[]).await;
+    assert!(units <= MAX_COMPUTE_UNITS, "withdraw used {} CUs", units);
+    pool.process(&[withdraw], &[]).await.unwrap();
+    assert_eq!(
+        pool.token_balance(&user_pool_token).await,
+        initial_pool_tokens
+    );
+    assert!(pool.token_balance(&user_token_a).await > token_a_balance);
+    assert!(pool.token_balance(&user_token_b).await > token_b_balance);
+}
+
+#[tokio::test]
+async fn test_admin_pause() {
+    let mut pool = TestPool::new().await;
+    pool.initialize().await;
+    let swap_key = pool.swap.pubkey();
+    let admin_key = pool.payer.pubkey();
+
+    let pause = instruction::pause(&stable_swap::id(), &swap_key, &admin_key).unwrap();
+    let units = pool.compute_units(pause.clone(), &[]).await;
+    assert!(units <= MAX_COMPUTE_UNITS, "pause used {} CUs", units);
+    pool.process(&[pause], &[]).await.unwrap();
+    assert!(pool.swap_info().await.is_paused);
+
+    let swap = pool.swap_instruction(true, 1_000, 0);
+    assert_eq!(
+        pool.process(&[swap], &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(SwapError::IsPaused as u32)
+        ))
+    );
+
+    // only the admin can unpause
+    let not_admin = Keypair::new();
+    let unpause = instruction::unpause(&stable_swap::id(), &swap_key, &not_admin.pubkey()).unwrap();
+    assert_eq!(
+        pool.process(&[unpause], &[&not_admin]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(SwapError::Unauthorized as u32)
+        ))
+    );
+
+    let unpause = instruction::unpause(&stable_swap::id(), &swap_key, &admin_key).unwrap();
+    pool.process(&[unpause], &[]).await.unwrap();
+    assert!(!pool.swap_info().await.is_paused);
+
+    let swap = pool.swap_instruction(true, 2_000, 0);
+    pool.process(&[swap], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_sync_lp_position_funded_address() {
+    let mut pool = TestPool::new().await;
+    pool.initialize().await;
+    let swap_key = pool.swap.pubkey();
+    let owner_key = pool.payer.pubkey();
+    let user_pool_token = pool.user_pool_token;
+    let (lp_position_key, _bump_seed) =
+        find_lp_position_address(&stable_swap::id(), &swap_key, &owner_key);
+
+    // anyone can fund the address before the position is created
+    let rent = pool.banks_client.get_rent().await.unwrap();
+    let transfer =
+        system_instruction::transfer(&owner_key, &lp_position_key, rent.minimum_balance(0));
+    pool.process(&[transfer], &[]).await.unwrap();
+
+    let sync = instruction::sync_lp_position(
+        &stable_swap::id(),
+        &swap_key,
+        &owner_key,
+        &user_pool_token,
+        &owner_key,
+    )
+    .unwrap();
+    pool.process(&[sync], &[]).await.unwrap();
+
+    let account = pool.get_account(&lp_position_key).await;
+    assert_eq!(account.owner, stable_swap::id());
+    assert_eq!(account.data.len(), LpPosition::LEN);
+    assert_eq!(account.lamports, rent.minimum_balance(LpPosition::LEN));
+    let lp_position = LpPosition::unpack(&account.data).unwrap();
+    assert_eq!(lp_position.owner, owner_key);
+}
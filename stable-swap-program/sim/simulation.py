@@ -1,4 +1,5 @@
 # Source from: https://github.com/curvefi/curve-contract/blob/master/tests/simulation.py
+# Fees round up, in favor of the pool, to match the program.
 
 class Curve:
 
@@ -116,14 +117,14 @@ class Curve:
         x = xp[i] + dx
         y = self.y(i, j, x)
         dy = xp[j] - y
-        fee = dy * self.fee // 10 ** 10
+        fee = -(-dy * self.fee // 10 ** 10)
         assert dy > 0
         self.x[i] = x * 10 ** 18 // self.p[i]
         self.x[j] = (y + fee) * 10 ** 18 // self.p[j]
         return dy - fee
 
     def remove_liquidity_imbalance(self, amounts):
-        _fee = self.fee * self.n // (4 * (self.n - 1))
+        _fee = -(-self.fee * self.n // (4 * (self.n - 1)))
 
         old_balances = self.x
         new_balances = self.x[:]
@@ -137,7 +138,7 @@ class Curve:
         for i in range(self.n):
             ideal_balance = D1 * old_balances[i] // D0
             difference = abs(ideal_balance - new_balances[i])
-            fees[i] = _fee * difference // 10 ** 10
+            fees[i] = -(-_fee * difference // 10 ** 10)
             new_balances[i] -= fees[i]
         self.x = new_balances
         D2 = self.D()
@@ -155,14 +156,14 @@ class Curve:
         D1 = D0 - token_amount * D0 // self.tokens
         new_y = self.y_D(i, D1)
 
-        fee = self.fee * self.n // (4 * (self.n - 1))
+        fee = -(-self.fee * self.n // (4 * (self.n - 1)))
         for j in range(self.n):
           dx_expected = 0
           if j == i:
             dx_expected = xp[j] * D1 // D0 - new_y
           else:
             dx_expected = xp[j] - xp[j] * D1 // D0
-          xp_reduced[j] -= fee * dx_expected // 10 ** 10
+          xp_reduced[j] -= -(-fee * dx_expected // 10 ** 10)
 
         self.x = [x // (p // 10 ** 18) for x, p in zip(xp_reduced, self.p)]
         dy = xp_reduced[i] - self.y_D(i, D1) - 1    # Withdraw less to account for rounding errors